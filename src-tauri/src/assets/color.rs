@@ -0,0 +1,221 @@
+//! Color management for placed images. Photos arrive tagged with Adobe
+//! RGB, ProPhoto or a printer's CMYK profile, and drawn as if they were
+//! sRGB their colors shift; on import they are converted to the document's
+//! working space through their embedded ICC profile. Untagged images are
+//! taken to be sRGB, as browsers take them.
+
+use crate::import::raster::{self, SourceFormat};
+use image::{DynamicImage, ImageDecoder, ImageReader, RgbaImage};
+use moxcms::{ColorProfile, DataColorSpace, Layout, ProfileText, TransformOptions, Xyzd};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use zune_core::bytestream::ZCursor;
+use zune_core::colorspace::ColorSpace;
+use zune_core::options::DecoderOptions;
+use zune_jpeg::JpegDecoder;
+
+/// How far apart primaries may be, in XYZ, and still count as the same.
+const COLORANT_TOLERANCE: f64 = 1e-3;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WorkingSpace {
+    #[default]
+    #[serde(rename = "sRGB")]
+    Srgb,
+    #[serde(rename = "Display P3")]
+    DisplayP3,
+}
+
+impl WorkingSpace {
+    pub fn name(self) -> &'static str {
+        match self {
+            WorkingSpace::Srgb => "sRGB",
+            WorkingSpace::DisplayP3 => "Display P3",
+        }
+    }
+
+    fn profile(self) -> ColorProfile {
+        match self {
+            WorkingSpace::Srgb => ColorProfile::new_srgb(),
+            WorkingSpace::DisplayP3 => ColorProfile::new_display_p3(),
+        }
+    }
+
+    /// The profile to embed in images converted to this space; sRGB is
+    /// what an untagged image is anyway.
+    pub fn icc(self) -> Option<Vec<u8>> {
+        match self {
+            WorkingSpace::Srgb => None,
+            WorkingSpace::DisplayP3 => self.profile().encode().ok(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ColorProfileInfo {
+    /// The profile's own name, such as `Adobe RGB (1998)`.
+    pub description: Option<String>,
+    /// `RGB`, `CMYK` or `Gray`.
+    pub color_space: String,
+    /// Whether the profile is embedded in the file rather than assumed.
+    pub embedded: bool,
+}
+
+impl ColorProfileInfo {
+    /// What an image converted to `space` is in.
+    pub fn working(space: WorkingSpace) -> Self {
+        ColorProfileInfo { description: Some(space.name().to_string()), color_space: "RGB".to_string(), embedded: false }
+    }
+}
+
+fn text(text: &ProfileText) -> Option<String> {
+    let text = match text {
+        ProfileText::PlainString(text) => text.clone(),
+        ProfileText::Localizable(strings) => strings.first()?.value.clone(),
+        ProfileText::Description(description) => description.ascii_string.clone(),
+    };
+    let text = text.trim_end_matches('\0').trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+fn color_space_name(space: DataColorSpace) -> String {
+    match space {
+        DataColorSpace::Rgb => "RGB".to_string(),
+        DataColorSpace::Cmyk => "CMYK".to_string(),
+        DataColorSpace::Gray => "Gray".to_string(),
+        DataColorSpace::Lab => "Lab".to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+/// The ICC profile embedded in image bytes. HEIC and AVIF are converted
+/// to sRGB by the system decoder, so theirs doesn't apply.
+pub fn embedded_profile(bytes: &[u8]) -> Option<Vec<u8>> {
+    match SourceFormat::detect(bytes)? {
+        SourceFormat::Png | SourceFormat::Jpeg | SourceFormat::Webp => {
+            let reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format().ok()?;
+            reader.into_decoder().ok()?.icc_profile().ok()?
+        }
+        SourceFormat::Jxl => jxl_oxide::integration::JxlDecoder::new(Cursor::new(bytes)).ok()?.icc_profile().ok()?,
+        SourceFormat::Heic | SourceFormat::Avif => None,
+    }
+}
+
+/// The profile image bytes are in, and what it says about itself: the
+/// embedded one, or sRGB for untagged images and unreadable profiles.
+pub fn source_profile(bytes: &[u8]) -> (ColorProfile, ColorProfileInfo) {
+    let embedded = embedded_profile(bytes).and_then(|icc| ColorProfile::new_from_slice(&icc).ok());
+    let is_embedded = embedded.is_some();
+    let profile = embedded.unwrap_or_else(ColorProfile::new_srgb);
+    let description = profile.description.as_ref().and_then(text);
+    let info = ColorProfileInfo {
+        description: description.or_else(|| (!is_embedded).then(|| WorkingSpace::Srgb.name().to_string())),
+        color_space: color_space_name(profile.color_space),
+        embedded: is_embedded,
+    };
+    (profile, info)
+}
+
+fn same_colorant(a: Xyzd, b: Xyzd) -> bool {
+    (a.x - b.x).abs() < COLORANT_TOLERANCE && (a.y - b.y).abs() < COLORANT_TOLERANCE && (a.z - b.z).abs() < COLORANT_TOLERANCE
+}
+
+/// Whether an image in `profile` can be drawn in `space` as it is. RGB
+/// profiles are compared by their primaries, which is what tells the
+/// common working spaces apart.
+fn matches(profile: &ColorProfile, space: WorkingSpace) -> bool {
+    let working = space.profile();
+    profile.color_space == DataColorSpace::Rgb
+        && same_colorant(profile.red_colorant, working.red_colorant)
+        && same_colorant(profile.green_colorant, working.green_colorant)
+        && same_colorant(profile.blue_colorant, working.blue_colorant)
+}
+
+/// Whether a JPEG carries an Adobe APP14 segment, whose CMYK Photoshop
+/// writes inverted.
+fn has_adobe_marker(bytes: &[u8]) -> bool {
+    bytes.windows(9).take(64 * 1024).any(|w| w[..2] == [0xff, 0xee] && &w[4..9] == b"Adobe")
+}
+
+/// A CMYK JPEG's inks, four bytes to a pixel as stored, before orientation.
+fn decode_cmyk(bytes: &[u8]) -> Result<(u32, u32, Vec<u8>), String> {
+    let options = DecoderOptions::default().jpeg_set_out_colorspace(ColorSpace::CMYK);
+    let mut decoder = JpegDecoder::new_with_options(ZCursor::new(bytes), options);
+    let mut inks = decoder.decode().map_err(|e| format!("Failed to decode CMYK image: {:?}", e))?;
+    let info = decoder.info().ok_or("Failed to decode CMYK image")?;
+    if has_adobe_marker(bytes) {
+        inks.iter_mut().for_each(|ink| *ink = 255 - *ink);
+    }
+    Ok((info.width as u32, info.height as u32, inks))
+}
+
+fn cms_failed(e: moxcms::CmsError) -> String {
+    format!("Failed to convert image colors: {:?}", e)
+}
+
+/// CMYK JPEG pixels in `space`, upright.
+fn convert_cmyk(bytes: &[u8], profile: &ColorProfile, space: WorkingSpace) -> Result<RgbaImage, String> {
+    let (width, height, inks) = decode_cmyk(bytes)?;
+    let transform = profile
+        .create_transform_8bit(Layout::Rgba, &space.profile(), Layout::Rgba, TransformOptions::default())
+        .map_err(cms_failed)?;
+    let mut pixels = vec![0u8; inks.len()];
+    transform.transform(&inks, &mut pixels).map_err(cms_failed)?;
+    let image = RgbaImage::from_raw(width, height, pixels).ok_or("Failed to convert image colors")?;
+
+    let mut decoder = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| format!("Failed to read image: {}", e))?
+        .into_decoder()
+        .map_err(|e| format!("Failed to read image: {}", e))?;
+    let orientation = decoder.orientation().map_err(|e| format!("Failed to read image orientation: {}", e))?;
+    let mut image = DynamicImage::ImageRgba8(image);
+    image.apply_orientation(orientation);
+    Ok(image.into_rgba8())
+}
+
+/// Image bytes decoded and converted to `space`, or nothing when they are
+/// in it already and can be used as they are.
+pub fn convert(bytes: &[u8], space: WorkingSpace) -> Result<Option<RgbaImage>, String> {
+    let (profile, info) = source_profile(bytes);
+    if matches(&profile, space) {
+        return Ok(None);
+    }
+    if profile.color_space == DataColorSpace::Cmyk {
+        return convert_cmyk(bytes, &profile, space).map(Some);
+    }
+
+    let mut image = raster::decode(bytes)?;
+    let working = space.profile();
+    match profile.color_space {
+        DataColorSpace::Rgb => {
+            let transform = profile
+                .create_transform_8bit(Layout::Rgba, &working, Layout::Rgba, TransformOptions::default())
+                .map_err(cms_failed)?;
+            let source = image.as_raw().clone();
+            transform.transform(&source, &mut image).map_err(cms_failed)?;
+        }
+        DataColorSpace::Gray => {
+            let transform = profile
+                .create_transform_8bit(Layout::GrayAlpha, &working, Layout::Rgba, TransformOptions::default())
+                .map_err(cms_failed)?;
+            let source: Vec<u8> = image.pixels().flat_map(|p| [p[0], p[3]]).collect();
+            transform.transform(&source, &mut image).map_err(cms_failed)?;
+        }
+        _ => return Err(format!("Images in the {} color space can't be converted", info.color_space)),
+    }
+    Ok(Some(image))
+}
+
+/// Image bytes decoded for re-encoding, with the profile to tag the result
+/// with: RGB images keep their own, others are converted to sRGB.
+pub fn decode_tagged(bytes: &[u8]) -> Result<(RgbaImage, Option<Vec<u8>>), String> {
+    let icc = embedded_profile(bytes);
+    let profile = icc.as_ref().and_then(|icc| ColorProfile::new_from_slice(icc).ok());
+    if profile.is_none_or(|profile| profile.color_space == DataColorSpace::Rgb) {
+        return Ok((raster::decode(bytes)?, icc));
+    }
+    let image = convert(bytes, WorkingSpace::Srgb)?;
+    Ok((image.map_or_else(|| raster::decode(bytes), Ok)?, None))
+}
@@ -0,0 +1,350 @@
+//! What image files say about themselves: the EXIF a camera writes, and
+//! the resolution fields of JPEG and PNG. Only a few fields are read, for
+//! the asset panel; stripping takes all of it out of exported images
+//! except the orientation, which a JPEG needs to show upright.
+
+use crate::import::raster::SourceFormat;
+use image::{ImageDecoder, ImageReader};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+
+const CM_PER_INCH: f64 = 2.54;
+
+const TAG_MAKE: u16 = 0x010f;
+const TAG_MODEL: u16 = 0x0110;
+const TAG_ORIENTATION: u16 = 0x0112;
+const TAG_X_RESOLUTION: u16 = 0x011a;
+const TAG_Y_RESOLUTION: u16 = 0x011b;
+const TAG_RESOLUTION_UNIT: u16 = 0x0128;
+const TAG_DATE_TIME: u16 = 0x0132;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_GPS_IFD: u16 = 0x8825;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_OFFSET_TIME_ORIGINAL: u16 = 0x9011;
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct Resolution {
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageMetadata {
+    /// Pixels per inch across and down, where the file says.
+    pub dpi: Option<Resolution>,
+    /// When the photo was taken, in ISO 8601 local time with the UTC
+    /// offset where the camera recorded one.
+    pub captured_at: Option<String>,
+    /// EXIF orientation, from 1 to 8, applied to show the image upright.
+    pub orientation: Option<u8>,
+    pub camera: Option<String>,
+    /// Whether the file records where it was taken.
+    pub has_location: bool,
+}
+
+struct IfdEntry {
+    tag: u16,
+    kind: u16,
+    count: usize,
+    /// Where the value is: in the entry itself when it fits in four bytes.
+    at: usize,
+}
+
+/// A TIFF structure as EXIF is stored in.
+struct Tiff<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(chunk: &'a [u8]) -> Option<Self> {
+        let data = chunk.strip_prefix(b"Exif\0\0").unwrap_or(chunk);
+        match data.get(..4)? {
+            b"II*\0" => Some(Tiff { data, little_endian: true }),
+            b"MM\0*" => Some(Tiff { data, little_endian: false }),
+            _ => None,
+        }
+    }
+
+    fn u16(&self, at: usize) -> Option<u16> {
+        let bytes = self.data.get(at..at + 2)?.try_into().ok()?;
+        Some(if self.little_endian { u16::from_le_bytes(bytes) } else { u16::from_be_bytes(bytes) })
+    }
+
+    fn u32(&self, at: usize) -> Option<u32> {
+        let bytes = self.data.get(at..at + 4)?.try_into().ok()?;
+        Some(if self.little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) })
+    }
+
+    fn ifd(&self, offset: usize) -> Vec<IfdEntry> {
+        let count = self.u16(offset).unwrap_or_default() as usize;
+        let entries = (0..count).map(|i| offset + 2 + i * 12);
+        entries
+            .map_while(|at| {
+                let (kind, count) = (self.u16(at + 2)?, self.u32(at + 4)? as usize);
+                let size = match kind {
+                    3 => 2,
+                    4 | 9 => 4,
+                    5 | 10 => 8,
+                    _ => 1,
+                };
+                let value = if size * count <= 4 { at + 8 } else { self.u32(at + 8)? as usize };
+                Some(IfdEntry { tag: self.u16(at)?, kind, count, at: value })
+            })
+            .collect()
+    }
+
+    fn first_ifd(&self) -> Vec<IfdEntry> {
+        self.u32(4).map(|offset| self.ifd(offset as usize)).unwrap_or_default()
+    }
+
+    fn number(&self, entry: &IfdEntry) -> Option<f64> {
+        match entry.kind {
+            3 => self.u16(entry.at).map(f64::from),
+            4 => self.u32(entry.at).map(f64::from),
+            5 => {
+                let (numerator, denominator) = (self.u32(entry.at)?, self.u32(entry.at + 4)?);
+                (denominator != 0).then(|| numerator as f64 / denominator as f64)
+            }
+            _ => None,
+        }
+    }
+
+    fn text(&self, entry: &IfdEntry) -> Option<String> {
+        let bytes = self.data.get(entry.at..entry.at + entry.count)?;
+        let text = String::from_utf8_lossy(bytes);
+        let text = text.trim_end_matches('\0').trim();
+        (!text.is_empty()).then(|| text.to_string())
+    }
+}
+
+fn find(entries: &[IfdEntry], tag: u16) -> Option<&IfdEntry> {
+    entries.iter().find(|entry| entry.tag == tag)
+}
+
+/// `2024:05:01 12:30:00` as `2024-05-01T12:30:00`, with `offset` after.
+fn iso_date(exif: &str, offset: Option<String>) -> Option<String> {
+    let (date, time) = exif.split_once(' ')?;
+    if date.len() != 10 || date.starts_with("0000") {
+        return None;
+    }
+    Some(format!("{}T{}{}", date.replace(':', "-"), time, offset.unwrap_or_default()))
+}
+
+fn read_exif(chunk: &[u8], metadata: &mut ImageMetadata) {
+    let Some(tiff) = Tiff::new(chunk) else { return };
+    let ifd0 = tiff.first_ifd();
+    let exif = find(&ifd0, TAG_EXIF_IFD).and_then(|entry| tiff.u32(entry.at)).map(|offset| tiff.ifd(offset as usize)).unwrap_or_default();
+
+    metadata.orientation = find(&ifd0, TAG_ORIENTATION).and_then(|entry| tiff.number(entry)).map(|o| o as u8).filter(|o| (1..=8).contains(o));
+    let unit = find(&ifd0, TAG_RESOLUTION_UNIT).and_then(|entry| tiff.number(entry)).unwrap_or(2.0);
+    let resolution = |tag| find(&ifd0, tag).and_then(|entry| tiff.number(entry)).filter(|r| *r > 0.0);
+    if let (Some(x), Some(y)) = (resolution(TAG_X_RESOLUTION), resolution(TAG_Y_RESOLUTION)) {
+        // Unit 1 is no unit at all, only an aspect ratio
+        let per_inch = match unit as u32 {
+            2 => Some(1.0),
+            3 => Some(CM_PER_INCH),
+            _ => None,
+        };
+        metadata.dpi = per_inch.map(|scale| Resolution { x: x * scale, y: y * scale }).or(metadata.dpi);
+    }
+
+    let offset = find(&exif, TAG_OFFSET_TIME_ORIGINAL).and_then(|entry| tiff.text(entry));
+    let captured = find(&exif, TAG_DATE_TIME_ORIGINAL).or_else(|| find(&ifd0, TAG_DATE_TIME));
+    metadata.captured_at = captured.and_then(|entry| tiff.text(entry)).and_then(|date| iso_date(&date, offset));
+
+    let make = find(&ifd0, TAG_MAKE).and_then(|entry| tiff.text(entry));
+    let model = find(&ifd0, TAG_MODEL).and_then(|entry| tiff.text(entry));
+    metadata.camera = match (make, model) {
+        (Some(make), Some(model)) if model.starts_with(&make) => Some(model),
+        (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+        (make, model) => make.or(model),
+    };
+    metadata.has_location = find(&ifd0, TAG_GPS_IFD).is_some();
+}
+
+/// A marker segment of a JPEG and its byte range, marker included.
+struct Segment {
+    marker: u8,
+    start: usize,
+    end: usize,
+}
+
+/// The marker segments of a JPEG up to its scan data, and where the scan
+/// starts.
+fn jpeg_segments(bytes: &[u8]) -> Option<(Vec<Segment>, usize)> {
+    if !bytes.starts_with(&[0xff, 0xd8]) {
+        return None;
+    }
+    let (mut segments, mut at) = (Vec::new(), 2);
+    loop {
+        if *bytes.get(at)? != 0xff {
+            return None;
+        }
+        match *bytes.get(at + 1)? {
+            // Fill byte before a marker
+            0xff => at += 1,
+            0xda => return Some((segments, at)),
+            marker @ (0x01 | 0xd0..=0xd7) => {
+                segments.push(Segment { marker, start: at, end: at + 2 });
+                at += 2;
+            }
+            marker => {
+                let length = u16::from_be_bytes([*bytes.get(at + 2)?, *bytes.get(at + 3)?]) as usize;
+                let end = at + 2 + length;
+                if end > bytes.len() {
+                    return None;
+                }
+                segments.push(Segment { marker, start: at, end });
+                at = end;
+            }
+        }
+    }
+}
+
+/// Resolution from a JPEG's JFIF header.
+fn jfif_dpi(bytes: &[u8]) -> Option<Resolution> {
+    let (segments, _) = jpeg_segments(bytes)?;
+    let start = segments.into_iter().find(|s| s.marker == 0xe0 && bytes.get(s.start + 4..s.start + 9) == Some(b"JFIF\0"))?.start;
+    let field = |at: usize| Some(u16::from_be_bytes([*bytes.get(start + at)?, *bytes.get(start + at + 1)?]) as f64);
+    let (x, y) = (field(12)?, field(14)?);
+    let scale = match bytes.get(start + 11)? {
+        1 => 1.0,
+        2 => CM_PER_INCH,
+        _ => return None,
+    };
+    (x > 0.0 && y > 0.0).then_some(Resolution { x: x * scale, y: y * scale })
+}
+
+/// The chunks of a PNG, each as its type and byte range including length
+/// and checksum.
+fn png_chunks(bytes: &[u8]) -> Option<Vec<([u8; 4], usize, usize)>> {
+    if !bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return None;
+    }
+    let (mut chunks, mut at) = (Vec::new(), 8);
+    while at < bytes.len() {
+        let length = u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?) as usize;
+        let kind: [u8; 4] = bytes.get(at + 4..at + 8)?.try_into().ok()?;
+        let end = at + 12 + length;
+        if end > bytes.len() {
+            return None;
+        }
+        chunks.push((kind, at, end));
+        at = end;
+    }
+    Some(chunks)
+}
+
+/// Resolution from a PNG's `pHYs` chunk, which is in pixels per meter.
+fn png_dpi(bytes: &[u8]) -> Option<Resolution> {
+    let (_, start, _) = png_chunks(bytes)?.into_iter().find(|(kind, _, _)| kind == b"pHYs")?;
+    let field = |at: usize| Some(u32::from_be_bytes(bytes.get(start + at..start + at + 4)?.try_into().ok()?) as f64);
+    let (x, y) = (field(8)?, field(12)?);
+    (*bytes.get(start + 16)? == 1 && x > 0.0 && y > 0.0).then(|| Resolution { x: x * CM_PER_INCH / 100.0, y: y * CM_PER_INCH / 100.0 })
+}
+
+/// The metadata of image bytes, as far as they have any.
+pub fn read(bytes: &[u8]) -> ImageMetadata {
+    let format = SourceFormat::detect(bytes);
+    let mut metadata = ImageMetadata {
+        dpi: match format {
+            Some(SourceFormat::Jpeg) => jfif_dpi(bytes),
+            Some(SourceFormat::Png) => png_dpi(bytes),
+            _ => None,
+        },
+        ..Default::default()
+    };
+    if matches!(format, Some(SourceFormat::Png | SourceFormat::Jpeg | SourceFormat::Webp)) {
+        let decoder = ImageReader::new(Cursor::new(bytes)).with_guessed_format().ok().and_then(|reader| reader.into_decoder().ok());
+        if let Some(exif) = decoder.and_then(|mut decoder| decoder.exif_metadata().ok().flatten()) {
+            read_exif(&exif, &mut metadata);
+        }
+    }
+    metadata
+}
+
+/// An APP1 segment holding EXIF with only `orientation` in it.
+fn orientation_segment(orientation: u8) -> Vec<u8> {
+    let mut segment = vec![0xff, 0xe1, 0, 34];
+    segment.extend_from_slice(b"Exif\0\0MM\0*\0\0\0\x08");
+    // One entry: orientation, a single SHORT, then no further IFD
+    segment.extend_from_slice(&[0, 1, 0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, orientation, 0, 0, 0, 0, 0, 0]);
+    segment
+}
+
+fn strip_jpeg(bytes: &[u8]) -> Option<Vec<u8>> {
+    let (segments, scan) = jpeg_segments(bytes)?;
+    let orientation = read(bytes).orientation.filter(|o| *o != 1);
+    let mut out = Vec::with_capacity(bytes.len());
+    out.extend_from_slice(&[0xff, 0xd8]);
+    // JFIF has to come first, so the orientation goes right after it
+    let jfif = segments.first().filter(|segment| segment.marker == 0xe0);
+    if let Some(segment) = jfif {
+        out.extend_from_slice(&bytes[segment.start..segment.end]);
+    }
+    if let Some(orientation) = orientation {
+        out.extend(orientation_segment(orientation));
+    }
+    for segment in segments.iter().skip(jfif.iter().len()) {
+        // ICC profiles and Adobe's color transform are needed to show the
+        // image; EXIF, XMP, IPTC, comments and other applications' data aren't
+        if !matches!(segment.marker, 0xe1 | 0xe3..=0xed | 0xef | 0xfe) {
+            out.extend_from_slice(&bytes[segment.start..segment.end]);
+        }
+    }
+    out.extend_from_slice(&bytes[scan..]);
+    Some(out)
+}
+
+fn strip_png(bytes: &[u8]) -> Option<Vec<u8>> {
+    let chunks = png_chunks(bytes)?;
+    let mut out = bytes[..8].to_vec();
+    for (kind, start, end) in chunks {
+        if !matches!(&kind, b"eXIf" | b"tEXt" | b"zTXt" | b"iTXt" | b"tIME") {
+            out.extend_from_slice(&bytes[start..end]);
+        }
+    }
+    Some(out)
+}
+
+fn strip_webp(bytes: &[u8]) -> Option<Vec<u8>> {
+    if bytes.get(..4)? != b"RIFF" || bytes.get(8..12)? != b"WEBP" {
+        return None;
+    }
+    let mut out = bytes[..12].to_vec();
+    let mut at = 12;
+    while at + 8 <= bytes.len() {
+        let kind = &bytes[at..at + 4];
+        let size = u32::from_le_bytes(bytes[at + 4..at + 8].try_into().ok()?) as usize;
+        let end = (at + 8 + size + size % 2).min(bytes.len());
+        match kind {
+            b"EXIF" | b"XMP " => {}
+            b"VP8X" => {
+                let flags = out.len() + 8;
+                out.extend_from_slice(&bytes[at..end]);
+                // The header's flags say when EXIF and XMP chunks follow
+                if let Some(flags) = out.get_mut(flags) {
+                    *flags &= !0x0c;
+                }
+            }
+            _ => out.extend_from_slice(&bytes[at..end]),
+        }
+        at = end;
+    }
+    let riff_size = (out.len() - 8) as u32;
+    out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    Some(out)
+}
+
+/// Image bytes without their metadata, or nothing for formats that can't
+/// be stripped here. A JPEG keeps its orientation so it still shows upright.
+pub fn strip(bytes: &[u8]) -> Option<Vec<u8>> {
+    match SourceFormat::detect(bytes)? {
+        SourceFormat::Jpeg => strip_jpeg(bytes),
+        SourceFormat::Png => strip_png(bytes),
+        SourceFormat::Webp => strip_webp(bytes),
+        SourceFormat::Heic | SourceFormat::Avif | SourceFormat::Jxl => None,
+    }
+}
@@ -0,0 +1,301 @@
+//! Images and other files placed in documents, stored once by the SHA-256
+//! of their contents under `assets/<hash>`, so the same logo placed fifty
+//! times is one file. The hash is the asset's id and what nodes reference
+//! it by; what was learned about the file on import is kept beside it in
+//! `<hash>.json`.
+
+pub mod color;
+pub mod metadata;
+pub mod placeholder;
+pub mod proxies;
+pub mod stock;
+
+use self::color::{ColorProfileInfo, WorkingSpace};
+use self::metadata::ImageMetadata;
+use self::stock::StockAttribution;
+use crate::atomic::write_atomic;
+use crate::bundle::now_millis;
+use crate::history::hex_digest;
+use crate::import::raster::{self, SourceFormat};
+use crate::import::sanitize::sanitize;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::{ExtendedColorType, ImageEncoder, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{command, State};
+
+const JPEG_QUALITY: u8 = 88;
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE", rename_all_fields = "camelCase")]
+pub enum AssetSource {
+    Path { path: String },
+    /// Base64-encoded contents, such as a pasted or dropped image.
+    Bytes { data: String, name: Option<String> },
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AssetInfo {
+    /// SHA-256 of the contents, in hex.
+    pub id: String,
+    pub mime_type: String,
+    pub size: u64,
+    /// Upright size in pixels; unset for files that aren't images.
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// File name the contents were first imported under.
+    pub name: Option<String>,
+    pub imported_at: u64,
+    /// For a downscaled proxy, the asset it was made from, if kept linked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub original: Option<String>,
+    /// Proxies made from this asset.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub proxies: Vec<String>,
+    /// The color profile an image is in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color_profile: Option<ColorProfileInfo>,
+    /// Versions of this image converted to other working spaces.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub converted: Vec<String>,
+    /// What the image file says about itself, such as when it was taken.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<ImageMetadata>,
+    /// A ThumbHash of a raster image, base64-encoded, to draw while the
+    /// image loads.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumb_hash: Option<String>,
+    /// Who to credit for a stock photo, and where it came from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attribution: Option<StockAttribution>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ImportAssetOptions {
+    /// The space of the document placing the image, which it is converted
+    /// to; sRGB when unset.
+    pub working_space: WorkingSpace,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedAsset {
+    pub asset: AssetInfo,
+    /// Whether the same contents were already stored, so nothing was added.
+    pub existing: bool,
+}
+
+pub struct AssetStore {
+    dir: PathBuf,
+}
+
+/// The MIME type of `bytes`, sniffed from their contents.
+fn sniff_mime(bytes: &[u8]) -> &'static str {
+    if let Some(format) = SourceFormat::detect(bytes) {
+        return format.mime_type();
+    }
+    match bytes.get(..4) {
+        Some(b"GIF8") => return "image/gif",
+        Some(b"\0\x01\0\0" | b"true") => return "font/ttf",
+        Some(b"OTTO") => return "font/otf",
+        Some(b"ttcf") => return "font/collection",
+        Some(b"wOFF") => return "font/woff",
+        Some(b"wOF2") => return "font/woff2",
+        _ => {}
+    }
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(1024)]);
+    if head.contains("<svg") {
+        return "image/svg+xml";
+    }
+    "application/octet-stream"
+}
+
+/// Pixel size of an image asset; SVG is sized by its viewport, rounded up.
+fn dimensions(bytes: &[u8], mime_type: &str) -> Option<(u32, u32)> {
+    match mime_type {
+        "image/svg+xml" => {
+            let tree = usvg::Tree::from_data(bytes, &usvg::Options::default()).ok()?;
+            Some((tree.size().width().ceil() as u32, tree.size().height().ceil() as u32))
+        }
+        _ if SourceFormat::detect(bytes).is_some() => raster::dimensions(bytes).ok(),
+        _ => None,
+    }
+}
+
+/// JPEG for opaque images, as photos usually are, and PNG otherwise,
+/// tagged with `icc` where that is set.
+fn encode(image: &RgbaImage, icc: Option<Vec<u8>>) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let (width, height) = image.dimensions();
+    if image.pixels().any(|p| p[3] < 255) {
+        let mut encoder = PngEncoder::new(&mut out);
+        if let Some(icc) = icc {
+            let _ = encoder.set_icc_profile(icc);
+        }
+        encoder.write_image(image.as_raw(), width, height, ExtendedColorType::Rgba8)
+            .map_err(|e| format!("Failed to encode PNG: {}", e))?;
+    } else {
+        let rgb: Vec<u8> = image.pixels().flat_map(|p| [p[0], p[1], p[2]]).collect();
+        let mut encoder = JpegEncoder::new_with_quality(&mut out, JPEG_QUALITY);
+        if let Some(icc) = icc {
+            let _ = encoder.set_icc_profile(icc);
+        }
+        encoder.write_image(&rgb, width, height, ExtendedColorType::Rgb8)
+            .map_err(|e| format!("Failed to encode JPEG: {}", e))?;
+    }
+    Ok(out)
+}
+
+fn file_name(path: &str) -> Option<String> {
+    Path::new(path).file_name().map(|name| name.to_string_lossy().into_owned())
+}
+
+impl AssetStore {
+    pub fn new(dir: PathBuf) -> Self {
+        AssetStore { dir }
+    }
+
+    /// Where the contents of asset `id` are stored. Ids are checked first,
+    /// so one can't name a file outside the store.
+    pub fn path(&self, id: &str) -> Result<PathBuf, String> {
+        if id.len() != 64 || !id.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(format!("Invalid asset id: {}", id));
+        }
+        Ok(self.dir.join(id))
+    }
+
+    fn info_path(&self, id: &str) -> Result<PathBuf, String> {
+        Ok(self.path(id)?.with_extension("json"))
+    }
+
+    pub fn info(&self, id: &str) -> Result<AssetInfo, String> {
+        let json = fs::read(self.info_path(id)?).map_err(|_| format!("No such asset: {}", id))?;
+        serde_json::from_slice(&json).map_err(|e| format!("Invalid asset record {}: {}", id, e))
+    }
+
+    pub fn write_info(&self, asset: &AssetInfo) -> Result<(), String> {
+        let json = serde_json::to_vec_pretty(asset).map_err(|e| format!("Failed to serialize asset record: {}", e))?;
+        write_atomic(&self.info_path(&asset.id)?.to_string_lossy(), &json).map_err(String::from)
+    }
+
+    pub fn read(&self, id: &str) -> Result<Vec<u8>, String> {
+        fs::read(self.path(id)?).map_err(|_| format!("No such asset: {}", id))
+    }
+
+    /// Store `bytes` unless the same contents are stored already. The
+    /// record is written after the contents, so a store interrupted in
+    /// between is finished by the next import rather than trusted.
+    pub fn ingest(&self, bytes: &[u8], name: Option<String>) -> Result<ImportedAsset, String> {
+        let id = hex_digest(bytes);
+        if let Ok(asset) = self.info(&id) {
+            if self.path(&id)?.exists() {
+                return Ok(ImportedAsset { asset, existing: true });
+            }
+        }
+
+        let mime_type = sniff_mime(bytes);
+        let pixels = dimensions(bytes, mime_type);
+        let raster = SourceFormat::detect(bytes).is_some();
+        let asset = AssetInfo {
+            mime_type: mime_type.to_string(),
+            size: bytes.len() as u64,
+            width: pixels.map(|(width, _)| width),
+            height: pixels.map(|(_, height)| height),
+            name,
+            imported_at: now_millis(),
+            original: None,
+            proxies: Vec::new(),
+            color_profile: raster.then(|| color::source_profile(bytes).1),
+            converted: Vec::new(),
+            metadata: raster.then(|| metadata::read(bytes)),
+            thumb_hash: placeholder::thumb_hash(bytes),
+            attribution: None,
+            id,
+        };
+        fs::create_dir_all(&self.dir).map_err(|e| format!("Failed to create asset store: {}", e))?;
+        write_atomic(&self.path(&asset.id)?.to_string_lossy(), bytes)?;
+        self.write_info(&asset)?;
+        Ok(ImportedAsset { asset, existing: false })
+    }
+
+    /// Store `bytes` as `ingest` does, and for an image that isn't in
+    /// `space` a version converted to it, linked to the original. The
+    /// asset to place is returned: the converted version where there is one.
+    /// SVG is stored with scripts and other active content removed.
+    pub fn import(&self, bytes: &[u8], name: Option<String>, space: WorkingSpace) -> Result<ImportedAsset, String> {
+        if sniff_mime(bytes) == "image/svg+xml" {
+            return self.ingest(sanitize(bytes)?.svg.as_bytes(), name);
+        }
+        let ImportedAsset { asset: mut original, existing } = self.ingest(bytes, name)?;
+        if original.color_profile.is_none() {
+            return Ok(ImportedAsset { asset: original, existing });
+        }
+        let working = ColorProfileInfo::working(space);
+        for converted in original.converted.iter().filter_map(|id| self.info(id).ok()) {
+            if converted.color_profile.as_ref() == Some(&working) {
+                return Ok(ImportedAsset { asset: converted, existing });
+            }
+        }
+        let Some(image) = color::convert(bytes, space)? else {
+            return Ok(ImportedAsset { asset: original, existing });
+        };
+
+        let mut converted = self.ingest(&encode(&image, space.icc())?, original.name.clone())?.asset;
+        converted.original = Some(original.id.clone());
+        converted.color_profile = Some(working);
+        self.write_info(&converted)?;
+        original.converted.push(converted.id.clone());
+        self.write_info(&original)?;
+        Ok(ImportedAsset { asset: converted, existing })
+    }
+}
+
+/// Add a file or pasted contents to the asset store, returning the asset to
+/// reference from the document. Contents already stored are not stored
+/// again; the existing asset is returned. Images in another color space
+/// than the document's are converted to it.
+#[command(async)]
+pub fn import_asset(
+    store: State<'_, AssetStore>,
+    source: AssetSource,
+    options: Option<ImportAssetOptions>,
+) -> Result<ImportedAsset, String> {
+    let space = options.unwrap_or_default().working_space;
+    match source {
+        AssetSource::Path { path } => {
+            let bytes = fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+            store.import(&bytes, file_name(&path), space)
+        }
+        AssetSource::Bytes { data, name } => {
+            let bytes = BASE64.decode(data.as_bytes()).map_err(|e| format!("Invalid asset data: {}", e))?;
+            store.import(&bytes, name, space)
+        }
+    }
+}
+
+/// What is known of an asset: its type and size, and for images their
+/// color profile, metadata such as resolution and capture date, and a
+/// placeholder hash. Images stored before placeholders were made get one
+/// the first time they are asked about.
+#[command(async)]
+pub fn get_asset_info(store: State<'_, AssetStore>, id: String) -> Result<AssetInfo, String> {
+    let mut asset = store.info(&id)?;
+    if asset.thumb_hash.is_none() && asset.color_profile.is_some() {
+        asset.thumb_hash = placeholder::thumb_hash(&store.read(&id)?);
+        if asset.thumb_hash.is_some() {
+            store.write_info(&asset)?;
+        }
+    }
+    Ok(asset)
+}
+
+/// The stored contents of an asset, base64-encoded.
+#[command(async)]
+pub fn read_asset(store: State<'_, AssetStore>, id: String) -> Result<String, String> {
+    store.read(&id).map(|bytes| BASE64.encode(bytes))
+}
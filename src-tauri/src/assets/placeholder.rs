@@ -0,0 +1,148 @@
+//! ThumbHash placeholders: a few dozen bytes describing an image's colors,
+//! aspect ratio and transparency, which the frontend draws as a blurred
+//! stand-in the moment a document opens, while the image itself loads.
+//! The encoding is Evan Wallace's, so any ThumbHash decoder reads it.
+
+use crate::import::raster::{self, SourceFormat};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use image::imageops;
+use std::f32::consts::PI;
+
+/// Largest side of the image a hash is taken from, as the format requires.
+const MAX_SIZE: u32 = 100;
+
+/// A channel's DCT: the constant term, the varying terms scaled to 0-1,
+/// and the scale they were divided by.
+struct Coefficients {
+    dc: f32,
+    ac: Vec<f32>,
+    scale: f32,
+}
+
+/// The lowest `nx` x `ny` frequencies of a `width` x `height` channel,
+/// keeping only the triangle of them below the diagonal.
+fn encode_channel(channel: &[f32], width: usize, height: usize, nx: usize, ny: usize) -> Coefficients {
+    let mut coefficients = Coefficients { dc: 0.0, ac: Vec::new(), scale: 0.0 };
+    let mut fx = vec![0.0; width];
+    for cy in 0..ny {
+        let mut cx = 0;
+        while cx * ny < nx * (ny - cy) {
+            fx.iter_mut().enumerate().for_each(|(x, f)| *f = (PI / width as f32 * cx as f32 * (x as f32 + 0.5)).cos());
+            let mut f = 0.0;
+            for y in 0..height {
+                let fy = (PI / height as f32 * cy as f32 * (y as f32 + 0.5)).cos();
+                for (x, fx) in fx.iter().enumerate() {
+                    f += channel[x + y * width] * fx * fy;
+                }
+            }
+            f /= (width * height) as f32;
+            if cx > 0 || cy > 0 {
+                coefficients.ac.push(f);
+                coefficients.scale = coefficients.scale.max(f.abs());
+            } else {
+                coefficients.dc = f;
+            }
+            cx += 1;
+        }
+    }
+    if coefficients.scale > 0.0 {
+        let scale = coefficients.scale;
+        coefficients.ac.iter_mut().for_each(|f| *f = 0.5 + 0.5 / scale * *f);
+    }
+    coefficients
+}
+
+/// The ThumbHash of an RGBA image no larger than 100 x 100.
+fn encode(width: usize, height: usize, rgba: &[u8]) -> Vec<u8> {
+    let (mut avg_r, mut avg_g, mut avg_b, mut avg_a) = (0.0, 0.0, 0.0, 0.0);
+    for p in rgba.chunks_exact(4) {
+        let alpha = p[3] as f32 / 255.0;
+        avg_r += alpha / 255.0 * p[0] as f32;
+        avg_g += alpha / 255.0 * p[1] as f32;
+        avg_b += alpha / 255.0 * p[2] as f32;
+        avg_a += alpha;
+    }
+    if avg_a > 0.0 {
+        avg_r /= avg_a;
+        avg_g /= avg_a;
+        avg_b /= avg_a;
+    }
+
+    let has_alpha = avg_a < (width * height) as f32;
+    // Luminance gets fewer terms when alpha needs room
+    let l_limit = if has_alpha { 5 } else { 7 };
+    let longest = width.max(height) as f32;
+    let lx = ((l_limit * width) as f32 / longest).round().max(1.0) as usize;
+    let ly = ((l_limit * height) as f32 / longest).round().max(1.0) as usize;
+
+    // Composited over the average color, in luminance, two chroma
+    // channels and alpha
+    let pixels = width * height;
+    let (mut l, mut p, mut q, mut a) =
+        (Vec::with_capacity(pixels), Vec::with_capacity(pixels), Vec::with_capacity(pixels), Vec::with_capacity(pixels));
+    for px in rgba.chunks_exact(4) {
+        let alpha = px[3] as f32 / 255.0;
+        let r = avg_r * (1.0 - alpha) + alpha / 255.0 * px[0] as f32;
+        let g = avg_g * (1.0 - alpha) + alpha / 255.0 * px[1] as f32;
+        let b = avg_b * (1.0 - alpha) + alpha / 255.0 * px[2] as f32;
+        l.push((r + g + b) / 3.0);
+        p.push((r + g) / 2.0 - b);
+        q.push(r - g);
+        a.push(alpha);
+    }
+
+    let l = encode_channel(&l, width, height, lx.max(3), ly.max(3));
+    let p = encode_channel(&p, width, height, 3, 3);
+    let q = encode_channel(&q, width, height, 3, 3);
+    let a = if has_alpha {
+        encode_channel(&a, width, height, 5, 5)
+    } else {
+        Coefficients { dc: 1.0, ac: Vec::new(), scale: 1.0 }
+    };
+
+    let is_landscape = width > height;
+    let header24 = (63.0 * l.dc).round() as u32
+        | ((31.5 + 31.5 * p.dc).round() as u32) << 6
+        | ((31.5 + 31.5 * q.dc).round() as u32) << 12
+        | ((31.0 * l.scale).round() as u32) << 18
+        | (has_alpha as u32) << 23;
+    let header16 = (if is_landscape { ly } else { lx }) as u16
+        | ((63.0 * p.scale).round() as u16) << 3
+        | ((63.0 * q.scale).round() as u16) << 9
+        | (is_landscape as u16) << 15;
+    let mut hash = vec![
+        (header24 & 255) as u8,
+        (header24 >> 8 & 255) as u8,
+        (header24 >> 16) as u8,
+        (header16 & 255) as u8,
+        (header16 >> 8) as u8,
+    ];
+    if has_alpha {
+        hash.push((15.0 * a.dc).round() as u8 | ((15.0 * a.scale).round() as u8) << 4);
+    }
+
+    // The varying terms, four bits each
+    let mut is_odd = false;
+    for f in l.ac.iter().chain(&p.ac).chain(&q.ac).chain(&a.ac) {
+        let u = (15.0 * f).round() as u8;
+        match hash.last_mut() {
+            Some(last) if is_odd => *last |= u << 4,
+            _ => hash.push(u),
+        }
+        is_odd = !is_odd;
+    }
+    hash
+}
+
+/// The ThumbHash of raster image bytes, base64-encoded, or nothing for
+/// files that aren't raster images or can't be decoded.
+pub fn thumb_hash(bytes: &[u8]) -> Option<String> {
+    SourceFormat::detect(bytes)?;
+    let image = raster::decode(bytes).ok()?;
+    let (width, height) = image.dimensions();
+    let scale = (MAX_SIZE as f64 / width.max(height) as f64).min(1.0);
+    let (small_width, small_height) =
+        (((width as f64 * scale).round() as u32).max(1), ((height as f64 * scale).round() as u32).max(1));
+    let small = imageops::thumbnail(&image, small_width, small_height);
+    Some(BASE64.encode(encode(small_width as usize, small_height as usize, small.as_raw())))
+}
@@ -0,0 +1,121 @@
+//! Downscaled proxies of photos placed far smaller than they were shot, so
+//! a 50-megapixel image in a 200px frame costs the document and the
+//! webview no more than the frame shows. A proxy is an asset of its own,
+//! linked back to the original unless asked not to be, so export can
+//! still reach the full-size image.
+
+use super::color::decode_tagged;
+use super::{encode, AssetInfo, AssetStore};
+use crate::import::raster::SourceFormat;
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use tauri::{command, State};
+
+const DEFAULT_MAX_DIMENSION: u32 = 2048;
+const DEFAULT_PIXEL_DENSITY: f64 = 2.0;
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ProxyOptions {
+    /// Longest side a proxy may have, however large it is placed; 2048
+    /// pixels when unset.
+    pub max_dimension: Option<u32>,
+    /// Image pixels per unit of placed size, so the proxy stays sharp on
+    /// high-density screens; 2 when unset.
+    pub pixel_density: Option<f64>,
+    /// Make the proxy without a link to the original, for documents that
+    /// should not depend on the full-size file. A proxy already linked by
+    /// an earlier request stays linked, as it is the same asset.
+    pub unlinked: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssetProxy {
+    /// The proxy, or the original when it is no larger than needed.
+    pub asset: AssetInfo,
+    pub downscaled: bool,
+}
+
+/// `image` resized to `width` x `height`. Pixels are premultiplied while
+/// filtering so transparent edges don't pick up the color of what is
+/// hidden under them.
+fn resize(image: &RgbaImage, width: u32, height: u32, alpha: bool) -> RgbaImage {
+    if !alpha {
+        return imageops::resize(image, width, height, FilterType::Lanczos3);
+    }
+    let mut premultiplied = image.clone();
+    for p in premultiplied.pixels_mut() {
+        let a = p[3] as u32;
+        p.0[..3].iter_mut().for_each(|c| *c = ((*c as u32 * a + 127) / 255) as u8);
+    }
+    let mut resized = imageops::resize(&premultiplied, width, height, FilterType::Lanczos3);
+    for p in resized.pixels_mut() {
+        let a = p[3] as u32;
+        p.0[..3].iter_mut().for_each(|c| *c = (*c as u32 * 255 + a / 2).checked_div(a).unwrap_or(0).min(255) as u8);
+    }
+    resized
+}
+
+/// A version of image asset `id` no larger than needed to show it at
+/// `width` x `height`, made and stored the first time it is asked for.
+pub fn proxy(store: &AssetStore, id: &str, width: f64, height: f64, options: &ProxyOptions) -> Result<AssetProxy, String> {
+    let mut original = store.info(id)?;
+    let (Some(full_width), Some(full_height)) = (original.width, original.height) else {
+        return Err(format!("Asset {} is not an image", id));
+    };
+    let max_dimension = options.max_dimension.unwrap_or(DEFAULT_MAX_DIMENSION).max(1);
+    let density = options.pixel_density.unwrap_or(DEFAULT_PIXEL_DENSITY).max(0.1);
+    let longest = ((width.max(height) * density).ceil() as u32).clamp(1, max_dimension);
+
+    let full_longest = full_width.max(full_height);
+    if full_longest <= longest || original.mime_type == "image/svg+xml" {
+        return Ok(AssetProxy { asset: original, downscaled: false });
+    }
+    for proxy in original.proxies.iter().filter_map(|proxy| store.info(proxy).ok()) {
+        let size = proxy.width.unwrap_or_default().max(proxy.height.unwrap_or_default());
+        if size == longest {
+            return Ok(AssetProxy { asset: proxy, downscaled: true });
+        }
+    }
+
+    let bytes = store.read(id)?;
+    if SourceFormat::detect(&bytes).is_none() {
+        return Err(format!("Asset {} can't be downscaled", id));
+    }
+    let (image, icc) = decode_tagged(&bytes)?;
+    let scale = longest as f64 / full_longest as f64;
+    let (proxy_width, proxy_height) = (
+        ((image.width() as f64 * scale).round() as u32).max(1),
+        ((image.height() as f64 * scale).round() as u32).max(1),
+    );
+    let alpha = image.pixels().any(|p| p[3] < 255);
+    let resized = resize(&image, proxy_width, proxy_height, alpha);
+    drop(image);
+
+    // Tagged as the original is, so it keeps the original's colors
+    let mut proxy = store.ingest(&encode(&resized, icc)?, original.name.clone())?.asset;
+    if !options.unlinked && proxy.original.is_none() {
+        proxy.original = Some(original.id.clone());
+        store.write_info(&proxy)?;
+    }
+    if !original.proxies.contains(&proxy.id) {
+        original.proxies.push(proxy.id.clone());
+        store.write_info(&original)?;
+    }
+    Ok(AssetProxy { asset: proxy, downscaled: true })
+}
+
+/// The asset to place for image `id` in a `width` x `height` frame: a
+/// downscaled proxy when the image is much larger, otherwise the image.
+#[command(async)]
+pub fn create_asset_proxy(
+    store: State<'_, AssetStore>,
+    id: String,
+    width: f64,
+    height: f64,
+    options: Option<ProxyOptions>,
+) -> Result<AssetProxy, String> {
+    proxy(&store, &id, width, height, &options.unwrap_or_default())
+}
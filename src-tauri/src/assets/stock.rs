@@ -0,0 +1,388 @@
+//! Stock photos from Unsplash and Pexels, with the user's own API key for
+//! each: searching, thumbnails for the results, and downloading a photo at
+//! full size into the asset store. Both licenses ask that the photographer
+//! be credited, so who took a photo and where it came from is recorded on
+//! its asset.
+//!
+//! Keys are kept in the app's config directory and never sent back to the
+//! webview.
+
+use super::color::WorkingSpace;
+use super::{AssetStore, ImportedAsset};
+use crate::atomic::write_atomic;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+use tauri::{command, State};
+use tauri_plugin_http::reqwest::{header::AUTHORIZATION, Client, RequestBuilder, Url};
+
+const UNSPLASH_API_URL: &str = "https://api.unsplash.com";
+const PEXELS_API_URL: &str = "https://api.pexels.com/v1";
+/// Hosts thumbnails are fetched from; nothing else is fetched on the
+/// webview's say-so.
+const IMAGE_HOSTS: [&str; 2] = ["images.unsplash.com", "images.pexels.com"];
+/// Sent with links back to Unsplash, as its guidelines require.
+const REFERRAL: &str = "utm_source=DesignLibre&utm_medium=referral";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+const DEFAULT_PER_PAGE: u32 = 30;
+/// Most results either service returns a page.
+const MAX_PER_PAGE: u32 = 80;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum StockProvider {
+    Unsplash,
+    Pexels,
+}
+
+impl StockProvider {
+    fn name(self) -> &'static str {
+        match self {
+            StockProvider::Unsplash => "Unsplash",
+            StockProvider::Pexels => "Pexels",
+        }
+    }
+
+    fn license(self) -> &'static str {
+        match self {
+            StockProvider::Unsplash => "Unsplash License",
+            StockProvider::Pexels => "Pexels License",
+        }
+    }
+}
+
+/// Who to credit for a stock photo, kept on its asset.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StockAttribution {
+    /// `Unsplash` or `Pexels`.
+    pub provider: String,
+    pub photo_id: String,
+    pub photographer: String,
+    pub photographer_url: Option<String>,
+    /// The photo's page on the provider's site.
+    pub source_url: String,
+    pub license: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StockPhoto {
+    pub id: String,
+    pub width: u32,
+    pub height: u32,
+    pub description: Option<String>,
+    /// Average color, as `#rrggbb`, to fill the space while it loads.
+    pub color: Option<String>,
+    pub thumbnail_url: String,
+    /// Around 1000 pixels wide, for a larger preview.
+    pub preview_url: String,
+    pub attribution: StockAttribution,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StockSearch {
+    pub photos: Vec<StockPhoto>,
+    pub total: u64,
+    pub page: u32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StockProviderInfo {
+    pub provider: StockProvider,
+    pub name: String,
+    /// Whether a key has been set, which searching needs.
+    pub configured: bool,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct StockSearchOptions {
+    /// From 1; the first page when unset.
+    pub page: Option<u32>,
+    /// 30 when unset.
+    pub per_page: Option<u32>,
+    /// `landscape`, `portrait` or `square`.
+    pub orientation: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StockThumbnail {
+    /// Base64-encoded image data.
+    pub data: String,
+    pub mime_type: String,
+}
+
+/// API keys by provider, and the client that uses them.
+pub struct StockPhotos {
+    store: PathBuf,
+    keys: Mutex<BTreeMap<StockProvider, String>>,
+    client: Client,
+}
+
+fn text(value: &Value, key: &str) -> Option<String> {
+    value.get(key)?.as_str().map(str::to_string).filter(|s| !s.is_empty())
+}
+
+fn with_referral(url: &str) -> String {
+    let separator = if url.contains('?') { '&' } else { '?' };
+    format!("{}{}{}", url, separator, REFERRAL)
+}
+
+fn unexpected(provider: StockProvider) -> String {
+    format!("Unexpected response from {}", provider.name())
+}
+
+fn unsplash_photo(photo: &Value) -> Option<StockPhoto> {
+    let user = photo.get("user")?;
+    let urls = photo.get("urls")?;
+    Some(StockPhoto {
+        id: text(photo, "id")?,
+        width: photo.get("width")?.as_u64()? as u32,
+        height: photo.get("height")?.as_u64()? as u32,
+        description: text(photo, "description").or_else(|| text(photo, "alt_description")),
+        color: text(photo, "color"),
+        thumbnail_url: text(urls, "small")?,
+        preview_url: text(urls, "regular")?,
+        attribution: StockAttribution {
+            provider: StockProvider::Unsplash.name().to_string(),
+            photo_id: text(photo, "id")?,
+            photographer: text(user, "name")?,
+            photographer_url: user.get("links").and_then(|links| text(links, "html")).map(|url| with_referral(&url)),
+            source_url: with_referral(&text(photo.get("links")?, "html")?),
+            license: StockProvider::Unsplash.license().to_string(),
+        },
+    })
+}
+
+fn pexels_photo(photo: &Value) -> Option<StockPhoto> {
+    let src = photo.get("src")?;
+    let id = photo.get("id")?.as_u64()?.to_string();
+    Some(StockPhoto {
+        width: photo.get("width")?.as_u64()? as u32,
+        height: photo.get("height")?.as_u64()? as u32,
+        description: text(photo, "alt"),
+        color: text(photo, "avg_color"),
+        thumbnail_url: text(src, "medium")?,
+        preview_url: text(src, "large")?,
+        attribution: StockAttribution {
+            provider: StockProvider::Pexels.name().to_string(),
+            photo_id: id.clone(),
+            photographer: text(photo, "photographer")?,
+            photographer_url: text(photo, "photographer_url"),
+            source_url: text(photo, "url")?,
+            license: StockProvider::Pexels.license().to_string(),
+        },
+        id,
+    })
+}
+
+impl StockPhotos {
+    pub fn load(store: PathBuf) -> Result<Self, String> {
+        let keys = fs::read(&store).ok().and_then(|bytes| serde_json::from_slice(&bytes).ok()).unwrap_or_default();
+        let client = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+        Ok(StockPhotos { store, keys: Mutex::new(keys), client })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<StockProvider, String>> {
+        self.keys.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn set_key(&self, provider: StockProvider, key: Option<String>) -> Result<(), String> {
+        let mut keys = self.lock();
+        match key.map(|key| key.trim().to_string()).filter(|key| !key.is_empty()) {
+            Some(key) => keys.insert(provider, key),
+            None => keys.remove(&provider),
+        };
+        if let Some(parent) = self.store.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to create settings directory: {}", e))?;
+        }
+        let json = serde_json::to_vec_pretty(&*keys).map_err(|e| format!("Failed to encode stock photo keys: {}", e))?;
+        write_atomic(&self.store.to_string_lossy(), &json).map_err(String::from)
+    }
+
+    pub fn providers(&self) -> Vec<StockProviderInfo> {
+        let keys = self.lock();
+        [StockProvider::Unsplash, StockProvider::Pexels]
+            .into_iter()
+            .map(|provider| StockProviderInfo { provider, name: provider.name().to_string(), configured: keys.contains_key(&provider) })
+            .collect()
+    }
+
+    /// A request to `provider`'s API, signed with the user's key.
+    fn request(&self, provider: StockProvider, url: Url) -> Result<RequestBuilder, String> {
+        let key = self.lock().get(&provider).cloned();
+        let key = key.ok_or_else(|| format!("Set an API key for {} first", provider.name()))?;
+        let authorization = match provider {
+            StockProvider::Unsplash => format!("Client-ID {}", key),
+            StockProvider::Pexels => key,
+        };
+        Ok(self.client.get(url).header(AUTHORIZATION, authorization).header("Accept-Version", "v1"))
+    }
+
+    async fn send(&self, provider: StockProvider, request: RequestBuilder) -> Result<Vec<u8>, String> {
+        let response = request
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| match e.status().map(|status| status.as_u16()) {
+                Some(401 | 403) => format!("{} did not accept the API key", provider.name()),
+                Some(429) => format!("Too many requests to {}; try again later", provider.name()),
+                _ => format!("Failed to reach {}: {}", provider.name(), e),
+            })?;
+        let body = response.bytes().await.map_err(|e| format!("Failed to download from {}: {}", provider.name(), e))?;
+        Ok(body.to_vec())
+    }
+
+    async fn get_json(&self, provider: StockProvider, url: Url) -> Result<Value, String> {
+        let body = self.send(provider, self.request(provider, url)?).await?;
+        serde_json::from_slice(&body).map_err(|_| unexpected(provider))
+    }
+
+    pub async fn search(&self, provider: StockProvider, query: &str, options: &StockSearchOptions) -> Result<StockSearch, String> {
+        let page = options.page.unwrap_or(1).max(1);
+        let per_page = options.per_page.unwrap_or(DEFAULT_PER_PAGE).clamp(1, MAX_PER_PAGE);
+        let mut params = vec![("query", query.to_string()), ("page", page.to_string()), ("per_page", per_page.to_string())];
+        if let Some(orientation) = &options.orientation {
+            // Unsplash calls square `squarish`
+            let orientation = match (provider, orientation.as_str()) {
+                (StockProvider::Unsplash, "square") => "squarish",
+                (_, orientation) => orientation,
+            };
+            params.push(("orientation", orientation.to_string()));
+        }
+
+        let (endpoint, results, total) = match provider {
+            StockProvider::Unsplash => (format!("{}/search/photos", UNSPLASH_API_URL), "results", "total"),
+            StockProvider::Pexels => (format!("{}/search", PEXELS_API_URL), "photos", "total_results"),
+        };
+        let url = Url::parse_with_params(&endpoint, &params).map_err(|e| e.to_string())?;
+        let found = self.get_json(provider, url).await?;
+        let photos = found.get(results).and_then(Value::as_array).ok_or_else(|| unexpected(provider))?;
+        let photos = photos.iter().filter_map(|photo| match provider {
+            StockProvider::Unsplash => unsplash_photo(photo),
+            StockProvider::Pexels => pexels_photo(photo),
+        });
+        Ok(StockSearch {
+            photos: photos.collect(),
+            total: found.get(total).and_then(Value::as_u64).unwrap_or_default(),
+            page,
+        })
+    }
+
+    /// A thumbnail or preview from one of the providers' image hosts.
+    pub async fn thumbnail(&self, url: &str) -> Result<StockThumbnail, String> {
+        let url = Url::parse(url).map_err(|e| format!("Invalid image URL {}: {}", url, e))?;
+        if url.scheme() != "https" || !url.host_str().is_some_and(|host| IMAGE_HOSTS.contains(&host)) {
+            return Err(format!("Not a stock photo URL: {}", url));
+        }
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("Failed to download thumbnail: {}", e))?;
+        let mime_type = response
+            .headers()
+            .get("content-type")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("image/jpeg")
+            .to_string();
+        let body = response.bytes().await.map_err(|e| format!("Failed to download thumbnail: {}", e))?;
+        Ok(StockThumbnail { data: BASE64.encode(&body), mime_type })
+    }
+
+    /// Photo `id` at full size, with its attribution. Unsplash counts a
+    /// download only through its download endpoint, which its terms ask
+    /// apps to use.
+    async fn download(&self, provider: StockProvider, id: &str) -> Result<(Vec<u8>, StockAttribution), String> {
+        if id.is_empty() || !id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_') {
+            return Err(format!("Invalid photo id: {}", id));
+        }
+        let (photo, full_url) = match provider {
+            StockProvider::Unsplash => {
+                let url = Url::parse(&format!("{}/photos/{}", UNSPLASH_API_URL, id)).map_err(|e| e.to_string())?;
+                let photo = self.get_json(provider, url).await?;
+                let location = photo.get("links").and_then(|links| text(links, "download_location")).ok_or_else(|| unexpected(provider))?;
+                let location = Url::parse(&location).map_err(|_| unexpected(provider))?;
+                let download = self.get_json(provider, location).await?;
+                let full_url = text(&download, "url").ok_or_else(|| unexpected(provider))?;
+                (unsplash_photo(&photo), full_url)
+            }
+            StockProvider::Pexels => {
+                let url = Url::parse(&format!("{}/photos/{}", PEXELS_API_URL, id)).map_err(|e| e.to_string())?;
+                let photo = self.get_json(provider, url).await?;
+                let full_url = photo.get("src").and_then(|src| text(src, "original")).ok_or_else(|| unexpected(provider))?;
+                (pexels_photo(&photo), full_url)
+            }
+        };
+        let attribution = photo.ok_or_else(|| unexpected(provider))?.attribution;
+        let full_url = Url::parse(&full_url).map_err(|_| unexpected(provider))?;
+        let bytes = self.send(provider, self.client.get(full_url)).await?;
+        Ok((bytes, attribution))
+    }
+}
+
+#[command]
+pub fn list_stock_photo_providers(stock: State<'_, StockPhotos>) -> Vec<StockProviderInfo> {
+    stock.providers()
+}
+
+/// Set the user's API key for `provider`, or remove it when unset.
+#[command]
+pub fn set_stock_photo_key(stock: State<'_, StockPhotos>, provider: StockProvider, key: Option<String>) -> Result<(), String> {
+    stock.set_key(provider, key)
+}
+
+#[command]
+pub async fn search_stock_photos(
+    stock: State<'_, StockPhotos>,
+    provider: StockProvider,
+    query: String,
+    options: Option<StockSearchOptions>,
+) -> Result<StockSearch, String> {
+    stock.search(provider, &query, &options.unwrap_or_default()).await
+}
+
+/// A search result's thumbnail or preview, by its URL.
+#[command]
+pub async fn fetch_stock_thumbnail(stock: State<'_, StockPhotos>, url: String) -> Result<StockThumbnail, String> {
+    stock.thumbnail(&url).await
+}
+
+/// Download a stock photo at full size into the asset store, converted to
+/// `working_space` as `import_asset` converts images, with its
+/// attribution recorded on the asset and on the original it was
+/// converted from.
+#[command]
+pub async fn download_stock_photo(
+    stock: State<'_, StockPhotos>,
+    assets: State<'_, AssetStore>,
+    provider: StockProvider,
+    id: String,
+    working_space: Option<WorkingSpace>,
+) -> Result<ImportedAsset, String> {
+    let (bytes, attribution) = stock.download(provider, &id).await?;
+    let name = format!("{}-{}", attribution.provider.to_lowercase(), id);
+    let mut imported = assets.import(&bytes, Some(name), working_space.unwrap_or_default())?;
+    imported.asset.attribution = Some(attribution.clone());
+    assets.write_info(&imported.asset)?;
+    if let Some(original) = &imported.asset.original {
+        let mut original = assets.info(original)?;
+        original.attribution = Some(attribution);
+        assets.write_info(&original)?;
+    }
+    Ok(imported)
+}
@@ -0,0 +1,77 @@
+use crate::error::{FileError, FileErrorKind};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+fn temp_path_for(target: &Path) -> Result<PathBuf, FileError> {
+    let display = target.to_string_lossy();
+    let name = target
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| FileError::new(FileErrorKind::InvalidPath, &display, "Path has no file name"))?;
+
+    let dir = match target.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    if !dir.is_dir() {
+        return Err(FileError::new(FileErrorKind::InvalidPath, &display, "Parent directory does not exist"));
+    }
+
+    Ok(dir.join(format!(".{}.{}.tmp", name, std::process::id())))
+}
+
+/// Write `bytes` to `path` so that readers only ever observe the old or the new
+/// contents: the data goes to a temp file in the same directory, is fsynced, and
+/// is then renamed over the target.
+pub fn write_atomic(path: &str, bytes: &[u8]) -> Result<(), FileError> {
+    let target = Path::new(path);
+    let tmp = temp_path_for(target)?;
+
+    let result = write_and_sync(&tmp, target, bytes)
+        .and_then(|_| fs::rename(&tmp, target).map_err(|e| FileError::from_io(&e, path, "replace file")));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+        return result;
+    }
+
+    sync_parent_dir(target);
+    Ok(())
+}
+
+fn write_and_sync(tmp: &Path, target: &Path, bytes: &[u8]) -> Result<(), FileError> {
+    let path = target.to_string_lossy();
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(tmp)
+        .map_err(|e| FileError::from_io(&e, &path, "create temporary file"))?;
+
+    // Keep the permissions of the file being replaced
+    if let Ok(meta) = fs::metadata(target) {
+        let _ = file.set_permissions(meta.permissions());
+    }
+
+    file.write_all(bytes)
+        .map_err(|e| FileError::from_io(&e, &path, "write file"))?;
+    file.sync_all()
+        .map_err(|e| FileError::from_io(&e, &path, "flush file to disk"))?;
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn sync_parent_dir(target: &Path) {
+    // Persist the rename itself; failure here is not fatal since the data is
+    // already durable in the renamed file.
+    if let Some(dir) = target.parent().filter(|p| !p.as_os_str().is_empty()) {
+        if let Ok(handle) = fs::File::open(dir) {
+            let _ = handle.sync_all();
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent_dir(_target: &Path) {}
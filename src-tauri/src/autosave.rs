@@ -0,0 +1,338 @@
+use crate::atomic::write_atomic;
+use crate::bundle::now_millis;
+use crate::commands::EncryptOptions;
+use crate::encryption;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+use tauri::{command, State};
+
+pub const DEFAULT_INTERVAL_MS: u64 = 30_000;
+const MIN_INTERVAL_MS: u64 = 1_000;
+const MAX_INTERVAL_MS: u64 = 24 * 60 * 60 * 1_000;
+/// Quiet period after the latest snapshot before it is written.
+const SETTLE_MS: u64 = 1_500;
+pub const SNAPSHOT_FILE: &str = "autosave.json";
+pub const META_FILE: &str = "meta.json";
+
+/// Sidecar written next to every autosave snapshot.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AutosaveMeta {
+    pub document_id: String,
+    pub source_path: Option<String>,
+    pub saved_at: u64,
+    pub size: u64,
+    pub pid: u32,
+    pub session_id: String,
+    /// The snapshot is sealed with the document's passphrase.
+    #[serde(default)]
+    pub encrypted: bool,
+}
+
+struct PendingSnapshot {
+    source_path: Option<String>,
+    content: String,
+    /// Set for documents saved with a passphrase, so the snapshot isn't
+    /// left on disk in plaintext.
+    encrypt: Option<EncryptOptions>,
+    first_received: u64,
+    last_received: u64,
+}
+
+struct AutosaveState {
+    enabled: bool,
+    interval_ms: u64,
+    pending: HashMap<String, PendingSnapshot>,
+    last_attempt: HashMap<String, u64>,
+    last_saved: HashMap<String, u64>,
+    last_error: Option<String>,
+    /// Bumped when a document's autosave is discarded, so a snapshot taken
+    /// from the queue before then is not written after it.
+    generations: HashMap<String, u64>,
+}
+
+impl AutosaveState {
+    fn generation(&self, document_id: &str) -> u64 {
+        self.generations.get(document_id).copied().unwrap_or(0)
+    }
+}
+
+struct Shared {
+    dir: PathBuf,
+    session_id: String,
+    state: Mutex<AutosaveState>,
+    wake: Condvar,
+    /// Held while a snapshot is written or a recovery directory removed, so
+    /// the two never interleave.
+    io: Mutex<()>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutosaveStatus {
+    pub enabled: bool,
+    pub interval_ms: u64,
+    pub pending: Vec<String>,
+    pub last_saved_at: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+/// Debounces document snapshots from the frontend and writes them to a
+/// per-document recovery directory from a background thread.
+pub struct AutosaveManager {
+    shared: Arc<Shared>,
+}
+
+/// Map a document id to a directory name that is safe on every platform.
+pub fn sanitize_id(id: &str) -> String {
+    let cleaned: String = id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+
+    if cleaned.is_empty() { "untitled".to_string() } else { cleaned }
+}
+
+impl AutosaveManager {
+    pub fn new(dir: PathBuf, session_id: String) -> Self {
+        let shared = Arc::new(Shared {
+            dir,
+            session_id,
+            state: Mutex::new(AutosaveState {
+                enabled: true,
+                interval_ms: DEFAULT_INTERVAL_MS,
+                pending: HashMap::new(),
+                last_attempt: HashMap::new(),
+                last_saved: HashMap::new(),
+                last_error: None,
+                generations: HashMap::new(),
+            }),
+            wake: Condvar::new(),
+            io: Mutex::new(()),
+        });
+
+        let worker = Arc::clone(&shared);
+        thread::Builder::new()
+            .name("autosave".into())
+            .spawn(move || run_worker(worker))
+            .expect("failed to spawn autosave thread");
+
+        AutosaveManager { shared }
+    }
+
+    pub fn document_dir(&self, document_id: &str) -> PathBuf {
+        self.shared.dir.join(sanitize_id(document_id))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, AutosaveState> {
+        self.shared.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn submit(&self, document_id: String, source_path: Option<String>, content: String, encrypt: Option<EncryptOptions>) {
+        let now = now_millis();
+        let mut state = self.lock();
+        let first_received = state.pending.get(&document_id).map_or(now, |p| p.first_received);
+        state.pending.insert(document_id, PendingSnapshot {
+            source_path,
+            content,
+            encrypt,
+            first_received,
+            last_received: now,
+        });
+        drop(state);
+        self.shared.wake.notify_all();
+    }
+
+    /// Drop any pending snapshot and the on-disk recovery copy, e.g. after the
+    /// document was saved explicitly or closed cleanly.
+    pub fn discard(&self, document_id: &str) -> Result<(), String> {
+        let _io = self.shared.io.lock().unwrap_or_else(|e| e.into_inner());
+        let mut state = self.lock();
+        state.pending.remove(document_id);
+        state.last_attempt.remove(document_id);
+        state.last_saved.remove(document_id);
+        *state.generations.entry(document_id.to_string()).or_insert(0) += 1;
+        drop(state);
+
+        let dir = self.document_dir(document_id);
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir)
+                .map_err(|e| format!("Failed to remove autosave: {}", e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Write `snapshot` unless the document's autosave was discarded since it
+/// was taken at `generation`, in which case there is nothing to do.
+fn write_current(shared: &Shared, document_id: &str, generation: u64, snapshot: &PendingSnapshot) -> Option<Result<u64, String>> {
+    let _io = shared.io.lock().unwrap_or_else(|e| e.into_inner());
+    let current = shared.state.lock().unwrap_or_else(|e| e.into_inner()).generation(document_id);
+    (current == generation).then(|| write_snapshot(shared, document_id, snapshot))
+}
+
+fn write_snapshot(shared: &Shared, document_id: &str, snapshot: &PendingSnapshot) -> Result<u64, String> {
+    let doc_dir = shared.dir.join(sanitize_id(document_id));
+    std::fs::create_dir_all(&doc_dir)
+        .map_err(|e| format!("Failed to create autosave directory: {}", e))?;
+
+    let saved_at = now_millis();
+    let meta = AutosaveMeta {
+        document_id: document_id.to_string(),
+        source_path: snapshot.source_path.clone(),
+        saved_at,
+        size: snapshot.content.len() as u64,
+        pid: std::process::id(),
+        session_id: shared.session_id.clone(),
+        encrypted: snapshot.encrypt.is_some(),
+    };
+    let meta_json = serde_json::to_vec_pretty(&meta)
+        .map_err(|e| format!("Failed to encode autosave metadata: {}", e))?;
+
+    let snapshot_path = doc_dir.join(SNAPSHOT_FILE).to_string_lossy().into_owned();
+    let content = snapshot.content.as_bytes();
+    let sealed = snapshot.encrypt.as_ref().map(|options| encryption::encrypt(content, &options.passphrase, &snapshot_path)).transpose()?;
+    write_atomic(&snapshot_path, sealed.as_deref().unwrap_or(content))?;
+    write_atomic(&doc_dir.join(META_FILE).to_string_lossy(), &meta_json)?;
+
+    Ok(saved_at)
+}
+
+/// Milliseconds until a pending snapshot should be written; zero means now.
+///
+/// A document is written at most once per interval, and only once edits have
+/// settled, unless it has been pending for a whole interval already.
+fn due_in(state: &AutosaveState, id: &str, snapshot: &PendingSnapshot, now: u64) -> u64 {
+    let interval = state.interval_ms;
+    let throttle = state
+        .last_attempt
+        .get(id)
+        .map_or(0, |t| t.saturating_add(interval).saturating_sub(now));
+    let settle = snapshot.last_received.saturating_add(SETTLE_MS)
+        .saturating_sub(now)
+        .min(snapshot.first_received.saturating_add(interval).saturating_sub(now));
+
+    throttle.max(settle)
+}
+
+fn run_worker(shared: Arc<Shared>) {
+    let mut state = shared.state.lock().unwrap_or_else(|e| e.into_inner());
+    loop {
+        if !state.enabled || state.pending.is_empty() {
+            state = shared.wake.wait(state).unwrap_or_else(|e| e.into_inner());
+            continue;
+        }
+
+        let now = now_millis();
+        let waits: Vec<(String, u64)> = state
+            .pending
+            .iter()
+            .map(|(id, snapshot)| (id.clone(), due_in(&state, id, snapshot, now)))
+            .collect();
+        let due: Vec<String> = waits.iter().filter(|(_, w)| *w == 0).map(|(id, _)| id.clone()).collect();
+
+        if due.is_empty() {
+            let next = waits.iter().map(|(_, w)| *w).min().unwrap_or(state.interval_ms);
+            state = shared
+                .wake
+                .wait_timeout(state, Duration::from_millis(next.max(1)))
+                .map(|(guard, _)| guard)
+                .unwrap_or_else(|e| e.into_inner().0);
+            continue;
+        }
+
+        let batch: Vec<(String, u64, PendingSnapshot)> = due
+            .into_iter()
+            .filter_map(|id| {
+                let generation = state.generation(&id);
+                state.pending.remove(&id).map(|s| (id, generation, s))
+            })
+            .collect();
+        drop(state);
+
+        let results: Vec<_> = batch
+            .into_iter()
+            .filter_map(|(id, generation, snapshot)| {
+                let result = write_current(&shared, &id, generation, &snapshot)?;
+                Some((id, generation, snapshot, result))
+            })
+            .collect();
+
+        state = shared.state.lock().unwrap_or_else(|e| e.into_inner());
+        for (id, generation, snapshot, result) in results {
+            if state.generation(&id) != generation {
+                continue;
+            }
+            // Failed writes are retried after the next interval rather than spinning on a failing disk
+            state.last_attempt.insert(id.clone(), now_millis());
+            match result {
+                Ok(saved_at) => {
+                    state.last_saved.insert(id, saved_at);
+                    state.last_error = None;
+                }
+                Err(e) => {
+                    state.last_error = Some(e);
+                    state.pending.entry(id).or_insert(snapshot);
+                }
+            }
+        }
+    }
+}
+
+#[command]
+pub fn autosave_snapshot(
+    manager: State<'_, AutosaveManager>,
+    document_id: String,
+    source_path: Option<String>,
+    content: String,
+    encrypt: Option<EncryptOptions>,
+) {
+    manager.submit(document_id, source_path, content, encrypt);
+}
+
+#[command]
+pub fn discard_autosave(manager: State<'_, AutosaveManager>, document_id: String) -> Result<(), String> {
+    manager.discard(&document_id)
+}
+
+#[command]
+pub fn set_autosave_enabled(manager: State<'_, AutosaveManager>, enabled: bool) {
+    manager.lock().enabled = enabled;
+    manager.shared.wake.notify_all();
+}
+
+#[command]
+pub fn set_autosave_interval(manager: State<'_, AutosaveManager>, interval_ms: u64) -> Result<(), String> {
+    if !(MIN_INTERVAL_MS..=MAX_INTERVAL_MS).contains(&interval_ms) {
+        return Err(format!("Autosave interval must be between {} and {} ms", MIN_INTERVAL_MS, MAX_INTERVAL_MS));
+    }
+
+    manager.lock().interval_ms = interval_ms;
+    manager.shared.wake.notify_all();
+    Ok(())
+}
+
+#[command]
+pub fn get_autosave_status(manager: State<'_, AutosaveManager>, document_id: Option<String>) -> AutosaveStatus {
+    let state = manager.lock();
+
+    let last_saved_at = match &document_id {
+        Some(id) => state.last_saved.get(id).copied(),
+        None => state.last_saved.values().max().copied(),
+    };
+
+    let mut pending: Vec<String> = state.pending.keys().cloned().collect();
+    pending.sort();
+
+    AutosaveStatus {
+        enabled: state.enabled,
+        interval_ms: state.interval_ms,
+        pending,
+        last_saved_at,
+        last_error: state.last_error.clone(),
+    }
+}
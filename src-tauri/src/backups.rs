@@ -0,0 +1,305 @@
+use crate::atomic::write_atomic;
+use crate::autosave::sanitize_id;
+use crate::bundle::now_millis;
+use crate::history::hex_digest;
+use crate::locks::FileLocks;
+use crate::mapped::MappedBundles;
+use crate::watcher::{normalize, FileWatcher};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use tauri::{command, State};
+
+/// Records which document a backup directory belongs to.
+const SOURCE_FILE: &str = "source.json";
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BackupSettings {
+    pub enabled: bool,
+    /// Backups go to the `backups` folder in the app data directory when unset.
+    pub directory: Option<String>,
+    /// Copies kept of each document; older ones are pruned first.
+    pub max_copies: usize,
+    /// Limit on all backups together. The latest copy of each document is
+    /// kept even when it alone goes over.
+    pub max_total_bytes: u64,
+}
+
+impl Default for BackupSettings {
+    fn default() -> Self {
+        BackupSettings { enabled: true, directory: None, max_copies: 10, max_total_bytes: 1024 * 1024 * 1024 }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct BackupSource {
+    path: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupInfo {
+    pub id: String,
+    /// The copy itself, which can be opened like any document.
+    pub path: String,
+    pub created_at: u64,
+    pub size: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupStatus {
+    pub settings: BackupSettings,
+    /// Directory backups are written to, with the default resolved.
+    pub directory: String,
+    pub total_bytes: u64,
+    /// Why the last backup failed; saving itself is unaffected.
+    pub last_error: Option<String>,
+}
+
+struct BackupState {
+    settings: BackupSettings,
+    last_error: Option<String>,
+}
+
+/// Timestamped copies of each saved document, kept in one directory per
+/// document and rotated by count and total size.
+pub struct BackupManager {
+    store: PathBuf,
+    default_dir: PathBuf,
+    state: Mutex<BackupState>,
+}
+
+/// Backups of one document, newest first.
+fn list_dir(dir: &Path) -> Vec<BackupInfo> {
+    let mut backups: Vec<BackupInfo> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let id = entry.file_name().to_str()?.to_string();
+            let created_at = id.split_once('-')?.0.parse().ok()?;
+            let size = entry.metadata().ok().filter(|m| m.is_file())?.len();
+            Some(BackupInfo { path: entry.path().to_string_lossy().into_owned(), id, created_at, size })
+        })
+        .collect();
+    backups.sort_by_key(|b| std::cmp::Reverse(b.created_at));
+    backups
+}
+
+impl BackupManager {
+    pub fn load(store: PathBuf, default_dir: PathBuf) -> Self {
+        let settings = fs::read(&store)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+
+        BackupManager { store, default_dir, state: Mutex::new(BackupState { settings, last_error: None }) }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BackupState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn root(&self, settings: &BackupSettings) -> PathBuf {
+        settings.directory.as_ref().map(PathBuf::from).unwrap_or_else(|| self.default_dir.clone())
+    }
+
+    /// `dir/design.dlibre` is backed up to `<root>/design-<hash>/`, the hash
+    /// telling apart documents of the same name in different folders.
+    fn document_dir(root: &Path, document: &Path) -> PathBuf {
+        let stem = document.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+        let hash = hex_digest(document.to_string_lossy().as_bytes());
+        root.join(format!("{}-{}", sanitize_id(stem), &hash[..12]))
+    }
+
+    fn persist(&self, settings: &BackupSettings) -> Result<(), String> {
+        if let Some(dir) = self.store.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create app config directory: {}", e))?;
+        }
+        let json = serde_json::to_vec_pretty(settings)
+            .map_err(|e| format!("Failed to encode backup settings: {}", e))?;
+        write_atomic(&self.store.to_string_lossy(), &json)?;
+        Ok(())
+    }
+
+    /// Store `bytes`, as written to `path`, as its newest backup and prune.
+    /// Encrypted and compressed files are copied as they are on disk.
+    fn store(&self, settings: &BackupSettings, path: &str, bytes: &[u8]) -> Result<(), String> {
+        let document = normalize(Path::new(path));
+        let root = self.root(settings);
+        let dir = Self::document_dir(&root, &document);
+        fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create backup directory: {}", e))?;
+
+        let source = dir.join(SOURCE_FILE);
+        if !source.exists() {
+            let json = serde_json::to_vec_pretty(&BackupSource { path: document.to_string_lossy().into_owned() })
+                .map_err(|e| format!("Failed to encode backup source: {}", e))?;
+            write_atomic(&source.to_string_lossy(), &json)?;
+        }
+
+        // Saving again without changes does not push older copies out
+        let latest = list_dir(&dir).into_iter().next();
+        if latest.is_some_and(|b| b.size == bytes.len() as u64 && fs::read(&b.path).is_ok_and(|d| d == bytes)) {
+            return Ok(());
+        }
+
+        let name = document.file_name().and_then(|n| n.to_str()).unwrap_or("document");
+        let mut created_at = now_millis();
+        while dir.join(format!("{}-{}", created_at, name)).exists() {
+            created_at += 1;
+        }
+        write_atomic(&dir.join(format!("{}-{}", created_at, name)).to_string_lossy(), bytes)?;
+
+        prune(&root, settings)
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.lock().settings.enabled
+    }
+
+    /// Back up a document that has just been saved. Failures are recorded
+    /// for `get_backup_status` rather than failing the save.
+    pub fn back_up(&self, path: &str, bytes: &[u8]) {
+        let mut state = self.lock();
+        if !state.settings.enabled {
+            return;
+        }
+        let settings = state.settings.clone();
+        state.last_error = self.store(&settings, path, bytes).err();
+    }
+
+    pub fn list(&self, path: &str) -> Vec<BackupInfo> {
+        let root = self.root(&self.lock().settings);
+        list_dir(&Self::document_dir(&root, &normalize(Path::new(path))))
+    }
+
+    pub fn status(&self) -> BackupStatus {
+        let state = self.lock();
+        let root = self.root(&state.settings);
+        let total_bytes = document_dirs(&root).iter().flat_map(|dir| list_dir(dir)).map(|b| b.size).sum();
+
+        BackupStatus {
+            settings: state.settings.clone(),
+            directory: root.to_string_lossy().into_owned(),
+            total_bytes,
+            last_error: state.last_error.clone(),
+        }
+    }
+
+    /// Save `settings` and prune the backup directory to the new limits.
+    pub fn configure(&self, settings: BackupSettings) -> Result<(), String> {
+        if settings.max_copies == 0 {
+            return Err("At least one backup must be kept per document".to_string());
+        }
+        if settings.directory.as_deref().is_some_and(|d| !Path::new(d).is_absolute()) {
+            return Err("Backup directory must be an absolute path".to_string());
+        }
+        let mut state = self.lock();
+        self.persist(&settings)?;
+        prune(&self.root(&settings), &settings)?;
+        state.settings = settings;
+        Ok(())
+    }
+}
+
+/// Per-document directories under `root`. Only those with a source record
+/// are considered, so a directory chosen by the user is never emptied of
+/// anything else.
+fn document_dirs(root: &Path) -> Vec<PathBuf> {
+    fs::read_dir(root)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|dir| dir.join(SOURCE_FILE).is_file())
+        .collect()
+}
+
+/// Drop copies beyond `max_copies` for each document, then the oldest copies
+/// overall until the total fits `max_total_bytes`.
+fn prune(root: &Path, settings: &BackupSettings) -> Result<(), String> {
+    let remove = |backup: &BackupInfo| {
+        fs::remove_file(&backup.path).map_err(|e| format!("Failed to remove backup {}: {}", backup.id, e))
+    };
+
+    let mut candidates = Vec::new();
+    let mut total: u64 = 0;
+    for dir in document_dirs(root) {
+        let backups = list_dir(&dir);
+        if backups.is_empty() {
+            fs::remove_dir_all(&dir).map_err(|e| format!("Failed to remove backup directory: {}", e))?;
+            continue;
+        }
+        for (i, backup) in backups.into_iter().enumerate() {
+            if i >= settings.max_copies {
+                remove(&backup)?;
+            } else {
+                total += backup.size;
+                if i > 0 {
+                    candidates.push(backup);
+                }
+            }
+        }
+    }
+
+    candidates.sort_by_key(|b| b.created_at);
+    for backup in candidates {
+        if total <= settings.max_total_bytes {
+            break;
+        }
+        remove(&backup)?;
+        total -= backup.size;
+    }
+    Ok(())
+}
+
+#[command]
+pub fn list_backups(backups: State<'_, BackupManager>, path: String) -> Vec<BackupInfo> {
+    backups.list(&path)
+}
+
+/// Replace the document on disk with one of its backups; the frontend reloads
+/// it afterwards. The current contents are backed up first so a restore can
+/// itself be undone.
+#[command(async)]
+pub fn restore_backup(
+    backups: State<'_, BackupManager>,
+    watcher: State<'_, FileWatcher>,
+    locks: State<'_, FileLocks>,
+    mapped: State<'_, MappedBundles>,
+    path: String,
+    backup_id: String,
+) -> Result<(), String> {
+    locks.check_writable(&path)?;
+    let backup = backups
+        .list(&path)
+        .into_iter()
+        .find(|b| b.id == backup_id)
+        .ok_or_else(|| format!("Unknown backup: {}", backup_id))?;
+    let data = fs::read(&backup.path).map_err(|e| format!("Failed to read backup: {}", e))?;
+
+    if let Ok(current) = fs::read(&path) {
+        backups.back_up(&path, &current);
+    }
+    watcher.acknowledge(&path, &data);
+    write_atomic(&path, &data)?;
+    mapped.forget(&path);
+
+    Ok(())
+}
+
+#[command]
+pub fn get_backup_status(backups: State<'_, BackupManager>) -> BackupStatus {
+    backups.status()
+}
+
+#[command]
+pub fn set_backup_settings(backups: State<'_, BackupManager>, settings: BackupSettings) -> Result<BackupStatus, String> {
+    backups.configure(settings)?;
+    Ok(backups.status())
+}
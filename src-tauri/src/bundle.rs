@@ -0,0 +1,506 @@
+use crate::backups::BackupManager;
+use crate::commands::write_file;
+use crate::locks::FileLocks;
+use crate::mapped::MappedBundles;
+use crate::watcher::FileWatcher;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{Read, Seek, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{command, State};
+use usvg::fontdb::Database;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// Bundle layout version written into every manifest. Version 2 keeps each
+/// page's contents in its own entry under `pages/`.
+pub const BUNDLE_FORMAT_VERSION: u32 = 2;
+
+pub const MANIFEST_ENTRY: &str = "manifest.json";
+pub const DOCUMENT_ENTRY: &str = "document.json";
+pub const IMAGES_DIR: &str = "images/";
+pub const FONTS_DIR: &str = "fonts/";
+pub const PAGES_DIR: &str = "pages/";
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AssetKind {
+    Image,
+    Font,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestAsset {
+    pub path: String,
+    pub kind: AssetKind,
+    pub size: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleManifest {
+    pub format_version: u32,
+    pub name: String,
+    /// Milliseconds since the Unix epoch.
+    pub created_at: u64,
+    pub modified_at: u64,
+    pub document: String,
+    pub assets: Vec<ManifestAsset>,
+    /// Pages whose nodes are kept out of the document entry, in page order.
+    #[serde(default)]
+    pub pages: Vec<ManifestPage>,
+}
+
+/// A page of the document stored as its own entry: a JSON array of every
+/// node under the page, which stays in the document entry itself.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestPage {
+    pub id: String,
+    pub name: String,
+    pub entry: String,
+    pub node_count: usize,
+}
+
+/// An embedded asset as exchanged with the frontend. `path` is relative to the
+/// bundle root and must live under `images/` or `fonts/`.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleAsset {
+    pub path: String,
+    pub data: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DesignBundle {
+    pub path: String,
+    pub manifest: BundleManifest,
+    pub document: String,
+    pub assets: Vec<BundleAsset>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssetCollection {
+    /// Assets no node refers to, which are removed unless it's a dry run.
+    pub unused: Vec<ManifestAsset>,
+    pub bytes_freed: u64,
+    pub removed: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleEntry {
+    pub name: String,
+    pub size: u64,
+    pub compressed_size: u64,
+    pub is_dir: bool,
+}
+
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Classify an asset path, rejecting anything that could escape the bundle root.
+pub fn asset_kind(path: &str) -> Result<AssetKind, String> {
+    if path.starts_with('/') || path.contains('\\') || path.split('/').any(|p| p == ".." || p.is_empty()) {
+        return Err(format!("Invalid asset path: {}", path));
+    }
+
+    if path.starts_with(IMAGES_DIR) {
+        Ok(AssetKind::Image)
+    } else if path.starts_with(FONTS_DIR) {
+        Ok(AssetKind::Font)
+    } else {
+        Err(format!("Asset path must be under {} or {}: {}", IMAGES_DIR, FONTS_DIR, path))
+    }
+}
+
+/// Whether `bytes` look like a bundle (zip) rather than a plain JSON document.
+pub fn is_bundle(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"PK\x03\x04")
+}
+
+fn open_archive(path: &str) -> Result<ZipArchive<File>, String> {
+    let file = File::open(path)
+        .map_err(|e| format!("Failed to open bundle: {}", e))?;
+
+    ZipArchive::new(file)
+        .map_err(|e| format!("Invalid bundle: {}", e))
+}
+
+pub fn read_entry<R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str) -> Result<Vec<u8>, String> {
+    let mut entry = archive.by_name(name)
+        .map_err(|e| format!("Missing bundle entry {}: {}", name, e))?;
+
+    let mut buf = Vec::with_capacity(entry.size() as usize);
+    entry.read_to_end(&mut buf)
+        .map_err(|e| format!("Failed to read bundle entry {}: {}", name, e))?;
+
+    Ok(buf)
+}
+
+pub fn read_manifest<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<BundleManifest, String> {
+    let bytes = read_entry(archive, MANIFEST_ENTRY)?;
+    let manifest: BundleManifest = serde_json::from_slice(&bytes)
+        .map_err(|e| format!("Invalid bundle manifest: {}", e))?;
+
+    if manifest.format_version > BUNDLE_FORMAT_VERSION {
+        return Err(format!(
+            "Bundle format version {} is newer than supported version {}",
+            manifest.format_version, BUNDLE_FORMAT_VERSION
+        ));
+    }
+
+    Ok(manifest)
+}
+
+/// A document as stored in a bundle: its top level, and the nodes under each
+/// page as the page's entry.
+struct SplitDocument {
+    document: Vec<u8>,
+    pages: Vec<(ManifestPage, Vec<u8>)>,
+}
+
+/// Split a document into its top level and the nodes under each page, so
+/// pages can be read on their own. `None` for a document that isn't shaped
+/// like one, which is then stored whole.
+fn split_pages(document: &str) -> Option<SplitDocument> {
+    let mut doc: Value = serde_json::from_str(document).ok()?;
+    let root_id = doc.get("rootId")?.as_str()?.to_string();
+    let nodes = doc.get_mut("nodes")?.as_array_mut()?;
+
+    let mut children: HashMap<String, Vec<usize>> = HashMap::new();
+    for (i, node) in nodes.iter().enumerate() {
+        if let Some(parent) = node.get("parentId").and_then(Value::as_str) {
+            children.entry(parent.to_string()).or_default().push(i);
+        }
+    }
+
+    let mut owner = vec![None; nodes.len()];
+    let mut pages = Vec::new();
+    for &page in children.get(&root_id).map(Vec::as_slice).unwrap_or_default() {
+        let index = pages.len();
+        let mut stack = vec![page];
+        let mut count = 0;
+        while let Some(i) = stack.pop() {
+            let id = nodes[i].get("id").and_then(Value::as_str).unwrap_or_default();
+            for &child in children.get(id).map(Vec::as_slice).unwrap_or_default() {
+                // A node reached twice belongs to a cycle; leave it where it is
+                if owner[child].is_none() {
+                    owner[child] = Some(index);
+                    count += 1;
+                    stack.push(child);
+                }
+            }
+        }
+        let page = &nodes[page];
+        let text = |key: &str, value: &Value| value.get(key).and_then(Value::as_str).unwrap_or_default().to_string();
+        pages.push(ManifestPage {
+            id: text("id", page),
+            name: page.get("data").map(|data| text("name", data)).unwrap_or_default(),
+            entry: format!("{}{}.json", PAGES_DIR, index),
+            node_count: count,
+        });
+    }
+
+    let mut contents: Vec<Vec<Value>> = vec![Vec::new(); pages.len()];
+    let mut top = Vec::new();
+    for (node, owner) in std::mem::take(nodes).into_iter().zip(owner) {
+        match owner {
+            Some(page) => contents[page].push(node),
+            None => top.push(node),
+        }
+    }
+    *nodes = top;
+
+    let document = serde_json::to_vec(&doc).ok()?;
+    let pages = pages
+        .into_iter()
+        .zip(contents)
+        .map(|(page, nodes)| Some((page, serde_json::to_vec(&nodes).ok()?)))
+        .collect::<Option<_>>()?;
+    Some(SplitDocument { document, pages })
+}
+
+/// Serialize a bundle into zip bytes. Images are stored as-is since they are
+/// already compressed; JSON and fonts are deflated, except for pages, which
+/// are stored so they can be read straight out of a mapped bundle.
+pub fn encode_bundle(
+    name: &str,
+    created_at: u64,
+    document: &str,
+    assets: &[(String, Vec<u8>)],
+) -> Result<(BundleManifest, Vec<u8>), String> {
+    let mut manifest_assets = Vec::with_capacity(assets.len());
+    for (path, bytes) in assets {
+        manifest_assets.push(ManifestAsset {
+            path: path.clone(),
+            kind: asset_kind(path)?,
+            size: bytes.len() as u64,
+        });
+    }
+
+    let SplitDocument { document, pages } = split_pages(document)
+        .unwrap_or_else(|| SplitDocument { document: document.as_bytes().to_vec(), pages: Vec::new() });
+
+    let manifest = BundleManifest {
+        format_version: BUNDLE_FORMAT_VERSION,
+        name: name.to_string(),
+        created_at,
+        modified_at: now_millis(),
+        document: DOCUMENT_ENTRY.to_string(),
+        assets: manifest_assets,
+        pages: pages.iter().map(|(page, _)| page.clone()).collect(),
+    };
+
+    let manifest_json = serde_json::to_vec_pretty(&manifest)
+        .map_err(|e| format!("Failed to encode manifest: {}", e))?;
+
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+    let mut zip = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let write_err = |e: &dyn std::fmt::Display| format!("Failed to write bundle: {}", e);
+
+    zip.start_file(MANIFEST_ENTRY, deflated).map_err(|e| write_err(&e))?;
+    zip.write_all(&manifest_json).map_err(|e| write_err(&e))?;
+
+    zip.start_file(DOCUMENT_ENTRY, deflated).map_err(|e| write_err(&e))?;
+    zip.write_all(&document).map_err(|e| write_err(&e))?;
+
+    for (page, bytes) in &pages {
+        zip.start_file(page.entry.as_str(), stored.large_file(bytes.len() >= u32::MAX as usize)).map_err(|e| write_err(&e))?;
+        zip.write_all(bytes).map_err(|e| write_err(&e))?;
+    }
+
+    for ((path, bytes), entry) in assets.iter().zip(&manifest.assets) {
+        let options = if entry.kind == AssetKind::Image { stored } else { deflated };
+        zip.start_file(path.as_str(), options).map_err(|e| write_err(&e))?;
+        zip.write_all(bytes).map_err(|e| write_err(&e))?;
+    }
+
+    let cursor = zip.finish().map_err(|e| write_err(&e))?;
+    Ok((manifest, cursor.into_inner()))
+}
+
+/// Check a page entry named in a manifest, so it can't name anything else in
+/// the bundle.
+pub fn check_page_entry(entry: &str) -> Result<(), String> {
+    let name = entry.strip_prefix(PAGES_DIR).unwrap_or_default();
+    if name.is_empty() || name.contains('/') || name.contains('\\') || name.starts_with('.') {
+        return Err(format!("Invalid page entry: {}", entry));
+    }
+    Ok(())
+}
+
+/// Put the nodes of every page back into `document`.
+fn join_pages(document: String, pages: Vec<Vec<u8>>) -> Result<String, String> {
+    if pages.is_empty() {
+        return Ok(document);
+    }
+
+    let mut doc: Value = serde_json::from_str(&document)
+        .map_err(|e| format!("Invalid bundle document: {}", e))?;
+    let nodes = doc.get_mut("nodes").and_then(Value::as_array_mut)
+        .ok_or("Bundle document has no nodes")?;
+
+    for bytes in pages {
+        let page: Vec<Value> = serde_json::from_slice(&bytes)
+            .map_err(|e| format!("Invalid bundle page: {}", e))?;
+        nodes.extend(page);
+    }
+
+    serde_json::to_string(&doc).map_err(|e| format!("Failed to join bundle pages: {}", e))
+}
+
+/// A bundle decoded from disk with raw asset bytes.
+pub struct LoadedBundle {
+    pub manifest: BundleManifest,
+    pub document: String,
+    pub assets: Vec<(String, Vec<u8>)>,
+}
+
+/// The whole document of a bundle, its pages joined back in.
+fn read_document(archive: &mut ZipArchive<File>, manifest: &BundleManifest) -> Result<String, String> {
+    let document = String::from_utf8(read_entry(archive, &manifest.document)?)
+        .map_err(|e| format!("Bundle document is not valid UTF-8: {}", e))?;
+
+    let mut pages = Vec::with_capacity(manifest.pages.len());
+    for page in &manifest.pages {
+        check_page_entry(&page.entry)?;
+        pages.push(read_entry(archive, &page.entry)?);
+    }
+    join_pages(document, pages)
+}
+
+pub fn load_bundle_manifest(path: &str) -> Result<BundleManifest, String> {
+    read_manifest(&mut open_archive(path)?)
+}
+
+/// Read the whole document of a bundle on disk, leaving its assets.
+pub fn load_bundle_document(path: &str) -> Result<String, String> {
+    let mut archive = open_archive(path)?;
+    let manifest = read_manifest(&mut archive)?;
+    read_document(&mut archive, &manifest)
+}
+
+/// Read the manifest, whole document and every listed asset of a bundle on
+/// disk.
+pub fn load_bundle(path: &str) -> Result<LoadedBundle, String> {
+    let mut archive = open_archive(path)?;
+    let manifest = read_manifest(&mut archive)?;
+    let document = read_document(&mut archive, &manifest)?;
+
+    let mut assets = Vec::with_capacity(manifest.assets.len());
+    for asset in &manifest.assets {
+        asset_kind(&asset.path)?;
+        assets.push((asset.path.clone(), read_entry(&mut archive, &asset.path)?));
+    }
+
+    Ok(LoadedBundle { manifest, document, assets })
+}
+
+#[command(async)]
+pub fn open_bundle(path: String) -> Result<DesignBundle, String> {
+    let LoadedBundle { manifest, document, assets } = load_bundle(&path)?;
+
+    let assets = assets
+        .into_iter()
+        .map(|(path, bytes)| BundleAsset { path, data: BASE64.encode(bytes) })
+        .collect();
+
+    Ok(DesignBundle { path, manifest, document, assets })
+}
+
+#[command(async)]
+#[allow(clippy::too_many_arguments)]
+pub fn save_bundle(
+    watcher: State<'_, FileWatcher>,
+    backups: State<'_, BackupManager>,
+    locks: State<'_, FileLocks>,
+    mapped: State<'_, MappedBundles>,
+    path: String,
+    name: String,
+    document: String,
+    assets: Vec<BundleAsset>,
+) -> Result<BundleManifest, String> {
+    // Preserve the original creation time when overwriting an existing bundle
+    let created_at = open_archive(&path)
+        .and_then(|mut archive| read_manifest(&mut archive))
+        .map(|m| m.created_at)
+        .unwrap_or_else(|_| now_millis());
+
+    let mut decoded = Vec::with_capacity(assets.len());
+    for asset in assets {
+        let bytes = BASE64.decode(asset.data.as_bytes())
+            .map_err(|e| format!("Invalid base64 data for {}: {}", asset.path, e))?;
+        decoded.push((asset.path, bytes));
+    }
+
+    let (manifest, bytes) = encode_bundle(&name, created_at, &document, &decoded)?;
+
+    write_file(&watcher, &locks, &mapped, &path, &bytes)?;
+    backups.back_up(&path, &bytes);
+
+    Ok(manifest)
+}
+
+#[command(async)]
+pub fn list_bundle_entries(path: String) -> Result<Vec<BundleEntry>, String> {
+    let mut archive = open_archive(&path)?;
+    let mut entries = Vec::with_capacity(archive.len());
+
+    for i in 0..archive.len() {
+        let entry = archive.by_index(i)
+            .map_err(|e| format!("Failed to read bundle entry: {}", e))?;
+
+        entries.push(BundleEntry {
+            name: entry.name().to_string(),
+            size: entry.size(),
+            compressed_size: entry.compressed_size(),
+            is_dir: entry.is_dir(),
+        });
+    }
+
+    Ok(entries)
+}
+
+#[command(async)]
+pub fn read_bundle_entry(path: String, name: String) -> Result<String, String> {
+    let mut archive = open_archive(&path)?;
+    let bytes = read_entry(&mut archive, &name)?;
+
+    Ok(BASE64.encode(bytes))
+}
+
+/// Every string in `value`, keys aside.
+fn collect_strings<'a>(value: &'a Value, found: &mut HashSet<&'a str>) {
+    match value {
+        Value::String(text) => {
+            found.insert(text);
+        }
+        Value::Array(items) => items.iter().for_each(|item| collect_strings(item, found)),
+        Value::Object(fields) => fields.values().for_each(|field| collect_strings(field, found)),
+        _ => {}
+    }
+}
+
+/// Whether anything in the document refers to `asset`, given every string
+/// in it. Images are referred to by path or file name and fonts by family;
+/// a font whose families can't be read is kept.
+fn is_referenced(asset: &ManifestAsset, bytes: &[u8], strings: &HashSet<&str>) -> bool {
+    match asset.kind {
+        AssetKind::Image => {
+            let name = asset.path.strip_prefix(IMAGES_DIR).unwrap_or(&asset.path);
+            strings.contains(asset.path.as_str()) || strings.contains(name)
+        }
+        AssetKind::Font => {
+            let mut db = Database::new();
+            db.load_font_data(bytes.to_vec());
+            let mut families = db.faces().flat_map(|face| face.families.iter().map(|(family, _)| family.as_str())).peekable();
+            families.peek().is_none() || families.any(|family| strings.contains(family))
+        }
+    }
+}
+
+/// Find the images and fonts in a bundle that no node refers to any more,
+/// and unless `dry_run` is set, rewrite the bundle without them.
+#[command(async)]
+pub fn collect_unused_assets(
+    watcher: State<'_, FileWatcher>,
+    locks: State<'_, FileLocks>,
+    mapped: State<'_, MappedBundles>,
+    path: String,
+    dry_run: Option<bool>,
+) -> Result<AssetCollection, String> {
+    let LoadedBundle { manifest, document, assets } = load_bundle(&path)?;
+    let doc: Value = serde_json::from_str(&document)
+        .map_err(|e| format!("Invalid bundle document: {}", e))?;
+    let mut strings = HashSet::new();
+    collect_strings(&doc, &mut strings);
+
+    let (used, unused): (Vec<_>, Vec<_>) = assets
+        .into_iter()
+        .zip(manifest.assets)
+        .partition(|((_, bytes), asset)| is_referenced(asset, bytes, &strings));
+    let unused: Vec<ManifestAsset> = unused.into_iter().map(|(_, asset)| asset).collect();
+    let bytes_freed = unused.iter().map(|asset| asset.size).sum();
+
+    let removed = !dry_run.unwrap_or(false) && !unused.is_empty();
+    if removed {
+        let kept: Vec<(String, Vec<u8>)> = used.into_iter().map(|(asset, _)| asset).collect();
+        let (_, bytes) = encode_bundle(&manifest.name, manifest.created_at, &document, &kept)?;
+        write_file(&watcher, &locks, &mapped, &path, &bytes)?;
+    }
+
+    Ok(AssetCollection { unused, bytes_freed, removed })
+}
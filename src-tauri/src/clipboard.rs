@@ -0,0 +1,130 @@
+//! The system clipboard beyond the plain text the clipboard plugin's
+//! JavaScript API is used for. Writes go through the plugin's clipboard,
+//! which lives as long as the app, so on X11 the copied data is still
+//! served after the command returns.
+
+use crate::assets::{AssetStore, ImportAssetOptions, ImportedAsset};
+use crate::export::raster::{demultiply, render_pixmap};
+use crate::export::resolve_scope;
+use crate::export::svg::{write_svg, SvgExportOptions};
+use crate::model::{DocumentTree, Rgba};
+use image::codecs::png::PngEncoder;
+use image::{ExtendedColorType, ImageEncoder};
+use serde::{Deserialize, Serialize};
+use tauri::image::Image;
+use tauri::{command, AppHandle, State};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use tiny_skia::Color;
+
+/// Pixels per canvas unit for copied images. Chat apps and documents show
+/// a pasted image at half its size on high-density screens.
+const DEFAULT_COPY_SCALE: f64 = 2.0;
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CopyImageOptions {
+    /// Nodes to copy; defaults to the document root.
+    pub node_ids: Option<Vec<String>>,
+    pub padding: f64,
+    /// Painted under the content, which is otherwise transparent.
+    pub background: Option<Rgba>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CopiedImage {
+    pub width: u32,
+    pub height: u32,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CopiedSvg {
+    pub width: f64,
+    pub height: f64,
+    pub warnings: Vec<String>,
+}
+
+/// `text` with the characters HTML gives meaning to escaped.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Render nodes with the export renderer and put them on the clipboard as
+/// an image, at `scale` pixels per canvas unit (2 by default). The clipboard
+/// gets PNG on Linux, PNG and a DIB on Windows, and on macOS an image that
+/// apps read as TIFF or PNG.
+#[command(async)]
+pub fn copy_image_to_clipboard(
+    app: AppHandle,
+    node_json: String,
+    scale: Option<f64>,
+    options: Option<CopyImageOptions>,
+) -> Result<CopiedImage, String> {
+    let options = options.unwrap_or_default();
+    let tree = DocumentTree::parse(&node_json)?;
+    let background = options.background.map(|bg| {
+        Color::from_rgba(bg.r as f32, bg.g as f32, bg.b as f32, bg.a as f32).unwrap_or(Color::WHITE)
+    });
+    let (pixmap, warnings) =
+        render_pixmap(&tree, scale.unwrap_or(DEFAULT_COPY_SCALE), options.node_ids, options.padding, background)?;
+
+    let (width, height) = (pixmap.width(), pixmap.height());
+    let image = Image::new_owned(demultiply(&pixmap), width, height);
+    app.clipboard().write_image(&image)
+        .map_err(|e| format!("Failed to copy image: {}", e))?;
+    Ok(CopiedImage { width, height, warnings })
+}
+
+/// Add the image on the clipboard, such as a screenshot or an image copied
+/// in a browser, to the asset store as a PNG, converted to the document's
+/// working space. Resolves to `None` when the clipboard holds no image.
+#[command(async)]
+pub fn paste_clipboard_image(
+    store: State<'_, AssetStore>,
+    options: Option<ImportAssetOptions>,
+) -> Result<Option<ImportedAsset>, String> {
+    let space = options.unwrap_or_default().working_space;
+    let mut clipboard = arboard::Clipboard::new()
+        .map_err(|e| format!("Failed to open clipboard: {}", e))?;
+    let image = match clipboard.get_image() {
+        Ok(image) => image,
+        Err(arboard::Error::ContentNotAvailable) => return Ok(None),
+        Err(e) => return Err(format!("Failed to read clipboard: {}", e)),
+    };
+
+    // Screenshots are mostly flat color, which PNG stores smaller and exactly
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png)
+        .write_image(&image.bytes, image.width as u32, image.height as u32, ExtendedColorType::Rgba8)
+        .map_err(|e| format!("Failed to encode PNG: {}", e))?;
+    store.import(&png, Some("Pasted image.png".to_string()), space).map(Some)
+}
+
+/// Export nodes as SVG and put the markup on the clipboard twice: as plain
+/// text, which code editors paste, and as HTML, which browsers, documents
+/// and chat apps paste as the drawing. The markup has no XML declaration,
+/// which can't appear inside HTML.
+#[command(async)]
+pub fn copy_svg_to_clipboard(app: AppHandle, node_json: String, options: Option<SvgExportOptions>) -> Result<CopiedSvg, String> {
+    let options = SvgExportOptions { include_xml_declaration: false, ..options.unwrap_or_default() };
+    let tree = DocumentTree::parse(&node_json)?;
+    let scope = resolve_scope(&tree, options.node_ids.clone(), options.padding)?;
+    let export = write_svg(&scope, &options);
+    app.clipboard().write_html(export.svg.as_str(), Some(export.svg.as_str()))
+        .map_err(|e| format!("Failed to copy SVG: {}", e))?;
+    Ok(CopiedSvg { width: export.width, height: export.height, warnings: export.warnings })
+}
+
+/// Put generated code, such as CSS or SwiftUI for a layer, on the clipboard
+/// as plain text, and as an HTML code block so documents keep it monospaced.
+/// `language` is the block's `language-*` class, which some editors
+/// highlight by.
+#[command]
+pub fn copy_code_to_clipboard(app: AppHandle, code: String, language: Option<String>) -> Result<(), String> {
+    let class = language.map(|language| format!(" class=\"language-{}\"", escape_html(&language))).unwrap_or_default();
+    let html = format!("<pre><code{}>{}</code></pre>", class, escape_html(&code));
+    app.clipboard().write_html(html.as_str(), Some(code.as_str()))
+        .map_err(|e| format!("Failed to copy code: {}", e))
+}
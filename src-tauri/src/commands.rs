@@ -1,98 +1,216 @@
+use crate::atomic::write_atomic;
+use crate::backups::BackupManager;
+use crate::compression;
+use crate::encryption;
+use crate::error::{FileError, FileErrorKind};
+use crate::locks::FileLocks;
+use crate::mapped::MappedBundles;
+use crate::watcher::FileWatcher;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use std::fs;
-use tauri::command;
+use tauri::{command, State};
 
 #[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct DesignFile {
     pub path: String,
     pub content: String,
+    /// The file is passphrase-encrypted; saving without `encrypt` would
+    /// store it in the clear.
+    pub encrypted: bool,
+    /// The file is zstd-compressed; saving without `compress` would store
+    /// it uncompressed.
+    pub compressed: bool,
 }
 
-#[command]
-pub fn read_design_file(path: String) -> Result<DesignFile, String> {
-    let content = fs::read_to_string(&path)
-        .map_err(|e| format!("Failed to read file: {}", e))?;
+/// Passphrase to encrypt a file with when writing it.
+#[derive(serde::Deserialize)]
+pub struct EncryptOptions {
+    pub passphrase: String,
+}
 
-    Ok(DesignFile { path, content })
+/// A file's contents with encryption and compression undone.
+pub struct Contents {
+    pub bytes: Vec<u8>,
+    pub encrypted: bool,
+    pub compressed: bool,
+    pub on_disk: u64,
 }
 
-#[command]
-pub fn write_design_file(path: String, content: String) -> Result<(), String> {
-    fs::write(&path, &content)
-        .map_err(|e| format!("Failed to write file: {}", e))?;
+pub fn read_contents(path: &str, passphrase: Option<&str>) -> Result<Contents, FileError> {
+    let mut bytes = fs::read(path)
+        .map_err(|e| FileError::from_io(&e, path, "read file"))?;
+    let on_disk = bytes.len() as u64;
+    let encrypted = encryption::is_encrypted(&bytes);
+    if encrypted {
+        let passphrase = passphrase.filter(|p| !p.is_empty()).ok_or_else(|| {
+            FileError::new(FileErrorKind::PassphraseRequired, path, "The file is encrypted; a passphrase is needed to open it")
+        })?;
+        bytes = encryption::decrypt(&bytes, passphrase, path)?;
+    }
+    let compressed = compression::is_compressed(&bytes);
+    if compressed {
+        bytes = compression::decompress(&bytes, path)?;
+    }
+    Ok(Contents { bytes, encrypted, compressed, on_disk })
+}
+
+/// Write `bytes` to `path` as the app's own save: it's refused while
+/// another instance holds the lock, isn't reported back as an outside
+/// change, and drops any mapping of the file as it was.
+pub fn write_file(watcher: &FileWatcher, locks: &FileLocks, mapped: &MappedBundles, path: &str, bytes: &[u8]) -> Result<(), FileError> {
+    locks.check_writable(path)?;
+    watcher.acknowledge(path, bytes);
+    write_atomic(path, bytes)?;
+    mapped.forget(path);
+    Ok(())
+}
 
+#[allow(clippy::too_many_arguments)]
+pub fn write_contents(
+    watcher: &FileWatcher,
+    backups: &BackupManager,
+    locks: &FileLocks,
+    mapped: &MappedBundles,
+    path: &str,
+    bytes: &[u8],
+    encrypt: Option<EncryptOptions>,
+    compress: bool,
+) -> Result<(), FileError> {
+    locks.check_writable(path)?;
+    let packed = compress.then(|| compression::compress(bytes));
+    let bytes = packed.as_deref().unwrap_or(bytes);
+    let sealed = encrypt.map(|options| encryption::encrypt(bytes, &options.passphrase, path)).transpose()?;
+    let bytes = sealed.as_deref().unwrap_or(bytes);
+    write_file(watcher, locks, mapped, path, bytes)?;
+    backups.back_up(path, bytes);
     Ok(())
 }
 
+/// Read a text design file. Compressed files are detected and decompressed.
+/// Encrypted files fail with `passphraseRequired` until `passphrase` is
+/// given, and with `wrongPassphrase` if it does not match.
+#[command(async)]
+pub fn read_design_file(path: String, passphrase: Option<String>) -> Result<DesignFile, FileError> {
+    let contents = read_contents(&path, passphrase.as_deref())?;
+    let content = String::from_utf8(contents.bytes)
+        .map_err(|_| FileError::new(FileErrorKind::Io, &path, "The file is not valid UTF-8 text"))?;
+
+    Ok(DesignFile { path, content, encrypted: contents.encrypted, compressed: contents.compressed })
+}
+
+/// Write a text design file, zstd-compressed when `compress` is set. A
+/// backup copy is kept according to the backup settings. Fails with `locked`
+/// while another instance holds the file's lock.
+#[command(async)]
+#[allow(clippy::too_many_arguments)]
+pub fn write_design_file(
+    watcher: State<'_, FileWatcher>,
+    backups: State<'_, BackupManager>,
+    locks: State<'_, FileLocks>,
+    mapped: State<'_, MappedBundles>,
+    path: String,
+    content: String,
+    encrypt: Option<EncryptOptions>,
+    compress: Option<bool>,
+) -> Result<(), FileError> {
+    write_contents(&watcher, &backups, &locks, &mapped, &path, content.as_bytes(), encrypt, compress.unwrap_or(false))
+}
+
+/// Binary file contents, base64-encoded so embedded assets survive IPC intact.
+#[derive(serde::Serialize)]
+pub struct BinaryDesignFile {
+    pub path: String,
+    pub data: String,
+    pub encrypted: bool,
+    pub compressed: bool,
+}
+
+#[command(async)]
+pub fn read_design_file_binary(path: String, passphrase: Option<String>) -> Result<BinaryDesignFile, FileError> {
+    let contents = read_contents(&path, passphrase.as_deref())?;
+
+    Ok(BinaryDesignFile {
+        path,
+        data: BASE64.encode(contents.bytes),
+        encrypted: contents.encrypted,
+        compressed: contents.compressed,
+    })
+}
+
+#[command(async)]
+#[allow(clippy::too_many_arguments)]
+pub fn write_design_file_binary(
+    watcher: State<'_, FileWatcher>,
+    backups: State<'_, BackupManager>,
+    locks: State<'_, FileLocks>,
+    mapped: State<'_, MappedBundles>,
+    path: String,
+    data: String,
+    encrypt: Option<EncryptOptions>,
+    compress: Option<bool>,
+) -> Result<(), FileError> {
+    let bytes = BASE64.decode(data.as_bytes())
+        .map_err(|e| FileError::new(FileErrorKind::Io, &path, format!("Invalid base64 data: {}", e)))?;
+
+    write_contents(&watcher, &backups, &locks, &mapped, &path, &bytes, encrypt, compress.unwrap_or(false))
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DesignFileSize {
+    pub path: String,
+    /// Bytes the file takes on disk.
+    pub on_disk_bytes: u64,
+    /// Bytes of the contents once decrypted and decompressed.
+    pub in_memory_bytes: u64,
+    pub encrypted: bool,
+    pub compressed: bool,
+}
+
+/// Report how much space a design file takes on disk against the size of
+/// its contents, as shown when choosing whether to compress it. Encrypted
+/// files need their passphrase.
 #[command]
-pub fn get_system_fonts() -> Result<Vec<String>, String> {
-    let mut fonts = Vec::new();
-
-    #[cfg(target_os = "macos")]
-    {
-        let font_dirs = [
-            "/System/Library/Fonts",
-            "/Library/Fonts",
-        ];
-
-        for dir in font_dirs {
-            if let Ok(entries) = fs::read_dir(dir) {
-                for entry in entries.flatten() {
-                    if let Some(name) = entry.file_name().to_str() {
-                        if name.ends_with(".ttf") || name.ends_with(".otf") || name.ends_with(".ttc") {
-                            fonts.push(name.to_string());
-                        }
-                    }
-                }
-            }
-        }
-
-        // Also check user fonts
-        if let Ok(home) = std::env::var("HOME") {
-            let user_fonts = format!("{}/Library/Fonts", home);
-            if let Ok(entries) = fs::read_dir(&user_fonts) {
-                for entry in entries.flatten() {
-                    if let Some(name) = entry.file_name().to_str() {
-                        if name.ends_with(".ttf") || name.ends_with(".otf") || name.ends_with(".ttc") {
-                            fonts.push(name.to_string());
-                        }
-                    }
-                }
-            }
-        }
-    }
+pub fn get_design_file_size(path: String, passphrase: Option<String>) -> Result<DesignFileSize, FileError> {
+    let contents = read_contents(&path, passphrase.as_deref())?;
 
-    #[cfg(target_os = "linux")]
-    {
-        let font_dirs = [
-            "/usr/share/fonts",
-            "/usr/local/share/fonts",
-        ];
-
-        for dir in font_dirs {
-            if let Ok(entries) = fs::read_dir(dir) {
-                for entry in entries.flatten() {
-                    if let Some(name) = entry.file_name().to_str() {
-                        fonts.push(name.to_string());
-                    }
-                }
-            }
-        }
-    }
+    Ok(DesignFileSize {
+        path,
+        on_disk_bytes: contents.on_disk,
+        in_memory_bytes: contents.bytes.len() as u64,
+        encrypted: contents.encrypted,
+        compressed: contents.compressed,
+    })
+}
 
-    #[cfg(target_os = "windows")]
-    {
-        if let Ok(entries) = fs::read_dir("C:\\Windows\\Fonts") {
-            for entry in entries.flatten() {
-                if let Some(name) = entry.file_name().to_str() {
-                    if name.ends_with(".ttf") || name.ends_with(".otf") || name.ends_with(".ttc") {
-                        fonts.push(name.to_string());
-                    }
-                }
-            }
-        }
+/// Rewrite a design file with compression turned on or off, keeping its
+/// contents and encryption. This is the migration path for existing plain
+/// files; saving from the editor with `compress` set converts them too.
+#[command(async)]
+pub fn set_design_file_compression(
+    watcher: State<'_, FileWatcher>,
+    backups: State<'_, BackupManager>,
+    locks: State<'_, FileLocks>,
+    mapped: State<'_, MappedBundles>,
+    path: String,
+    compress: bool,
+    passphrase: Option<String>,
+) -> Result<DesignFileSize, FileError> {
+    let contents = read_contents(&path, passphrase.as_deref())?;
+    if contents.compressed != compress {
+        let encrypt = contents.encrypted.then(|| EncryptOptions { passphrase: passphrase.clone().unwrap_or_default() });
+        write_contents(&watcher, &backups, &locks, &mapped, &path, &contents.bytes, encrypt, compress)?;
     }
+    let on_disk_bytes = fs::metadata(&path)
+        .map_err(|e| FileError::from_io(&e, &path, "read file"))?
+        .len();
 
-    fonts.sort();
-    fonts.dedup();
-    Ok(fonts)
+    Ok(DesignFileSize {
+        path,
+        on_disk_bytes,
+        in_memory_bytes: contents.bytes.len() as u64,
+        encrypted: contents.encrypted,
+        compressed: compress,
+    })
 }
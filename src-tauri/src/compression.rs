@@ -0,0 +1,43 @@
+//! Zstandard compression of design files. A compressed file is a single
+//! zstd frame, told apart from plain JSON by the frame magic number, so old
+//! files keep opening as they are and are converted the next time they are
+//! saved with compression on. Encryption, when used, wraps the compressed
+//! frame: ciphertext does not compress.
+
+use crate::error::{FileError, FileErrorKind};
+use ruzstd::decoding::StreamingDecoder;
+use ruzstd::encoding::{compress_to_vec, CompressionLevel};
+use std::io::Read;
+
+const MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+/// Upper bound on decompressed contents, so a crafted file cannot expand
+/// until memory runs out.
+const MAX_CONTENT_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+
+/// Whether `bytes` start with a zstd frame.
+pub fn is_compressed(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+/// Compress `bytes` into one zstd frame. The fastest level is used: design
+/// JSON is repetitive and shrinks several times over even so, and saving
+/// stays quick for large documents.
+pub fn compress(bytes: &[u8]) -> Vec<u8> {
+    compress_to_vec(bytes, CompressionLevel::Fastest)
+}
+
+/// Decompress a file written by `compress`.
+pub fn decompress(bytes: &[u8], path: &str) -> Result<Vec<u8>, FileError> {
+    let damaged = |e: String| FileError::new(FileErrorKind::Io, path, format!("The compressed file is damaged: {}", e));
+    let decoder = StreamingDecoder::new(bytes).map_err(|e| damaged(e.to_string()))?;
+    let mut out = Vec::with_capacity(bytes.len() * 4);
+    decoder
+        .take(MAX_CONTENT_BYTES + 1)
+        .read_to_end(&mut out)
+        .map_err(|e| damaged(e.to_string()))?;
+    if out.len() as u64 > MAX_CONTENT_BYTES {
+        return Err(FileError::new(FileErrorKind::Io, path, "The compressed file expands to more than 2 GiB"));
+    }
+    Ok(out)
+}
@@ -0,0 +1,1546 @@
+//! Lottie (Bodymovin) JSON animations. Layers import as nodes, with their
+//! keyframes returned beside the document in the frontend's animation model;
+//! export writes transform, opacity and path animation back out as shape
+//! layers.
+
+use crate::atomic::write_atomic;
+use crate::bundle::{BundleAsset, IMAGES_DIR};
+use crate::geometry::{apply, compose, invert, multiply, path_bounds, scale, scale_factor, transform_path, translate, Matrix, Rect, IDENTITY};
+use crate::import::{gradient_placement, paint_transform, place, unmapped_report, NodeBuilder, Placement, UnmappedFeature};
+use crate::model::{
+    generate_node_id, DocumentTree, GradientStop, NodeData, NodeType, Paint, PathCommand, Rgba, SerializedDocument,
+    VectorPath, WindingRule, FORMAT_VERSION,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use tauri::command;
+
+/// Lottie blend modes by their index in `bm`.
+const BLEND_MODES: [&str; 16] = [
+    "NORMAL",
+    "MULTIPLY",
+    "SCREEN",
+    "OVERLAY",
+    "DARKEN",
+    "LIGHTEN",
+    "COLOR_DODGE",
+    "COLOR_BURN",
+    "HARD_LIGHT",
+    "SOFT_LIGHT",
+    "DIFFERENCE",
+    "EXCLUSION",
+    "HUE",
+    "SATURATION",
+    "COLOR",
+    "LUMINOSITY",
+];
+
+/// Bezier handle length for a quarter circle.
+const KAPPA: f64 = 0.552_284_749_8;
+
+/// Guards against layers that are their own ancestors.
+const MAX_NESTING: usize = 32;
+
+/// Keyframe easing, as `EasingDefinition` in `src/animation/types/easing.ts`.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Easing {
+    Preset { preset: String },
+    CubicBezier { x1: f64, y1: f64, x2: f64, y2: f64 },
+    Spring { mass: f64, stiffness: f64, damping: f64, #[serde(default)] velocity: Option<f64> },
+    Steps { steps: u32, #[serde(default)] position: Option<String> },
+}
+
+/// `Keyframe` in `src/animation/types/keyframe.ts`. The easing shapes the
+/// segment that starts at this keyframe, as the property animator applies it.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Keyframe {
+    /// 0 at the start of the animation, 1 at its end.
+    pub time: f64,
+    pub value: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub easing: Option<Easing>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AnimatedProperty {
+    /// Node property, such as `x`, `rotation` or `vectorPaths`.
+    pub path: String,
+    pub keyframes: Vec<Keyframe>,
+}
+
+/// `Animation` in `src/animation/types/animation.ts`, with the node it
+/// plays on.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeAnimation {
+    #[serde(default)]
+    pub id: String,
+    pub node_id: String,
+    /// Milliseconds.
+    pub duration: f64,
+    #[serde(default)]
+    pub delay: f64,
+    /// Used by keyframes without an easing of their own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub easing: Option<Easing>,
+    pub properties: Vec<AnimatedProperty>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LottieImport {
+    pub document: SerializedDocument,
+    pub animations: Vec<NodeAnimation>,
+    pub frame_rate: f64,
+    /// Embedded images, referenced from nodes by their `path`.
+    pub assets: Vec<BundleAsset>,
+    /// Lottie features with no native equivalent, most common first.
+    pub unmapped: Vec<UnmappedFeature>,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LottieExportOptions {
+    /// Frame to export as the composition; defaults to the first frame on
+    /// the first page.
+    pub node_id: Option<String>,
+    pub frame_rate: f64,
+}
+
+impl Default for LottieExportOptions {
+    fn default() -> Self {
+        LottieExportOptions { node_id: None, frame_rate: 60.0 }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LottieExport {
+    pub layer_count: usize,
+    pub frame_count: u32,
+    /// Properties that were approximated or dropped.
+    pub warnings: Vec<String>,
+}
+
+fn num(v: &Value, key: &str) -> Option<f64> {
+    v.get(key)?.as_f64()
+}
+
+fn text<'a>(v: &'a Value, key: &str) -> Option<&'a str> {
+    v.get(key)?.as_str()
+}
+
+fn flag(v: &Value, key: &str) -> bool {
+    match v.get(key) {
+        Some(Value::Bool(b)) => *b,
+        Some(Value::Number(n)) => n.as_f64() == Some(1.0),
+        _ => false,
+    }
+}
+
+fn list<'a>(v: &'a Value, key: &str) -> impl Iterator<Item = &'a Value> {
+    v.get(key).and_then(Value::as_array).into_iter().flatten()
+}
+
+/// Keyframes of an animated property, or `None` for a static one.
+fn keys(prop: &Value) -> Option<&Vec<Value>> {
+    let k = prop.get("k")?.as_array()?;
+    let animated = num(prop, "a") == Some(1.0) || k.first().is_some_and(|f| f.get("t").is_some());
+    Some(k).filter(|_| animated)
+}
+
+fn animated(prop: Option<&Value>) -> bool {
+    prop.and_then(keys).is_some()
+}
+
+/// A property's value at the start; the first keyframe's if it is animated.
+fn initial(prop: &Value) -> Option<&Value> {
+    match keys(prop) {
+        Some(frames) => frames.first()?.get("s"),
+        None => prop.get("k"),
+    }
+}
+
+/// Component `i` of a value that is a number or an array of numbers.
+fn component(v: &Value, i: usize) -> Option<f64> {
+    match v {
+        Value::Array(a) => a.get(i)?.as_f64(),
+        _ if i == 0 => v.as_f64(),
+        _ => None,
+    }
+}
+
+fn scalar(prop: Option<&Value>, default: f64) -> f64 {
+    prop.and_then(initial).and_then(|v| component(v, 0)).unwrap_or(default)
+}
+
+fn pair(prop: Option<&Value>, default: (f64, f64)) -> (f64, f64) {
+    let v = prop.and_then(initial);
+    let at = |i, d| v.and_then(|v| component(v, i)).unwrap_or(d);
+    (at(0, default.0), at(1, default.1))
+}
+
+fn rotate(degrees: f64) -> Matrix {
+    compose(0.0, 0.0, degrees.to_radians(), 1.0, 1.0)
+}
+
+/// Colors are 0-1, except in files from early exporters that used 0-255.
+fn color(v: &Value) -> Rgba {
+    let c: Vec<f64> = (0..3).map(|i| component(v, i).unwrap_or(0.0)).collect();
+    let range = if c.iter().any(|c| *c > 1.0) { 255.0 } else { 1.0 };
+    Rgba { r: c[0] / range, g: c[1] / range, b: c[2] / range, a: 1.0 }
+}
+
+/// Linear easing handles are left out, as the frontend's default.
+fn easing(keyframe: &Value, dim: usize) -> Option<Easing> {
+    if flag(keyframe, "h") {
+        return Some(Easing::Preset { preset: "step-end".to_string() });
+    }
+    let handle = |side: &str, axis: &str| {
+        let v = keyframe.get(side)?.get(axis)?;
+        component(v, dim).or_else(|| component(v, 0))
+    };
+    let (x1, y1) = (handle("o", "x")?, handle("o", "y")?);
+    let (x2, y2) = (handle("i", "x")?, handle("i", "y")?);
+    if x1 == y1 && x2 == y2 {
+        return None;
+    }
+    Some(Easing::CubicBezier { x1, y1, x2, y2 })
+}
+
+/// A `sh` shape value as a path. Keyframe values wrap the shape in a
+/// one-element array.
+fn shape_path(v: &Value) -> Option<VectorPath> {
+    let v = v.as_array().and_then(|a| a.first()).unwrap_or(v);
+    let points = |key| -> Vec<(f64, f64)> {
+        list(v, key).map(|p| (component(p, 0).unwrap_or(0.0), component(p, 1).unwrap_or(0.0))).collect()
+    };
+    let (vertices, ins, outs) = (points("v"), points("i"), points("o"));
+    let first = *vertices.first()?;
+    let tangent = |t: &[(f64, f64)], i: usize| t.get(i).copied().unwrap_or((0.0, 0.0));
+
+    let mut commands = vec![PathCommand::MoveTo { x: first.0, y: first.1 }];
+    let closed = flag(v, "c");
+    let count = vertices.len();
+    let segments = if closed { count } else { count - 1 };
+    for i in 0..segments {
+        let (from, to) = (vertices[i], vertices[(i + 1) % count]);
+        let (out, inn) = (tangent(&outs, i), tangent(&ins, (i + 1) % count));
+        commands.push(if out == (0.0, 0.0) && inn == (0.0, 0.0) {
+            PathCommand::LineTo { x: to.0, y: to.1 }
+        } else {
+            PathCommand::CurveTo {
+                x1: from.0 + out.0,
+                y1: from.1 + out.1,
+                x2: to.0 + inn.0,
+                y2: to.1 + inn.1,
+                x: to.0,
+                y: to.1,
+            }
+        });
+    }
+    if closed {
+        commands.push(PathCommand::ClosePath);
+    }
+    Some(VectorPath { winding_rule: WindingRule::Nonzero, commands })
+}
+
+fn rect_path(center: (f64, f64), size: (f64, f64), radius: f64) -> VectorPath {
+    let (l, t) = (center.0 - size.0 / 2.0, center.1 - size.1 / 2.0);
+    let (r, b) = (l + size.0, t + size.1);
+    let radius = radius.clamp(0.0, size.0.min(size.1) / 2.0);
+    if radius <= 0.0 {
+        let corners = [(r, t), (r, b), (l, b)];
+        let mut commands = vec![PathCommand::MoveTo { x: l, y: t }];
+        commands.extend(corners.iter().map(|&(x, y)| PathCommand::LineTo { x, y }));
+        commands.push(PathCommand::ClosePath);
+        return VectorPath { winding_rule: WindingRule::Nonzero, commands };
+    }
+
+    let k = radius * (1.0 - KAPPA);
+    let curve = |x1, y1, x2, y2, x, y| PathCommand::CurveTo { x1, y1, x2, y2, x, y };
+    let commands = vec![
+        PathCommand::MoveTo { x: l + radius, y: t },
+        PathCommand::LineTo { x: r - radius, y: t },
+        curve(r - k, t, r, t + k, r, t + radius),
+        PathCommand::LineTo { x: r, y: b - radius },
+        curve(r, b - k, r - k, b, r - radius, b),
+        PathCommand::LineTo { x: l + radius, y: b },
+        curve(l + k, b, l, b - k, l, b - radius),
+        PathCommand::LineTo { x: l, y: t + radius },
+        curve(l, t + k, l + k, t, l + radius, t),
+        PathCommand::ClosePath,
+    ];
+    VectorPath { winding_rule: WindingRule::Nonzero, commands }
+}
+
+fn ellipse_path(center: (f64, f64), size: (f64, f64)) -> VectorPath {
+    let (cx, cy) = center;
+    let (rx, ry) = (size.0 / 2.0, size.1 / 2.0);
+    let (kx, ky) = (rx * KAPPA, ry * KAPPA);
+    let curve = |x1, y1, x2, y2, x, y| PathCommand::CurveTo { x1, y1, x2, y2, x, y };
+    let commands = vec![
+        PathCommand::MoveTo { x: cx, y: cy - ry },
+        curve(cx + kx, cy - ry, cx + rx, cy - ky, cx + rx, cy),
+        curve(cx + rx, cy + ky, cx + kx, cy + ry, cx, cy + ry),
+        curve(cx - kx, cy + ry, cx - rx, cy + ky, cx - rx, cy),
+        curve(cx - rx, cy - ky, cx - kx, cy - ry, cx, cy - ry),
+        PathCommand::ClosePath,
+    ];
+    VectorPath { winding_rule: WindingRule::Nonzero, commands }
+}
+
+/// A `sr` polystar: `sy` 1 is a star with inner points, 2 a polygon.
+fn star_path(item: &Value) -> VectorPath {
+    let center = pair(item.get("p"), (0.0, 0.0));
+    let points = scalar(item.get("pt"), 5.0).round().max(3.0) as usize;
+    let outer = scalar(item.get("or"), 0.0);
+    let inner = scalar(item.get("ir"), outer / 2.0);
+    let star = num(item, "sy") != Some(2.0);
+    let start = (scalar(item.get("r"), 0.0) - 90.0).to_radians();
+
+    let count = if star { points * 2 } else { points };
+    let mut commands = Vec::with_capacity(count + 1);
+    for i in 0..count {
+        let radius = if star && i % 2 == 1 { inner } else { outer };
+        let angle = start + std::f64::consts::TAU * i as f64 / count as f64;
+        let (x, y) = (center.0 + radius * angle.cos(), center.1 + radius * angle.sin());
+        commands.push(if i == 0 { PathCommand::MoveTo { x, y } } else { PathCommand::LineTo { x, y } });
+    }
+    commands.push(PathCommand::ClosePath);
+    VectorPath { winding_rule: WindingRule::Nonzero, commands }
+}
+
+/// Gradient stops from a `gf`/`gs` item: `p` color stops of offset and RGB,
+/// then optional offset and alpha pairs.
+fn gradient_stops(g: &Value) -> Vec<GradientStop> {
+    let count = num(g, "p").unwrap_or(0.0) as usize;
+    let values: Vec<f64> = g
+        .get("k")
+        .and_then(initial)
+        .and_then(Value::as_array)
+        .map(|a| a.iter().filter_map(Value::as_f64).collect())
+        .unwrap_or_default();
+    let split = (count * 4).min(values.len() - values.len() % 4);
+    let (colors, alphas) = values.split_at(split);
+    let alphas: Vec<&[f64]> = alphas.chunks_exact(2).collect();
+
+    let alpha_at = |position: f64| {
+        let Some(first) = alphas.first() else { return 1.0 };
+        let after = alphas.iter().position(|a| a[0] >= position);
+        match after {
+            None => alphas[alphas.len() - 1][1],
+            Some(0) => first[1],
+            Some(i) => {
+                let (a, b) = (alphas[i - 1], alphas[i]);
+                let t = if b[0] > a[0] { (position - a[0]) / (b[0] - a[0]) } else { 0.0 };
+                a[1] + (b[1] - a[1]) * t
+            }
+        }
+    };
+    colors
+        .chunks_exact(4)
+        .map(|c| GradientStop { position: c[0], color: Rgba { r: c[1], g: c[2], b: c[3], a: alpha_at(c[0]) } })
+        .collect()
+}
+
+/// Place a box of `width` by `height` under `t`, folding its scale into the
+/// size since nodes only carry a position and rotation.
+fn place_box(t: &Matrix, width: f64, height: f64) -> Placement {
+    let sx = (t[0] * t[0] + t[1] * t[1]).sqrt().max(1e-9);
+    let sy = (t[2] * t[2] + t[3] * t[3]).sqrt().max(1e-9);
+    place(&multiply(t, &scale(1.0 / sx, 1.0 / sy)), Rect::new(0.0, 0.0, width * sx, height * sy), None)
+}
+
+/// Paints and the stroke that apply to the shapes of a group.
+#[derive(Clone, Default)]
+struct ShapeStyle {
+    /// In Lottie order, topmost first.
+    fills: Vec<Value>,
+    stroke: Option<Value>,
+}
+
+/// Where the contents of a layer or group are added.
+#[derive(Clone, Copy)]
+struct Space {
+    /// Lottie coordinates of the contents to composition coordinates.
+    content: Matrix,
+    /// World transform of the node they are added under.
+    node: Matrix,
+    /// Frames added to the contents' keyframe times.
+    offset: f64,
+}
+
+struct Importer {
+    dir: PathBuf,
+    /// First frame and length of the composition, in frames.
+    start: f64,
+    length: f64,
+    duration_ms: f64,
+    precomps: HashMap<String, Value>,
+    image_assets: HashMap<String, Value>,
+    /// Lottie asset id -> asset path.
+    images: HashMap<String, String>,
+    assets: Vec<BundleAsset>,
+    animations: Vec<NodeAnimation>,
+    out: NodeBuilder,
+    unmapped: BTreeMap<&'static str, usize>,
+}
+
+impl Importer {
+    fn unmapped(&mut self, feature: &'static str) {
+        *self.unmapped.entry(feature).or_default() += 1;
+    }
+
+    fn animate(&mut self, node_id: &str, properties: Vec<AnimatedProperty>) {
+        if !properties.is_empty() {
+            self.animations.push(NodeAnimation {
+                id: generate_node_id(),
+                node_id: node_id.to_string(),
+                duration: self.duration_ms,
+                delay: 0.0,
+                easing: None,
+                properties,
+            });
+        }
+    }
+
+    fn time(&self, frame: f64) -> f64 {
+        (frame - self.start) / self.length
+    }
+
+    /// Keyframes of `prop` with values mapped through `value`, or `None` if
+    /// it is static. Layer times are shifted by `offset` frames.
+    fn keyframes(&self, prop: &Value, offset: f64, dim: usize, value: impl Fn(&Value) -> Option<Value>) -> Option<Vec<Keyframe>> {
+        let frames = keys(prop)?;
+        let mut out = Vec::with_capacity(frames.len());
+        let mut previous_end = None;
+        for (i, frame) in frames.iter().enumerate() {
+            // Older files leave the start value out and repeat the previous
+            // keyframe's end value instead
+            let Some(v) = frame.get("s").or(previous_end) else { continue };
+            previous_end = frame.get("e");
+            let Some(mapped) = value(v) else { continue };
+            let t = num(frame, "t").unwrap_or(0.0) + offset;
+            let easing = if i + 1 < frames.len() { easing(frame, dim) } else { None };
+            out.push(Keyframe { time: self.time(t), value: mapped, easing });
+        }
+        Some(out).filter(|k| !k.is_empty())
+    }
+
+    /// Apply a layer or group transform `ks` to `data`, which is added in
+    /// `parent`. Returns the world transform of the layer's content and its
+    /// animated properties.
+    fn transform(&mut self, data: &mut NodeData, ks: &Value, parent: &Space) -> (Matrix, Vec<AnimatedProperty>) {
+        let (parent_content, offset) = (&parent.content, parent.offset);
+        let position = ks.get("p");
+        let split = position.is_some_and(|p| flag(p, "s"));
+        let (px, py) = match position {
+            Some(p) if split => (scalar(p.get("x"), 0.0), scalar(p.get("y"), 0.0)),
+            _ => pair(position, (0.0, 0.0)),
+        };
+        let (ax, ay) = pair(ks.get("a"), (0.0, 0.0));
+        let (sx, sy) = pair(ks.get("s"), (100.0, 100.0));
+        let rotation_prop = ks.get("r").or(ks.get("rz"));
+        let r = scalar(rotation_prop, 0.0);
+        let opacity = scalar(ks.get("o"), 100.0) / 100.0;
+        if scalar(ks.get("sk"), 0.0) != 0.0 {
+            self.unmapped("Skewed layers");
+        }
+
+        let to_node = invert(&parent.node).unwrap_or(IDENTITY);
+        let pivot = multiply(parent_content, &multiply(&translate(px, py), &rotate(r)));
+        let local = multiply(&to_node, &pivot);
+        let rotation = local[1].atan2(local[0]).to_degrees();
+        data.x = Some(local[4]);
+        data.y = Some(local[5]);
+        data.rotation = Some(rotation).filter(|r| r.abs() > 1e-6);
+        data.opacity = Some(opacity).filter(|o| *o < 1.0);
+        let content = multiply(&pivot, &multiply(&scale(sx / 100.0, sy / 100.0), &translate(-ax, -ay)));
+
+        // Parent content space -> the node's parent space, constant over time
+        let a = multiply(&to_node, parent_content);
+        let mut properties = Vec::new();
+        let mut push = |path: &str, keyframes: Option<Vec<Keyframe>>| {
+            if let Some(keyframes) = keyframes {
+                properties.push(AnimatedProperty { path: path.to_string(), keyframes });
+            }
+        };
+        match position {
+            Some(p) if split => {
+                let mixes_axes = a[1].abs() > 1e-9 || a[2].abs() > 1e-9;
+                if mixes_axes && (animated(p.get("x")) || animated(p.get("y"))) {
+                    self.unmapped("Separate X and Y motion inside rotated layers");
+                } else {
+                    for (key, m, t) in [("x", a[0], a[4]), ("y", a[3], a[5])] {
+                        if let Some(prop) = p.get(key) {
+                            push(key, self.keyframes(prop, offset, 0, |v| Some((m * component(v, 0)? + t).into())));
+                        }
+                    }
+                }
+            }
+            Some(p) if keys(p).is_some() => {
+                let curved = keys(p).into_iter().flatten().any(|k| {
+                    ["ti", "to"].iter().any(|t| list(k, t).any(|c| c.as_f64().is_some_and(|c| c != 0.0)))
+                });
+                if curved {
+                    self.unmapped("Curved motion paths (imported as straight)");
+                }
+                for (axis, key) in [(0, "x"), (1, "y")] {
+                    let value = |v: &Value| {
+                        let (x, y) = apply(&a, component(v, 0)?, component(v, 1)?);
+                        Some(if axis == 0 { x } else { y }.into())
+                    };
+                    push(key, self.keyframes(p, offset, axis, value));
+                }
+            }
+            _ => {}
+        }
+        if let Some(prop) = rotation_prop {
+            let base = rotation - r;
+            push("rotation", self.keyframes(prop, offset, 0, |v| Some((component(v, 0)? + base).into())));
+        }
+        if let Some(prop) = ks.get("o") {
+            push("opacity", self.keyframes(prop, offset, 0, |v| Some((component(v, 0)? / 100.0).into())));
+        }
+        if animated(ks.get("s")) {
+            self.unmapped("Scale animations (imported at their first value)");
+        }
+        if animated(ks.get("a")) {
+            self.unmapped("Anchor point animations");
+        }
+        (content, properties)
+    }
+
+    /// Limit a layer to its in and out points by holding its opacity at zero
+    /// outside them.
+    fn in_out_points(&mut self, data: &mut NodeData, properties: &mut Vec<AnimatedProperty>, ip: f64, op: f64) {
+        let end = self.start + self.length;
+        if ip <= self.start && op >= end {
+            return;
+        }
+        if op <= self.start || ip >= end {
+            data.visible = false;
+            return;
+        }
+        if properties.iter().any(|p| p.path == "opacity") {
+            self.unmapped("In and out points of layers with animated opacity");
+            return;
+        }
+
+        let opacity = data.opacity();
+        let hold = Some(Easing::Preset { preset: "step-end".to_string() });
+        let starts_hidden = ip > self.start;
+        let mut keyframes = vec![Keyframe {
+            time: 0.0,
+            value: if starts_hidden { 0.0 } else { opacity }.into(),
+            easing: hold.clone(),
+        }];
+        if starts_hidden {
+            keyframes.push(Keyframe { time: self.time(ip), value: opacity.into(), easing: hold });
+            data.opacity = Some(0.0);
+        }
+        if op < end {
+            keyframes.push(Keyframe { time: self.time(op), value: 0.0.into(), easing: None });
+        }
+        properties.push(AnimatedProperty { path: "opacity".to_string(), keyframes });
+    }
+
+    /// Copy an image asset into the assets, returning its asset path.
+    fn image(&mut self, asset_id: &str) -> Option<String> {
+        if let Some(path) = self.images.get(asset_id) {
+            return Some(path.clone());
+        }
+        let asset = self.image_assets.get(asset_id)?;
+        let name = text(asset, "p")?;
+        let bytes = match name.strip_prefix("data:") {
+            Some(rest) => BASE64.decode(rest.split_once(";base64,")?.1.as_bytes()).ok()?,
+            None => std::fs::read(self.dir.join(text(asset, "u").unwrap_or_default()).join(name)).ok()?,
+        };
+        let extension = match image::guess_format(&bytes).ok()? {
+            image::ImageFormat::Png => "png",
+            image::ImageFormat::Jpeg => "jpg",
+            image::ImageFormat::WebP => "webp",
+            _ => return None,
+        };
+
+        let path = format!("{}{}.{}", IMAGES_DIR, generate_node_id(), extension);
+        self.assets.push(BundleAsset { path: path.clone(), data: BASE64.encode(&bytes) });
+        self.images.insert(asset_id.to_string(), path.clone());
+        Some(path)
+    }
+
+    /// A fill or stroke item as a paint. Gradient points are in the shape's
+    /// content space, which `to_local` maps into the node box `to`.
+    fn paint(&mut self, item: &Value, to_local: &Matrix, to: Rect) -> Option<Paint> {
+        let opacity = scalar(item.get("o"), 100.0) / 100.0;
+        if animated(item.get("o")) || animated(item.get("c")) || item.get("g").is_some_and(|g| animated(g.get("k"))) {
+            self.unmapped("Color and opacity animations of fills and strokes");
+        }
+
+        match text(item, "ty")? {
+            "gf" | "gs" => {
+                let from = pair(item.get("s"), (0.0, 0.0));
+                let end = pair(item.get("e"), (0.0, 0.0));
+                let radial = num(item, "t") == Some(2.0);
+                if radial && scalar(item.get("h"), 0.0) != 0.0 {
+                    self.unmapped("Radial gradient highlights");
+                }
+                let gradient_stops = gradient_stops(item.get("g")?);
+                let placed = gradient_placement(from, end, radial.then_some(1.0));
+                let gradient_transform = paint_transform(&placed, to_local, to)?;
+                Some(match radial {
+                    true => Paint::GradientRadial { visible: true, opacity, gradient_stops, gradient_transform },
+                    false => Paint::GradientLinear { visible: true, opacity, gradient_stops, gradient_transform },
+                })
+            }
+            _ => {
+                let color = color(item.get("c").and_then(initial)?);
+                Some(Paint::Solid { visible: true, opacity, color })
+            }
+        }
+    }
+
+    fn stroke(&mut self, data: &mut NodeData, stroke: &Value, width_scale: f64) {
+        data.stroke_weight = Some(scalar(stroke.get("w"), 1.0) * width_scale);
+        if animated(stroke.get("w")) {
+            self.unmapped("Stroke width animations");
+        }
+        data.stroke_cap = match num(stroke, "lc") {
+            Some(2.0) => Some("ROUND".to_string()),
+            Some(3.0) => Some("SQUARE".to_string()),
+            _ => None,
+        };
+        data.stroke_join = match num(stroke, "lj") {
+            Some(2.0) => Some("ROUND".to_string()),
+            Some(3.0) => Some("BEVEL".to_string()),
+            _ => None,
+        };
+        data.stroke_miter_limit = num(stroke, "ml");
+
+        let mut dashes = Vec::new();
+        for dash in list(stroke, "d") {
+            let value = scalar(dash.get("v"), 0.0) * width_scale;
+            match text(dash, "n") {
+                Some("o") => data.dash_offset = Some(value),
+                _ => dashes.push(value),
+            }
+        }
+        data.dash_pattern = Some(dashes).filter(|d| !d.is_empty());
+    }
+
+    /// Combine the geometry of one shape group into a vector node.
+    fn vector(&mut self, parent: &str, geometry: &[&Value], style: &ShapeStyle, space: &Space) {
+        let mut paths: Vec<VectorPath> = Vec::new();
+        for item in geometry {
+            let path = match text(item, "ty") {
+                Some("sh") => item.get("ks").and_then(initial).and_then(shape_path),
+                Some("rc") => Some(rect_path(
+                    pair(item.get("p"), (0.0, 0.0)),
+                    pair(item.get("s"), (0.0, 0.0)),
+                    scalar(item.get("r"), 0.0),
+                )),
+                Some("el") => Some(ellipse_path(pair(item.get("p"), (0.0, 0.0)), pair(item.get("s"), (0.0, 0.0)))),
+                _ => Some(star_path(item)),
+            };
+            if text(item, "ty") != Some("sh") && ["p", "s", "r", "or", "ir", "pt"].iter().any(|k| animated(item.get(*k))) {
+                self.unmapped("Animated rectangle, ellipse and star parameters");
+            }
+            paths.extend(path);
+        }
+        if paths.is_empty() {
+            return;
+        }
+        let evenodd = style.fills.first().is_some_and(|f| num(f, "r") == Some(2.0));
+        for path in &mut paths {
+            path.winding_rule = if evenodd { WindingRule::Evenodd } else { WindingRule::Nonzero };
+        }
+
+        let t = multiply(&invert(&space.node).unwrap_or(IDENTITY), &space.content);
+        let placement = place(&t, Rect::new(0.0, 0.0, 0.0, 0.0), Some(&mut paths));
+        let name = geometry.first().and_then(|g| text(g, "nm")).unwrap_or("Path");
+        let mut data = NodeData::new(generate_node_id(), NodeType::Vector, name);
+        data.x = Some(placement.x);
+        data.y = Some(placement.y);
+        data.rotation = placement.rotation;
+        data.width = Some(placement.bounds.width);
+        data.height = Some(placement.bounds.height);
+        let to = placement.bounds;
+
+        let fills: Vec<Paint> = style.fills.iter().rev().filter_map(|f| self.paint(f, &placement.to_local, to)).collect();
+        data.fills = Some(fills);
+        if let Some(stroke) = &style.stroke {
+            self.stroke(&mut data, stroke, scale_factor(&t));
+            data.strokes = Some(self.paint(stroke, &placement.to_local, to).into_iter().collect());
+        }
+
+        let mut properties = Vec::new();
+        let animated_paths: Vec<&&Value> = geometry.iter().filter(|g| g.get("ks").is_some_and(|k| keys(k).is_some())).collect();
+        match (geometry.len(), animated_paths.first()) {
+            (_, None) => {}
+            (1, Some(item)) => {
+                let to_local = placement.to_local;
+                let path = |v: &Value| {
+                    let mut path = shape_path(v)?;
+                    path.winding_rule = paths[0].winding_rule;
+                    Some(transform_path(&path, &to_local))
+                };
+                let keyframes = item.get("ks").and_then(|k| self.keyframes(k, space.offset, 0, |v| serde_json::to_value(vec![path(v)?]).ok()));
+                if let Some(keyframes) = keyframes {
+                    // The renderer stretches paths to the node size, so the
+                    // size follows the path
+                    let size = |k: &Keyframe, horizontal: bool| {
+                        let paths: Vec<VectorPath> = serde_json::from_value(k.value.clone()).ok()?;
+                        let b = paths.iter().filter_map(path_bounds).reduce(|a, b| a.union(&b))?;
+                        Some(Keyframe { time: k.time, value: if horizontal { b.width } else { b.height }.into(), easing: k.easing.clone() })
+                    };
+                    let widths: Vec<Keyframe> = keyframes.iter().filter_map(|k| size(k, true)).collect();
+                    let heights: Vec<Keyframe> = keyframes.iter().filter_map(|k| size(k, false)).collect();
+                    let varies = |k: &[Keyframe]| k.windows(2).any(|w| w[0].value.as_f64() != w[1].value.as_f64());
+                    if varies(&widths) {
+                        properties.push(AnimatedProperty { path: "width".to_string(), keyframes: widths });
+                    }
+                    if varies(&heights) {
+                        properties.push(AnimatedProperty { path: "height".to_string(), keyframes: heights });
+                    }
+                    properties.push(AnimatedProperty { path: "vectorPaths".to_string(), keyframes });
+                }
+            }
+            _ => self.unmapped("Path animations in compound shapes"),
+        }
+        data.vector_paths = Some(paths);
+
+        let id = self.out.add(Some(parent), data);
+        self.animate(&id, properties);
+    }
+
+    /// Convert the items of a shape layer or group. Paints apply to the
+    /// geometry of their group and of nested groups.
+    fn shapes(&mut self, items: &[Value], parent: &str, space: &Space, inherited: &ShapeStyle, depth: usize) {
+        let items: Vec<&Value> = items.iter().filter(|i| !flag(i, "hd")).collect();
+        let mut style = ShapeStyle::default();
+        for item in &items {
+            match text(item, "ty") {
+                Some("fl" | "gf") => style.fills.push((*item).clone()),
+                Some("st" | "gs") if style.stroke.is_none() => style.stroke = Some((*item).clone()),
+                Some("st" | "gs") => self.unmapped("Multiple strokes (imported with the first)"),
+                _ => {}
+            }
+        }
+        style.fills.extend(inherited.fills.iter().cloned());
+        style.stroke = style.stroke.or_else(|| inherited.stroke.clone());
+
+        let geometry: Vec<&Value> = items
+            .iter()
+            .copied()
+            .filter(|i| matches!(text(i, "ty"), Some("sh" | "rc" | "el" | "sr")))
+            .collect();
+        let mut vector_added = false;
+
+        // Items listed first are drawn on top
+        for item in items.iter().rev() {
+            match text(item, "ty").unwrap_or_default() {
+                "sh" | "rc" | "el" | "sr" if !vector_added => {
+                    vector_added = true;
+                    self.vector(parent, &geometry, &style, space);
+                }
+                "gr" if depth < MAX_NESTING => {
+                    let mut data = NodeData::new(generate_node_id(), NodeType::Group, text(item, "nm").unwrap_or("Group"));
+                    let children: Vec<Value> = list(item, "it").cloned().collect();
+                    let (content, properties) = match children.iter().find(|i| text(i, "ty") == Some("tr")) {
+                        Some(tr) => self.transform(&mut data, tr, space),
+                        None => (space.content, Vec::new()),
+                    };
+                    let group = Space { content, node: multiply(&space.node, &data.local_transform()), ..*space };
+                    let id = self.out.add(Some(parent), data);
+                    self.animate(&id, properties);
+                    self.shapes(&children, &id, &group, &style, depth + 1);
+                }
+                "tm" => self.unmapped("Trim paths"),
+                "rp" => self.unmapped("Repeaters"),
+                "mm" => self.unmapped("Merge paths"),
+                "rd" => self.unmapped("Rounded corners modifiers"),
+                "op" | "pb" | "tw" | "zz" => self.unmapped("Path modifiers"),
+                _ => {}
+            }
+        }
+    }
+
+    /// Convert the layers of a composition whose `parent` is `parent_index`;
+    /// child layers are nested in the node of the layer they follow.
+    fn layers(&mut self, layers: &[Value], parent_index: Option<f64>, parent: &str, space: &Space, depth: usize) {
+        if depth >= MAX_NESTING {
+            self.out.warn("Layers nested too deeply were skipped");
+            return;
+        }
+        let indexes: Vec<f64> = layers.iter().filter_map(|l| num(l, "ind")).collect();
+        let parent_of = |l: &Value| num(l, "parent").filter(|p| indexes.contains(p));
+
+        // Layers listed first are drawn on top
+        for layer in layers.iter().rev().filter(|l| parent_of(l) == parent_index) {
+            let kind = num(layer, "ty").unwrap_or(-1.0) as i64;
+            let mut data = NodeData::new(generate_node_id(), NodeType::Group, text(layer, "nm").unwrap_or("Layer"));
+            // Matte sources only shape the layer below them
+            data.visible = !flag(layer, "hd") && !flag(layer, "td");
+            data.blend_mode = num(layer, "bm")
+                .and_then(|i| BLEND_MODES.get(i as usize))
+                .filter(|mode| **mode != "NORMAL")
+                .map(|mode| mode.to_string());
+            if flag(layer, "td") || layer.get("tt").is_some() {
+                self.unmapped("Track mattes");
+            }
+            if list(layer, "masksProperties").next().is_some() {
+                self.unmapped("Masks");
+            }
+            if list(layer, "ef").next().is_some() {
+                self.unmapped("Layer effects");
+            }
+            if flag(layer, "ddd") {
+                self.unmapped("3D layers");
+            }
+
+            let ks = layer.get("ks").cloned().unwrap_or(Value::Null);
+            let (content, mut properties) = self.transform(&mut data, &ks, space);
+            let ip = num(layer, "ip").unwrap_or(self.start) + space.offset;
+            let op = num(layer, "op").unwrap_or(self.start + self.length) + space.offset;
+            self.in_out_points(&mut data, &mut properties, ip, op);
+
+            let node_world = multiply(&space.node, &data.local_transform());
+            let contents = Space { content, node: node_world, ..*space };
+            let id = self.out.add(Some(parent), data);
+            self.animate(&id, properties);
+            let to_content = multiply(&invert(&node_world).unwrap_or(IDENTITY), &content);
+            let size = (num(layer, "w").unwrap_or(0.0), num(layer, "h").unwrap_or(0.0));
+
+            match kind {
+                0 => {
+                    let Some(precomp) = text(layer, "refId").and_then(|r| self.precomps.get(r)).cloned() else {
+                        self.out.warn("Some precompositions are missing from the file and were skipped");
+                        continue;
+                    };
+                    if layer.get("tm").is_some() {
+                        self.unmapped("Time remapping");
+                    }
+                    let placement = place_box(&to_content, size.0, size.1);
+                    let mut frame = NodeData::new(generate_node_id(), NodeType::Frame, text(layer, "nm").unwrap_or("Precomp"));
+                    frame.x = Some(placement.x);
+                    frame.y = Some(placement.y);
+                    frame.rotation = placement.rotation;
+                    frame.width = Some(placement.bounds.width);
+                    frame.height = Some(placement.bounds.height);
+                    frame.fills = Some(Vec::new());
+                    frame.clips_content = Some(true);
+                    let frame_world = multiply(&node_world, &frame.local_transform());
+                    let frame_id = self.out.add(Some(&id), frame);
+
+                    let precomp_layers: Vec<Value> = list(&precomp, "layers").cloned().collect();
+                    let offset = space.offset + num(layer, "st").unwrap_or(0.0);
+                    let precomp = Space { content, node: frame_world, offset };
+                    self.layers(&precomp_layers, None, &frame_id, &precomp, depth + 1);
+                }
+                1 => {
+                    let size = (num(layer, "sw").unwrap_or(0.0), num(layer, "sh").unwrap_or(0.0));
+                    let placement = place_box(&to_content, size.0, size.1);
+                    let mut solid = NodeData::new(generate_node_id(), NodeType::Rectangle, "Solid");
+                    solid.x = Some(placement.x);
+                    solid.y = Some(placement.y);
+                    solid.rotation = placement.rotation;
+                    solid.width = Some(placement.bounds.width);
+                    solid.height = Some(placement.bounds.height);
+                    let color = text(layer, "sc").map(|hex| {
+                        let hex = hex.trim_start_matches('#');
+                        let c = |i: usize| hex.get(i..i + 2).and_then(|s| u8::from_str_radix(s, 16).ok()).unwrap_or(0) as f64 / 255.0;
+                        Rgba { r: c(0), g: c(2), b: c(4), a: 1.0 }
+                    });
+                    solid.fills = Some(color.map(|color| Paint::Solid { visible: true, opacity: 1.0, color }).into_iter().collect());
+                    self.out.add(Some(&id), solid);
+                }
+                2 => {
+                    let asset_id = text(layer, "refId").unwrap_or_default().to_string();
+                    let asset = self.image_assets.get(&asset_id).cloned().unwrap_or(Value::Null);
+                    let Some(image_ref) = self.image(&asset_id) else {
+                        self.out.warn("Some images are missing from the file and were dropped");
+                        continue;
+                    };
+                    let placement = place_box(&to_content, num(&asset, "w").unwrap_or(0.0), num(&asset, "h").unwrap_or(0.0));
+                    let mut image = NodeData::new(generate_node_id(), NodeType::Image, text(layer, "nm").unwrap_or("Image"));
+                    image.x = Some(placement.x);
+                    image.y = Some(placement.y);
+                    image.rotation = placement.rotation;
+                    image.width = Some(placement.bounds.width);
+                    image.height = Some(placement.bounds.height);
+                    image.image_ref = Some(image_ref);
+                    self.out.add(Some(&id), image);
+                }
+                3 => {}
+                4 => {
+                    let shapes: Vec<Value> = list(layer, "shapes").cloned().collect();
+                    self.shapes(&shapes, &id, &contents, &ShapeStyle::default(), depth);
+                }
+                5 => self.unmapped("Text layers"),
+                _ => self.unmapped("Other layer types"),
+            }
+
+            if let Some(index) = num(layer, "ind") {
+                self.layers(layers, Some(index), &id, &contents, depth + 1);
+            }
+        }
+    }
+}
+
+/// Import a Lottie JSON animation as a page holding one frame the size of
+/// the composition. Keyframes come back as animations of the imported nodes.
+#[command(async)]
+pub fn import_lottie(path: String) -> Result<LottieImport, String> {
+    let bytes = std::fs::read(&path)
+        .map_err(|e| format!("Failed to read Lottie file: {}", e))?;
+    let lottie: Value = serde_json::from_slice(&bytes)
+        .map_err(|e| format!("Invalid Lottie file: {}", e))?;
+    if lottie.get("layers").is_none() {
+        return Err("Not a Lottie animation: the file has no layers".to_string());
+    }
+
+    let frame_rate = num(&lottie, "fr").filter(|f| *f > 0.0).unwrap_or(30.0);
+    let start = num(&lottie, "ip").unwrap_or(0.0);
+    let length = (num(&lottie, "op").unwrap_or(start + 1.0) - start).max(1.0);
+    let (width, height) = (num(&lottie, "w").unwrap_or(0.0), num(&lottie, "h").unwrap_or(0.0));
+    let name = text(&lottie, "nm").unwrap_or("Animation").to_string();
+
+    let mut precomps = HashMap::new();
+    let mut image_assets = HashMap::new();
+    for asset in list(&lottie, "assets") {
+        let Some(id) = text(asset, "id") else { continue };
+        if asset.get("layers").is_some() {
+            precomps.insert(id.to_string(), asset.clone());
+        } else {
+            image_assets.insert(id.to_string(), asset.clone());
+        }
+    }
+
+    let mut importer = Importer {
+        dir: Path::new(&path).parent().map(Path::to_path_buf).unwrap_or_default(),
+        start,
+        length,
+        duration_ms: length / frame_rate * 1000.0,
+        precomps,
+        image_assets,
+        images: HashMap::new(),
+        assets: Vec::new(),
+        animations: Vec::new(),
+        out: NodeBuilder::default(),
+        unmapped: BTreeMap::new(),
+    };
+    if list(&lottie, "fonts").next().is_some() || list(&lottie, "chars").next().is_some() {
+        importer.out.warn("Embedded fonts and glyphs were not imported");
+    }
+
+    let root_id = importer.out.add(None, NodeData::new(generate_node_id(), NodeType::Document, name.clone()));
+    let page_id = importer.out.add(Some(&root_id), NodeData::new(generate_node_id(), NodeType::Page, "Page 1"));
+    let mut frame = NodeData::new(generate_node_id(), NodeType::Frame, name.clone());
+    frame.x = Some(0.0);
+    frame.y = Some(0.0);
+    frame.width = Some(width);
+    frame.height = Some(height);
+    frame.fills = Some(Vec::new());
+    frame.clips_content = Some(true);
+    let frame_id = importer.out.add(Some(&page_id), frame);
+
+    let layers: Vec<Value> = list(&lottie, "layers").cloned().collect();
+    let composition = Space { content: IDENTITY, node: IDENTITY, offset: 0.0 };
+    importer.layers(&layers, None, &frame_id, &composition, 0);
+
+    let unmapped = unmapped_report(&importer.unmapped);
+    let assets = std::mem::take(&mut importer.assets);
+    let animations = std::mem::take(&mut importer.animations);
+    let (nodes, warnings) = importer.out.into_parts();
+    Ok(LottieImport {
+        document: SerializedDocument {
+            version: "1.0.0".to_string(),
+            format_version: FORMAT_VERSION,
+            name,
+            created_at: String::new(),
+            updated_at: String::new(),
+            nodes,
+            root_id,
+        },
+        animations,
+        frame_rate,
+        assets,
+        unmapped,
+        warnings,
+    })
+}
+
+/// Bezier handles of the frontend's cubic easing presets, from
+/// `src/animation/easing/presets.ts`.
+fn preset_bezier(preset: &str) -> Option<[f64; 4]> {
+    Some(match preset {
+        "ease" => [0.25, 0.1, 0.25, 1.0],
+        "ease-in" => [0.42, 0.0, 1.0, 1.0],
+        "ease-out" => [0.0, 0.0, 0.58, 1.0],
+        "ease-in-out" => [0.42, 0.0, 0.58, 1.0],
+        "ease-in-quad" => [0.55, 0.085, 0.68, 0.53],
+        "ease-out-quad" => [0.25, 0.46, 0.45, 0.94],
+        "ease-in-out-quad" => [0.455, 0.03, 0.515, 0.955],
+        "ease-in-cubic" => [0.55, 0.055, 0.675, 0.19],
+        "ease-out-cubic" => [0.215, 0.61, 0.355, 1.0],
+        "ease-in-out-cubic" => [0.645, 0.045, 0.355, 1.0],
+        "ease-in-quart" => [0.895, 0.03, 0.685, 0.22],
+        "ease-out-quart" => [0.165, 0.84, 0.44, 1.0],
+        "ease-in-out-quart" => [0.77, 0.0, 0.175, 1.0],
+        "ease-in-quint" => [0.755, 0.05, 0.855, 0.06],
+        "ease-out-quint" => [0.23, 1.0, 0.32, 1.0],
+        "ease-in-out-quint" => [0.86, 0.0, 0.07, 1.0],
+        "ease-in-sine" => [0.47, 0.0, 0.745, 0.715],
+        "ease-out-sine" => [0.39, 0.575, 0.565, 1.0],
+        "ease-in-out-sine" => [0.445, 0.05, 0.55, 0.95],
+        "ease-in-expo" => [0.95, 0.05, 0.795, 0.035],
+        "ease-out-expo" => [0.19, 1.0, 0.22, 1.0],
+        "ease-in-out-expo" => [1.0, 0.0, 0.0, 1.0],
+        "ease-in-circ" => [0.6, 0.04, 0.98, 0.335],
+        "ease-out-circ" => [0.075, 0.82, 0.165, 1.0],
+        "ease-in-out-circ" => [0.785, 0.135, 0.15, 0.86],
+        "ease-in-back" => [0.6, -0.28, 0.735, 0.045],
+        "ease-out-back" => [0.175, 0.885, 0.32, 1.275],
+        "ease-in-out-back" => [0.68, -0.55, 0.265, 1.55],
+        _ => return None,
+    })
+}
+
+fn fixed(k: Value) -> Value {
+    json!({ "a": 0, "k": k })
+}
+
+/// A path's contours as Lottie shape values, with tangents relative to
+/// their vertex.
+fn shape_values(path: &VectorPath) -> Vec<Value> {
+    struct Contour {
+        vertices: Vec<(f64, f64)>,
+        ins: Vec<(f64, f64)>,
+        outs: Vec<(f64, f64)>,
+        closed: bool,
+    }
+    let mut contours: Vec<Contour> = Vec::new();
+    for command in &path.commands {
+        match *command {
+            PathCommand::MoveTo { x, y } => {
+                contours.push(Contour { vertices: vec![(x, y)], ins: vec![(0.0, 0.0)], outs: vec![(0.0, 0.0)], closed: false });
+            }
+            PathCommand::LineTo { x, y } => {
+                let Some(c) = contours.last_mut() else { continue };
+                c.vertices.push((x, y));
+                c.ins.push((0.0, 0.0));
+                c.outs.push((0.0, 0.0));
+            }
+            PathCommand::CurveTo { x1, y1, x2, y2, x, y } => {
+                let Some(c) = contours.last_mut() else { continue };
+                let (px, py) = c.vertices[c.vertices.len() - 1];
+                let last = c.outs.len() - 1;
+                c.outs[last] = (x1 - px, y1 - py);
+                c.vertices.push((x, y));
+                c.ins.push((x2 - x, y2 - y));
+                c.outs.push((0.0, 0.0));
+            }
+            PathCommand::ClosePath => {
+                let Some(c) = contours.last_mut() else { continue };
+                c.closed = true;
+                // A closing vertex on top of the first one is the same point
+                let (first, last) = (c.vertices[0], c.vertices[c.vertices.len() - 1]);
+                if c.vertices.len() > 1 && (first.0 - last.0).abs() < 1e-6 && (first.1 - last.1).abs() < 1e-6 {
+                    c.vertices.pop();
+                    c.ins[0] = c.ins.pop().unwrap_or_default();
+                    c.outs.pop();
+                }
+            }
+        }
+    }
+
+    let points = |p: &[(f64, f64)]| Value::from(p.iter().map(|&(x, y)| json!([x, y])).collect::<Vec<_>>());
+    contours
+        .iter()
+        .map(|c| json!({ "c": c.closed, "v": points(&c.vertices), "i": points(&c.ins), "o": points(&c.outs) }))
+        .collect()
+}
+
+fn lottie_stops(stops: &[GradientStop]) -> Value {
+    let mut k: Vec<f64> = stops.iter().flat_map(|s| [s.position, s.color.r, s.color.g, s.color.b]).collect();
+    if stops.iter().any(|s| s.color.a < 1.0) {
+        k.extend(stops.iter().flat_map(|s| [s.position, s.color.a]));
+    }
+    json!({ "p": stops.len(), "k": fixed(k.into()) })
+}
+
+struct Exporter<'a> {
+    tree: &'a DocumentTree,
+    frame_rate: f64,
+    frame_count: u32,
+    assets: HashMap<String, Vec<u8>>,
+    animations: HashMap<&'a str, Vec<&'a NodeAnimation>>,
+    /// Drawing order, bottom first.
+    layers: Vec<Value>,
+    images: Vec<Value>,
+    warnings: Vec<String>,
+}
+
+impl Exporter<'_> {
+    fn warn(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        if !self.warnings.contains(&message) {
+            self.warnings.push(message);
+        }
+    }
+
+    fn frame(&self, animation: &NodeAnimation, time: f64) -> f64 {
+        (animation.delay + time.clamp(0.0, 1.0) * animation.duration) * self.frame_rate / 1000.0
+    }
+
+    /// Lottie keyframes for `property`, with values mapped through `value`.
+    fn keyframes(&mut self, animation: &NodeAnimation, property: &AnimatedProperty, value: impl Fn(&Value) -> Option<Value>) -> Option<Value> {
+        let mut frames: Vec<&Keyframe> = property.keyframes.iter().collect();
+        frames.sort_by(|a, b| a.time.total_cmp(&b.time));
+        let mut out = Vec::with_capacity(frames.len());
+        for (i, keyframe) in frames.iter().enumerate() {
+            let s = value(&keyframe.value)?;
+            let s = if s.is_array() { s } else { json!([s]) };
+            let mut frame = json!({ "t": self.frame(animation, keyframe.time), "s": s });
+            if i + 1 < frames.len() {
+                let handles = match keyframe.easing.as_ref().or(animation.easing.as_ref()) {
+                    None => Some([0.0, 0.0, 1.0, 1.0]),
+                    Some(Easing::Preset { preset }) if preset == "linear" => Some([0.0, 0.0, 1.0, 1.0]),
+                    Some(Easing::Preset { preset }) if preset.starts_with("step") => None,
+                    Some(Easing::Preset { preset }) => Some(preset_bezier(preset).unwrap_or_else(|| {
+                        self.warn("Spring, elastic and bounce easings were exported as linear");
+                        [0.0, 0.0, 1.0, 1.0]
+                    })),
+                    Some(Easing::CubicBezier { x1, y1, x2, y2 }) => Some([*x1, *y1, *x2, *y2]),
+                    Some(Easing::Spring { .. }) => {
+                        self.warn("Spring, elastic and bounce easings were exported as linear");
+                        Some([0.0, 0.0, 1.0, 1.0])
+                    }
+                    Some(Easing::Steps { steps, .. }) => {
+                        if *steps > 1 {
+                            self.warn("Easings with several steps were exported as a single step");
+                        }
+                        None
+                    }
+                };
+                match handles {
+                    Some([x1, y1, x2, y2]) => {
+                        frame["o"] = json!({ "x": [x1], "y": [y1] });
+                        frame["i"] = json!({ "x": [x2], "y": [y2] });
+                    }
+                    None => frame["h"] = 1.into(),
+                }
+            }
+            out.push(frame);
+        }
+        Some(out).filter(|k| !k.is_empty()).map(|k| json!({ "a": 1, "k": k }))
+    }
+
+    fn property(&self, node_id: &str, path: &str) -> Option<(&NodeAnimation, &AnimatedProperty)> {
+        self.animations.get(node_id)?.iter().find_map(|a| Some((*a, a.properties.iter().find(|p| p.path == path)?)))
+    }
+
+    fn paint(&mut self, paint: &Paint, stroke: bool, width: f64, height: f64, evenodd: bool) -> Option<Value> {
+        if !paint.visible() {
+            return None;
+        }
+        let bbox = scale(width.max(1e-6), height.max(1e-6));
+        let mut item = match paint {
+            Paint::Solid { opacity, color, .. } => json!({
+                "ty": if stroke { "st" } else { "fl" },
+                "c": fixed(json!([color.r, color.g, color.b, 1])),
+                "o": fixed((opacity * color.a * 100.0).into()),
+            }),
+            Paint::GradientLinear { opacity, gradient_stops, gradient_transform, .. }
+            | Paint::GradientRadial { opacity, gradient_stops, gradient_transform, .. } => {
+                let radial = matches!(paint, Paint::GradientRadial { .. });
+                let m = multiply(&bbox, gradient_transform);
+                let (from, to) = if radial { ((0.5, 0.5), (1.0, 0.5)) } else { ((0.0, 0.5), (1.0, 0.5)) };
+                let (s, e) = (apply(&m, from.0, from.1), apply(&m, to.0, to.1));
+                json!({
+                    "ty": if stroke { "gs" } else { "gf" },
+                    "t": if radial { 2 } else { 1 },
+                    "s": fixed(json!([s.0, s.1])),
+                    "e": fixed(json!([e.0, e.1])),
+                    "g": lottie_stops(gradient_stops),
+                    "o": fixed((opacity * 100.0).into()),
+                })
+            }
+            Paint::Image { .. } => {
+                self.warn("Image fills are not supported by Lottie and were left out");
+                return None;
+            }
+            Paint::Pattern { .. } => {
+                self.warn("Pattern fills are not supported by Lottie and were left out");
+                return None;
+            }
+        };
+        if !stroke {
+            item["r"] = if evenodd { 2 } else { 1 }.into();
+        }
+        Some(item)
+    }
+
+    /// Geometry of a node in its local space; empty for vectors without
+    /// paths.
+    fn geometry(&mut self, node: &NodeData) -> Vec<Value> {
+        let (width, height) = (node.width.unwrap_or(0.0), node.height.unwrap_or(0.0));
+        let center = fixed(json!([width / 2.0, height / 2.0]));
+        let size = fixed(json!([width, height]));
+        match node.node_type {
+            NodeType::Ellipse => vec![json!({ "ty": "el", "p": center, "s": size })],
+            NodeType::Vector => {
+                let Some(paths) = node.fitted_vector_paths() else { return Vec::new() };
+                let mut shapes: Vec<Value> =
+                    paths.iter().flat_map(shape_values).map(|shape| json!({ "ty": "sh", "ks": fixed(shape) })).collect();
+
+                if let Some((animation, property)) = self.property(&node.id, "vectorPaths") {
+                    let (animation, property) = (animation.clone(), property.clone());
+                    let size_at = |path: &str, time: f64, fallback: Option<f64>| {
+                        self.property(&node.id, path)
+                            .and_then(|(_, p)| p.keyframes.iter().find(|k| (k.time - time).abs() < 1e-6))
+                            .and_then(|k| k.value.as_f64())
+                            .or(fallback)
+                    };
+                    let mut per_keyframe = Vec::with_capacity(property.keyframes.len());
+                    for keyframe in &property.keyframes {
+                        let mut fitted = node.clone();
+                        fitted.vector_paths = serde_json::from_value(keyframe.value.clone()).ok();
+                        fitted.width = size_at("width", keyframe.time, node.width);
+                        fitted.height = size_at("height", keyframe.time, node.height);
+                        let contours: Vec<Value> = fitted.fitted_vector_paths().unwrap_or_default().iter().flat_map(shape_values).collect();
+                        per_keyframe.push((keyframe.time, contours));
+                    }
+
+                    let vertex_count = |c: &Value| c["v"].as_array().map_or(0, Vec::len);
+                    let matches = per_keyframe.iter().all(|(_, contours)| {
+                        contours.len() == shapes.len()
+                            && contours.iter().zip(&shapes).all(|(c, s)| vertex_count(c) == vertex_count(&s["ks"]["k"]) && c["c"] == s["ks"]["k"]["c"])
+                    });
+                    if !matches {
+                        self.warn("Path animations between paths with different points were left out");
+                    } else {
+                        for (i, shape) in shapes.iter_mut().enumerate() {
+                            let contour = AnimatedProperty {
+                                path: property.path.clone(),
+                                keyframes: property
+                                    .keyframes
+                                    .iter()
+                                    .zip(&per_keyframe)
+                                    .map(|(k, (_, contours))| Keyframe { time: k.time, value: contours[i].clone(), easing: k.easing.clone() })
+                                    .collect(),
+                            };
+                            if let Some(ks) = self.keyframes(&animation, &contour, |v| Some(json!([v]))) {
+                                shape["ks"] = ks;
+                            }
+                        }
+                    }
+                }
+                shapes
+            }
+            _ => vec![json!({ "ty": "rc", "p": center, "s": size, "r": fixed(node.corner_radius.unwrap_or(0.0).into()) })],
+        }
+    }
+
+    /// The shape items that draw `node`; empty when it has no visible paint.
+    fn shape_items(&mut self, node: &NodeData) -> Vec<Value> {
+        let (width, height) = (node.width.unwrap_or(0.0), node.height.unwrap_or(0.0));
+        let evenodd = node.vector_paths.iter().flatten().next().is_some_and(|p| p.winding_rule == WindingRule::Evenodd);
+        let fills: Vec<Value> = node.fills().iter().rev().filter_map(|p| self.paint(p, false, width, height, evenodd)).collect();
+        let mut strokes: Vec<Value> = Vec::new();
+        if node.stroke_weight() > 0.0 {
+            strokes = node.strokes().iter().rev().filter_map(|p| self.paint(p, true, width, height, evenodd)).collect();
+        }
+        if fills.is_empty() && strokes.is_empty() {
+            return Vec::new();
+        }
+
+        if !strokes.is_empty() && node.stroke_align.as_deref().is_some_and(|a| a != "CENTER") {
+            self.warn("Inside and outside strokes were exported as centered");
+        }
+        let dashes: Vec<Value> = node
+            .dash_pattern
+            .iter()
+            .flatten()
+            .enumerate()
+            .map(|(i, v)| json!({ "n": if i % 2 == 0 { "d" } else { "g" }, "nm": if i % 2 == 0 { "dash" } else { "gap" }, "v": fixed((*v).into()) }))
+            .chain(node.dash_offset.map(|v| json!({ "n": "o", "nm": "offset", "v": fixed(v.into()) })))
+            .collect();
+        for stroke in &mut strokes {
+            stroke["w"] = fixed(node.stroke_weight().into());
+            stroke["lc"] = match node.stroke_cap.as_deref() {
+                Some("ROUND") => 2,
+                Some("SQUARE") => 3,
+                _ => 1,
+            }
+            .into();
+            stroke["lj"] = match node.stroke_join.as_deref() {
+                Some("ROUND") => 2,
+                Some("BEVEL") => 3,
+                _ => 1,
+            }
+            .into();
+            stroke["ml"] = node.stroke_miter_limit.unwrap_or(4.0).into();
+            if !dashes.is_empty() {
+                stroke["d"] = dashes.clone().into();
+            }
+        }
+
+        let mut items = self.geometry(node);
+        if items.is_empty() {
+            return Vec::new();
+        }
+        items.extend(strokes);
+        items.extend(fills);
+        items.push(json!({
+            "ty": "tr",
+            "p": fixed(json!([0, 0])),
+            "a": fixed(json!([0, 0])),
+            "s": fixed(json!([100, 100])),
+            "r": fixed(0.into()),
+            "o": fixed(100.into()),
+        }));
+        vec![json!({ "ty": "gr", "nm": node.name, "it": items })]
+    }
+
+    /// Add the layer for `id` and its descendants. `inherited` is the
+    /// product of the ancestors' opacities.
+    fn layer(&mut self, id: &str, parent: Option<usize>, inherited: f64) {
+        let Some(node) = self.tree.get(id).cloned() else { return };
+        if !node.visible {
+            return;
+        }
+        let index = self.layers.len() + 1;
+        let has_children = !self.tree.children(id).is_empty();
+
+        if node.clips_content == Some(true) && has_children {
+            self.warn("Clipping was not exported");
+        }
+        if node.effects.as_ref().is_some_and(|e| !e.is_empty()) {
+            self.warn("Effects were not exported");
+        }
+        let animated: Vec<String> = self.animations.get(id).into_iter().flatten().flat_map(|a| &a.properties).map(|p| p.path.clone()).collect();
+        for path in &animated {
+            let vector = node.node_type == NodeType::Vector && animated.iter().any(|p| p == "vectorPaths");
+            let supported = matches!(path.as_str(), "x" | "y" | "rotation" | "opacity" | "vectorPaths") && (path != "vectorPaths" || node.node_type == NodeType::Vector)
+                || (vector && matches!(path.as_str(), "width" | "height"));
+            if !supported {
+                self.warn(format!("Animations of {} are not supported by Lottie export and were left out", path));
+            }
+        }
+        if has_children && animated.iter().any(|p| p == "opacity") {
+            self.warn("Opacity animations of groups do not apply to their contents in Lottie");
+        }
+
+        let (x, y) = (node.x.unwrap_or(0.0), node.y.unwrap_or(0.0));
+        let opacity = node.opacity() * inherited;
+        let mut ks = json!({
+            "a": fixed(json!([0, 0, 0])),
+            "s": fixed(json!([100, 100, 100])),
+            "r": fixed(node.rotation.unwrap_or(0.0).into()),
+            "o": fixed((opacity * 100.0).into()),
+        });
+        let x_keys = self.property(id, "x").map(|(a, p)| (a.clone(), p.clone()));
+        let y_keys = self.property(id, "y").map(|(a, p)| (a.clone(), p.clone()));
+        let number = |v: &Value| v.as_f64().map(Value::from);
+        if x_keys.is_some() || y_keys.is_some() {
+            let x = x_keys.and_then(|(a, p)| self.keyframes(&a, &p, number)).unwrap_or_else(|| fixed(x.into()));
+            let y = y_keys.and_then(|(a, p)| self.keyframes(&a, &p, number)).unwrap_or_else(|| fixed(y.into()));
+            ks["p"] = json!({ "s": true, "x": x, "y": y });
+        } else {
+            ks["p"] = fixed(json!([x, y, 0]));
+        }
+        if let Some((a, p)) = self.property(id, "rotation").map(|(a, p)| (a.clone(), p.clone())) {
+            if let Some(r) = self.keyframes(&a, &p, number) {
+                ks["r"] = r;
+            }
+        }
+        if let Some((a, p)) = self.property(id, "opacity").map(|(a, p)| (a.clone(), p.clone())) {
+            if let Some(o) = self.keyframes(&a, &p, |v| Some((v.as_f64()? * inherited * 100.0).into())) {
+                ks["o"] = o;
+            }
+        }
+
+        let mut layer = json!({
+            "ddd": 0,
+            "ind": index,
+            "ty": 3,
+            "nm": node.name,
+            "sr": 1,
+            "ks": ks,
+            "ao": 0,
+            "ip": 0,
+            "op": self.frame_count,
+            "st": 0,
+            "bm": node.blend_mode.as_deref().and_then(|m| BLEND_MODES.iter().position(|b| *b == m)).unwrap_or(0),
+        });
+        if let Some(parent) = parent {
+            layer["parent"] = parent.into();
+        }
+
+        match node.node_type {
+            NodeType::Text => self.warn("Text was exported as empty layers"),
+            NodeType::Image => match node.image_ref.as_ref().and_then(|r| self.assets.get(r)).cloned() {
+                Some(bytes) => {
+                    let format = image::guess_format(&bytes).ok();
+                    let dimensions = format.and_then(|f| image::ImageReader::with_format(std::io::Cursor::new(&bytes), f).into_dimensions().ok());
+                    let mime = match format {
+                        Some(image::ImageFormat::Jpeg) => "image/jpeg",
+                        Some(image::ImageFormat::WebP) => "image/webp",
+                        _ => "image/png",
+                    };
+                    match dimensions {
+                        Some((w, h)) => {
+                            let asset_id = format!("image_{}", self.images.len());
+                            let uri = format!("data:{};base64,{}", mime, BASE64.encode(&bytes));
+                            self.images.push(json!({ "id": asset_id, "w": w, "h": h, "u": "", "p": uri, "e": 1 }));
+                            let (sx, sy) = (node.width.unwrap_or(w as f64) / w as f64, node.height.unwrap_or(h as f64) / h as f64);
+                            layer["ty"] = 2.into();
+                            layer["refId"] = asset_id.into();
+                            layer["ks"]["s"] = fixed(json!([sx * 100.0, sy * 100.0, 100]));
+                        }
+                        None => self.warn("Some images could not be read and were left out"),
+                    }
+                }
+                None => self.warn("Some images are missing from the assets and were left out"),
+            },
+            _ => {
+                let shapes = self.shape_items(&node);
+                if !shapes.is_empty() {
+                    layer["ty"] = 4.into();
+                    layer["shapes"] = shapes.into();
+                }
+            }
+        }
+        self.layers.push(layer);
+
+        // An animated opacity only reaches the node's own layer
+        let inherited = if animated.iter().any(|p| p == "opacity") { inherited } else { opacity };
+        for child in self.tree.children(id).to_vec() {
+            self.layer(&child, Some(index), inherited);
+        }
+    }
+}
+
+/// Write a frame and the animations of its contents as a Lottie JSON
+/// animation. Transforms, opacity and path keyframes are kept; `assets`
+/// holds the bitmaps the document's image refs point at.
+#[command(async)]
+pub fn export_lottie(
+    path: String,
+    document: String,
+    assets: Vec<BundleAsset>,
+    animations: Vec<NodeAnimation>,
+    options: Option<LottieExportOptions>,
+) -> Result<LottieExport, String> {
+    let options = options.unwrap_or_default();
+    if !(options.frame_rate.is_finite() && options.frame_rate > 0.0) {
+        return Err(format!("Invalid frame rate: {}", options.frame_rate));
+    }
+    let serialized: SerializedDocument = serde_json::from_str(&document)
+        .map_err(|e| format!("Invalid document: {}", e))?;
+    let tree = DocumentTree::from_serialized(serialized);
+
+    let root_id = match &options.node_id {
+        Some(id) => id.clone(),
+        None => tree
+            .pages()
+            .iter()
+            .flat_map(|page| tree.children(&page.id))
+            .find(|id| tree.get(id).is_some_and(|n| n.node_type == NodeType::Frame))
+            .cloned()
+            .ok_or_else(|| "Nothing to export: the document has no frames".to_string())?,
+    };
+    let root = tree.get(&root_id).cloned()
+        .ok_or_else(|| format!("Unknown node: {}", root_id))?;
+
+    let mut decoded = HashMap::with_capacity(assets.len());
+    for asset in assets {
+        let bytes = BASE64.decode(asset.data.as_bytes())
+            .map_err(|e| format!("Invalid base64 data for {}: {}", asset.path, e))?;
+        decoded.insert(asset.path, bytes);
+    }
+
+    let end_ms = animations.iter().map(|a| a.delay + a.duration).fold(0.0, f64::max);
+    let frame_count = ((end_ms * options.frame_rate / 1000.0).ceil() as u32).max(1);
+    let mut by_node: HashMap<&str, Vec<&NodeAnimation>> = HashMap::new();
+    for animation in &animations {
+        by_node.entry(animation.node_id.as_str()).or_default().push(animation);
+    }
+
+    let mut exporter = Exporter {
+        tree: &tree,
+        frame_rate: options.frame_rate,
+        frame_count,
+        assets: decoded,
+        animations: by_node,
+        layers: Vec::new(),
+        images: Vec::new(),
+        warnings: Vec::new(),
+    };
+
+    // The frame's own fills are the bottom layer
+    let mut background = root.clone();
+    background.node_type = NodeType::Rectangle;
+    background.strokes = None;
+    background.x = Some(0.0);
+    background.y = Some(0.0);
+    background.rotation = None;
+    background.opacity = None;
+    let background_shapes = exporter.shape_items(&background);
+    if !background_shapes.is_empty() {
+        exporter.layers.push(json!({
+            "ddd": 0, "ind": 1, "ty": 4, "nm": format!("{} background", root.name), "sr": 1,
+            "ks": {
+                "a": fixed(json!([0, 0, 0])), "p": fixed(json!([0, 0, 0])), "s": fixed(json!([100, 100, 100])),
+                "r": fixed(0.into()), "o": fixed((root.opacity() * 100.0).into()),
+            },
+            "ao": 0, "ip": 0, "op": frame_count, "st": 0, "bm": 0, "shapes": background_shapes,
+        }));
+    }
+    if root.effects.as_ref().is_some_and(|e| !e.is_empty()) {
+        exporter.warn("Effects were not exported");
+    }
+    for child in tree.children(&root_id).to_vec() {
+        exporter.layer(&child, None, root.opacity());
+    }
+
+    let mut stack = vec![root_id.clone()];
+    let mut inside = std::collections::HashSet::new();
+    while let Some(id) = stack.pop() {
+        stack.extend(tree.children(&id).iter().cloned());
+        inside.insert(id);
+    }
+    if animations.iter().any(|a| !inside.contains(&a.node_id)) {
+        exporter.warn("Animations of nodes outside the exported frame were left out");
+    }
+
+    let layer_count = exporter.layers.len();
+    let mut layers = std::mem::take(&mut exporter.layers);
+    // Lottie lists the topmost layer first
+    layers.reverse();
+    let lottie = json!({
+        "v": "5.7.4",
+        "fr": options.frame_rate,
+        "ip": 0,
+        "op": frame_count,
+        "w": root.width.unwrap_or(0.0),
+        "h": root.height.unwrap_or(0.0),
+        "nm": root.name,
+        "ddd": 0,
+        "assets": exporter.images,
+        "layers": layers,
+        "markers": [],
+    });
+    let json = serde_json::to_vec(&lottie)
+        .map_err(|e| format!("Failed to encode Lottie file: {}", e))?;
+    write_atomic(&path, &json)?;
+
+    Ok(LottieExport { layer_count, frame_count, warnings: exporter.warnings })
+}
@@ -0,0 +1,5 @@
+//! Converters for formats that are both read and written, so documents can
+//! be exchanged with other tools in both directions.
+
+pub mod lottie;
+pub mod penpot;
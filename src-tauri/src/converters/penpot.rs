@@ -0,0 +1,1319 @@
+//! Penpot 2.x `.penpot` exports: a zip with a JSON manifest, one JSON file
+//! per page and per shape, and media stored as separate objects.
+
+use crate::atomic::write_atomic;
+use crate::bundle::{BundleAsset, IMAGES_DIR};
+use crate::geometry::{apply, invert, multiply, path_bounds, scale, transform_path, translate, Matrix, Rect, IDENTITY};
+use crate::import::{gradient_placement, paint_transform, place, unmapped_report, NodeBuilder, UnmappedFeature};
+use crate::model::{
+    generate_node_id, DocumentTree, GradientStop, NodeData, NodeType, Paint, PathCommand, Rgba, ScaleMode,
+    SerializedDocument, TextStyleRange, VectorPath, WindingRule, FORMAT_VERSION,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{Cursor, Read, Write};
+use tauri::command;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// Penpot's root frame, the parent of every top-level shape on a page.
+const ROOT_FRAME: &str = "00000000-0000-0000-0000-000000000000";
+
+/// Features declared by files that Penpot 2.x writes.
+const FEATURES: [&str; 3] = ["components/v2", "styles/v2", "layout/grid"];
+
+/// File data version written on export; Penpot migrates older data forward.
+const DATA_VERSION: u32 = 67;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PenpotImport {
+    pub document: SerializedDocument,
+    /// Embedded images, referenced from nodes by their `path`.
+    pub assets: Vec<BundleAsset>,
+    /// Penpot features with no native equivalent, most common first.
+    pub unmapped: Vec<UnmappedFeature>,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PenpotExport {
+    pub page_count: usize,
+    pub shape_count: usize,
+    /// Properties that were approximated or dropped.
+    pub warnings: Vec<String>,
+}
+
+fn num(v: &Value, key: &str) -> Option<f64> {
+    match v.get(key)? {
+        Value::Number(n) => n.as_f64(),
+        // Text attributes are stored as strings
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+fn text<'a>(v: &'a Value, key: &str) -> Option<&'a str> {
+    v.get(key)?.as_str()
+}
+
+fn flag(v: &Value, key: &str) -> bool {
+    v.get(key).and_then(Value::as_bool).unwrap_or(false)
+}
+
+fn list<'a>(v: &'a Value, key: &str) -> impl Iterator<Item = &'a Value> {
+    v.get(key).and_then(Value::as_array).into_iter().flatten()
+}
+
+fn parse_hex(hex: &str, alpha: f64) -> Rgba {
+    let hex = hex.trim_start_matches('#');
+    let hex: String = if hex.len() == 3 { hex.chars().flat_map(|c| [c, c]).collect() } else { hex.to_string() };
+    let c = |i: usize| hex.get(i..i + 2).and_then(|s| u8::from_str_radix(s, 16).ok()).unwrap_or(0) as f64 / 255.0;
+    Rgba { r: c(0), g: c(2), b: c(4), a: alpha }
+}
+
+/// Penpot blend modes are the CSS names.
+fn blend_mode_from_css(mode: &str) -> Option<String> {
+    Some(mode.to_uppercase().replace('-', "_")).filter(|m| m != "NORMAL")
+}
+
+fn blend_mode_to_css(mode: &str) -> Option<String> {
+    Some(mode.to_lowercase().replace('_', "-")).filter(|m| m != "normal" && m != "pass-through")
+}
+
+/// Penpot's default typeface is stored by its font id.
+fn font_family(family: &str) -> String {
+    match family {
+        "sourcesanspro" => "Source Sans Pro".to_string(),
+        _ => family.to_string(),
+    }
+}
+
+/// Dash arrays Penpot derives from a stroke style, with the stroke width
+/// added to every entry.
+fn dash_pattern(style: &str, width: f64) -> Option<Vec<f64>> {
+    let values: &[f64] = match style {
+        "dotted" => &[5.0, 5.0],
+        "dashed" => &[10.0, 10.0],
+        "mixed" => &[5.0, 5.0, 1.0, 5.0],
+        _ => return None,
+    };
+    Some(values.iter().map(|v| v + width).collect())
+}
+
+fn path_content(content: Option<&Value>) -> Option<VectorPath> {
+    let mut commands = Vec::new();
+    for segment in content?.as_array()? {
+        let params = segment.get("params");
+        let at = |key| params.and_then(|p| num(p, key)).unwrap_or(0.0);
+        commands.push(match text(segment, "command")? {
+            "move-to" => PathCommand::MoveTo { x: at("x"), y: at("y") },
+            "line-to" => PathCommand::LineTo { x: at("x"), y: at("y") },
+            "curve-to" => PathCommand::CurveTo {
+                x1: at("c1x"),
+                y1: at("c1y"),
+                x2: at("c2x"),
+                y2: at("c2y"),
+                x: at("x"),
+                y: at("y"),
+            },
+            "close-path" => PathCommand::ClosePath,
+            _ => return None,
+        });
+    }
+    Some(VectorPath { winding_rule: WindingRule::Nonzero, commands })
+}
+
+struct Importer {
+    archive: ZipArchive<File>,
+    entries: Vec<String>,
+    /// Shapes of the page being converted, by id.
+    objects: HashMap<String, Value>,
+    /// Penpot shape id -> node id, for every page.
+    ids: HashMap<String, String>,
+    /// Component id -> id of its main shape.
+    main_instances: HashMap<String, String>,
+    media: HashMap<String, Value>,
+    /// Media id -> asset path.
+    images: HashMap<String, String>,
+    assets: Vec<BundleAsset>,
+    out: NodeBuilder,
+    unmapped: BTreeMap<&'static str, usize>,
+}
+
+impl Importer {
+    fn unmapped(&mut self, feature: &'static str) {
+        *self.unmapped.entry(feature).or_default() += 1;
+    }
+
+    /// Copy a media object into the assets, returning its asset path.
+    fn image(&mut self, media_id: &str) -> Option<String> {
+        if let Some(path) = self.images.get(media_id) {
+            return Some(path.clone());
+        }
+
+        // Media ids point at a storage object holding the bytes
+        let object_id = self.media.get(media_id).and_then(|m| text(m, "mediaId")).unwrap_or(media_id).to_string();
+        let prefix = format!("objects/{}.", object_id);
+        let entry = self.entries.iter().find(|e| e.starts_with(&prefix) && !e.ends_with(".json"))?.clone();
+        let mut bytes = Vec::new();
+        self.archive.by_name(&entry).ok()?.read_to_end(&mut bytes).ok()?;
+
+        let path = format!("{}{}", IMAGES_DIR, &entry["objects/".len()..]);
+        self.assets.push(BundleAsset { path: path.clone(), data: BASE64.encode(&bytes) });
+        self.images.insert(media_id.to_string(), path.clone());
+        Some(path)
+    }
+
+    /// `prefix` is `fill` or `stroke`. Gradient points are normalized to the
+    /// shape box `size`, which `paint_space` maps into layer space.
+    fn paint(&mut self, v: &Value, prefix: &str, paint_space: &Matrix, size: Rect, m: &Matrix, to: Rect) -> Option<Paint> {
+        let opacity = num(v, &format!("{}Opacity", prefix)).unwrap_or(1.0);
+
+        if let Some(g) = v.get(format!("{}ColorGradient", prefix)) {
+            let at = |x, y| (num(g, x).unwrap_or(0.5) * size.width, num(g, y).unwrap_or(0.5) * size.height);
+            let (from, end) = (at("startX", "startY"), at("endX", "endY"));
+            let gradient_stops = list(g, "stops")
+                .map(|s| GradientStop {
+                    position: num(s, "offset").unwrap_or(0.0),
+                    color: parse_hex(text(s, "color").unwrap_or("#000000"), num(s, "opacity").unwrap_or(1.0)),
+                })
+                .collect();
+            let radial = match text(g, "type") {
+                Some("radial") => Some(num(g, "width").filter(|w| *w > 0.0).unwrap_or(1.0)),
+                _ => None,
+            };
+            let placed = multiply(paint_space, &gradient_placement(from, end, radial));
+            let gradient_transform = paint_transform(&placed, m, to)?;
+            return Some(match radial {
+                Some(_) => Paint::GradientRadial { visible: true, opacity, gradient_stops, gradient_transform },
+                None => Paint::GradientLinear { visible: true, opacity, gradient_stops, gradient_transform },
+            });
+        }
+
+        if let Some(image) = v.get(format!("{}Image", prefix)) {
+            let Some(image_ref) = text(image, "id").and_then(|id| self.image(id)) else {
+                self.out.warn("Some images are missing from the file and were dropped");
+                return None;
+            };
+            // Images either cover the shape or stretch to it
+            let scale_mode = if image.get("keepAspectRatio").and_then(Value::as_bool).unwrap_or(true) {
+                ScaleMode::Fill
+            } else {
+                ScaleMode::Crop
+            };
+            return Some(Paint::Image { visible: true, opacity, image_ref, scale_mode, image_transform: IDENTITY });
+        }
+
+        let color = parse_hex(text(v, &format!("{}Color", prefix))?, 1.0);
+        Some(Paint::Solid { visible: true, opacity, color })
+    }
+
+    fn style(&mut self, data: &mut NodeData, shape: &Value, paint_space: &Matrix, size: Rect, m: &Matrix) {
+        let to = Rect::new(0.0, 0.0, data.width.unwrap_or(0.0), data.height.unwrap_or(0.0));
+
+        data.opacity = num(shape, "opacity").filter(|o| *o < 1.0);
+        data.blend_mode = text(shape, "blendMode").and_then(blend_mode_from_css);
+
+        let fills: Vec<&Value> = list(shape, "fills").collect();
+        data.fills = Some(fills.into_iter().filter_map(|f| self.paint(f, "fill", paint_space, size, m, to)).collect());
+
+        let strokes: Vec<&Value> = list(shape, "strokes").filter(|s| text(s, "strokeStyle") != Some("none")).collect();
+        if let Some(first) = strokes.first() {
+            if strokes.iter().any(|s| num(s, "strokeWidth") != num(first, "strokeWidth")
+                || s.get("strokeAlignment") != first.get("strokeAlignment"))
+            {
+                self.unmapped("Multiple stroke widths (imported with the first)");
+            }
+            let width = num(first, "strokeWidth").unwrap_or(1.0);
+            data.stroke_weight = Some(width);
+            data.stroke_align = Some(
+                match text(first, "strokeAlignment") {
+                    Some("inner") => "INSIDE",
+                    Some("outer") => "OUTSIDE",
+                    _ => "CENTER",
+                }
+                .to_string(),
+            );
+            data.dash_pattern = text(first, "strokeStyle").and_then(|s| dash_pattern(s, width));
+
+            let (start, end) = (text(first, "strokeCapStart"), text(first, "strokeCapEnd"));
+            data.stroke_cap = match (start, end) {
+                (Some("round"), Some("round")) => Some("ROUND".to_string()),
+                (Some("square"), Some("square")) => Some("SQUARE".to_string()),
+                _ => None,
+            };
+            if [start, end].iter().flatten().any(|cap| !matches!(*cap, "round" | "square")) {
+                self.unmapped("Arrowheads and markers");
+            }
+
+            let strokes = strokes.into_iter().filter_map(|s| self.paint(s, "stroke", paint_space, size, m, to)).collect();
+            data.strokes = Some(strokes);
+        }
+
+        let mut effects = Vec::new();
+        for shadow in list(shape, "shadow") {
+            let kind = match text(shadow, "style") {
+                Some("inner-shadow") => "INNER_SHADOW",
+                _ => "DROP_SHADOW",
+            };
+            let color = shadow.get("color");
+            let c = parse_hex(
+                color.and_then(|c| text(c, "color")).unwrap_or("#000000"),
+                color.and_then(|c| num(c, "opacity")).unwrap_or(0.2),
+            );
+            effects.push(json!({
+                "type": kind,
+                "visible": !flag(shadow, "hidden"),
+                "color": { "r": c.r, "g": c.g, "b": c.b, "a": c.a },
+                "offset": { "x": num(shadow, "offsetX").unwrap_or(0.0), "y": num(shadow, "offsetY").unwrap_or(0.0) },
+                "radius": num(shadow, "blur").unwrap_or(0.0),
+                "spread": num(shadow, "spread").unwrap_or(0.0),
+            }));
+        }
+        if let Some(blur) = shape.get("blur").filter(|b| b.is_object()) {
+            effects.push(json!({
+                "type": "BLUR",
+                "visible": !flag(blur, "hidden"),
+                "radius": num(blur, "value").unwrap_or(0.0),
+            }));
+        }
+        data.effects = Some(effects).filter(|e| !e.is_empty());
+    }
+
+    fn text(&mut self, data: &mut NodeData, shape: &Value, paint_space: &Matrix, size: Rect, m: &Matrix) {
+        let to = Rect::new(0.0, 0.0, data.width.unwrap_or(0.0), data.height.unwrap_or(0.0));
+        let root = shape.get("content").cloned().unwrap_or(Value::Null);
+        let paragraphs: Vec<&Value> = list(&root, "children").flat_map(|set| list(set, "children")).collect();
+
+        let mut characters = String::new();
+        let mut styles: Vec<TextStyleRange> = Vec::new();
+        let mut align = None;
+        for (i, paragraph) in paragraphs.iter().enumerate() {
+            if i > 0 {
+                // The line break belongs to the previous paragraph's last run
+                characters.push('\n');
+                if let Some(last) = styles.last_mut() {
+                    last.end += 1;
+                }
+            }
+            align = align.or_else(|| text(paragraph, "textAlign"));
+
+            for leaf in list(paragraph, "children") {
+                let content = text(leaf, "text").unwrap_or_default();
+                let start = characters.chars().count();
+                characters.push_str(content);
+
+                if text(leaf, "fontStyle") == Some("italic") {
+                    self.unmapped("Italic text");
+                }
+                if text(leaf, "textTransform").is_some_and(|t| t != "none") {
+                    self.unmapped("Text case transforms");
+                }
+
+                let font_size = num(leaf, "fontSize").unwrap_or(14.0);
+                let fill_values: Vec<&Value> = list(leaf, "fills").collect();
+                let fills = fill_values.into_iter().filter_map(|f| self.paint(f, "fill", paint_space, size, m, to)).collect();
+                styles.push(TextStyleRange {
+                    start,
+                    end: characters.chars().count(),
+                    font_family: font_family(text(leaf, "fontFamily").unwrap_or("sourcesanspro")),
+                    font_weight: num(leaf, "fontWeight").map(|w| w as u16).unwrap_or(400),
+                    font_size,
+                    fills,
+                    text_decoration: match text(leaf, "textDecoration") {
+                        Some("underline") => Some("UNDERLINE".to_string()),
+                        Some("line-through") => Some("STRIKETHROUGH".to_string()),
+                        _ => None,
+                    },
+                    letter_spacing: num(leaf, "letterSpacing").unwrap_or(0.0),
+                    // Line heights are multiples of the font size
+                    line_height: num(leaf, "lineHeight").map(|h| Value::from(h * font_size)),
+                });
+            }
+        }
+
+        data.fills = styles.first().map(|s| s.fills.clone());
+        data.characters = Some(characters);
+        data.text_styles = Some(styles);
+        data.text_align_horizontal = Some(
+            match align {
+                Some("center") => "CENTER",
+                Some("right") => "RIGHT",
+                Some("justify") => "JUSTIFIED",
+                _ => "LEFT",
+            }
+            .to_string(),
+        );
+        data.text_align_vertical = match text(&root, "verticalAlign") {
+            Some("center") => Some("CENTER".to_string()),
+            Some("bottom") => Some("BOTTOM".to_string()),
+            _ => None,
+        };
+        let resize = match text(shape, "growType") {
+            Some("auto-width") => "WIDTH_AND_HEIGHT",
+            Some("auto-height") => "HEIGHT",
+            _ => "NONE",
+        };
+        data.extra.insert("textAutoResize".to_string(), resize.into());
+    }
+
+    fn children(&mut self, shape: &Value, parent: &str, parent_world: &Matrix) {
+        let ids: Vec<String> = list(shape, "shapes").filter_map(Value::as_str).map(str::to_string).collect();
+        for id in ids {
+            self.shape(&id, parent, parent_world);
+        }
+    }
+
+    fn shape(&mut self, id: &str, parent: &str, parent_world: &Matrix) {
+        let Some(shape) = self.objects.get(id).cloned() else {
+            self.out.warn("Some layers are missing from the file and were skipped");
+            return;
+        };
+        let kind = text(&shape, "type").unwrap_or_default();
+        let node_type = match kind {
+            _ if flag(&shape, "mainInstance") => NodeType::Component,
+            _ if flag(&shape, "componentRoot") && shape.get("componentId").is_some() => NodeType::Instance,
+            "frame" => NodeType::Frame,
+            "group" => NodeType::Group,
+            "rect" => NodeType::Rectangle,
+            "circle" => NodeType::Ellipse,
+            "path" | "bool" => NodeType::Vector,
+            "text" => NodeType::Text,
+            "image" => NodeType::Image,
+            "svg-raw" => {
+                self.unmapped("Embedded SVG elements");
+                return;
+            }
+            _ => {
+                self.unmapped("Other layer types");
+                return;
+            }
+        };
+
+        let node_id = self.ids.get(id).cloned().unwrap_or_else(generate_node_id);
+        let mut data = NodeData::new(node_id, node_type, text(&shape, "name").unwrap_or(kind));
+        data.visible = !flag(&shape, "hidden");
+        data.locked = flag(&shape, "blocked");
+
+        // Shapes are positioned in page space by their box and a transform
+        // about its center
+        let selrect = shape.get("selrect").unwrap_or(&shape);
+        let side = |key| num(selrect, key).or_else(|| num(&shape, key)).unwrap_or(0.0);
+        let (w, h) = (side("width"), side("height"));
+        let linear = shape
+            .get("transform")
+            .map(|t| {
+                let c = |key, default| num(t, key).unwrap_or(default);
+                [c("a", 1.0), c("b", 0.0), c("c", 0.0), c("d", 1.0), 0.0, 0.0]
+            })
+            .unwrap_or(IDENTITY);
+        let world = multiply(
+            &multiply(&translate(side("x") + w / 2.0, side("y") + h / 2.0), &linear),
+            &translate(-w / 2.0, -h / 2.0),
+        );
+        let to_parent = invert(parent_world).unwrap_or(IDENTITY);
+        let size = Rect::new(0.0, 0.0, w, h);
+
+        // Path content is already in page space
+        let mut paths = match kind {
+            "path" => path_content(shape.get("content")).map(|p| vec![p]),
+            "bool" => {
+                self.unmapped("Boolean groups (imported as flattened paths)");
+                path_content(shape.get("boolContent").or(shape.get("content"))).map(|p| vec![p])
+            }
+            _ => None,
+        };
+        if node_type == NodeType::Vector && paths.is_none() {
+            self.out.warn("Some paths use an unsupported encoding and were left empty");
+        }
+        let (t, paint_space) = match paths {
+            Some(_) => (to_parent, world),
+            None => (multiply(&to_parent, &world), IDENTITY),
+        };
+
+        let placement = place(&t, size, paths.as_mut());
+        if !placement.exact && !matches!(node_type, NodeType::Rectangle | NodeType::Ellipse) {
+            self.unmapped("Flipped or skewed layers");
+        }
+        data.x = Some(placement.x);
+        data.y = Some(placement.y);
+        data.rotation = placement.rotation;
+        data.width = Some(placement.bounds.width);
+        data.height = Some(placement.bounds.height);
+        data.vector_paths = paths;
+        let m = placement.to_local;
+
+        if node_type == NodeType::Text {
+            self.text(&mut data, &shape, &paint_space, size, &m);
+        } else {
+            self.style(&mut data, &shape, &paint_space, size, &m);
+        }
+
+        match node_type {
+            NodeType::Frame | NodeType::Component | NodeType::Instance => {
+                data.clips_content = Some(!flag(&shape, "showContent"));
+            }
+            NodeType::Rectangle => {
+                let radii: Vec<f64> = ["r1", "r2", "r3", "r4"].iter().filter_map(|r| num(&shape, r)).collect();
+                if radii.windows(2).any(|w| w[0] != w[1]) {
+                    self.unmapped("Independent corner radii (imported as uniform)");
+                }
+                let radius = radii.first().copied().or_else(|| num(&shape, "rx"));
+                data.corner_radius = radius.filter(|r| *r > 0.0);
+            }
+            NodeType::Image => {
+                let media_id = shape.get("metadata").and_then(|m| text(m, "id"));
+                data.image_ref = media_id.and_then(|id| self.image(id));
+                if data.image_ref.is_none() {
+                    self.out.warn("Some images are missing from the file and were dropped");
+                }
+            }
+            _ => {}
+        }
+        if node_type == NodeType::Instance {
+            let component = text(&shape, "componentId").and_then(|c| self.main_instances.get(c));
+            match component.and_then(|main| self.ids.get(main)) {
+                Some(main) => {
+                    data.extra.insert("componentId".to_string(), main.clone().into());
+                }
+                None => self.unmapped("Library components (imported as detached copies)"),
+            }
+        }
+        if shape.get("layout").is_some_and(|l| !l.is_null()) {
+            self.unmapped("Flex and grid layouts");
+        }
+
+        let world = multiply(parent_world, &data.local_transform());
+        let node_id = self.out.add(Some(parent), data);
+        if kind != "bool" {
+            self.children(&shape, &node_id, &world);
+        }
+    }
+}
+
+/// Zip entry names under a directory, without the `.json` suffix.
+fn json_entries<'a>(entries: &'a [String], dir: &'a str) -> impl Iterator<Item = &'a str> {
+    entries.iter().filter_map(move |e| {
+        let name = e.strip_prefix(dir)?.strip_suffix(".json")?;
+        Some(name).filter(|n| !n.contains('/'))
+    })
+}
+
+fn read_json(archive: &mut ZipArchive<File>, name: &str) -> Result<Value, String> {
+    let mut entry = archive.by_name(name)
+        .map_err(|_| format!("Missing {} in Penpot file", name))?;
+    let mut bytes = Vec::new();
+    entry.read_to_end(&mut bytes)
+        .map_err(|e| format!("Failed to read {}: {}", name, e))?;
+    serde_json::from_slice(&bytes)
+        .map_err(|e| format!("Invalid {}: {}", name, e))
+}
+
+struct PenpotPage {
+    name: String,
+    background: Option<Rgba>,
+    objects: HashMap<String, Value>,
+}
+
+#[command(async)]
+pub fn import_penpot(path: String) -> Result<PenpotImport, String> {
+    let file = File::open(&path)
+        .map_err(|e| format!("Failed to open Penpot file: {}", e))?;
+    let mut archive = ZipArchive::new(file)
+        .map_err(|_| "Not a Penpot 2 export; files from Penpot 1.x are not supported".to_string())?;
+    let entries: Vec<String> = archive.file_names().map(str::to_string).collect();
+
+    let manifest = read_json(&mut archive, "manifest.json")?;
+    let files: Vec<&Value> = list(&manifest, "files").collect();
+    let Some(file_id) = files.first().and_then(|f| text(f, "id")).map(str::to_string) else {
+        return Err("Penpot file contains no documents".to_string());
+    };
+    let file_dir = format!("files/{}/", file_id);
+    let file_json = read_json(&mut archive, &format!("files/{}.json", file_id))?;
+    let name = text(&file_json, "name")
+        .or_else(|| files.first().and_then(|f| text(f, "name")))
+        .unwrap_or("Untitled")
+        .to_string();
+
+    // Pages are ordered by the file's page list, falling back to their index
+    let pages_dir = format!("{}pages/", file_dir);
+    let page_ids: Vec<String> = json_entries(&entries, &pages_dir).map(str::to_string).collect();
+    let order: Vec<&str> = list(&file_json, "pages").filter_map(Value::as_str).collect();
+    let mut pages = Vec::with_capacity(page_ids.len());
+    let mut indexes = HashMap::new();
+    for page_id in &page_ids {
+        let page = read_json(&mut archive, &format!("{}{}.json", pages_dir, page_id))?;
+        indexes.insert(page_id.clone(), num(&page, "index").unwrap_or(f64::MAX));
+        let objects_dir = format!("{}{}/", pages_dir, page_id);
+        let mut objects = HashMap::new();
+        for object_id in json_entries(&entries, &objects_dir) {
+            objects.insert(object_id.to_string(), read_json(&mut archive, &format!("{}{}.json", objects_dir, object_id))?);
+        }
+        let background = page
+            .pointer("/options/background")
+            .or(page.get("background"))
+            .and_then(Value::as_str)
+            .map(|c| parse_hex(c, 1.0));
+        let page_name = text(&page, "name").unwrap_or("Page").to_string();
+        pages.push((page_id.clone(), PenpotPage { name: page_name, background, objects }));
+    }
+    pages.sort_by(|(a, _), (b, _)| {
+        let rank = |id: &String| order.iter().position(|o| o == id).map(|i| i as f64).unwrap_or(indexes[id]);
+        rank(a).total_cmp(&rank(b))
+    });
+
+    let mut media = HashMap::new();
+    let media_dir = format!("{}media/", file_dir);
+    for media_id in json_entries(&entries, &media_dir).map(str::to_string).collect::<Vec<_>>() {
+        media.insert(media_id.clone(), read_json(&mut archive, &format!("{}{}.json", media_dir, media_id))?);
+    }
+    let mut main_instances = HashMap::new();
+    let components_dir = format!("{}components/", file_dir);
+    for component_id in json_entries(&entries, &components_dir).map(str::to_string).collect::<Vec<_>>() {
+        let component = read_json(&mut archive, &format!("{}{}.json", components_dir, component_id))?;
+        if let Some(main) = text(&component, "mainInstanceId") {
+            main_instances.insert(component_id, main.to_string());
+        }
+    }
+
+    // Node ids are assigned up front so instances can refer to components
+    // on later pages
+    let ids = pages
+        .iter()
+        .flat_map(|(_, page)| page.objects.keys())
+        .map(|id| (id.clone(), generate_node_id()))
+        .collect();
+
+    let mut importer = Importer {
+        archive,
+        entries,
+        objects: HashMap::new(),
+        ids,
+        main_instances,
+        media,
+        images: HashMap::new(),
+        assets: Vec::new(),
+        out: NodeBuilder::default(),
+        unmapped: BTreeMap::new(),
+    };
+    if files.len() > 1 {
+        importer.out.warn("Only the first file of a multi-file export was imported");
+    }
+
+    let root_id = importer.out.add(None, NodeData::new(generate_node_id(), NodeType::Document, name.clone()));
+    for (_, page) in pages {
+        let mut data = NodeData::new(generate_node_id(), NodeType::Page, page.name);
+        data.background_color = page.background;
+        let page_id = importer.out.add(Some(&root_id), data);
+
+        importer.objects = page.objects;
+        if let Some(root) = importer.objects.get(ROOT_FRAME).cloned() {
+            importer.children(&root, &page_id, &IDENTITY);
+        }
+    }
+
+    let unmapped = unmapped_report(&importer.unmapped);
+    let assets = std::mem::take(&mut importer.assets);
+    let (nodes, warnings) = importer.out.into_parts();
+    Ok(PenpotImport {
+        document: SerializedDocument {
+            version: "1.0.0".to_string(),
+            format_version: FORMAT_VERSION,
+            name,
+            created_at: String::new(),
+            updated_at: String::new(),
+            nodes,
+            root_id,
+        },
+        assets,
+        unmapped,
+        warnings,
+    })
+}
+
+fn is_uuid(id: &str) -> bool {
+    id.len() == 36
+        && id.char_indices().all(|(i, c)| if matches!(i, 8 | 13 | 18 | 23) { c == '-' } else { c.is_ascii_hexdigit() })
+}
+
+/// A media object ready to be written.
+struct Media {
+    id: String,
+    object_id: String,
+    extension: &'static str,
+    mime_type: &'static str,
+    width: u32,
+    height: u32,
+    bytes: Vec<u8>,
+}
+
+struct Exporter<'a> {
+    tree: &'a DocumentTree,
+    file_id: String,
+    assets: HashMap<String, Vec<u8>>,
+    /// Node id -> Penpot id, for ids that are not UUIDs.
+    ids: HashMap<String, String>,
+    /// Component node id -> Penpot component id.
+    components: HashMap<String, String>,
+    /// Instance descendants -> the matching shape in their component.
+    shape_refs: HashMap<String, String>,
+    /// Image ref -> media, in first-use order.
+    media: Vec<(String, Media)>,
+    /// Page id -> shapes.
+    objects: Vec<(String, Vec<Value>)>,
+    warnings: Vec<String>,
+}
+
+impl Exporter<'_> {
+    fn warn(&mut self, message: &str) {
+        if !self.warnings.iter().any(|w| w == message) {
+            self.warnings.push(message.to_string());
+        }
+    }
+
+    fn uuid(&mut self, id: &str) -> String {
+        if is_uuid(id) {
+            return id.to_string();
+        }
+        self.ids.entry(id.to_string()).or_insert_with(generate_node_id).clone()
+    }
+
+    fn media(&mut self, image_ref: &str) -> Option<Value> {
+        if let Some((_, m)) = self.media.iter().find(|(r, _)| r == image_ref) {
+            return Some(json!({ "id": m.id, "width": m.width, "height": m.height, "mtype": m.mime_type }));
+        }
+
+        let bytes = match image_ref.strip_prefix("data:") {
+            Some(rest) => BASE64.decode(rest.split_once(";base64,")?.1.as_bytes()).ok()?,
+            None => self.assets.get(image_ref)?.clone(),
+        };
+        let format = image::guess_format(&bytes).ok()?;
+        let (width, height) = image::ImageReader::with_format(Cursor::new(&bytes), format).into_dimensions().ok()?;
+        let (extension, mime_type) = match format {
+            image::ImageFormat::Png => ("png", "image/png"),
+            image::ImageFormat::Jpeg => ("jpg", "image/jpeg"),
+            image::ImageFormat::WebP => ("webp", "image/webp"),
+            _ => return None,
+        };
+        let media = Media { id: generate_node_id(), object_id: generate_node_id(), extension, mime_type, width, height, bytes };
+        let value = json!({ "id": media.id, "width": width, "height": height, "mtype": mime_type });
+        self.media.push((image_ref.to_string(), media));
+        Some(value)
+    }
+
+    /// `prefix` is `fill` or `stroke`; `to_box` maps the node's normalized
+    /// box to the Penpot shape's.
+    fn paint(&mut self, paint: &Paint, prefix: &str, to_box: &Matrix, size: Rect) -> Option<Value> {
+        if !paint.visible() {
+            return None;
+        }
+        let mut out = Map::new();
+        let opacity = match paint {
+            Paint::Solid { color, .. } => paint.opacity() * color.a,
+            _ => paint.opacity(),
+        };
+        out.insert(format!("{}Opacity", prefix), opacity.into());
+        match paint {
+            Paint::Solid { color, .. } => {
+                out.insert(format!("{}Color", prefix), color.hex().into());
+            }
+            Paint::GradientLinear { gradient_stops, gradient_transform, .. }
+            | Paint::GradientRadial { gradient_stops, gradient_transform, .. } => {
+                let radial = matches!(paint, Paint::GradientRadial { .. });
+                let t = multiply(to_box, gradient_transform);
+                let (start, end) = if radial {
+                    (apply(&t, 0.5, 0.5), apply(&t, 1.0, 0.5))
+                } else {
+                    (apply(&t, 0.0, 0.5), apply(&t, 1.0, 0.5))
+                };
+                let px = |p: (f64, f64)| (p.0 * size.width, p.1 * size.height);
+                let length = |a: (f64, f64), b: (f64, f64)| ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt();
+                let minor = px(apply(&t, 0.5, 1.0));
+                let major = length(px(start), px(end));
+                let width = if radial && major > 0.0 { length(px(start), minor) / major } else { 1.0 };
+                let stops: Vec<Value> = gradient_stops
+                    .iter()
+                    .map(|s| json!({ "color": s.color.hex(), "opacity": s.color.a, "offset": s.position }))
+                    .collect();
+                out.insert(
+                    format!("{}ColorGradient", prefix),
+                    json!({
+                        "type": if radial { "radial" } else { "linear" },
+                        "startX": start.0,
+                        "startY": start.1,
+                        "endX": end.0,
+                        "endY": end.1,
+                        "width": width,
+                        "stops": stops,
+                    }),
+                );
+            }
+            Paint::Image { image_ref, scale_mode, .. } => {
+                let Some(mut image) = self.media(image_ref) else {
+                    self.warn("Some images could not be read and were left out");
+                    return None;
+                };
+                if matches!(scale_mode, ScaleMode::Fit | ScaleMode::Tile) {
+                    self.warn("Fit and tiled images are exported as fill");
+                }
+                image["keepAspectRatio"] = (*scale_mode != ScaleMode::Crop).into();
+                out.insert(format!("{}Image", prefix), image);
+            }
+            Paint::Pattern { .. } => {
+                self.warn("Pattern fills are not supported by Penpot and were left out");
+                return None;
+            }
+        }
+        Some(Value::Object(out))
+    }
+
+    fn text_content(&mut self, node: &NodeData, to_box: &Matrix, size: Rect) -> Value {
+        let characters = node.characters.clone().unwrap_or_default();
+        let chars: Vec<char> = characters.chars().collect();
+        let styles = node.text_styles.clone().filter(|s| !s.is_empty()).unwrap_or_else(|| {
+            vec![TextStyleRange {
+                start: 0,
+                end: chars.len(),
+                font_family: "Inter".to_string(),
+                font_weight: 400,
+                font_size: 14.0,
+                fills: node.fills().to_vec(),
+                text_decoration: None,
+                letter_spacing: 0.0,
+                line_height: None,
+            }]
+        });
+        let align = match node.text_align_horizontal.as_deref() {
+            Some("CENTER") => "center",
+            Some("RIGHT") => "right",
+            Some("JUSTIFIED") => "justify",
+            _ => "left",
+        };
+
+        let leaf = |exporter: &mut Self, style: &TextStyleRange, text: String| {
+            let fills: Vec<Value> = style.fills.iter().filter_map(|f| exporter.paint(f, "fill", to_box, size)).collect();
+            let family_slug = style.font_family.to_lowercase().replace(' ', "-");
+            json!({
+                "text": text,
+                "fontId": format!("gfont-{}", family_slug),
+                "fontFamily": style.font_family,
+                "fontVariantId": "regular",
+                "fontSize": style.font_size.to_string(),
+                "fontWeight": style.font_weight.to_string(),
+                "fontStyle": "normal",
+                "fills": fills,
+                "letterSpacing": style.letter_spacing.to_string(),
+                "lineHeight": (style.line_height_px() / style.font_size.max(1e-6)).to_string(),
+                "textDecoration": match style.text_decoration.as_deref() {
+                    Some("UNDERLINE") => "underline",
+                    Some("STRIKETHROUGH") => "line-through",
+                    _ => "none",
+                },
+                "textTransform": "none",
+            })
+        };
+
+        // Split runs at line breaks into Penpot paragraphs
+        let mut paragraphs = Vec::new();
+        let mut start = 0;
+        let breaks = chars.iter().enumerate().filter(|(_, c)| **c == '\n').map(|(i, _)| i);
+        for end in breaks.chain([chars.len()]) {
+            let mut leaves: Vec<Value> = styles
+                .iter()
+                .filter(|s| s.start.max(start) < s.end.min(end))
+                .map(|s| leaf(self, s, chars[s.start.max(start)..s.end.min(end)].iter().collect()))
+                .collect();
+            if leaves.is_empty() {
+                // Empty lines keep the style of the run they sit in
+                let style = styles.iter().find(|s| s.start <= start && start <= s.end).unwrap_or(&styles[0]);
+                leaves.push(leaf(self, style, String::new()));
+            }
+            let mut paragraph = leaves[0].clone();
+            paragraph["type"] = "paragraph".into();
+            paragraph["textAlign"] = align.into();
+            paragraph["children"] = leaves.into();
+            if let Some(attrs) = paragraph.as_object_mut() {
+                attrs.remove("text");
+            }
+            paragraphs.push(paragraph);
+            start = end + 1;
+        }
+
+        json!({
+            "type": "root",
+            "verticalAlign": match node.text_align_vertical.as_deref() {
+                Some("CENTER") => "center",
+                Some("BOTTOM") => "bottom",
+                _ => "top",
+            },
+            "children": [{ "type": "paragraph-set", "children": paragraphs }],
+        })
+    }
+
+    fn path_content(paths: &[VectorPath], m: &Matrix) -> Vec<Value> {
+        let point = |x, y| apply(m, x, y);
+        let mut segments = Vec::new();
+        for path in paths {
+            for command in &path.commands {
+                segments.push(match *command {
+                    PathCommand::MoveTo { x, y } => {
+                        let (x, y) = point(x, y);
+                        json!({ "command": "move-to", "params": { "x": x, "y": y } })
+                    }
+                    PathCommand::LineTo { x, y } => {
+                        let (x, y) = point(x, y);
+                        json!({ "command": "line-to", "params": { "x": x, "y": y } })
+                    }
+                    PathCommand::CurveTo { x1, y1, x2, y2, x, y } => {
+                        let ((c1x, c1y), (c2x, c2y), (x, y)) = (point(x1, y1), point(x2, y2), point(x, y));
+                        json!({
+                            "command": "curve-to",
+                            "params": { "c1x": c1x, "c1y": c1y, "c2x": c2x, "c2y": c2y, "x": x, "y": y },
+                        })
+                    }
+                    PathCommand::ClosePath => json!({ "command": "close-path", "params": {} }),
+                });
+            }
+        }
+        segments
+    }
+
+    /// Pair an instance's descendants with its component's by position, as
+    /// long as the two trees have the same shape.
+    fn match_shapes(&mut self, copy: &str, main: &str) {
+        let tree = self.tree;
+        let (copies, mains) = (tree.children(copy), tree.children(main));
+        if copies.len() != mains.len() {
+            return;
+        }
+        for (c, m) in copies.iter().zip(mains) {
+            let main_ref = self.uuid(m);
+            self.shape_refs.insert(c.clone(), main_ref);
+            self.match_shapes(c, m);
+        }
+    }
+
+    fn shape(&mut self, id: &str, parent: &str, frame: &str, out: &mut Vec<Value>) -> Option<String> {
+        let tree = self.tree;
+        let node = tree.get(id)?;
+        let kind = match node.node_type {
+            NodeType::Frame | NodeType::Component | NodeType::Instance => "frame",
+            NodeType::Group => "group",
+            NodeType::Rectangle => "rect",
+            NodeType::Ellipse => "circle",
+            NodeType::Text => "text",
+            NodeType::Image => "image",
+            NodeType::Vector | NodeType::Line | NodeType::Polygon | NodeType::Star | NodeType::BooleanOperation => "path",
+            NodeType::Slice => {
+                self.warn("Slices are not supported by Penpot and were left out");
+                return None;
+            }
+            NodeType::Document | NodeType::Page => return None,
+        };
+        let uuid = self.uuid(id);
+        let world = tree.world_transform(id);
+        let (w, h) = (node.width.unwrap_or(0.0), node.height.unwrap_or(0.0));
+
+        // Paths are written in page space with no transform of their own
+        let paths = match kind {
+            "path" => {
+                let paths = node.fitted_vector_paths().or_else(|| {
+                    (node.node_type == NodeType::Line).then(|| {
+                        vec![VectorPath {
+                            winding_rule: WindingRule::Nonzero,
+                            commands: vec![PathCommand::MoveTo { x: 0.0, y: 0.0 }, PathCommand::LineTo { x: w, y: 0.0 }],
+                        }]
+                    })
+                });
+                let Some(paths) = paths else {
+                    self.warn("Shapes without path data were left out");
+                    return None;
+                };
+                Some(paths)
+            }
+            _ => None,
+        };
+        let (selrect, linear) = match &paths {
+            Some(paths) => {
+                let world_paths: Vec<VectorPath> = paths.iter().map(|p| transform_path(p, &world)).collect();
+                let bounds = world_paths.iter().filter_map(path_bounds).reduce(|a, b| a.union(&b)).unwrap_or(Rect::new(0.0, 0.0, 0.0, 0.0));
+                (bounds, IDENTITY)
+            }
+            None if node.node_type == NodeType::Group => (tree.world_bounds(id).unwrap_or(Rect::new(0.0, 0.0, 0.0, 0.0)), IDENTITY),
+            None => {
+                let (cx, cy) = apply(&world, w / 2.0, h / 2.0);
+                (Rect::new(cx - w / 2.0, cy - h / 2.0, w, h), [world[0], world[1], world[2], world[3], 0.0, 0.0])
+            }
+        };
+        // Node box (normalized) to the shape's selrect (normalized)
+        let to_box = match &paths {
+            Some(_) => {
+                let unit = multiply(&translate(selrect.x, selrect.y), &scale(selrect.width.max(1e-6), selrect.height.max(1e-6)));
+                multiply(&invert(&unit).unwrap_or(IDENTITY), &multiply(&world, &scale(w, h)))
+            }
+            None => IDENTITY,
+        };
+        let size = Rect::new(0.0, 0.0, selrect.width, selrect.height);
+
+        let center = (selrect.x + selrect.width / 2.0, selrect.y + selrect.height / 2.0);
+        let corner = |x: f64, y: f64| {
+            let (x, y) = apply(&linear, x - selrect.width / 2.0, y - selrect.height / 2.0);
+            json!({ "x": center.0 + x, "y": center.1 + y })
+        };
+        let matrix = |m: &Matrix| json!({ "a": m[0], "b": m[1], "c": m[2], "d": m[3], "e": 0.0, "f": 0.0 });
+        let rotation = linear[1].atan2(linear[0]).to_degrees().rem_euclid(360.0);
+
+        let mut shape = json!({
+            "id": uuid,
+            "name": node.name,
+            "type": kind,
+            "parentId": parent,
+            "frameId": frame,
+            "x": selrect.x,
+            "y": selrect.y,
+            "width": selrect.width,
+            "height": selrect.height,
+            "rotation": rotation,
+            "selrect": {
+                "x": selrect.x,
+                "y": selrect.y,
+                "width": selrect.width,
+                "height": selrect.height,
+                "x1": selrect.x,
+                "y1": selrect.y,
+                "x2": selrect.right(),
+                "y2": selrect.bottom(),
+            },
+            "points": [
+                corner(0.0, 0.0),
+                corner(selrect.width, 0.0),
+                corner(selrect.width, selrect.height),
+                corner(0.0, selrect.height),
+            ],
+            "transform": matrix(&linear),
+            "transformInverse": matrix(&invert(&linear).unwrap_or(IDENTITY)),
+            "opacity": node.opacity(),
+            "hidden": !node.visible,
+            "blocked": node.locked,
+            "proportionLock": false,
+            "proportion": 1,
+        });
+        let attrs = shape.as_object_mut()?;
+        if let Some(mode) = node.blend_mode.as_deref().and_then(blend_mode_to_css) {
+            attrs.insert("blendMode".to_string(), mode.into());
+        }
+
+        let fills: Vec<Value> = node.fills().iter().filter_map(|p| self.paint(p, "fill", &to_box, size)).collect();
+        if node.node_type != NodeType::Text {
+            attrs.insert("fills".to_string(), fills.into());
+        }
+
+        let weight = node.stroke_weight();
+        let mut strokes = Vec::new();
+        for paint in node.strokes() {
+            let Some(Value::Object(mut stroke)) = self.paint(paint, "stroke", &to_box, size) else { continue };
+            stroke.insert("strokeWidth".to_string(), weight.into());
+            let alignment = match node.stroke_align.as_deref() {
+                Some("INSIDE") => "inner",
+                Some("OUTSIDE") => "outer",
+                _ => "center",
+            };
+            stroke.insert("strokeAlignment".to_string(), alignment.into());
+            let style = match node.dash_pattern.as_deref() {
+                Some(dashes) if !dashes.is_empty() => {
+                    if dash_pattern("dashed", weight).as_deref() != Some(dashes) {
+                        self.warn("Custom dash patterns are exported as dashed strokes");
+                    }
+                    "dashed"
+                }
+                _ => "solid",
+            };
+            stroke.insert("strokeStyle".to_string(), style.into());
+            if let Some(cap) = node.stroke_cap.as_deref().filter(|c| matches!(*c, "ROUND" | "SQUARE")) {
+                stroke.insert("strokeCapStart".to_string(), cap.to_lowercase().into());
+                stroke.insert("strokeCapEnd".to_string(), cap.to_lowercase().into());
+            }
+            strokes.push(Value::Object(stroke));
+        }
+        attrs.insert("strokes".to_string(), strokes.into());
+
+        let mut shadows = Vec::new();
+        for effect in node.effects.iter().flatten() {
+            let visible = effect.get("visible").and_then(Value::as_bool).unwrap_or(true);
+            let radius = effect.get("radius").and_then(Value::as_f64).unwrap_or(0.0);
+            match effect.get("type").and_then(Value::as_str) {
+                Some(kind @ ("DROP_SHADOW" | "INNER_SHADOW")) => {
+                    let c = effect.get("color");
+                    let channel = |key, default| c.and_then(|c| c.get(key)).and_then(Value::as_f64).unwrap_or(default);
+                    let color = Rgba { r: channel("r", 0.0), g: channel("g", 0.0), b: channel("b", 0.0), a: channel("a", 0.25) };
+                    let offset = |key| effect.pointer(&format!("/offset/{}", key)).and_then(Value::as_f64).unwrap_or(0.0);
+                    shadows.push(json!({
+                        "id": generate_node_id(),
+                        "style": if kind == "DROP_SHADOW" { "drop-shadow" } else { "inner-shadow" },
+                        "offsetX": offset("x"),
+                        "offsetY": offset("y"),
+                        "blur": radius,
+                        "spread": effect.get("spread").and_then(Value::as_f64).unwrap_or(0.0),
+                        "hidden": !visible,
+                        "color": { "color": color.hex(), "opacity": color.a },
+                    }));
+                }
+                Some("BLUR") => {
+                    let blur = json!({ "id": generate_node_id(), "type": "layer-blur", "value": radius, "hidden": !visible });
+                    attrs.insert("blur".to_string(), blur);
+                }
+                _ => self.warn("Background blurs are not supported by Penpot and were left out"),
+            }
+        }
+        if !shadows.is_empty() {
+            attrs.insert("shadow".to_string(), shadows.into());
+        }
+
+        match node.node_type {
+            NodeType::Rectangle | NodeType::Frame | NodeType::Component | NodeType::Instance | NodeType::Image => {
+                let r = node.corner_radius.unwrap_or(0.0);
+                for key in ["r1", "r2", "r3", "r4"] {
+                    attrs.insert(key.to_string(), r.into());
+                }
+            }
+            _ => {}
+        }
+        if kind == "frame" {
+            attrs.insert("showContent".to_string(), (!node.clips_content.unwrap_or(true)).into());
+        }
+        match node.node_type {
+            NodeType::Component => {
+                let component_id = self.components.get(id).cloned()?;
+                attrs.insert("componentId".to_string(), component_id.into());
+                attrs.insert("componentFile".to_string(), self.file_id.clone().into());
+                attrs.insert("componentRoot".to_string(), true.into());
+                attrs.insert("mainInstance".to_string(), true.into());
+            }
+            NodeType::Instance => {
+                let main = node.extra.get("componentId").and_then(Value::as_str).map(str::to_string);
+                match main.and_then(|main| Some((self.components.get(&main).cloned()?, main))) {
+                    Some((component_id, main)) => {
+                        let main_ref = self.uuid(&main);
+                        attrs.insert("componentId".to_string(), component_id.into());
+                        attrs.insert("componentFile".to_string(), self.file_id.clone().into());
+                        attrs.insert("componentRoot".to_string(), true.into());
+                        attrs.insert("shapeRef".to_string(), main_ref.into());
+                        self.match_shapes(id, &main);
+                    }
+                    None => self.warn("Instances of components outside the document were exported as frames"),
+                }
+            }
+            _ => {}
+        }
+        if let Some(shape_ref) = self.shape_refs.get(id) {
+            attrs.insert("shapeRef".to_string(), shape_ref.clone().into());
+        }
+
+        match kind {
+            "path" => {
+                let segments = Self::path_content(paths.as_deref().unwrap_or_default(), &world);
+                attrs.insert("content".to_string(), segments.into());
+            }
+            "text" => {
+                let content = self.text_content(node, &to_box, size);
+                let grow_type = match node.extra.get("textAutoResize").and_then(Value::as_str) {
+                    Some("WIDTH_AND_HEIGHT") => "auto-width",
+                    Some("HEIGHT") => "auto-height",
+                    _ => "fixed",
+                };
+                let attrs = shape.as_object_mut()?;
+                attrs.insert("content".to_string(), content);
+                attrs.insert("growType".to_string(), grow_type.into());
+            }
+            "image" => {
+                let metadata = node.image_ref.as_deref().and_then(|r| self.media(r));
+                if metadata.is_none() {
+                    self.warn("Some images could not be read and were left out");
+                }
+                shape.as_object_mut()?.insert("metadata".to_string(), metadata.unwrap_or(Value::Null));
+            }
+            _ => {}
+        }
+
+        let child_frame = if kind == "frame" { uuid.clone() } else { frame.to_string() };
+        let mut children = Vec::new();
+        if kind != "path" {
+            for child in tree.children(id) {
+                if let Some(child) = self.shape(child, &uuid, &child_frame, out) {
+                    children.push(Value::from(child));
+                }
+            }
+        }
+        if matches!(kind, "frame" | "group") {
+            shape.as_object_mut()?.insert("shapes".to_string(), children.into());
+        }
+        out.push(shape);
+        Some(uuid)
+    }
+}
+
+/// Write `document` as a Penpot 2.x export. `assets` holds the bitmaps the
+/// document's image refs point at.
+#[command(async)]
+pub fn export_penpot(path: String, document: String, assets: Vec<BundleAsset>) -> Result<PenpotExport, String> {
+    let serialized: SerializedDocument = serde_json::from_str(&document)
+        .map_err(|e| format!("Invalid document: {}", e))?;
+    let name = serialized.name.clone();
+    let tree = DocumentTree::from_serialized(serialized);
+
+    let mut decoded = HashMap::with_capacity(assets.len());
+    for asset in assets {
+        let bytes = BASE64.decode(asset.data.as_bytes())
+            .map_err(|e| format!("Invalid base64 data for {}: {}", asset.path, e))?;
+        decoded.insert(asset.path, bytes);
+    }
+
+    let file_id = generate_node_id();
+    let mut exporter = Exporter {
+        tree: &tree,
+        file_id: file_id.clone(),
+        assets: decoded,
+        ids: HashMap::new(),
+        components: HashMap::new(),
+        shape_refs: HashMap::new(),
+        media: Vec::new(),
+        objects: Vec::new(),
+        warnings: Vec::new(),
+    };
+
+    // Components get ids before any instance refers to them
+    let pages: Vec<(String, String)> = tree.pages().iter().map(|p| (p.id.clone(), p.name.clone())).collect();
+    let mut components = Vec::new();
+    let mut stack: Vec<(String, String)> = pages.iter().map(|(id, _)| (id.clone(), id.clone())).collect();
+    while let Some((id, page)) = stack.pop() {
+        if tree.get(&id).is_some_and(|n| n.node_type == NodeType::Component) {
+            let component_id = generate_node_id();
+            exporter.components.insert(id.clone(), component_id.clone());
+            components.push((component_id, id.clone(), page.clone()));
+        }
+        stack.extend(tree.children(&id).iter().map(|c| (c.clone(), page.clone())));
+    }
+
+    let mut page_entries = Vec::with_capacity(pages.len());
+    let mut shape_count = 0;
+    for (index, (page_id, page_name)) in pages.iter().enumerate() {
+        let page_uuid = exporter.uuid(page_id);
+        let mut objects = Vec::new();
+        let mut top = Vec::new();
+        for child in tree.children(page_id).to_vec() {
+            if let Some(id) = exporter.shape(&child, ROOT_FRAME, ROOT_FRAME, &mut objects) {
+                top.push(Value::from(id));
+            }
+        }
+        shape_count += objects.len();
+
+        let background = tree.get(page_id).and_then(|p| p.background_color).map(Rgba::hex);
+        objects.push(json!({
+            "id": ROOT_FRAME,
+            "name": "Root Frame",
+            "type": "frame",
+            "parentId": ROOT_FRAME,
+            "frameId": ROOT_FRAME,
+            "x": 0, "y": 0, "width": 0.01, "height": 0.01, "rotation": 0,
+            "selrect": { "x": 0, "y": 0, "width": 0.01, "height": 0.01, "x1": 0, "y1": 0, "x2": 0.01, "y2": 0.01 },
+            "points": [{ "x": 0, "y": 0 }, { "x": 0.01, "y": 0 }, { "x": 0.01, "y": 0.01 }, { "x": 0, "y": 0.01 }],
+            "transform": { "a": 1, "b": 0, "c": 0, "d": 1, "e": 0, "f": 0 },
+            "transformInverse": { "a": 1, "b": 0, "c": 0, "d": 1, "e": 0, "f": 0 },
+            "fills": [{ "fillColor": "#FFFFFF", "fillOpacity": 1 }],
+            "strokes": [],
+            "shapes": top,
+        }));
+        let mut page = json!({ "id": page_uuid, "name": page_name, "index": index });
+        if let Some(background) = background {
+            page["options"] = json!({ "background": background });
+        }
+        page_entries.push(page);
+        exporter.objects.push((page_uuid, objects));
+    }
+
+    let manifest = json!({
+        "type": "penpot/export-files",
+        "version": 1,
+        "generatedBy": format!("designlibre/{}", env!("CARGO_PKG_VERSION")),
+        "refer": "penpot",
+        "files": [{ "id": file_id, "name": name, "features": FEATURES }],
+        "relations": [],
+    });
+    let page_ids: Vec<&Value> = page_entries.iter().map(|p| &p["id"]).collect();
+    let file = json!({
+        "id": file_id,
+        "name": name,
+        "revn": 0,
+        "vern": 0,
+        "isShared": false,
+        "version": DATA_VERSION,
+        "features": FEATURES,
+        "pages": page_ids,
+    });
+
+    let mut entries: Vec<(String, Vec<u8>)> = Vec::new();
+    let mut add_json = |name: String, value: &Value| entries.push((name, value.to_string().into_bytes()));
+    add_json("manifest.json".to_string(), &manifest);
+    let dir = format!("files/{}", file_id);
+    add_json(format!("{}.json", dir), &file);
+    for page in &page_entries {
+        add_json(format!("{}/pages/{}.json", dir, page["id"].as_str().unwrap_or_default()), page);
+    }
+    for (page_id, objects) in &exporter.objects {
+        for object in objects {
+            add_json(format!("{}/pages/{}/{}.json", dir, page_id, object["id"].as_str().unwrap_or_default()), object);
+        }
+    }
+    for (component_id, main, page) in &components {
+        let main_uuid = exporter.uuid(main);
+        let page_uuid = exporter.uuid(page);
+        let name = tree.get(main).map(|n| n.name.clone()).unwrap_or_default();
+        let component = json!({
+            "id": component_id,
+            "name": name,
+            "path": "",
+            "mainInstanceId": main_uuid,
+            "mainInstancePage": page_uuid,
+        });
+        entries.push((format!("{}/components/{}.json", dir, component_id), component.to_string().into_bytes()));
+    }
+    for (_, media) in std::mem::take(&mut exporter.media) {
+        let meta = json!({
+            "id": media.id,
+            "name": media.id,
+            "width": media.width,
+            "height": media.height,
+            "mtype": media.mime_type,
+            "mediaId": media.object_id,
+        });
+        let object = json!({
+            "id": media.object_id,
+            "size": media.bytes.len(),
+            "contentType": media.mime_type,
+            "bucket": "file-media-object",
+        });
+        entries.push((format!("{}/media/{}.json", dir, media.id), meta.to_string().into_bytes()));
+        entries.push((format!("objects/{}.json", media.object_id), object.to_string().into_bytes()));
+        entries.push((format!("objects/{}.{}", media.object_id, media.extension), media.bytes));
+    }
+
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let write_err = |e: &dyn std::fmt::Display| format!("Failed to write Penpot file: {}", e);
+    for (name, bytes) in &entries {
+        // Media is already compressed
+        let options = if name.ends_with(".json") { deflated } else { stored };
+        zip.start_file(name.as_str(), options).map_err(|e| write_err(&e))?;
+        zip.write_all(bytes).map_err(|e| write_err(&e))?;
+    }
+    let bytes = zip.finish().map_err(|e| write_err(&e))?.into_inner();
+    write_atomic(&path, &bytes)?;
+
+    Ok(PenpotExport { page_count: page_entries.len(), shape_count, warnings: exporter.warnings })
+}
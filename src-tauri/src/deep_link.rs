@@ -0,0 +1,143 @@
+//! `designlibre://` links, for pointing from an issue tracker or a doc
+//! straight at a document or a frame in it:
+//!
+//! - `designlibre://open?path=/designs/app.designlibre&node=12:40` opens a
+//!   document, and goes to a node in it when `node` is given.
+//! - `designlibre://node/12:40` goes to a node of the open document, or of
+//!   the one at `path` when that is given.
+//!
+//! The installers register the scheme from the `deep-link` entry of the
+//! bundle config. Links reach the app the way opened files do: as launch
+//! arguments on Windows and Linux, forwarded from a second launch, and as
+//! an open-URLs event on macOS.
+
+use crate::open_files::bring_to_front;
+use percent_encoding::percent_decode_str;
+use serde::Serialize;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use tauri::{command, AppHandle, Emitter, Manager, RunEvent, State, Url};
+
+pub const SCHEME: &str = "designlibre";
+
+pub const DEEP_LINK_EVENT: &str = "deep-link";
+
+#[derive(Serialize, Clone)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE", rename_all_fields = "camelCase")]
+pub enum DeepLink {
+    /// Open the document at `path`, at `node_id` if given.
+    Open { path: String, node_id: Option<String> },
+    /// Go to node `id`, in the document at `path` if given, otherwise in the
+    /// open one.
+    Node { id: String, path: Option<String> },
+    /// A link that can't be followed, for the frontend to say why.
+    Invalid { url: String, message: String },
+}
+
+fn parse_link(link: &str) -> Result<DeepLink, String> {
+    let url = Url::parse(link).map_err(|e| format!("Not a valid link: {}", e))?;
+    if url.scheme() != SCHEME {
+        return Err(format!("Not a {}:// link", SCHEME));
+    }
+    let query = |key: &str| url.query_pairs().find(|(k, _)| k == key).map(|(_, v)| v.into_owned());
+    let absolute = |path: Option<String>| match path {
+        Some(path) if !Path::new(&path).is_absolute() => Err(format!("The document path {} is not absolute", path)),
+        path => Ok(path),
+    };
+    match url.host_str() {
+        Some("open") => {
+            let path = absolute(query("path"))?.ok_or("The link names no document to open")?;
+            Ok(DeepLink::Open { path, node_id: query("node") })
+        }
+        Some("node") => {
+            let id = percent_decode_str(url.path().trim_matches('/'))
+                .decode_utf8()
+                .map_err(|_| "The node id in the link is not valid text".to_string())?
+                .into_owned();
+            if id.is_empty() {
+                return Err("The link names no node".to_string());
+            }
+            Ok(DeepLink::Node { id, path: absolute(query("path"))? })
+        }
+        _ => Err("The link is neither an open nor a node link".to_string()),
+    }
+}
+
+/// What `link` asks for, or why it can't be followed.
+pub fn parse(link: &str) -> DeepLink {
+    parse_link(link).unwrap_or_else(|message| DeepLink::Invalid { url: link.to_string(), message })
+}
+
+/// The `designlibre://` links among a launch's arguments.
+pub fn link_arguments(args: &[String]) -> Vec<String> {
+    let prefix = format!("{}:", SCHEME);
+    args.iter().skip(1).filter(|arg| arg.to_lowercase().starts_with(&prefix)).cloned().collect()
+}
+
+struct Pending {
+    /// Whether the frontend has taken the links from launch, after which
+    /// links are sent to it as they come.
+    ready: bool,
+    links: Vec<DeepLink>,
+}
+
+pub struct DeepLinks {
+    pending: Mutex<Pending>,
+}
+
+impl DeepLinks {
+    pub fn new(links: Vec<String>) -> Self {
+        let links = links.iter().map(|link| parse(link)).collect();
+        DeepLinks { pending: Mutex::new(Pending { ready: false, links }) }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Pending> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Send each of `links` to the frontend as a `deep-link` event, or keep
+    /// them until it is ready, and bring the app to the front.
+    pub fn follow(&self, app: &AppHandle, links: Vec<String>) {
+        if links.is_empty() {
+            return;
+        }
+        bring_to_front(app);
+        let links: Vec<DeepLink> = links.iter().map(|link| parse(link)).collect();
+        let mut pending = self.lock();
+        if pending.ready {
+            drop(pending);
+            for link in links {
+                let _ = app.emit(DEEP_LINK_EVENT, link);
+            }
+        } else {
+            pending.links.extend(links);
+        }
+    }
+}
+
+/// Follow the links another launch of the app was given.
+pub fn handle_second_instance(app: &AppHandle, args: &[String]) {
+    app.state::<DeepLinks>().follow(app, link_arguments(args));
+}
+
+/// Follow the links macOS asks the running app to open.
+pub fn handle_run_event(app: &AppHandle, event: &RunEvent) {
+    #[cfg(target_os = "macos")]
+    if let RunEvent::Opened { urls } = event {
+        let links = urls.iter().filter(|url| url.scheme() == SCHEME).map(|url| url.to_string()).collect();
+        if let Some(deep_links) = app.try_state::<DeepLinks>() {
+            deep_links.follow(app, links);
+        }
+    }
+    #[cfg(not(target_os = "macos"))]
+    let _ = (app, event);
+}
+
+/// Links the app was opened with before the frontend was listening for
+/// `deep-link`, to call once it is. Later links come as events.
+#[command]
+pub fn take_deep_links(deep_links: State<'_, DeepLinks>) -> Vec<DeepLink> {
+    let mut pending = deep_links.lock();
+    pending.ready = true;
+    std::mem::take(&mut pending.links)
+}
@@ -0,0 +1,445 @@
+//! A CRDT over the document store, so edits made concurrently by other
+//! clients, or by background work, merge the same way everywhere without
+//! conflicts. Every property of every node is a last-writer-wins register,
+//! as are a node's place in the tree and whether it is deleted, and
+//! siblings are ordered by fractional position keys. Replicas exchange
+//! the registers the other hasn't seen, going by state vectors of the
+//! latest change seen from each client.
+
+use super::store::{announce, Document, DocumentHandle, DocumentOp};
+use crate::model::{NodeData, SerializedNode};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::time::SystemTime;
+use tauri::{command, AppHandle};
+
+/// Digits of position keys, in ASCII order.
+const DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// Properties kept in the tree structure rather than in registers.
+const STRUCTURAL_PROPS: [&str; 3] = ["id", "parentId", "childIds"];
+
+/// The latest change seen from each client, by client id.
+pub type StateVector = HashMap<u64, u64>;
+
+/// When a change was made. Lamport time, then client, orders every change;
+/// `clock` counts the client's own changes.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Stamp {
+    pub time: u64,
+    pub client: u64,
+    pub clock: u64,
+}
+
+/// Where a node sits: under `parent_id`, ordered among its siblings by
+/// `position`. `from` is the parent it was moved from, which it falls back
+/// to if concurrent moves would put it inside itself.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Place {
+    pub parent_id: String,
+    pub position: String,
+    #[serde(default)]
+    pub from: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE", rename_all_fields = "camelCase")]
+pub enum Field {
+    /// A property by its serialized name. `null` unsets it.
+    Prop { name: String, value: Value },
+    Place(Place),
+    Deleted { deleted: bool },
+}
+
+/// A register's value, as sent between replicas.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Change {
+    pub node_id: String,
+    pub stamp: Stamp,
+    pub field: Field,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Update {
+    /// The sender's state vector, which the receiver has caught up to once
+    /// it has merged `changes`.
+    state_vector: StateVector,
+    changes: Vec<Change>,
+}
+
+#[derive(Default)]
+struct Registers {
+    props: HashMap<String, (Stamp, Value)>,
+    place: Option<(Stamp, Place)>,
+    deleted: Option<(Stamp, bool)>,
+}
+
+impl Registers {
+    fn is_deleted(&self) -> bool {
+        self.deleted.as_ref().is_some_and(|(_, d)| *d)
+    }
+}
+
+/// A position key between `lo` and `hi`, either of which may be open.
+/// Keys never end in the lowest digit, so there is always room below one.
+fn key_between(lo: Option<&str>, hi: Option<&str>) -> String {
+    let digit = |key: &[u8], i: usize| key.get(i).and_then(|c| DIGITS.iter().position(|d| d == c));
+    let lo = lo.unwrap_or("").as_bytes();
+    let mut hi = hi.map(str::as_bytes).filter(|h| *h > lo);
+    let mut key = Vec::new();
+    for i in 0.. {
+        let a = digit(lo, i).unwrap_or(0);
+        let Some(b) = hi.and_then(|h| digit(h, i)) else {
+            // Open above, step the first digit that can, to keep keys short
+            // when appending
+            if a + 1 < DIGITS.len() {
+                key.push(DIGITS[a + 1]);
+                break;
+            }
+            key.push(DIGITS[a]);
+            hi = None;
+            continue;
+        };
+        if b > a + 1 {
+            key.push(DIGITS[(a + b) / 2]);
+            break;
+        }
+        key.push(DIGITS[a]);
+        if b == a + 1 {
+            hi = None;
+        }
+    }
+    String::from_utf8(key).unwrap_or_default()
+}
+
+/// `count` keys spread evenly, all the same length, with room for a key
+/// between each two.
+fn spread_keys(count: usize) -> Vec<String> {
+    let base = DIGITS.len() as u128;
+    let mut width = 1;
+    while base.pow(width) < 2 * (count as u128 + 1) {
+        width += 1;
+    }
+    let span = base.pow(width);
+    (1..=count as u128)
+        .map(|i| {
+            let mut n = i * span / (count as u128 + 1);
+            if n.is_multiple_of(base) {
+                n += 1;
+            }
+            let mut key = vec![DIGITS[0]; width as usize];
+            for slot in key.iter_mut().rev() {
+                *slot = DIGITS[(n % base) as usize];
+                n /= base;
+            }
+            String::from_utf8(key).unwrap_or_default()
+        })
+        .collect()
+}
+
+/// The properties of `node` as registers hold them.
+fn props_of(node: &NodeData) -> Map<String, Value> {
+    let mut props = match serde_json::to_value(node) {
+        Ok(Value::Object(props)) => props,
+        _ => Map::new(),
+    };
+    props.retain(|name, _| !STRUCTURAL_PROPS.contains(&name.as_str()));
+    props
+}
+
+/// A fresh client id, to tell a replica's changes from other clients'.
+pub fn new_client_id() -> u64 {
+    let seed = format!("{}-{:?}", std::process::id(), SystemTime::now());
+    let digest = Sha256::digest(seed.as_bytes());
+    u64::from_le_bytes([digest[0], digest[1], digest[2], digest[3], digest[4], digest[5], digest[6], digest[7]])
+}
+
+/// The document as a set of registers, changed locally through the
+/// document's own operations and remotely by merging updates.
+pub struct Replica {
+    client: u64,
+    /// Lamport time of the latest change seen.
+    time: u64,
+    root_id: String,
+    nodes: HashMap<String, Registers>,
+    /// Children by parent, ordered by position and then id, deleted or not.
+    children: HashMap<String, BTreeSet<(String, String)>>,
+    state_vector: StateVector,
+}
+
+impl Replica {
+    /// A replica holding `document`, as changes by `client`.
+    pub fn new(document: &Document, client: u64) -> Self {
+        let doc = document.to_serialized();
+        let mut replica = Replica {
+            client,
+            time: 0,
+            root_id: doc.root_id,
+            nodes: HashMap::with_capacity(doc.nodes.len()),
+            children: HashMap::new(),
+            state_vector: StateVector::new(),
+        };
+        // Nodes come parents first, so each node's key is ready
+        let mut positions: HashMap<String, String> = HashMap::new();
+        for node in doc.nodes {
+            positions.extend(node.data.child_ids.iter().cloned().zip(spread_keys(node.data.child_ids.len())));
+            for (name, value) in props_of(&node.data) {
+                replica.push(&node.id, Field::Prop { name, value });
+            }
+            if let (Some(parent_id), Some(position)) = (node.parent_id, positions.remove(&node.id)) {
+                replica.push(&node.id, Field::Place(Place { parent_id, position, from: None }));
+            }
+        }
+        replica
+    }
+
+    pub fn state_vector(&self) -> &StateVector {
+        &self.state_vector
+    }
+
+    /// Put `change` in its register if it is newer than what is there,
+    /// returning whether it was.
+    fn integrate(&mut self, change: Change) -> bool {
+        let Change { node_id, stamp, field } = change;
+        let registers = self.nodes.entry(node_id.clone()).or_default();
+        match field {
+            Field::Prop { name, value } => match registers.props.get(&name) {
+                Some((old, _)) if *old >= stamp => false,
+                _ => {
+                    registers.props.insert(name, (stamp, value));
+                    true
+                }
+            },
+            Field::Place(place) => {
+                if registers.place.as_ref().is_some_and(|(old, _)| *old >= stamp) {
+                    return false;
+                }
+                if let Some((_, old)) = registers.place.as_ref() {
+                    if let Some(siblings) = self.children.get_mut(&old.parent_id) {
+                        siblings.remove(&(old.position.clone(), node_id.clone()));
+                    }
+                }
+                self.children.entry(place.parent_id.clone()).or_default().insert((place.position.clone(), node_id));
+                registers.place = Some((stamp, place));
+                true
+            }
+            Field::Deleted { deleted } => match registers.deleted {
+                Some((old, _)) if old >= stamp => false,
+                _ => {
+                    registers.deleted = Some((stamp, deleted));
+                    true
+                }
+            },
+        }
+    }
+
+    /// Make a change here.
+    fn push(&mut self, node_id: &str, field: Field) {
+        self.time += 1;
+        let clock = self.state_vector.get(&self.client).copied().unwrap_or(0) + 1;
+        self.state_vector.insert(self.client, clock);
+        let stamp = Stamp { time: self.time, client: self.client, clock };
+        self.integrate(Change { node_id: node_id.to_string(), stamp, field });
+    }
+
+    /// A key placing a child of `parent_id` at `index` among the others
+    /// but `id`, or last.
+    fn position_at(&self, parent_id: &str, id: &str, index: Option<usize>) -> String {
+        let siblings: Vec<&str> = self
+            .children
+            .get(parent_id)
+            .into_iter()
+            .flatten()
+            .filter(|(_, kid)| kid != id && self.nodes.get(kid).is_some_and(|r| !r.is_deleted()))
+            .map(|(position, _)| position.as_str())
+            .collect();
+        let at = index.unwrap_or(siblings.len()).min(siblings.len());
+        let lo = at.checked_sub(1).map(|i| siblings[i]);
+        key_between(lo, siblings.get(at).copied())
+    }
+
+    /// Record `op`, just applied to the document.
+    pub fn record(&mut self, op: &DocumentOp) {
+        match op {
+            DocumentOp::Insert { node, parent_id, index } => {
+                let mut props = props_of(node);
+                if let Some(registers) = self.nodes.get(&node.id) {
+                    // Properties left from before it was deleted
+                    for name in registers.props.keys() {
+                        props.entry(name.clone()).or_insert(Value::Null);
+                    }
+                    if registers.is_deleted() {
+                        self.push(&node.id, Field::Deleted { deleted: false });
+                    }
+                }
+                for (name, value) in props {
+                    self.push(&node.id, Field::Prop { name, value });
+                }
+                let position = self.position_at(parent_id, &node.id, *index);
+                self.push(&node.id, Field::Place(Place { parent_id: parent_id.clone(), position, from: None }));
+            }
+            DocumentOp::SetProps { id, props } => {
+                for (name, value) in props {
+                    self.push(id, Field::Prop { name: name.clone(), value: value.clone() });
+                }
+            }
+            DocumentOp::Reparent { id, parent_id, index } => {
+                let from = self.nodes.get(id).and_then(|r| r.place.as_ref()).map(|(_, p)| p.parent_id.clone());
+                let position = self.position_at(parent_id, id, *index);
+                self.push(id, Field::Place(Place { parent_id: parent_id.clone(), position, from }));
+            }
+            DocumentOp::Delete { id } => self.push(id, Field::Deleted { deleted: true }),
+        }
+    }
+
+    /// The changes a replica at `since` hasn't seen, as an update payload.
+    pub fn encode(&self, since: &StateVector) -> Result<String, String> {
+        let unseen = |stamp: &Stamp| stamp.clock > since.get(&stamp.client).copied().unwrap_or(0);
+        let mut changes = Vec::new();
+        for (id, registers) in &self.nodes {
+            let change = |stamp: &Stamp, field: Field| Change { node_id: id.clone(), stamp: *stamp, field };
+            for (name, (stamp, value)) in registers.props.iter().filter(|(_, (s, _))| unseen(s)) {
+                changes.push(change(stamp, Field::Prop { name: name.clone(), value: value.clone() }));
+            }
+            if let Some((stamp, place)) = registers.place.as_ref().filter(|(s, _)| unseen(s)) {
+                changes.push(change(stamp, Field::Place(place.clone())));
+            }
+            if let Some((stamp, deleted)) = registers.deleted.filter(|(s, _)| unseen(s)) {
+                changes.push(change(&stamp, Field::Deleted { deleted }));
+            }
+        }
+        let update = Update { state_vector: self.state_vector.clone(), changes };
+        let json = serde_json::to_vec(&update).map_err(|e| e.to_string())?;
+        Ok(BASE64.encode(json))
+    }
+
+    /// Merge an update payload, returning whether anything changed.
+    pub fn merge(&mut self, payload: &str) -> Result<bool, String> {
+        let bytes = BASE64.decode(payload.trim()).map_err(|e| format!("Invalid update: {}", e))?;
+        let update: Update = serde_json::from_slice(&bytes).map_err(|e| format!("Invalid update: {}", e))?;
+        let mut changed = false;
+        for change in update.changes {
+            self.time = self.time.max(change.stamp.time);
+            changed |= self.integrate(change);
+        }
+        for (client, clock) in update.state_vector {
+            let seen = self.state_vector.entry(client).or_insert(0);
+            *seen = (*seen).max(clock);
+        }
+        Ok(changed)
+    }
+
+    /// Each placed node's parent, with moves that would put a node inside
+    /// itself undone: in each such loop, the node moved last goes back to
+    /// where it came from, or to the root.
+    fn parents(&self) -> HashMap<&str, &str> {
+        let mut parents: HashMap<&str, &str> = HashMap::new();
+        for (id, registers) in &self.nodes {
+            if let Some((_, place)) = &registers.place {
+                parents.insert(id, &place.parent_id);
+            }
+        }
+        let mut settled: HashSet<&str> = HashSet::new();
+        let ids: Vec<&str> = parents.keys().copied().collect();
+        for start in ids {
+            let mut path: Vec<&str> = Vec::new();
+            let mut current = start;
+            loop {
+                if settled.contains(current) || !parents.contains_key(current) {
+                    break;
+                }
+                if let Some(at) = path.iter().position(|p| *p == current) {
+                    let stamp = |id: &str| self.nodes.get(id).and_then(|r| r.place.as_ref()).map(|(s, _)| *s);
+                    let Some(breaker) = path[at..].iter().copied().max_by_key(|id| stamp(id)) else { break };
+                    let from = self.nodes.get(breaker).and_then(|r| r.place.as_ref()).and_then(|(_, p)| p.from.as_deref());
+                    let fallback = from.filter(|f| !path[at..].contains(f)).unwrap_or(self.root_id.as_str());
+                    parents.insert(breaker, fallback);
+                    // Walk again from the start with the loop broken
+                    path.clear();
+                    current = start;
+                    continue;
+                }
+                path.push(current);
+                current = parents[current];
+            }
+            settled.extend(path);
+        }
+        parents
+    }
+
+    /// The nodes as the registers have them, for `Document::with_nodes`.
+    pub fn nodes(&self) -> Vec<SerializedNode> {
+        let parents = self.parents();
+        let mut order: HashMap<&str, Vec<(&str, &str)>> = HashMap::new();
+        for (id, parent) in &parents {
+            let position = self.nodes[*id].place.as_ref().map(|(_, p)| p.position.as_str()).unwrap_or("");
+            order.entry(parent).or_default().push((position, id));
+        }
+        let mut child_index: HashMap<&str, usize> = HashMap::new();
+        for kids in order.values_mut() {
+            kids.sort();
+            child_index.extend(kids.iter().enumerate().map(|(i, (_, id))| (*id, i)));
+        }
+
+        self.nodes
+            .iter()
+            .filter(|(_, registers)| !registers.is_deleted())
+            .filter_map(|(id, registers)| {
+                let mut fields: Map<String, Value> = registers
+                    .props
+                    .iter()
+                    .filter(|(_, (_, value))| !value.is_null())
+                    .map(|(name, (_, value))| (name.clone(), value.clone()))
+                    .collect();
+                fields.insert("id".into(), Value::String(id.clone()));
+                let data: NodeData = serde_json::from_value(Value::Object(fields)).ok()?;
+                Some(SerializedNode {
+                    id: id.clone(),
+                    parent_id: parents.get(id.as_str()).map(|p| p.to_string()),
+                    child_index: child_index.get(id.as_str()).copied().unwrap_or(0),
+                    data,
+                })
+            })
+            .collect()
+    }
+}
+
+/// The open document's state vector.
+#[command]
+pub fn get_state_vector(store: DocumentHandle) -> Result<StateVector, String> {
+    let state = store.lock();
+    let open = state.as_ref().ok_or("No document is open")?;
+    Ok(open.replica.state_vector().clone())
+}
+
+/// An update payload with the changes a replica at `state_vector` is
+/// missing, or all of them.
+#[command]
+pub fn encode_document_update(store: DocumentHandle, state_vector: Option<StateVector>) -> Result<String, String> {
+    let state = store.lock();
+    let open = state.as_ref().ok_or("No document is open")?;
+    open.replica.encode(&state_vector.unwrap_or_default())
+}
+
+/// Merge an update payload from another replica into the open document,
+/// announcing the operations it comes to. Returns the new revision.
+#[command]
+pub fn apply_document_update(app: AppHandle, store: DocumentHandle, update: String) -> Result<u64, String> {
+    let mut state = store.lock();
+    let open = state.as_mut().ok_or("No document is open")?;
+    if open.replica.merge(&update)? {
+        let merged = open.document.with_nodes(open.replica.nodes())?;
+        let ops = open.document.sync_to(&merged);
+        if !ops.is_empty() {
+            open.unsaved.extend(ops.iter().cloned());
+            announce(&app, &store.window, open.document.revision(), ops);
+        }
+    }
+    Ok(open.document.revision())
+}
@@ -0,0 +1,258 @@
+//! Saving the open document as a base snapshot and an append-only log of
+//! the operations made since, so saving a small edit to a large document
+//! writes only the edit. `dir/design.dlibre` logs to
+//! `dir/.design.dlibre.delta`, one JSON line per save after a header naming
+//! the snapshot it follows. The log is folded into a fresh snapshot once
+//! it outgrows half the snapshot, and on every encrypted save, so edits are
+//! never left beside an encrypted file in the clear.
+
+use super::migrate::{read_document, MigrationReport};
+use super::store::{Document, DocumentHandle, DocumentInfo, DocumentOp, OpenDocument};
+use super::undo::{forget_history, load_history, save_history};
+use crate::backups::BackupManager;
+use crate::commands::{read_contents, write_contents, EncryptOptions};
+use crate::compression;
+use crate::error::{FileError, FileErrorKind};
+use crate::history::hex_digest;
+use crate::locks::FileLocks;
+use crate::mapped::MappedBundles;
+use crate::watcher::{normalize, FileWatcher};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tauri::{command, State};
+
+/// The snapshot the open document was last saved to in full.
+pub struct Snapshot {
+    path: PathBuf,
+    log: PathBuf,
+    /// Hash of the snapshot's contents, as the log's header gives it.
+    hash: String,
+    size: u64,
+    /// Bytes in the log so far, header and all.
+    log_size: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LogHeader {
+    base_hash: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LogEntry {
+    ops: Vec<DocumentOp>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenedDocument {
+    #[serde(flatten)]
+    pub info: DocumentInfo,
+    /// Saves replayed from the log on top of the snapshot.
+    pub replayed: usize,
+    pub encrypted: bool,
+    pub compressed: bool,
+    /// Migrations run to bring the file up to the current format.
+    pub migrations: MigrationReport,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SaveOptions {
+    pub encrypt: Option<EncryptOptions>,
+    pub compress: bool,
+    /// Write the whole document even if the log has room.
+    pub compact: bool,
+    /// Keep the undo history, to come back when the document is reopened.
+    /// Refused with `encrypt`, as the history is kept unencrypted.
+    pub keep_history: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentSave {
+    /// Whether the whole document was written, rather than appended.
+    pub full: bool,
+    pub bytes_written: u64,
+}
+
+pub fn log_path(document: &Path) -> Result<PathBuf, FileError> {
+    let display = document.to_string_lossy();
+    let name = document
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| FileError::new(FileErrorKind::InvalidPath, &display, "Path has no file name"))?;
+    let parent = document.parent().unwrap_or_else(|| Path::new("."));
+    Ok(parent.join(format!(".{}.delta", name)))
+}
+
+/// Saves in the log at `log` that follow a snapshot hashing to `hash`, and
+/// the length of the log up to the end of the last whole save. A save cut
+/// short by a crash ends the log early.
+fn read_log(log: &Path, hash: &str) -> Option<(Vec<LogEntry>, u64)> {
+    let bytes = fs::read(log).ok()?;
+    let mut lines = bytes.split_inclusive(|b| *b == b'\n');
+    let first = lines.next()?;
+    let header: LogHeader = serde_json::from_slice(first.strip_suffix(b"\n")?).ok()?;
+    if header.base_hash != hash {
+        return None;
+    }
+    let mut length = first.len();
+    let mut entries = Vec::new();
+    for line in lines {
+        let Some(entry) = line.strip_suffix(b"\n").and_then(|json| serde_json::from_slice(json).ok()) else { break };
+        entries.push(entry);
+        length += line.len();
+    }
+    Some((entries, length as u64))
+}
+
+/// Cut the log at `log` back to its first `length` bytes, dropping a save
+/// cut short so the next one isn't appended onto it.
+fn truncate_log(log: &Path, length: u64) -> bool {
+    match fs::metadata(log) {
+        Ok(metadata) if metadata.len() == length => true,
+        Ok(_) => OpenOptions::new().write(true).open(log).and_then(|file| file.set_len(length)).is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Whether the file at `path` still holds the snapshot, rather than having
+/// been rewritten by another program or a sync client since. Appending to
+/// the log of a snapshot that is gone would lose the edits on reopen.
+fn snapshot_intact(watcher: &FileWatcher, path: &str, snapshot: &Snapshot) -> bool {
+    let Ok(on_disk) = fs::read(path) else { return false };
+    let contents = if compression::is_compressed(&on_disk) {
+        match compression::decompress(&on_disk, path) {
+            Ok(bytes) => Cow::Owned(bytes),
+            Err(_) => return false,
+        }
+    } else {
+        Cow::Borrowed(&on_disk)
+    };
+    let log_size = fs::metadata(&snapshot.log).map(|m| m.len()).unwrap_or(0);
+    if hex_digest(&contents) != snapshot.hash || log_size != snapshot.log_size {
+        return false;
+    }
+    watcher.acknowledge(path, &on_disk);
+    true
+}
+
+/// Open the design file at `path`, with the saves logged since its last
+/// full save, as the open document.
+#[command(async)]
+pub fn open_document_file(store: DocumentHandle, path: String, passphrase: Option<String>) -> Result<OpenedDocument, FileError> {
+    let contents = read_contents(&path, passphrase.as_deref())?;
+    let (doc, migrations) = read_document(&contents.bytes).map_err(|e| FileError::new(FileErrorKind::Io, &path, e))?;
+    let mut document = Document::from_serialized(doc).map_err(|e| FileError::new(FileErrorKind::Io, &path, e))?;
+
+    let target = normalize(Path::new(&path));
+    let log_file = log_path(&target)?;
+    let hash = hex_digest(&contents.bytes);
+    let log = if contents.encrypted { None } else { read_log(&log_file, &hash) };
+    let mut replayed = 0;
+    let mut replay_failed = false;
+    for entry in log.iter().flat_map(|(entries, _)| entries) {
+        if document.apply_all(entry.ops.clone()).is_err() {
+            replay_failed = true;
+            break;
+        }
+        replayed += 1;
+    }
+
+    let history = if contents.encrypted { None } else { load_history(&store.history_dir, &target, &document) };
+    let history = history.unwrap_or_default();
+    // Without a snapshot the next save is a full one, which drops a log that
+    // doesn't follow this file or couldn't be replayed to its end
+    let size = contents.bytes.len() as u64;
+    let snapshot = match &log {
+        Some((_, log_size)) if !replay_failed && truncate_log(&log_file, *log_size) => {
+            Some(Snapshot { path: target, log: log_file, hash, size, log_size: *log_size })
+        }
+        None if !contents.encrypted && !log_file.exists() => Some(Snapshot { path: target, log: log_file, hash, size, log_size: 0 }),
+        _ => None,
+    };
+    let info = document.info();
+    *store.lock() = Some(OpenDocument::new(document, snapshot, history));
+    Ok(OpenedDocument { info, replayed, encrypted: contents.encrypted, compressed: contents.compressed, migrations })
+}
+
+/// Save the open document to `path`. The edits since the last save are
+/// appended to the file's log when it follows the file's snapshot, the file
+/// on disk is still that snapshot, and the log has room left; otherwise the
+/// whole document is written, zstd-compressed with `compress`, and the log
+/// cleared.
+#[command(async)]
+pub fn save_document(
+    watcher: State<'_, FileWatcher>,
+    backups: State<'_, BackupManager>,
+    locks: State<'_, FileLocks>,
+    mapped: State<'_, MappedBundles>,
+    store: DocumentHandle,
+    path: String,
+    options: Option<SaveOptions>,
+) -> Result<DocumentSave, FileError> {
+    let SaveOptions { encrypt, compress, compact, keep_history } = options.unwrap_or_default();
+    locks.check_writable(&path)?;
+    let mut state = store.lock();
+    let open = state.as_mut().ok_or_else(|| FileError::new(FileErrorKind::Io, &path, "No document is open"))?;
+    let target = normalize(Path::new(&path));
+    let log = log_path(&target)?;
+
+    if encrypt.is_some() {
+        if keep_history {
+            return Err(FileError::new(FileErrorKind::Io, &path, "Undo history can't be kept for an encrypted save"));
+        }
+        // History kept from before would leave the document readable
+        forget_history(&store.history_dir, &target);
+    } else if keep_history {
+        save_history(&store.history_dir, &target, &open.document, &open.history).map_err(|e| FileError::new(FileErrorKind::Io, &path, e))?;
+    }
+
+    let appendable = open.snapshot.as_ref().filter(|s| s.path == target && !compact && encrypt.is_none());
+    if let Some(snapshot) = appendable.filter(|snapshot| snapshot_intact(&watcher, &path, snapshot)) {
+        if open.unsaved.is_empty() {
+            return Ok(DocumentSave { full: false, bytes_written: 0 });
+        }
+        let entry = serde_json::to_string(&LogEntry { ops: open.unsaved.clone() }).map_err(|e| FileError::new(FileErrorKind::Io, &path, e.to_string()))?;
+        let header = if snapshot.log_size == 0 {
+            serde_json::to_string(&LogHeader { base_hash: snapshot.hash.clone() }).unwrap_or_default() + "\n"
+        } else {
+            String::new()
+        };
+        let line = header + &entry + "\n";
+        if snapshot.log_size + line.len() as u64 <= snapshot.size / 2 {
+            let appended = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&log)
+                .and_then(|mut file| file.write_all(line.as_bytes()).and_then(|_| file.sync_data()));
+            appended.map_err(|e| FileError::from_io(&e, &log.to_string_lossy(), "append to save log"))?;
+            if let Some(snapshot) = open.snapshot.as_mut() {
+                snapshot.log_size += line.len() as u64;
+            }
+            open.unsaved.clear();
+            // Backups hold whole documents, so the snapshot and log are
+            // folded into one
+            if backups.enabled() {
+                if let Ok(bytes) = serde_json::to_vec(&open.document.to_serialized()) {
+                    backups.back_up(&path, &bytes);
+                }
+            }
+            return Ok(DocumentSave { full: false, bytes_written: line.len() as u64 });
+        }
+    }
+
+    let bytes = serde_json::to_vec(&open.document.to_serialized()).map_err(|e| FileError::new(FileErrorKind::Io, &path, e.to_string()))?;
+    let encrypted = encrypt.is_some();
+    write_contents(&watcher, &backups, &locks, &mapped, &path, &bytes, encrypt, compress)?;
+    if log.exists() {
+        fs::remove_file(&log).map_err(|e| FileError::from_io(&e, &log.to_string_lossy(), "remove save log"))?;
+    }
+    open.snapshot = (!encrypted).then(|| Snapshot { path: target, log, hash: hex_digest(&bytes), size: bytes.len() as u64, log_size: 0 });
+    open.unsaved.clear();
+    Ok(DocumentSave { full: true, bytes_written: bytes.len() as u64 })
+}
@@ -0,0 +1,271 @@
+//! Comparing two design files node by node, for reviewing design changes as
+//! code changes are reviewed. Nodes are matched by id; a node added or
+//! removed with everything under it is one change, and a modified node
+//! lists each property that differs, nested objects down to their fields.
+
+use super::migrate::read_document;
+use super::store::Document;
+use crate::bundle::{is_bundle, load_bundle_document};
+use crate::commands::read_contents;
+use crate::model::{NodeData, NodeType};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fmt::Write;
+use tauri::command;
+
+/// Longest a value is shown in the summary before it is cut short.
+const SUMMARY_VALUE_CHARS: usize = 60;
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PropertyChange {
+    /// Dotted within nested objects, such as `constraints.horizontal`.
+    pub property: String,
+    /// Unset where the property is only on the other side.
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeMove {
+    pub from_parent: Option<String>,
+    pub to_parent: Option<String>,
+    pub from_index: usize,
+    pub to_index: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeChange {
+    pub kind: ChangeKind,
+    pub id: String,
+    pub name: String,
+    pub node_type: NodeType,
+    /// Names from the page down to the node, in the document it is in.
+    pub path: Vec<String>,
+    /// For added and removed nodes, how many came or went with them.
+    pub descendants: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub moved: Option<NodeMove>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub properties: Vec<PropertyChange>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentDiff {
+    /// Added and modified nodes in the order of the second document, then
+    /// the removed ones in the order of the first.
+    pub changes: Vec<NodeChange>,
+    pub added: usize,
+    pub removed: usize,
+    pub modified: usize,
+    /// The changes as text, one node to a line and its properties under it.
+    pub summary: String,
+}
+
+/// The design file at `path`, a plain or bundled document, migrated to the
+/// current format.
+pub fn load_document_file(path: &str, passphrase: Option<&str>) -> Result<Document, String> {
+    let contents = read_contents(path, passphrase)?;
+    let json = if is_bundle(&contents.bytes) { load_bundle_document(path)?.into_bytes() } else { contents.bytes };
+    let (doc, _) = read_document(&json)?;
+    Document::from_serialized(doc)
+}
+
+/// A node's properties as the file holds them, without those the tree
+/// already says.
+pub fn own_properties(node: &NodeData) -> Map<String, Value> {
+    let mut fields = match serde_json::to_value(node) {
+        Ok(Value::Object(fields)) => fields,
+        _ => Map::new(),
+    };
+    for key in ["id", "parentId", "childIds"] {
+        fields.remove(key);
+    }
+    fields
+}
+
+/// The properties that differ between `before` and `after`, by key.
+pub fn property_changes(before: &Map<String, Value>, after: &Map<String, Value>, prefix: &str, out: &mut Vec<PropertyChange>) {
+    let mut keys: Vec<&String> = before.keys().chain(after.keys().filter(|key| !before.contains_key(*key))).collect();
+    keys.sort();
+    for key in keys {
+        let property = format!("{}{}", prefix, key);
+        match (before.get(key), after.get(key)) {
+            (Some(a), Some(b)) if a == b => {}
+            (Some(Value::Object(a)), Some(Value::Object(b))) => property_changes(a, b, &format!("{}.", property), out),
+            (a, b) => out.push(PropertyChange { property, before: a.cloned(), after: b.cloned() }),
+        }
+    }
+}
+
+/// Names from the page down to `id`.
+fn path(document: &Document, id: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut at = document.get(id);
+    while let Some(node) = at.filter(|node| node.node_type != NodeType::Document) {
+        names.push(node.name.clone());
+        at = node.parent_id.as_deref().and_then(|parent| document.get(parent));
+    }
+    names.reverse();
+    names
+}
+
+fn child_index(document: &Document, node: &NodeData) -> usize {
+    let siblings = node.parent_id.as_deref().map(|parent| document.children(parent)).unwrap_or_default();
+    siblings.iter().position(|id| *id == node.id).unwrap_or_default()
+}
+
+/// Where `node` falls among those of its siblings that `other` has too, so
+/// a node added or removed beside it doesn't count as moving it.
+fn shared_index(document: &Document, other: &Document, node: &NodeData) -> usize {
+    let siblings = node.parent_id.as_deref().map(|parent| document.children(parent)).unwrap_or_default();
+    siblings.iter().filter(|id| other.get(id).is_some()).position(|id| *id == node.id).unwrap_or_default()
+}
+
+/// `node` of `document` added or removed, with what under it `other`
+/// doesn't have; nodes moved in or out of it are changes of their own.
+fn whole_node(document: &Document, other: &Document, node: &NodeData, kind: ChangeKind) -> NodeChange {
+    let inside = document.subtree(&node.id).iter().skip(1).filter(|inner| other.get(&inner.id).is_none()).count();
+    NodeChange {
+        kind,
+        id: node.id.clone(),
+        name: node.name.clone(),
+        node_type: node.node_type,
+        path: path(document, &node.id),
+        descendants: inside,
+        moved: None,
+        properties: Vec::new(),
+    }
+}
+
+/// Whether `node` of `document` is missing from `other` while its parent
+/// isn't, so it is the top of what was added or removed.
+fn is_missing_top(node: &NodeData, other: &Document) -> bool {
+    other.get(&node.id).is_none() && node.parent_id.as_deref().is_none_or(|parent| other.get(parent).is_some())
+}
+
+/// What changed from `before` to `after`.
+pub fn diff(before: &Document, after: &Document) -> Vec<NodeChange> {
+    let mut changes = Vec::new();
+    for node in after.subtree(after.root_id()) {
+        let Some(old) = before.get(&node.id) else {
+            if is_missing_top(node, before) {
+                changes.push(whole_node(after, before, node, ChangeKind::Added));
+            }
+            continue;
+        };
+        let mut properties = Vec::new();
+        property_changes(&own_properties(old), &own_properties(node), "", &mut properties);
+        let reordered = shared_index(before, after, old) != shared_index(after, before, node);
+        let moved = (old.parent_id != node.parent_id || reordered).then(|| NodeMove {
+            from_parent: old.parent_id.clone(),
+            to_parent: node.parent_id.clone(),
+            from_index: child_index(before, old),
+            to_index: child_index(after, node),
+        });
+        if moved.is_none() && properties.is_empty() {
+            continue;
+        }
+        changes.push(NodeChange {
+            kind: ChangeKind::Modified,
+            id: node.id.clone(),
+            name: node.name.clone(),
+            node_type: node.node_type,
+            path: path(after, &node.id),
+            descendants: 0,
+            moved,
+            properties,
+        });
+    }
+    let removed = before.subtree(before.root_id()).into_iter().filter(|node| is_missing_top(node, after));
+    changes.extend(removed.map(|node| whole_node(before, after, node, ChangeKind::Removed)));
+    changes
+}
+
+/// `value` as compact JSON, cut short if long.
+fn short(value: Option<&Value>) -> String {
+    let Some(value) = value else { return "(unset)".to_string() };
+    let text = value.to_string();
+    match text.char_indices().nth(SUMMARY_VALUE_CHARS) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text,
+    }
+}
+
+/// The changes as text for reading through, in the style of a code review.
+pub fn summarize(changes: &[NodeChange], before: &Document, after: &Document) -> String {
+    let count = |kind| changes.iter().filter(|change| change.kind == kind).count();
+    let mut text = format!(
+        "{} added, {} removed, {} modified\n",
+        count(ChangeKind::Added),
+        count(ChangeKind::Removed),
+        count(ChangeKind::Modified)
+    );
+    let parent_path = |document: &Document, parent: &Option<String>| match parent.as_deref().map(|id| path(document, id)) {
+        Some(names) if !names.is_empty() => names.join(" / "),
+        _ => "the document".to_string(),
+    };
+    for change in changes {
+        let mark = match change.kind {
+            ChangeKind::Added => '+',
+            ChangeKind::Removed => '-',
+            ChangeKind::Modified => '~',
+        };
+        let node_type = serde_json::to_value(change.node_type).ok().and_then(|t| t.as_str().map(String::from)).unwrap_or_default();
+        let _ = write!(text, "{} {} ({})", mark, change.path.join(" / "), node_type);
+        match change.descendants {
+            0 => text.push('\n'),
+            1 => text.push_str(", with 1 node inside\n"),
+            n => {
+                let _ = writeln!(text, ", with {} nodes inside", n);
+            }
+        }
+        if let Some(moved) = &change.moved {
+            if moved.from_parent == moved.to_parent {
+                let _ = writeln!(text, "    moved from position {} to {}", moved.from_index + 1, moved.to_index + 1);
+            } else {
+                let (from, to) = (parent_path(before, &moved.from_parent), parent_path(after, &moved.to_parent));
+                let _ = writeln!(text, "    moved from {} to {}", from, to);
+            }
+        }
+        for property in &change.properties {
+            let (from, to) = (short(property.before.as_ref()), short(property.after.as_ref()));
+            let _ = writeln!(text, "    {}: {} → {}", property.property, from, to);
+        }
+    }
+    text
+}
+
+/// Compare the design files at `path_a` and `path_b`, giving what changed
+/// from the first to the second both as data and as a summary to read.
+#[command(async)]
+pub fn diff_documents(
+    path_a: String,
+    path_b: String,
+    passphrase_a: Option<String>,
+    passphrase_b: Option<String>,
+) -> Result<DocumentDiff, String> {
+    let before = load_document_file(&path_a, passphrase_a.as_deref())?;
+    let after = load_document_file(&path_b, passphrase_b.as_deref())?;
+    let changes = diff(&before, &after);
+    let summary = summarize(&changes, &before, &after);
+    let count = |kind| changes.iter().filter(|change| change.kind == kind).count();
+    Ok(DocumentDiff {
+        added: count(ChangeKind::Added),
+        removed: count(ChangeKind::Removed),
+        modified: count(ChangeKind::Modified),
+        summary,
+        changes,
+    })
+}
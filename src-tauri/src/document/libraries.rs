@@ -0,0 +1,620 @@
+//! Shared component libraries. A library is a file of components and styles
+//! published from one document; other documents subscribe to it, bring in
+//! copies of its components and are told when it is published again with
+//! changes. A copy remembers the library, key and version it came from in
+//! `libraryComponent`, and instances keep pointing at the copy, so updating
+//! the copy in place carries the change to every instance except where an
+//! instance overrides what changed.
+//!
+//! Shared styles are kept by the webview, so they are published from it
+//! and handed back to it as they are, versioned like the components.
+
+use super::diff::{own_properties, property_changes, PropertyChange};
+use super::overrides::{instance_overrides, instances_of};
+use super::store::{Document, DocumentHandle, DocumentOp, DocumentStore};
+use crate::atomic::write_atomic;
+use crate::bundle::now_millis;
+use crate::history::hex_digest;
+use crate::model::{generate_node_id, NodeData, NodeType, SerializedNode};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use tauri::{command, AppHandle};
+
+/// Root property holding a document's subscriptions.
+const SUBSCRIPTIONS_PROP: &str = "librarySubscriptions";
+
+/// Property of a component copy naming where it came from.
+const ORIGIN_PROP: &str = "libraryComponent";
+
+/// Properties of a component's root that place it in its own document
+/// rather than describe it, so they are neither compared nor updated.
+const PLACEMENT_PROPS: [&str; 2] = ["x", "y"];
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryComponent {
+    /// The component's id in the document it was published from.
+    pub key: String,
+    pub name: String,
+    /// Bumped each time the component is published changed.
+    pub version: u64,
+    pub hash: String,
+    /// The component and everything under it, in tree order.
+    pub nodes: Vec<SerializedNode>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryStyle {
+    /// The style's id in the document it was published from.
+    pub key: String,
+    pub name: String,
+    pub version: u64,
+    pub hash: String,
+    /// The style as the webview gave it.
+    pub style: Value,
+}
+
+/// A library file.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Library {
+    pub id: String,
+    pub name: String,
+    /// Bumped by every publish that changes anything.
+    pub version: u64,
+    pub published_at: u64,
+    pub components: Vec<LibraryComponent>,
+    pub styles: Vec<LibraryStyle>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Subscription {
+    pub library_id: String,
+    pub name: String,
+    pub path: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Origin {
+    library_id: String,
+    key: String,
+    version: u64,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct PublishOptions {
+    /// Defaults to the name already published, or the file's name.
+    pub name: Option<String>,
+    /// The components to publish; by default every component made in the
+    /// document, leaving out copies from other libraries.
+    pub component_ids: Option<Vec<String>>,
+    /// The shared styles to publish, each with an `id` and `name`.
+    pub styles: Vec<Value>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublishResult {
+    pub library_id: String,
+    pub version: u64,
+    /// Names of the components and styles new to the library.
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryItem {
+    pub key: String,
+    pub name: String,
+    pub version: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibrarySummary {
+    pub id: String,
+    pub name: String,
+    pub path: String,
+    pub version: u64,
+    pub components: Vec<LibraryItem>,
+    pub styles: Vec<LibraryItem>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstanceUpdate {
+    pub instance_id: String,
+    pub name: String,
+    /// Changed properties the instance takes on.
+    pub applied: Vec<String>,
+    /// Changed properties the instance overrides, so keeps its own value of.
+    pub kept: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ComponentUpdate {
+    /// The copy in this document.
+    pub component_id: String,
+    pub key: String,
+    pub name: String,
+    pub from_version: u64,
+    pub to_version: u64,
+    /// Changes to the component's own properties.
+    pub changes: Vec<PropertyChange>,
+    /// Whether anything under the component changed.
+    pub children_changed: bool,
+    pub instances: Vec<InstanceUpdate>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryUpdates {
+    pub library_id: String,
+    pub name: String,
+    pub path: String,
+    pub version: u64,
+    pub components: Vec<ComponentUpdate>,
+    /// Copies whose component the library no longer has.
+    pub removed: Vec<String>,
+    /// Newer versions of the styles asked about.
+    pub styles: Vec<LibraryStyle>,
+    /// Why the library couldn't be read, when it couldn't.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+pub fn read_library(path: &str) -> Result<Library, String> {
+    let bytes = fs::read(path).map_err(|e| format!("Failed to read library {}: {}", path, e))?;
+    serde_json::from_slice(&bytes).map_err(|e| format!("Invalid library {}: {}", path, e))
+}
+
+fn subscriptions(document: &Document) -> Vec<Subscription> {
+    let root = document.get(document.root_id());
+    let listed = root.and_then(|root| root.extra.get(SUBSCRIPTIONS_PROP));
+    listed.and_then(|list| serde_json::from_value(list.clone()).ok()).unwrap_or_default()
+}
+
+fn subscription(document: &Document, library_id: &str) -> Result<Subscription, String> {
+    let found = subscriptions(document).into_iter().find(|sub| sub.library_id == library_id);
+    found.ok_or_else(|| format!("Not subscribed to library {}", library_id))
+}
+
+fn origin(node: &NodeData) -> Option<Origin> {
+    serde_json::from_value(node.extra.get(ORIGIN_PROP)?.clone()).ok()
+}
+
+/// A node's properties as compared between a copy and its library, left
+/// without placement and origin if it is the component itself.
+fn content(node: &NodeData, is_root: bool) -> Map<String, Value> {
+    let mut properties = own_properties(node);
+    if is_root {
+        for key in PLACEMENT_PROPS.iter().chain(&[ORIGIN_PROP]) {
+            properties.remove(*key);
+        }
+    }
+    properties
+}
+
+/// What under a component's root makes it what it is, ids aside: each
+/// node's depth and properties, in tree order.
+fn shape<'a>(nodes: impl Iterator<Item = &'a NodeData>, depths: impl Fn(&NodeData) -> usize) -> Vec<(usize, Map<String, Value>)> {
+    nodes.map(|node| (depths(node), content(node, false))).collect()
+}
+
+fn serialized_depth(nodes: &[SerializedNode], id: &str) -> usize {
+    let mut depth = 0;
+    let mut at = nodes.iter().find(|node| node.id == id);
+    while let Some(parent) = at.and_then(|node| node.parent_id.as_deref()) {
+        depth += 1;
+        at = nodes.iter().find(|node| node.id == parent);
+    }
+    depth
+}
+
+fn library_shape(component: &LibraryComponent) -> Vec<(usize, Map<String, Value>)> {
+    shape(component.nodes.iter().skip(1).map(|node| &node.data), |node| serialized_depth(&component.nodes, &node.id))
+}
+
+fn copy_shape(document: &Document, id: &str) -> Vec<(usize, Map<String, Value>)> {
+    let depth = |node: &NodeData| {
+        let mut depth = 0;
+        let mut at = node.parent_id.as_deref();
+        while let Some(parent) = at.filter(|parent| *parent != id) {
+            depth += 1;
+            at = document.get(parent).and_then(|node| node.parent_id.as_deref());
+        }
+        depth + 1
+    };
+    shape(document.subtree(id).into_iter().skip(1), depth)
+}
+
+/// The component `id` as published: its subtree with the root detached.
+fn component_nodes(document: &Document, id: &str) -> Vec<SerializedNode> {
+    let mut nodes: Vec<SerializedNode> = document
+        .subtree(id)
+        .into_iter()
+        .map(|node| {
+            let siblings = node.parent_id.as_deref().map(|parent| document.children(parent)).unwrap_or_default();
+            SerializedNode {
+                id: node.id.clone(),
+                parent_id: node.parent_id.clone(),
+                child_index: siblings.iter().position(|sibling| *sibling == node.id).unwrap_or_default(),
+                data: node.clone(),
+            }
+        })
+        .collect();
+    if let Some(root) = nodes.first_mut() {
+        root.parent_id = None;
+        root.data.parent_id = None;
+        root.child_index = 0;
+    }
+    nodes
+}
+
+fn component_hash(document: &Document, id: &str) -> String {
+    let mut content: Vec<Map<String, Value>> = vec![document.get(id).map(|root| content(root, true)).unwrap_or_default()];
+    content.extend(copy_shape(document, id).into_iter().map(|(depth, mut properties)| {
+        properties.insert("depth".to_string(), Value::from(depth));
+        properties
+    }));
+    hex_digest(&serde_json::to_vec(&content).unwrap_or_default())
+}
+
+/// The new version of an item published before as `previous`.
+fn next_version(previous: Option<(u64, &str)>, hash: &str) -> u64 {
+    match previous {
+        Some((version, old)) if old == hash => version,
+        Some((version, _)) => version + 1,
+        None => 1,
+    }
+}
+
+/// `library` brought up to the components of `document` and `styles`,
+/// with what changed.
+fn publish(document: &Document, library: &mut Library, options: PublishOptions) -> Result<PublishResult, String> {
+    let ids = match options.component_ids {
+        Some(ids) => ids,
+        None => document
+            .subtree(document.root_id())
+            .into_iter()
+            .filter(|node| node.node_type == NodeType::Component && origin(node).is_none())
+            .map(|node| node.id.clone())
+            .collect(),
+    };
+    let (mut added, mut updated, mut removed) = (Vec::new(), Vec::new(), Vec::new());
+
+    let mut components = Vec::with_capacity(ids.len());
+    for id in ids {
+        let node = document.get(&id).filter(|node| node.node_type == NodeType::Component);
+        let node = node.ok_or_else(|| format!("Not a component: {}", id))?;
+        let hash = component_hash(document, &id);
+        let previous = library.components.iter().find(|component| component.key == id);
+        let version = next_version(previous.map(|p| (p.version, p.hash.as_str())), &hash);
+        match previous {
+            None => added.push(node.name.clone()),
+            Some(previous) if previous.version != version => updated.push(node.name.clone()),
+            Some(_) => {}
+        }
+        components.push(LibraryComponent { key: id.clone(), name: node.name.clone(), version, hash, nodes: component_nodes(document, &id) });
+    }
+    removed.extend(library.components.iter().filter(|old| !components.iter().any(|c| c.key == old.key)).map(|old| old.name.clone()));
+
+    let mut styles = Vec::with_capacity(options.styles.len());
+    for style in options.styles {
+        let key = style.get("id").and_then(Value::as_str).ok_or("Every style needs an id")?.to_string();
+        let name = style.get("name").and_then(Value::as_str).unwrap_or_default().to_string();
+        let hash = hex_digest(style.to_string().as_bytes());
+        let previous = library.styles.iter().find(|old| old.key == key);
+        let version = next_version(previous.map(|p| (p.version, p.hash.as_str())), &hash);
+        match previous {
+            None => added.push(name.clone()),
+            Some(previous) if previous.version != version => updated.push(name.clone()),
+            Some(_) => {}
+        }
+        styles.push(LibraryStyle { key, name, version, hash, style });
+    }
+    removed.extend(library.styles.iter().filter(|old| !styles.iter().any(|s| s.key == old.key)).map(|old| old.name.clone()));
+
+    let renamed = options.name.as_ref().is_some_and(|name| *name != library.name);
+    if !added.is_empty() || !updated.is_empty() || !removed.is_empty() || renamed || library.version == 0 {
+        library.version += 1;
+        library.published_at = now_millis();
+    }
+    if let Some(name) = options.name {
+        library.name = name;
+    }
+    library.components = components;
+    library.styles = styles;
+    Ok(PublishResult { library_id: library.id.clone(), version: library.version, added, updated, removed })
+}
+
+/// Whether an override of `path` covers a change to `property`, or the
+/// other way round; both are dotted.
+fn overlaps(path: &str, property: &str) -> bool {
+    let nested = |outer: &str, inner: &str| inner.strip_prefix(outer).is_some_and(|rest| rest.starts_with('.'));
+    path == property || nested(path, property) || nested(property, path)
+}
+
+fn component_update(document: &Document, copy: &NodeData, from_version: u64, component: &LibraryComponent) -> ComponentUpdate {
+    let mut changes = Vec::new();
+    let published = component.nodes.first().map(|root| content(&root.data, true)).unwrap_or_default();
+    property_changes(&content(copy, true), &published, "", &mut changes);
+
+    let instances = instances_of(document, &copy.id)
+        .into_iter()
+        .map(|instance| {
+            let overridden: Vec<String> = instance_overrides(instance).into_iter().map(|o| o.path.join(".")).collect();
+            let (kept, applied) = changes
+                .iter()
+                .map(|change| change.property.clone())
+                .partition(|property| overridden.iter().any(|path| overlaps(path, property)));
+            InstanceUpdate { instance_id: instance.id.clone(), name: instance.name.clone(), applied, kept }
+        })
+        .collect();
+
+    ComponentUpdate {
+        component_id: copy.id.clone(),
+        key: component.key.clone(),
+        name: component.name.clone(),
+        from_version,
+        to_version: component.version,
+        changes,
+        children_changed: copy_shape(document, &copy.id) != library_shape(component),
+        instances,
+    }
+}
+
+/// Copies of `library`'s components in `document` with a newer version
+/// published, and those it no longer has.
+fn pending(document: &Document, library: &Library) -> (Vec<(ComponentUpdate, LibraryComponent)>, Vec<String>) {
+    let (mut updates, mut removed) = (Vec::new(), Vec::new());
+    for copy in document.subtree(document.root_id()) {
+        let Some(origin) = origin(copy).filter(|origin| origin.library_id == library.id) else { continue };
+        match library.components.iter().find(|component| component.key == origin.key) {
+            Some(component) if component.version > origin.version => {
+                updates.push((component_update(document, copy, origin.version, component), component.clone()));
+            }
+            Some(_) => {}
+            None => removed.push(copy.id.clone()),
+        }
+    }
+    (updates, removed)
+}
+
+/// Operations inserting `nodes` under `parent_id`, with fresh ids; the
+/// first node goes at `index` and the rest under it as they were.
+fn insert_ops(nodes: &[SerializedNode], parent_id: &str, index: Option<usize>, root_id: String) -> Vec<DocumentOp> {
+    let mut ids: HashMap<&str, String> = HashMap::new();
+    let mut ops = Vec::with_capacity(nodes.len());
+    for (i, node) in nodes.iter().enumerate() {
+        let id = if i == 0 { root_id.clone() } else { generate_node_id() };
+        ids.insert(&node.id, id.clone());
+        let parent = match node.parent_id.as_deref().and_then(|parent| ids.get(parent)) {
+            Some(parent) => parent.clone(),
+            None => parent_id.to_string(),
+        };
+        let mut data = node.data.clone();
+        data.id = id;
+        ops.push(DocumentOp::Insert { node: Box::new(data), parent_id: parent, index: if i == 0 { index } else { None } });
+    }
+    ops
+}
+
+fn set_subscriptions(store: &DocumentStore, app: &AppHandle, document_root: String, subscriptions: Vec<Subscription>, label: &str) -> Result<(), String> {
+    let mut props = Map::new();
+    props.insert(SUBSCRIPTIONS_PROP.to_string(), serde_json::to_value(subscriptions).map_err(|e| e.to_string())?);
+    store.change(app, vec![DocumentOp::SetProps { id: document_root, props }], Some(label.to_string()))?;
+    Ok(())
+}
+
+/// Publish components and styles of the open document to the library file
+/// at `path`, creating it if needed. Items whose content is unchanged keep
+/// their version, so subscribers are only told about real changes.
+#[command(async)]
+pub fn publish_library(store: DocumentHandle, path: String, options: Option<PublishOptions>) -> Result<PublishResult, String> {
+    let options = options.unwrap_or_default();
+    let mut library = match fs::metadata(&path) {
+        Ok(_) => read_library(&path)?,
+        Err(_) => Library {
+            id: generate_node_id(),
+            name: Path::new(&path).file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default(),
+            version: 0,
+            published_at: 0,
+            components: Vec::new(),
+            styles: Vec::new(),
+        },
+    };
+    let before = library.version;
+    let result = {
+        let state = store.lock();
+        let open = state.as_ref().ok_or("No document is open")?;
+        publish(&open.document, &mut library, options)?
+    };
+    if library.version != before {
+        let bytes = serde_json::to_vec(&library).map_err(|e| e.to_string())?;
+        write_atomic(&path, &bytes)?;
+    }
+    Ok(result)
+}
+
+/// Subscribe the open document to the library at `path`, so its
+/// components can be brought in and updates to them are found.
+#[command(async)]
+pub fn subscribe_library(app: AppHandle, store: DocumentHandle, path: String) -> Result<LibrarySummary, String> {
+    let library = read_library(&path)?;
+    let (root, mut subs) = {
+        let state = store.lock();
+        let open = state.as_ref().ok_or("No document is open")?;
+        (open.document.root_id().to_string(), subscriptions(&open.document))
+    };
+    subs.retain(|sub| sub.library_id != library.id);
+    subs.push(Subscription { library_id: library.id.clone(), name: library.name.clone(), path: path.clone() });
+    set_subscriptions(&store, &app, root, subs, "Subscribe to library")?;
+
+    let item = |key: &String, name: &String, version: u64| LibraryItem { key: key.clone(), name: name.clone(), version };
+    Ok(LibrarySummary {
+        components: library.components.iter().map(|c| item(&c.key, &c.name, c.version)).collect(),
+        styles: library.styles.iter().map(|s| item(&s.key, &s.name, s.version)).collect(),
+        id: library.id,
+        name: library.name,
+        path,
+        version: library.version,
+    })
+}
+
+/// Stop following a library. Components already brought in stay as they
+/// are.
+#[command]
+pub fn unsubscribe_library(app: AppHandle, store: DocumentHandle, library_id: String) -> Result<(), String> {
+    let (root, mut subs) = {
+        let state = store.lock();
+        let open = state.as_ref().ok_or("No document is open")?;
+        (open.document.root_id().to_string(), subscriptions(&open.document))
+    };
+    let count = subs.len();
+    subs.retain(|sub| sub.library_id != library_id);
+    if subs.len() == count {
+        return Err(format!("Not subscribed to library {}", library_id));
+    }
+    set_subscriptions(&store, &app, root, subs, "Unsubscribe from library")
+}
+
+#[command]
+pub fn list_library_subscriptions(store: DocumentHandle) -> Result<Vec<Subscription>, String> {
+    let state = store.lock();
+    let open = state.as_ref().ok_or("No document is open")?;
+    Ok(subscriptions(&open.document))
+}
+
+/// Bring a copy of component `key` from a subscribed library into the open
+/// document under `parent_id`, returning the copy's id for instances to
+/// point at.
+#[command(async)]
+pub fn import_library_component(
+    app: AppHandle,
+    store: DocumentHandle,
+    library_id: String,
+    key: String,
+    parent_id: String,
+    index: Option<usize>,
+) -> Result<String, String> {
+    let sub = {
+        let state = store.lock();
+        let open = state.as_ref().ok_or("No document is open")?;
+        subscription(&open.document, &library_id)?
+    };
+    let library = read_library(&sub.path)?;
+    let component = library.components.iter().find(|component| component.key == key);
+    let component = component.ok_or_else(|| format!("Library {} has no component {}", library.name, key))?;
+
+    let id = generate_node_id();
+    let mut ops = insert_ops(&component.nodes, &parent_id, index, id.clone());
+    if let Some(DocumentOp::Insert { node, .. }) = ops.first_mut() {
+        let origin = Origin { library_id, key, version: component.version };
+        node.extra.insert(ORIGIN_PROP.to_string(), serde_json::to_value(origin).map_err(|e| e.to_string())?);
+    }
+    store.change(&app, ops, Some(format!("Insert {}", component.name)))?;
+    Ok(id)
+}
+
+/// Look through the subscribed libraries for newer versions of the
+/// components brought in from them, with what each update would change on
+/// each instance. `style_versions` gives the version of each library style
+/// the webview holds, by key, to be told of newer ones.
+#[command(async)]
+pub fn check_library_updates(store: DocumentHandle, style_versions: Option<HashMap<String, u64>>) -> Result<Vec<LibraryUpdates>, String> {
+    let style_versions = style_versions.unwrap_or_default();
+    let state = store.lock();
+    let open = state.as_ref().ok_or("No document is open")?;
+    let mut found = Vec::new();
+    for sub in subscriptions(&open.document) {
+        let mut updates = LibraryUpdates {
+            library_id: sub.library_id.clone(),
+            name: sub.name.clone(),
+            path: sub.path.clone(),
+            version: 0,
+            components: Vec::new(),
+            removed: Vec::new(),
+            styles: Vec::new(),
+            error: None,
+        };
+        match read_library(&sub.path) {
+            Ok(library) => {
+                let (components, removed) = pending(&open.document, &library);
+                updates.version = library.version;
+                updates.components = components.into_iter().map(|(update, _)| update).collect();
+                updates.removed = removed;
+                updates.styles = library
+                    .styles
+                    .into_iter()
+                    .filter(|style| style_versions.get(&style.key).is_some_and(|&held| style.version > held))
+                    .collect();
+            }
+            Err(e) => updates.error = Some(e),
+        }
+        found.push(updates);
+    }
+    Ok(found)
+}
+
+/// Bring component copies up to their library's latest version, as one
+/// undoable change: all pending updates, or those of one library or of the
+/// given copies. Instances keep their overrides.
+#[command(async)]
+pub fn apply_library_updates(
+    app: AppHandle,
+    store: DocumentHandle,
+    library_id: Option<String>,
+    component_ids: Option<Vec<String>>,
+) -> Result<Vec<ComponentUpdate>, String> {
+    let mut applied = Vec::new();
+    let mut ops = Vec::new();
+    {
+        let state = store.lock();
+        let open = state.as_ref().ok_or("No document is open")?;
+        let document = &open.document;
+        let subs = subscriptions(document).into_iter().filter(|sub| library_id.as_ref().is_none_or(|id| *id == sub.library_id));
+        for sub in subs {
+            let library = read_library(&sub.path)?;
+            let (updates, _) = pending(document, &library);
+            for (update, component) in updates {
+                if component_ids.as_ref().is_some_and(|ids| !ids.contains(&update.component_id)) {
+                    continue;
+                }
+                let Some(copy) = document.get(&update.component_id) else { continue };
+
+                let mut props: Map<String, Value> = Map::new();
+                let published = component.nodes.first().map(|root| content(&root.data, true)).unwrap_or_default();
+                for (key, _) in content(copy, true) {
+                    props.insert(key, Value::Null);
+                }
+                props.extend(published);
+                let origin = Origin { library_id: library.id.clone(), key: component.key.clone(), version: component.version };
+                props.insert(ORIGIN_PROP.to_string(), serde_json::to_value(origin).map_err(|e| e.to_string())?);
+                ops.push(DocumentOp::SetProps { id: copy.id.clone(), props });
+
+                if update.children_changed {
+                    ops.extend(copy.child_ids.iter().map(|kid| DocumentOp::Delete { id: kid.clone() }));
+                    // The root is already in place; only what is under it is inserted
+                    let inserts = insert_ops(&component.nodes, &copy.id, None, copy.id.clone());
+                    ops.extend(inserts.into_iter().skip(1));
+                }
+                applied.push(update);
+            }
+        }
+    }
+    if !ops.is_empty() {
+        store.change(&app, ops, Some("Update library components".to_string()))?;
+    }
+    Ok(applied)
+}
@@ -0,0 +1,175 @@
+//! Images linked by path instead of embedded, as photographers and print
+//! designers place them: the document stays small and the image is edited
+//! where it lives. A link is an `imageRef` that is an absolute path, in a
+//! node's own `imageRef` or in an image paint. Moving a folder of photos
+//! breaks every link into it at once, so relinking replaces a directory
+//! prefix across the whole document.
+
+use super::overrides::set_path;
+use super::store::{DocumentHandle, DocumentOp};
+use crate::model::NodeData;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use tauri::{command, AppHandle};
+
+/// Node properties holding paints, whose image paints may be links.
+const PAINT_PROPS: [&str; 2] = ["fills", "strokes"];
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageLink {
+    pub path: String,
+    /// Nodes drawing the image.
+    pub node_ids: Vec<String>,
+    pub missing: bool,
+    pub size: Option<u64>,
+    /// When the file was last changed, in milliseconds since the epoch.
+    pub modified: Option<u64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RelinkOptions {
+    /// Leave links that still resolve alone, and only move broken ones.
+    pub missing_only: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelinkResult {
+    /// Links pointed at their new place.
+    pub relinked: usize,
+    /// Links under the old prefix with no file at the new place, which are
+    /// left as they were.
+    pub unresolved: Vec<String>,
+}
+
+/// The file an `imageRef` links to, if it is a link rather than a data
+/// URL, an asset id or a path inside a bundle.
+pub fn linked_path(image_ref: &str) -> Option<&Path> {
+    let path = Path::new(image_ref);
+    path.is_absolute().then_some(path)
+}
+
+/// Every `imageRef` of `node`, with where it is in its properties.
+fn image_refs(node: &NodeData) -> Vec<(Vec<String>, String)> {
+    let mut refs = Vec::new();
+    if let Some(image_ref) = &node.image_ref {
+        refs.push((vec!["imageRef".to_string()], image_ref.clone()));
+    }
+    let Ok(Value::Object(props)) = serde_json::to_value(node) else { return refs };
+    for key in PAINT_PROPS {
+        let Some(Value::Array(paints)) = props.get(key) else { continue };
+        for (i, paint) in paints.iter().enumerate() {
+            if let Some(image_ref) = paint.get("imageRef").and_then(Value::as_str) {
+                refs.push((vec![key.to_string(), i.to_string(), "imageRef".to_string()], image_ref.to_string()));
+            }
+        }
+    }
+    refs
+}
+
+fn file_link(path: String, node_ids: Vec<String>) -> ImageLink {
+    let found = fs::metadata(&path).ok().filter(|meta| meta.is_file());
+    let modified = found.as_ref().and_then(|meta| meta.modified().ok());
+    ImageLink {
+        missing: found.is_none(),
+        size: found.as_ref().map(|meta| meta.len()),
+        modified: modified.and_then(|time| time.duration_since(UNIX_EPOCH).ok()).map(|since| since.as_millis() as u64),
+        node_ids,
+        path,
+    }
+}
+
+/// `path` moved from under `from` to under `to`, if it is under `from`.
+/// Prefixes are matched by whole path components, so `/photos` doesn't
+/// take in `/photos-old`.
+fn remap(path: &str, from: &str, to: &str) -> Option<String> {
+    let rest = Path::new(path).strip_prefix(from).ok()?;
+    let moved = if rest.as_os_str().is_empty() { Path::new(to).to_path_buf() } else { Path::new(to).join(rest) };
+    Some(moved.to_string_lossy().into_owned())
+}
+
+/// The images the open document links to, broken ones first, for checking
+/// a document once it is opened.
+#[command(async)]
+pub fn check_image_links(store: DocumentHandle) -> Result<Vec<ImageLink>, String> {
+    let state = store.lock();
+    let open = state.as_ref().ok_or("No document is open")?;
+    let document = &open.document;
+    let mut users: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for node in document.subtree(document.root_id()) {
+        for (_, image_ref) in image_refs(node) {
+            if linked_path(&image_ref).is_some() {
+                let ids = users.entry(image_ref).or_default();
+                if !ids.contains(&node.id) {
+                    ids.push(node.id.clone());
+                }
+            }
+        }
+    }
+    drop(state);
+
+    let mut links: Vec<ImageLink> = users.into_iter().map(|(path, node_ids)| file_link(path, node_ids)).collect();
+    links.sort_by_key(|link| !link.missing);
+    Ok(links)
+}
+
+/// Point every link under directory `from` at the same place under `to`,
+/// as one undoable change. A single file can be relinked by giving its
+/// old and new paths.
+#[command(async)]
+pub fn relink_images(
+    app: AppHandle,
+    store: DocumentHandle,
+    from: String,
+    to: String,
+    options: Option<RelinkOptions>,
+) -> Result<RelinkResult, String> {
+    let options = options.unwrap_or_default();
+    if linked_path(&from).is_none() || linked_path(&to).is_none() {
+        return Err("Links are relinked between absolute paths".to_string());
+    }
+    let mut ops = Vec::new();
+    let mut result = RelinkResult { relinked: 0, unresolved: Vec::new() };
+    {
+        let state = store.lock();
+        let open = state.as_ref().ok_or("No document is open")?;
+        let document = &open.document;
+        for node in document.subtree(document.root_id()) {
+            let mut props = Value::Object(Map::new());
+            let mut changed = false;
+            for (path, image_ref) in image_refs(node) {
+                let Some(moved) = linked_path(&image_ref).and_then(|_| remap(&image_ref, &from, &to)) else { continue };
+                if options.missing_only && Path::new(&image_ref).is_file() {
+                    continue;
+                }
+                if !Path::new(&moved).is_file() {
+                    if !result.unresolved.contains(&image_ref) {
+                        result.unresolved.push(image_ref);
+                    }
+                    continue;
+                }
+                // Whole top-level properties are set, paints and all
+                if props.get(&path[0]).is_none() {
+                    let current = serde_json::to_value(node).ok().and_then(|value| value.get(&path[0]).cloned());
+                    props[&path[0]] = current.unwrap_or(Value::Null);
+                }
+                set_path(&mut props, &path, Value::String(moved))?;
+                changed = true;
+                result.relinked += 1;
+            }
+            if let (true, Value::Object(props)) = (changed, props) {
+                ops.push(DocumentOp::SetProps { id: node.id.clone(), props });
+            }
+        }
+    }
+    if !ops.is_empty() {
+        store.change(&app, ops, Some("Relink images".to_string()))?;
+    }
+    Ok(result)
+}
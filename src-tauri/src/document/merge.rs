@@ -0,0 +1,372 @@
+//! Three-way merging of design files edited apart, as git merges text: what
+//! only one side changed since the common base is taken from that side, and
+//! what both changed differently is a conflict. Properties merge one by
+//! one, nested objects down to their fields, and a node's parent as one
+//! more property; sibling order follows ours, with what theirs added or
+//! moved in placed after the sibling it follows there.
+//!
+//! Conflicts are resolved by calling again with a side for each conflict
+//! id. Until then the merged document takes ours, and keeps a node one
+//! side removed and the other changed.
+
+use super::diff::{load_document_file, own_properties};
+use super::store::Document;
+use crate::model::{NodeData, SerializedNode};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use tauri::command;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Ours,
+    Theirs,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictKind {
+    /// Both sides set a property differently.
+    Property,
+    /// Both sides moved the node to different parents.
+    Parent,
+    /// One side removed a node the other changed or added children to.
+    Removed,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeConflict {
+    /// The key to pass a side for in `resolutions`.
+    pub id: String,
+    pub kind: ConflictKind,
+    pub node_id: String,
+    pub name: String,
+    /// For property conflicts, dotted within nested objects.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub property: Option<String>,
+    /// Each side's value, unset where the property or node is missing. For
+    /// parent conflicts, the parent ids.
+    pub base: Option<Value>,
+    pub ours: Option<Value>,
+    pub theirs: Option<Value>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct MergeOptions {
+    /// Used for whichever of the three files are encrypted.
+    pub passphrase: Option<String>,
+    /// A side for each conflict id from an earlier merge of the same files.
+    pub resolutions: HashMap<String, Side>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeResult {
+    /// The merged document, with conflicts left unresolved settled as the
+    /// module describes.
+    pub document: String,
+    /// Conflicts `resolutions` didn't settle.
+    pub conflicts: Vec<MergeConflict>,
+    /// Changes taken from theirs without conflict.
+    pub merged: usize,
+}
+
+/// A node as the merge leaves it.
+struct Merged {
+    parent: Option<String>,
+    properties: Map<String, Value>,
+}
+
+struct Merger<'a> {
+    base: &'a Document,
+    ours: &'a Document,
+    theirs: &'a Document,
+    resolutions: &'a HashMap<String, Side>,
+    conflicts: Vec<MergeConflict>,
+    merged: usize,
+}
+
+fn parent_value(node: Option<&NodeData>) -> Option<Value> {
+    node.map(|node| node.parent_id.clone().map_or(Value::Null, Value::String))
+}
+
+impl Merger<'_> {
+    /// The side to take for `conflict`, recording it if it isn't resolved.
+    fn conflict(&mut self, conflict: MergeConflict) -> Side {
+        match self.resolutions.get(&conflict.id) {
+            Some(&side) => side,
+            None => {
+                self.conflicts.push(conflict);
+                Side::Ours
+            }
+        }
+    }
+
+    fn name(&self, id: &str) -> String {
+        [self.ours, self.theirs, self.base].iter().find_map(|doc| doc.get(id)).map(|node| node.name.clone()).unwrap_or_default()
+    }
+
+    /// The three-way merge of one property of `id`, unset if it ends up
+    /// removed.
+    fn merge_value(&mut self, id: &str, property: String, base: Option<&Value>, ours: Option<&Value>, theirs: Option<&Value>) -> Option<Value> {
+        if ours == theirs || theirs == base {
+            return ours.cloned();
+        }
+        if ours == base {
+            self.merged += 1;
+            return theirs.cloned();
+        }
+        if let (Some(Value::Object(a)), Some(Value::Object(b))) = (ours, theirs) {
+            let empty = Map::new();
+            let base = match base {
+                Some(Value::Object(base)) => base,
+                _ => &empty,
+            };
+            return Some(Value::Object(self.merge_map(id, &format!("{}.", property), base, a, b)));
+        }
+        let conflict = MergeConflict {
+            id: format!("{}/{}", id, property),
+            kind: ConflictKind::Property,
+            node_id: id.to_string(),
+            name: self.name(id),
+            property: Some(property),
+            base: base.cloned(),
+            ours: ours.cloned(),
+            theirs: theirs.cloned(),
+        };
+        match self.conflict(conflict) {
+            Side::Ours => ours.cloned(),
+            Side::Theirs => theirs.cloned(),
+        }
+    }
+
+    fn merge_map(&mut self, id: &str, prefix: &str, base: &Map<String, Value>, ours: &Map<String, Value>, theirs: &Map<String, Value>) -> Map<String, Value> {
+        let mut keys: Vec<&String> = ours.keys().chain(theirs.keys()).chain(base.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        let mut merged = Map::new();
+        for key in keys {
+            let property = format!("{}{}", prefix, key);
+            if let Some(value) = self.merge_value(id, property, base.get(key), ours.get(key), theirs.get(key)) {
+                merged.insert(key.clone(), value);
+            }
+        }
+        merged
+    }
+
+    /// `id` as present on both sides, and as in the base if it was there.
+    fn merge_node(&mut self, id: &str, base: Option<&NodeData>, ours: &NodeData, theirs: &NodeData) -> Merged {
+        let base_properties = base.map(own_properties).unwrap_or_default();
+        let properties = self.merge_map(id, "", &base_properties, &own_properties(ours), &own_properties(theirs));
+
+        let base_parent = base.map(|node| &node.parent_id);
+        let parent = if ours.parent_id == theirs.parent_id || base_parent == Some(&theirs.parent_id) {
+            ours.parent_id.clone()
+        } else if base_parent == Some(&ours.parent_id) {
+            self.merged += 1;
+            theirs.parent_id.clone()
+        } else {
+            let conflict = MergeConflict {
+                id: format!("{}/parentId", id),
+                kind: ConflictKind::Parent,
+                node_id: id.to_string(),
+                name: ours.name.clone(),
+                property: None,
+                base: parent_value(base),
+                ours: parent_value(Some(ours)),
+                theirs: parent_value(Some(theirs)),
+            };
+            match self.conflict(conflict) {
+                Side::Ours => ours.parent_id.clone(),
+                Side::Theirs => theirs.parent_id.clone(),
+            }
+        };
+        Merged { parent, properties }
+    }
+
+    /// The side to take for `id`, removed on one side and kept on `kept`.
+    fn removed_conflict(&mut self, id: &str, kept: Side) -> Side {
+        let value = |doc: &Document| doc.get(id).and_then(|node| serde_json::to_value(node).ok());
+        let conflict = MergeConflict {
+            id: id.to_string(),
+            kind: ConflictKind::Removed,
+            node_id: id.to_string(),
+            name: self.name(id),
+            property: None,
+            base: value(self.base),
+            ours: value(self.ours),
+            theirs: value(self.theirs),
+        };
+        // Unresolved, the node is kept rather than lose the change
+        let resolved = self.resolutions.contains_key(id);
+        let side = self.conflict(conflict);
+        if resolved { side } else { kept }
+    }
+}
+
+fn changed(base: &NodeData, node: &NodeData) -> bool {
+    base.parent_id != node.parent_id || own_properties(base) != own_properties(node)
+}
+
+fn kept(node: &NodeData) -> Merged {
+    Merged { parent: node.parent_id.clone(), properties: own_properties(node) }
+}
+
+/// The children of `parent` in the merged tree: ours' order, with the rest
+/// after the sibling they follow in theirs. When ours left the order as it
+/// was, theirs' order leads instead.
+fn order_children(merger: &Merger, parent: &str, members: &HashSet<&str>) -> Vec<String> {
+    let listed = |doc: &Document| -> Vec<String> { doc.children(parent).iter().filter(|id| members.contains(id.as_str())).cloned().collect() };
+    let (ours, theirs, base) = (listed(merger.ours), listed(merger.theirs), listed(merger.base));
+    let common = |a: &[String], b: &[String]| -> Vec<String> { a.iter().filter(|id| b.contains(id)).cloned().collect() };
+    let (lead, follow) = if common(&ours, &base) == common(&base, &ours) { (theirs, ours) } else { (ours, theirs) };
+
+    let mut order = lead;
+    let mut previous: Option<String> = None;
+    for id in follow {
+        if !order.contains(&id) {
+            let at = previous.as_ref().and_then(|before| order.iter().position(|other| other == before)).map_or(0, |i| i + 1);
+            order.insert(at, id.clone());
+        }
+        previous = Some(id);
+    }
+    // Whatever neither side lists here came in from elsewhere
+    let mut rest: Vec<&&str> = members.iter().filter(|id| !order.iter().any(|listed| listed == **id)).collect();
+    rest.sort();
+    order.extend(rest.into_iter().map(|id| id.to_string()));
+    order
+}
+
+/// Merge `theirs` into `ours`, both changed from `base`.
+pub fn merge(base: &Document, ours: &Document, theirs: &Document, resolutions: &HashMap<String, Side>) -> Result<MergeResult, String> {
+    let mut merger = Merger { base, ours, theirs, resolutions, conflicts: Vec::new(), merged: 0 };
+    let mut nodes: HashMap<String, Merged> = HashMap::new();
+
+    let mut ids: Vec<&str> = Vec::new();
+    let mut listed = HashSet::new();
+    for doc in [ours, theirs, base] {
+        ids.extend(doc.subtree(doc.root_id()).into_iter().map(|node| node.id.as_str()).filter(|id| listed.insert(*id)));
+    }
+    for id in ids {
+        let merged = match (base.get(id), ours.get(id), theirs.get(id)) {
+            (b, Some(o), Some(t)) => Some(merger.merge_node(id, b, o, t)),
+            (Some(b), Some(o), None) if changed(b, o) => match merger.removed_conflict(id, Side::Ours) {
+                Side::Ours => Some(kept(o)),
+                Side::Theirs => None,
+            },
+            (Some(b), None, Some(t)) if changed(b, t) => match merger.removed_conflict(id, Side::Theirs) {
+                Side::Ours => None,
+                Side::Theirs => Some(kept(t)),
+            },
+            (Some(_), Some(_), None) => {
+                merger.merged += 1;
+                None
+            }
+            (None, Some(o), None) => Some(kept(o)),
+            (None, None, Some(t)) => {
+                merger.merged += 1;
+                Some(kept(t))
+            }
+            _ => None,
+        };
+        if let Some(node) = merged {
+            nodes.insert(id.to_string(), node);
+        }
+    }
+
+    // A node kept under one the other side removed brings it back, unless
+    // its removal is what was chosen, which takes the node and everything
+    // under it too. Each parent is decided once, so nothing comes back
+    // after it has gone
+    let mut decided: HashMap<String, bool> = HashMap::new();
+    loop {
+        let orphan = nodes.iter().find_map(|(id, node)| {
+            let parent = node.parent.as_ref().filter(|parent| !nodes.contains_key(*parent))?;
+            Some((id.clone(), parent.clone()))
+        });
+        let Some((orphan, parent)) = orphan else { break };
+        if let Entry::Vacant(decision) = decided.entry(parent.clone()) {
+            let keeper = [(Side::Ours, ours), (Side::Theirs, theirs)].into_iter().find_map(|(side, doc)| Some((side, doc.get(&parent)?)));
+            let keep = match keeper {
+                Some((side, node)) if merger.removed_conflict(&parent, side) == side => {
+                    nodes.insert(parent.clone(), kept(node));
+                    true
+                }
+                _ => false,
+            };
+            decision.insert(keep);
+            if keep {
+                continue;
+            }
+        }
+        let mut removing = vec![orphan];
+        while let Some(id) = removing.pop() {
+            nodes.remove(&id);
+            removing.extend(nodes.iter().filter(|(_, node)| node.parent.as_ref() == Some(&id)).map(|(child, _)| child.clone()));
+            decided.insert(id, false);
+        }
+    }
+
+    // A node moved under its own descendant on the other side goes back to
+    // its parent in the base
+    let ids: Vec<String> = nodes.keys().cloned().collect();
+    for id in ids {
+        let mut chain = HashSet::new();
+        let mut at = id.clone();
+        while let Some(parent) = nodes.get(&at).and_then(|node| node.parent.clone()) {
+            if !chain.insert(at.clone()) {
+                let fallback = base.get(&id).and_then(|node| node.parent_id.clone()).filter(|p| nodes.contains_key(p));
+                if let Some(node) = nodes.get_mut(&id) {
+                    node.parent = fallback.or_else(|| Some(ours.root_id().to_string()));
+                }
+                break;
+            }
+            at = parent;
+        }
+    }
+
+    let mut children: HashMap<&str, HashSet<&str>> = HashMap::new();
+    for (id, node) in &nodes {
+        if let Some(parent) = &node.parent {
+            children.entry(parent.as_str()).or_default().insert(id.as_str());
+        }
+    }
+    let mut serialized = Vec::with_capacity(nodes.len());
+    let mut indexes: HashMap<String, usize> = HashMap::new();
+    for (parent, members) in &children {
+        for (i, id) in order_children(&merger, parent, members).into_iter().enumerate() {
+            indexes.insert(id, i);
+        }
+    }
+    for (id, node) in &nodes {
+        let mut data = node.properties.clone();
+        data.insert("id".to_string(), Value::String(id.clone()));
+        let data: NodeData = serde_json::from_value(Value::Object(data)).map_err(|e| format!("Merged node {} is invalid: {}", id, e))?;
+        serialized.push(SerializedNode {
+            id: id.clone(),
+            parent_id: node.parent.clone(),
+            child_index: indexes.get(id).copied().unwrap_or_default(),
+            data,
+        });
+    }
+
+    let document = ours.with_nodes(serialized)?;
+    let document = serde_json::to_string(&document.to_serialized()).map_err(|e| e.to_string())?;
+    Ok(MergeResult { document, conflicts: merger.conflicts, merged: merger.merged })
+}
+
+/// Merge the design file at `theirs` into the one at `ours`, both changed
+/// from `base`, as for a merge of branches under version control.
+#[command(async)]
+pub fn merge_documents(base: String, ours: String, theirs: String, options: Option<MergeOptions>) -> Result<MergeResult, String> {
+    let options = options.unwrap_or_default();
+    let passphrase = options.passphrase.as_deref();
+    let base = load_document_file(&base, passphrase)?;
+    let ours = load_document_file(&ours, passphrase)?;
+    let theirs = load_document_file(&theirs, passphrase)?;
+    merge(&base, &ours, &theirs, &options.resolutions)
+}
@@ -0,0 +1,120 @@
+//! Bringing documents written in an older layout up to `FORMAT_VERSION`.
+//! Migrations work on the JSON before it is read into the model, one version
+//! at a time, so each only has to know the layout just before its own.
+
+use crate::model::{SerializedDocument, FORMAT_VERSION};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tauri::command;
+
+struct Migration {
+    /// What the migration changes, for the report.
+    description: &'static str,
+    apply: fn(&mut Map<String, Value>),
+}
+
+/// Every migration, oldest first: the one at index `n` takes a document
+/// from version `n` to `n + 1`. A change to the layout bumps
+/// `FORMAT_VERSION` and adds its migration at the end.
+const MIGRATIONS: &[Migration] = &[Migration {
+    description: "Constraints named after sides become MIN, MAX and STRETCH",
+    apply: constraint_names,
+}];
+
+const _: () = assert!(MIGRATIONS.len() == FORMAT_VERSION as usize);
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppliedMigration {
+    pub from_version: u32,
+    pub to_version: u32,
+    pub description: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationReport {
+    /// The version the document was written in.
+    pub from_version: u32,
+    pub to_version: u32,
+    /// Oldest first; empty when the document was already current.
+    pub applied: Vec<AppliedMigration>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigratedDocument {
+    pub document: String,
+    pub migrations: MigrationReport,
+}
+
+/// The `data` of every node.
+fn node_data(doc: &mut Map<String, Value>) -> impl Iterator<Item = &mut Map<String, Value>> {
+    let nodes = doc.get_mut("nodes").and_then(Value::as_array_mut).map(|nodes| nodes.iter_mut());
+    nodes.into_iter().flatten().filter_map(|node| node.get_mut("data")?.as_object_mut())
+}
+
+/// Version 0 to 1: constraints imported as Figma writes them, which the
+/// editor doesn't recognize.
+fn constraint_names(doc: &mut Map<String, Value>) {
+    for data in node_data(doc) {
+        let Some(constraints) = data.get_mut("constraints").and_then(Value::as_object_mut) else { continue };
+        for axis in ["horizontal", "vertical"] {
+            let Some(constraint) = constraints.get_mut(axis) else { continue };
+            let renamed = match constraint.as_str() {
+                Some("LEFT" | "TOP") => "MIN",
+                Some("RIGHT" | "BOTTOM") => "MAX",
+                Some("LEFT_RIGHT" | "TOP_BOTTOM") => "STRETCH",
+                _ => continue,
+            };
+            *constraint = Value::String(renamed.to_string());
+        }
+    }
+}
+
+/// Run the migrations `doc` is missing, marking it with the current version.
+pub fn migrate(doc: &mut Value) -> Result<MigrationReport, String> {
+    let fields = doc.as_object_mut().ok_or("Invalid document: not an object")?;
+    let from_version = match fields.get("formatVersion") {
+        None | Some(Value::Null) => 0,
+        Some(version) => version
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| format!("Invalid document: format version {} is not a whole number", version))?,
+    };
+    if from_version > FORMAT_VERSION {
+        return Err(format!(
+            "Document format version {} is newer than supported version {}",
+            from_version, FORMAT_VERSION
+        ));
+    }
+
+    let mut applied = Vec::new();
+    for (from, migration) in (from_version..).zip(&MIGRATIONS[from_version as usize..]) {
+        (migration.apply)(fields);
+        applied.push(AppliedMigration {
+            from_version: from,
+            to_version: from + 1,
+            description: migration.description.to_string(),
+        });
+    }
+    fields.insert("formatVersion".to_string(), Value::from(FORMAT_VERSION));
+    Ok(MigrationReport { from_version, to_version: FORMAT_VERSION, applied })
+}
+
+/// The document in `json`, migrated to the current version.
+pub fn read_document(json: &[u8]) -> Result<(SerializedDocument, MigrationReport), String> {
+    let mut doc: Value = serde_json::from_slice(json).map_err(|e| format!("Invalid document: {}", e))?;
+    let report = migrate(&mut doc)?;
+    let doc = serde_json::from_value(doc).map_err(|e| format!("Invalid document: {}", e))?;
+    Ok((doc, report))
+}
+
+/// Bring a document's JSON up to the current format version, for documents
+/// the webview reads itself. Properties the backend doesn't model are kept.
+#[command(async)]
+pub fn migrate_document(document_json: String) -> Result<MigratedDocument, String> {
+    let mut doc: Value = serde_json::from_str(&document_json).map_err(|e| format!("Invalid document: {}", e))?;
+    let migrations = migrate(&mut doc)?;
+    Ok(MigratedDocument { document: doc.to_string(), migrations })
+}
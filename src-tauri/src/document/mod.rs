@@ -0,0 +1,16 @@
+//! The document model kept in the backend: the scene graph the webview
+//! edits through commands.
+
+pub mod crdt;
+pub mod deltas;
+pub mod diff;
+pub mod libraries;
+pub mod links;
+pub mod merge;
+pub mod migrate;
+pub mod overrides;
+pub mod search;
+pub mod stats;
+pub mod store;
+pub mod undo;
+pub mod validate;
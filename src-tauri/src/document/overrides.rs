@@ -0,0 +1,305 @@
+//! Component instances and their overrides. An instance holds only what it
+//! changes from its main component, as `overrides`: a path into the
+//! component's properties and the value there, as `PropertyOverride` in
+//! `src/scene/nodes/base-node.ts`. Everything else is read through to the
+//! main component, so editing the main component reaches every instance
+//! that hasn't overridden the same property.
+//!
+//! Overrides are kept sparse: setting one to the main component's value,
+//! or pushing one to the main component, drops it.
+
+use super::store::{Document, DocumentHandle, DocumentOp};
+use crate::model::{NodeData, NodeType};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tauri::{command, AppHandle};
+
+const OVERRIDES_PROP: &str = "overrides";
+
+/// Properties an instance has of its own rather than from its main
+/// component: what it is, where it sits and what it points at.
+const INSTANCE_PROPS: [&str; 12] = [
+    "id",
+    "type",
+    "name",
+    "visible",
+    "locked",
+    "parentId",
+    "childIds",
+    "x",
+    "y",
+    "rotation",
+    "componentId",
+    OVERRIDES_PROP,
+];
+
+/// Properties of a main component that describe it as published or
+/// placed, not what its instances look like.
+const MAIN_ONLY_PROPS: [&str; 2] = ["libraryComponent", "propertyDefinitions"];
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PropertyOverride {
+    /// Keys into the main component's serialized properties, and indexes
+    /// into arrays, such as `["fills", "0", "color"]`.
+    pub path: Vec<String>,
+    pub value: Value,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OverrideInfo {
+    pub path: Vec<String>,
+    pub value: Value,
+    /// What the instance would have without the override; unset where the
+    /// main component has nothing at the path.
+    pub main_value: Option<Value>,
+}
+
+pub fn instance_overrides(instance: &NodeData) -> Vec<PropertyOverride> {
+    let listed = instance.extra.get(OVERRIDES_PROP);
+    listed.and_then(|list| serde_json::from_value(list.clone()).ok()).unwrap_or_default()
+}
+
+/// The instances in `document` of the component `main_id`.
+pub fn instances_of<'a>(document: &'a Document, main_id: &str) -> Vec<&'a NodeData> {
+    let nodes = document.subtree(document.root_id()).into_iter();
+    nodes.filter(|node| node.node_type == NodeType::Instance && main_component_id(node) == Some(main_id)).collect()
+}
+
+fn main_component_id(instance: &NodeData) -> Option<&str> {
+    instance.extra.get("componentId").and_then(Value::as_str)
+}
+
+fn instance(document: &Document, id: &str) -> Result<(NodeData, NodeData), String> {
+    let instance = document.get(id).filter(|node| node.node_type == NodeType::Instance);
+    let instance = instance.ok_or_else(|| format!("Not an instance: {}", id))?;
+    let main_id = main_component_id(instance).ok_or_else(|| format!("Instance {} has no main component", id))?;
+    let main = document.get(main_id).filter(|node| node.node_type == NodeType::Component);
+    let main = main.ok_or_else(|| format!("Main component {} of instance {} is missing", main_id, id))?;
+    Ok((instance.clone(), main.clone()))
+}
+
+fn properties(node: &NodeData) -> Map<String, Value> {
+    match serde_json::to_value(node) {
+        Ok(Value::Object(fields)) => fields,
+        _ => Map::new(),
+    }
+}
+
+pub fn get_path<'a>(value: &'a Value, path: &[String]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |at, segment| match at {
+        Value::Object(fields) => fields.get(segment),
+        Value::Array(items) => items.get(segment.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+/// Set `path` of `value` to `new`, making objects on the way where there
+/// is nothing.
+pub fn set_path(value: &mut Value, path: &[String], new: Value) -> Result<(), String> {
+    let Some((last, parents)) = path.split_last() else {
+        *value = new;
+        return Ok(());
+    };
+    let mut at = value;
+    for segment in parents {
+        if at.is_null() {
+            *at = Value::Object(Map::new());
+        }
+        at = match at {
+            Value::Object(fields) => fields.entry(segment.clone()).or_insert(Value::Null),
+            Value::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get_mut(i)).ok_or_else(|| format!("No item {} at {}", segment, path.join(".")))?,
+            _ => return Err(format!("Can't set {}: {} is not an object", path.join("."), segment)),
+        };
+    }
+    if at.is_null() {
+        *at = Value::Object(Map::new());
+    }
+    match at {
+        Value::Object(fields) => {
+            fields.insert(last.clone(), new);
+        }
+        Value::Array(items) => {
+            let item = last.parse::<usize>().ok().and_then(|i| items.get_mut(i)).ok_or_else(|| format!("No item {} at {}", last, path.join(".")))?;
+            *item = new;
+        }
+        _ => return Err(format!("Can't set {}", path.join("."))),
+    }
+    Ok(())
+}
+
+/// Whether `path` is `prefix` or under it.
+fn starts_with(path: &[String], prefix: &[String]) -> bool {
+    path.len() >= prefix.len() && path[..prefix.len()] == *prefix
+}
+
+/// Whether a path can be overridden: not empty, and not into what the
+/// instance has of its own.
+fn check_path(path: &[String]) -> Result<(), String> {
+    match path.first() {
+        None => Err("An override needs a path".to_string()),
+        Some(key) if INSTANCE_PROPS.contains(&key.as_str()) || MAIN_ONLY_PROPS.contains(&key.as_str()) => {
+            Err(format!("{} can't be overridden", key))
+        }
+        Some(_) => Ok(()),
+    }
+}
+
+/// `overrides` without those that match the main component anyway.
+fn sparse(main: &Value, overrides: Vec<PropertyOverride>) -> Vec<PropertyOverride> {
+    let differs = |o: &PropertyOverride| match get_path(main, &o.path) {
+        Some(value) => *value != o.value,
+        None => !o.value.is_null(),
+    };
+    overrides.into_iter().filter(differs).collect()
+}
+
+/// The properties of `instance` as it is seen: the main component's, with
+/// the overrides on top and the instance's own properties kept.
+pub fn resolve(instance: &NodeData, main: &NodeData) -> Result<NodeData, String> {
+    let mut resolved = Value::Object(properties(main));
+    if let Value::Object(fields) = &mut resolved {
+        for key in INSTANCE_PROPS.iter().chain(&MAIN_ONLY_PROPS) {
+            fields.remove(*key);
+        }
+    }
+    for o in instance_overrides(instance) {
+        set_path(&mut resolved, &o.path, o.value)?;
+    }
+    if let Value::Object(fields) = &mut resolved {
+        let own = properties(instance);
+        fields.extend(own.into_iter().filter(|(key, _)| INSTANCE_PROPS.contains(&key.as_str())));
+    }
+    serde_json::from_value(resolved).map_err(|e| format!("Instance {} resolves to invalid properties: {}", instance.id, e))
+}
+
+fn set_overrides(id: &str, overrides: &[PropertyOverride]) -> DocumentOp {
+    let value = if overrides.is_empty() { Value::Null } else { serde_json::to_value(overrides).unwrap_or_default() };
+    let mut props = Map::new();
+    props.insert(OVERRIDES_PROP.to_string(), value);
+    DocumentOp::SetProps { id: id.to_string(), props }
+}
+
+/// The overrides of an instance, with what the main component has in
+/// their place.
+#[command]
+pub fn list_instance_overrides(store: DocumentHandle, instance_id: String) -> Result<Vec<OverrideInfo>, String> {
+    let state = store.lock();
+    let open = state.as_ref().ok_or("No document is open")?;
+    let (instance, main) = self::instance(&open.document, &instance_id)?;
+    let main = Value::Object(properties(&main));
+    let listed = instance_overrides(&instance).into_iter().map(|o| OverrideInfo { main_value: get_path(&main, &o.path).cloned(), path: o.path, value: o.value });
+    Ok(listed.collect())
+}
+
+/// An instance's properties as drawn: its main component's with its
+/// overrides applied.
+#[command]
+pub fn resolve_instance(store: DocumentHandle, instance_id: String) -> Result<NodeData, String> {
+    let state = store.lock();
+    let open = state.as_ref().ok_or("No document is open")?;
+    let (instance, main) = self::instance(&open.document, &instance_id)?;
+    resolve(&instance, &main)
+}
+
+/// Override properties of an instance. Each override replaces any at the
+/// same path or under it; one that matches the main component is dropped
+/// instead of stored. Returns the instance's overrides after.
+#[command]
+pub fn set_instance_overrides(
+    app: AppHandle,
+    store: DocumentHandle,
+    instance_id: String,
+    overrides: Vec<PropertyOverride>,
+) -> Result<Vec<PropertyOverride>, String> {
+    let (instance, main) = {
+        let state = store.lock();
+        let open = state.as_ref().ok_or("No document is open")?;
+        self::instance(&open.document, &instance_id)?
+    };
+    let mut stored = instance_overrides(&instance);
+    for o in overrides {
+        check_path(&o.path)?;
+        stored.retain(|old| !starts_with(&old.path, &o.path));
+        stored.push(o);
+    }
+    let stored = sparse(&Value::Object(properties(&main)), stored);
+    store.change(&app, vec![set_overrides(&instance_id, &stored)], Some("Override instance".to_string()))?;
+    Ok(stored)
+}
+
+/// Drop an instance's overrides at `paths` and under them, or all of them,
+/// so it follows its main component again. Returns how many were dropped.
+#[command]
+pub fn reset_instance_overrides(
+    app: AppHandle,
+    store: DocumentHandle,
+    instance_id: String,
+    paths: Option<Vec<Vec<String>>>,
+) -> Result<usize, String> {
+    let instance = {
+        let state = store.lock();
+        let open = state.as_ref().ok_or("No document is open")?;
+        self::instance(&open.document, &instance_id)?.0
+    };
+    let mut stored = instance_overrides(&instance);
+    let count = stored.len();
+    match &paths {
+        Some(paths) => stored.retain(|o| !paths.iter().any(|path| starts_with(&o.path, path))),
+        None => stored.clear(),
+    }
+    if stored.len() != count {
+        store.change(&app, vec![set_overrides(&instance_id, &stored)], Some("Reset instance".to_string()))?;
+    }
+    Ok(count - stored.len())
+}
+
+/// Make an instance's overrides at `paths`, or all of them, part of its
+/// main component, for every instance that doesn't override them itself.
+/// Overrides left matching the new main component are dropped from all
+/// its instances. Returns how many overrides were pushed.
+#[command]
+pub fn push_overrides_to_main(
+    app: AppHandle,
+    store: DocumentHandle,
+    instance_id: String,
+    paths: Option<Vec<Vec<String>>>,
+) -> Result<usize, String> {
+    let mut ops = Vec::new();
+    let pushed = {
+        let state = store.lock();
+        let open = state.as_ref().ok_or("No document is open")?;
+        let document = &open.document;
+        let (instance, main) = self::instance(document, &instance_id)?;
+
+        let selected = |o: &PropertyOverride| paths.as_ref().is_none_or(|paths| paths.iter().any(|path| starts_with(&o.path, path)));
+        let pushed: Vec<PropertyOverride> = instance_overrides(&instance).into_iter().filter(selected).collect();
+        if pushed.is_empty() {
+            return Ok(0);
+        }
+
+        let mut updated = Value::Object(properties(&main));
+        for o in &pushed {
+            set_path(&mut updated, &o.path, o.value.clone())?;
+        }
+        // Whole top-level properties are set, as the main component sees them
+        let mut changed = Map::new();
+        for key in pushed.iter().filter_map(|o| o.path.first()) {
+            changed.insert(key.clone(), updated.get(key).cloned().unwrap_or(Value::Null));
+        }
+        ops.push(DocumentOp::SetProps { id: main.id.clone(), props: changed });
+
+        // The pushing instance's own overrides now match and go with the rest
+        for other in instances_of(document, &main.id) {
+            let stored = instance_overrides(other);
+            let kept = sparse(&updated, stored.clone());
+            if kept != stored {
+                ops.push(set_overrides(&other.id, &kept));
+            }
+        }
+        pushed.len()
+    };
+    store.change(&app, ops, Some("Push overrides to main".to_string()))?;
+    Ok(pushed)
+}
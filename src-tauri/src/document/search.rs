@@ -0,0 +1,169 @@
+//! Finding text, layer names and style names across the open document.
+//! Ranges are in UTF-16 code units, as the webview indexes strings, so they
+//! line up with `textStyles` ranges and selection offsets.
+
+use super::store::DocumentHandle;
+use crate::model::NodeData;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tauri::command;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchField {
+    /// The characters of text nodes.
+    Text,
+    /// Layer names.
+    Name,
+    /// Names of the shared styles a node uses.
+    Style,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SearchOptions {
+    /// Treat the query as a regular expression rather than plain text.
+    pub regex: bool,
+    pub match_case: bool,
+    pub whole_word: bool,
+    /// Where to look; everywhere when unset.
+    pub fields: Option<Vec<SearchField>>,
+    /// Shared style names by style id. Nodes only hold the ids, in their
+    /// `styleReferences`, so style names can't be searched without these.
+    pub style_names: HashMap<String, String>,
+    /// Search only this node and the nodes under it.
+    pub within: Option<String>,
+    /// Stop after this many matches.
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchMatch {
+    pub node_id: String,
+    pub field: SearchField,
+    /// For style matches, which of the node's styles matched, such as
+    /// `fill` or `text`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub style: Option<String>,
+    /// For style matches, the id of the style that matched.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub style_id: Option<String>,
+    /// Where the query matched, within the text, name or style name.
+    pub ranges: Vec<MatchRange>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResults {
+    /// Matches in tree order.
+    pub matches: Vec<SearchMatch>,
+    /// Whether the search stopped at the limit with more left to find.
+    pub truncated: bool,
+}
+
+fn build_pattern(query: &str, options: &SearchOptions) -> Result<Regex, String> {
+    if query.is_empty() {
+        return Err("Search query is empty".to_string());
+    }
+    let pattern = if options.regex { query.to_string() } else { regex::escape(query) };
+    let pattern = if options.whole_word { format!(r"\b(?:{})\b", pattern) } else { pattern };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(!options.match_case)
+        .build()
+        .map_err(|e| format!("Invalid search pattern: {}", e))
+}
+
+/// Where `pattern` matches `text`, in UTF-16 code units. Empty matches are
+/// skipped; they mark no text to highlight.
+fn find_ranges(pattern: &Regex, text: &str) -> Vec<MatchRange> {
+    let mut ranges = Vec::new();
+    let (mut byte, mut unit) = (0, 0);
+    let mut advance = |to: usize| {
+        unit += text[byte..to].encode_utf16().count();
+        byte = to;
+        unit
+    };
+    for found in pattern.find_iter(text).filter(|m| !m.is_empty()) {
+        let start = advance(found.start());
+        let end = advance(found.end());
+        ranges.push(MatchRange { start, end });
+    }
+    ranges
+}
+
+/// Everything in `node` that `pattern` matches, field by field.
+fn search_node(node: &NodeData, pattern: &Regex, fields: &[SearchField], options: &SearchOptions) -> Vec<SearchMatch> {
+    let found = |field, style: Option<(&str, &str)>, ranges: Vec<MatchRange>| SearchMatch {
+        node_id: node.id.clone(),
+        field,
+        style: style.map(|(kind, _)| kind.to_string()),
+        style_id: style.map(|(_, id)| id.to_string()),
+        ranges,
+    };
+    let mut matches = Vec::new();
+    for &field in fields {
+        match field {
+            SearchField::Text => {
+                let ranges = node.characters.as_deref().map(|text| find_ranges(pattern, text)).unwrap_or_default();
+                if !ranges.is_empty() {
+                    matches.push(found(field, None, ranges));
+                }
+            }
+            SearchField::Name => {
+                let ranges = find_ranges(pattern, &node.name);
+                if !ranges.is_empty() {
+                    matches.push(found(field, None, ranges));
+                }
+            }
+            SearchField::Style => {
+                let Some(references) = node.extra.get("styleReferences").and_then(|r| r.as_object()) else { continue };
+                for (kind, id) in references {
+                    let Some(id) = id.as_str() else { continue };
+                    let Some(name) = options.style_names.get(id) else { continue };
+                    let ranges = find_ranges(pattern, name);
+                    if !ranges.is_empty() {
+                        matches.push(found(field, Some((kind, id)), ranges));
+                    }
+                }
+            }
+        }
+    }
+    matches
+}
+
+/// Search the open document for `query`, as plain text or, with `regex`, a
+/// regular expression. Case is ignored unless `matchCase` is set.
+#[command(async)]
+pub fn search_document(store: DocumentHandle, query: String, options: Option<SearchOptions>) -> Result<SearchResults, String> {
+    let options = options.unwrap_or_default();
+    let pattern = build_pattern(&query, &options)?;
+    let fields = options.fields.clone().unwrap_or_else(|| vec![SearchField::Text, SearchField::Name, SearchField::Style]);
+    let limit = options.limit.unwrap_or(usize::MAX);
+
+    let state = store.lock();
+    let open = state.as_ref().ok_or("No document is open")?;
+    let document = &open.document;
+    let within = options.within.as_deref().unwrap_or(document.root_id());
+    if document.get(within).is_none() {
+        return Err(format!("Node not found: {}", within));
+    }
+
+    let mut matches = Vec::new();
+    for node in document.subtree(within) {
+        matches.extend(search_node(node, &pattern, &fields, &options));
+        if matches.len() > limit {
+            matches.truncate(limit);
+            return Ok(SearchResults { matches, truncated: true });
+        }
+    }
+    Ok(SearchResults { matches, truncated: false })
+}
@@ -0,0 +1,221 @@
+//! What the open document is made of, for telling why a file is slow to
+//! work with or large on disk.
+
+use super::store::{Document, DocumentHandle};
+use crate::bundle::{load_bundle_manifest, AssetKind};
+use crate::model::{NodeData, NodeType, Paint};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use tauri::command;
+
+/// Nodes nested deeper than this are reported; layout and hit testing walk
+/// every level.
+const DEEP_NESTING: usize = 24;
+
+/// Entries in each of the largest-of lists.
+const TOP_COUNT: usize = 10;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypeStats {
+    pub node_type: NodeType,
+    pub count: usize,
+    /// Serialized size of these nodes, as a measure of their footprint.
+    pub bytes: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeSize {
+    pub id: String,
+    pub name: String,
+    pub node_type: NodeType,
+    pub bytes: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssetStats {
+    pub path: String,
+    pub kind: AssetKind,
+    pub size: u64,
+    /// Nodes whose fills or image refer to the asset.
+    pub used_by: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FontUsage {
+    pub family: String,
+    /// Weights in use, lightest first.
+    pub weights: Vec<u16>,
+    pub text_nodes: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NestingWarning {
+    /// The outermost node past `DEEP_NESTING` levels on its branch.
+    pub id: String,
+    pub name: String,
+    /// The deepest level under it.
+    pub max_depth: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentStats {
+    pub node_count: usize,
+    /// Most numerous first.
+    pub types: Vec<TypeStats>,
+    /// Serialized size of the whole document, the bulk of what it keeps in
+    /// memory and of its file before compression.
+    pub document_bytes: u64,
+    pub largest_nodes: Vec<NodeSize>,
+    /// Assets of the bundle the document was opened from, largest first.
+    /// Empty when no bundle is given.
+    pub largest_assets: Vec<AssetStats>,
+    pub asset_bytes: u64,
+    pub fonts: Vec<FontUsage>,
+    pub max_depth: usize,
+    pub deep_nesting: Vec<NestingWarning>,
+}
+
+fn node_bytes(node: &NodeData) -> u64 {
+    serde_json::to_vec(node).map(|bytes| bytes.len() as u64).unwrap_or_default()
+}
+
+fn image_refs(node: &NodeData) -> Vec<&str> {
+    let paints = node.fills.iter().chain(&node.strokes).flatten();
+    let from_paints = paints.filter_map(|paint| match paint {
+        Paint::Image { image_ref, .. } => Some(image_ref.as_str()),
+        _ => None,
+    });
+    node.image_ref.as_deref().into_iter().chain(from_paints).collect()
+}
+
+/// Depth of every node below the root, and the warnings for branches that
+/// go too deep.
+fn nesting(document: &Document) -> (usize, Vec<NestingWarning>) {
+    let mut depths: HashMap<&str, usize> = HashMap::new();
+    let mut max_depth = 0;
+    // The deepest level under each node, filled in bottom up
+    let mut deepest: HashMap<&str, usize> = HashMap::new();
+    let order = document.subtree(document.root_id());
+    for node in &order {
+        let depth = node.parent_id.as_deref().and_then(|p| depths.get(p)).map_or(0, |d| d + 1);
+        depths.insert(&node.id, depth);
+        max_depth = max_depth.max(depth);
+    }
+    for node in order.iter().rev() {
+        let below = document.children(&node.id).iter().filter_map(|kid| deepest.get(kid.as_str())).max().copied();
+        deepest.insert(&node.id, below.unwrap_or(depths[node.id.as_str()]));
+    }
+
+    let warnings = order
+        .iter()
+        .filter(|node| depths[node.id.as_str()] == DEEP_NESTING + 1)
+        .map(|node| NestingWarning {
+            id: node.id.clone(),
+            name: node.name.clone(),
+            max_depth: deepest[node.id.as_str()],
+        })
+        .collect();
+    (max_depth, warnings)
+}
+
+/// Node counts and sizes, font usage and nesting of the open document, and
+/// with `bundle_path` the sizes of the assets in the bundle it came from.
+#[command]
+pub fn get_document_stats(store: DocumentHandle, bundle_path: Option<String>) -> Result<DocumentStats, String> {
+    let state = store.lock();
+    let open = state.as_ref().ok_or("No document is open")?;
+    let document = &open.document;
+    let nodes = document.subtree(document.root_id());
+
+    let mut types: Vec<TypeStats> = Vec::new();
+    let mut sizes = Vec::with_capacity(nodes.len());
+    let mut fonts: HashMap<&str, (Vec<u16>, usize)> = HashMap::new();
+    let mut asset_users: HashMap<&str, usize> = HashMap::new();
+    for node in &nodes {
+        let bytes = node_bytes(node);
+        match types.iter_mut().find(|t| t.node_type == node.node_type) {
+            Some(stats) => {
+                stats.count += 1;
+                stats.bytes += bytes;
+            }
+            None => types.push(TypeStats { node_type: node.node_type, count: 1, bytes }),
+        }
+        sizes.push((bytes, *node));
+
+        let mut families: Vec<&str> = Vec::new();
+        for style in node.text_styles.iter().flatten() {
+            let (weights, _) = fonts.entry(&style.font_family).or_default();
+            if !weights.contains(&style.font_weight) {
+                weights.push(style.font_weight);
+            }
+            if !families.contains(&style.font_family.as_str()) {
+                families.push(&style.font_family);
+            }
+        }
+        for family in families {
+            fonts.entry(family).or_default().1 += 1;
+        }
+
+        let mut refs = image_refs(node);
+        refs.sort_unstable();
+        refs.dedup();
+        for image in refs {
+            *asset_users.entry(image).or_default() += 1;
+        }
+    }
+    types.sort_by_key(|t| Reverse(t.count));
+    let document_bytes = serde_json::to_vec(&document.to_serialized()).map(|bytes| bytes.len() as u64).unwrap_or_default();
+
+    sizes.sort_by_key(|(bytes, _)| Reverse(*bytes));
+    let largest_nodes = sizes
+        .into_iter()
+        .take(TOP_COUNT)
+        .map(|(bytes, node)| NodeSize { id: node.id.clone(), name: node.name.clone(), node_type: node.node_type, bytes })
+        .collect();
+
+    let mut assets = match bundle_path {
+        Some(path) => load_bundle_manifest(&path)?.assets,
+        None => Vec::new(),
+    };
+    let asset_bytes = assets.iter().map(|asset| asset.size).sum();
+    assets.sort_by_key(|asset| Reverse(asset.size));
+    let largest_assets = assets
+        .into_iter()
+        .take(TOP_COUNT)
+        .map(|asset| AssetStats {
+            used_by: asset_users.get(asset.path.as_str()).copied().unwrap_or_default(),
+            path: asset.path,
+            kind: asset.kind,
+            size: asset.size,
+        })
+        .collect();
+
+    let mut fonts: Vec<FontUsage> = fonts
+        .into_iter()
+        .map(|(family, (mut weights, text_nodes))| {
+            weights.sort_unstable();
+            FontUsage { family: family.to_string(), weights, text_nodes }
+        })
+        .collect();
+    fonts.sort_by(|a, b| b.text_nodes.cmp(&a.text_nodes).then_with(|| a.family.cmp(&b.family)));
+
+    let (max_depth, deep_nesting) = nesting(document);
+    Ok(DocumentStats {
+        node_count: nodes.len(),
+        types,
+        document_bytes,
+        largest_nodes,
+        largest_assets,
+        asset_bytes,
+        fonts,
+        max_depth,
+        deep_nesting,
+    })
+}
@@ -0,0 +1,575 @@
+//! The open document, held in the backend as the source of truth. Nodes
+//! live in an arena indexed by id, and change only through operations:
+//! insert, set properties, reparent and delete. Every change is announced
+//! with a `document-changed` event carrying the operations applied, which
+//! the webview replays on its own copy of the scene.
+//!
+//! Each window has a document of its own. Commands take a
+//! `DocumentHandle`, the store of the window that called them, and changes
+//! are announced to that window only.
+
+use super::crdt::{new_client_id, Replica};
+use super::deltas::Snapshot;
+use super::migrate::read_document;
+use super::undo::History;
+use crate::model::{NodeData, SerializedDocument, SerializedNode, FORMAT_VERSION};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::ops::Deref;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tauri::ipc::{CommandArg, CommandItem, InvokeError};
+use tauri::{command, AppHandle, Emitter, Manager, Runtime};
+
+pub const DOCUMENT_CHANGED_EVENT: &str = "document-changed";
+
+/// Properties owned by the tree structure, changed only by reparenting.
+const STRUCTURAL_PROPS: [&str; 3] = ["id", "parentId", "childIds"];
+
+/// A change to the document.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE", rename_all_fields = "camelCase")]
+pub enum DocumentOp {
+    /// Add `node` under `parent_id` at `index`, or last. The node's own
+    /// parent and children are ignored; children are inserted after it.
+    Insert {
+        node: Box<NodeData>,
+        parent_id: String,
+        #[serde(default)]
+        index: Option<usize>,
+    },
+    /// Set properties by their serialized names. `null` unsets one.
+    SetProps { id: String, props: Map<String, Value> },
+    /// Move `id` under `parent_id` at `index`, or last, counted without it.
+    Reparent {
+        id: String,
+        parent_id: String,
+        #[serde(default)]
+        index: Option<usize>,
+    },
+    /// Remove `id` and everything under it.
+    Delete { id: String },
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentChangedEvent {
+    pub revision: u64,
+    pub ops: Vec<DocumentOp>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentInfo {
+    pub root_id: String,
+    pub node_count: usize,
+    pub revision: u64,
+}
+
+pub struct Document {
+    version: String,
+    name: String,
+    created_at: String,
+    updated_at: String,
+    root_id: String,
+    /// Node slots; deleted nodes leave a hole that the next insert reuses.
+    nodes: Vec<Option<NodeData>>,
+    slots: HashMap<String, usize>,
+    free: Vec<usize>,
+    /// Bumped by every change, so the webview can tell which it has seen.
+    revision: u64,
+}
+
+impl Document {
+    /// The document in `doc`. Nodes out of reach of the root are dropped,
+    /// and each node's parent and children are taken from the tree rather
+    /// than from its own data.
+    pub fn from_serialized(doc: SerializedDocument) -> Result<Self, String> {
+        let mut ordered: HashMap<String, Vec<(usize, String)>> = HashMap::new();
+        let mut data: HashMap<String, NodeData> = HashMap::with_capacity(doc.nodes.len());
+        for node in doc.nodes {
+            if let Some(parent) = node.parent_id {
+                ordered.entry(parent).or_default().push((node.child_index, node.id.clone()));
+            }
+            data.insert(node.id, node.data);
+        }
+        if !data.contains_key(&doc.root_id) {
+            return Err(format!("Invalid document: root {} is missing", doc.root_id));
+        }
+
+        let mut document = Document {
+            version: doc.version,
+            name: doc.name,
+            created_at: doc.created_at,
+            updated_at: doc.updated_at,
+            root_id: doc.root_id.clone(),
+            nodes: Vec::with_capacity(data.len()),
+            slots: HashMap::with_capacity(data.len()),
+            free: Vec::new(),
+            revision: 0,
+        };
+        let mut stack = vec![(doc.root_id, None)];
+        let mut seen = HashSet::new();
+        while let Some((id, parent)) = stack.pop() {
+            let Some(mut node) = data.remove(&id).filter(|_| seen.insert(id.clone())) else { continue };
+            let mut kids = ordered.remove(&id).unwrap_or_default();
+            kids.sort_by_key(|(index, _)| *index);
+            node.child_ids = kids.into_iter().map(|(_, kid)| kid).filter(|kid| data.contains_key(kid)).collect();
+            node.parent_id = parent;
+            stack.extend(node.child_ids.iter().rev().map(|kid| (kid.clone(), Some(id.clone()))));
+            document.slots.insert(id, document.nodes.len());
+            document.nodes.push(Some(node));
+        }
+        Ok(document)
+    }
+
+    /// The document in its file form, nodes in tree order.
+    pub fn to_serialized(&self) -> SerializedDocument {
+        let mut nodes = Vec::with_capacity(self.slots.len());
+        let mut stack = vec![(self.root_id.as_str(), 0)];
+        while let Some((id, child_index)) = stack.pop() {
+            let Some(node) = self.get(id) else { continue };
+            stack.extend(node.child_ids.iter().enumerate().rev().map(|(i, kid)| (kid.as_str(), i)));
+            nodes.push(SerializedNode { id: node.id.clone(), parent_id: node.parent_id.clone(), child_index, data: node.clone() });
+        }
+        SerializedDocument {
+            version: self.version.clone(),
+            format_version: FORMAT_VERSION,
+            name: self.name.clone(),
+            created_at: self.created_at.clone(),
+            updated_at: self.updated_at.clone(),
+            nodes,
+            root_id: self.root_id.clone(),
+        }
+    }
+
+    /// A document with the same name and dates holding `nodes` instead.
+    pub fn with_nodes(&self, nodes: Vec<SerializedNode>) -> Result<Document, String> {
+        Document::from_serialized(SerializedDocument {
+            version: self.version.clone(),
+            format_version: FORMAT_VERSION,
+            name: self.name.clone(),
+            created_at: self.created_at.clone(),
+            updated_at: self.updated_at.clone(),
+            nodes,
+            root_id: self.root_id.clone(),
+        })
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn root_id(&self) -> &str {
+        &self.root_id
+    }
+
+    /// `id` and the nodes under it, in tree order.
+    pub fn subtree(&self, id: &str) -> Vec<&NodeData> {
+        let mut found = Vec::new();
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            let Some(node) = self.get(id) else { continue };
+            stack.extend(node.child_ids.iter().rev().map(String::as_str));
+            found.push(node);
+        }
+        found
+    }
+
+    pub fn info(&self) -> DocumentInfo {
+        DocumentInfo { root_id: self.root_id.clone(), node_count: self.slots.len(), revision: self.revision }
+    }
+
+    pub fn get(&self, id: &str) -> Option<&NodeData> {
+        self.nodes.get(*self.slots.get(id)?)?.as_ref()
+    }
+
+    fn get_mut(&mut self, id: &str) -> Option<&mut NodeData> {
+        self.nodes.get_mut(*self.slots.get(id)?)?.as_mut()
+    }
+
+    pub fn children(&self, id: &str) -> &[String] {
+        self.get(id).map(|n| n.child_ids.as_slice()).unwrap_or(&[])
+    }
+
+    fn node(&self, id: &str) -> Result<&NodeData, String> {
+        self.get(id).ok_or_else(|| format!("Node not found: {}", id))
+    }
+
+    fn node_mut(&mut self, id: &str) -> Result<&mut NodeData, String> {
+        self.get_mut(id).ok_or_else(|| format!("Node not found: {}", id))
+    }
+
+    fn add(&mut self, node: NodeData) {
+        let id = node.id.clone();
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.nodes[slot] = Some(node);
+                slot
+            }
+            None => {
+                self.nodes.push(Some(node));
+                self.nodes.len() - 1
+            }
+        };
+        self.slots.insert(id, slot);
+    }
+
+    fn take(&mut self, id: &str) -> Option<NodeData> {
+        let slot = self.slots.remove(id)?;
+        self.free.push(slot);
+        self.nodes[slot].take()
+    }
+
+    /// Where to put a child so that it ends up at `index` of `count`.
+    fn position(index: Option<usize>, count: usize) -> Result<usize, String> {
+        match index {
+            Some(i) if i > count => Err(format!("Invalid index: {}", i)),
+            Some(i) => Ok(i),
+            None => Ok(count),
+        }
+    }
+
+    /// Apply `op`, returning the operations that undo it.
+    fn apply(&mut self, op: DocumentOp) -> Result<Vec<DocumentOp>, String> {
+        match op {
+            DocumentOp::Insert { mut node, parent_id, index } => {
+                if self.slots.contains_key(&node.id) {
+                    return Err(format!("Node already exists: {}", node.id));
+                }
+                let at = Self::position(index, self.node(&parent_id)?.child_ids.len())?;
+                let id = node.id.clone();
+                self.node_mut(&parent_id)?.child_ids.insert(at, id.clone());
+                node.parent_id = Some(parent_id);
+                node.child_ids.clear();
+                self.add(*node);
+                Ok(vec![DocumentOp::Delete { id }])
+            }
+            DocumentOp::SetProps { id, props } => {
+                if let Some(key) = props.keys().find(|k| STRUCTURAL_PROPS.contains(&k.as_str())) {
+                    return Err(format!("Property {} can't be set", key));
+                }
+                let node = self.node_mut(&id)?;
+                let mut value = serde_json::to_value(&*node).map_err(|e| e.to_string())?;
+                let Some(fields) = value.as_object_mut() else { return Err(format!("Node not found: {}", id)) };
+                let mut previous = Map::new();
+                for (key, value) in props {
+                    let old = if value.is_null() { fields.remove(&key) } else { fields.insert(key.clone(), value) };
+                    previous.insert(key, old.unwrap_or(Value::Null));
+                }
+                *node = serde_json::from_value(value).map_err(|e| format!("Invalid properties for {}: {}", id, e))?;
+                Ok(vec![DocumentOp::SetProps { id, props: previous }])
+            }
+            DocumentOp::Reparent { id, parent_id, index } => {
+                let old_parent = self.node(&id)?.parent_id.clone().ok_or("The root can't be moved")?;
+                // The new parent can't be the node or be inside it
+                let mut ancestor = Some(parent_id.as_str());
+                while let Some(a) = ancestor {
+                    if a == id {
+                        return Err(format!("Can't move {} into itself", id));
+                    }
+                    ancestor = self.node(a)?.parent_id.as_deref();
+                }
+                let old_index = self.node(&old_parent)?.child_ids.iter().position(|c| *c == id).unwrap_or(0);
+                let remaining = self.node(&parent_id)?.child_ids.len() - usize::from(parent_id == old_parent);
+                let at = Self::position(index, remaining)?;
+
+                self.node_mut(&old_parent)?.child_ids.retain(|c| *c != id);
+                self.node_mut(&parent_id)?.child_ids.insert(at, id.clone());
+                self.node_mut(&id)?.parent_id = Some(parent_id);
+                Ok(vec![DocumentOp::Reparent { id, parent_id: old_parent, index: Some(old_index) }])
+            }
+            DocumentOp::Delete { id } => {
+                let parent_id = self.node(&id)?.parent_id.clone().ok_or("The root can't be deleted")?;
+                let index = self.node(&parent_id)?.child_ids.iter().position(|c| *c == id).unwrap_or(0);
+                self.node_mut(&parent_id)?.child_ids.retain(|c| *c != id);
+
+                // Put back parents before their children, each in its place
+                let mut inverse = Vec::new();
+                let mut stack = vec![(id, parent_id, index)];
+                while let Some((id, parent_id, index)) = stack.pop() {
+                    let Some(mut node) = self.take(&id) else { continue };
+                    let kids = std::mem::take(&mut node.child_ids);
+                    stack.extend(kids.into_iter().enumerate().rev().map(|(i, kid)| (kid, id.clone(), i)));
+                    inverse.push(DocumentOp::Insert { node: Box::new(node), parent_id, index: Some(index) });
+                }
+                Ok(inverse)
+            }
+        }
+    }
+
+    /// Turn this document into `target` node by node, as one change,
+    /// returning the operations that did it.
+    pub fn sync_to(&mut self, target: &Document) -> Vec<DocumentOp> {
+        let order: Vec<String> = target.to_serialized().nodes.into_iter().map(|n| n.id).collect();
+        let mut ops = Vec::new();
+        let mut run = |document: &mut Document, op: DocumentOp| {
+            if document.apply(op.clone()).is_ok() {
+                ops.push(op);
+            }
+        };
+
+        // New nodes and moved ones, parents first so each has its parent
+        // in place, then whatever is gone
+        for id in &order {
+            let Some(node) = target.get(id) else { continue };
+            let Some(parent_id) = node.parent_id.clone() else { continue };
+            match self.get(id) {
+                None => run(self, DocumentOp::Insert { node: Box::new(node.clone()), parent_id, index: None }),
+                Some(current) if current.parent_id.as_ref() != Some(&parent_id) => {
+                    run(self, DocumentOp::Reparent { id: id.clone(), parent_id, index: None })
+                }
+                Some(_) => {}
+            }
+        }
+        let gone: Vec<String> = self.slots.keys().filter(|id| target.get(id).is_none()).cloned().collect();
+        for id in gone {
+            let parent_kept = self.get(&id).and_then(|n| n.parent_id.as_deref()).is_some_and(|p| target.get(p).is_some());
+            if parent_kept {
+                run(self, DocumentOp::Delete { id });
+            }
+        }
+
+        // Then the order of children, and properties
+        for id in &order {
+            for (i, kid) in target.children(id).iter().enumerate() {
+                if self.children(id).get(i) != Some(kid) {
+                    run(self, DocumentOp::Reparent { id: kid.clone(), parent_id: id.clone(), index: Some(i) });
+                }
+            }
+        }
+        for id in &order {
+            let (Some(current), Some(wanted)) = (self.get(id), target.get(id)) else { continue };
+            if current == wanted {
+                continue;
+            }
+            let (Ok(Value::Object(mut current)), Ok(Value::Object(wanted))) = (serde_json::to_value(current), serde_json::to_value(wanted)) else {
+                continue;
+            };
+            let mut props: Map<String, Value> = wanted.into_iter().filter(|(k, v)| current.remove(k).as_ref() != Some(v)).collect();
+            props.extend(current.into_iter().map(|(k, _)| (k, Value::Null)));
+            props.retain(|k, _| !STRUCTURAL_PROPS.contains(&k.as_str()));
+            if !props.is_empty() {
+                run(self, DocumentOp::SetProps { id: id.clone(), props });
+            }
+        }
+
+        if !ops.is_empty() {
+            self.revision += 1;
+        }
+        ops
+    }
+
+    /// Apply `ops` in order as one change, or none of them if any fails.
+    pub fn apply_all(&mut self, ops: Vec<DocumentOp>) -> Result<Vec<DocumentOp>, String> {
+        let mut undo: Vec<Vec<DocumentOp>> = Vec::with_capacity(ops.len());
+        for op in ops {
+            match self.apply(op) {
+                Ok(inverse) => undo.push(inverse),
+                Err(err) => {
+                    for op in undo.into_iter().rev().flatten() {
+                        let _ = self.apply(op);
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        self.revision += 1;
+        Ok(undo.into_iter().rev().flatten().collect())
+    }
+}
+
+/// The open document, with the replica its changes merge through and
+/// what is needed to save it and undo its changes.
+pub struct OpenDocument {
+    pub document: Document,
+    pub replica: Replica,
+    /// Operations since the last save, for the save log.
+    pub unsaved: Vec<DocumentOp>,
+    pub snapshot: Option<Snapshot>,
+    pub history: History,
+}
+
+impl OpenDocument {
+    pub fn new(document: Document, snapshot: Option<Snapshot>, history: History) -> Self {
+        let replica = Replica::new(&document, new_client_id());
+        OpenDocument { document, replica, unsaved: Vec::new(), snapshot, history }
+    }
+
+    /// Apply `ops` as one change, pass them on to the replica and the save
+    /// log and announce them to `window`, returning the operations that
+    /// undo them.
+    pub fn apply(&mut self, app: &AppHandle, window: &str, ops: Vec<DocumentOp>) -> Result<Vec<DocumentOp>, String> {
+        let inverse = self.document.apply_all(ops.clone())?;
+        for op in &ops {
+            self.replica.record(op);
+        }
+        self.unsaved.extend(ops.iter().cloned());
+        // Sent under the store's lock, so events arrive in revision order
+        announce(app, window, self.document.revision, ops);
+        Ok(inverse)
+    }
+}
+
+/// The document open in one window.
+pub struct DocumentStore {
+    state: Mutex<Option<OpenDocument>>,
+    /// Where undo history is kept between runs.
+    pub history_dir: PathBuf,
+    /// Label of the window the document is open in.
+    pub window: String,
+}
+
+impl DocumentStore {
+    pub fn new(history_dir: PathBuf, window: String) -> Self {
+        DocumentStore { state: Mutex::new(None), history_dir, window }
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, Option<OpenDocument>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Apply `ops` to the open document as one undoable change.
+    pub fn change(&self, app: &AppHandle, ops: Vec<DocumentOp>, label: Option<String>) -> Result<u64, String> {
+        let mut state = self.lock();
+        let open = state.as_mut().ok_or("No document is open")?;
+        let inverse = open.apply(app, &self.window, ops)?;
+        open.history.push(label, inverse);
+        Ok(open.document.revision)
+    }
+}
+
+/// The documents of all windows, managed as app state. A window's store is
+/// made the first time it is asked for and dropped when the window closes.
+pub struct DocumentStores {
+    stores: Mutex<HashMap<String, Arc<DocumentStore>>>,
+    history_dir: PathBuf,
+}
+
+impl DocumentStores {
+    pub fn new(history_dir: PathBuf) -> Self {
+        DocumentStores { stores: Mutex::new(HashMap::new()), history_dir }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Arc<DocumentStore>>> {
+        self.stores.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn for_window(&self, window: &str) -> Arc<DocumentStore> {
+        let mut stores = self.lock();
+        let store = stores
+            .entry(window.to_string())
+            .or_insert_with(|| Arc::new(DocumentStore::new(self.history_dir.clone(), window.to_string())));
+        Arc::clone(store)
+    }
+
+    /// Close the document of a window that is gone.
+    pub fn remove(&self, window: &str) {
+        self.lock().remove(window);
+    }
+}
+
+/// The store of the window a command was called from.
+pub struct DocumentHandle(Arc<DocumentStore>);
+
+impl Deref for DocumentHandle {
+    type Target = DocumentStore;
+
+    fn deref(&self) -> &DocumentStore {
+        &self.0
+    }
+}
+
+impl<'de, R: Runtime> CommandArg<'de, R> for DocumentHandle {
+    fn from_command(command: CommandItem<'de, R>) -> Result<Self, InvokeError> {
+        let webview = command.message.webview();
+        let store = webview.state::<DocumentStores>().for_window(webview.window().label());
+        Ok(DocumentHandle(store))
+    }
+}
+
+/// Tell `window` about `ops`, applied to reach `revision`.
+pub fn announce(app: &AppHandle, window: &str, revision: u64, ops: Vec<DocumentOp>) {
+    let _ = app.emit_to(window, DOCUMENT_CHANGED_EVENT, DocumentChangedEvent { revision, ops });
+}
+
+/// Make `document_json`, a serialized document, the open document.
+#[command]
+pub fn open_document(store: DocumentHandle, document_json: String) -> Result<DocumentInfo, String> {
+    let (doc, _) = read_document(document_json.as_bytes())?;
+    let document = Document::from_serialized(doc)?;
+    let info = document.info();
+    *store.lock() = Some(OpenDocument::new(document, None, History::default()));
+    Ok(info)
+}
+
+#[command]
+pub fn close_document(store: DocumentHandle) {
+    *store.lock() = None;
+}
+
+/// The open document, serialized for saving.
+#[command]
+pub fn get_document(store: DocumentHandle) -> Result<String, String> {
+    let state = store.lock();
+    let open = state.as_ref().ok_or("No document is open")?;
+    serde_json::to_string(&open.document.to_serialized()).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn get_document_info(store: DocumentHandle) -> Result<DocumentInfo, String> {
+    store.lock().as_ref().map(|open| open.document.info()).ok_or_else(|| "No document is open".to_string())
+}
+
+#[command]
+pub fn get_nodes(store: DocumentHandle, ids: Vec<String>) -> Result<Vec<NodeData>, String> {
+    let state = store.lock();
+    let open = state.as_ref().ok_or("No document is open")?;
+    ids.iter().map(|id| open.document.node(id).cloned()).collect()
+}
+
+/// Each mutation returns the document's new revision.
+#[command]
+pub fn insert_node(
+    app: AppHandle,
+    store: DocumentHandle,
+    node: NodeData,
+    parent_id: String,
+    index: Option<usize>,
+) -> Result<u64, String> {
+    store.change(&app, vec![DocumentOp::Insert { node: Box::new(node), parent_id, index }], None)
+}
+
+#[command]
+pub fn set_node_props(app: AppHandle, store: DocumentHandle, id: String, props: Map<String, Value>) -> Result<u64, String> {
+    store.change(&app, vec![DocumentOp::SetProps { id, props }], None)
+}
+
+#[command]
+pub fn reparent_node(
+    app: AppHandle,
+    store: DocumentHandle,
+    id: String,
+    parent_id: String,
+    index: Option<usize>,
+) -> Result<u64, String> {
+    store.change(&app, vec![DocumentOp::Reparent { id, parent_id, index }], None)
+}
+
+#[command]
+pub fn delete_node(app: AppHandle, store: DocumentHandle, id: String) -> Result<u64, String> {
+    store.change(&app, vec![DocumentOp::Delete { id }], None)
+}
+
+/// Apply `ops` in order as one change: all of them, or none if any fails.
+/// `label` names the change in the undo history.
+#[command]
+pub fn apply_document_ops(
+    app: AppHandle,
+    store: DocumentHandle,
+    ops: Vec<DocumentOp>,
+    label: Option<String>,
+) -> Result<u64, String> {
+    store.change(&app, ops, label)
+}
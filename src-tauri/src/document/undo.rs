@@ -0,0 +1,137 @@
+//! Undo and redo of the open document's local edits, as the operations
+//! that reverse each change. Changes merged from other clients aren't
+//! undone; an undo that no longer applies after them is dropped. History
+//! can be kept with a save, so reopening the same document where it was
+//! saved brings its history back.
+
+use super::store::{Document, DocumentHandle, DocumentOp, DocumentStore};
+use crate::atomic::write_atomic;
+use crate::history::hex_digest;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{command, AppHandle};
+
+/// Changes kept for undo before the oldest are forgotten.
+const MAX_ENTRIES: usize = 500;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    label: Option<String>,
+    /// The operations that take the change back, or for redo make it again.
+    ops: Vec<DocumentOp>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct History {
+    undo: Vec<Entry>,
+    redo: Vec<Entry>,
+}
+
+impl History {
+    /// Record a change made locally, which `inverse` undoes.
+    pub fn push(&mut self, label: Option<String>, inverse: Vec<DocumentOp>) {
+        self.undo.push(Entry { label, ops: inverse });
+        if self.undo.len() > MAX_ENTRIES {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+}
+
+/// History kept for a document, valid while the document is as it was
+/// when saved.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SavedHistory {
+    document_hash: String,
+    history: History,
+}
+
+fn document_hash(document: &Document) -> String {
+    hex_digest(&serde_json::to_vec(&document.to_serialized()).unwrap_or_default())
+}
+
+fn history_file(dir: &Path, document: &Path) -> PathBuf {
+    dir.join(format!("{}.json", hex_digest(document.to_string_lossy().as_bytes())))
+}
+
+/// Keep `history` in `dir` for the document saved at `path`.
+pub fn save_history(dir: &Path, path: &Path, document: &Document, history: &History) -> Result<(), String> {
+    let saved = SavedHistory { document_hash: document_hash(document), history: history.clone() };
+    let json = serde_json::to_vec(&saved).map_err(|e| e.to_string())?;
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    write_atomic(&history_file(dir, path).to_string_lossy(), &json)?;
+    Ok(())
+}
+
+/// Drop any history kept in `dir` for the document at `path`.
+pub fn forget_history(dir: &Path, path: &Path) {
+    let _ = fs::remove_file(history_file(dir, path));
+}
+
+/// The history kept in `dir` for the document at `path`, if the document
+/// is still as it was saved with it.
+pub fn load_history(dir: &Path, path: &Path, document: &Document) -> Option<History> {
+    let bytes = fs::read(history_file(dir, path)).ok()?;
+    let saved: SavedHistory = serde_json::from_slice(&bytes).ok()?;
+    (saved.document_hash == document_hash(document)).then_some(saved.history)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryItem {
+    pub label: Option<String>,
+    pub op_count: usize,
+}
+
+/// Undo and redo stacks, oldest first, so the last of each is next.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryInfo {
+    pub undo: Vec<HistoryItem>,
+    pub redo: Vec<HistoryItem>,
+}
+
+/// Take the last entry of one stack, apply it and push its reverse onto
+/// the other. Returns the document's revision.
+fn step(app: &AppHandle, store: &DocumentStore, undo: bool) -> Result<u64, String> {
+    let mut state = store.lock();
+    let open = state.as_mut().ok_or("No document is open")?;
+    let entry = if undo { open.history.undo.pop() } else { open.history.redo.pop() };
+    let Some(Entry { label, ops }) = entry else { return Ok(open.document.revision()) };
+    let action = if undo { "undo" } else { "redo" };
+    let reverse = open.apply(app, &store.window, ops).map_err(|e| format!("Can't {}: {}", action, e))?;
+    let other = if undo { &mut open.history.redo } else { &mut open.history.undo };
+    other.push(Entry { label, ops: reverse });
+    Ok(open.document.revision())
+}
+
+/// Undo the last local change, returning the document's revision.
+#[command]
+pub fn undo(app: AppHandle, store: DocumentHandle) -> Result<u64, String> {
+    step(&app, &store, true)
+}
+
+#[command]
+pub fn redo(app: AppHandle, store: DocumentHandle) -> Result<u64, String> {
+    step(&app, &store, false)
+}
+
+#[command]
+pub fn get_history(store: DocumentHandle) -> Result<HistoryInfo, String> {
+    let state = store.lock();
+    let open = state.as_ref().ok_or("No document is open")?;
+    let items = |entries: &[Entry]| entries.iter().map(|e| HistoryItem { label: e.label.clone(), op_count: e.ops.len() }).collect();
+    Ok(HistoryInfo { undo: items(&open.history.undo), redo: items(&open.history.redo) })
+}
+
+#[command]
+pub fn clear_history(store: DocumentHandle) -> Result<(), String> {
+    let mut state = store.lock();
+    let open = state.as_mut().ok_or("No document is open")?;
+    open.history = History::default();
+    Ok(())
+}
@@ -0,0 +1,299 @@
+//! Checking document JSON against the serde types in `model` and the tree
+//! they have to form, so files from other tools or edited by hand are
+//! reported on rather than half loaded. Most problems can be repaired:
+//! nodes are dropped, moved or have a bad property removed, and only a
+//! document without a usable root can't be.
+
+use crate::model::{NodeData, NodeType};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use tauri::command;
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The document can't be loaded as it is.
+    Error,
+    /// The document loads, but not as it says.
+    Warning,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationIssue {
+    pub severity: Severity,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_id: Option<String>,
+    /// Where in the JSON, such as `nodes[3].data.fills`.
+    pub path: String,
+    pub message: String,
+    /// Whether the repaired document fixes it.
+    pub repaired: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationReport {
+    /// Whether the document has no errors as it is.
+    pub valid: bool,
+    pub issues: Vec<ValidationIssue>,
+    /// With `repair`, the document with every issue fixed, unless one can't
+    /// be.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub document: Option<String>,
+}
+
+/// A node of the document as it will be written back.
+struct Entry {
+    /// Position in the original `nodes` array.
+    index: usize,
+    id: String,
+    parent: Option<String>,
+    child_index: usize,
+    data: Map<String, Value>,
+}
+
+impl Entry {
+    fn node_type(&self) -> Option<NodeType> {
+        serde_json::from_value(self.data.get("type")?.clone()).ok()
+    }
+}
+
+struct Checker {
+    repair: bool,
+    issues: Vec<ValidationIssue>,
+}
+
+impl Checker {
+    fn report(&mut self, severity: Severity, node_id: Option<&str>, path: String, message: String, repairable: bool) {
+        self.issues.push(ValidationIssue {
+            severity,
+            node_id: node_id.map(String::from),
+            path,
+            message,
+            repaired: self.repair && repairable,
+        });
+    }
+
+    /// The node at `nodes[index]`, as far as it can be saved.
+    fn entry(&mut self, index: usize, node: &Value, seen: &mut HashSet<String>) -> Option<Entry> {
+        let path = format!("nodes[{}]", index);
+        let Some(fields) = node.as_object() else {
+            self.report(Severity::Error, None, path, "Node is not an object".to_string(), true);
+            return None;
+        };
+        let Some(id) = fields.get("id").and_then(Value::as_str).filter(|id| !id.is_empty()) else {
+            self.report(Severity::Error, None, path, "Node has no id".to_string(), true);
+            return None;
+        };
+        if !seen.insert(id.to_string()) {
+            self.report(Severity::Error, Some(id), path, format!("Duplicate node id {}", id), true);
+            return None;
+        }
+        let Some(mut data) = fields.get("data").and_then(Value::as_object).cloned() else {
+            self.report(Severity::Error, Some(id), format!("{}.data", path), "Node has no data".to_string(), true);
+            return None;
+        };
+
+        let parent = match fields.get("parentId") {
+            None | Some(Value::Null) => None,
+            Some(Value::String(parent)) => Some(parent.clone()),
+            Some(_) => {
+                self.report(Severity::Error, Some(id), format!("{}.parentId", path), "Parent id is not a string".to_string(), true);
+                None
+            }
+        };
+        let child_index = match fields.get("childIndex") {
+            None => 0,
+            Some(value) => value.as_u64().map(|i| i as usize).unwrap_or_else(|| {
+                self.report(Severity::Warning, Some(id), format!("{}.childIndex", path), "Child index is not a whole number".to_string(), true);
+                0
+            }),
+        };
+
+        if data.get("id").and_then(Value::as_str) != Some(id) {
+            self.report(Severity::Warning, Some(id), format!("{}.data.id", path), "Node data has a different id".to_string(), true);
+            data.insert("id".to_string(), Value::String(id.to_string()));
+        }
+        self.check_data(id, &path, &mut data);
+        Some(Entry { index, id: id.to_string(), parent, child_index, data })
+    }
+
+    /// Check the node's properties against `NodeData`, removing those that
+    /// don't fit. An unknown type becomes a frame, which can hold whatever
+    /// the node held.
+    fn check_data(&mut self, id: &str, path: &str, data: &mut Map<String, Value>) {
+        let node_type = data.get("type").cloned().unwrap_or(Value::Null);
+        if serde_json::from_value::<NodeType>(node_type.clone()).is_err() {
+            self.report(Severity::Error, Some(id), format!("{}.data.type", path), format!("Unknown node type {}", node_type), true);
+            data.insert("type".to_string(), serde_json::to_value(NodeType::Frame).unwrap_or_default());
+        }
+        if serde_json::from_value::<NodeData>(Value::Object(data.clone())).is_ok() {
+            return;
+        }
+
+        // Try each property on its own to find the ones at fault
+        let keys: Vec<String> = data.keys().filter(|key| !matches!(key.as_str(), "id" | "type")).cloned().collect();
+        for key in keys {
+            let mut alone = Map::new();
+            for field in ["id", "type"] {
+                alone.insert(field.to_string(), data[field].clone());
+            }
+            alone.insert(key.clone(), data[&key].clone());
+            if let Err(e) = serde_json::from_value::<NodeData>(Value::Object(alone)) {
+                self.report(Severity::Error, Some(id), format!("{}.data.{}", path, key), format!("Invalid {}: {}", key, e), true);
+                data.remove(&key);
+            }
+        }
+    }
+}
+
+/// Check `document_json` and, with `repair`, fix what can be fixed.
+pub fn validate(document_json: &str, repair: bool) -> ValidationReport {
+    let mut checker = Checker { repair, issues: Vec::new() };
+    let fatal = |mut checker: Checker, path: &str, message: String| {
+        checker.report(Severity::Error, None, path.to_string(), message, false);
+        ValidationReport { valid: false, issues: checker.issues, document: None }
+    };
+
+    let mut doc: Map<String, Value> = match serde_json::from_str(document_json) {
+        Ok(Value::Object(doc)) => doc,
+        Ok(_) => return fatal(checker, "", "Document is not an object".to_string()),
+        Err(e) => return fatal(checker, "", format!("Invalid JSON: {}", e)),
+    };
+    let Some(nodes) = doc.get("nodes").and_then(Value::as_array) else {
+        return fatal(checker, "nodes", "Document has no nodes".to_string());
+    };
+
+    let mut seen = HashSet::new();
+    let mut entries: Vec<Entry> = nodes
+        .iter()
+        .enumerate()
+        .filter_map(|(index, node)| checker.entry(index, node, &mut seen))
+        .collect();
+    let slots: HashMap<String, usize> = entries.iter().enumerate().map(|(slot, entry)| (entry.id.clone(), slot)).collect();
+
+    // A missing root is looked for among the nodes
+    let named_root = doc.get("rootId").and_then(Value::as_str).filter(|id| slots.contains_key(*id));
+    let root_id = match named_root {
+        Some(id) => id.to_string(),
+        None => match entries.iter().find(|entry| entry.node_type() == Some(NodeType::Document)) {
+            Some(found) => {
+                checker.report(Severity::Error, None, "rootId".to_string(), format!("Root is missing; {} is the document node", found.id), true);
+                found.id.clone()
+            }
+            None => return fatal(checker, "rootId", "Root is missing and there is no document node".to_string()),
+        },
+    };
+
+    let root_slot = slots[&root_id];
+    if entries[root_slot].parent.take().is_some() {
+        let path = format!("nodes[{}].parentId", entries[root_slot].index);
+        checker.report(Severity::Warning, Some(&root_id), path, "Root has a parent".to_string(), true);
+    }
+
+    // Stray nodes go on the first page, and stray pages on the root
+    let first_page = entries
+        .iter()
+        .filter(|entry| entry.parent.as_deref() == Some(root_id.as_str()) && entry.node_type() == Some(NodeType::Page))
+        .min_by_key(|entry| (entry.child_index, entry.index))
+        .map(|entry| entry.id.clone());
+    let home = |entry: &Entry| match (&first_page, entry.node_type()) {
+        (Some(page), Some(node_type)) if node_type != NodeType::Page && *page != entry.id => page.clone(),
+        _ => root_id.clone(),
+    };
+
+    for (slot, entry) in entries.iter_mut().enumerate() {
+        if slot == root_slot {
+            continue;
+        }
+        let message = match &entry.parent {
+            None => "Node has no parent".to_string(),
+            Some(parent) if !slots.contains_key(parent) => format!("Parent {} is missing", parent),
+            Some(_) => continue,
+        };
+        checker.report(Severity::Error, Some(&entry.id), format!("nodes[{}].parentId", entry.index), message, true);
+        entry.parent = Some(home(entry));
+    }
+
+    // Every chain of parents has to end at the root; a cycle is broken where
+    // the walk comes back round
+    let mut reaches_root: HashSet<usize> = HashSet::from([root_slot]);
+    for start in 0..entries.len() {
+        let mut chain: Vec<usize> = Vec::new();
+        let mut at = start;
+        while !reaches_root.contains(&at) {
+            if chain.contains(&at) {
+                let (id, path, target) = (entries[at].id.clone(), format!("nodes[{}].parentId", entries[at].index), home(&entries[at]));
+                checker.report(Severity::Error, Some(&id), path, "Node is its own ancestor".to_string(), true);
+                entries[at].parent = Some(target);
+                break;
+            }
+            chain.push(at);
+            at = entries[at].parent.as_ref().map_or(root_slot, |parent| slots[parent]);
+        }
+        reaches_root.extend(chain);
+    }
+
+    // Siblings are put in order, and each node's own idea of its parent and
+    // children brought in line with the tree
+    let mut children: HashMap<String, Vec<usize>> = HashMap::new();
+    for (slot, entry) in entries.iter().enumerate() {
+        if let Some(parent) = &entry.parent {
+            children.entry(parent.clone()).or_default().push(slot);
+        }
+    }
+    let mut child_ids: HashMap<String, Vec<String>> = HashMap::new();
+    for (parent, mut kids) in children {
+        kids.sort_by_key(|&slot| (entries[slot].child_index, entries[slot].index));
+        let shared = kids.windows(2).any(|pair| entries[pair[0]].child_index == entries[pair[1]].child_index);
+        if shared {
+            checker.report(Severity::Warning, Some(&parent), format!("nodes[{}]", entries[slots[&parent]].index), "Children share a child index".to_string(), true);
+        }
+        for (i, &slot) in kids.iter().enumerate() {
+            entries[slot].child_index = i;
+        }
+        child_ids.insert(parent, kids.iter().map(|&slot| entries[slot].id.clone()).collect());
+    }
+    for entry in &mut entries {
+        let kids = child_ids.remove(&entry.id).unwrap_or_default();
+        let listed: Vec<String> = entry.data.get("childIds").and_then(|ids| serde_json::from_value(ids.clone()).ok()).unwrap_or_default();
+        let own_parent = entry.data.get("parentId").and_then(Value::as_str);
+        if listed != kids || (own_parent.is_some() && own_parent != entry.parent.as_deref()) {
+            let path = format!("nodes[{}].data", entry.index);
+            checker.report(Severity::Warning, Some(&entry.id), path, "Node data disagrees with the tree about its parent or children".to_string(), true);
+        }
+        entry.data.insert("childIds".to_string(), serde_json::to_value(kids).unwrap_or_default());
+        match &entry.parent {
+            Some(parent) => entry.data.insert("parentId".to_string(), Value::String(parent.clone())),
+            None => entry.data.remove("parentId"),
+        };
+    }
+
+    let valid = !checker.issues.iter().any(|issue| issue.severity == Severity::Error);
+    let document = repair.then(|| {
+        let nodes = entries
+            .into_iter()
+            .map(|entry| {
+                serde_json::json!({
+                    "id": entry.id,
+                    "parentId": entry.parent,
+                    "childIndex": entry.child_index,
+                    "data": entry.data,
+                })
+            })
+            .collect();
+        doc.insert("nodes".to_string(), Value::Array(nodes));
+        doc.insert("rootId".to_string(), Value::String(root_id));
+        Value::Object(doc).to_string()
+    });
+    ValidationReport { valid, issues: checker.issues, document }
+}
+
+/// Check a document's JSON for structural problems and, with `repair`,
+/// return it with every recoverable one fixed.
+#[command(async)]
+pub fn validate_document(document_json: String, repair: Option<bool>) -> ValidationReport {
+    validate(&document_json, repair.unwrap_or(false))
+}
@@ -0,0 +1,209 @@
+//! Windows each holding a document of their own. The backend keeps what
+//! the OS shows for a window, its title and whether it has unsaved changes,
+//! and the Window menu lists them. Closing a window with unsaved changes is
+//! held back and `close-requested` sent to it, for the frontend to offer to
+//! save before calling `close_window`; once a window is gone its document
+//! is dropped and its file unlocked.
+//!
+//! Windows are told when another one gains focus and whenever the list
+//! changes, so panels such as a libraries list can follow.
+
+use crate::document::store::DocumentStores;
+use crate::locks::FileLocks;
+use crate::menu::refresh_windows;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use tauri::{command, AppHandle, Emitter, Manager, State, WebviewUrl, WebviewWindow, WebviewWindowBuilder, Window, WindowEvent};
+
+pub const DOCUMENT_WINDOWS_EVENT: &str = "document-windows-changed";
+
+pub const WINDOW_FOCUSED_EVENT: &str = "window-focused";
+
+pub const CLOSE_REQUESTED_EVENT: &str = "close-requested";
+
+/// The window from the app config, opened at launch.
+pub const MAIN_WINDOW: &str = "main";
+
+const UNTITLED: &str = "Untitled";
+
+#[derive(Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentWindow {
+    pub label: String,
+    /// The document's name, without the unsaved mark.
+    pub title: String,
+    /// The file the document is saved to, once it is.
+    pub path: Option<String>,
+    /// Whether the document has changes that aren't saved.
+    pub dirty: bool,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WindowFocusedEvent {
+    pub label: String,
+}
+
+pub struct DocumentWindows {
+    windows: Mutex<BTreeMap<String, DocumentWindow>>,
+    next_id: AtomicU64,
+}
+
+/// The title shown for a document, marked the platform's way when it has
+/// unsaved changes.
+fn window_title(window: &DocumentWindow) -> String {
+    match window.dirty {
+        #[cfg(target_os = "macos")]
+        true => format!("{} — Edited", window.title),
+        #[cfg(not(target_os = "macos"))]
+        true => format!("*{}", window.title),
+        false => window.title.clone(),
+    }
+}
+
+/// The windows, with the window from the app config already open.
+impl Default for DocumentWindows {
+    fn default() -> Self {
+        let main = DocumentWindow { label: MAIN_WINDOW.to_string(), title: UNTITLED.to_string(), path: None, dirty: false };
+        DocumentWindows { windows: Mutex::new(BTreeMap::from([(main.label.clone(), main)])), next_id: AtomicU64::new(1) }
+    }
+}
+
+impl DocumentWindows {
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<String, DocumentWindow>> {
+        self.windows.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn list(&self) -> Vec<DocumentWindow> {
+        self.lock().values().cloned().collect()
+    }
+
+    fn get(&self, label: &str) -> Option<DocumentWindow> {
+        self.lock().get(label).cloned()
+    }
+
+    /// The window showing the file at `path`.
+    fn showing(&self, path: &str) -> Option<String> {
+        self.lock().values().find(|window| window.path.as_deref() == Some(path)).map(|window| window.label.clone())
+    }
+}
+
+/// Tell every window about the list and rebuild the Window menu.
+fn announce(app: &AppHandle) {
+    let windows = app.state::<DocumentWindows>().list();
+    refresh_windows(app, &windows);
+    let _ = app.emit(DOCUMENT_WINDOWS_EVENT, windows);
+}
+
+fn focus(window: &WebviewWindow) -> Result<(), String> {
+    let _ = window.unminimize();
+    window.set_focus().map_err(|e| format!("Failed to focus window: {}", e))
+}
+
+/// Hold back closing a window with unsaved changes, and put away what a
+/// closed window held.
+pub fn handle_window_event(window: &Window, event: &WindowEvent) {
+    let app = window.app_handle();
+    let windows = app.state::<DocumentWindows>();
+    match event {
+        WindowEvent::Focused(true) => {
+            let _ = app.emit(WINDOW_FOCUSED_EVENT, WindowFocusedEvent { label: window.label().to_string() });
+        }
+        WindowEvent::CloseRequested { api, .. } if windows.get(window.label()).is_some_and(|document| document.dirty) => {
+            api.prevent_close();
+            let _ = window.emit_to(window.label(), CLOSE_REQUESTED_EVENT, ());
+        }
+        WindowEvent::Destroyed => {
+            let closed = windows.lock().remove(window.label());
+            app.state::<DocumentStores>().remove(window.label());
+            if let Some(path) = closed.and_then(|document| document.path) {
+                let _ = app.state::<FileLocks>().release(&path);
+            }
+            announce(app);
+        }
+        _ => {}
+    }
+}
+
+/// Open a window for a new document, or for the file at `path`. A file
+/// already open in a window is brought to the front there instead. The
+/// window asks for its document with `get_window_document` once loaded.
+/// Resolves to the label of the window.
+#[command]
+pub async fn new_window(app: AppHandle, windows: State<'_, DocumentWindows>, path: Option<String>) -> Result<String, String> {
+    if let Some(label) = path.as_deref().and_then(|path| windows.showing(path)) {
+        if let Some(window) = app.get_webview_window(&label) {
+            focus(&window)?;
+        }
+        return Ok(label);
+    }
+
+    let label = format!("document-{}", windows.next_id.fetch_add(1, Ordering::Relaxed));
+    let title = path
+        .as_deref()
+        .and_then(|path| std::path::Path::new(path).file_stem())
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| UNTITLED.to_string());
+    let document = DocumentWindow { label: label.clone(), title, path, dirty: false };
+
+    // Sized like the window from the app config
+    let builder = WebviewWindowBuilder::new(&app, &label, WebviewUrl::default())
+        .title(window_title(&document))
+        .inner_size(1400.0, 900.0)
+        .min_inner_size(900.0, 600.0);
+    #[cfg(target_os = "macos")]
+    let builder = builder.title_bar_style(tauri::TitleBarStyle::Overlay).hidden_title(true);
+    windows.lock().insert(label.clone(), document);
+    if let Err(e) = builder.build() {
+        windows.lock().remove(&label);
+        return Err(format!("Failed to open window: {}", e));
+    }
+    announce(&app);
+    Ok(label)
+}
+
+/// The document of the calling window.
+#[command]
+pub fn get_window_document(window: Window, windows: State<'_, DocumentWindows>) -> Result<DocumentWindow, String> {
+    windows.get(window.label()).ok_or_else(|| format!("Unknown window: {}", window.label()))
+}
+
+/// Record what the calling window shows, after opening, saving or editing
+/// its document, and update its title.
+#[command]
+pub fn set_window_document(
+    app: AppHandle,
+    window: Window,
+    windows: State<'_, DocumentWindows>,
+    title: String,
+    path: Option<String>,
+    dirty: bool,
+) -> Result<(), String> {
+    let document = DocumentWindow { label: window.label().to_string(), title, path, dirty };
+    window.set_title(&window_title(&document)).map_err(|e| format!("Failed to set window title: {}", e))?;
+    let previous = windows.lock().insert(document.label.clone(), document.clone());
+    if previous.as_ref() != Some(&document) {
+        announce(&app);
+    }
+    Ok(())
+}
+
+#[command]
+pub fn list_document_windows(windows: State<'_, DocumentWindows>) -> Vec<DocumentWindow> {
+    windows.list()
+}
+
+#[command]
+pub fn focus_window(app: AppHandle, label: String) -> Result<(), String> {
+    let window = app.get_webview_window(&label).ok_or_else(|| format!("Unknown window: {}", label))?;
+    focus(&window)
+}
+
+/// Close the calling window without asking about unsaved changes, for the
+/// frontend once it has saved or the user chose to discard them.
+#[command]
+pub fn close_window(window: Window) -> Result<(), String> {
+    window.destroy().map_err(|e| format!("Failed to close window: {}", e))
+}
@@ -0,0 +1,64 @@
+use serde::Serialize;
+use std::fmt;
+use std::io;
+
+/// Failure category the frontend can branch on without parsing messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FileErrorKind {
+    PermissionDenied,
+    DiskFull,
+    NotFound,
+    InvalidPath,
+    Io,
+    /// The file is encrypted and no passphrase was given.
+    PassphraseRequired,
+    WrongPassphrase,
+    /// Another running instance holds the file's lock.
+    Locked,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileError {
+    pub kind: FileErrorKind,
+    pub path: String,
+    pub message: String,
+}
+
+impl FileError {
+    pub fn new(kind: FileErrorKind, path: &str, message: impl Into<String>) -> Self {
+        FileError { kind, path: path.to_string(), message: message.into() }
+    }
+
+    pub fn from_io(err: &io::Error, path: &str, action: &str) -> Self {
+        FileError::new(classify(err), path, format!("Failed to {}: {}", action, err))
+    }
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.path)
+    }
+}
+
+impl std::error::Error for FileError {}
+
+impl From<FileError> for String {
+    fn from(err: FileError) -> Self {
+        err.to_string()
+    }
+}
+
+fn classify(err: &io::Error) -> FileErrorKind {
+    match err.kind() {
+        io::ErrorKind::PermissionDenied | io::ErrorKind::ReadOnlyFilesystem => FileErrorKind::PermissionDenied,
+        io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded => FileErrorKind::DiskFull,
+        io::ErrorKind::NotFound => FileErrorKind::NotFound,
+        io::ErrorKind::InvalidInput
+        | io::ErrorKind::InvalidFilename
+        | io::ErrorKind::NotADirectory
+        | io::ErrorKind::IsADirectory => FileErrorKind::InvalidPath,
+        _ => FileErrorKind::Io,
+    }
+}
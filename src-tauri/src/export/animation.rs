@@ -0,0 +1,347 @@
+use super::raster::{demultiply, render_pixmap};
+use crate::model::{DocumentTree, Rgba};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use color_quant::NeuQuant;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use tauri::command;
+use tiny_skia::Color;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+}
+
+impl AnimationFormat {
+    fn mime_type(self) -> &'static str {
+        match self {
+            AnimationFormat::Gif => "image/gif",
+            AnimationFormat::Apng => "image/apng",
+        }
+    }
+}
+
+/// One frame of the animation: either an image rendered elsewhere, or a
+/// node to render from the shared document or from a document snapshot of
+/// its own.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnimationFrame {
+    /// Base64-encoded PNG, JPEG or WebP.
+    #[serde(default)]
+    pub image: Option<String>,
+    /// Document to render this frame from, instead of the shared one.
+    #[serde(default)]
+    pub node_json: Option<String>,
+    /// Node to render; defaults to the document root.
+    #[serde(default)]
+    pub node_id: Option<String>,
+    /// How long the frame is shown; defaults to one frame at the frame rate.
+    #[serde(default)]
+    pub duration_ms: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AnimationExportOptions {
+    pub fps: f64,
+    /// Times the animation plays; 0 loops forever.
+    pub loop_count: u16,
+    /// Pixels per canvas unit for rendered frames.
+    pub scale: f64,
+    pub padding: f64,
+    /// Painted under rendered frames.
+    pub background: Option<Rgba>,
+    /// GIF palette size per frame, 16-256.
+    pub colors: u16,
+    /// GIF quantizer sampling, from 1 (best, slowest) to 30.
+    pub speed: i32,
+}
+
+impl Default for AnimationExportOptions {
+    fn default() -> Self {
+        AnimationExportOptions {
+            fps: 24.0,
+            loop_count: 0,
+            scale: 1.0,
+            padding: 0.0,
+            background: None,
+            colors: 256,
+            speed: 10,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnimationExport {
+    /// Base64-encoded animation.
+    pub data: String,
+    pub mime_type: &'static str,
+    pub width: u32,
+    pub height: u32,
+    pub frame_count: usize,
+    pub duration_ms: f64,
+    pub warnings: Vec<String>,
+}
+
+/// Straight-alpha RGBA pixels of one frame.
+pub struct FramePixels {
+    pub rgba: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// How frames given as nodes are rendered.
+pub struct FrameRender {
+    /// Pixels per canvas unit.
+    pub scale: f64,
+    pub padding: f64,
+    pub background: Option<Rgba>,
+}
+
+pub fn frame_pixels(
+    index: usize,
+    frame: &AnimationFrame,
+    shared: Option<&DocumentTree>,
+    render: &FrameRender,
+    warnings: &mut Vec<String>,
+) -> Result<FramePixels, String> {
+    if let Some(image) = &frame.image {
+        let bytes = BASE64.decode(image)
+            .map_err(|e| format!("Failed to decode frame {}: {}", index, e))?;
+        let decoded = image::load_from_memory(&bytes)
+            .map_err(|e| format!("Failed to decode frame {}: {}", index, e))?
+            .into_rgba8();
+        return Ok(FramePixels { width: decoded.width(), height: decoded.height(), rgba: decoded.into_raw() });
+    }
+
+    let own;
+    let tree = match &frame.node_json {
+        Some(json) => {
+            own = DocumentTree::parse(json)?;
+            &own
+        }
+        None => shared.ok_or_else(|| format!("Frame {} has neither an image nor a document", index))?,
+    };
+    let background = render.background
+        .map(|bg| Color::from_rgba(bg.r as f32, bg.g as f32, bg.b as f32, bg.a as f32).unwrap_or(Color::WHITE));
+    let (pixmap, frame_warnings) =
+        render_pixmap(tree, render.scale, frame.node_id.clone().map(|id| vec![id]), render.padding, background)?;
+    warnings.extend(frame_warnings.into_iter().map(|w| format!("Frame {}: {}", index, w)));
+    Ok(FramePixels { rgba: demultiply(&pixmap), width: pixmap.width(), height: pixmap.height() })
+}
+
+/// Colors of a frame's opaque pixels in first-seen order, or `None` if
+/// there are more than `limit`. Flat artwork often fits a palette as is.
+fn exact_palette(rgba: &[u8], limit: usize) -> Option<HashMap<[u8; 3], u8>> {
+    let mut palette = HashMap::new();
+    for p in rgba.chunks_exact(4).filter(|p| p[3] >= 128) {
+        let next = palette.len();
+        if let Entry::Vacant(entry) = palette.entry([p[0], p[1], p[2]]) {
+            if next == limit {
+                return None;
+            }
+            entry.insert(next as u8);
+        }
+    }
+    Some(palette)
+}
+
+/// A palette and palette indices for a GIF frame, plus the transparent index
+/// if the frame has one. GIF transparency is a single bit, so pixels under
+/// half alpha become transparent; frames with too many colors are reduced
+/// with NeuQuant.
+fn quantize(rgba: &[u8], colors: usize, speed: i32) -> (Vec<u8>, Vec<u8>, Option<u8>) {
+    let has_transparency = rgba.chunks_exact(4).any(|p| p[3] < 128);
+    let opaque_colors = if has_transparency { colors - 1 } else { colors };
+
+    let (mut palette, lookup) = match exact_palette(rgba, opaque_colors) {
+        Some(exact) => {
+            let mut palette = vec![0; exact.len() * 3];
+            for (color, &index) in &exact {
+                palette[index as usize * 3..index as usize * 3 + 3].copy_from_slice(color);
+            }
+            (palette, exact)
+        }
+        None => {
+            let samples: Vec<u8> = rgba
+                .chunks_exact(4)
+                .filter(|p| p[3] >= 128)
+                .flat_map(|p| [p[0], p[1], p[2], 255])
+                .collect();
+            let quant = NeuQuant::new(speed.clamp(1, 30), opaque_colors, &samples);
+            let mut lookup = HashMap::new();
+            for p in samples.chunks_exact(4) {
+                lookup.entry([p[0], p[1], p[2]]).or_insert_with(|| quant.index_of(p) as u8);
+            }
+            (quant.color_map_rgb(), lookup)
+        }
+    };
+
+    let transparent = has_transparency.then(|| {
+        palette.extend_from_slice(&[0, 0, 0]);
+        (palette.len() / 3 - 1) as u8
+    });
+    let indices = rgba
+        .chunks_exact(4)
+        .map(|p| match transparent {
+            Some(index) if p[3] < 128 => index,
+            _ => lookup[&[p[0], p[1], p[2]]],
+        })
+        .collect();
+    (palette, indices, transparent)
+}
+
+/// Per-frame delays in `unit_ms` steps, taken from rounded timestamps so the
+/// total duration does not drift when frames are not a whole number of
+/// steps long.
+pub fn frame_delays(durations: &[f64], unit_ms: f64) -> Vec<u16> {
+    let mut elapsed = 0.0;
+    durations
+        .iter()
+        .map(|duration| {
+            let start = (elapsed / unit_ms).round();
+            elapsed += duration;
+            ((elapsed / unit_ms).round() - start).clamp(0.0, u16::MAX as f64) as u16
+        })
+        .collect()
+}
+
+fn encode_gif(
+    (width, height): (u32, u32),
+    durations: &[f64],
+    options: &AnimationExportOptions,
+    next_frame: &mut dyn FnMut(usize) -> Result<FramePixels, String>,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<u8>, String> {
+    if width > u16::MAX as u32 || height > u16::MAX as u32 {
+        return Err(format!("GIF frames cannot be larger than {}px", u16::MAX));
+    }
+    let gif_error = |e: gif::EncodingError| format!("Failed to encode GIF: {}", e);
+    let colors = options.colors.clamp(16, 256) as usize;
+
+    // Most viewers slow down frames shorter than 20 ms to 100 ms
+    let mut delays = frame_delays(durations, 10.0);
+    if delays.iter().any(|&d| d < 2) {
+        warnings.push("GIF plays at most 50 frames per second; shorter frames were lengthened".to_string());
+        delays.iter_mut().for_each(|d| *d = (*d).max(2));
+    }
+
+    let mut out = Vec::new();
+    {
+        let mut encoder = gif::Encoder::new(&mut out, width as u16, height as u16, &[]).map_err(gif_error)?;
+        // The loop count in the file is the number of repeats after the first play
+        match options.loop_count {
+            0 => encoder.set_repeat(gif::Repeat::Infinite).map_err(gif_error)?,
+            1 => {}
+            n => encoder.set_repeat(gif::Repeat::Finite(n - 1)).map_err(gif_error)?,
+        }
+        for (index, &delay) in delays.iter().enumerate() {
+            let pixels = next_frame(index)?;
+            let (palette, indices, transparent) = quantize(&pixels.rgba, colors, options.speed);
+            let frame = gif::Frame {
+                width: width as u16,
+                height: height as u16,
+                delay,
+                dispose: gif::DisposalMethod::Background,
+                transparent,
+                palette: Some(palette),
+                buffer: indices.into(),
+                ..Default::default()
+            };
+            encoder.write_frame(&frame).map_err(gif_error)?;
+        }
+    }
+    Ok(out)
+}
+
+fn encode_apng(
+    (width, height): (u32, u32),
+    durations: &[f64],
+    options: &AnimationExportOptions,
+    next_frame: &mut dyn FnMut(usize) -> Result<FramePixels, String>,
+) -> Result<Vec<u8>, String> {
+    let png_error = |e: png::EncodingError| format!("Failed to encode APNG: {}", e);
+
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+    encoder.set_animated(durations.len() as u32, options.loop_count as u32).map_err(png_error)?;
+
+    let mut writer = encoder.write_header().map_err(png_error)?;
+    for (index, delay) in frame_delays(durations, 1.0).into_iter().enumerate() {
+        let pixels = next_frame(index)?;
+        writer.set_frame_delay(delay.max(1), 1000).map_err(png_error)?;
+        writer.write_image_data(&pixels.rgba).map_err(png_error)?;
+    }
+    writer.finish().map_err(png_error)?;
+    Ok(out)
+}
+
+/// Encode a sequence of frames as an animated GIF or APNG. Frames are either
+/// images rendered by the frontend or nodes rendered here, from `node_json`
+/// or from a per-frame document snapshot; all must be the same size. Frames
+/// are decoded and encoded one at a time, so long animations do not hold
+/// every frame in memory.
+#[command(async)]
+pub fn export_animation(
+    format: AnimationFormat,
+    frames: Vec<AnimationFrame>,
+    node_json: Option<String>,
+    options: Option<AnimationExportOptions>,
+) -> Result<AnimationExport, String> {
+    let options = options.unwrap_or_default();
+    if frames.is_empty() {
+        return Err("Nothing to export: the animation has no frames".to_string());
+    }
+    if !(options.fps.is_finite() && options.fps > 0.0) {
+        return Err(format!("Invalid frame rate: {}", options.fps));
+    }
+    let shared = node_json.as_deref().map(DocumentTree::parse).transpose()?;
+    let durations: Vec<f64> = frames
+        .iter()
+        .map(|f| f.duration_ms.filter(|d| d.is_finite() && *d > 0.0).unwrap_or(1000.0 / options.fps))
+        .collect();
+
+    let render = FrameRender { scale: options.scale, padding: options.padding, background: options.background };
+    let mut warnings = Vec::new();
+    let first = frame_pixels(0, &frames[0], shared.as_ref(), &render, &mut warnings)?;
+    let size = (first.width, first.height);
+    let mut first = Some(first);
+    let mut frame_warnings = Vec::new();
+    let mut next_frame = |index: usize| {
+        let pixels = match first.take() {
+            Some(pixels) => pixels,
+            None => frame_pixels(index, &frames[index], shared.as_ref(), &render, &mut frame_warnings)?,
+        };
+        if (pixels.width, pixels.height) != size {
+            return Err(format!(
+                "Frame {} is {}x{}, but the animation is {}x{}",
+                index, pixels.width, pixels.height, size.0, size.1
+            ));
+        }
+        Ok(pixels)
+    };
+
+    let bytes = match format {
+        AnimationFormat::Gif => encode_gif(size, &durations, &options, &mut next_frame, &mut warnings)?,
+        AnimationFormat::Apng => encode_apng(size, &durations, &options, &mut next_frame)?,
+    };
+    warnings.append(&mut frame_warnings);
+
+    Ok(AnimationExport {
+        data: BASE64.encode(bytes),
+        mime_type: format.mime_type(),
+        width: size.0,
+        height: size.1,
+        frame_count: frames.len(),
+        duration_ms: durations.iter().sum(),
+        warnings,
+    })
+}
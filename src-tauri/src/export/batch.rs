@@ -0,0 +1,216 @@
+use super::optimize::SizeReport;
+use super::pdf::{render_pdf, PdfExportOptions};
+use super::raster::{render_raster, RasterExportOptions, RasterFormat};
+use super::resolve_scope;
+use super::svg::{write_svg, SvgExportOptions};
+use crate::atomic::write_atomic;
+use crate::model::{DocumentTree, Rgba};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Instant;
+use tauri::command;
+use tauri::ipc::Channel;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchFormat {
+    Svg,
+    Pdf,
+    Png,
+    #[serde(alias = "jpg")]
+    Jpeg,
+    Webp,
+    Avif,
+}
+
+impl BatchFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            BatchFormat::Svg => "svg",
+            BatchFormat::Pdf => "pdf",
+            BatchFormat::Png => "png",
+            BatchFormat::Jpeg => "jpg",
+            BatchFormat::Webp => "webp",
+            BatchFormat::Avif => "avif",
+        }
+    }
+}
+
+/// One file to write.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportJob {
+    pub node_id: String,
+    pub format: BatchFormat,
+    /// Pixels per canvas unit; only used by raster formats.
+    #[serde(default)]
+    pub scale: Option<f64>,
+    /// File path to write; parent directories are created.
+    pub destination: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BatchExportOptions {
+    pub padding: f64,
+    /// Painted under raster exports.
+    pub background: Option<Rgba>,
+    /// JPEG, AVIF and lossy WebP quality, 1-100.
+    pub quality: u8,
+    /// Write WebP losslessly, which ignores `quality`.
+    pub lossless: bool,
+    /// Losslessly shrink PNG output.
+    pub optimize: bool,
+    /// Worker threads; defaults to the number of CPUs.
+    pub threads: Option<usize>,
+}
+
+impl Default for BatchExportOptions {
+    fn default() -> Self {
+        BatchExportOptions { padding: 0.0, background: None, quality: 90, lossless: true, optimize: false, threads: None }
+    }
+}
+
+/// Messages delivered over the channel passed to `export_batch`. Job events
+/// arrive in completion order, not job order.
+#[derive(Serialize, Clone)]
+#[serde(tag = "event", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum BatchExportEvent {
+    Started { total: usize, threads: usize },
+    JobFinished {
+        index: usize,
+        destination: String,
+        bytes: u64,
+        warnings: Vec<String>,
+        /// Sizes without and with optimizing, for optimized raster jobs.
+        optimization: Option<SizeReport>,
+        completed: usize,
+        total: usize,
+    },
+    JobFailed { index: usize, destination: String, message: String, completed: usize, total: usize },
+    Finished { succeeded: usize, failed: usize, elapsed_ms: u64 },
+}
+
+/// The file contents of one job, with any warnings and what optimizing saved.
+struct EncodedJob {
+    bytes: Vec<u8>,
+    warnings: Vec<String>,
+    optimization: Option<SizeReport>,
+}
+
+fn encode_job(tree: &DocumentTree, job: &ExportJob, options: &BatchExportOptions) -> Result<EncodedJob, String> {
+    let node_ids = Some(vec![job.node_id.clone()]);
+    let raster = |format| {
+        let raster_options = RasterExportOptions {
+            node_ids: node_ids.clone(),
+            padding: options.padding,
+            background: options.background,
+            quality: options.quality,
+            lossless: options.lossless,
+            optimize: options.optimize,
+        };
+        render_raster(tree, format, job.scale.unwrap_or(1.0), &raster_options).map(|r| EncodedJob { bytes: r.bytes, warnings: r.warnings, optimization: r.optimization })
+    };
+
+    match job.format {
+        BatchFormat::Svg => {
+            let scope = resolve_scope(tree, node_ids.clone(), options.padding)?;
+            let export = write_svg(&scope, &SvgExportOptions::default());
+            Ok(EncodedJob { bytes: export.svg.into_bytes(), warnings: export.warnings, optimization: None })
+        }
+        BatchFormat::Pdf => {
+            let pdf_options = PdfExportOptions { node_ids: node_ids.clone(), padding: options.padding, ..Default::default() };
+            render_pdf(tree, &pdf_options).map(|pdf| EncodedJob { bytes: pdf.bytes, warnings: pdf.warnings, optimization: None })
+        }
+        BatchFormat::Png => raster(RasterFormat::Png),
+        BatchFormat::Jpeg => raster(RasterFormat::Jpeg),
+        BatchFormat::Webp => raster(RasterFormat::Webp),
+        BatchFormat::Avif => raster(RasterFormat::Avif),
+    }
+}
+
+fn run_job(tree: &DocumentTree, job: &ExportJob, options: &BatchExportOptions) -> Result<(u64, EncodedJob), String> {
+    let encoded = encode_job(tree, job, options)?;
+    if let Some(parent) = Path::new(&job.destination).parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    write_atomic(&job.destination, &encoded.bytes)?;
+    Ok((encoded.bytes.len() as u64, encoded))
+}
+
+/// Run export jobs against one document on a pool of worker threads,
+/// reporting each job and a final summary through `on_event`. Returns once
+/// the jobs are queued; a failed job does not stop the others.
+#[command(async)]
+pub fn export_batch(
+    node_json: String,
+    jobs: Vec<ExportJob>,
+    options: Option<BatchExportOptions>,
+    on_event: Channel<BatchExportEvent>,
+) -> Result<(), String> {
+    let options = options.unwrap_or_default();
+    if jobs.is_empty() {
+        return Err("Nothing to export: no jobs were given".to_string());
+    }
+    let tree = DocumentTree::parse(&node_json)?;
+    if let Some(job) = jobs.iter().find(|job| tree.get(&job.node_id).is_none()) {
+        return Err(format!("Unknown node: {}", job.node_id));
+    }
+
+    let cpus = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let threads = options.threads.unwrap_or(cpus).clamp(1, jobs.len());
+    let total = jobs.len();
+    let _ = on_event.send(BatchExportEvent::Started { total, threads });
+
+    thread::Builder::new()
+        .name("export-batch".to_string())
+        .spawn(move || {
+            let started = Instant::now();
+            let (tree, jobs, options) = (&tree, &jobs, &options);
+            let next = AtomicUsize::new(0);
+            let completed = AtomicUsize::new(0);
+            let failed = AtomicUsize::new(0);
+
+            thread::scope(|scope| {
+                for _ in 0..threads {
+                    scope.spawn(|| loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(job) = jobs.get(index) else { break };
+                        let result = run_job(tree, job, options);
+                        let done = completed.fetch_add(1, Ordering::Relaxed) + 1;
+                        let destination = job.destination.clone();
+                        let event = match result {
+                            Ok((bytes, encoded)) => BatchExportEvent::JobFinished {
+                                index,
+                                destination,
+                                bytes,
+                                warnings: encoded.warnings,
+                                optimization: encoded.optimization,
+                                completed: done,
+                                total,
+                            },
+                            Err(message) => {
+                                failed.fetch_add(1, Ordering::Relaxed);
+                                BatchExportEvent::JobFailed { index, destination, message, completed: done, total }
+                            }
+                        };
+                        let _ = on_event.send(event);
+                    });
+                }
+            });
+
+            let failed = failed.load(Ordering::Relaxed);
+            let _ = on_event.send(BatchExportEvent::Finished {
+                succeeded: total - failed,
+                failed,
+                elapsed_ms: started.elapsed().as_millis() as u64,
+            });
+        })
+        .map_err(|e| format!("Failed to start export: {}", e))?;
+
+    Ok(())
+}
@@ -0,0 +1,269 @@
+use super::raster::{encode_png, render_pixmap};
+use crate::atomic::write_atomic;
+use crate::model::{DocumentTree, Rgba};
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use tauri::command;
+use tiny_skia::{Color, IntSize, Pixmap, PixmapPaint, Transform};
+
+/// ICNS entry types by pixel size. Retina types hold the same pixels as the
+/// plain type of twice the size, so both are written.
+const ICNS_TYPES: [(&[u8; 4], u32); 11] = [
+    (b"icp4", 16),
+    (b"icp5", 32),
+    (b"ic11", 32),
+    (b"icp6", 64),
+    (b"ic12", 64),
+    (b"ic07", 128),
+    (b"ic08", 256),
+    (b"ic13", 256),
+    (b"ic09", 512),
+    (b"ic14", 512),
+    (b"ic10", 1024),
+];
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AppIconOptions {
+    /// File name stem of every written file.
+    pub name: String,
+    /// Sizes stored in the `.ico`, up to 256; empty to skip it.
+    pub ico_sizes: Vec<u32>,
+    pub icns: bool,
+    /// Sizes written as `<name>-<size>.png`; empty to skip them.
+    pub png_sizes: Vec<u32>,
+    pub background: Option<Rgba>,
+}
+
+impl Default for AppIconOptions {
+    fn default() -> Self {
+        AppIconOptions {
+            name: "icon".to_string(),
+            ico_sizes: vec![16, 24, 32, 48, 64, 128, 256],
+            icns: true,
+            png_sizes: vec![16, 32, 48, 64, 128, 256, 512, 1024],
+            background: None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppIconFile {
+    pub path: String,
+    pub bytes: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppIconExport {
+    pub files: Vec<AppIconFile>,
+    pub warnings: Vec<String>,
+}
+
+/// Render `node_id` once into a transparent square of `size` pixels, with
+/// non-square frames centered.
+fn render_master(tree: &DocumentTree, node_id: &str, size: u32, background: Option<Rgba>) -> Result<(Pixmap, Vec<String>), String> {
+    let bounds = tree.world_bounds(node_id)
+        .filter(|b| b.width > 0.0 && b.height > 0.0)
+        .ok_or_else(|| format!("Node has no size: {}", node_id))?;
+    let longest = bounds.width.max(bounds.height);
+    let background = background
+        .map(|bg| Color::from_rgba(bg.r as f32, bg.g as f32, bg.b as f32, bg.a as f32).unwrap_or(Color::WHITE));
+    let (pixmap, mut warnings) = render_pixmap(tree, size as f64 / longest, Some(vec![node_id.to_string()]), 0.0, background)?;
+
+    if (bounds.width - bounds.height).abs() > 1e-6 {
+        warnings.push(format!(
+            "The icon frame is {}x{}, not square; it was centered on a square canvas",
+            bounds.width, bounds.height
+        ));
+    }
+    let mut master = Pixmap::new(size, size)
+        .ok_or_else(|| "Failed to allocate icon image".to_string())?;
+    let x = (size as i32 - pixmap.width() as i32) / 2;
+    let y = (size as i32 - pixmap.height() as i32) / 2;
+    master.draw_pixmap(x, y, pixmap.as_ref(), &PixmapPaint::default(), Transform::identity(), None);
+    Ok((master, warnings))
+}
+
+/// Downscale with a Lanczos filter. The pixels stay premultiplied while
+/// filtering so transparent edges do not pick up dark fringes.
+fn downscale(master: &Pixmap, size: u32) -> Result<Pixmap, String> {
+    if size == master.width() {
+        return Ok(master.clone());
+    }
+    let source = RgbaImage::from_raw(master.width(), master.height(), master.data().to_vec())
+        .ok_or_else(|| "Failed to read icon image".to_string())?;
+    let mut data = imageops::resize(&source, size, size, FilterType::Lanczos3).into_raw();
+    // Filter overshoot can leave a color brighter than its alpha allows
+    for p in data.chunks_exact_mut(4) {
+        let a = p[3];
+        p[..3].iter_mut().for_each(|c| *c = (*c).min(a));
+    }
+    IntSize::from_wh(size, size)
+        .and_then(|s| Pixmap::from_vec(data, s))
+        .ok_or_else(|| "Failed to allocate icon image".to_string())
+}
+
+/// A 32-bit bottom-up DIB as stored in `.ico` files: the color rows, then a
+/// 1-bit mask of fully transparent pixels for old readers.
+fn ico_bitmap(pixmap: &Pixmap) -> Vec<u8> {
+    let (width, height) = (pixmap.width() as usize, pixmap.height() as usize);
+    let mask_stride = width.div_ceil(32) * 4;
+    let image_size = width * height * 4 + mask_stride * height;
+
+    let mut out = Vec::with_capacity(40 + image_size);
+    out.extend_from_slice(&40u32.to_le_bytes());
+    out.extend_from_slice(&(width as i32).to_le_bytes());
+    // Height covers the color rows and the mask rows
+    out.extend_from_slice(&(height as i32 * 2).to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&32u16.to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&(image_size as u32).to_le_bytes());
+    out.extend_from_slice(&[0; 16]);
+
+    let pixels = pixmap.pixels();
+    for y in (0..height).rev() {
+        for p in &pixels[y * width..(y + 1) * width] {
+            let c = p.demultiply();
+            out.extend_from_slice(&[c.blue(), c.green(), c.red(), c.alpha()]);
+        }
+    }
+    for y in (0..height).rev() {
+        let mut row = vec![0u8; mask_stride];
+        for (x, p) in pixels[y * width..(y + 1) * width].iter().enumerate() {
+            if p.alpha() == 0 {
+                row[x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+        out.extend_from_slice(&row);
+    }
+    out
+}
+
+/// A Windows icon. The 256px image is stored as PNG, which every reader
+/// since Vista expects at that size; smaller ones as bitmaps for the shell
+/// and older tools.
+fn encode_ico(images: &[(u32, Pixmap)]) -> Result<Vec<u8>, String> {
+    let mut entries = Vec::with_capacity(images.len());
+    for (size, pixmap) in images {
+        let data = if *size >= 256 { encode_png(pixmap, 1.0)? } else { ico_bitmap(pixmap) };
+        entries.push((*size, data));
+    }
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    let mut offset = 6 + 16 * entries.len();
+    for (size, data) in &entries {
+        // 0 stands for 256
+        let dimension = if *size >= 256 { 0 } else { *size as u8 };
+        out.extend_from_slice(&[dimension, dimension, 0, 0]);
+        out.extend_from_slice(&1u16.to_le_bytes());
+        out.extend_from_slice(&32u16.to_le_bytes());
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += data.len();
+    }
+    for (_, data) in &entries {
+        out.extend_from_slice(data);
+    }
+    Ok(out)
+}
+
+/// A macOS icon with a PNG for every standard and Retina size.
+fn encode_icns(png_at: impl Fn(u32) -> Vec<u8>) -> Vec<u8> {
+    let mut body = Vec::new();
+    for (kind, size) in ICNS_TYPES {
+        let png = png_at(size);
+        body.extend_from_slice(kind);
+        body.extend_from_slice(&(png.len() as u32 + 8).to_be_bytes());
+        body.extend_from_slice(&png);
+    }
+    let mut out = Vec::with_capacity(body.len() + 8);
+    out.extend_from_slice(b"icns");
+    out.extend_from_slice(&(body.len() as u32 + 8).to_be_bytes());
+    out.extend_from_slice(&body);
+    out
+}
+
+/// Generate app icons from one frame: a multi-size Windows `.ico`, a macOS
+/// `.icns` and a set of PNGs, all written to `output_dir`. The frame is
+/// rendered once at the largest size needed and downscaled from there.
+#[command(async)]
+pub fn export_app_icons(
+    node_json: String,
+    node_id: String,
+    output_dir: String,
+    options: Option<AppIconOptions>,
+) -> Result<AppIconExport, String> {
+    let options = options.unwrap_or_default();
+    if let Some(size) = options.ico_sizes.iter().find(|s| !(1..=256).contains(*s)) {
+        return Err(format!("Invalid .ico size: {}; sizes go from 1 to 256", size));
+    }
+    if let Some(size) = options.png_sizes.iter().find(|s| !(1..=4096).contains(*s)) {
+        return Err(format!("Invalid icon size: {}", size));
+    }
+    if options.ico_sizes.is_empty() && !options.icns && options.png_sizes.is_empty() {
+        return Err("Nothing to export: no icon formats were selected".to_string());
+    }
+    let name = options.name.trim();
+    if name.is_empty() || name.contains(['/', '\\']) {
+        return Err(format!("Invalid icon name: {}", options.name));
+    }
+    let tree = DocumentTree::parse(&node_json)?;
+    if tree.get(&node_id).is_none() {
+        return Err(format!("Unknown node: {}", node_id));
+    }
+    let dir = Path::new(&output_dir);
+    fs::create_dir_all(dir)
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+    let icns_sizes = if options.icns { ICNS_TYPES.map(|(_, size)| size).to_vec() } else { Vec::new() };
+    let largest = options.ico_sizes.iter().chain(&icns_sizes).chain(&options.png_sizes).copied().max().unwrap_or(1);
+    let (master, warnings) = render_master(&tree, &node_id, largest, options.background)?;
+
+    // Each size is filtered and encoded once, however many files use it
+    let wanted: BTreeSet<u32> = options.ico_sizes.iter().chain(&icns_sizes).chain(&options.png_sizes).copied().collect();
+    let mut images = Vec::with_capacity(wanted.len());
+    for size in wanted {
+        let pixmap = downscale(&master, size)?;
+        let png = encode_png(&pixmap, 1.0)?;
+        images.push((size, pixmap, png));
+    }
+    let png_at = |size: u32| images.iter().find(|(s, ..)| *s == size).map(|(_, _, png)| png.clone()).unwrap_or_default();
+
+    let mut files = Vec::new();
+    let mut write = |file: String, bytes: &[u8]| -> Result<(), String> {
+        let path = dir.join(file);
+        let path = path.to_string_lossy().into_owned();
+        write_atomic(&path, bytes)?;
+        files.push(AppIconFile { path, bytes: bytes.len() as u64 });
+        Ok(())
+    };
+
+    if !options.ico_sizes.is_empty() {
+        let mut sizes = options.ico_sizes.clone();
+        sizes.sort_unstable();
+        sizes.dedup();
+        let ico_images: Vec<(u32, Pixmap)> = images
+            .iter()
+            .filter(|(size, ..)| sizes.contains(size))
+            .map(|(size, pixmap, _)| (*size, pixmap.clone()))
+            .collect();
+        write(format!("{}.ico", name), &encode_ico(&ico_images)?)?;
+    }
+    if options.icns {
+        write(format!("{}.icns", name), &encode_icns(png_at))?;
+    }
+    for &size in &options.png_sizes {
+        write(format!("{}-{}.png", name, size), &png_at(size))?;
+    }
+    Ok(AppIconExport { files, warnings })
+}
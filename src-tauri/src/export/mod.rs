@@ -0,0 +1,48 @@
+//! Exporters that serialize document nodes into other formats.
+
+pub mod animation;
+pub mod batch;
+pub mod icons;
+pub mod optimize;
+pub mod pdf;
+pub mod presets;
+pub mod raster;
+pub mod svg;
+pub mod video;
+
+use crate::geometry::Rect;
+use crate::model::DocumentTree;
+
+/// The nodes being exported and the area they cover.
+pub struct ExportScope<'a> {
+    pub tree: &'a DocumentTree,
+    /// Exported nodes, drawn in this order.
+    pub ids: Vec<String>,
+    /// World-space area of the export, including padding.
+    pub bounds: Rect,
+}
+
+/// Resolve the exported nodes of `tree`: `node_ids` if given, otherwise the
+/// root.
+pub fn resolve_scope(tree: &DocumentTree, node_ids: Option<Vec<String>>, padding: f64) -> Result<ExportScope<'_>, String> {
+    let ids = node_ids.filter(|ids| !ids.is_empty()).unwrap_or_else(|| vec![tree.root_id.clone()]);
+
+    if let Some(missing) = ids.iter().find(|id| tree.get(id).is_none()) {
+        return Err(format!("Unknown node: {}", missing));
+    }
+
+    let bounds = ids
+        .iter()
+        .filter_map(|id| tree.world_bounds(id))
+        .reduce(|a, b| a.union(&b))
+        .ok_or_else(|| "Nothing to export: the selection has no visible area".to_string())?;
+    let padding = padding.max(0.0);
+    let bounds = Rect::new(
+        bounds.x - padding,
+        bounds.y - padding,
+        bounds.width + padding * 2.0,
+        bounds.height + padding * 2.0,
+    );
+
+    Ok(ExportScope { tree, ids, bounds })
+}
@@ -0,0 +1,154 @@
+//! Making exported PNGs smaller without changing a pixel, the way oxipng
+//! does: the image is stored in the narrowest color type that holds it
+//! exactly, a palette where it has few colors, and every row filter is
+//! tried at the highest compression level to keep the smallest result.
+//! JPEG, AVIF and lossy WebP exports are sized by their quality setting
+//! instead.
+
+use super::raster::demultiply;
+use png::{AdaptiveFilterType, BitDepth, ColorType, Compression, FilterType};
+use serde::Serialize;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use tiny_skia::Pixmap;
+
+/// Most colors a palette holds.
+const PALETTE_SIZE: usize = 256;
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct SizeReport {
+    /// Bytes as the export writes the image without optimizing.
+    pub before: u64,
+    pub after: u64,
+}
+
+/// One way of storing the image: a color type and depth, the rows packed
+/// for it, and a palette with its transparency where it uses one.
+struct Candidate {
+    color: ColorType,
+    depth: BitDepth,
+    data: Vec<u8>,
+    palette: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+/// The colors of an image in order of first use, and where each is.
+struct Palette {
+    colors: Vec<[u8; 4]>,
+    index: HashMap<[u8; 4], u8>,
+}
+
+/// The palette of `rgba`, if its colors fit one.
+fn palette(rgba: &[u8]) -> Option<Palette> {
+    let mut palette = Palette { colors: Vec::new(), index: HashMap::new() };
+    for p in rgba.chunks_exact(4) {
+        let color = [p[0], p[1], p[2], p[3]];
+        if let Entry::Vacant(entry) = palette.index.entry(color) {
+            if palette.colors.len() == PALETTE_SIZE {
+                return None;
+            }
+            entry.insert(palette.colors.len() as u8);
+            palette.colors.push(color);
+        }
+    }
+    Some(palette)
+}
+
+/// Palette indexes at `bits` each, packed into rows starting on a byte.
+fn pack(indexes: &[u8], width: usize, bits: u8) -> Vec<u8> {
+    if bits == 8 {
+        return indexes.to_vec();
+    }
+    let per_byte = (8 / bits) as usize;
+    let mut out = Vec::with_capacity(indexes.len() / per_byte + 1);
+    for row in indexes.chunks(width) {
+        for group in row.chunks(per_byte) {
+            let byte = group.iter().enumerate().fold(0u8, |byte, (i, index)| byte | index << (8 - bits as usize * (i + 1)));
+            out.push(byte);
+        }
+    }
+    out
+}
+
+fn candidates(rgba: &[u8], width: usize) -> Vec<Candidate> {
+    let opaque = rgba.chunks_exact(4).all(|p| p[3] == 255);
+    let gray = rgba.chunks_exact(4).all(|p| p[0] == p[1] && p[1] == p[2]);
+    let channels: &[usize] = match (gray, opaque) {
+        (true, true) => &[0],
+        (true, false) => &[0, 3],
+        (false, true) => &[0, 1, 2],
+        (false, false) => &[0, 1, 2, 3],
+    };
+    let color = match (gray, opaque) {
+        (true, true) => ColorType::Grayscale,
+        (true, false) => ColorType::GrayscaleAlpha,
+        (false, true) => ColorType::Rgb,
+        (false, false) => ColorType::Rgba,
+    };
+    let data = rgba.chunks_exact(4).flat_map(|p| channels.iter().map(move |c| p[*c])).collect();
+    let mut candidates = vec![Candidate { color, depth: BitDepth::Eight, data, palette: None }];
+
+    if let Some(Palette { colors, index }) = palette(rgba) {
+        let (bits, depth) = match colors.len() {
+            0..=2 => (1, BitDepth::One),
+            3..=4 => (2, BitDepth::Two),
+            5..=16 => (4, BitDepth::Four),
+            _ => (8, BitDepth::Eight),
+        };
+        let indexes: Vec<u8> = rgba.chunks_exact(4).map(|p| index[&[p[0], p[1], p[2], p[3]]]).collect();
+        let rgb = colors.iter().flat_map(|c| [c[0], c[1], c[2]]).collect();
+        // Trailing opaque entries can be left out of tRNS
+        let alphas = colors.iter().rposition(|c| c[3] != 255).map_or(0, |last| last + 1);
+        let trns = colors[..alphas].iter().map(|c| c[3]).collect();
+        candidates.push(Candidate { color: ColorType::Indexed, depth, data: pack(&indexes, width, bits), palette: Some((rgb, trns)) });
+    }
+    candidates
+}
+
+fn encode(candidate: &Candidate, width: u32, height: u32, density: u32, filter: Option<FilterType>) -> Result<Vec<u8>, png::EncodingError> {
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, width, height);
+    encoder.set_color(candidate.color);
+    encoder.set_depth(candidate.depth);
+    encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+    encoder.set_pixel_dims(Some(png::PixelDimensions { xppu: density, yppu: density, unit: png::Unit::Meter }));
+    encoder.set_compression(Compression::Best);
+    match filter {
+        Some(filter) => encoder.set_filter(filter),
+        None => encoder.set_adaptive_filter(AdaptiveFilterType::Adaptive),
+    }
+    if let Some((palette, trns)) = &candidate.palette {
+        encoder.set_palette(palette.as_slice());
+        if !trns.is_empty() {
+            encoder.set_trns(trns.as_slice());
+        }
+    }
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&candidate.data)?;
+    writer.finish()?;
+    Ok(out)
+}
+
+/// The smallest PNG of `pixmap` found, tagged as `encode_png` tags it.
+pub fn optimize_png(pixmap: &Pixmap, scale: f64) -> Result<Vec<u8>, String> {
+    let (width, height) = (pixmap.width(), pixmap.height());
+    let density = (72.0 * scale / 0.0254).round() as u32;
+    let filters = [
+        Some(FilterType::NoFilter),
+        Some(FilterType::Sub),
+        Some(FilterType::Up),
+        Some(FilterType::Avg),
+        Some(FilterType::Paeth),
+        None,
+    ];
+    let mut best: Option<Vec<u8>> = None;
+    for candidate in candidates(&demultiply(pixmap), width as usize) {
+        for filter in filters {
+            let png = encode(&candidate, width, height, density, filter).map_err(|e| format!("Failed to encode PNG: {}", e))?;
+            if best.as_ref().is_none_or(|best| png.len() < best.len()) {
+                best = Some(png);
+            }
+        }
+    }
+    best.ok_or_else(|| "Failed to encode PNG".to_string())
+}
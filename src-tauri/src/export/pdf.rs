@@ -0,0 +1,306 @@
+use super::resolve_scope;
+use super::svg::{write_svg, SvgExportOptions};
+use crate::fonts::emoji::font_resolver;
+use crate::fonts::license::{embedding_restriction, subsetting_allowed};
+use crate::fonts::{face_data, font_database};
+use crate::model::{DocumentTree, NodeType};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use pdf_writer::types::OutputIntentSubtype;
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref, TextStr};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use tauri::command;
+use usvg::fontdb::Database;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ColorSpace {
+    #[default]
+    Rgb,
+    Cmyk,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PdfExportOptions {
+    /// Nodes to export, one page each. Defaults to every top-level frame of
+    /// every page, or the page itself when it has no frames.
+    pub node_ids: Option<Vec<String>>,
+    pub padding: f64,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    /// Keep text selectable with embedded font subsets; when false text is
+    /// converted to outlines.
+    pub embed_text: bool,
+    pub compress: bool,
+    /// With `CMYK`, an output intent for `outputCondition` is attached so
+    /// print workflows know which press condition the document targets.
+    /// Colors themselves stay in RGB.
+    pub color_space: ColorSpace,
+    /// Registered characterization name, e.g. `FOGRA39` or `CGATS21_CRPC1`.
+    pub output_condition: String,
+    /// CMYK ICC profile to embed as the destination output profile.
+    pub icc_profile: Option<String>,
+    /// Take EXIF and other metadata out of placed images.
+    pub strip_metadata: bool,
+}
+
+impl Default for PdfExportOptions {
+    fn default() -> Self {
+        PdfExportOptions {
+            node_ids: None,
+            padding: 0.0,
+            title: None,
+            author: None,
+            embed_text: true,
+            compress: true,
+            color_space: ColorSpace::Rgb,
+            output_condition: "FOGRA39".to_string(),
+            icc_profile: None,
+            strip_metadata: false,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfExport {
+    /// Base64-encoded PDF.
+    pub data: String,
+    pub page_count: usize,
+    pub warnings: Vec<String>,
+}
+
+/// Default page list: the visible top-level frames and components of each
+/// page, in order.
+fn default_pages(tree: &DocumentTree) -> Vec<String> {
+    let mut ids = Vec::new();
+    for page in tree.pages() {
+        let frames: Vec<String> = tree
+            .children(&page.id)
+            .iter()
+            .filter(|id| {
+                tree.get(id)
+                    .is_some_and(|n| n.visible && matches!(n.node_type, NodeType::Frame | NodeType::Component))
+            })
+            .cloned()
+            .collect();
+        if frames.is_empty() {
+            if tree.world_bounds(&page.id).is_some() {
+                ids.push(page.id.clone());
+            }
+        } else {
+            ids.extend(frames);
+        }
+    }
+    ids
+}
+
+/// The nodes that become pages, one each: those asked for, or the default
+/// pages.
+pub fn page_ids(tree: &DocumentTree, options: &PdfExportOptions) -> Vec<String> {
+    options
+        .node_ids
+        .clone()
+        .filter(|ids| !ids.is_empty())
+        .unwrap_or_else(|| default_pages(tree))
+}
+
+/// Families of the faces `group` draws text with that the PDF must not
+/// embed: those whose license forbids embedding or subsetting, since PDF
+/// fonts are always subset.
+fn restricted_fonts(group: &usvg::Group, db: &Database, out: &mut Vec<String>) {
+    for child in group.children() {
+        match child {
+            usvg::Node::Group(g) => restricted_fonts(g, db, out),
+            usvg::Node::Text(text) => {
+                let faces = text.layouted().iter().flat_map(|span| &span.positioned_glyphs).map(|glyph| glyph.font);
+                for id in faces {
+                    let Some(face) = db.face(id) else { continue };
+                    let Some(family) = face.families.first().map(|(name, _)| name.clone()) else { continue };
+                    if out.contains(&family) {
+                        continue;
+                    }
+                    let restricted = face_data(db, face)
+                        .is_some_and(|data| embedding_restriction(&data).is_some() || !subsetting_allowed(&data));
+                    if restricted {
+                        out.push(family);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A PDF file and what went into it.
+pub struct RenderedPdf {
+    pub bytes: Vec<u8>,
+    pub page_count: usize,
+    pub warnings: Vec<String>,
+}
+
+/// Where a node goes on its page, in points from the page's bottom left.
+pub struct PagePlacement {
+    pub width: f32,
+    pub height: f32,
+    /// The box the node's bounds are drawn into.
+    pub drawing: Rect,
+    /// Anything outside is cut off.
+    pub clip: Option<Rect>,
+}
+
+/// Write nodes of `tree` as a PDF with one page per node. Pages are sized
+/// 1pt per pixel, like the canvas, and shapes stay vectors.
+pub fn render_pdf(tree: &DocumentTree, options: &PdfExportOptions) -> Result<RenderedPdf, String> {
+    render_pdf_placed(tree, options, |width, height| PagePlacement {
+        width,
+        height,
+        drawing: Rect::new(0.0, 0.0, width, height),
+        clip: None,
+    })
+}
+
+/// Write nodes of `tree` as a PDF with one page per node, each sized and
+/// placed by `place` from the node's size in pixels.
+pub fn render_pdf_placed(
+    tree: &DocumentTree,
+    options: &PdfExportOptions,
+    place: impl Fn(f32, f32) -> PagePlacement,
+) -> Result<RenderedPdf, String> {
+    let ids = page_ids(tree, options);
+    if ids.is_empty() {
+        return Err("Nothing to export: the document has no frames".to_string());
+    }
+
+    let svg_options = SvgExportOptions {
+        include_xml_declaration: false,
+        minify: true,
+        strip_metadata: options.strip_metadata,
+        ..Default::default()
+    };
+    let usvg_options = usvg::Options { fontdb: font_database(), font_resolver: font_resolver(), ..Default::default() };
+
+    let mut alloc = Ref::new(1);
+    let catalog_id = alloc.bump();
+    let page_tree_id = alloc.bump();
+    let info_id = alloc.bump();
+    let mut pdf = Pdf::new();
+    let mut page_ids = Vec::with_capacity(ids.len());
+    let mut warnings: Vec<String> = Vec::new();
+
+    for id in &ids {
+        let scope = resolve_scope(tree, Some(vec![id.clone()]), options.padding)?;
+        let export = write_svg(&scope, &svg_options);
+        for warning in export.warnings {
+            if !warnings.contains(&warning) {
+                warnings.push(warning);
+            }
+        }
+
+        let svg_tree = usvg::Tree::from_str(&export.svg, &usvg_options)
+            .map_err(|e| format!("Failed to prepare page for PDF: {}", e))?;
+        // Pages with text in fonts that may not be embedded get outlined text
+        let mut restricted = Vec::new();
+        if options.embed_text {
+            restricted_fonts(svg_tree.root(), svg_tree.fontdb(), &mut restricted);
+        }
+        for family in &restricted {
+            let warning = format!("Text was converted to outlines because the license of {} does not allow embedding", family);
+            if !warnings.contains(&warning) {
+                warnings.push(warning);
+            }
+        }
+        let conversion = svg2pdf::ConversionOptions {
+            compress: options.compress,
+            embed_text: options.embed_text && restricted.is_empty(),
+            ..Default::default()
+        };
+        let (chunk, svg_id) = svg2pdf::to_chunk(&svg_tree, conversion)
+            .map_err(|e| format!("Failed to convert page to PDF: {}", e))?;
+
+        let mut renumbered = HashMap::new();
+        let chunk = chunk.renumber(|old| *renumbered.entry(old).or_insert_with(|| alloc.bump()));
+        let svg_id = renumbered[&svg_id];
+
+        let page_id = alloc.bump();
+        let content_id = alloc.bump();
+        let placement = place(export.width as f32, export.height as f32);
+
+        let mut page = pdf.page(page_id);
+        page.media_box(Rect::new(0.0, 0.0, placement.width, placement.height));
+        page.parent(page_tree_id);
+        page.contents(content_id);
+        page.resources().x_objects().pair(Name(b"S1"), svg_id);
+        page.finish();
+
+        let mut content = Content::new();
+        if let Some(clip) = placement.clip {
+            content.rect(clip.x1, clip.y1, clip.x2 - clip.x1, clip.y2 - clip.y1).clip_nonzero().end_path();
+        }
+        // The converted XObject is 1pt square; scale it up to its box
+        let Rect { x1, y1, x2, y2 } = placement.drawing;
+        content.transform([x2 - x1, 0.0, 0.0, y2 - y1, x1, y1]).x_object(Name(b"S1"));
+        pdf.stream(content_id, &content.finish());
+        pdf.extend(&chunk);
+        page_ids.push(page_id);
+    }
+
+    pdf.pages(page_tree_id).kids(page_ids.iter().copied()).count(page_ids.len() as i32);
+
+    let profile_id = match (&options.icc_profile, options.color_space) {
+        (Some(path), ColorSpace::Cmyk) => {
+            let profile = fs::read(path)
+                .map_err(|e| format!("Failed to read ICC profile: {}", e))?;
+            let profile_id = alloc.bump();
+            pdf.icc_profile(profile_id, &profile).n(4);
+            Some(profile_id)
+        }
+        _ => None,
+    };
+
+    let mut catalog = pdf.catalog(catalog_id);
+    catalog.pages(page_tree_id);
+    if options.color_space == ColorSpace::Cmyk {
+        let condition = TextStr(&options.output_condition);
+        let mut intents = catalog.output_intents();
+        let mut intent = intents.push();
+        intent
+            .subtype(OutputIntentSubtype::PDFX)
+            .output_condition_identifier(condition)
+            .output_condition(condition)
+            .registry_name(TextStr("http://www.color.org"));
+        if let Some(profile_id) = profile_id {
+            intent.dest_output_profile(profile_id);
+        }
+        intent.finish();
+        intents.finish();
+    }
+    catalog.finish();
+
+    let mut info = pdf.document_info(info_id);
+    info.producer(TextStr("DesignLibre"));
+    if let Some(title) = &options.title {
+        info.title(TextStr(title));
+    }
+    if let Some(author) = &options.author {
+        info.author(TextStr(author));
+    }
+    info.finish();
+
+    Ok(RenderedPdf { bytes: pdf.finish(), page_count: page_ids.len(), warnings })
+}
+
+/// Export nodes as a PDF; see `render_pdf`.
+#[command(async)]
+pub fn export_pdf(node_json: String, options: Option<PdfExportOptions>) -> Result<PdfExport, String> {
+    let options = options.unwrap_or_default();
+    let tree = DocumentTree::parse(&node_json)?;
+    let pdf = render_pdf(&tree, &options)?;
+    Ok(PdfExport {
+        data: BASE64.encode(pdf.bytes),
+        page_count: pdf.page_count,
+        warnings: pdf.warnings,
+    })
+}
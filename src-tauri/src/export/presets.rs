@@ -0,0 +1,259 @@
+use super::batch::{BatchFormat, ExportJob};
+use crate::atomic::write_atomic;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use tauri::{command, State};
+
+/// How node names become file names.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum NameStyle {
+    #[default]
+    Original,
+    /// Lowercase with underscores, as Android resource names require.
+    SnakeCase,
+}
+
+/// One output of a preset: a scale, a file name suffix and a subdirectory.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PresetVariant {
+    pub scale: f64,
+    #[serde(default)]
+    pub suffix: String,
+    /// Relative to the output directory; empty for the directory itself.
+    #[serde(default)]
+    pub directory: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportPreset {
+    pub id: String,
+    pub name: String,
+    pub format: BatchFormat,
+    #[serde(default)]
+    pub name_style: NameStyle,
+    pub variants: Vec<PresetVariant>,
+    /// Filled in when listing; not persisted.
+    #[serde(skip_deserializing, default)]
+    pub builtin: bool,
+}
+
+/// A node to export under a preset, and the file name to use for it.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PresetTarget {
+    pub node_id: String,
+    pub name: String,
+}
+
+fn variant(scale: f64, suffix: &str, directory: &str) -> PresetVariant {
+    PresetVariant { scale, suffix: suffix.to_string(), directory: directory.to_string() }
+}
+
+/// Presets shipped with the app. They can be copied but not changed.
+fn builtin_presets() -> Vec<ExportPreset> {
+    let preset = |id: &str, name: &str, name_style, variants| ExportPreset {
+        id: id.to_string(),
+        name: name.to_string(),
+        format: BatchFormat::Png,
+        name_style,
+        variants,
+        builtin: true,
+    };
+    vec![
+        preset(
+            "ios",
+            "iOS",
+            NameStyle::Original,
+            vec![variant(1.0, "", ""), variant(2.0, "@2x", ""), variant(3.0, "@3x", "")],
+        ),
+        preset(
+            "android",
+            "Android",
+            NameStyle::SnakeCase,
+            vec![
+                variant(1.0, "", "drawable-mdpi"),
+                variant(1.5, "", "drawable-hdpi"),
+                variant(2.0, "", "drawable-xhdpi"),
+                variant(3.0, "", "drawable-xxhdpi"),
+                variant(4.0, "", "drawable-xxxhdpi"),
+            ],
+        ),
+        preset("web", "Web", NameStyle::Original, vec![variant(1.0, "", ""), variant(2.0, "@2x", "")]),
+    ]
+}
+
+/// A node name as a file name without an extension: path separators and
+/// characters reserved on Windows are replaced.
+pub fn file_stem(name: &str, style: NameStyle) -> String {
+    let cleaned: String = name
+        .trim()
+        .chars()
+        .map(|c| if c.is_control() || "/\\:*?\"<>|".contains(c) { '-' } else { c })
+        .collect();
+
+    let stem = match style {
+        NameStyle::Original => cleaned.trim_matches(|c| c == '.' || c == ' ').to_string(),
+        NameStyle::SnakeCase => {
+            let mut out = String::with_capacity(cleaned.len());
+            for c in cleaned.chars() {
+                if c.is_ascii_alphanumeric() {
+                    out.push(c.to_ascii_lowercase());
+                } else if !out.ends_with('_') {
+                    out.push('_');
+                }
+            }
+            // Resource names cannot start with a digit
+            let out = out.trim_matches('_');
+            if out.starts_with(|c: char| c.is_ascii_digit()) { format!("ic_{}", out) } else { out.to_string() }
+        }
+    };
+    if stem.is_empty() { "export".to_string() } else { stem }
+}
+
+/// Concrete export jobs for `targets` under `preset`, with files laid out in
+/// `output_dir`. Targets that share a name are numbered.
+pub fn expand_preset(preset: &ExportPreset, targets: &[PresetTarget], output_dir: &Path) -> Vec<ExportJob> {
+    let separator = if preset.name_style == NameStyle::SnakeCase { "_" } else { "-" };
+    let mut used = HashSet::new();
+    let mut jobs = Vec::with_capacity(targets.len() * preset.variants.len());
+
+    for target in targets {
+        let base = file_stem(&target.name, preset.name_style);
+        let mut stem = base.clone();
+        let mut n = 1;
+        while !used.insert(stem.clone()) {
+            n += 1;
+            stem = format!("{}{}{}", base, separator, n);
+        }
+
+        for variant in &preset.variants {
+            let file = format!("{}{}.{}", stem, variant.suffix, preset.format.extension());
+            let destination = output_dir.join(&variant.directory).join(file);
+            jobs.push(ExportJob {
+                node_id: target.node_id.clone(),
+                format: preset.format,
+                scale: Some(variant.scale),
+                destination: destination.to_string_lossy().into_owned(),
+            });
+        }
+    }
+    jobs
+}
+
+/// User export presets persisted as JSON in the app config directory.
+pub struct ExportPresets {
+    store: PathBuf,
+    presets: Mutex<Vec<ExportPreset>>,
+}
+
+impl ExportPresets {
+    pub fn load(store: PathBuf) -> Self {
+        let presets = std::fs::read(&store)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+
+        ExportPresets { store, presets: Mutex::new(presets) }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<ExportPreset>> {
+        self.presets.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn persist(&self, presets: &[ExportPreset]) -> Result<(), String> {
+        if let Some(dir) = self.store.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create app config directory: {}", e))?;
+        }
+        let json = serde_json::to_vec_pretty(presets)
+            .map_err(|e| format!("Failed to encode export presets: {}", e))?;
+        write_atomic(&self.store.to_string_lossy(), &json)?;
+        Ok(())
+    }
+
+    /// Built-in presets first, then the user's in the order they were added.
+    pub fn list(&self) -> Vec<ExportPreset> {
+        let mut presets = builtin_presets();
+        presets.extend(self.lock().iter().cloned());
+        presets
+    }
+
+    pub fn get(&self, id: &str) -> Option<ExportPreset> {
+        self.list().into_iter().find(|p| p.id == id)
+    }
+
+    /// Add `preset`, or replace the user preset with the same id.
+    pub fn save(&self, mut preset: ExportPreset) -> Result<(), String> {
+        if preset.id.trim().is_empty() {
+            return Err("Export preset id is empty".to_string());
+        }
+        if builtin_presets().iter().any(|p| p.id == preset.id) {
+            return Err(format!("Built-in export preset cannot be changed: {}", preset.id));
+        }
+        if preset.variants.is_empty() {
+            return Err("Export preset has no variants".to_string());
+        }
+        if let Some(v) = preset.variants.iter().find(|v| !(v.scale.is_finite() && v.scale > 0.0)) {
+            return Err(format!("Invalid export scale: {}", v.scale));
+        }
+        if let Some(v) = preset.variants.iter().find(|v| Path::new(&v.directory).is_absolute() || v.directory.contains("..")) {
+            return Err(format!("Preset directory must be relative: {}", v.directory));
+        }
+        preset.builtin = false;
+
+        let mut presets = self.lock();
+        match presets.iter_mut().find(|p| p.id == preset.id) {
+            Some(existing) => *existing = preset,
+            None => presets.push(preset),
+        }
+        self.persist(&presets)
+    }
+
+    pub fn delete(&self, id: &str) -> Result<(), String> {
+        if builtin_presets().iter().any(|p| p.id == id) {
+            return Err(format!("Built-in export preset cannot be deleted: {}", id));
+        }
+        let mut presets = self.lock();
+        let before = presets.len();
+        presets.retain(|p| p.id != id);
+        if presets.len() == before {
+            return Err(format!("Unknown export preset: {}", id));
+        }
+        self.persist(&presets)
+    }
+}
+
+#[command]
+pub fn list_export_presets(presets: State<'_, ExportPresets>) -> Vec<ExportPreset> {
+    presets.list()
+}
+
+#[command]
+pub fn save_export_preset(presets: State<'_, ExportPresets>, preset: ExportPreset) -> Result<Vec<ExportPreset>, String> {
+    presets.save(preset)?;
+    Ok(presets.list())
+}
+
+#[command]
+pub fn delete_export_preset(presets: State<'_, ExportPresets>, id: String) -> Result<Vec<ExportPreset>, String> {
+    presets.delete(&id)?;
+    Ok(presets.list())
+}
+
+/// Expand a preset into jobs for `export_batch`.
+#[command]
+pub fn expand_export_preset(
+    presets: State<'_, ExportPresets>,
+    preset_id: String,
+    targets: Vec<PresetTarget>,
+    output_dir: String,
+) -> Result<Vec<ExportJob>, String> {
+    let preset = presets.get(&preset_id)
+        .ok_or_else(|| format!("Unknown export preset: {}", preset_id))?;
+    Ok(expand_preset(&preset, &targets, Path::new(&output_dir)))
+}
@@ -0,0 +1,256 @@
+use super::optimize::{optimize_png, SizeReport};
+use super::resolve_scope;
+use super::svg::{write_svg, SvgExportOptions};
+use crate::fonts::emoji::font_resolver;
+use crate::fonts::font_database;
+use crate::model::{DocumentTree, Rgba};
+use crate::render::MAX_DIMENSION;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::{ExtendedColorType, ImageEncoder};
+use serde::{Deserialize, Serialize};
+use tauri::command;
+use tiny_skia::{Color, Pixmap, Transform};
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RasterFormat {
+    Png,
+    #[serde(alias = "jpg")]
+    Jpeg,
+    /// Lossless unless `lossless` is turned off.
+    Webp,
+    Avif,
+}
+
+impl RasterFormat {
+    fn mime_type(self) -> &'static str {
+        match self {
+            RasterFormat::Png => "image/png",
+            RasterFormat::Jpeg => "image/jpeg",
+            RasterFormat::Webp => "image/webp",
+            RasterFormat::Avif => "image/avif",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RasterExportOptions {
+    /// Nodes to export; defaults to the document root.
+    pub node_ids: Option<Vec<String>>,
+    pub padding: f64,
+    /// Painted under the content. JPEG has no alpha and defaults to white.
+    pub background: Option<Rgba>,
+    /// JPEG, AVIF and lossy WebP quality, 1-100.
+    pub quality: u8,
+    /// Write WebP losslessly, which ignores `quality`.
+    pub lossless: bool,
+    /// Losslessly shrink PNG output, which is slower to encode.
+    pub optimize: bool,
+}
+
+impl Default for RasterExportOptions {
+    fn default() -> Self {
+        RasterExportOptions { node_ids: None, padding: 0.0, background: None, quality: 90, lossless: true, optimize: false }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RasterExport {
+    /// Base64-encoded image.
+    pub data: String,
+    pub mime_type: &'static str,
+    pub width: u32,
+    pub height: u32,
+    pub warnings: Vec<String>,
+    /// Sizes without and with optimizing, when it was asked for.
+    pub optimization: Option<SizeReport>,
+}
+
+/// Straight-alpha RGBA bytes of a premultiplied pixmap.
+pub fn demultiply(pixmap: &Pixmap) -> Vec<u8> {
+    pixmap
+        .pixels()
+        .iter()
+        .flat_map(|p| {
+            let c = p.demultiply();
+            [c.red(), c.green(), c.blue(), c.alpha()]
+        })
+        .collect()
+}
+
+/// PNG tagged as sRGB, with a pixel density matching `scale` so a 2x export
+/// is shown at its intended size.
+pub fn encode_png(pixmap: &Pixmap, scale: f64) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, pixmap.width(), pixmap.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+    // 72 dpi at 1x, in pixels per meter
+    let density = (72.0 * scale / 0.0254).round() as u32;
+    encoder.set_pixel_dims(Some(png::PixelDimensions { xppu: density, yppu: density, unit: png::Unit::Meter }));
+
+    let mut writer = encoder.write_header()
+        .map_err(|e| format!("Failed to encode PNG: {}", e))?;
+    writer.write_image_data(&demultiply(pixmap))
+        .map_err(|e| format!("Failed to encode PNG: {}", e))?;
+    writer.finish()
+        .map_err(|e| format!("Failed to encode PNG: {}", e))?;
+    Ok(out)
+}
+
+/// rav1e's speed for AVIF exports, from 1 (smallest files) to 10 (fastest).
+const AVIF_SPEED: u8 = 6;
+
+fn encode(pixmap: &Pixmap, format: RasterFormat, scale: f64, quality: u8, lossless: bool) -> Result<Vec<u8>, String> {
+    let (width, height) = (pixmap.width(), pixmap.height());
+    let quality = quality.clamp(1, 100);
+    let mut out = Vec::new();
+    match format {
+        RasterFormat::Png => return encode_png(pixmap, scale),
+        RasterFormat::Jpeg => {
+            // Opaque after the background fill, so premultiplied equals straight
+            let rgb: Vec<u8> = pixmap.data().chunks_exact(4).flat_map(|p| [p[0], p[1], p[2]]).collect();
+            JpegEncoder::new_with_quality(&mut out, quality)
+                .encode(&rgb, width, height, ExtendedColorType::Rgb8)
+                .map_err(|e| format!("Failed to encode JPEG: {}", e))?;
+        }
+        RasterFormat::Webp if lossless => {
+            WebPEncoder::new_lossless(&mut out)
+                .encode(&demultiply(pixmap), width, height, ExtendedColorType::Rgba8)
+                .map_err(|e| format!("Failed to encode WebP: {}", e))?;
+        }
+        RasterFormat::Webp => {
+            let rgba = demultiply(pixmap);
+            let webp = webp::Encoder::from_rgba(&rgba, width, height)
+                .encode_simple(false, quality as f32)
+                .map_err(|e| format!("Failed to encode WebP: {:?}", e))?;
+            out.extend_from_slice(&webp);
+        }
+        RasterFormat::Avif => {
+            AvifEncoder::new_with_speed_quality(&mut out, AVIF_SPEED, quality)
+                .write_image(&demultiply(pixmap), width, height, ExtendedColorType::Rgba8)
+                .map_err(|e| format!("Failed to encode AVIF: {}", e))?;
+        }
+    }
+    Ok(out)
+}
+
+/// An encoded image and the size it was rendered at.
+pub struct RenderedRaster {
+    pub bytes: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    pub warnings: Vec<String>,
+    pub optimization: Option<SizeReport>,
+}
+
+/// Rasterize nodes of `tree` at `scale` pixels per canvas unit onto a pixmap
+/// filled with `background`.
+pub fn render_pixmap(
+    tree: &DocumentTree,
+    scale: f64,
+    node_ids: Option<Vec<String>>,
+    padding: f64,
+    background: Option<Color>,
+) -> Result<(Pixmap, Vec<String>), String> {
+    if !(scale.is_finite() && scale > 0.0) {
+        return Err(format!("Invalid export scale: {}", scale));
+    }
+
+    let scope = resolve_scope(tree, node_ids, padding)?;
+    let svg_options = SvgExportOptions { include_xml_declaration: false, minify: true, ..Default::default() };
+    let export = write_svg(&scope, &svg_options);
+
+    let width = (export.width * scale).ceil().max(1.0);
+    let height = (export.height * scale).ceil().max(1.0);
+    if width > MAX_DIMENSION as f64 || height > MAX_DIMENSION as f64 {
+        return Err(format!(
+            "Export of {}x{} exceeds the maximum size of {}px",
+            width, height, MAX_DIMENSION
+        ));
+    }
+
+    let usvg_options = usvg::Options { fontdb: font_database(), font_resolver: font_resolver(), ..Default::default() };
+    let svg_tree = usvg::Tree::from_str(&export.svg, &usvg_options)
+        .map_err(|e| format!("Failed to prepare export: {}", e))?;
+
+    let mut pixmap = Pixmap::new(width as u32, height as u32)
+        .ok_or_else(|| "Failed to allocate export image".to_string())?;
+    if let Some(color) = background {
+        pixmap.fill(color);
+    }
+    resvg::render(&svg_tree, Transform::from_scale(scale as f32, scale as f32), &mut pixmap.as_mut());
+    Ok((pixmap, export.warnings))
+}
+
+/// Rasterize nodes of `tree` at `scale` pixels per canvas unit and encode
+/// them as `format`.
+pub fn render_raster(
+    tree: &DocumentTree,
+    format: RasterFormat,
+    scale: f64,
+    options: &RasterExportOptions,
+) -> Result<RenderedRaster, String> {
+    let background = match (options.background, format) {
+        (Some(bg), _) => Some(bg),
+        (None, RasterFormat::Jpeg) => Some(Rgba { r: 1.0, g: 1.0, b: 1.0, a: 1.0 }),
+        (None, _) => None,
+    };
+    // JPEG cannot store transparency, so its background is always opaque
+    let background = background.map(|bg| {
+        let alpha = if format == RasterFormat::Jpeg { 1.0 } else { bg.a };
+        Color::from_rgba(bg.r as f32, bg.g as f32, bg.b as f32, alpha as f32).unwrap_or(Color::WHITE)
+    });
+    let (pixmap, mut warnings) = render_pixmap(tree, scale, options.node_ids.clone(), options.padding, background)?;
+
+    let mut bytes = encode(&pixmap, format, scale, options.quality, options.lossless)?;
+    let mut optimization = None;
+    if options.optimize {
+        let before = bytes.len() as u64;
+        if format == RasterFormat::Png {
+            let optimized = optimize_png(&pixmap, scale)?;
+            if optimized.len() < bytes.len() {
+                bytes = optimized;
+            }
+        } else {
+            warnings.push("Only PNG exports are optimized; other formats are sized by their quality".to_string());
+        }
+        optimization = Some(SizeReport { before, after: bytes.len() as u64 });
+    }
+
+    Ok(RenderedRaster {
+        bytes,
+        width: pixmap.width(),
+        height: pixmap.height(),
+        warnings,
+        optimization,
+    })
+}
+
+/// Rasterize nodes at `scale` pixels per canvas unit. Rendering goes through
+/// the SVG exporter and resvg, so output is independent of the webview.
+#[command(async)]
+pub fn export_raster(
+    node_json: String,
+    format: RasterFormat,
+    scale: Option<f64>,
+    options: Option<RasterExportOptions>,
+) -> Result<RasterExport, String> {
+    let options = options.unwrap_or_default();
+    let tree = DocumentTree::parse(&node_json)?;
+    let raster = render_raster(&tree, format, scale.unwrap_or(1.0), &options)?;
+    Ok(RasterExport {
+        data: BASE64.encode(raster.bytes),
+        mime_type: format.mime_type(),
+        width: raster.width,
+        height: raster.height,
+        warnings: raster.warnings,
+        optimization: raster.optimization,
+    })
+}
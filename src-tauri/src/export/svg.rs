@@ -0,0 +1,1163 @@
+use super::{resolve_scope, ExportScope};
+use crate::assets::metadata::strip as strip_metadata;
+use crate::fonts::emoji::font_resolver;
+use crate::fonts::font_database;
+use crate::fonts::license::{embedding_restriction, subsetting_allowed};
+use crate::fonts::subset::{embeddable_face, subset_font};
+use crate::fonts::variations::instantiate;
+use crate::fonts::webfont::encode_woff2;
+use crate::geometry::markers::marked_stroke;
+use crate::geometry::stroke::dash_array;
+use crate::geometry::{compose, multiply, scale, translate, Matrix, Rect, IDENTITY};
+use crate::import::raster::SourceFormat;
+use crate::model::{DocumentTree, GradientStop, NodeData, NodeType, Paint, PathCommand, Rgba, ScaleMode, TextStyleRange, VectorPath, WindingRule};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use tauri::command;
+use usvg::tiny_skia_path::PathSegment;
+
+const SVG_NS: &str = "http://www.w3.org/2000/svg";
+const XLINK_NS: &str = "http://www.w3.org/1999/xlink";
+
+/// Options mirror `SVGExportOptions` in `src/persistence/export/svg-exporter.ts`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SvgExportOptions {
+    /// Nodes to export; defaults to the document root.
+    pub node_ids: Option<Vec<String>>,
+    pub include_xml_declaration: bool,
+    pub include_view_box: bool,
+    pub padding: f64,
+    /// Decimal places for coordinates.
+    pub precision: usize,
+    pub minify: bool,
+    /// Emit `id` attributes derived from layer names.
+    pub preserve_ids: bool,
+    /// Convert text to paths using installed fonts.
+    pub outline_text: bool,
+    /// Embed subsets of the fonts text uses, holding just the glyphs needed,
+    /// so the SVG looks the same where they are not installed.
+    pub embed_fonts: bool,
+    /// Embed fonts as WOFF2, for SVGs shown in browsers, which is smaller
+    /// than TrueType but not read by every SVG renderer.
+    pub woff2_fonts: bool,
+    /// Take EXIF, XMP and other metadata out of embedded images, so photos
+    /// don't carry where and when they were taken.
+    pub strip_metadata: bool,
+}
+
+impl Default for SvgExportOptions {
+    fn default() -> Self {
+        SvgExportOptions {
+            node_ids: None,
+            include_xml_declaration: true,
+            include_view_box: true,
+            padding: 0.0,
+            precision: 2,
+            minify: false,
+            preserve_ids: false,
+            outline_text: false,
+            embed_fonts: false,
+            woff2_fonts: false,
+            strip_metadata: false,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct SvgExport {
+    pub svg: String,
+    pub width: f64,
+    pub height: f64,
+    /// Node features that SVG cannot express and were dropped or approximated.
+    pub warnings: Vec<String>,
+}
+
+#[derive(Clone)]
+enum Content {
+    Element(Element),
+    Text(String),
+}
+
+#[derive(Clone)]
+struct Element {
+    tag: &'static str,
+    attrs: Vec<(&'static str, String)>,
+    children: Vec<Content>,
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+impl Element {
+    fn new(tag: &'static str) -> Self {
+        Element { tag, attrs: Vec::new(), children: Vec::new() }
+    }
+
+    fn attr(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.set(name, value);
+        self
+    }
+
+    fn set(&mut self, name: &'static str, value: impl Into<String>) {
+        let value = value.into();
+        match self.attrs.iter_mut().find(|(n, _)| *n == name) {
+            Some(attr) => attr.1 = value,
+            None => self.attrs.push((name, value)),
+        }
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.attrs.iter().any(|(n, _)| *n == name)
+    }
+
+    fn push(&mut self, child: Element) {
+        self.children.push(Content::Element(child));
+    }
+
+    fn text(mut self, text: impl Into<String>) -> Self {
+        self.children.push(Content::Text(text.into()));
+        self
+    }
+
+    /// Serialize with two-space indentation, or on one line when `indent`
+    /// is None. Text content is always written inline since whitespace
+    /// inside it is significant.
+    fn write(&self, out: &mut String, indent: Option<usize>) {
+        if let Some(depth) = indent {
+            out.push_str(&"  ".repeat(depth));
+        }
+        out.push('<');
+        out.push_str(self.tag);
+        for (name, value) in &self.attrs {
+            out.push_str(&format!(" {}=\"{}\"", name, escape(value)));
+        }
+
+        let inline = self.tag == "text" || self.children.iter().any(|c| matches!(c, Content::Text(_)));
+        if self.children.is_empty() {
+            out.push_str("/>");
+        } else {
+            out.push('>');
+            let child_indent = if inline { None } else { indent.map(|d| d + 1) };
+            if child_indent.is_some() {
+                out.push('\n');
+            }
+            for child in &self.children {
+                match child {
+                    Content::Element(el) => el.write(out, child_indent),
+                    Content::Text(text) => out.push_str(&escape(text)),
+                }
+            }
+            if let (false, Some(depth)) = (inline, indent) {
+                out.push_str(&"  ".repeat(depth));
+            }
+            out.push_str(&format!("</{}>", self.tag));
+        }
+        if indent.is_some() {
+            out.push('\n');
+        }
+    }
+}
+
+struct Format {
+    precision: usize,
+}
+
+/// A pattern's source drawn into defs, and the size it's drawn at.
+#[derive(Clone)]
+struct PatternTile {
+    id: String,
+    width: f64,
+    height: f64,
+}
+
+impl Format {
+    fn num(&self, v: f64) -> String {
+        let s = format!("{:.*}", self.precision, v);
+        let s = if s.contains('.') { s.trim_end_matches('0').trim_end_matches('.') } else { &s };
+        if s == "-0" { "0".to_string() } else { s.to_string() }
+    }
+
+    fn transform(&self, m: &Matrix) -> String {
+        if m[..4] == IDENTITY[..4] {
+            format!("translate({} {})", self.num(m[4]), self.num(m[5]))
+        } else {
+            let parts: Vec<String> = m.iter().map(|v| self.num(*v)).collect();
+            format!("matrix({})", parts.join(" "))
+        }
+    }
+
+    fn path(&self, path: &VectorPath) -> String {
+        let mut d = String::new();
+        for cmd in &path.commands {
+            match *cmd {
+                PathCommand::MoveTo { x, y } => d.push_str(&format!("M{} {}", self.num(x), self.num(y))),
+                PathCommand::LineTo { x, y } => d.push_str(&format!("L{} {}", self.num(x), self.num(y))),
+                PathCommand::CurveTo { x1, y1, x2, y2, x, y } => d.push_str(&format!(
+                    "C{} {} {} {} {} {}",
+                    self.num(x1),
+                    self.num(y1),
+                    self.num(x2),
+                    self.num(y2),
+                    self.num(x),
+                    self.num(y)
+                )),
+                PathCommand::ClosePath => d.push('Z'),
+            }
+        }
+        d
+    }
+
+    fn skia_path(&self, path: &usvg::tiny_skia_path::Path) -> String {
+        let p = |pt: usvg::tiny_skia_path::Point| format!("{} {}", self.num(pt.x as f64), self.num(pt.y as f64));
+        let mut d = String::new();
+        for segment in path.segments() {
+            match segment {
+                PathSegment::MoveTo(a) => d.push_str(&format!("M{}", p(a))),
+                PathSegment::LineTo(a) => d.push_str(&format!("L{}", p(a))),
+                PathSegment::QuadTo(a, b) => d.push_str(&format!("Q{} {}", p(a), p(b))),
+                PathSegment::CubicTo(a, b, c) => d.push_str(&format!("C{} {} {}", p(a), p(b), p(c))),
+                PathSegment::Close => d.push('Z'),
+            }
+        }
+        d
+    }
+}
+
+fn blend_mode_css(mode: &str) -> Option<String> {
+    match mode {
+        "NORMAL" | "PASS_THROUGH" => None,
+        other => Some(other.to_lowercase().replace('_', "-")),
+    }
+}
+
+fn font_family_value(family: &str) -> String {
+    if family.contains([' ', ',']) { format!("'{}'", family) } else { family.to_string() }
+}
+
+struct Exporter<'a> {
+    scope: &'a ExportScope<'a>,
+    options: &'a SvgExportOptions,
+    fmt: Format,
+    defs: Vec<Element>,
+    used_ids: HashSet<String>,
+    uses_xlink: bool,
+    /// Text drawn with each (family, weight), for embedding fonts.
+    font_usage: BTreeMap<(String, u16), Vec<String>>,
+    /// Pattern sources drawn once into defs, by id and scale.
+    pattern_tiles: HashMap<(String, u64), Option<PatternTile>>,
+    /// Pattern sources being drawn, so one can't contain itself.
+    tiling: Vec<String>,
+    warnings: Vec<String>,
+}
+
+impl Exporter<'_> {
+    fn warn(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        if !self.warnings.contains(&message) {
+            self.warnings.push(message);
+        }
+    }
+
+    fn unique_id(&mut self, base: &str) -> String {
+        let mut id = base.to_string();
+        let mut n = 1;
+        while !self.used_ids.insert(id.clone()) {
+            n += 1;
+            id = format!("{}-{}", base, n);
+        }
+        id
+    }
+
+    fn def(&mut self, prefix: &str, mut element: Element) -> String {
+        let id = self.unique_id(prefix);
+        element.set("id", id.clone());
+        self.defs.push(element);
+        format!("url(#{})", id)
+    }
+
+    /// An XML id derived from the layer name.
+    fn layer_id(&mut self, node: &NodeData) -> String {
+        let mut base: String = node
+            .name
+            .trim()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+            .collect();
+        if base.is_empty() {
+            base = "layer".to_string();
+        }
+        if !base.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            base.insert(0, '_');
+        }
+        self.unique_id(&base)
+    }
+
+    /// `source` drawn once at `factor` times its size, from the top left of
+    /// its bounds, for the patterns that tile it to share.
+    fn pattern_tile(&mut self, source: &str, factor: f64) -> Option<PatternTile> {
+        let key = (source.to_string(), factor.to_bits());
+        if let Some(tile) = self.pattern_tiles.get(&key) {
+            return tile.clone();
+        }
+        if self.tiling.iter().any(|id| id == source) {
+            self.warn("Patterns that contain themselves were left out");
+            return None;
+        }
+
+        let tree = self.scope.tree;
+        let tile = tree.world_bounds(source).filter(|b| b.width > 0.0 && b.height > 0.0).and_then(|bounds| {
+            let placed = multiply(&scale(factor, factor), &translate(-bounds.x, -bounds.y));
+            self.tiling.push(source.to_string());
+            let el = self.node(source, multiply(&placed, &tree.world_transform(source)));
+            self.tiling.pop();
+            let mut el = el?;
+            let id = self.unique_id("pattern-tile");
+            el.set("id", id.clone());
+            self.defs.push(el);
+            Some(PatternTile { id, width: bounds.width * factor, height: bounds.height * factor })
+        });
+        if tile.is_none() {
+            self.warn("Some pattern fills have no source to tile and were left out");
+        }
+        self.pattern_tiles.insert(key, tile.clone());
+        tile
+    }
+
+    fn image_href(&mut self, image_ref: &str) -> String {
+        self.uses_xlink = true;
+        if !self.options.strip_metadata {
+            return image_ref.to_string();
+        }
+        let embedded = image_ref.strip_prefix("data:").and_then(|rest| rest.split_once(";base64,"));
+        let Some((mime, data)) = embedded else { return image_ref.to_string() };
+        let Ok(bytes) = BASE64.decode(data.as_bytes()) else { return image_ref.to_string() };
+        match strip_metadata(&bytes) {
+            Some(stripped) => format!("data:{};base64,{}", mime, BASE64.encode(stripped)),
+            None => {
+                if SourceFormat::detect(&bytes).is_some() {
+                    self.warn("Metadata could not be removed from HEIC, AVIF and JPEG XL images");
+                }
+                image_ref.to_string()
+            }
+        }
+    }
+
+    /// Paint as an attribute value plus its opacity. `bounds` is the node's
+    /// local box, which gradient and image transforms are relative to.
+    fn paint(&mut self, paint: &Paint, bounds: Rect) -> Option<(String, f64)> {
+        if !paint.visible() {
+            return None;
+        }
+        let unit = multiply(&translate(bounds.x, bounds.y), &scale(bounds.width, bounds.height));
+        let stops = |stops: &[GradientStop], fmt: &Format| {
+            stops
+                .iter()
+                .map(|s| {
+                    let mut stop = Element::new("stop")
+                        .attr("offset", fmt.num(s.position))
+                        .attr("stop-color", s.color.hex());
+                    if s.color.a < 1.0 {
+                        stop.set("stop-opacity", fmt.num(s.color.a));
+                    }
+                    stop
+                })
+                .collect::<Vec<_>>()
+        };
+
+        match paint {
+            Paint::Solid { color, opacity, .. } => Some((color.hex(), color.a * opacity)),
+            Paint::GradientLinear { gradient_stops, gradient_transform, opacity, .. } => {
+                let mut el = Element::new("linearGradient")
+                    .attr("gradientUnits", "userSpaceOnUse")
+                    .attr("x1", "0")
+                    .attr("y1", "0.5")
+                    .attr("x2", "1")
+                    .attr("y2", "0.5")
+                    .attr("gradientTransform", self.fmt.transform(&multiply(&unit, gradient_transform)));
+                for stop in stops(gradient_stops, &self.fmt) {
+                    el.push(stop);
+                }
+                Some((self.def("gradient", el), *opacity))
+            }
+            Paint::GradientRadial { gradient_stops, gradient_transform, opacity, .. } => {
+                let mut el = Element::new("radialGradient")
+                    .attr("gradientUnits", "userSpaceOnUse")
+                    .attr("cx", "0.5")
+                    .attr("cy", "0.5")
+                    .attr("r", "0.5")
+                    .attr("gradientTransform", self.fmt.transform(&multiply(&unit, gradient_transform)));
+                for stop in stops(gradient_stops, &self.fmt) {
+                    el.push(stop);
+                }
+                Some((self.def("gradient", el), *opacity))
+            }
+            Paint::Image { image_ref, scale_mode, image_transform, opacity, .. } => {
+                if *scale_mode == ScaleMode::Tile {
+                    self.warn("Tiled image fills are exported as cropped fills");
+                }
+                let mut image = Element::new("image")
+                    .attr("width", self.fmt.num(bounds.width))
+                    .attr("height", self.fmt.num(bounds.height))
+                    .attr("preserveAspectRatio", if *scale_mode == ScaleMode::Fit { "xMidYMid meet" } else { "xMidYMid slice" })
+                    .attr("xlink:href", self.image_href(image_ref));
+                if *image_transform != IDENTITY {
+                    image.set("transform", self.fmt.transform(image_transform));
+                }
+                let mut pattern = Element::new("pattern")
+                    .attr("patternUnits", "userSpaceOnUse")
+                    .attr("x", self.fmt.num(bounds.x))
+                    .attr("y", self.fmt.num(bounds.y))
+                    .attr("width", self.fmt.num(bounds.width))
+                    .attr("height", self.fmt.num(bounds.height));
+                pattern.push(image);
+                Some((self.def("pattern", pattern), *opacity))
+            }
+            Paint::Pattern { source_node_id, tile_type, scaling_factor, spacing, rotation, opacity, .. } => {
+                let tile = self.pattern_tile(source_node_id, *scaling_factor)?;
+                let ((cell_width, cell_height), offsets) = tile_type.layout((tile.width + spacing.x, tile.height + spacing.y));
+                let placement = compose(bounds.x, bounds.y, rotation.to_radians(), 1.0, 1.0);
+                let mut pattern = Element::new("pattern")
+                    .attr("patternUnits", "userSpaceOnUse")
+                    .attr("width", self.fmt.num(cell_width))
+                    .attr("height", self.fmt.num(cell_height))
+                    .attr("patternTransform", self.fmt.transform(&placement));
+                self.uses_xlink = true;
+                for (x, y) in offsets {
+                    let mut el = Element::new("use").attr("xlink:href", format!("#{}", tile.id));
+                    if (x, y) != (0.0, 0.0) {
+                        el.set("x", self.fmt.num(x));
+                        el.set("y", self.fmt.num(y));
+                    }
+                    pattern.push(el);
+                }
+                Some((self.def("pattern", pattern), *opacity))
+            }
+        }
+    }
+
+    fn set_paint(&mut self, el: &mut Element, attr: &'static str, paint: &Paint, bounds: Rect) -> bool {
+        let Some((value, opacity)) = self.paint(paint, bounds) else { return false };
+        el.set(attr, value);
+        if opacity < 1.0 {
+            let name = if attr == "fill" { "fill-opacity" } else { "stroke-opacity" };
+            el.set(name, self.fmt.num(opacity));
+        }
+        true
+    }
+
+    fn set_stroke(&mut self, el: &mut Element, node: &NodeData, paint: &Paint, width: f64, bounds: Rect) -> bool {
+        if !self.set_paint(el, "stroke", paint, bounds) {
+            return false;
+        }
+        el.set("stroke-width", self.fmt.num(width));
+        match node.stroke_cap.as_deref() {
+            Some("ROUND") => el.set("stroke-linecap", "round"),
+            Some("SQUARE") => el.set("stroke-linecap", "square"),
+            _ => {}
+        }
+        match node.stroke_join.as_deref() {
+            Some("ROUND") => el.set("stroke-linejoin", "round"),
+            Some("BEVEL") => el.set("stroke-linejoin", "bevel"),
+            _ => {}
+        }
+        if let Some(limit) = node.stroke_miter_limit.filter(|l| *l != 4.0) {
+            el.set("stroke-miterlimit", self.fmt.num(limit));
+        }
+        if let Some(dashes) = node.dash_pattern.as_deref().and_then(dash_array) {
+            let dashes: Vec<String> = dashes.iter().map(|d| self.fmt.num(*d)).collect();
+            el.set("stroke-dasharray", dashes.join(" "));
+            if let Some(offset) = node.dash_offset.filter(|o| *o != 0.0) {
+                el.set("stroke-dashoffset", self.fmt.num(offset));
+            }
+        }
+        true
+    }
+
+    /// Paint-less geometry of a node in its local space.
+    fn shapes(&self, node: &NodeData) -> Vec<Element> {
+        let fmt = &self.fmt;
+        if let Some(paths) = node.fitted_vector_paths() {
+            return paths
+                .iter()
+                .map(|p| {
+                    let el = Element::new("path").attr("d", fmt.path(p));
+                    match p.winding_rule {
+                        WindingRule::Evenodd => el.attr("fill-rule", "evenodd"),
+                        WindingRule::Nonzero => el,
+                    }
+                })
+                .collect();
+        }
+
+        let Some(bounds) = node.local_bounds().filter(|b| b.width > 0.0 && b.height > 0.0) else {
+            return Vec::new();
+        };
+        let (w, h) = (bounds.width, bounds.height);
+
+        match node.node_type {
+            NodeType::Ellipse => vec![Element::new("ellipse")
+                .attr("cx", fmt.num(w / 2.0))
+                .attr("cy", fmt.num(h / 2.0))
+                .attr("rx", fmt.num(w / 2.0))
+                .attr("ry", fmt.num(h / 2.0))],
+            NodeType::Frame | NodeType::Component | NodeType::Instance | NodeType::Rectangle | NodeType::Image => {
+                let mut rect = Element::new("rect").attr("width", fmt.num(w)).attr("height", fmt.num(h));
+                let radius = node.corner_radius.unwrap_or(0.0).min(w / 2.0).min(h / 2.0);
+                if radius > 0.0 {
+                    rect.set("rx", fmt.num(radius));
+                }
+                vec![rect]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn clip_path(&mut self, shapes: &[Element]) -> String {
+        let mut clip = Element::new("clipPath");
+        for shape in shapes {
+            let mut shape = shape.clone();
+            if let Some(rule) = shape.attrs.iter_mut().find(|(n, _)| *n == "fill-rule") {
+                rule.0 = "clip-rule";
+            }
+            clip.push(shape);
+        }
+        self.def("clip", clip)
+    }
+
+    /// Mask hiding everything inside `shapes`, for outside-aligned strokes.
+    fn outside_mask(&mut self, shapes: &[Element], bounds: Rect, margin: f64) -> String {
+        let area = |el: Element, fmt: &Format| {
+            el.attr("x", fmt.num(bounds.x - margin))
+                .attr("y", fmt.num(bounds.y - margin))
+                .attr("width", fmt.num(bounds.width + margin * 2.0))
+                .attr("height", fmt.num(bounds.height + margin * 2.0))
+        };
+        let mut mask = area(Element::new("mask").attr("maskUnits", "userSpaceOnUse"), &self.fmt);
+        mask.push(area(Element::new("rect"), &self.fmt).attr("fill", "#fff"));
+        for shape in shapes {
+            mask.push(shape.clone().attr("fill", "#000"));
+        }
+        self.def("mask", mask)
+    }
+
+    fn paint_shapes(&mut self, g: &mut Element, node: &NodeData, bounds: Rect) {
+        let shapes = self.shapes(node);
+        if shapes.is_empty() {
+            return;
+        }
+
+        let fills: Vec<&Paint> = node.fills().iter().filter(|p| p.visible()).collect();
+        let weight = node.stroke_weight();
+        let strokes: Vec<&Paint> = if weight > 0.0 {
+            node.strokes().iter().filter(|p| p.visible()).collect()
+        } else {
+            Vec::new()
+        };
+        let align = node.stroke_align.as_deref().unwrap_or("CENTER");
+        let marked = marked_stroke(node);
+
+        // The common single fill + centered stroke case is one element per shape
+        if fills.len() <= 1 && strokes.len() == 1 && align == "CENTER" && marked.is_none() {
+            for shape in &shapes {
+                let mut el = shape.clone();
+                if !fills.first().is_some_and(|f| self.set_paint(&mut el, "fill", f, bounds)) {
+                    el.set("fill", "none");
+                }
+                self.set_stroke(&mut el, node, strokes[0], weight, bounds);
+                g.push(el);
+            }
+            return;
+        }
+
+        for fill in &fills {
+            for shape in &shapes {
+                let mut el = shape.clone();
+                if self.set_paint(&mut el, "fill", fill, bounds) {
+                    g.push(el);
+                }
+            }
+        }
+
+        if strokes.is_empty() {
+            return;
+        }
+
+        // SVG strokes are centered; inside/outside strokes are drawn at double
+        // width and clipped or masked to one side of the outline
+        let (width, side) = match align {
+            "INSIDE" => (weight * 2.0, Some(("clip-path", self.clip_path(&shapes)))),
+            "OUTSIDE" => (weight * 2.0, Some(("mask", self.outside_mask(&shapes, bounds, weight * 4.0)))),
+            _ => (weight, None),
+        };
+        // Markers cut the stroke back and are filled with its paints
+        let lines: Vec<Element> = match &marked {
+            Some(m) => m.paths.iter().map(|p| Element::new("path").attr("d", self.fmt.path(p))).collect(),
+            None => shapes,
+        };
+        for stroke in &strokes {
+            for line in &lines {
+                let mut el = line.clone().attr("fill", "none");
+                if self.set_stroke(&mut el, node, stroke, width, bounds) {
+                    if let Some((attr, value)) = &side {
+                        el.set(attr, value.clone());
+                    }
+                    g.push(el);
+                }
+            }
+            if let Some(heads) = marked.as_ref().map(|m| &m.heads).filter(|h| !h.commands.is_empty()) {
+                let mut el = Element::new("path").attr("d", self.fmt.path(heads));
+                if self.set_paint(&mut el, "fill", stroke, bounds) {
+                    g.push(el);
+                }
+            }
+        }
+    }
+
+    fn image_node(&mut self, g: &mut Element, node: &NodeData, bounds: Rect) {
+        let Some(image_ref) = node.image_ref.as_deref() else { return };
+        let preserve = match node.scale_mode.unwrap_or_default() {
+            ScaleMode::Fit => "xMidYMid meet",
+            _ => "xMidYMid slice",
+        };
+        let mut image = Element::new("image")
+            .attr("width", self.fmt.num(bounds.width))
+            .attr("height", self.fmt.num(bounds.height))
+            .attr("preserveAspectRatio", preserve)
+            .attr("xlink:href", self.image_href(image_ref));
+        if node.corner_radius.unwrap_or(0.0) > 0.0 {
+            let shapes = self.shapes(node);
+            image.set("clip-path", self.clip_path(&shapes));
+        }
+        g.push(image);
+    }
+
+    /// SVG filter for the node's effects, if any can be expressed.
+    fn filter(&mut self, node: &NodeData, bounds: Rect) -> Option<String> {
+        let effects = node.effects.as_ref()?;
+        let mut primitives: Vec<Element> = Vec::new();
+        let mut under = Vec::new();
+        let mut over = Vec::new();
+        let mut source = "SourceGraphic".to_string();
+        let mut margin: f64 = 0.0;
+
+        for (i, effect) in effects.iter().enumerate() {
+            if effect.get("visible").and_then(Value::as_bool) == Some(false) {
+                continue;
+            }
+            let kind = effect.get("type").and_then(Value::as_str).unwrap_or_default();
+            let radius = effect.get("radius").and_then(Value::as_f64).unwrap_or(0.0);
+            let spread = effect.get("spread").and_then(Value::as_f64).unwrap_or(0.0);
+            let offset = |axis: &str| effect.pointer(&format!("/offset/{}", axis)).and_then(Value::as_f64).unwrap_or(0.0);
+            let (dx, dy) = (offset("x"), offset("y"));
+            let color: Rgba = effect
+                .get("color")
+                .and_then(|c| serde_json::from_value(c.clone()).ok())
+                .unwrap_or(Rgba { r: 0.0, g: 0.0, b: 0.0, a: 0.25 });
+            let deviation = self.fmt.num(radius / 2.0);
+            let result = format!("effect{}", i + 1);
+
+            let flood = |fmt: &Format| {
+                Element::new("feFlood")
+                    .attr("flood-color", color.hex())
+                    .attr("flood-opacity", fmt.num(color.a))
+                    .attr("result", format!("{}-color", result))
+            };
+
+            match kind {
+                "DROP_SHADOW" | "INNER_SHADOW" => {
+                    if spread != 0.0 {
+                        self.warn("Shadow spread is not supported in SVG export");
+                    }
+                    margin = margin.max(radius * 1.5 + dx.abs().max(dy.abs()));
+                    primitives.push(
+                        Element::new("feOffset")
+                            .attr("in", "SourceAlpha")
+                            .attr("dx", self.fmt.num(dx))
+                            .attr("dy", self.fmt.num(dy))
+                            .attr("result", format!("{}-offset", result)),
+                    );
+                    primitives.push(
+                        Element::new("feGaussianBlur")
+                            .attr("in", format!("{}-offset", result))
+                            .attr("stdDeviation", deviation)
+                            .attr("result", format!("{}-blur", result)),
+                    );
+                    primitives.push(flood(&self.fmt));
+                    if kind == "DROP_SHADOW" {
+                        primitives.push(
+                            Element::new("feComposite")
+                                .attr("in", format!("{}-color", result))
+                                .attr("in2", format!("{}-blur", result))
+                                .attr("operator", "in")
+                                .attr("result", result.clone()),
+                        );
+                        under.push(result);
+                    } else {
+                        // The part of the shape not covered by its shifted, blurred copy
+                        primitives.push(
+                            Element::new("feComposite")
+                                .attr("in", "SourceAlpha")
+                                .attr("in2", format!("{}-blur", result))
+                                .attr("operator", "out")
+                                .attr("result", format!("{}-edge", result)),
+                        );
+                        primitives.push(
+                            Element::new("feComposite")
+                                .attr("in", format!("{}-color", result))
+                                .attr("in2", format!("{}-edge", result))
+                                .attr("operator", "in")
+                                .attr("result", result.clone()),
+                        );
+                        over.push(result);
+                    }
+                }
+                "BLUR" => {
+                    margin = margin.max(radius * 1.5);
+                    primitives.push(
+                        Element::new("feGaussianBlur")
+                            .attr("in", source.clone())
+                            .attr("stdDeviation", deviation)
+                            .attr("result", result.clone()),
+                    );
+                    source = result;
+                }
+                other => self.warn(format!("{} effects are not supported in SVG export", other)),
+            }
+        }
+
+        if primitives.is_empty() {
+            return None;
+        }
+
+        let mut merge = Element::new("feMerge");
+        for input in under.iter().chain(std::iter::once(&source)).chain(over.iter()) {
+            merge.push(Element::new("feMergeNode").attr("in", input.clone()));
+        }
+        primitives.push(merge);
+
+        let mut filter = Element::new("filter")
+            .attr("filterUnits", "userSpaceOnUse")
+            .attr("x", self.fmt.num(bounds.x - margin))
+            .attr("y", self.fmt.num(bounds.y - margin))
+            .attr("width", self.fmt.num(bounds.width + margin * 2.0))
+            .attr("height", self.fmt.num(bounds.height + margin * 2.0))
+            .attr("color-interpolation-filters", "sRGB");
+        for primitive in primitives {
+            filter.push(primitive);
+        }
+        Some(self.def("filter", filter))
+    }
+
+    /// Text as `<text>` with one `<tspan>` per line; `solid_only` replaces
+    /// gradient and image fills with a flat color.
+    fn text(&mut self, node: &NodeData, bounds: Rect, solid_only: bool) -> Element {
+        let characters: Vec<char> = node.characters.as_deref().unwrap_or_default().chars().collect();
+        let mut styles = node.text_styles.clone().unwrap_or_default();
+        styles.sort_by_key(|s| s.start);
+        let fallback = TextStyleRange::unstyled(characters.len());
+        let base = styles.first().cloned().unwrap_or(fallback.clone());
+        let style_at = |i: usize| styles.iter().find(|s| s.start <= i && i < s.end).unwrap_or(&base);
+
+        let fill_attrs = |exporter: &mut Self, el: &mut Element, style: &TextStyleRange| {
+            let fills = if style.fills.is_empty() { node.fills() } else { &style.fills };
+            let Some(fill) = fills.iter().find(|f| f.visible()) else { return };
+            if solid_only {
+                if let Some(color) = match fill {
+                    Paint::Solid { color, .. } => Some(*color),
+                    Paint::GradientLinear { gradient_stops, .. } | Paint::GradientRadial { gradient_stops, .. } => {
+                        gradient_stops.first().map(|s| s.color)
+                    }
+                    Paint::Image { .. } | Paint::Pattern { .. } => None,
+                } {
+                    el.set("fill", color.hex());
+                }
+            } else {
+                exporter.set_paint(el, "fill", fill, bounds);
+            }
+        };
+
+        let (anchor, x) = match node.text_align_horizontal.as_deref() {
+            Some("CENTER") => ("middle", bounds.width / 2.0),
+            Some("RIGHT") => ("end", bounds.width),
+            _ => ("start", 0.0),
+        };
+
+        let mut text = Element::new("text")
+            .attr("xml:space", "preserve")
+            .attr("font-family", font_family_value(&base.font_family))
+            .attr("font-size", self.fmt.num(base.font_size));
+        if base.font_weight != 400 {
+            text.set("font-weight", base.font_weight.to_string());
+        }
+        if anchor != "start" {
+            text.set("text-anchor", anchor);
+        }
+        if base.letter_spacing != 0.0 {
+            text.set("letter-spacing", self.fmt.num(base.letter_spacing));
+        }
+        fill_attrs(self, &mut text, &base);
+
+        // Split into lines, each a list of (style, text) runs
+        let mut lines: Vec<Vec<(&TextStyleRange, String)>> = vec![Vec::new()];
+        for (i, c) in characters.iter().enumerate() {
+            if *c == '\n' {
+                lines.push(Vec::new());
+                continue;
+            }
+            let style = style_at(i);
+            let line = lines.last_mut().expect("lines is never empty");
+            match line.last_mut() {
+                Some((s, run)) if std::ptr::eq(*s, style) => run.push(*c),
+                _ => line.push((style, c.to_string())),
+            }
+        }
+
+        let metrics: Vec<(f64, f64)> = lines
+            .iter()
+            .map(|runs| {
+                let styles = runs.iter().map(|(s, _)| *s).chain(runs.is_empty().then_some(&base));
+                styles.fold((0.0, 0.0), |(lh, size), s| (f64::max(lh, s.line_height_px()), f64::max(size, s.font_size)))
+            })
+            .collect();
+        let total: f64 = metrics.iter().map(|(lh, _)| lh).sum();
+        let mut y = match node.text_align_vertical.as_deref() {
+            Some("CENTER") => (bounds.height - total) / 2.0,
+            Some("BOTTOM") => bounds.height - total,
+            _ => 0.0,
+        };
+
+        for (runs, (line_height, size)) in lines.iter().zip(metrics) {
+            // Approximate ascent as 0.8em, centered in the line box
+            let baseline = y + (line_height - size) / 2.0 + size * 0.8;
+            let mut line = Element::new("tspan").attr("x", self.fmt.num(x)).attr("y", self.fmt.num(baseline));
+
+            for (style, run) in runs {
+                if self.options.embed_fonts && !solid_only {
+                    let key = (style.font_family.clone(), style.font_weight);
+                    self.font_usage.entry(key).or_default().push(run.clone());
+                }
+                if std::ptr::eq(*style, &base) || *style == &base {
+                    line.children.push(Content::Text(run.clone()));
+                    continue;
+                }
+                let mut span = Element::new("tspan");
+                if style.font_family != base.font_family {
+                    span.set("font-family", font_family_value(&style.font_family));
+                }
+                if style.font_size != base.font_size {
+                    span.set("font-size", self.fmt.num(style.font_size));
+                }
+                if style.font_weight != base.font_weight {
+                    span.set("font-weight", style.font_weight.to_string());
+                }
+                if style.letter_spacing != base.letter_spacing {
+                    span.set("letter-spacing", self.fmt.num(style.letter_spacing));
+                }
+                match style.text_decoration.as_deref() {
+                    Some("UNDERLINE") => span.set("text-decoration", "underline"),
+                    Some("STRIKETHROUGH") => span.set("text-decoration", "line-through"),
+                    _ => {}
+                }
+                if style.fills != base.fills {
+                    fill_attrs(self, &mut span, style);
+                }
+                line.push(span.text(run.clone()));
+            }
+            text.push(line);
+            y += line_height;
+        }
+
+        match base.text_decoration.as_deref() {
+            Some("UNDERLINE") => text.set("text-decoration", "underline"),
+            Some("STRIKETHROUGH") => text.set("text-decoration", "line-through"),
+            _ => {}
+        }
+        text
+    }
+
+    /// Text converted to paths by laying it out with usvg. Returns None when
+    /// no glyphs could be produced, e.g. because the font is not installed.
+    fn outlined_text(&mut self, node: &NodeData, bounds: Rect) -> Option<Element> {
+        let text = self.text(node, bounds, true);
+        let mut doc = Element::new("svg")
+            .attr("xmlns", SVG_NS)
+            .attr("width", self.fmt.num(bounds.width.max(1.0)))
+            .attr("height", self.fmt.num(bounds.height.max(1.0)));
+        doc.push(text);
+        let mut source = String::new();
+        doc.write(&mut source, None);
+
+        let options = usvg::Options { fontdb: font_database(), font_resolver: font_resolver(), ..Default::default() };
+        let tree = usvg::Tree::from_str(&source, &options).ok()?;
+
+        fn collect(group: &usvg::Group, out: &mut Vec<(usvg::tiny_skia_path::Path, Option<usvg::Color>)>) {
+            for child in group.children() {
+                match child {
+                    usvg::Node::Group(g) => collect(g, out),
+                    usvg::Node::Text(t) => collect(t.flattened(), out),
+                    usvg::Node::Path(p) => {
+                        if let Some(path) = p.data().clone().transform(p.abs_transform()) {
+                            let color = p.fill().and_then(|f| match f.paint() {
+                                usvg::Paint::Color(c) => Some(*c),
+                                _ => None,
+                            });
+                            out.push((path, color));
+                        }
+                    }
+                    usvg::Node::Image(_) => {}
+                }
+            }
+        }
+        let mut paths = Vec::new();
+        collect(tree.root(), &mut paths);
+        if paths.is_empty() {
+            return None;
+        }
+
+        // With a single fill for the whole node, the real paint (which may be a
+        // gradient) goes on the group; otherwise each glyph keeps its run color
+        let styles = node.text_styles.as_deref().unwrap_or_default();
+        let uniform = styles.windows(2).all(|w| w[0].fills == w[1].fills);
+        let fill = styles.first().map(|s| s.fills.as_slice()).filter(|f| !f.is_empty()).unwrap_or(node.fills());
+
+        let mut g = Element::new("g");
+        let shared = uniform && fill.first().is_some_and(|f| self.set_paint(&mut g, "fill", f, bounds));
+        for (path, color) in paths {
+            let mut el = Element::new("path").attr("d", self.fmt.skia_path(&path));
+            if !shared {
+                if let Some(c) = color {
+                    el.set("fill", format!("#{:02x}{:02x}{:02x}", c.red, c.green, c.blue));
+                }
+            }
+            g.push(el);
+        }
+        Some(g)
+    }
+
+    /// `@font-face` rules embedding a subset of each font used by text whose
+    /// license allows it.
+    fn font_faces(&mut self) -> Option<Element> {
+        let mut css = String::new();
+        for ((family, weight), texts) in std::mem::take(&mut self.font_usage) {
+            let Some(face) = embeddable_face(&family, weight) else {
+                self.warn(format!("{} was not embedded because it is not installed", family));
+                continue;
+            };
+            if let Some(reason) = embedding_restriction(&face.data) {
+                self.warn(format!("{} was not embedded because {}", family, reason));
+                continue;
+            }
+            // Fonts that forbid subsetting are embedded whole
+            let subset = if subsetting_allowed(&face.data) {
+                subset_font(&face.data, &face.coordinates, &texts)
+            } else {
+                instantiate(&face.data, &face.coordinates, None)
+            };
+            let packed = if self.options.woff2_fonts {
+                subset.and_then(|font| encode_woff2(&font)).map(|font| (font, "font/woff2", "woff2"))
+            } else {
+                subset.map(|font| (font, "font/ttf", "truetype"))
+            };
+            match packed {
+                Ok((font, mime_type, format)) => css.push_str(&format!(
+                    "@font-face{{font-family:'{}';font-weight:{};src:url(data:{};base64,{}) format('{}')}}",
+                    family.replace('\\', "\\\\").replace('\'', "\\'"),
+                    weight,
+                    mime_type,
+                    BASE64.encode(font),
+                    format
+                )),
+                Err(e) => self.warn(format!("{} was not embedded: {}", family, e)),
+            }
+        }
+        (!css.is_empty()).then(|| Element::new("style").text(css))
+    }
+
+    fn node(&mut self, id: &str, transform: Matrix) -> Option<Element> {
+        let tree = self.scope.tree;
+        let node = tree.get(id)?;
+        if !node.visible {
+            return None;
+        }
+
+        let bounds = node.local_bounds().unwrap_or(Rect::new(0.0, 0.0, 0.0, 0.0));
+        let mut g = Element::new("g");
+        if self.options.preserve_ids {
+            g.set("id", self.layer_id(node));
+        }
+        if transform != IDENTITY {
+            g.set("transform", self.fmt.transform(&transform));
+        }
+        if node.opacity() < 1.0 {
+            g.set("opacity", self.fmt.num(node.opacity()));
+        }
+        if let Some(mode) = node.blend_mode.as_deref().and_then(blend_mode_css) {
+            g.set("style", format!("mix-blend-mode:{}", mode));
+        }
+        if let Some(filter) = self.filter(node, bounds) {
+            g.set("filter", filter);
+        }
+
+        match node.node_type {
+            NodeType::Text => {
+                let outlined = if self.options.outline_text {
+                    let outlined = self.outlined_text(node, bounds);
+                    if outlined.is_none() {
+                        self.warn("Some text could not be outlined because its font is not installed");
+                    }
+                    outlined
+                } else {
+                    None
+                };
+                let text = outlined.unwrap_or_else(|| self.text(node, bounds, false));
+                g.push(text);
+            }
+            NodeType::Image if node.fills().is_empty() => self.image_node(&mut g, node, bounds),
+            _ => self.paint_shapes(&mut g, node, bounds),
+        }
+
+        let children = tree.children(id);
+        if !children.is_empty() {
+            let mut clip = None;
+            if node.clips_content.unwrap_or(false) {
+                let shapes = self.shapes(node);
+                if !shapes.is_empty() {
+                    clip = Some(Element::new("g").attr("clip-path", self.clip_path(&shapes)));
+                }
+            }
+            for child in children {
+                let local = tree.get(child).map(NodeData::local_transform).unwrap_or(IDENTITY);
+                if let Some(el) = self.node(child, local) {
+                    match clip.as_mut() {
+                        Some(clip) => clip.push(el),
+                        None => g.push(el),
+                    }
+                }
+            }
+            if let Some(clip) = clip.filter(|c| !c.children.is_empty()) {
+                g.push(clip);
+            }
+        }
+
+        if g.children.is_empty() {
+            return None;
+        }
+        Some(collapse(g))
+    }
+}
+
+/// Fold a group's transform and id into its only child where that does not
+/// change the result.
+fn collapse(mut g: Element) -> Element {
+    let movable = g.attrs.iter().all(|(n, _)| *n == "transform" || *n == "id");
+    if !movable || g.children.len() != 1 {
+        return g;
+    }
+    let Some(Content::Element(child)) = g.children.first() else { return g };
+    if child.has("transform") || child.has("id") {
+        return g;
+    }
+
+    let Some(Content::Element(mut child)) = g.children.pop() else { unreachable!() };
+    for (name, value) in g.attrs {
+        child.set(name, value);
+    }
+    child
+}
+
+pub fn write_svg(scope: &ExportScope, options: &SvgExportOptions) -> SvgExport {
+    let bounds = scope.bounds;
+    let mut exporter = Exporter {
+        scope,
+        options,
+        fmt: Format { precision: options.precision.min(8) },
+        defs: Vec::new(),
+        used_ids: HashSet::new(),
+        uses_xlink: false,
+        font_usage: BTreeMap::new(),
+        pattern_tiles: HashMap::new(),
+        tiling: Vec::new(),
+        warnings: Vec::new(),
+    };
+
+    let mut body = Vec::new();
+    let origin = translate(-bounds.x, -bounds.y);
+
+    if let [id] = scope.ids.as_slice() {
+        if let Some(background) = scope.tree.get(id).filter(|n| n.node_type == NodeType::Page).and_then(|n| n.background_color) {
+            body.push(
+                Element::new("rect")
+                    .attr("width", exporter.fmt.num(bounds.width))
+                    .attr("height", exporter.fmt.num(bounds.height))
+                    .attr("fill", background.hex()),
+            );
+        }
+    }
+    for id in &scope.ids {
+        let transform = multiply(&origin, &scope.tree.world_transform(id));
+        if let Some(el) = exporter.node(id, transform) {
+            body.push(el);
+        }
+    }
+
+    if let Some(style) = exporter.font_faces() {
+        exporter.defs.insert(0, style);
+    }
+
+    let fmt = &exporter.fmt;
+    let mut svg = Element::new("svg")
+        .attr("xmlns", SVG_NS)
+        .attr("width", fmt.num(bounds.width))
+        .attr("height", fmt.num(bounds.height));
+    if exporter.uses_xlink {
+        svg.set("xmlns:xlink", XLINK_NS);
+    }
+    if options.include_view_box {
+        svg.set("viewBox", format!("0 0 {} {}", fmt.num(bounds.width), fmt.num(bounds.height)));
+    }
+    if !exporter.defs.is_empty() {
+        let mut defs = Element::new("defs");
+        for def in exporter.defs.drain(..) {
+            defs.push(def);
+        }
+        svg.push(defs);
+    }
+    for el in body {
+        svg.push(el);
+    }
+
+    let mut out = String::new();
+    if options.include_xml_declaration {
+        out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>");
+        if !options.minify {
+            out.push('\n');
+        }
+    }
+    svg.write(&mut out, (!options.minify).then_some(0));
+
+    SvgExport { svg: out, width: bounds.width, height: bounds.height, warnings: exporter.warnings }
+}
+
+/// Serialize nodes to SVG. `node_json` is a serialized document or subtree
+/// (`{ rootId, nodes }`); `options.nodeIds` selects what to export.
+#[command(async)]
+pub fn export_svg(node_json: String, options: Option<SvgExportOptions>) -> Result<SvgExport, String> {
+    let options = options.unwrap_or_default();
+    let tree = DocumentTree::parse(&node_json)?;
+    let scope = resolve_scope(&tree, options.node_ids.clone(), options.padding)?;
+    Ok(write_svg(&scope, &options))
+}
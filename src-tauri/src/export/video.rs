@@ -0,0 +1,509 @@
+//! MP4 and WebM export through an ffmpeg binary installed on the system.
+//! Frames are rendered or decoded here and piped to ffmpeg as raw RGBA, so
+//! the app itself carries no video codecs.
+
+use super::animation::{frame_delays, frame_pixels, AnimationFrame, FramePixels, FrameRender};
+use crate::model::{DocumentTree, Rgba};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+use tauri::ipc::Channel;
+use tauri::{command, State};
+
+/// Where ffmpeg is usually installed. Apps started from the Finder or a
+/// desktop launcher do not inherit the shell's `PATH`.
+const FFMPEG_LOCATIONS: [&str; 3] = ["/opt/homebrew/bin", "/usr/local/bin", "/usr/bin"];
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoContainer {
+    Mp4,
+    Webm,
+}
+
+impl VideoContainer {
+    fn muxer(self) -> &'static str {
+        match self {
+            VideoContainer::Mp4 => "mp4",
+            VideoContainer::Webm => "webm",
+        }
+    }
+
+    fn default_codec(self) -> VideoCodec {
+        match self {
+            VideoContainer::Mp4 => VideoCodec::H264,
+            VideoContainer::Webm => VideoCodec::Vp9,
+        }
+    }
+
+    fn supports(self, codec: VideoCodec) -> bool {
+        match self {
+            VideoContainer::Mp4 => matches!(codec, VideoCodec::H264 | VideoCodec::H265 | VideoCodec::Av1),
+            VideoContainer::Webm => matches!(codec, VideoCodec::Vp8 | VideoCodec::Vp9 | VideoCodec::Av1),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoCodec {
+    H264,
+    H265,
+    Vp8,
+    Vp9,
+    Av1,
+}
+
+impl VideoCodec {
+    const ALL: [VideoCodec; 5] = [VideoCodec::H264, VideoCodec::H265, VideoCodec::Vp8, VideoCodec::Vp9, VideoCodec::Av1];
+
+    /// ffmpeg encoder name.
+    fn encoder(self) -> &'static str {
+        match self {
+            VideoCodec::H264 => "libx264",
+            VideoCodec::H265 => "libx265",
+            VideoCodec::Vp8 => "libvpx",
+            VideoCodec::Vp9 => "libvpx-vp9",
+            VideoCodec::Av1 => "libaom-av1",
+        }
+    }
+
+    /// Encoder options for a target bitrate, or for constant quality.
+    fn rate_args(self, bitrate_kbps: Option<u32>) -> Vec<String> {
+        let args: &[&str] = match (self, bitrate_kbps) {
+            (_, Some(kbps)) => return vec!["-b:v".to_string(), format!("{}k", kbps)],
+            (VideoCodec::H264, None) => &["-crf", "20", "-preset", "medium"],
+            (VideoCodec::H265, None) => &["-crf", "24", "-preset", "medium"],
+            // VP8 treats the bitrate as a ceiling in constant quality mode
+            (VideoCodec::Vp8, None) => &["-crf", "10", "-b:v", "8M"],
+            (VideoCodec::Vp9, None) => &["-crf", "32", "-b:v", "0", "-row-mt", "1"],
+            (VideoCodec::Av1, None) => &["-crf", "32", "-b:v", "0", "-cpu-used", "6", "-row-mt", "1"],
+        };
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    fn supports_alpha(self) -> bool {
+        matches!(self, VideoCodec::Vp8 | VideoCodec::Vp9)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct VideoExportOptions {
+    /// Defaults to H.264 for MP4 and VP9 for WebM.
+    pub codec: Option<VideoCodec>,
+    /// Target bitrate; without one the codec's constant quality mode is used.
+    pub bitrate_kbps: Option<u32>,
+    pub fps: f64,
+    /// Pixels per canvas unit for rendered frames.
+    pub scale: f64,
+    pub padding: f64,
+    /// Painted under every frame. Without one, VP8 and VP9 keep transparency
+    /// and other codecs get white.
+    pub background: Option<Rgba>,
+    /// ffmpeg binary; looked up on the `PATH` and in the usual install
+    /// locations otherwise.
+    pub ffmpeg_path: Option<String>,
+}
+
+impl Default for VideoExportOptions {
+    fn default() -> Self {
+        VideoExportOptions {
+            codec: None,
+            bitrate_kbps: None,
+            fps: 30.0,
+            scale: 1.0,
+            padding: 0.0,
+            background: None,
+            ffmpeg_path: None,
+        }
+    }
+}
+
+/// Messages delivered over the channel passed to `export_video`.
+#[derive(Serialize, Clone)]
+#[serde(tag = "event", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum VideoExportEvent {
+    Started { export_id: u64, total_frames: u64, width: u32, height: u32 },
+    /// Video frames handed to the encoder so far.
+    Progress { frames_encoded: u64, total_frames: u64 },
+    Finished { path: String, bytes: u64, elapsed_ms: u64, warnings: Vec<String> },
+    Cancelled,
+    Failed { message: String },
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoExportSupport {
+    /// `None` when ffmpeg was not found.
+    pub ffmpeg_path: Option<String>,
+    /// First line of `ffmpeg -version`.
+    pub version: Option<String>,
+    /// Codecs the installed ffmpeg can encode.
+    pub codecs: Vec<VideoCodec>,
+}
+
+/// Cancellation flags for in-flight video exports.
+#[derive(Default)]
+pub struct VideoExports {
+    next_id: AtomicU64,
+    active: Mutex<HashMap<u64, Arc<AtomicBool>>>,
+}
+
+impl VideoExports {
+    fn register(&self) -> (u64, Arc<AtomicBool>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let flag = Arc::new(AtomicBool::new(false));
+        self.active.lock().unwrap_or_else(|e| e.into_inner()).insert(id, Arc::clone(&flag));
+        (id, flag)
+    }
+
+    fn finish(&self, id: u64) {
+        self.active.lock().unwrap_or_else(|e| e.into_inner()).remove(&id);
+    }
+
+    fn cancel(&self, id: u64) -> bool {
+        match self.active.lock().unwrap_or_else(|e| e.into_inner()).get(&id) {
+            Some(flag) => {
+                flag.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn find_ffmpeg(explicit: Option<&str>) -> Result<PathBuf, String> {
+    if let Some(path) = explicit {
+        return Some(PathBuf::from(path))
+            .filter(|p| p.is_file())
+            .ok_or_else(|| format!("ffmpeg was not found at {}", path));
+    }
+    let name = if cfg!(windows) { "ffmpeg.exe" } else { "ffmpeg" };
+    std::env::var_os("PATH")
+        .iter()
+        .flat_map(std::env::split_paths)
+        .chain(FFMPEG_LOCATIONS.iter().map(PathBuf::from))
+        .map(|dir| dir.join(name))
+        .find(|p| p.is_file())
+        .ok_or_else(|| "Video export needs ffmpeg, which was not found. Install it or set its location".to_string())
+}
+
+fn ffmpeg_command(ffmpeg: &Path) -> Command {
+    let mut command = Command::new(ffmpeg);
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        // CREATE_NO_WINDOW: no console flashing up behind the app
+        command.creation_flags(0x0800_0000);
+    }
+    command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+    command
+}
+
+/// Blend straight-alpha pixels over an opaque background.
+fn flatten(rgba: &mut [u8], background: Rgba) {
+    let bg = [background.r, background.g, background.b].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u32);
+    for p in rgba.chunks_exact_mut(4) {
+        let a = p[3] as u32;
+        for i in 0..3 {
+            p[i] = ((p[i] as u32 * a + bg[i] * (255 - a) + 127) / 255) as u8;
+        }
+        p[3] = 255;
+    }
+}
+
+/// Pixels grown to even dimensions, which 4:2:0 chroma subsampling needs, by
+/// repeating the last column and row.
+fn pad_even(pixels: &FramePixels) -> Vec<u8> {
+    let (width, height) = (pixels.width as usize, pixels.height as usize);
+    let (padded_width, padded_height) = (width + width % 2, height + height % 2);
+    if (padded_width, padded_height) == (width, height) {
+        return pixels.rgba.clone();
+    }
+    let mut out = Vec::with_capacity(padded_width * padded_height * 4);
+    for y in 0..padded_height {
+        let row = &pixels.rgba[y.min(height - 1) * width * 4..][..width * 4];
+        out.extend_from_slice(row);
+        if padded_width > width {
+            out.extend_from_slice(&row[(width - 1) * 4..]);
+        }
+    }
+    out
+}
+
+/// Everything an export needs once it has moved to its worker thread.
+struct VideoJob {
+    ffmpeg: PathBuf,
+    path: PathBuf,
+    container: VideoContainer,
+    codec: VideoCodec,
+    options: VideoExportOptions,
+    frames: Vec<AnimationFrame>,
+    shared: Option<DocumentTree>,
+    first: Option<FramePixels>,
+    /// Video frames each source frame is shown for.
+    repeats: Vec<u16>,
+    size: (u32, u32),
+    alpha: bool,
+    warnings: Vec<String>,
+}
+
+fn temp_path_for(target: &Path) -> Result<PathBuf, String> {
+    let name = target.file_name().and_then(|n| n.to_str())
+        .ok_or_else(|| format!("Invalid path: {}", target.display()))?;
+    let dir = match target.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    if !dir.is_dir() {
+        return Err(format!("Folder does not exist: {}", dir.display()));
+    }
+    Ok(dir.join(format!(".{}.{}.tmp", name, std::process::id())))
+}
+
+fn spawn_encoder(job: &VideoJob, output: &Path) -> Result<Child, String> {
+    let (width, height) = (job.size.0 + job.size.0 % 2, job.size.1 + job.size.1 % 2);
+    let mut command = ffmpeg_command(&job.ffmpeg);
+    command
+        .args(["-hide_banner", "-loglevel", "error", "-y"])
+        .args(["-f", "rawvideo", "-pix_fmt", "rgba", "-s", &format!("{}x{}", width, height)])
+        .args(["-framerate", &job.options.fps.to_string(), "-i", "pipe:0", "-an"])
+        .args(["-c:v", job.codec.encoder()])
+        .args(job.codec.rate_args(job.options.bitrate_kbps))
+        .args(["-pix_fmt", if job.alpha { "yuva420p" } else { "yuv420p" }]);
+    if job.alpha && job.codec == VideoCodec::Vp8 {
+        command.args(["-auto-alt-ref", "0"]);
+    }
+    if job.container == VideoContainer::Mp4 {
+        // Index up front so playback can start before the download finishes
+        command.args(["-movflags", "+faststart"]);
+        if job.codec == VideoCodec::H265 {
+            // QuickTime and Safari only play HEVC tagged this way
+            command.args(["-tag:v", "hvc1"]);
+        }
+    }
+    command.args(["-f", job.container.muxer()]).arg(output);
+    command.stdin(Stdio::piped()).stderr(Stdio::piped());
+    command.spawn().map_err(|e| format!("Failed to start ffmpeg: {}", e))
+}
+
+fn encode_video(mut job: VideoJob, cancelled: &AtomicBool, on_event: &Channel<VideoExportEvent>) -> Result<VideoExportEvent, String> {
+    let started = Instant::now();
+    let tmp = temp_path_for(&job.path)?;
+    let mut child = spawn_encoder(&job, &tmp)?;
+
+    // Drain errors on their own thread so a chatty ffmpeg cannot block on a
+    // full pipe while we are blocked writing frames to it
+    let mut stderr = child.stderr.take();
+    let errors = thread::spawn(move || {
+        let mut text = String::new();
+        if let Some(stderr) = stderr.as_mut() {
+            let _ = stderr.read_to_string(&mut text);
+        }
+        text
+    });
+    let ffmpeg_error = |errors: thread::JoinHandle<String>, fallback: String| {
+        let text = errors.join().unwrap_or_default();
+        match text.lines().rev().find(|l| !l.trim().is_empty()) {
+            Some(line) => format!("ffmpeg failed: {}", line.trim()),
+            None => fallback,
+        }
+    };
+
+    let total_frames: u64 = job.repeats.iter().map(|&r| r as u64).sum();
+    let mut frames_encoded = 0;
+    let mut stdin = child.stdin.take();
+    let mut result = Ok(());
+    for index in 0..job.frames.len() {
+        if cancelled.load(Ordering::Relaxed) {
+            break;
+        }
+        if job.repeats[index] == 0 {
+            continue;
+        }
+        let render = FrameRender { scale: job.options.scale, padding: job.options.padding, background: job.options.background };
+        let pixels = match job.first.take() {
+            Some(pixels) => Ok(pixels),
+            None => frame_pixels(index, &job.frames[index], job.shared.as_ref(), &render, &mut job.warnings),
+        };
+        let mut pixels = match pixels {
+            Ok(pixels) if (pixels.width, pixels.height) == job.size => pixels,
+            Ok(pixels) => {
+                result = Err(format!(
+                    "Frame {} is {}x{}, but the video is {}x{}",
+                    index, pixels.width, pixels.height, job.size.0, job.size.1
+                ));
+                break;
+            }
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        };
+        if !job.alpha {
+            flatten(&mut pixels.rgba, job.options.background.unwrap_or(Rgba { r: 1.0, g: 1.0, b: 1.0, a: 1.0 }));
+        }
+        let data = pad_even(&pixels);
+
+        let Some(pipe) = stdin.as_mut() else { break };
+        for _ in 0..job.repeats[index] {
+            if let Err(e) = pipe.write_all(&data) {
+                result = Err(format!("Failed to send frames to ffmpeg: {}", e));
+                break;
+            }
+        }
+        if result.is_err() {
+            break;
+        }
+        frames_encoded += job.repeats[index] as u64;
+        let _ = on_event.send(VideoExportEvent::Progress { frames_encoded, total_frames });
+    }
+    // Closing the pipe tells ffmpeg the input is complete
+    drop(stdin);
+
+    if cancelled.load(Ordering::Relaxed) || result.is_err() {
+        let _ = child.kill();
+        let _ = child.wait();
+        let _ = fs::remove_file(&tmp);
+        return match result {
+            // A dead ffmpeg breaks the pipe; its own message says why
+            Err(message) if message.starts_with("Failed to send") => Err(ffmpeg_error(errors, message)),
+            Err(message) => Err(message),
+            Ok(()) => Ok(VideoExportEvent::Cancelled),
+        };
+    }
+
+    let status = child.wait().map_err(|e| format!("Failed to wait for ffmpeg: {}", e))?;
+    if !status.success() {
+        let _ = fs::remove_file(&tmp);
+        return Err(ffmpeg_error(errors, format!("ffmpeg failed with {}", status)));
+    }
+    if let Err(e) = fs::rename(&tmp, &job.path) {
+        let _ = fs::remove_file(&tmp);
+        return Err(format!("Failed to write {}: {}", job.path.display(), e));
+    }
+
+    let bytes = fs::metadata(&job.path).map(|m| m.len()).unwrap_or(0);
+    Ok(VideoExportEvent::Finished {
+        path: job.path.to_string_lossy().into_owned(),
+        bytes,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+        warnings: job.warnings,
+    })
+}
+
+/// Report whether ffmpeg is installed and which codecs it can encode, so
+/// the export dialog only offers what will work.
+#[command(async)]
+pub fn video_export_support(ffmpeg_path: Option<String>) -> VideoExportSupport {
+    let Ok(ffmpeg) = find_ffmpeg(ffmpeg_path.as_deref()) else {
+        return VideoExportSupport { ffmpeg_path: None, version: None, codecs: Vec::new() };
+    };
+    let run = |args: &[&str]| {
+        let output = ffmpeg_command(&ffmpeg).args(args).stdout(Stdio::piped()).output().ok()?;
+        Some(String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+
+    let version = run(&["-hide_banner", "-version"]).and_then(|v| v.lines().next().map(str::to_string));
+    let encoders = run(&["-hide_banner", "-encoders"]).unwrap_or_default();
+    let codecs = VideoCodec::ALL
+        .into_iter()
+        .filter(|codec| encoders.lines().any(|l| l.split_whitespace().nth(1) == Some(codec.encoder())))
+        .collect();
+    VideoExportSupport { ffmpeg_path: Some(ffmpeg.to_string_lossy().into_owned()), version, codecs }
+}
+
+/// Encode frames into an MP4 or WebM file with ffmpeg on a background
+/// thread. Frames are given as for `export_animation` and must all be the
+/// same size; each is held for its duration at the constant output frame
+/// rate. Returns the export id to pass to `cancel_video_export`.
+#[command(async)]
+pub fn export_video(
+    exports: State<'_, Arc<VideoExports>>,
+    path: String,
+    container: VideoContainer,
+    frames: Vec<AnimationFrame>,
+    node_json: Option<String>,
+    options: Option<VideoExportOptions>,
+    on_event: Channel<VideoExportEvent>,
+) -> Result<u64, String> {
+    let options = options.unwrap_or_default();
+    if frames.is_empty() {
+        return Err("Nothing to export: the video has no frames".to_string());
+    }
+    if !(options.fps.is_finite() && options.fps > 0.0 && options.fps <= 240.0) {
+        return Err(format!("Invalid frame rate: {}", options.fps));
+    }
+    let codec = options.codec.unwrap_or(container.default_codec());
+    if !container.supports(codec) {
+        return Err(format!("{} cannot be stored in {}", codec.encoder(), container.muxer()));
+    }
+    let ffmpeg = find_ffmpeg(options.ffmpeg_path.as_deref())?;
+    let shared = node_json.as_deref().map(DocumentTree::parse).transpose()?;
+
+    let durations: Vec<f64> = frames
+        .iter()
+        .map(|f| f.duration_ms.filter(|d| d.is_finite() && *d > 0.0).unwrap_or(1000.0 / options.fps))
+        .collect();
+    let repeats = frame_delays(&durations, 1000.0 / options.fps);
+    let mut warnings = Vec::new();
+    if repeats.contains(&0) {
+        warnings.push(format!("Frames shorter than one frame at {} fps were skipped", options.fps));
+    }
+
+    // The first frame fixes the size, so failures show up before the export starts
+    let render = FrameRender { scale: options.scale, padding: options.padding, background: options.background };
+    let first = frame_pixels(0, &frames[0], shared.as_ref(), &render, &mut warnings)?;
+    let size = (first.width, first.height);
+    if size.0 % 2 == 1 || size.1 % 2 == 1 {
+        warnings.push(format!("Frames were padded to an even size, {}x{}", size.0 + size.0 % 2, size.1 + size.1 % 2));
+    }
+    let alpha = options.background.is_none() && codec.supports_alpha();
+    let total_frames = repeats.iter().map(|&r| r as u64).sum();
+
+    let job = VideoJob {
+        ffmpeg,
+        path: PathBuf::from(path),
+        container,
+        codec,
+        options,
+        frames,
+        shared,
+        first: Some(first),
+        repeats,
+        size,
+        alpha,
+        warnings,
+    };
+
+    let exports = Arc::clone(&exports);
+    let (export_id, cancelled) = exports.register();
+    let _ = on_event.send(VideoExportEvent::Started { export_id, total_frames, width: size.0, height: size.1 });
+
+    thread::Builder::new()
+        .name(format!("export-video-{}", export_id))
+        .spawn(move || {
+            let last = encode_video(job, &cancelled, &on_event)
+                .unwrap_or_else(|message| VideoExportEvent::Failed { message });
+            exports.finish(export_id);
+            let _ = on_event.send(last);
+        })
+        .map_err(|e| format!("Failed to start export: {}", e))?;
+
+    Ok(export_id)
+}
+
+/// Request cancellation; returns false if the export already completed.
+/// The partial file is removed.
+#[command]
+pub fn cancel_video_export(exports: State<'_, Arc<VideoExports>>, export_id: u64) -> bool {
+    exports.cancel(export_id)
+}
@@ -0,0 +1,218 @@
+//! Picking a color from anywhere on screen, for the eyedropper outside the
+//! app's window, where the webview can't see. Each platform has the user
+//! pick the pixel: macOS with its color sampler loupe, Linux through the
+//! desktop portal (which asks the compositor, so it works under Wayland),
+//! and Windows by clicking, which is kept from the window under the cursor.
+//! Escape cancels.
+
+use crate::model::Rgba;
+use serde::Serialize;
+use tauri::{command, AppHandle};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScreenColor {
+    /// The picked color in sRGB, for fills and strokes.
+    pub color: Rgba,
+    pub hex: String,
+    /// The components as the screen had them, 0 to 1, in `color_space`.
+    /// On a wide-gamut display they can be outside what sRGB shows.
+    pub raw: Vec<f64>,
+    /// The name of the space `raw` is in, such as `Display P3`.
+    pub color_space: String,
+}
+
+fn screen_color(srgb: [f64; 3], raw: Vec<f64>, color_space: String) -> ScreenColor {
+    let color = Rgba { r: srgb[0], g: srgb[1], b: srgb[2], a: 1.0 };
+    ScreenColor { hex: color.hex(), color, raw, color_space }
+}
+
+/// Have the user pick a pixel anywhere on screen. Resolves to `None` when
+/// they cancel.
+#[command]
+pub async fn pick_screen_color(app: AppHandle) -> Result<Option<ScreenColor>, String> {
+    pick(&app).await
+}
+
+/// NSColorSampler, run on the main thread as AppKit needs; it calls back
+/// there once the user has picked or cancelled.
+#[cfg(target_os = "macos")]
+async fn pick(app: &AppHandle) -> Result<Option<ScreenColor>, String> {
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+    app.run_on_main_thread(move || macos::show_sampler(sender))
+        .map_err(|e| format!("Failed to show color picker: {}", e))?;
+    receiver.await.map_err(|_| "The color picker closed unexpectedly".to_string())?
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use super::{screen_color, ScreenColor};
+    use block2::RcBlock;
+    use objc2_app_kit::{NSColor, NSColorSampler, NSColorSpace};
+    use std::cell::Cell;
+    use std::ptr::NonNull;
+    use tokio::sync::oneshot::Sender;
+
+    type Picked = Result<Option<ScreenColor>, String>;
+
+    pub fn show_sampler(sender: Sender<Picked>) {
+        let sender = Cell::new(Some(sender));
+        let handler = RcBlock::new(move |color: *mut NSColor| {
+            let picked = match unsafe { color.as_ref() } {
+                Some(color) => read(color).map(Some),
+                None => Ok(None),
+            };
+            if let Some(sender) = sender.take() {
+                let _ = sender.send(picked);
+            }
+        });
+        // The sampler keeps itself alive until the user is done
+        let sampler = NSColorSampler::new();
+        unsafe { sampler.showSamplerWithSelectionHandler(&handler) };
+    }
+
+    /// The sampled color's components in the display's space, and in sRGB.
+    fn read(color: &NSColor) -> Result<ScreenColor, String> {
+        let failed = || "Failed to read the picked color".to_string();
+        let count = color.numberOfComponents().max(1) as usize;
+        let mut raw = vec![0.0; count];
+        unsafe { color.getComponents(NonNull::new(raw.as_mut_ptr()).ok_or_else(failed)?) };
+        // The last component is alpha, which is always opaque on screen
+        raw.pop();
+        let space = color.colorSpace().localizedName().map(|name| name.to_string()).unwrap_or_default();
+        let srgb = color.colorUsingColorSpace(&NSColorSpace::sRGBColorSpace()).ok_or_else(failed)?;
+        let srgb = [srgb.redComponent(), srgb.greenComponent(), srgb.blueComponent()];
+        Ok(screen_color(srgb, raw, space))
+    }
+}
+
+/// The desktop portal's color picker. It gives sRGB.
+#[cfg(target_os = "linux")]
+async fn pick(_app: &AppHandle) -> Result<Option<ScreenColor>, String> {
+    use ashpd::desktop::{Color, ResponseError};
+
+    match Color::pick().send().await.and_then(|request| request.response()) {
+        Ok(color) => {
+            let srgb = [color.red(), color.green(), color.blue()];
+            Ok(Some(screen_color(srgb, srgb.to_vec(), "sRGB".to_string())))
+        }
+        Err(ashpd::Error::Response(ResponseError::Cancelled)) => Ok(None),
+        Err(e) => Err(format!("Failed to pick color: {}", e)),
+    }
+}
+
+/// Low-level hooks that wait for a click anywhere, on a thread of their own
+/// since they are called from the installing thread's message loop.
+#[cfg(windows)]
+async fn pick(_app: &AppHandle) -> Result<Option<ScreenColor>, String> {
+    tauri::async_runtime::spawn_blocking(windows::pick_with_click)
+        .await
+        .map_err(|e| format!("Failed to pick color: {}", e))?
+}
+
+#[cfg(windows)]
+mod windows {
+    use super::{screen_color, ScreenColor};
+    use std::cell::Cell;
+    use std::ptr::{null, null_mut};
+    use windows_sys::Win32::Foundation::{LPARAM, LRESULT, POINT, WPARAM};
+    use windows_sys::Win32::Graphics::Gdi::{GetDC, GetPixel, ReleaseDC, CLR_INVALID};
+    use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
+    use windows_sys::Win32::UI::Input::KeyboardAndMouse::VK_ESCAPE;
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        CallNextHookEx, GetMessageW, PostQuitMessage, SetWindowsHookExW, UnhookWindowsHookEx, KBDLLHOOKSTRUCT, MSG,
+        MSLLHOOKSTRUCT, WH_KEYBOARD_LL, WH_MOUSE_LL, WM_KEYDOWN, WM_LBUTTONDOWN, WM_LBUTTONUP,
+    };
+
+    #[derive(Clone, Copy)]
+    enum Outcome {
+        Picked(POINT),
+        Cancelled,
+    }
+
+    thread_local! {
+        static OUTCOME: Cell<Option<Outcome>> = const { Cell::new(None) };
+    }
+
+    unsafe extern "system" fn mouse_hook(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+        if code >= 0 {
+            match wparam as u32 {
+                WM_LBUTTONDOWN => {
+                    let event = &*(lparam as *const MSLLHOOKSTRUCT);
+                    OUTCOME.set(Some(Outcome::Picked(event.pt)));
+                    return 1;
+                }
+                // The press was kept from the window under the cursor, so
+                // the release is too
+                WM_LBUTTONUP if OUTCOME.get().is_some() => {
+                    PostQuitMessage(0);
+                    return 1;
+                }
+                _ => {}
+            }
+        }
+        CallNextHookEx(null_mut(), code, wparam, lparam)
+    }
+
+    unsafe extern "system" fn keyboard_hook(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+        if code >= 0 && wparam as u32 == WM_KEYDOWN {
+            let event = &*(lparam as *const KBDLLHOOKSTRUCT);
+            if event.vkCode == VK_ESCAPE as u32 {
+                OUTCOME.set(Some(Outcome::Cancelled));
+                PostQuitMessage(0);
+                return 1;
+            }
+        }
+        CallNextHookEx(null_mut(), code, wparam, lparam)
+    }
+
+    pub fn pick_with_click() -> Result<Option<ScreenColor>, String> {
+        OUTCOME.set(None);
+        let (mouse, keyboard) = unsafe {
+            let module = GetModuleHandleW(null());
+            (
+                SetWindowsHookExW(WH_MOUSE_LL, Some(mouse_hook), module, 0),
+                SetWindowsHookExW(WH_KEYBOARD_LL, Some(keyboard_hook), module, 0),
+            )
+        };
+        if !mouse.is_null() && !keyboard.is_null() {
+            let mut message: MSG = unsafe { std::mem::zeroed() };
+            while unsafe { GetMessageW(&mut message, null_mut(), 0, 0) } > 0 {}
+        }
+        for hook in [mouse, keyboard] {
+            if !hook.is_null() {
+                unsafe { UnhookWindowsHookEx(hook) };
+            }
+        }
+        if mouse.is_null() || keyboard.is_null() {
+            return Err("Failed to start color picker".to_string());
+        }
+        match OUTCOME.take() {
+            Some(Outcome::Picked(point)) => sample(point).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// The pixel at `point`, in physical pixels of the virtual screen.
+    fn sample(point: POINT) -> Result<ScreenColor, String> {
+        let failed = || "Failed to read the screen".to_string();
+        let screen = unsafe { GetDC(null_mut()) };
+        if screen.is_null() {
+            return Err(failed());
+        }
+        let pixel = unsafe { GetPixel(screen, point.x, point.y) };
+        unsafe { ReleaseDC(null_mut(), screen) };
+        if pixel == CLR_INVALID {
+            return Err(failed());
+        }
+        // COLORREF is 0x00bbggrr
+        let channel = |shift: u32| ((pixel >> shift) & 0xff) as f64 / 255.0;
+        let srgb = [channel(0), channel(8), channel(16)];
+        Ok(screen_color(srgb, srgb.to_vec(), "sRGB".to_string()))
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux", windows)))]
+async fn pick(_app: &AppHandle) -> Result<Option<ScreenColor>, String> {
+    Err("Picking colors from the screen is not supported on this platform".to_string())
+}
@@ -0,0 +1,98 @@
+//! Files dragged from the desktop onto a window. The webview gets only the
+//! drop, not `File` objects to read: each file is imported here, by what
+//! it is, and the results are sent back with where the drop landed, in CSS
+//! pixels of the window, for the frontend to place.
+//!
+//! Images go into the asset store, SVG and PDF become nodes, fonts are
+//! installed for the user, and design files are named for the frontend to
+//! open, as opening one is more than placing it.
+
+use crate::assets::color::WorkingSpace;
+use crate::assets::{AssetInfo, AssetStore};
+use crate::fonts::user::{ManagedFont, UserFonts};
+use crate::import::pdf::import_pdf;
+use crate::import::raster::SourceFormat;
+use crate::import::svg::import_svg;
+use crate::import::ImportResult;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use tauri::{DragDropEvent, Emitter, Manager, Window, WindowEvent};
+
+pub const FILES_DROPPED_EVENT: &str = "files-dropped";
+
+const FONT_EXTENSIONS: &[&str] = &["ttf", "otf", "ttc", "otc", "woff", "woff2"];
+
+/// Formats opened as documents of their own, by extension.
+const DOCUMENT_EXTENSIONS: &[&str] = &["designlibre", "fig", "sketch", "penpot"];
+
+#[derive(Serialize, Clone)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE", rename_all_fields = "camelCase")]
+pub enum DroppedFile {
+    Asset { path: String, asset: Box<AssetInfo> },
+    Nodes { path: String, import: ImportResult },
+    Font { path: String, font: ManagedFont },
+    /// A design file to open, with its extension as its format.
+    Document { path: String, format: String },
+    Failed { path: String, message: String },
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FilesDroppedEvent {
+    pub x: f64,
+    pub y: f64,
+    /// One for each file, in the order they were dropped.
+    pub files: Vec<DroppedFile>,
+}
+
+fn extension(path: &Path) -> String {
+    path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default()
+}
+
+fn import_file(window: &Window, path: &Path) -> Result<DroppedFile, String> {
+    let name = path.to_string_lossy().into_owned();
+    let extension = extension(path);
+    if DOCUMENT_EXTENSIONS.contains(&extension.as_str()) {
+        return Ok(DroppedFile::Document { path: name, format: extension });
+    }
+    if FONT_EXTENSIONS.contains(&extension.as_str()) {
+        let font = window.state::<UserFonts>().install(&name)?;
+        return Ok(DroppedFile::Font { path: name, font });
+    }
+    match extension.as_str() {
+        "svg" => return Ok(DroppedFile::Nodes { import: import_svg(name.clone(), None)?, path: name }),
+        "pdf" => return Ok(DroppedFile::Nodes { import: import_pdf(name.clone(), None)?, path: name }),
+        _ => {}
+    }
+
+    let bytes = fs::read(path).map_err(|e| format!("Failed to read {}: {}", name, e))?;
+    if SourceFormat::detect(&bytes).is_none() {
+        return Err(format!("{} is not a file that can be placed", name));
+    }
+    let file_name = path.file_name().map(|n| n.to_string_lossy().into_owned());
+    let imported = window.state::<AssetStore>().import(&bytes, file_name, WorkingSpace::default())?;
+    Ok(DroppedFile::Asset { path: name, asset: Box::new(imported.asset) })
+}
+
+/// Import files dropped on `window`, off the event loop, and emit
+/// `files-dropped` to it when they are all in.
+pub fn handle_window_event(window: &Window, event: &WindowEvent) {
+    let WindowEvent::DragDrop(DragDropEvent::Drop { paths, position }) = event else { return };
+    let position = position.to_logical::<f64>(window.scale_factor().unwrap_or(1.0));
+    let (window, paths) = (window.clone(), paths.clone());
+    let _ = thread::Builder::new().name("file-drop".to_string()).spawn(move || {
+        let files = paths
+            .iter()
+            .map(|path| {
+                import_file(&window, path).unwrap_or_else(|message| DroppedFile::Failed {
+                    path: path.to_string_lossy().into_owned(),
+                    message,
+                })
+            })
+            .collect();
+        let event = FilesDroppedEvent { x: position.x, y: position.y, files };
+        let _ = window.emit_to(window.label(), FILES_DROPPED_EVENT, event);
+    });
+}
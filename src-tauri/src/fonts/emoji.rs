@@ -0,0 +1,138 @@
+//! Color emoji. usvg draws COLR, CBDT, sbix and SVG glyphs, but its font
+//! fallback takes the first installed font with a glyph, which for emoji is
+//! often a monochrome symbol font; emoji are sent to a color font first.
+
+use super::shaping::shape;
+use super::{find_face, font_database};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use rustybuzz::ttf_parser;
+use serde::Serialize;
+use tauri::command;
+use tiny_skia::{Pixmap, Transform};
+use usvg::fontdb::{Database, ID};
+use usvg::FontResolver;
+
+/// Color emoji fonts of the platforms and common Linux distributions, best
+/// first.
+const EMOJI_FAMILIES: &[&str] = &[
+    "Apple Color Emoji", "Segoe UI Emoji", "Noto Color Emoji", "Twemoji Mozilla", "Twitter Color Emoji",
+    "JoyPixels", "EmojiOne Color",
+];
+const MAX_EMOJI_SIZE: f32 = 512.0;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenderedEmoji {
+    /// PNG as a `data:` URL for direct use in an `<img>`.
+    pub data_url: String,
+    pub width: u32,
+    pub height: u32,
+    /// The color font it was drawn with.
+    pub family: String,
+}
+
+/// Pictographs, dingbats, regional indicators for flags and keycap and tag
+/// characters, which have color forms.
+pub fn is_emoji(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x1F000..=0x1FAFF | 0x2600..=0x27BF | 0x2B00..=0x2BFF | 0x2300..=0x23FF | 0x20E3 | 0xFE0F | 0xE0020..=0xE007F
+    )
+}
+
+/// Whether the face has color glyphs in any of the formats usvg draws.
+pub fn is_color_font(face: &ttf_parser::Face) -> bool {
+    let tables = face.tables();
+    tables.colr.is_some() || tables.sbix.is_some() || tables.cbdt.is_some() || tables.svg.is_some()
+}
+
+fn has_glyph(db: &Database, id: ID, c: char) -> bool {
+    db.with_face_data(id, |data, index| ttf_parser::Face::parse(data, index).ok()?.glyph_index(c))
+        .flatten()
+        .is_some()
+}
+
+/// The first installed color emoji font with a glyph for `c`.
+fn emoji_face(db: &Database, c: char, exclude: &[ID]) -> Option<(ID, String)> {
+    EMOJI_FAMILIES.iter().find_map(|family| {
+        let face = find_face(db, family, "Regular")?;
+        let usable = !exclude.contains(&face.id) && has_glyph(db, face.id, c);
+        usable.then(|| (face.id, family.to_string()))
+    })
+}
+
+/// usvg's font resolver with emoji drawn from a color font when one is
+/// installed. Used wherever text is laid out with usvg, so exports match.
+pub fn font_resolver() -> FontResolver<'static> {
+    let fallback = FontResolver::default_fallback_selector();
+    FontResolver {
+        select_font: FontResolver::default_font_selector(),
+        select_fallback: Box::new(move |c, exclude, db| {
+            if is_emoji(c) {
+                if let Some((id, _)) = emoji_face(db, c, exclude) {
+                    return Some(id);
+                }
+            }
+            fallback(c, exclude, db)
+        }),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Draw `text` in `family` at `size` pixels per em with resvg, which handles
+/// every color glyph format. The image is one line high and as wide as the
+/// text's advance.
+pub fn render_color_text(text: &str, family: &str, size: f32) -> Result<Pixmap, String> {
+    let db = font_database();
+    let face = find_face(&db, family, "Regular").ok_or_else(|| format!("Font not installed: {}", family))?;
+    let (advance, ascender, descender) = db
+        .with_face_data(face.id, |data, index| {
+            let face = rustybuzz::Face::from_slice(data, index)?;
+            let shaped = shape(&face, text, size, &[]);
+            Some((shaped.advance, shaped.ascender, shaped.descender))
+        })
+        .flatten()
+        .ok_or_else(|| format!("Failed to read font: {}", family))?;
+
+    let width = advance.ceil().max(1.0);
+    let height = (ascender - descender).ceil().max(1.0);
+    let svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\"><text x=\"0\" y=\"{}\" font-family=\"'{}'\" font-size=\"{}\">{}</text></svg>",
+        width,
+        height,
+        ascender,
+        escape(&family.replace('\'', "")),
+        size,
+        escape(text)
+    );
+    let options = usvg::Options { fontdb: db, font_resolver: font_resolver(), ..Default::default() };
+    let tree = usvg::Tree::from_str(&svg, &options).map_err(|e| format!("Failed to lay out text: {}", e))?;
+
+    let mut pixmap = Pixmap::new(width as u32, height as u32).ok_or_else(|| "Failed to allocate image".to_string())?;
+    resvg::render(&tree, Transform::identity(), &mut pixmap.as_mut());
+    Ok(pixmap)
+}
+
+/// Rasterize one emoji, such as a flag or a ZWJ family sequence, in color at
+/// `size` pixels per em, for the emoji picker and text editor.
+#[command(async)]
+pub fn render_emoji(cluster: String, size: f32) -> Result<RenderedEmoji, String> {
+    if !(size.is_finite() && size > 0.0 && size <= MAX_EMOJI_SIZE) {
+        return Err(format!("Invalid emoji size: {}", size));
+    }
+    let first = cluster.chars().next().ok_or_else(|| "No emoji given".to_string())?;
+    let (_, family) = emoji_face(&font_database(), first, &[])
+        .ok_or_else(|| format!("No color emoji font has {}", cluster))?;
+
+    let pixmap = render_color_text(&cluster, &family, size)?;
+    let png = pixmap.encode_png().map_err(|e| format!("Failed to encode emoji: {}", e))?;
+    Ok(RenderedEmoji {
+        data_url: format!("data:image/png;base64,{}", BASE64.encode(&png)),
+        width: pixmap.width(),
+        height: pixmap.height(),
+        family,
+    })
+}
@@ -0,0 +1,141 @@
+//! Choosing fonts for the characters a text layer's own font lacks, such as
+//! CJK, emoji and symbols, so mixed-script text renders without tofu.
+
+use super::{find_face, font_database, style_name};
+use rustybuzz::ttf_parser;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashSet};
+use tauri::command;
+use usvg::fontdb::{Database, FaceInfo};
+
+/// Families tried before the rest of the installed fonts, best first. They
+/// cover most text between them, which saves opening every font file.
+#[cfg(target_os = "macos")]
+const PREFERRED_FALLBACKS: &[&str] = &[
+    "Helvetica Neue", "Apple Color Emoji", "PingFang SC", "Hiragino Sans", "Apple SD Gothic Neo",
+    "Geeza Pro", "Kohinoor Devanagari", "Thonburi", "Apple Symbols", "Arial Unicode MS",
+];
+#[cfg(target_os = "windows")]
+const PREFERRED_FALLBACKS: &[&str] = &[
+    "Segoe UI", "Segoe UI Emoji", "Microsoft YaHei", "Yu Gothic", "Malgun Gothic", "Nirmala UI",
+    "Leelawadee UI", "Segoe UI Symbol", "Segoe UI Historic",
+];
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+const PREFERRED_FALLBACKS: &[&str] = &[
+    "Noto Sans", "DejaVu Sans", "Noto Color Emoji", "Noto Sans CJK SC", "Noto Sans Arabic",
+    "Noto Sans Devanagari", "Noto Sans Thai", "Noto Sans Symbols", "Noto Sans Symbols 2",
+];
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FallbackFont {
+    pub family: String,
+    pub style_name: String,
+}
+
+/// A stretch of text drawn with one font.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FallbackRun {
+    /// UTF-16 offsets, to slice the JavaScript string with.
+    pub start: usize,
+    pub end: usize,
+    /// Index into `fonts`, or none for characters no installed font has.
+    pub font: Option<usize>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FontFallback {
+    /// The preferred family when installed, then the fallbacks in the order
+    /// they were needed.
+    pub fonts: Vec<FallbackFont>,
+    pub runs: Vec<FallbackRun>,
+    /// Characters no installed font has a glyph for.
+    pub missing: Vec<String>,
+}
+
+/// Joiners, variation selectors, combining marks and emoji modifiers belong
+/// with the character before them rather than being looked up on their own.
+pub fn attaches_to_previous(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x200C..=0x200D
+            | 0xFE00..=0xFE0F
+            | 0x0300..=0x036F
+            | 0x1AB0..=0x1AFF
+            | 0x1DC0..=0x1DFF
+            | 0x20D0..=0x20FF
+            | 0xFE20..=0xFE2F
+            | 0x1F3FB..=0x1F3FF
+            | 0xE0020..=0xE007F
+            | 0xE0100..=0xE01EF
+    )
+}
+
+/// Which of `chars` the face has glyphs for.
+fn coverage(db: &Database, face: &FaceInfo, chars: &BTreeSet<char>) -> BTreeSet<char> {
+    db.with_face_data(face.id, |data, index| {
+        let Ok(parsed) = ttf_parser::Face::parse(data, index) else { return BTreeSet::new() };
+        chars.iter().copied().filter(|&c| parsed.glyph_index(c).is_some()).collect()
+    })
+    .unwrap_or_default()
+}
+
+/// Find fonts for every character of `text`: the preferred family first,
+/// then the platform's usual fallbacks, then any installed font. Returns the
+/// fonts needed in order and the runs of text each one draws.
+#[command(async)]
+pub fn resolve_fallback(text: String, preferred_family: Option<String>) -> FontFallback {
+    let db = font_database();
+    let mut uncovered: BTreeSet<char> = text.chars().filter(|&c| !c.is_control() && !attaches_to_previous(c)).collect();
+
+    let mut families: Vec<String> = preferred_family.into_iter().collect();
+    families.extend(PREFERRED_FALLBACKS.iter().map(|f| f.to_string()));
+    let mut seen = HashSet::new();
+    let mut remaining = db.faces().filter_map(|f| f.families.first()).map(|(name, _)| name.clone()).collect::<Vec<_>>();
+    remaining.sort();
+    families.extend(remaining);
+
+    // (font, the characters it covers)
+    let mut chain: Vec<(FallbackFont, BTreeSet<char>)> = Vec::new();
+    let all: BTreeSet<char> = uncovered.clone();
+    for family in families {
+        if uncovered.is_empty() {
+            break;
+        }
+        if !seen.insert(family.to_lowercase()) {
+            continue;
+        }
+        let Some(face) = find_face(&db, &family, "Regular") else { continue };
+        let covered = coverage(&db, face, &all);
+        if covered.is_disjoint(&uncovered) {
+            continue;
+        }
+        uncovered.retain(|c| !covered.contains(c));
+        let name = face.families.first().map_or(family, |(name, _)| name.clone());
+        chain.push((FallbackFont { family: name, style_name: style_name(face) }, covered));
+    }
+
+    let mut runs: Vec<FallbackRun> = Vec::new();
+    let mut offset = 0;
+    for c in text.chars() {
+        let len = c.len_utf16();
+        let previous = runs.last().map(|r| r.font);
+        let font = match previous {
+            Some(font) if c.is_control() || attaches_to_previous(c) => font,
+            _ => chain.iter().position(|(_, covered)| covered.contains(&c)),
+        };
+        match runs.last_mut() {
+            Some(run) if previous == Some(font) => run.end += len,
+            _ => runs.push(FallbackRun { start: offset, end: offset + len, font }),
+        }
+        offset += len;
+    }
+
+    FontFallback {
+        fonts: chain.into_iter().map(|(font, _)| font).collect(),
+        runs,
+        missing: uncovered.into_iter().map(String::from).collect(),
+    }
+}
@@ -0,0 +1,159 @@
+//! The OpenType features a face offers, such as ligatures, figure styles
+//! and stylistic sets, for the typography panel.
+
+use super::variations::load_face;
+use serde::Serialize;
+use skrifa::raw::tables::layout::{FeatureList, FeatureParams};
+use skrifa::raw::TableProvider;
+use skrifa::{FontRef, MetadataProvider};
+use std::collections::BTreeMap;
+use tauri::command;
+
+/// Groups in the order the panel shows them.
+const CATEGORIES: &[&str] =
+    &["ligatures", "letterCase", "numbers", "stylisticSets", "characterVariants", "alternates", "positioning", "other"];
+
+/// Registered features worth offering: tag, name, category and whether
+/// shaping applies it by default.
+const FEATURES: &[(&str, &str, &str, bool)] = &[
+    ("liga", "Standard Ligatures", "ligatures", true),
+    ("clig", "Contextual Ligatures", "ligatures", true),
+    ("dlig", "Discretionary Ligatures", "ligatures", false),
+    ("hlig", "Historical Ligatures", "ligatures", false),
+    ("smcp", "Small Capitals", "letterCase", false),
+    ("c2sc", "Capitals to Small Capitals", "letterCase", false),
+    ("pcap", "Petite Capitals", "letterCase", false),
+    ("c2pc", "Capitals to Petite Capitals", "letterCase", false),
+    ("unic", "Unicase", "letterCase", false),
+    ("case", "Case-Sensitive Forms", "letterCase", false),
+    ("cpsp", "Capital Spacing", "letterCase", false),
+    ("titl", "Titling", "letterCase", false),
+    ("lnum", "Lining Figures", "numbers", false),
+    ("onum", "Oldstyle Figures", "numbers", false),
+    ("pnum", "Proportional Figures", "numbers", false),
+    ("tnum", "Tabular Figures", "numbers", false),
+    ("frac", "Fractions", "numbers", false),
+    ("afrc", "Alternative Fractions", "numbers", false),
+    ("zero", "Slashed Zero", "numbers", false),
+    ("sups", "Superscript", "numbers", false),
+    ("subs", "Subscript", "numbers", false),
+    ("sinf", "Scientific Inferiors", "numbers", false),
+    ("ordn", "Ordinals", "numbers", false),
+    ("numr", "Numerators", "numbers", false),
+    ("dnom", "Denominators", "numbers", false),
+    ("calt", "Contextual Alternates", "alternates", true),
+    ("salt", "Stylistic Alternates", "alternates", false),
+    ("swsh", "Swash", "alternates", false),
+    ("cswh", "Contextual Swash", "alternates", false),
+    ("hist", "Historical Forms", "alternates", false),
+    ("ornm", "Ornaments", "alternates", false),
+    ("nalt", "Alternate Annotation Forms", "alternates", false),
+    ("aalt", "Access All Alternates", "alternates", false),
+    ("fwid", "Full Widths", "alternates", false),
+    ("hwid", "Half Widths", "alternates", false),
+    ("pwid", "Proportional Widths", "alternates", false),
+    ("jp78", "JIS78 Forms", "alternates", false),
+    ("jp90", "JIS90 Forms", "alternates", false),
+    ("trad", "Traditional Forms", "alternates", false),
+    ("smpl", "Simplified Forms", "alternates", false),
+    ("kern", "Kerning", "positioning", true),
+    ("palt", "Proportional Alternate Widths", "positioning", false),
+    ("halt", "Alternate Half Widths", "positioning", false),
+];
+
+/// Features shaping applies on its own where a script or writing direction
+/// needs them, which are not choices for the user.
+const AUTOMATIC: &[&str] = &[
+    "abvf", "abvm", "abvs", "akhn", "blwf", "blwm", "blws", "ccmp", "cfar", "cjct", "curs", "dist", "dtls",
+    "fin2", "fin3", "fina", "flac", "half", "haln", "init", "isol", "ljmo", "locl", "ltra", "ltrm", "mark",
+    "med2", "medi", "mkmk", "nukt", "pref", "pres", "pstf", "psts", "rclt", "rkrf", "rlig", "rphf", "rtla",
+    "rtlm", "rvrn", "ssty", "stch", "tjmo", "vatu", "vert", "vjmo", "vkrn", "vrt2",
+];
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FontFeature {
+    /// Such as `liga` or `ss01`, as used in `font-feature-settings` and by
+    /// `shape_text`.
+    pub tag: String,
+    /// The font's own name for stylistic sets and character variants that
+    /// have one, otherwise the registered name.
+    pub name: String,
+    /// One of `ligatures`, `letterCase`, `numbers`, `stylisticSets`,
+    /// `characterVariants`, `alternates`, `positioning` or `other`.
+    pub category: &'static str,
+    /// Applied unless turned off with `tag=0`.
+    pub on_by_default: bool,
+}
+
+/// Tags of `features`, with the label the font gives them if any.
+fn collect(font: &FontRef, features: FeatureList, out: &mut BTreeMap<String, Option<String>>) {
+    let name = |id| font.localized_strings(id).english_or_first().map(|s| s.to_string());
+    for record in features.feature_records() {
+        let tag = record.feature_tag().to_string();
+        let label = record.feature(features.offset_data()).ok().and_then(|feature| match feature.feature_params()? {
+            Ok(FeatureParams::StylisticSet(params)) => name(params.ui_name_id()),
+            Ok(FeatureParams::CharacterVariant(params)) => name(params.feat_ui_label_name_id()),
+            _ => None,
+        });
+        let entry = out.entry(tag).or_default();
+        if entry.is_none() {
+            *entry = label;
+        }
+    }
+}
+
+/// Name and category of an unregistered feature: a numbered stylistic set or
+/// character variant, or else one the font made up, shown by its tag.
+fn describe(tag: &str) -> (String, &'static str) {
+    let number = |prefix: &str| tag.strip_prefix(prefix).and_then(|n| n.parse::<u8>().ok());
+    if let Some(n) = number("ss").filter(|n| (1..=20).contains(n)) {
+        (format!("Stylistic Set {}", n), "stylisticSets")
+    } else if let Some(n) = number("cv").filter(|n| (1..=99).contains(n)) {
+        (format!("Character Variant {}", n), "characterVariants")
+    } else {
+        (tag.to_string(), "other")
+    }
+}
+
+/// The OpenType features an installed face offers, with names to show and
+/// grouped as the typography panel lists them. Features that a script
+/// needs, such as Arabic joining forms, are left out since shaping applies
+/// them anyway.
+#[command(async)]
+pub fn get_font_features(family: String, style: Option<String>) -> Result<Vec<FontFeature>, String> {
+    let data = load_face(&family, style)?;
+    let font = FontRef::new(&data).map_err(|e| format!("Failed to parse font: {}", e))?;
+
+    let mut tags = BTreeMap::new();
+    if let Some(list) = font.gsub().ok().and_then(|gsub| gsub.feature_list().ok()) {
+        collect(&font, list, &mut tags);
+    }
+    if let Some(list) = font.gpos().ok().and_then(|gpos| gpos.feature_list().ok()) {
+        collect(&font, list, &mut tags);
+    }
+
+    let mut features: Vec<FontFeature> = tags
+        .into_iter()
+        // Padded or unprintable tags belong to other layout engines, such as Graphite
+        .filter(|(tag, _)| tag.bytes().all(|b| b.is_ascii_alphanumeric()) && !AUTOMATIC.contains(&tag.as_str()))
+        .map(|(tag, label)| {
+            let (name, category, on_by_default) = match FEATURES.iter().find(|(t, ..)| *t == tag) {
+                Some(&(_, name, category, on)) => (name.to_string(), category, on),
+                None => {
+                    let (name, category) = describe(&tag);
+                    (name, category, false)
+                }
+            };
+            FontFeature { name: label.filter(|l| !l.trim().is_empty()).unwrap_or(name), tag, category, on_by_default }
+        })
+        .collect();
+
+    let rank = |f: &FontFeature| {
+        let category = CATEGORIES.iter().position(|c| *c == f.category).unwrap_or(CATEGORIES.len());
+        let order = FEATURES.iter().position(|(t, ..)| *t == f.tag).unwrap_or(FEATURES.len());
+        (category, order)
+    };
+    features.sort_by(|a, b| rank(a).cmp(&rank(b)).then_with(|| a.tag.cmp(&b.tag)));
+    Ok(features)
+}
@@ -0,0 +1,124 @@
+//! Licensing details fonts carry in their `name` and `OS/2` tables, so the
+//! font picker can show them and exports can respect embedding permissions.
+
+use super::variations::load_face;
+use serde::Serialize;
+use skrifa::raw::TableProvider;
+use skrifa::string::StringId;
+use skrifa::{FontRef, MetadataProvider};
+use tauri::command;
+
+/// `fsType` bits, from the OpenType `OS/2` table.
+const RESTRICTED: u16 = 0x0002;
+const PREVIEW_AND_PRINT: u16 = 0x0004;
+const EDITABLE: u16 = 0x0008;
+const NO_SUBSETTING: u16 = 0x0100;
+const BITMAP_ONLY: u16 = 0x0200;
+
+/// What a font's vendor allows documents that embed it to do.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Embedding {
+    /// May be embedded and installed permanently where the document is
+    /// opened.
+    Installable,
+    /// May be embedded in documents that can be edited.
+    Editable,
+    /// May be embedded in documents that are only viewed or printed.
+    PreviewAndPrint,
+    /// Must not be embedded at all.
+    Restricted,
+}
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingPermissions {
+    pub embedding: Embedding,
+    /// Whether embedding a subset is allowed, rather than only the whole
+    /// font.
+    pub subsetting: bool,
+    /// Whether only the font's bitmaps may be embedded, not its outlines.
+    pub bitmap_only: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FontLicense {
+    pub license: Option<String>,
+    pub license_url: Option<String>,
+    pub copyright: Option<String>,
+    pub designer: Option<String>,
+    pub designer_url: Option<String>,
+    pub manufacturer: Option<String>,
+    pub vendor_url: Option<String>,
+    pub version: Option<String>,
+    #[serde(flatten)]
+    pub permissions: EmbeddingPermissions,
+}
+
+/// Embedding permissions from the font's `fsType`. Fonts without an `OS/2`
+/// table carry no restrictions.
+pub fn embedding_permissions(font: &FontRef) -> EmbeddingPermissions {
+    let fs_type = font.os2().map_or(0, |os2| os2.fs_type());
+    // Older fonts may set several usage bits, in which case the least
+    // restrictive one applies
+    let embedding = if fs_type & 0x000F == 0 {
+        Embedding::Installable
+    } else if fs_type & EDITABLE != 0 {
+        Embedding::Editable
+    } else if fs_type & PREVIEW_AND_PRINT != 0 {
+        Embedding::PreviewAndPrint
+    } else if fs_type & RESTRICTED != 0 {
+        Embedding::Restricted
+    } else {
+        Embedding::Installable
+    };
+    EmbeddingPermissions {
+        embedding,
+        subsetting: fs_type & NO_SUBSETTING == 0,
+        bitmap_only: fs_type & BITMAP_ONLY != 0,
+    }
+}
+
+/// Why `data` must not be embedded in an export, if its license forbids it.
+pub fn embedding_restriction(data: &[u8]) -> Option<&'static str> {
+    let font = FontRef::new(data).ok()?;
+    let permissions = embedding_permissions(&font);
+    if permissions.embedding == Embedding::Restricted {
+        Some("its license does not allow embedding")
+    } else if permissions.bitmap_only {
+        Some("its license only allows embedding bitmaps")
+    } else {
+        None
+    }
+}
+
+/// Whether an export may embed a subset of `data` rather than all of it.
+pub fn subsetting_allowed(data: &[u8]) -> bool {
+    FontRef::new(data).is_ok_and(|font| embedding_permissions(&font).subsetting)
+}
+
+/// The license, credits and embedding permissions of an installed face,
+/// for the font picker and for warning before an export embeds it.
+#[command(async)]
+pub fn get_font_license(family: String, style: Option<String>) -> Result<FontLicense, String> {
+    let data = load_face(&family, style)?;
+    let font = FontRef::new(&data).map_err(|e| format!("Failed to parse font: {}", e))?;
+    let name = |id| {
+        let value = font.localized_strings(id).english_or_first()?.to_string();
+        let value = value.trim();
+        (!value.is_empty()).then(|| value.to_string())
+    };
+
+    Ok(FontLicense {
+        license: name(StringId::LICENSE_DESCRIPTION),
+        license_url: name(StringId::LICENSE_URL),
+        copyright: name(StringId::COPYRIGHT_NOTICE),
+        designer: name(StringId::DESIGNER),
+        designer_url: name(StringId::DESIGNER_URL),
+        manufacturer: name(StringId::MANUFACTURER),
+        vendor_url: name(StringId::VENDOR_URL),
+        version: name(StringId::VERSION_STRING),
+        permissions: embedding_permissions(&font),
+    })
+}
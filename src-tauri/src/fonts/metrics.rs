@@ -0,0 +1,170 @@
+//! Glyph measurements for the text editor, taken from the same shaping as
+//! exports so carets and letter spacing line up with the output.
+
+use super::shaping::{parse_features, shape, with_face, FontSpec};
+use rustybuzz::ttf_parser::GlyphId;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use tauri::command;
+
+/// One glyph in visual order, in pixels at the requested size with y up.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GlyphMetrics {
+    pub glyph_id: u16,
+    /// UTF-16 offset of the first character the glyph was made from.
+    pub cluster: usize,
+    /// Pen position before the glyph.
+    pub x: f32,
+    /// Where the glyph is drawn relative to the pen position, for marks
+    /// placed over their base.
+    pub x_offset: f32,
+    pub y_offset: f32,
+    /// Advance after shaping and letter spacing.
+    pub advance: f32,
+    /// Advance the font gives the glyph on its own.
+    pub nominal_advance: f32,
+    pub left_side_bearing: f32,
+    pub right_side_bearing: f32,
+    /// Ink bounds as `[xMin, yMin, xMax, yMax]` relative to the pen
+    /// position; none for blank glyphs such as spaces.
+    pub bounds: Option<[f32; 4]>,
+}
+
+/// Space the font adds or removes between two neighbouring glyphs.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KerningPair {
+    /// Indices into `glyphs`.
+    pub left: usize,
+    pub right: usize,
+    pub value: f32,
+}
+
+/// Where the caret goes before the character at `offset`.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaretPosition {
+    /// UTF-16 offset; the last caret is at the end of the text.
+    pub offset: usize,
+    pub x: f32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextMetrics {
+    pub glyphs: Vec<GlyphMetrics>,
+    pub kerning: Vec<KerningPair>,
+    /// One per character boundary, in logical order.
+    pub carets: Vec<CaretPosition>,
+    pub advance: f32,
+    pub ascender: f32,
+    pub descender: f32,
+    pub line_gap: f32,
+    pub direction: &'static str,
+}
+
+/// Caret positions at every character boundary. Characters that shaped into
+/// one glyph, such as the letters of a ligature, share its width evenly.
+fn carets(text: &str, glyphs: &[GlyphMetrics], rtl: bool, advance: f32) -> Vec<CaretPosition> {
+    // Visual extent of each cluster, by its starting offset
+    let mut extents: BTreeMap<usize, (f32, f32)> = BTreeMap::new();
+    for glyph in glyphs {
+        let extent = extents.entry(glyph.cluster).or_insert((f32::MAX, f32::MIN));
+        extent.0 = extent.0.min(glyph.x);
+        extent.1 = extent.1.max(glyph.x + glyph.advance);
+    }
+
+    let mut boundaries = Vec::new();
+    let mut offset = 0;
+    for c in text.chars() {
+        boundaries.push(offset);
+        offset += c.len_utf16();
+    }
+
+    let mut carets: Vec<CaretPosition> = boundaries
+        .iter()
+        .map(|&b| {
+            let Some((&start, &(x0, x1))) = extents.range(..=b).next_back() else {
+                return CaretPosition { offset: b, x: if rtl { advance } else { 0.0 } };
+            };
+            let end = extents.range(start + 1..).next().map_or(offset, |(&s, _)| s);
+            let chars = boundaries.iter().filter(|&&o| o >= start && o < end);
+            let n = chars.clone().count().max(1) as f32;
+            let k = chars.filter(|&&o| o < b).count() as f32;
+            let x = if rtl { x1 - (x1 - x0) * k / n } else { x0 + (x1 - x0) * k / n };
+            CaretPosition { offset: b, x }
+        })
+        .collect();
+    carets.push(CaretPosition { offset, x: if rtl { 0.0 } else { advance } });
+    carets
+}
+
+/// Shape `text` and measure each glyph: its advance before and after
+/// shaping, side bearings and ink bounds, the kerning between neighbours and
+/// where the caret goes at each character. `letter_spacing`, in pixels, is
+/// added after each cluster as exports do.
+#[command(async)]
+pub fn get_glyph_metrics(
+    font: FontSpec,
+    size: f32,
+    text: String,
+    letter_spacing: Option<f32>,
+    features: Option<Vec<String>>,
+) -> Result<TextMetrics, String> {
+    if !(size.is_finite() && size > 0.0) {
+        return Err(format!("Invalid font size: {}", size));
+    }
+    let features = parse_features(&features.unwrap_or_default())?;
+    let spacing = letter_spacing.filter(|s| s.is_finite()).unwrap_or(0.0);
+
+    with_face(&font, |face| {
+        let shaped = shape(face, &text, size, &features);
+        let scale = size / face.units_per_em() as f32;
+
+        let mut glyphs = Vec::with_capacity(shaped.glyphs.len());
+        let mut kerning = Vec::new();
+        let mut x = 0.0;
+        for (i, glyph) in shaped.glyphs.iter().enumerate() {
+            let id = GlyphId(glyph.glyph_id);
+            let nominal_advance = face.glyph_hor_advance(id).map_or(0.0, |a| a as f32 * scale);
+            let bounds = face.glyph_bounding_box(id).map(|b| {
+                [b.x_min as f32 * scale, b.y_min as f32 * scale, b.x_max as f32 * scale, b.y_max as f32 * scale]
+            });
+            let (left, right) = bounds.map_or((0.0, 0.0), |[x_min, _, x_max, _]| (x_min, nominal_advance - x_max));
+
+            let kern = glyph.x_advance - nominal_advance;
+            if kern.abs() > f32::EPSILON && i + 1 < shaped.glyphs.len() {
+                kerning.push(KerningPair { left: i, right: i + 1, value: kern });
+            }
+            let ends_cluster = shaped.glyphs.get(i + 1).is_none_or(|next| next.cluster != glyph.cluster);
+            let advance = glyph.x_advance + if ends_cluster { spacing } else { 0.0 };
+
+            glyphs.push(GlyphMetrics {
+                glyph_id: glyph.glyph_id,
+                cluster: glyph.cluster,
+                x,
+                x_offset: glyph.x_offset,
+                y_offset: glyph.y_offset,
+                advance,
+                nominal_advance,
+                left_side_bearing: left,
+                right_side_bearing: right,
+                bounds,
+            });
+            x += advance;
+        }
+
+        let rtl = shaped.direction == "rtl";
+        TextMetrics {
+            carets: carets(&text, &glyphs, rtl, x),
+            glyphs,
+            kerning,
+            advance: x,
+            ascender: shaped.ascender,
+            descender: shaped.descender,
+            line_gap: shaped.line_gap,
+            direction: shaped.direction,
+        }
+    })
+}
@@ -0,0 +1,243 @@
+//! Finding the fonts a document uses that are not installed, with
+//! substitutes that would keep its text about the same size and shape.
+
+use super::remote::GoogleFonts;
+use super::{find_face, font_database};
+use crate::model::{DocumentTree, NodeType};
+use rustybuzz::ttf_parser::{self, Tag};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use tauri::{command, State};
+use usvg::fontdb::{Database, FaceInfo, Family, Query, Stretch, Style, Weight};
+
+/// Text layers without style ranges are drawn with this, as on the canvas.
+const DEFAULT_FAMILY: &str = "Inter";
+const DEFAULT_WEIGHT: u16 = 400;
+const MAX_SUBSTITUTES: usize = 5;
+/// Characters whose advances give a family's average width.
+const WIDTH_SAMPLE: &str = "abcdefghijklmnopqrstuvwxyz";
+
+/// Families designed with the same metrics as a common font, so text set in
+/// one lays out identically in the others.
+const METRIC_COMPATIBLE: &[(&str, &[&str])] = &[
+    ("Arial", &["Liberation Sans", "Arimo", "Helvetica", "Nimbus Sans"]),
+    ("Helvetica", &["Arial", "Liberation Sans", "Arimo", "Nimbus Sans"]),
+    ("Helvetica Neue", &["Arial", "Liberation Sans", "Arimo", "Nimbus Sans"]),
+    ("Times New Roman", &["Liberation Serif", "Tinos", "Times", "Nimbus Roman"]),
+    ("Times", &["Times New Roman", "Liberation Serif", "Tinos", "Nimbus Roman"]),
+    ("Courier New", &["Liberation Mono", "Cousine", "Courier", "Nimbus Mono PS"]),
+    ("Courier", &["Courier New", "Liberation Mono", "Cousine", "Nimbus Mono PS"]),
+    ("Calibri", &["Carlito"]),
+    ("Cambria", &["Caladea"]),
+    ("Georgia", &["Gelasio"]),
+    ("Segoe UI", &["Selawik"]),
+    ("Arial Narrow", &["Liberation Sans Narrow"]),
+];
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FontSubstitute {
+    pub family: String,
+    /// Made to the same metrics as the missing family, so no text reflows.
+    pub metric_compatible: bool,
+    /// How close the metrics are, from 0 to 1. Without a metric-compatible
+    /// font to compare with, this is measured against the default font of
+    /// the generic family the name suggests, such as monospace for "Code".
+    pub similarity: f32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MissingFont {
+    pub family: String,
+    /// Weights the document uses this family at.
+    pub weights: Vec<u16>,
+    /// Text layers that use it.
+    pub node_ids: Vec<String>,
+    /// Best first.
+    pub substitutes: Vec<FontSubstitute>,
+    /// The family is on Google Fonts and can be downloaded instead.
+    pub downloadable: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MissingFontsReport {
+    pub missing: Vec<MissingFont>,
+    /// Families the document uses that are installed.
+    pub installed: Vec<String>,
+}
+
+/// Proportions of a family's regular face, relative to its em.
+#[derive(Clone, Copy)]
+struct Metrics {
+    x_height: f32,
+    cap_height: f32,
+    width: f32,
+    ascender: f32,
+    descender: f32,
+    monospace: bool,
+    /// From the PANOSE serif style, where the font sets it.
+    serif: Option<bool>,
+}
+
+fn metrics(db: &Database, face: &FaceInfo) -> Option<Metrics> {
+    db.with_face_data(face.id, |data, index| {
+        let face = ttf_parser::Face::parse(data, index).ok()?;
+        let em = face.units_per_em() as f32;
+        let advances: Vec<f32> = WIDTH_SAMPLE
+            .chars()
+            .filter_map(|c| face.glyph_index(c))
+            .filter_map(|g| face.glyph_hor_advance(g))
+            .map(f32::from)
+            .collect();
+        if advances.is_empty() {
+            return None;
+        }
+        let serif = match face.raw_face().table(Tag::from_bytes(b"OS/2")).and_then(|os2| os2.get(33)) {
+            Some(2..=10) => Some(true),
+            Some(11..=13) => Some(false),
+            _ => None,
+        };
+
+        Some(Metrics {
+            x_height: face.x_height().map_or(0.0, f32::from) / em,
+            cap_height: face.capital_height().map_or(0.0, f32::from) / em,
+            width: advances.iter().sum::<f32>() / advances.len() as f32 / em,
+            ascender: f32::from(face.ascender()) / em,
+            descender: f32::from(face.descender()) / em,
+            monospace: face.is_monospaced(),
+            serif,
+        })
+    })
+    .flatten()
+}
+
+fn similarity(a: &Metrics, b: &Metrics) -> f32 {
+    let mut distance = 2.0 * (a.x_height - b.x_height).abs()
+        + (a.cap_height - b.cap_height).abs()
+        + 3.0 * (a.width - b.width).abs()
+        + 0.5 * ((a.ascender - b.ascender).abs() + (a.descender - b.descender).abs());
+    if a.monospace != b.monospace {
+        distance += 0.5;
+    }
+    if let (Some(a), Some(b)) = (a.serif, b.serif) {
+        if a != b {
+            distance += 0.2;
+        }
+    }
+    1.0 / (1.0 + 8.0 * distance)
+}
+
+/// The generic family a name suggests, to compare against when nothing is
+/// known about the font itself.
+fn generic_family(family: &str) -> Family<'static> {
+    let name = family.to_lowercase();
+    let has = |words: &[&str]| words.iter().any(|w| name.contains(w));
+    if has(&["mono", "code", "courier", "consol"]) {
+        Family::Monospace
+    } else if has(&["serif", "times", "roman", "georgia", "garamond", "baskerville", "caslon", "bodoni", "didot"])
+        && !name.contains("sans")
+    {
+        Family::Serif
+    } else {
+        Family::SansSerif
+    }
+}
+
+fn metric_compatible(family: &str) -> &'static [&'static str] {
+    METRIC_COMPATIBLE
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(family))
+        .map_or(&[], |(_, compatible)| compatible)
+}
+
+/// Installed families other than hidden system ones, with the metrics of
+/// their regular faces.
+fn installed_metrics(db: &Database) -> Vec<(String, Metrics)> {
+    let names: BTreeSet<&str> = db
+        .faces()
+        .filter_map(|f| f.families.first())
+        .map(|(name, _)| name.as_str())
+        .filter(|name| !name.starts_with('.'))
+        .collect();
+    names
+        .into_iter()
+        .filter_map(|name| Some((name.to_string(), metrics(db, find_face(db, name, "Regular")?)?)))
+        .collect()
+}
+
+fn substitutes(db: &Database, family: &str, installed: &[(String, Metrics)]) -> Vec<FontSubstitute> {
+    let find = |name: &str| installed.iter().find(|(n, _)| n.eq_ignore_ascii_case(name));
+    let mut substitutes: Vec<FontSubstitute> = metric_compatible(family)
+        .iter()
+        .filter_map(|name| find(name))
+        .map(|(name, _)| FontSubstitute { family: name.clone(), metric_compatible: true, similarity: 1.0 })
+        .collect();
+
+    // Compare against a metric-compatible font when one is installed, since
+    // it stands in for the missing one exactly
+    let reference = metric_compatible(family).iter().find_map(|name| find(name)).map(|(_, m)| *m).or_else(|| {
+        let query = Query { families: &[generic_family(family)], weight: Weight::NORMAL, stretch: Stretch::Normal, style: Style::Normal };
+        metrics(db, db.face(db.query(&query)?)?)
+    });
+    let Some(reference) = reference else { return substitutes };
+
+    let mut ranked: Vec<(f32, &String)> = installed
+        .iter()
+        .filter(|(name, _)| !substitutes.iter().any(|s| &s.family == name))
+        .map(|(name, m)| (similarity(&reference, m), name))
+        .collect();
+    ranked.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+    let room = MAX_SUBSTITUTES.saturating_sub(substitutes.len());
+    substitutes.extend(ranked.into_iter().take(room).map(|(similarity, name)| FontSubstitute {
+        family: name.clone(),
+        metric_compatible: false,
+        similarity,
+    }));
+    substitutes
+}
+
+/// Check the fonts a document's text uses against the installed ones, for
+/// the missing fonts dialog shown when it is opened. Each missing family
+/// comes with substitutes ranked by how closely their metrics match, and
+/// whether it can be downloaded from Google Fonts instead.
+#[command(async)]
+pub fn find_missing_fonts(google_fonts: State<'_, GoogleFonts>, node_json: String) -> Result<MissingFontsReport, String> {
+    let tree = DocumentTree::parse(&node_json)?;
+
+    // family -> (weights, node ids), keyed case-insensitively
+    let mut used: BTreeMap<String, (String, BTreeSet<u16>, BTreeSet<String>)> = BTreeMap::new();
+    for node in tree.nodes().filter(|n| n.node_type == NodeType::Text) {
+        let styles: Vec<(&str, u16)> = match node.text_styles.as_deref() {
+            Some(styles) if !styles.is_empty() => styles.iter().map(|s| (s.font_family.as_str(), s.font_weight)).collect(),
+            _ => vec![(DEFAULT_FAMILY, DEFAULT_WEIGHT)],
+        };
+        for (family, weight) in styles {
+            let entry = used.entry(family.to_lowercase()).or_insert_with(|| (family.to_string(), BTreeSet::new(), BTreeSet::new()));
+            entry.1.insert(weight);
+            entry.2.insert(node.id.clone());
+        }
+    }
+
+    let db = font_database();
+    let (installed, missing): (Vec<_>, Vec<_>) = used.into_values().partition(|(family, _, _)| find_face(&db, family, "Regular").is_some());
+    if missing.is_empty() {
+        return Ok(MissingFontsReport { missing: Vec::new(), installed: installed.into_iter().map(|(f, _, _)| f).collect() });
+    }
+
+    let metrics = installed_metrics(&db);
+    let downloadable: HashSet<String> = google_fonts.cached_families();
+    let missing = missing
+        .into_iter()
+        .map(|(family, weights, node_ids)| MissingFont {
+            substitutes: substitutes(&db, &family, &metrics),
+            downloadable: downloadable.contains(&family.to_lowercase()),
+            weights: weights.into_iter().collect(),
+            node_ids: node_ids.into_iter().collect(),
+            family,
+        })
+        .collect();
+
+    Ok(MissingFontsReport { missing, installed: installed.into_iter().map(|(f, _, _)| f).collect() })
+}
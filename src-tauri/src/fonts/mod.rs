@@ -0,0 +1,492 @@
+//! Installed fonts: discovery for the font picker, loading faces for the
+//! webview and text layout.
+
+pub mod emoji;
+pub mod fallback;
+pub mod features;
+pub mod license;
+pub mod metrics;
+pub mod missing;
+pub mod outlines;
+pub mod preview;
+pub mod remote;
+pub mod shaping;
+pub mod subset;
+pub mod user;
+pub mod variations;
+pub mod vertical;
+pub mod watch;
+pub mod webfont;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use tauri::command;
+use usvg::fontdb::{Database, FaceInfo, Family, Query, Source, Stretch, Style, Weight};
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FontFaceInfo {
+    /// Style as a font picker shows it, such as "SemiBold Condensed Italic".
+    pub style_name: String,
+    /// `normal`, `italic` or `oblique`, as in CSS.
+    pub style: &'static str,
+    /// 100 to 900.
+    pub weight: u16,
+    /// Width as a percentage of normal, as in CSS `font-stretch`.
+    pub width: f32,
+    pub monospace: bool,
+    pub postscript_name: String,
+    pub path: String,
+    /// Face index within a `.ttc` collection; 0 for single-face files.
+    pub index: u32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadedFont {
+    pub family: String,
+    pub style_name: String,
+    /// Descriptors to register the face with, as in `new FontFace(family,
+    /// data, { style, weight, stretch })`.
+    pub style: &'static str,
+    pub weight: u16,
+    pub width: f32,
+    /// Base64-encoded font file holding just this face.
+    pub data: String,
+    pub mime_type: &'static str,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FontFamilyInfo {
+    pub family: String,
+    /// Narrowest first, then lightest, with upright before italic.
+    pub faces: Vec<FontFaceInfo>,
+}
+
+/// The font database once it has been loaded, and directories of fonts the
+/// app manages to load into it alongside the system's.
+static FONTS: RwLock<Option<Arc<Database>>> = RwLock::new(None);
+static FONT_DIRS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+fn load_database() -> Database {
+    let mut db = Database::new();
+    db.load_system_fonts();
+    load_fontconfig_fonts(&mut db);
+    for dir in FONT_DIRS.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+        db.load_fonts_dir(dir);
+    }
+
+    let installed = |db: &Database, name: &str| db.faces().any(|f| f.families.iter().any(|(n, _)| n == name));
+    let fallbacks = [
+        (Family::SansSerif, ["DejaVu Sans", "Liberation Sans", "Noto Sans"]),
+        (Family::Serif, ["DejaVu Serif", "Liberation Serif", "Noto Serif"]),
+        (Family::Monospace, ["DejaVu Sans Mono", "Liberation Mono", "Noto Sans Mono"]),
+    ];
+    for (generic, candidates) in fallbacks {
+        if installed(&db, db.family_name(&generic)) {
+            continue;
+        }
+        if let Some(name) = candidates.into_iter().find(|c| installed(&db, c)) {
+            match generic {
+                Family::Serif => db.set_serif_family(name),
+                Family::Monospace => db.set_monospace_family(name),
+                _ => db.set_sans_serif_family(name),
+            }
+        }
+    }
+    db
+}
+
+/// Add the fonts fontconfig lists that fontdb's reading of its configuration
+/// missed, such as those in directories built into the distribution's
+/// fontconfig or mounted from the host under Flatpak, and use fontconfig's
+/// choices for the generic families. `fc-list` and `fc-match` are run rather
+/// than linking libfontconfig, so nothing changes where it is not installed.
+#[cfg(target_os = "linux")]
+fn load_fontconfig_fonts(db: &mut Database) {
+    use std::collections::HashSet;
+    use std::process::Command;
+
+    let run = |program: &str, args: &[&str]| {
+        let output = Command::new(program).args(args).output().ok().filter(|o| o.status.success())?;
+        String::from_utf8(output.stdout).ok()
+    };
+
+    if let Some(files) = run("fc-list", &["--format", "%{file}\n"]) {
+        let mut loaded: HashSet<PathBuf> = db
+            .faces()
+            .filter_map(|face| match &face.source {
+                Source::File(path) | Source::SharedFile(path, _) => std::fs::canonicalize(path).ok(),
+                Source::Binary(_) => None,
+            })
+            .collect();
+        for file in files.lines().filter(|l| !l.is_empty()) {
+            let Ok(path) = std::fs::canonicalize(file) else { continue };
+            if loaded.insert(path.clone()) {
+                let _ = db.load_font_file(&path);
+            }
+        }
+    }
+
+    for (generic, pattern) in [(Family::SansSerif, "sans-serif"), (Family::Serif, "serif"), (Family::Monospace, "monospace")] {
+        let Some(name) = run("fc-match", &["--format", "%{family[0]}", pattern]) else { continue };
+        if db.faces().any(|f| f.families.iter().any(|(n, _)| *n == name)) {
+            match generic {
+                Family::Serif => db.set_serif_family(name),
+                Family::Monospace => db.set_monospace_family(name),
+                _ => db.set_sans_serif_family(name),
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn load_fontconfig_fonts(_db: &mut Database) {}
+
+/// System fonts for text layout, loaded on first use, along with those in
+/// registered font directories.
+///
+/// fontdb maps generic families to Windows/macOS fonts; where those are
+/// missing, common Linux families are used instead so text is not dropped.
+pub fn font_database() -> Arc<Database> {
+    if let Some(db) = FONTS.read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        return Arc::clone(db);
+    }
+    let mut fonts = FONTS.write().unwrap_or_else(|e| e.into_inner());
+    Arc::clone(fonts.get_or_insert_with(|| Arc::new(load_database())))
+}
+
+/// Apply `f` to a copy of the database, if it has been loaded, and publish the
+/// result. Users of the previous copy keep it until they are done.
+fn update_database(f: impl FnOnce(&mut Database)) {
+    let mut fonts = FONTS.write().unwrap_or_else(|e| e.into_inner());
+    if let Some(current) = fonts.as_mut() {
+        let mut db = Database::clone(current);
+        f(&mut db);
+        *current = Arc::new(db);
+    }
+}
+
+/// Load the fonts in `dir` and its subdirectories from now on, for fonts the
+/// app installs itself.
+pub fn register_font_dir(dir: PathBuf) {
+    let mut dirs = FONT_DIRS.lock().unwrap_or_else(|e| e.into_inner());
+    if dirs.contains(&dir) {
+        return;
+    }
+    dirs.push(dir.clone());
+    drop(dirs);
+    update_database(|db| db.load_fonts_dir(&dir));
+}
+
+/// Make newly added font files usable without reloading every font.
+pub fn register_font_files(files: &[PathBuf]) {
+    update_database(|db| {
+        for file in files {
+            let _ = db.load_font_file(file);
+        }
+    });
+}
+
+/// Forget the faces loaded from files under `path`, e.g. before deleting them.
+pub fn unregister_fonts(path: &Path) {
+    update_database(|db| {
+        let ids: Vec<_> = db
+            .faces()
+            .filter(|face| match &face.source {
+                Source::File(file) | Source::SharedFile(file, _) => file.starts_with(path),
+                Source::Binary(_) => false,
+            })
+            .map(|face| face.id)
+            .collect();
+        for id in ids {
+            db.remove_face(id);
+        }
+    });
+}
+
+/// Faces that reloading font files added and removed, grouped as
+/// `get_system_fonts` lists them. A font file that was replaced shows up in
+/// both.
+#[derive(Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct FontsChange {
+    pub added: Vec<FontFamilyInfo>,
+    pub removed: Vec<FontFamilyInfo>,
+}
+
+impl FontsChange {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Reload the fonts at `paths`, files or directories, after they were
+/// created, changed or deleted by another program. Nothing is done until
+/// the database has been loaded, as it will read them then.
+pub fn reload_font_paths(paths: &[PathBuf]) -> FontsChange {
+    let mut change = FontsChange::default();
+    update_database(|db| {
+        let under = |face: &FaceInfo| match &face.source {
+            Source::File(file) | Source::SharedFile(file, _) => paths.iter().any(|path| file.starts_with(path)),
+            Source::Binary(_) => false,
+        };
+        let removed: Vec<FaceInfo> = db.faces().filter(|face| under(face)).cloned().collect();
+        for face in &removed {
+            db.remove_face(face.id);
+        }
+
+        for path in paths {
+            if path.is_dir() {
+                db.load_fonts_dir(path);
+            } else if path.is_file() {
+                let _ = db.load_font_file(path);
+            }
+        }
+        change.added = group_families(db.faces().filter(|face| under(face)));
+        change.removed = group_families(removed.iter());
+    });
+    change
+}
+
+/// Numeric weight implied by a style name such as "SemiBold Italic".
+pub fn weight_from_style(style: &str) -> u16 {
+    let style = style.to_lowercase().replace([' ', '-', '_'], "");
+    // Compound names first so "semibold" is not read as "bold"
+    [
+        ("thin", 100),
+        ("hairline", 100),
+        ("extralight", 200),
+        ("ultralight", 200),
+        ("semibold", 600),
+        ("demibold", 600),
+        ("extrabold", 800),
+        ("ultrabold", 800),
+        ("light", 300),
+        ("medium", 500),
+        ("bold", 700),
+        ("black", 900),
+        ("heavy", 900),
+    ]
+    .iter()
+    .find(|(name, _)| style.contains(name))
+    .map(|(_, weight)| *weight)
+    .unwrap_or(400)
+}
+
+/// Family and weight for a PostScript font name, from the installed face if
+/// there is one, otherwise guessed from the name ("Helvetica-Bold").
+pub fn resolve_postscript_name(name: &str) -> (String, u16) {
+    let db = font_database();
+    if let Some(face) = db.faces().find(|f| f.post_script_name == name) {
+        if let Some((family, _)) = face.families.first() {
+            return (family.clone(), face.weight.0);
+        }
+    }
+    match name.split_once('-') {
+        Some((family, style)) => (family.to_string(), weight_from_style(style)),
+        None => (name.to_string(), 400),
+    }
+}
+
+fn stretch_percent(stretch: Stretch) -> f32 {
+    match stretch {
+        Stretch::UltraCondensed => 50.0,
+        Stretch::ExtraCondensed => 62.5,
+        Stretch::Condensed => 75.0,
+        Stretch::SemiCondensed => 87.5,
+        Stretch::Normal => 100.0,
+        Stretch::SemiExpanded => 112.5,
+        Stretch::Expanded => 125.0,
+        Stretch::ExtraExpanded => 150.0,
+        Stretch::UltraExpanded => 200.0,
+    }
+}
+
+/// Style name built from the face's weight, width and slant. fontdb does not
+/// keep the subfamily name, and reading it would mean opening every file.
+fn style_name(face: &FaceInfo) -> String {
+    let weight = match face.weight.0 {
+        0..=149 => "Thin",
+        150..=249 => "ExtraLight",
+        250..=349 => "Light",
+        350..=449 => "",
+        450..=549 => "Medium",
+        550..=649 => "SemiBold",
+        650..=749 => "Bold",
+        750..=849 => "ExtraBold",
+        _ => "Black",
+    };
+    let width = match face.stretch {
+        Stretch::UltraCondensed => "UltraCondensed",
+        Stretch::ExtraCondensed => "ExtraCondensed",
+        Stretch::Condensed => "Condensed",
+        Stretch::SemiCondensed => "SemiCondensed",
+        Stretch::Normal => "",
+        Stretch::SemiExpanded => "SemiExpanded",
+        Stretch::Expanded => "Expanded",
+        Stretch::ExtraExpanded => "ExtraExpanded",
+        Stretch::UltraExpanded => "UltraExpanded",
+    };
+    let slant = match face.style {
+        Style::Normal => "",
+        Style::Italic => "Italic",
+        Style::Oblique => "Oblique",
+    };
+
+    let parts: Vec<&str> = [weight, width, slant].into_iter().filter(|p| !p.is_empty()).collect();
+    if parts.is_empty() { "Regular".to_string() } else { parts.join(" ") }
+}
+
+fn css_style(style: Style) -> &'static str {
+    match style {
+        Style::Normal => "normal",
+        Style::Italic => "italic",
+        Style::Oblique => "oblique",
+    }
+}
+
+/// Copy face `index` out of a TrueType collection into a standalone font
+/// file, since webviews do not accept collections. Tables shared between
+/// faces are simply duplicated.
+fn extract_collection_face(data: &[u8], index: u32) -> Option<Vec<u8>> {
+    let read_u32 = |at: usize| data.get(at..at + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]));
+    let read_u16 = |at: usize| data.get(at..at + 2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+
+    if read_u32(8)? <= index {
+        return None;
+    }
+    let header = read_u32(12 + 4 * index as usize)? as usize;
+    let num_tables = read_u16(header + 4)? as usize;
+    let directory_len = 12 + 16 * num_tables;
+
+    let mut out = data.get(header..header + directory_len)?.to_vec();
+    for i in 0..num_tables {
+        let record = header + 12 + 16 * i;
+        let offset = read_u32(record + 8)? as usize;
+        let length = read_u32(record + 12)? as usize;
+        let table = data.get(offset..offset.checked_add(length)?)?;
+
+        let new_offset = out.len() as u32;
+        out[12 + 16 * i + 8..12 + 16 * i + 12].copy_from_slice(&new_offset.to_be_bytes());
+        out.extend_from_slice(table);
+        // Tables start on four-byte boundaries
+        out.resize(out.len().next_multiple_of(4), 0);
+    }
+    Some(out)
+}
+
+/// The file holding `face`, narrowed down to the one face for collections.
+pub fn face_data(db: &Database, face: &FaceInfo) -> Option<Vec<u8>> {
+    db.with_face_data(face.id, |data, index| {
+        if data.starts_with(b"ttcf") { extract_collection_face(data, index) } else { Some(data.to_vec()) }
+    })
+    .flatten()
+}
+
+/// `faces` grouped by family as the font picker lists them. Families are
+/// sorted by name; faces loaded from memory rather than a file are left out.
+fn group_families<'a>(faces: impl Iterator<Item = &'a FaceInfo>) -> Vec<FontFamilyInfo> {
+    let mut families: BTreeMap<String, FontFamilyInfo> = BTreeMap::new();
+
+    for face in faces {
+        let path = match &face.source {
+            Source::File(path) | Source::SharedFile(path, _) => path,
+            Source::Binary(_) => continue,
+        };
+        // The first family name is the English one when the font has it
+        let Some((family, _)) = face.families.first() else { continue };
+
+        let style = css_style(face.style);
+        families
+            .entry(family.to_lowercase())
+            .or_insert_with(|| FontFamilyInfo { family: family.clone(), faces: Vec::new() })
+            .faces
+            .push(FontFaceInfo {
+                style_name: style_name(face),
+                style,
+                weight: face.weight.0,
+                width: stretch_percent(face.stretch),
+                monospace: face.monospaced,
+                postscript_name: face.post_script_name.clone(),
+                path: path.to_string_lossy().into_owned(),
+                index: face.index,
+            });
+    }
+
+    let mut families: Vec<FontFamilyInfo> = families.into_values().collect();
+    for family in &mut families {
+        family.faces.sort_by(|a, b| {
+            a.width
+                .total_cmp(&b.width)
+                .then(a.weight.cmp(&b.weight))
+                .then((a.style != "normal").cmp(&(b.style != "normal")))
+        });
+        // The same file can be found through more than one font directory
+        family.faces.dedup_by(|a, b| a.postscript_name == b.postscript_name && a.style_name == b.style_name);
+    }
+    families
+}
+
+/// Installed font faces grouped by family, for the font picker.
+#[command(async)]
+pub fn get_system_fonts() -> Vec<FontFamilyInfo> {
+    group_families(font_database().faces())
+}
+
+/// The face of `family` with the style name `style`, as listed by
+/// `get_system_fonts`, or else the closest match for a name such as
+/// "Bold Italic". Family names are matched case-insensitively.
+pub fn find_face<'a>(db: &'a Database, family: &str, style: &str) -> Option<&'a FaceInfo> {
+    let in_family = |face: &&FaceInfo| face.families.iter().any(|(name, _)| name.eq_ignore_ascii_case(family));
+    if let Some(face) = db.faces().filter(in_family).find(|face| style_name(face).eq_ignore_ascii_case(style)) {
+        return Some(face);
+    }
+
+    let name = db.faces().find(in_family)?.families.first()?;
+    let lower = style.to_lowercase();
+    let slant = if lower.contains("italic") {
+        Style::Italic
+    } else if lower.contains("oblique") {
+        Style::Oblique
+    } else {
+        Style::Normal
+    };
+    let query = Query {
+        families: &[Family::Name(&name.0)],
+        weight: Weight(weight_from_style(style)),
+        stretch: Stretch::Normal,
+        style: slant,
+    };
+    db.query(&query).and_then(|id| db.face(id))
+}
+
+/// Load an installed face so the webview can render text with it. `style` is
+/// a style name from `get_system_fonts`; other names such as "Bold Italic"
+/// get the closest face of the family. Collections are narrowed down to the
+/// one face.
+#[command(async)]
+pub fn load_font(family: String, style: Option<String>) -> Result<LoadedFont, String> {
+    let db = font_database();
+    let style = style.unwrap_or_else(|| "Regular".to_string());
+    let face = find_face(&db, &family, &style)
+        .ok_or_else(|| format!("Font not installed: {} {}", family, style))?;
+
+    let bytes = face_data(&db, face)
+        .ok_or_else(|| format!("Failed to read font: {} {}", family, style))?;
+    let mime_type = if bytes.starts_with(b"OTTO") { "font/otf" } else { "font/ttf" };
+
+    Ok(LoadedFont {
+        family: face.families.first().map_or(family.clone(), |(name, _)| name.clone()),
+        style_name: style_name(face),
+        style: css_style(face.style),
+        weight: face.weight.0,
+        width: stretch_percent(face.stretch),
+        data: BASE64.encode(bytes),
+        mime_type,
+    })
+}
@@ -0,0 +1,299 @@
+//! Text converted to vector paths, for finalizing logos and for exporting to
+//! formats that cannot embed fonts.
+
+use super::shaping::{bidi_runs, level_direction, shape_in_direction};
+use super::subset::embeddable_face;
+use crate::geometry::{path_bounds, transform_path, translate};
+use crate::model::{generate_node_id, NodeData, NodeType, Paint, PathCommand, TextStyleRange, VectorPath, WindingRule};
+use rustybuzz::ttf_parser::{GlyphId, OutlineBuilder, Tag};
+use rustybuzz::{Direction, Face, Variation};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use tauri::command;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutlinedText {
+    /// A group standing in for the text node, first, then one vector node
+    /// inside it for each distinct fill.
+    pub nodes: Vec<NodeData>,
+    /// Characters the font has no glyph for, which were left out.
+    pub missing: Vec<String>,
+}
+
+/// Glyph outlines in font units, appended as path commands placed at `x`,
+/// `y` in pixels with y down.
+struct PathPen<'a> {
+    commands: &'a mut Vec<PathCommand>,
+    x: f64,
+    y: f64,
+    scale: f64,
+    current: (f64, f64),
+}
+
+impl PathPen<'_> {
+    fn point(&self, x: f32, y: f32) -> (f64, f64) {
+        (self.x + x as f64 * self.scale, self.y - y as f64 * self.scale)
+    }
+}
+
+impl OutlineBuilder for PathPen<'_> {
+    fn move_to(&mut self, x: f32, y: f32) {
+        let (x, y) = self.point(x, y);
+        self.commands.push(PathCommand::MoveTo { x, y });
+        self.current = (x, y);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let (x, y) = self.point(x, y);
+        self.commands.push(PathCommand::LineTo { x, y });
+        self.current = (x, y);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        // Documents only hold cubics; raise the degree exactly
+        let ((qx, qy), (x, y), (x0, y0)) = (self.point(x1, y1), self.point(x, y), self.current);
+        self.commands.push(PathCommand::CurveTo {
+            x1: x0 + (qx - x0) * 2.0 / 3.0,
+            y1: y0 + (qy - y0) * 2.0 / 3.0,
+            x2: x + (qx - x) * 2.0 / 3.0,
+            y2: y + (qy - y) * 2.0 / 3.0,
+            x,
+            y,
+        });
+        self.current = (x, y);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let ((x1, y1), (x2, y2), (x, y)) = (self.point(x1, y1), self.point(x2, y2), self.point(x, y));
+        self.commands.push(PathCommand::CurveTo { x1, y1, x2, y2, x, y });
+        self.current = (x, y);
+    }
+
+    fn close(&mut self) {
+        self.commands.push(PathCommand::ClosePath);
+    }
+}
+
+fn push_rect(commands: &mut Vec<PathCommand>, x0: f64, x1: f64, y: f64, thickness: f64) {
+    let (top, bottom) = (y - thickness / 2.0, y + thickness / 2.0);
+    commands.extend([
+        PathCommand::MoveTo { x: x0, y: top },
+        PathCommand::LineTo { x: x1, y: top },
+        PathCommand::LineTo { x: x1, y: bottom },
+        PathCommand::LineTo { x: x0, y: bottom },
+        PathCommand::ClosePath,
+    ]);
+}
+
+/// A run of one style and direction on one line.
+struct Run<'a> {
+    style: &'a TextStyleRange,
+    text: String,
+    direction: Direction,
+}
+
+/// Shape `run` with its font and append its glyphs and decoration at `x`,
+/// `baseline`. Characters without a glyph are added to `missing`.
+fn draw_run(
+    face: &Face,
+    run: &Run,
+    x: f64,
+    baseline: f64,
+    commands: &mut Vec<PathCommand>,
+    missing: &mut BTreeSet<char>,
+) -> f64 {
+    let style = run.style;
+    let size = style.font_size as f32;
+    let shaped = shape_in_direction(face, &run.text, size, &[], Some(run.direction));
+    let scale = style.font_size / face.units_per_em() as f64;
+    missing.extend(run.text.chars().filter(|c| !c.is_whitespace() && face.glyph_index(*c).is_none()));
+
+    let mut pen_x = x;
+    for (i, glyph) in shaped.glyphs.iter().enumerate() {
+        if glyph.glyph_id != 0 {
+            let mut pen = PathPen {
+                commands,
+                x: pen_x + glyph.x_offset as f64,
+                y: baseline - glyph.y_offset as f64,
+                scale,
+                current: (0.0, 0.0),
+            };
+            face.outline_glyph(GlyphId(glyph.glyph_id), &mut pen);
+        }
+        let ends_cluster = shaped.glyphs.get(i + 1).is_none_or(|next| next.cluster != glyph.cluster);
+        pen_x += glyph.x_advance as f64 + if ends_cluster { style.letter_spacing } else { 0.0 };
+    }
+
+    let units = |v: i16| v as f64 * scale;
+    let decoration = match style.text_decoration.as_deref() {
+        Some("UNDERLINE") => Some(face.underline_metrics().map_or(
+            (-0.1 * style.font_size, 0.05 * style.font_size),
+            |m| (units(m.position), units(m.thickness)),
+        )),
+        Some("STRIKETHROUGH") => Some(face.strikeout_metrics().map_or(
+            (0.3 * style.font_size, 0.05 * style.font_size),
+            |m| (units(m.position), units(m.thickness)),
+        )),
+        _ => None,
+    };
+    if let Some((position, thickness)) = decoration.filter(|_| pen_x > x) {
+        push_rect(commands, x, pen_x, baseline - position, thickness.max(0.5));
+    }
+    pen_x - x
+}
+
+/// Run `f` with the face `style` is drawn with, at its weight.
+fn with_style_face<T>(style: &TextStyleRange, f: impl FnOnce(&Face) -> T) -> Result<T, String> {
+    let font = embeddable_face(&style.font_family, style.font_weight)
+        .ok_or_else(|| format!("Font not installed: {}", style.font_family))?;
+    let mut face = Face::from_slice(&font.data, 0).ok_or_else(|| format!("Failed to read font: {}", style.font_family))?;
+    let variations: Vec<Variation> = font
+        .coordinates
+        .iter()
+        .map(|(tag, value)| Variation { tag: Tag::from_bytes(&tag.to_be_bytes()), value: *value })
+        .collect();
+    face.set_variations(&variations);
+    Ok(f(&face))
+}
+
+/// Vector nodes drawn with the glyph outlines of a text node's installed
+/// fonts, underlines and strikethroughs included. Lines are placed as SVG
+/// export places them, with right-to-left text reordered by the Unicode
+/// bidi algorithm.
+pub fn outline_text(node: &NodeData) -> Result<OutlinedText, String> {
+    if node.node_type != NodeType::Text {
+        return Err(format!("Not a text node: {}", node.id));
+    }
+
+    let characters: Vec<char> = node.characters.as_deref().unwrap_or_default().chars().collect();
+    let mut styles = node.text_styles.clone().unwrap_or_default();
+    styles.sort_by_key(|s| s.start);
+    let fallback = TextStyleRange::unstyled(characters.len());
+    let base = styles.first().unwrap_or(&fallback);
+    let style_at = |i: usize| styles.iter().find(|s| s.start <= i && i < s.end).unwrap_or(base);
+
+    // Lines of runs in display order: the bidi runs, split where the style
+    // changes
+    let text: String = characters.iter().collect();
+    let mut lines: Vec<Vec<Run>> = Vec::new();
+    let mut line_start = 0;
+    for line in text.split('\n') {
+        let mut runs = Vec::new();
+        for (range, level) in bidi_runs(line, None).1 {
+            let direction = level_direction(level);
+            let first = line_start + line[..range.start].chars().count();
+            let mut pieces: Vec<Run> = Vec::new();
+            for (index, c) in (first..).zip(line[range].chars()) {
+                let style = style_at(index);
+                match pieces.last_mut() {
+                    Some(run) if std::ptr::eq(run.style, style) => run.text.push(c),
+                    _ => pieces.push(Run { style, text: c.to_string(), direction }),
+                }
+            }
+            // A right-to-left run is displayed from its end
+            if direction == Direction::RightToLeft {
+                pieces.reverse();
+            }
+            runs.extend(pieces);
+        }
+        line_start += line.chars().count() + 1;
+        lines.push(runs);
+    }
+
+    let width = node.width.unwrap_or(0.0);
+    let height = node.height.unwrap_or(0.0);
+    let metrics: Vec<(f64, f64)> = lines
+        .iter()
+        .map(|runs| {
+            let styles = runs.iter().map(|r| r.style).chain(runs.is_empty().then_some(base));
+            styles.fold((0.0, 0.0), |(lh, size), s| (f64::max(lh, s.line_height_px()), f64::max(size, s.font_size)))
+        })
+        .collect();
+    let total: f64 = metrics.iter().map(|(lh, _)| lh).sum();
+    let mut y = match node.text_align_vertical.as_deref() {
+        Some("CENTER") => (height - total) / 2.0,
+        Some("BOTTOM") => height - total,
+        _ => 0.0,
+    };
+
+    // Paths by fill, in the order the fills first appear
+    let mut layers: Vec<(&[Paint], Vec<PathCommand>)> = Vec::new();
+    let mut missing = BTreeSet::new();
+    for (runs, (line_height, size)) in lines.iter().zip(metrics) {
+        // Approximate ascent as 0.8em, centered in the line box
+        let baseline = y + (line_height - size) / 2.0 + size * 0.8;
+        let mut drawn = Vec::with_capacity(runs.len());
+        let mut line_width = 0.0;
+        for run in runs {
+            let (x, mut commands) = (line_width, Vec::new());
+            line_width += with_style_face(run.style, |face| draw_run(face, run, x, baseline, &mut commands, &mut missing))?;
+            let fills = if run.style.fills.is_empty() { node.fills() } else { &run.style.fills };
+            drawn.push((fills, commands));
+        }
+
+        let offset = match node.text_align_horizontal.as_deref() {
+            Some("CENTER") => (width - line_width) / 2.0,
+            Some("RIGHT") => width - line_width,
+            _ => 0.0,
+        };
+        for (fills, commands) in drawn {
+            let path = transform_path(&VectorPath { winding_rule: WindingRule::Nonzero, commands }, &translate(offset, 0.0));
+            match layers.iter_mut().find(|(f, _)| *f == fills) {
+                Some((_, layer)) => layer.extend(path.commands),
+                None => layers.push((fills, path.commands)),
+            }
+        }
+        y += line_height;
+    }
+
+    let mut group = NodeData::new(generate_node_id(), NodeType::Group, node.name.clone());
+    group.parent_id = node.parent_id.clone();
+    group.x = node.x;
+    group.y = node.y;
+    group.width = node.width;
+    group.height = node.height;
+    group.rotation = node.rotation;
+    group.opacity = node.opacity;
+    group.blend_mode = node.blend_mode.clone();
+    group.effects = node.effects.clone();
+    group.visible = node.visible;
+    group.locked = node.locked;
+
+    let mut nodes = Vec::with_capacity(layers.len());
+    for (fills, commands) in layers {
+        let path = VectorPath { winding_rule: WindingRule::Nonzero, commands };
+        let Some(bounds) = path_bounds(&path) else { continue };
+
+        let mut vector = NodeData::new(generate_node_id(), NodeType::Vector, node.name.clone());
+        vector.parent_id = Some(group.id.clone());
+        vector.x = Some(bounds.x);
+        vector.y = Some(bounds.y);
+        vector.width = Some(bounds.width);
+        vector.height = Some(bounds.height);
+        vector.fills = Some(fills.to_vec());
+        vector.strokes = node.strokes.clone();
+        vector.stroke_weight = node.stroke_weight;
+        vector.stroke_align = node.stroke_align.clone();
+        vector.stroke_join = node.stroke_join.clone();
+        vector.vector_paths = Some(vec![transform_path(&path, &translate(-bounds.x, -bounds.y))]);
+        group.child_ids.push(vector.id.clone());
+        nodes.push(vector);
+    }
+    if nodes.is_empty() {
+        return Err("The text has no glyphs to outline".to_string());
+    }
+
+    nodes.insert(0, group);
+    Ok(OutlinedText { nodes, missing: missing.into_iter().map(String::from).collect() })
+}
+
+/// Convert a text node into vector nodes with `outline_text`. The result
+/// replaces the text node: a group with its position, rotation, opacity and
+/// effects, holding one vector node per fill so gradients and per-run
+/// colors are kept.
+#[command(async)]
+pub fn text_to_outlines(node_json: String) -> Result<OutlinedText, String> {
+    let node: NodeData = serde_json::from_str(&node_json).map_err(|e| format!("Invalid node: {}", e))?;
+    outline_text(&node)
+}
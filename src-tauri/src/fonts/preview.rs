@@ -0,0 +1,189 @@
+//! Sample images of fonts for the font picker, which can then list every
+//! installed family without loading each one into the webview.
+
+use super::emoji::{is_color_font, render_color_text};
+use super::shaping::shape;
+use super::{find_face, font_database};
+use crate::atomic::write_atomic;
+use crate::history::hex_digest;
+use crate::thumbnails::{evict_png_cache, png_size};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use rustybuzz::ttf_parser::{GlyphId, OutlineBuilder};
+use rustybuzz::Face;
+use serde::Serialize;
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use tauri::{command, State};
+use tiny_skia::{FillRule, Paint, PathBuilder, Pixmap, Transform};
+use usvg::fontdb::Source;
+
+/// Largest em size in pixels, allowing for high-density displays.
+const MAX_SIZE: f32 = 512.0;
+/// Longer samples are cut off at this width, in pixels.
+const MAX_WIDTH: f32 = 2048.0;
+const MAX_CACHE_BYTES: u64 = 32 * 1024 * 1024;
+const MAX_CACHE_ENTRIES: usize = 5000;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FontPreview {
+    /// Cached PNG on disk.
+    pub cache_path: String,
+    /// The same PNG as a `data:` URL for direct use in an `<img>`.
+    pub data_url: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Rendered font samples, keyed by the font file, text and size so a sample
+/// is redrawn when the font is updated.
+pub struct FontPreviews {
+    dir: PathBuf,
+}
+
+/// Glyph outlines in font units, placed at `x`, `y` in pixels.
+struct GlyphPen<'a> {
+    builder: &'a mut PathBuilder,
+    x: f32,
+    y: f32,
+    scale: f32,
+}
+
+impl GlyphPen<'_> {
+    fn point(&self, x: f32, y: f32) -> (f32, f32) {
+        (self.x + x * self.scale, self.y - y * self.scale)
+    }
+}
+
+impl OutlineBuilder for GlyphPen<'_> {
+    fn move_to(&mut self, x: f32, y: f32) {
+        let (x, y) = self.point(x, y);
+        self.builder.move_to(x, y);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let (x, y) = self.point(x, y);
+        self.builder.line_to(x, y);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let ((x1, y1), (x, y)) = (self.point(x1, y1), self.point(x, y));
+        self.builder.quad_to(x1, y1, x, y);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let ((x1, y1), (x2, y2), (x, y)) = (self.point(x1, y1), self.point(x2, y2), self.point(x, y));
+        self.builder.cubic_to(x1, y1, x2, y2, x, y);
+    }
+
+    fn close(&mut self) {
+        self.builder.close();
+    }
+}
+
+/// Draw `text` in black on transparent, `size` pixels per em, one line
+/// between the font's ascender and descender.
+fn render(face: &Face, text: &str, size: f32) -> Result<Vec<u8>, String> {
+    let shaped = shape(face, text, size, &[]);
+    let scale = size / face.units_per_em() as f32;
+    let baseline = shaped.ascender.ceil();
+    let height = (baseline - shaped.descender.floor()).max(1.0);
+    let width = shaped.advance.clamp(1.0, MAX_WIDTH).ceil();
+
+    let mut builder = PathBuilder::new();
+    let mut x = 0.0;
+    for glyph in &shaped.glyphs {
+        if x >= MAX_WIDTH {
+            break;
+        }
+        let mut pen = GlyphPen { builder: &mut builder, x: x + glyph.x_offset, y: baseline - glyph.y_offset, scale };
+        face.outline_glyph(GlyphId(glyph.glyph_id), &mut pen);
+        x += glyph.x_advance;
+    }
+
+    let mut pixmap = Pixmap::new(width as u32, height as u32)
+        .ok_or_else(|| "Failed to allocate font preview".to_string())?;
+    if let Some(path) = builder.finish() {
+        let paint = Paint { anti_alias: true, ..Default::default() };
+        pixmap.fill_path(&path, &paint, FillRule::Winding, Transform::identity(), None);
+    }
+    pixmap.encode_png().map_err(|e| format!("Failed to encode font preview: {}", e))
+}
+
+impl FontPreviews {
+    pub fn new(dir: PathBuf) -> Self {
+        FontPreviews { dir }
+    }
+
+    fn get(&self, family: &str, text: &str, size: f32) -> Result<FontPreview, String> {
+        let db = font_database();
+        let face = find_face(&db, family, "Regular").ok_or_else(|| format!("Font not installed: {}", family))?;
+
+        let file = match &face.source {
+            Source::File(path) | Source::SharedFile(path, _) => {
+                let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+                format!("{}:{:?}", path.display(), modified)
+            }
+            Source::Binary(_) => String::new(),
+        };
+        let key = hex_digest(format!("{}\n{}\n{}\n{}\n{}", file, face.index, family, size, text).as_bytes());
+        let entry = self.dir.join(format!("{}.png", key));
+
+        let png = match fs::read(&entry) {
+            Ok(png) => {
+                // Bump the mtime so eviction treats this entry as recently used
+                if let Ok(file) = fs::File::options().write(true).open(&entry) {
+                    let _ = file.set_modified(SystemTime::now());
+                }
+                png
+            }
+            Err(_) => {
+                let png = db
+                    .with_face_data(face.id, |data, index| {
+                        let face = Face::from_slice(data, index)?;
+                        // Color glyphs are left to resvg, which draws every format
+                        Some(if is_color_font(&face) {
+                            render_color_text(text, family, size).and_then(|pixmap| {
+                                pixmap.encode_png().map_err(|e| format!("Failed to encode font preview: {}", e))
+                            })
+                        } else {
+                            render(&face, text, size)
+                        })
+                    })
+                    .flatten()
+                    .ok_or_else(|| format!("Failed to read font: {}", family))??;
+                fs::create_dir_all(&self.dir).map_err(|e| format!("Failed to create font preview cache: {}", e))?;
+                write_atomic(&entry.to_string_lossy(), &png)?;
+                evict_png_cache(&self.dir, MAX_CACHE_ENTRIES, MAX_CACHE_BYTES);
+                png
+            }
+        };
+
+        let (width, height) = png_size(&png).unwrap_or((0, 0));
+        Ok(FontPreview {
+            cache_path: entry.to_string_lossy().into_owned(),
+            data_url: format!("data:image/png;base64,{}", BASE64.encode(&png)),
+            width,
+            height,
+        })
+    }
+}
+
+/// A PNG of `text` set in the regular face of `family`, for the font picker.
+/// `size` is the em size in pixels; multiply it by the device pixel ratio
+/// for sharp previews. Glyphs are black on transparent, so the picker can
+/// tint them with a CSS mask. Defaults to the family name as the sample.
+#[command(async)]
+pub fn render_font_preview(
+    previews: State<'_, FontPreviews>,
+    family: String,
+    text: Option<String>,
+    size: f32,
+) -> Result<FontPreview, String> {
+    if !(size.is_finite() && size > 0.0 && size <= MAX_SIZE) {
+        return Err(format!("Invalid preview size: {}", size));
+    }
+    let text = text.filter(|t| !t.trim().is_empty()).unwrap_or_else(|| family.clone());
+    previews.get(&family, &text, size)
+}
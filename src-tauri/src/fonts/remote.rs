@@ -0,0 +1,336 @@
+//! Google Fonts: browsing the catalog, and downloading families into an
+//! app-managed directory whose fonts are available like installed ones.
+
+use super::{register_font_dir, register_font_files, unregister_fonts};
+use crate::atomic::write_atomic;
+use crate::autosave::sanitize_id;
+use crate::bundle::now_millis;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use tauri::{command, State};
+use tauri_plugin_http::reqwest::{Client, Url};
+
+const CATALOG_URL: &str = "https://fonts.google.com/metadata/fonts";
+const DOWNLOAD_LIST_URL: &str = "https://fonts.google.com/download/list";
+const CATALOG_FILE: &str = "catalog.json";
+const FAMILY_FILE: &str = "family.json";
+/// A cached catalog younger than this is used without asking the server.
+const CATALOG_MAX_AGE_MS: u64 = 24 * 60 * 60 * 1000;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GoogleFontAxis {
+    pub tag: String,
+    pub min: f32,
+    pub max: f32,
+    pub default_value: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GoogleFontFamily {
+    pub family: String,
+    /// `Sans Serif`, `Serif`, `Display`, `Handwriting` or `Monospace`.
+    pub category: String,
+    /// Styles as Google names them: `400` is regular, `700i` bold italic.
+    pub variants: Vec<String>,
+    /// Variation axes when the family is served as a variable font.
+    pub axes: Vec<GoogleFontAxis>,
+    pub subsets: Vec<String>,
+    pub designers: Vec<String>,
+    /// Rank by use, 1 being the most popular.
+    pub popularity: u32,
+    pub last_modified: String,
+    /// Filled in when listing; not cached.
+    #[serde(skip_deserializing, default)]
+    pub downloaded: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CachedCatalog {
+    fetched_at: u64,
+    families: Vec<GoogleFontFamily>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GoogleFontsCatalog {
+    pub families: Vec<GoogleFontFamily>,
+    pub fetched_at: u64,
+    /// The server could not be reached and the cached copy was returned.
+    pub offline: bool,
+}
+
+/// Record of a downloaded family, kept next to its files.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadedFamily {
+    pub family: String,
+    pub files: Vec<String>,
+    pub downloaded_at: u64,
+}
+
+// Shapes of Google's responses, which are JSON behind an anti-XSSI prefix
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RemoteCatalog {
+    family_metadata_list: Vec<RemoteFamily>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct RemoteFamily {
+    family: String,
+    category: String,
+    fonts: BTreeMap<String, serde_json::Value>,
+    axes: Vec<GoogleFontAxis>,
+    subsets: Vec<String>,
+    designers: Vec<String>,
+    popularity: u32,
+    last_modified: String,
+}
+
+#[derive(Deserialize)]
+struct DownloadList {
+    manifest: Manifest,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Manifest {
+    #[serde(default)]
+    files: Vec<ManifestFile>,
+    #[serde(default)]
+    file_refs: Vec<FileRef>,
+}
+
+#[derive(Deserialize)]
+struct ManifestFile {
+    filename: String,
+    contents: String,
+}
+
+#[derive(Deserialize)]
+struct FileRef {
+    filename: String,
+    url: String,
+}
+
+/// Downloaded Google Fonts, one directory per family under `dir`, along with
+/// the cached catalog.
+pub struct GoogleFonts {
+    dir: PathBuf,
+    client: Client,
+}
+
+fn parse_response<T: serde::de::DeserializeOwned>(body: &[u8]) -> Result<T, String> {
+    let json = body.strip_prefix(b")]}'").unwrap_or(body);
+    serde_json::from_slice(json).map_err(|e| format!("Unexpected response from Google Fonts: {}", e))
+}
+
+/// A file name from a manifest, without directories, or none if it could
+/// escape the family directory.
+fn safe_file_name(name: &str) -> Option<&str> {
+    let name = name.rsplit('/').next()?;
+    let valid = !name.is_empty() && !name.starts_with('.') && !name.contains(['\\', ':']);
+    valid.then_some(name)
+}
+
+fn is_font_file(name: &str) -> bool {
+    let lower = name.to_lowercase();
+    lower.ends_with(".ttf") || lower.ends_with(".otf")
+}
+
+impl GoogleFonts {
+    pub fn new(dir: PathBuf) -> Result<Self, String> {
+        let client = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+        register_font_dir(dir.clone());
+
+        Ok(GoogleFonts { dir, client })
+    }
+
+    fn family_dir(&self, family: &str) -> PathBuf {
+        self.dir.join(sanitize_id(family))
+    }
+
+    async fn get(&self, url: Url) -> Result<Vec<u8>, String> {
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("Failed to reach Google Fonts: {}", e))?;
+        let body = response.bytes().await.map_err(|e| format!("Failed to download from Google Fonts: {}", e))?;
+        Ok(body.to_vec())
+    }
+
+    fn read_cache(&self) -> Option<CachedCatalog> {
+        let bytes = fs::read(self.dir.join(CATALOG_FILE)).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    async fn fetch_catalog(&self) -> Result<CachedCatalog, String> {
+        let url = Url::parse(CATALOG_URL).map_err(|e| e.to_string())?;
+        let remote: RemoteCatalog = parse_response(&self.get(url).await?)?;
+
+        let families = remote
+            .family_metadata_list
+            .into_iter()
+            .map(|f| GoogleFontFamily {
+                family: f.family,
+                category: f.category,
+                variants: f.fonts.into_keys().collect(),
+                axes: f.axes,
+                subsets: f.subsets,
+                designers: f.designers,
+                popularity: f.popularity,
+                last_modified: f.last_modified,
+                downloaded: false,
+            })
+            .collect();
+        let catalog = CachedCatalog { fetched_at: now_millis(), families };
+
+        fs::create_dir_all(&self.dir).map_err(|e| format!("Failed to create font directory: {}", e))?;
+        let json = serde_json::to_vec(&catalog).map_err(|e| format!("Failed to encode font catalog: {}", e))?;
+        write_atomic(&self.dir.join(CATALOG_FILE).to_string_lossy(), &json)?;
+        Ok(catalog)
+    }
+
+    /// Lowercased names of the families in the cached catalog, which can be
+    /// downloaded; empty until the catalog has been fetched once.
+    pub fn cached_families(&self) -> HashSet<String> {
+        self.read_cache()
+            .map(|c| c.families.into_iter().map(|f| f.family.to_lowercase()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Families downloaded so far, by name.
+    pub fn downloaded(&self) -> Vec<DownloadedFamily> {
+        let mut families: Vec<DownloadedFamily> = fs::read_dir(&self.dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|entry| {
+                let bytes = fs::read(entry.path().join(FAMILY_FILE)).ok()?;
+                serde_json::from_slice(&bytes).ok()
+            })
+            .collect();
+        families.sort_by(|a, b| a.family.cmp(&b.family));
+        families
+    }
+
+    pub async fn catalog(&self, refresh: bool) -> Result<GoogleFontsCatalog, String> {
+        let cached = self.read_cache();
+        let fresh = cached.as_ref().is_some_and(|c| now_millis().saturating_sub(c.fetched_at) < CATALOG_MAX_AGE_MS);
+
+        let (catalog, offline) = match cached {
+            Some(cached) if fresh && !refresh => (cached, false),
+            cached => match (self.fetch_catalog().await, cached) {
+                (Ok(catalog), _) => (catalog, false),
+                (Err(_), Some(cached)) => (cached, true),
+                (Err(e), None) => return Err(e),
+            },
+        };
+
+        let downloaded: Vec<String> = self.downloaded().into_iter().map(|f| f.family).collect();
+        let mut families = catalog.families;
+        for family in &mut families {
+            family.downloaded = downloaded.contains(&family.family);
+        }
+        Ok(GoogleFontsCatalog { families, fetched_at: catalog.fetched_at, offline })
+    }
+
+    /// Download `family` and make its fonts available right away. Variable
+    /// fonts are preferred when Google serves both kinds, since a single
+    /// file covers every style.
+    pub async fn download(&self, family: &str) -> Result<DownloadedFamily, String> {
+        let url = Url::parse_with_params(DOWNLOAD_LIST_URL, &[("family", family)]).map_err(|e| e.to_string())?;
+        let list: DownloadList = parse_response(&self.get(url).await?)?;
+
+        let fonts: Vec<&FileRef> = list.manifest.file_refs.iter().filter(|f| is_font_file(&f.filename)).collect();
+        // Static instances of variable families are served under `static/`
+        let top_level: Vec<&FileRef> = fonts.iter().copied().filter(|f| !f.filename.contains('/')).collect();
+        let fonts = if top_level.is_empty() { fonts } else { top_level };
+        if fonts.is_empty() {
+            return Err(format!("Google Fonts has no font files for {}", family));
+        }
+
+        let mut downloads = Vec::with_capacity(fonts.len());
+        for file in fonts {
+            let name = safe_file_name(&file.filename).ok_or_else(|| format!("Invalid font file name: {}", file.filename))?;
+            let url = Url::parse(&file.url).map_err(|e| format!("Invalid font URL {}: {}", file.url, e))?;
+            downloads.push((name.to_string(), self.get(url).await?));
+        }
+
+        // Files are only written once every download succeeded, so a failed
+        // download does not leave half a family behind
+        let dir = self.family_dir(family);
+        fs::create_dir_all(&dir).map_err(|e| format!("Failed to create font directory: {}", e))?;
+        for license in &list.manifest.files {
+            if let Some(name) = safe_file_name(&license.filename) {
+                write_atomic(&dir.join(name).to_string_lossy(), license.contents.as_bytes())?;
+            }
+        }
+        let mut paths = Vec::with_capacity(downloads.len());
+        for (name, bytes) in &downloads {
+            let path = dir.join(name);
+            write_atomic(&path.to_string_lossy(), bytes)?;
+            paths.push(path);
+        }
+
+        let record = DownloadedFamily {
+            family: family.to_string(),
+            files: downloads.into_iter().map(|(name, _)| name).collect(),
+            downloaded_at: now_millis(),
+        };
+        let json = serde_json::to_vec_pretty(&record).map_err(|e| format!("Failed to encode font record: {}", e))?;
+        write_atomic(&dir.join(FAMILY_FILE).to_string_lossy(), &json)?;
+
+        unregister_fonts(&dir);
+        register_font_files(&paths);
+        Ok(record)
+    }
+
+    pub fn remove(&self, family: &str) -> Result<(), String> {
+        let dir = self.family_dir(family);
+        if !dir.join(FAMILY_FILE).is_file() {
+            return Err(format!("Google font not downloaded: {}", family));
+        }
+        unregister_fonts(&dir);
+        fs::remove_dir_all(&dir).map_err(|e| format!("Failed to remove font directory: {}", e))
+    }
+}
+
+/// The Google Fonts catalog, from a cache refreshed daily or when `refresh`
+/// is set. When offline the cached copy is returned with `offline` set.
+#[command]
+pub async fn get_google_fonts_catalog(fonts: State<'_, GoogleFonts>, refresh: Option<bool>) -> Result<GoogleFontsCatalog, String> {
+    fonts.catalog(refresh.unwrap_or(false)).await
+}
+
+/// Download a family from Google Fonts. Its fonts are listed by
+/// `get_system_fonts` from then on, in this and later sessions.
+#[command]
+pub async fn download_google_font(fonts: State<'_, GoogleFonts>, family: String) -> Result<DownloadedFamily, String> {
+    fonts.download(&family).await
+}
+
+#[command]
+pub fn list_downloaded_google_fonts(fonts: State<'_, GoogleFonts>) -> Vec<DownloadedFamily> {
+    fonts.downloaded()
+}
+
+#[command]
+pub fn remove_google_font(fonts: State<'_, GoogleFonts>, family: String) -> Result<(), String> {
+    fonts.remove(&family)
+}
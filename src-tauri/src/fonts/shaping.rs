@@ -0,0 +1,311 @@
+//! Text shaping with rustybuzz, so that kerning, ligatures and complex
+//! scripts such as Arabic and Devanagari are laid out the way exports
+//! render them rather than the way canvas `measureText` guesses.
+
+use super::{find_face, font_database};
+use rustybuzz::ttf_parser::Tag;
+use rustybuzz::{Direction, Face, Feature, UnicodeBuffer, Variation};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::str::FromStr;
+use tauri::command;
+use unicode_bidi::{Level, ParagraphBidiInfo};
+
+/// An installed face, named as in `get_system_fonts`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FontSpec {
+    pub family: String,
+    #[serde(default)]
+    pub style: Option<String>,
+    /// Variable font coordinates, axis tag -> value, as listed by
+    /// `get_font_variations`. Axes left out stay at their defaults.
+    #[serde(default)]
+    pub variations: HashMap<String, f32>,
+}
+
+/// One glyph in visual order. Advances and offsets are in pixels at the
+/// requested size, with y pointing up as in the font.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShapedGlyph {
+    pub glyph_id: u16,
+    /// UTF-16 offset of the first character the glyph was made from, so it
+    /// can index the JavaScript string directly. Ligatures share a cluster
+    /// with the characters they replace.
+    pub cluster: usize,
+    pub x_advance: f32,
+    pub y_advance: f32,
+    pub x_offset: f32,
+    pub y_offset: f32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShapedText {
+    pub glyphs: Vec<ShapedGlyph>,
+    /// Total advance along the direction of the text.
+    pub advance: f32,
+    pub ascender: f32,
+    pub descender: f32,
+    pub line_gap: f32,
+    /// `ltr`, `rtl`, `ttb` or `btt`, detected from the text.
+    pub direction: &'static str,
+    /// ISO 15924 tag of the detected script, such as `Arab`.
+    pub script: String,
+}
+
+/// A stretch of text in one direction, in the order lines display them.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VisualRun {
+    /// UTF-16 range of the run in the text.
+    pub start: usize,
+    pub end: usize,
+    /// Bidi embedding level; odd levels are right to left.
+    pub level: u8,
+    pub direction: &'static str,
+    /// Pen position where the run starts, from the left end of the line.
+    pub x: f32,
+    /// Clusters are UTF-16 offsets into the whole text.
+    pub glyphs: Vec<ShapedGlyph>,
+    pub advance: f32,
+}
+
+/// One line of text, reordered for display.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShapedLine {
+    /// UTF-16 range of the line, without its line break.
+    pub start: usize,
+    pub end: usize,
+    /// `ltr` or `rtl`, as given or else from the line's first letter with a
+    /// strong direction.
+    pub base_direction: &'static str,
+    pub runs: Vec<VisualRun>,
+    pub advance: f32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShapedParagraphs {
+    /// One per line of the text; each is its own bidi paragraph.
+    pub lines: Vec<ShapedLine>,
+    pub ascender: f32,
+    pub descender: f32,
+    pub line_gap: f32,
+}
+
+/// UTF-16 offset of every byte offset that starts a character in `text`.
+fn utf16_offsets(text: &str) -> Vec<usize> {
+    let mut offsets = vec![0; text.len() + 1];
+    let mut utf16 = 0;
+    for (i, c) in text.char_indices() {
+        offsets[i] = utf16;
+        utf16 += c.len_utf16();
+    }
+    offsets[text.len()] = utf16;
+    offsets
+}
+
+fn direction_name(direction: Direction) -> &'static str {
+    match direction {
+        Direction::RightToLeft => "rtl",
+        Direction::TopToBottom => "ttb",
+        Direction::BottomToTop => "btt",
+        _ => "ltr",
+    }
+}
+
+/// Parse features written as in CSS `font-feature-settings` or HarfBuzz,
+/// such as `liga=0`, `-kern` or `ss01`.
+pub fn parse_features(features: &[String]) -> Result<Vec<Feature>, String> {
+    features
+        .iter()
+        .map(|f| Feature::from_str(f.trim()).map_err(|_| format!("Invalid font feature: {}", f)))
+        .collect()
+}
+
+/// Shape a run of `text` with `face` at `size` pixels per em.
+pub fn shape(face: &Face, text: &str, size: f32, features: &[Feature]) -> ShapedText {
+    shape_in_direction(face, text, size, features, None)
+}
+
+/// Shape `text` as a run in `direction`, such as one of the runs from
+/// `bidi_runs`, or in the direction its script suggests when none is given.
+pub fn shape_in_direction(
+    face: &Face,
+    text: &str,
+    size: f32,
+    features: &[Feature],
+    direction: Option<Direction>,
+) -> ShapedText {
+    let mut buffer = UnicodeBuffer::new();
+    buffer.push_str(text);
+    buffer.guess_segment_properties();
+    if let Some(direction) = direction {
+        buffer.set_direction(direction);
+    }
+    let direction = buffer.direction();
+    let script = buffer.script().tag().to_string();
+
+    let output = rustybuzz::shape(face, features, buffer);
+    let scale = size / face.units_per_em() as f32;
+    let offsets = utf16_offsets(text);
+
+    let glyphs: Vec<ShapedGlyph> = output
+        .glyph_infos()
+        .iter()
+        .zip(output.glyph_positions())
+        .map(|(info, pos)| ShapedGlyph {
+            glyph_id: info.glyph_id as u16,
+            cluster: offsets[info.cluster as usize],
+            x_advance: pos.x_advance as f32 * scale,
+            y_advance: pos.y_advance as f32 * scale,
+            x_offset: pos.x_offset as f32 * scale,
+            y_offset: pos.y_offset as f32 * scale,
+        })
+        .collect();
+    let advance = match direction {
+        Direction::TopToBottom | Direction::BottomToTop => glyphs.iter().map(|g| g.y_advance.abs()).sum(),
+        _ => glyphs.iter().map(|g| g.x_advance).sum(),
+    };
+
+    ShapedText {
+        glyphs,
+        advance,
+        ascender: face.ascender() as f32 * scale,
+        descender: face.descender() as f32 * scale,
+        line_gap: face.line_gap() as f32 * scale,
+        direction: direction_name(direction),
+        script,
+    }
+}
+
+/// The direction of text at a bidi embedding level.
+pub fn level_direction(level: u8) -> Direction {
+    if level % 2 == 1 { Direction::RightToLeft } else { Direction::LeftToRight }
+}
+
+/// Byte ranges of `line`, a paragraph without line breaks, in the order
+/// they are displayed, each with its embedding level, and the paragraph's
+/// base direction: `base` if given, otherwise from its first strong
+/// character as the Unicode bidi algorithm decides.
+pub fn bidi_runs(line: &str, base: Option<Direction>) -> (Direction, Vec<(Range<usize>, u8)>) {
+    let level = base.map(|d| if d == Direction::RightToLeft { Level::rtl() } else { Level::ltr() });
+    let info = ParagraphBidiInfo::new(line, level);
+    let base = level_direction(info.paragraph_level.number());
+    if line.is_empty() {
+        return (base, Vec::new());
+    }
+
+    let (levels, runs) = info.visual_runs(0..line.len());
+    let runs = runs.into_iter().map(|run| (run.clone(), levels[run.start].number())).collect();
+    (base, runs)
+}
+
+/// Run `f` with the face `font` names, its variations applied.
+pub fn with_face<T>(font: &FontSpec, f: impl FnOnce(&Face) -> T) -> Result<T, String> {
+    let mut variations = Vec::with_capacity(font.variations.len());
+    for (tag, value) in &font.variations {
+        let &[a, b, c, d] = tag.as_bytes() else { return Err(format!("Invalid axis tag: {}", tag)) };
+        variations.push(Variation { tag: Tag::from_bytes(&[a, b, c, d]), value: *value });
+    }
+    let style = font.style.as_deref().unwrap_or("Regular");
+
+    let db = font_database();
+    let face = find_face(&db, &font.family, style)
+        .ok_or_else(|| format!("Font not installed: {} {}", font.family, style))?;
+
+    db.with_face_data(face.id, |data, index| {
+        let mut face = Face::from_slice(data, index)?;
+        face.set_variations(&variations);
+        Some(f(&face))
+    })
+    .flatten()
+    .ok_or_else(|| format!("Failed to read font: {} {}", font.family, style))
+}
+
+/// Shape `text` with an installed font. `features` turn OpenType features
+/// on or off, e.g. `["liga=0", "ss01"]`; the defaults of the script apply
+/// otherwise. Line breaking and mixing fonts are up to the caller.
+#[command(async)]
+pub fn shape_text(text: String, font: FontSpec, size: f32, features: Option<Vec<String>>) -> Result<ShapedText, String> {
+    if !(size.is_finite() && size > 0.0) {
+        return Err(format!("Invalid font size: {}", size));
+    }
+    let features = parse_features(&features.unwrap_or_default())?;
+    with_face(&font, |face| shape(face, &text, size, &features))
+}
+
+/// Shape `text` with the Unicode bidi algorithm, so right-to-left scripts
+/// such as Arabic and Hebrew mix with left-to-right text and numbers as they
+/// should. Each line is its own paragraph and comes back as runs in display
+/// order, positioned from the left end of the line. `direction` is `ltr`,
+/// `rtl` or `auto` (the default), which takes each line's direction from
+/// its first letter.
+#[command(async)]
+pub fn shape_paragraphs(
+    text: String,
+    font: FontSpec,
+    size: f32,
+    direction: Option<String>,
+    features: Option<Vec<String>>,
+) -> Result<ShapedParagraphs, String> {
+    if !(size.is_finite() && size > 0.0) {
+        return Err(format!("Invalid font size: {}", size));
+    }
+    let base = match direction.as_deref() {
+        None | Some("auto") => None,
+        Some("ltr") => Some(Direction::LeftToRight),
+        Some("rtl") => Some(Direction::RightToLeft),
+        Some(other) => return Err(format!("Invalid text direction: {}", other)),
+    };
+    let features = parse_features(&features.unwrap_or_default())?;
+    let offsets = utf16_offsets(&text);
+
+    with_face(&font, |face| {
+        let scale = size / face.units_per_em() as f32;
+        let mut lines = Vec::new();
+        let mut line_start = 0;
+        for line in text.split('\n') {
+            let (line_base, runs) = bidi_runs(line, base);
+            let mut x = 0.0;
+            let mut visual_runs = Vec::with_capacity(runs.len());
+            for (range, level) in runs {
+                let direction = level_direction(level);
+                let (start, end) = (line_start + range.start, line_start + range.end);
+                let mut shaped = shape_in_direction(face, &text[start..end], size, &features, Some(direction));
+                for glyph in &mut shaped.glyphs {
+                    glyph.cluster += offsets[start];
+                }
+                visual_runs.push(VisualRun {
+                    start: offsets[start],
+                    end: offsets[end],
+                    level,
+                    direction: direction_name(direction),
+                    x,
+                    advance: shaped.advance,
+                    glyphs: shaped.glyphs,
+                });
+                x += shaped.advance;
+            }
+            lines.push(ShapedLine {
+                start: offsets[line_start],
+                end: offsets[line_start + line.len()],
+                base_direction: direction_name(line_base),
+                runs: visual_runs,
+                advance: x,
+            });
+            line_start += line.len() + 1;
+        }
+
+        ShapedParagraphs {
+            lines,
+            ascender: face.ascender() as f32 * scale,
+            descender: face.descender() as f32 * scale,
+            line_gap: face.line_gap() as f32 * scale,
+        }
+    })
+}
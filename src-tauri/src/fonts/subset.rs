@@ -0,0 +1,63 @@
+//! Font subsets for embedding in exports, holding only the glyphs the
+//! exported text needs so files stay small.
+
+use super::variations::instantiate;
+use super::{face_data, font_database};
+use rustybuzz::{ttf_parser, Face, UnicodeBuffer, Variation};
+use skrifa::{FontRef, GlyphId, MetadataProvider, Tag};
+use std::collections::BTreeSet;
+use usvg::fontdb::{Family, Query, Stretch, Style, Weight};
+
+/// An installed face to embed, and where to instance it if it is variable.
+pub struct EmbeddableFace {
+    pub data: Vec<u8>,
+    pub coordinates: Vec<(Tag, f32)>,
+}
+
+/// The installed face `family` is drawn with at `weight`, along with the
+/// coordinates that give that weight when it is variable.
+pub fn embeddable_face(family: &str, weight: u16) -> Option<EmbeddableFace> {
+    let db = font_database();
+    let query = Query { families: &[Family::Name(family)], weight: Weight(weight), stretch: Stretch::Normal, style: Style::Normal };
+    let face = db.query(&query).and_then(|id| db.face(id))?;
+    let data = face_data(&db, face)?;
+
+    let font = FontRef::new(&data).ok()?;
+    let coordinates = match font.axes().get_by_tag(Tag::new(b"wght")) {
+        Some(axis) => vec![(axis.tag(), weight as f32)],
+        None => Vec::new(),
+    };
+    Some(EmbeddableFace { data, coordinates })
+}
+
+/// Glyphs that drawing `texts` can use: those the characters map to, and
+/// those shaping substitutes in, such as ligatures and contextual forms.
+fn used_glyphs(data: &[u8], coordinates: &[(Tag, f32)], texts: &[String]) -> Result<BTreeSet<GlyphId>, String> {
+    let mut face = Face::from_slice(data, 0).ok_or("Failed to parse font")?;
+    let variations: Vec<Variation> = coordinates
+        .iter()
+        .map(|(tag, value)| Variation { tag: ttf_parser::Tag::from_bytes(&tag.to_be_bytes()), value: *value })
+        .collect();
+    face.set_variations(&variations);
+
+    // .notdef is drawn for anything else
+    let mut glyphs = BTreeSet::from([GlyphId::NOTDEF]);
+    for text in texts {
+        glyphs.extend(text.chars().filter_map(|c| face.glyph_index(c)).map(|g| GlyphId::new(g.0 as u32)));
+
+        let mut buffer = UnicodeBuffer::new();
+        buffer.push_str(text);
+        buffer.guess_segment_properties();
+        let output = rustybuzz::shape(&face, &[], buffer);
+        glyphs.extend(output.glyph_infos().iter().map(|info| GlyphId::new(info.glyph_id)));
+    }
+    Ok(glyphs)
+}
+
+/// A TrueType font with outlines only for the glyphs `texts` use, static at
+/// `coordinates` when `data` is variable. The character map and layout
+/// tables are kept, so the subset renders `texts` like the full font.
+pub fn subset_font(data: &[u8], coordinates: &[(Tag, f32)], texts: &[String]) -> Result<Vec<u8>, String> {
+    let glyphs = used_glyphs(data, coordinates, texts)?;
+    instantiate(data, coordinates, Some(&glyphs))
+}
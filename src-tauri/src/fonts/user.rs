@@ -0,0 +1,162 @@
+//! Fonts installed for the current user by the app itself, such as a team's
+//! brand fonts, without touching the system font folders or needing admin
+//! rights.
+
+use super::webfont::{decode_webfont, is_webfont};
+use super::{register_font_dir, register_font_files, style_name, unregister_fonts};
+use crate::atomic::write_atomic;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{command, State};
+use usvg::fontdb::Database;
+
+const FONT_EXTENSIONS: &[&str] = &["ttf", "otf", "ttc", "otc"];
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ManagedFace {
+    pub family: String,
+    pub style_name: String,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ManagedFont {
+    /// Identifies the font for `uninstall_user_font`.
+    pub file_name: String,
+    pub path: String,
+    /// Several for font collections.
+    pub faces: Vec<ManagedFace>,
+}
+
+/// Font files copied into an app-managed directory, loaded along with the
+/// system fonts in this and later sessions.
+pub struct UserFonts {
+    dir: PathBuf,
+}
+
+/// The faces in a TrueType, OpenType or collection file, or an error saying
+/// why it cannot be installed.
+fn parse_faces(bytes: &[u8]) -> Result<Vec<ManagedFace>, String> {
+    let valid = matches!(bytes.get(..4), Some(b"\0\x01\0\0" | b"OTTO" | b"true" | b"ttcf"));
+    if !valid {
+        return Err("Not a TrueType or OpenType font".to_string());
+    }
+    let count = rustybuzz::ttf_parser::fonts_in_collection(bytes).unwrap_or(1);
+    for index in 0..count {
+        rustybuzz::ttf_parser::Face::parse(bytes, index).map_err(|e| format!("Invalid font: {}", e))?;
+    }
+
+    let mut db = Database::new();
+    db.load_font_data(bytes.to_vec());
+    let faces: Vec<ManagedFace> = db
+        .faces()
+        .filter_map(|face| {
+            let (family, _) = face.families.first()?;
+            Some(ManagedFace { family: family.clone(), style_name: style_name(face) })
+        })
+        .collect();
+    if faces.is_empty() {
+        return Err("The font has no family name".to_string());
+    }
+    Ok(faces)
+}
+
+fn is_font_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| FONT_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+}
+
+impl UserFonts {
+    pub fn new(dir: PathBuf) -> Self {
+        register_font_dir(dir.clone());
+        UserFonts { dir }
+    }
+
+    fn managed_path(&self, file_name: &str) -> Result<PathBuf, String> {
+        let valid = !file_name.is_empty()
+            && !file_name.starts_with('.')
+            && !file_name.contains(['/', '\\', ':'])
+            && is_font_file(Path::new(file_name));
+        if !valid {
+            return Err(format!("Invalid font file name: {}", file_name));
+        }
+        Ok(self.dir.join(file_name))
+    }
+
+    /// Copy the font at `source` in, replacing an installed file of the same
+    /// name, e.g. an older version of it. Webfonts are stored decoded, as
+    /// `.ttf` or `.otf`.
+    pub fn install(&self, source: &str) -> Result<ManagedFont, String> {
+        let source = Path::new(source);
+        let mut file_name = source
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| format!("Invalid font path: {}", source.display()))?
+            .to_string();
+        let mut bytes = fs::read(source).map_err(|e| format!("Failed to read font: {}", e))?;
+        if is_webfont(&bytes) {
+            bytes = decode_webfont(&bytes)?;
+            let extension = if bytes.starts_with(b"OTTO") { "otf" } else { "ttf" };
+            file_name = Path::new(&file_name).with_extension(extension).to_string_lossy().into_owned();
+        }
+        let path = self.managed_path(&file_name)?;
+        let faces = parse_faces(&bytes)?;
+
+        fs::create_dir_all(&self.dir).map_err(|e| format!("Failed to create font directory: {}", e))?;
+        unregister_fonts(&path);
+        write_atomic(&path.to_string_lossy(), &bytes)?;
+        register_font_files(std::slice::from_ref(&path));
+
+        Ok(ManagedFont { file_name, path: path.to_string_lossy().into_owned(), faces })
+    }
+
+    pub fn uninstall(&self, file_name: &str) -> Result<(), String> {
+        let path = self.managed_path(file_name)?;
+        if !path.is_file() {
+            return Err(format!("Font not installed: {}", file_name));
+        }
+        unregister_fonts(&path);
+        fs::remove_file(&path).map_err(|e| format!("Failed to remove font: {}", e))
+    }
+
+    /// Installed fonts by file name. Files that no longer parse are left out.
+    pub fn list(&self) -> Vec<ManagedFont> {
+        let Ok(entries) = fs::read_dir(&self.dir) else { return Vec::new() };
+        let mut fonts: Vec<ManagedFont> = entries
+            .flatten()
+            .map(|e| e.path())
+            .filter(|path| is_font_file(path))
+            .filter_map(|path| {
+                let faces = parse_faces(&fs::read(&path).ok()?).ok()?;
+                Some(ManagedFont {
+                    file_name: path.file_name()?.to_string_lossy().into_owned(),
+                    path: path.to_string_lossy().into_owned(),
+                    faces,
+                })
+            })
+            .collect();
+        fonts.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+        fonts
+    }
+}
+
+/// Install a `.ttf`, `.otf` or `.ttc` file, or a `.woff` or `.woff2`
+/// webfont, for this user. It is usable right away and listed by
+/// `get_system_fonts` like any installed font.
+#[command(async)]
+pub fn install_user_font(fonts: State<'_, UserFonts>, path: String) -> Result<ManagedFont, String> {
+    fonts.install(&path)
+}
+
+#[command]
+pub fn uninstall_user_font(fonts: State<'_, UserFonts>, file_name: String) -> Result<(), String> {
+    fonts.uninstall(&file_name)
+}
+
+#[command]
+pub fn list_user_fonts(fonts: State<'_, UserFonts>) -> Vec<ManagedFont> {
+    fonts.list()
+}
@@ -0,0 +1,321 @@
+//! Variable fonts: the axes and named instances a face offers, and static
+//! instances of it for exports that cannot carry variations.
+
+use super::{face_data, find_face, font_database};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use kurbo::{BezPath, PathEl, Point};
+use serde::Serialize;
+use skrifa::instance::Size;
+use skrifa::outline::{DrawSettings, OutlinePen};
+use skrifa::raw::TableProvider;
+use skrifa::{FontRef, GlyphId, MetadataProvider, Tag};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use tauri::command;
+use write_fonts::from_obj::ToOwnedTable;
+use write_fonts::tables::glyf::{Bbox, GlyfLocaBuilder, Glyph, SimpleGlyph};
+use write_fonts::tables::head::Head;
+use write_fonts::tables::hhea::Hhea;
+use write_fonts::tables::hmtx::{Hmtx, LongMetric};
+use write_fonts::tables::maxp::Maxp;
+use write_fonts::tables::os2::Os2;
+use write_fonts::tables::post::Post;
+use write_fonts::types::Version16Dot16;
+use write_fonts::FontBuilder;
+
+/// Tables that describe variations, or that refer to the original outlines
+/// and hinting, none of which apply to the instance.
+const DROPPED_TABLES: &[&[u8; 4]] = &[
+    b"fvar", b"avar", b"gvar", b"cvar", b"HVAR", b"VVAR", b"MVAR", b"STAT", b"CFF ", b"CFF2", b"cvt ",
+    b"fpgm", b"prep", b"hdmx", b"LTSH", b"VORG", b"vhea", b"vmtx", b"DSIG",
+];
+
+/// Largest distance, in font units, between a cubic curve and the quadratic
+/// curves that replace it in the instance.
+const QUADRATIC_TOLERANCE: f64 = 0.5;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FontAxis {
+    /// Such as `wght`, `wdth` or `slnt`.
+    pub tag: String,
+    pub name: String,
+    pub min: f32,
+    pub default: f32,
+    pub max: f32,
+    /// The font asks for the axis not to be shown to users.
+    pub hidden: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NamedFontInstance {
+    /// Style name, such as "SemiBold Condensed".
+    pub name: String,
+    pub postscript_name: Option<String>,
+    /// Axis tag -> value, in the axes' own units.
+    pub coordinates: BTreeMap<String, f32>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FontVariations {
+    /// Empty for fonts that are not variable.
+    pub axes: Vec<FontAxis>,
+    pub instances: Vec<NamedFontInstance>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FontInstance {
+    /// Base64-encoded TrueType font without variations.
+    pub data: String,
+    pub mime_type: &'static str,
+    /// The coordinates used, after clamping to the axes' ranges. Axes that
+    /// were not given are at their defaults.
+    pub coordinates: BTreeMap<String, f32>,
+}
+
+pub fn load_face(family: &str, style: Option<String>) -> Result<Vec<u8>, String> {
+    let style = style.unwrap_or_else(|| "Regular".to_string());
+    let db = font_database();
+    let face = find_face(&db, family, &style)
+        .ok_or_else(|| format!("Font not installed: {} {}", family, style))?;
+    face_data(&db, face).ok_or_else(|| format!("Failed to read font: {} {}", family, style))
+}
+
+/// Collects an outline into a path, with cubic curves split into quadratic
+/// ones since that is all `glyf` can hold.
+struct QuadraticPen(BezPath);
+
+impl OutlinePen for QuadraticPen {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.0.move_to((x as f64, y as f64));
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.0.line_to((x as f64, y as f64));
+    }
+
+    fn quad_to(&mut self, cx0: f32, cy0: f32, x: f32, y: f32) {
+        self.0.quad_to((cx0 as f64, cy0 as f64), (x as f64, y as f64));
+    }
+
+    fn curve_to(&mut self, cx0: f32, cy0: f32, cx1: f32, cy1: f32, x: f32, y: f32) {
+        let start = match self.0.elements().last() {
+            Some(PathEl::MoveTo(p) | PathEl::LineTo(p) | PathEl::QuadTo(_, p) | PathEl::CurveTo(_, _, p)) => *p,
+            _ => Point::ZERO,
+        };
+        let cubic = kurbo::CubicBez::new(
+            start,
+            Point::new(cx0 as f64, cy0 as f64),
+            Point::new(cx1 as f64, cy1 as f64),
+            Point::new(x as f64, y as f64),
+        );
+        for (_, _, quad) in cubic.to_quads(QUADRATIC_TOLERANCE) {
+            self.0.quad_to(quad.p1, quad.p2);
+        }
+    }
+
+    fn close(&mut self) {
+        self.0.close_path();
+    }
+}
+
+/// A static TrueType font with the outlines and advances of `data` at
+/// `coordinates`. Layout tables are kept; hinting is dropped since it was
+/// written for the variable outlines.
+///
+/// With `keep`, the outlines of all other glyphs are left empty. Glyph ids
+/// do not change, so layout tables still apply to the glyphs that remain.
+pub fn instantiate(data: &[u8], coordinates: &[(Tag, f32)], keep: Option<&BTreeSet<GlyphId>>) -> Result<Vec<u8>, String> {
+    let font = FontRef::new(data).map_err(|e| format!("Failed to parse font: {}", e))?;
+    let invalid = |e: skrifa::raw::ReadError| format!("Invalid font: {}", e);
+
+    let location = font.axes().location(coordinates.iter().copied());
+    let outlines = font.outline_glyphs();
+    let metrics = font.glyph_metrics(Size::unscaled(), &location);
+    let num_glyphs = font.maxp().map_err(invalid)?.num_glyphs();
+
+    let mut glyf = GlyfLocaBuilder::new();
+    let mut h_metrics = Vec::with_capacity(num_glyphs as usize);
+    let mut bounds: Option<Bbox> = None;
+    let (mut max_points, mut max_contours) = (0, 0);
+    let (mut min_lsb, mut min_rsb, mut max_extent) = (i16::MAX, i16::MAX, i16::MIN);
+
+    for gid in 0..num_glyphs {
+        let id = GlyphId::new(gid as u32);
+        let mut pen = QuadraticPen(BezPath::new());
+        if let Some(outline) = outlines.get(id).filter(|_| keep.is_none_or(|k| k.contains(&id))) {
+            outline
+                .draw(DrawSettings::unhinted(Size::unscaled(), &location), &mut pen)
+                .map_err(|e| format!("Failed to draw glyph {}: {}", gid, e))?;
+        }
+        let advance = metrics.advance_width(id).unwrap_or(0.0).round().clamp(0.0, u16::MAX as f32) as u16;
+
+        let glyph = if pen.0.elements().is_empty() {
+            h_metrics.push(LongMetric::new(advance, 0));
+            Glyph::Empty
+        } else {
+            let simple = SimpleGlyph::from_bezpath(&pen.0)
+                .map_err(|e| format!("Failed to convert glyph {}: {:?}", gid, e))?;
+            let bbox = simple.bbox;
+            h_metrics.push(LongMetric::new(advance, bbox.x_min));
+
+            bounds = Some(bounds.map_or(bbox, |b| b.union(bbox)));
+            max_points = max_points.max(simple.contours.iter().map(|c| c.len()).sum::<usize>());
+            max_contours = max_contours.max(simple.contours.len());
+            min_lsb = min_lsb.min(bbox.x_min);
+            min_rsb = min_rsb.min((advance as i32 - bbox.x_max as i32).clamp(i16::MIN as i32, i16::MAX as i32) as i16);
+            max_extent = max_extent.max(bbox.x_max);
+            Glyph::Simple(simple)
+        };
+        glyf.add_glyph(&glyph).map_err(|e| format!("Failed to write glyph {}: {:?}", gid, e))?;
+    }
+    let (glyf, loca, loca_format) = glyf.build();
+    let bounds = bounds.unwrap_or_default();
+
+    let mut head: Head = font.head().map_err(invalid)?.to_owned_table();
+    head.index_to_loc_format = loca_format as i16;
+    (head.x_min, head.y_min, head.x_max, head.y_max) = (bounds.x_min, bounds.y_min, bounds.x_max, bounds.y_max);
+
+    let mut hhea: Hhea = font.hhea().map_err(invalid)?.to_owned_table();
+    hhea.number_of_h_metrics = num_glyphs;
+    hhea.advance_width_max = h_metrics.iter().map(|m| m.advance).max().unwrap_or(0).into();
+    if max_extent > i16::MIN {
+        hhea.min_left_side_bearing = min_lsb.into();
+        hhea.min_right_side_bearing = min_rsb.into();
+        hhea.x_max_extent = max_extent.into();
+    }
+
+    let mut maxp = Maxp::new(num_glyphs);
+    maxp.max_points = Some(max_points.min(u16::MAX as usize) as u16);
+    maxp.max_contours = Some(max_contours.min(u16::MAX as usize) as u16);
+    maxp.max_composite_points = Some(0);
+    maxp.max_composite_contours = Some(0);
+    maxp.max_zones = Some(1);
+    maxp.max_twilight_points = Some(0);
+    maxp.max_storage = Some(0);
+    maxp.max_function_defs = Some(0);
+    maxp.max_instruction_defs = Some(0);
+    maxp.max_stack_elements = Some(0);
+    maxp.max_size_of_instructions = Some(0);
+    maxp.max_component_elements = Some(0);
+    maxp.max_component_depth = Some(0);
+
+    let mut builder = FontBuilder::new();
+    let write_error = |e: write_fonts::BuilderError| format!("Failed to write font: {}", e);
+    builder
+        .add_table(&glyf).map_err(write_error)?
+        .add_table(&loca).map_err(write_error)?
+        .add_table(&head).map_err(write_error)?
+        .add_table(&hhea).map_err(write_error)?
+        .add_table(&Hmtx::new(h_metrics, Vec::new())).map_err(write_error)?
+        .add_table(&maxp).map_err(write_error)?;
+
+    // Glyph names of a subset would mostly name empty glyphs
+    if let (Some(_), Ok(post)) = (keep, font.post()) {
+        let mut post: Post = post.to_owned_table();
+        post.version = Version16Dot16::VERSION_3_0;
+        (post.num_glyphs, post.glyph_name_index, post.string_data) = (None, None, None);
+        builder.add_table(&post).map_err(write_error)?;
+    }
+
+    // The weight and width classes follow the instance, for apps that pick
+    // faces by them
+    if let Ok(os2) = font.os2() {
+        let mut os2: Os2 = os2.to_owned_table();
+        let axes = font.axes();
+        if let Some(axis) = axes.get_by_tag(Tag::new(b"wght")) {
+            let weight = coordinates.iter().find(|(t, _)| *t == axis.tag()).map_or(axis.default_value(), |(_, v)| *v);
+            os2.us_weight_class = weight.clamp(axis.min_value(), axis.max_value()).round().clamp(1.0, 1000.0) as u16;
+        }
+        if let Some(axis) = axes.get_by_tag(Tag::new(b"wdth")) {
+            let width = coordinates.iter().find(|(t, _)| *t == axis.tag()).map_or(axis.default_value(), |(_, v)| *v);
+            let width = width.clamp(axis.min_value(), axis.max_value());
+            // Percentages of the nine OS/2 width classes
+            let classes = [50.0, 62.5, 75.0, 87.5, 100.0, 112.5, 125.0, 150.0, 200.0];
+            os2.us_width_class = classes.iter().position(|&c| width <= c).map_or(9, |i| i + 1) as u16;
+        }
+        builder.add_table(&os2).map_err(write_error)?;
+    }
+
+    for record in font.table_directory.table_records() {
+        let tag = record.tag();
+        if builder.contains(tag) || DROPPED_TABLES.iter().any(|t| Tag::new(t) == tag) {
+            continue;
+        }
+        if let Some(table) = font.table_data(tag) {
+            builder.add_raw(tag, table.as_bytes());
+        }
+    }
+    Ok(builder.build())
+}
+
+/// The variation axes of an installed face with their ranges, and the named
+/// instances its designer defined. Coordinates are in the axes' own units,
+/// such as 100 to 900 for `wght`; `avar` mappings are applied when shaping
+/// or instancing, not here.
+#[command(async)]
+pub fn get_font_variations(family: String, style: Option<String>) -> Result<FontVariations, String> {
+    let data = load_face(&family, style)?;
+    let font = FontRef::new(&data).map_err(|e| format!("Failed to parse font: {}", e))?;
+    let name = |id| font.localized_strings(id).english_or_first().map(|s| s.to_string());
+
+    let axes: Vec<FontAxis> = font
+        .axes()
+        .iter()
+        .map(|axis| FontAxis {
+            tag: axis.tag().to_string(),
+            name: name(axis.name_id()).unwrap_or_else(|| axis.tag().to_string()),
+            min: axis.min_value(),
+            default: axis.default_value(),
+            max: axis.max_value(),
+            hidden: axis.is_hidden(),
+        })
+        .collect();
+    let instances = font
+        .named_instances()
+        .iter()
+        .map(|instance| NamedFontInstance {
+            name: name(instance.subfamily_name_id()).unwrap_or_default(),
+            postscript_name: instance.postscript_name_id().and_then(name),
+            coordinates: axes.iter().map(|a| a.tag.clone()).zip(instance.user_coords()).collect(),
+        })
+        .collect();
+
+    Ok(FontVariations { axes, instances })
+}
+
+/// Produce a static instance of a variable face at `coordinates` (axis tag
+/// -> value), for embedding in exports where variations are not supported.
+#[command(async)]
+pub fn instance_font(family: String, style: Option<String>, coordinates: HashMap<String, f32>) -> Result<FontInstance, String> {
+    let data = load_face(&family, style)?;
+    let mut settings = Vec::with_capacity(coordinates.len());
+    for (tag, value) in &coordinates {
+        let tag = Tag::new_checked(tag.as_bytes()).map_err(|_| format!("Invalid axis tag: {}", tag))?;
+        if !value.is_finite() {
+            return Err(format!("Invalid value for axis {}: {}", tag, value));
+        }
+        settings.push((tag, *value));
+    }
+
+    let font = FontRef::new(&data).map_err(|e| format!("Failed to parse font: {}", e))?;
+    if font.axes().is_empty() {
+        return Err(format!("{} is not a variable font", family));
+    }
+    let applied = font
+        .axes()
+        .iter()
+        .map(|axis| {
+            let value = settings.iter().find(|(t, _)| *t == axis.tag()).map_or(axis.default_value(), |(_, v)| *v);
+            (axis.tag().to_string(), value.clamp(axis.min_value(), axis.max_value()))
+        })
+        .collect();
+
+    Ok(FontInstance {
+        data: BASE64.encode(instantiate(&data, &settings, None)?),
+        mime_type: "font/ttf",
+        coordinates: applied,
+    })
+}
@@ -0,0 +1,165 @@
+//! Vertical text for Chinese and Japanese: columns read top to bottom with
+//! CJK characters upright in their vertical forms and Latin text and
+//! some punctuation turned on its side.
+
+use super::fallback::attaches_to_previous;
+use super::shaping::{parse_features, shape_in_direction, with_face, FontSpec};
+use crate::geometry::Matrix;
+use rustybuzz::ttf_parser::{GlyphId, Tag};
+use rustybuzz::{Direction, Face, Feature};
+use serde::Serialize;
+use tauri::command;
+
+/// How a character stands in a vertical column, after Unicode's
+/// Vertical_Orientation property (UAX #50).
+#[derive(Clone, Copy, PartialEq)]
+enum Orientation {
+    Upright,
+    /// Brackets, dashes and the like, which fonts give vertical forms; turned
+    /// sideways when the font has none.
+    UprightIfVertical,
+    Sideways,
+}
+
+fn orientation(c: char) -> Orientation {
+    match c as u32 {
+        0x3008..=0x3011 | 0x3014..=0x301F | 0x3030 | 0x30A0 | 0x30FC | 0xFE59..=0xFE5E | 0xFF08 | 0xFF09
+        | 0xFF0D | 0xFF1A | 0xFF1B | 0xFF1C..=0xFF1E | 0xFF3B | 0xFF3D | 0xFF3F | 0xFF5B..=0xFF60 | 0xFFE3 => {
+            Orientation::UprightIfVertical
+        }
+        0x00A7 | 0x00A9 | 0x00AE | 0x00B1 | 0x00BC..=0x00BE | 0x00D7 | 0x00F7 | 0x1100..=0x11FF | 0x2E80..=0x2FFF
+        | 0x3000..=0x31FF | 0x3200..=0x4DBF | 0x4E00..=0x9FFF | 0xA000..=0xA4CF | 0xA960..=0xA97F | 0xAC00..=0xD7FF
+        | 0xE000..=0xFAFF | 0xFE10..=0xFE1F | 0xFE30..=0xFE4F | 0xFE50..=0xFE6F | 0xFF00..=0xFFEF | 0x1F000..=0x1FAFF
+        | 0x20000..=0x3FFFF => Orientation::Upright,
+        _ => Orientation::Sideways,
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerticalGlyph {
+    pub glyph_id: u16,
+    /// UTF-16 offset of the first character the glyph was made from.
+    pub cluster: usize,
+    /// Maps the glyph's outline, in pixels with y up as the font draws it,
+    /// onto the column: x from its center line, y down from its top.
+    pub transform: Matrix,
+    /// Space the glyph takes down the column.
+    pub advance: f32,
+    /// False for glyphs turned sideways, such as Latin letters.
+    pub upright: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerticalText {
+    pub glyphs: Vec<VerticalGlyph>,
+    /// Height of the column.
+    pub advance: f32,
+}
+
+/// Glyphs of one run turned a quarter turn clockwise, so their baseline runs
+/// down the column centered on it.
+fn push_sideways(face: &Face, text: &str, start: usize, size: f32, features: &[Feature], out: &mut VerticalText) {
+    let shaped = shape_in_direction(face, text, size, features, Some(Direction::LeftToRight));
+    let baseline = -(shaped.ascender + shaped.descender) as f64 / 2.0;
+    for glyph in shaped.glyphs {
+        let y = out.advance as f64 + glyph.x_offset as f64;
+        out.glyphs.push(VerticalGlyph {
+            glyph_id: glyph.glyph_id,
+            cluster: start + glyph.cluster,
+            transform: [0.0, 1.0, 1.0, 0.0, baseline + glyph.y_offset as f64, y],
+            advance: glyph.x_advance,
+            upright: false,
+        });
+        out.advance += glyph.x_advance;
+    }
+}
+
+/// Upright glyphs placed by their vertical metrics, with the `vert` and
+/// `vrt2` forms the font has. Characters that only stand upright in a
+/// vertical form are turned sideways when the font lacks one.
+fn push_upright(face: &Face, text: &str, start: usize, size: f32, features: &[Feature], out: &mut VerticalText) {
+    let mut features = features.to_vec();
+    features.push(Feature::new(Tag::from_bytes(b"vrt2"), 1, ..));
+    let shaped = shape_in_direction(face, text, size, &features, Some(Direction::TopToBottom));
+    let (ascender, descender) = (shaped.ascender, shaped.descender);
+    let scale = size / face.units_per_em() as f32;
+
+    // Characters by UTF-16 offset, to find what each cluster starts with
+    let mut chars = Vec::new();
+    let mut utf16 = 0;
+    for c in text.chars() {
+        chars.push((utf16, c));
+        utf16 += c.len_utf16();
+    }
+    for glyph in shaped.glyphs {
+        let c = chars.iter().find(|(offset, _)| *offset == glyph.cluster).map(|(_, c)| *c);
+        let unchanged = c.and_then(|c| face.glyph_index(c)).is_some_and(|g| g.0 == glyph.glyph_id);
+        if c.is_some_and(|c| orientation(c) == Orientation::UprightIfVertical) && unchanged {
+            let advance = face.glyph_hor_advance(GlyphId(glyph.glyph_id)).map_or(0.0, |a| a as f32 * scale);
+            out.glyphs.push(VerticalGlyph {
+                glyph_id: glyph.glyph_id,
+                cluster: start + glyph.cluster,
+                transform: [0.0, 1.0, 1.0, 0.0, -(ascender + descender) as f64 / 2.0, out.advance as f64],
+                advance,
+                upright: false,
+            });
+            out.advance += advance;
+            continue;
+        }
+
+        let y = out.advance as f64 - glyph.y_offset as f64;
+        out.glyphs.push(VerticalGlyph {
+            glyph_id: glyph.glyph_id,
+            cluster: start + glyph.cluster,
+            transform: [1.0, 0.0, 0.0, -1.0, glyph.x_offset as f64, y],
+            advance: glyph.y_advance.abs(),
+            upright: true,
+        });
+        out.advance += glyph.y_advance.abs();
+    }
+}
+
+/// Lay out `text` as one vertical column, top to bottom. Ideographs, kana
+/// and Hangul stand upright using the font's vertical alternates, so
+/// punctuation such as `、` and `「` takes its vertical form; Latin letters
+/// and digits are turned sideways. Each glyph comes with the transform that
+/// places it, for drawing and export. Splitting into columns is up to the
+/// caller.
+#[command(async)]
+pub fn shape_vertical_text(
+    text: String,
+    font: FontSpec,
+    size: f32,
+    features: Option<Vec<String>>,
+) -> Result<VerticalText, String> {
+    if !(size.is_finite() && size > 0.0) {
+        return Err(format!("Invalid font size: {}", size));
+    }
+    let features = parse_features(&features.unwrap_or_default())?;
+
+    // Runs of upright and sideways text; marks and joiners stay with the
+    // character before them
+    let mut runs: Vec<(bool, usize, usize)> = Vec::new();
+    for (i, c) in text.char_indices() {
+        let upright = orientation(c) != Orientation::Sideways;
+        match runs.last_mut() {
+            Some((run_upright, _, end)) if *run_upright == upright || attaches_to_previous(c) => *end = i + c.len_utf8(),
+            _ => runs.push((upright, i, i + c.len_utf8())),
+        }
+    }
+
+    with_face(&font, |face| {
+        let mut out = VerticalText { glyphs: Vec::new(), advance: 0.0 };
+        for (upright, start, end) in runs {
+            let offset = text[..start].encode_utf16().count();
+            if upright {
+                push_upright(face, &text[start..end], offset, size, &features, &mut out);
+            } else {
+                push_sideways(face, &text[start..end], offset, size, &features, &mut out);
+            }
+        }
+        out
+    })
+}
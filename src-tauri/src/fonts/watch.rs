@@ -0,0 +1,104 @@
+//! Picks up fonts other programs install or remove while the app runs, so
+//! the font picker and text layout see them without a restart.
+
+use super::reload_font_paths;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+
+pub const FONTS_CHANGED_EVENT: &str = "fonts-changed";
+
+/// How long to wait for a burst of changes to settle, as font installers
+/// copy many files and write each in several steps.
+const SETTLE_TIME: Duration = Duration::from_millis(500);
+
+const FONT_EXTENSIONS: &[&str] = &["ttf", "otf", "ttc", "otc"];
+
+/// Watches the system's and the user's font directories and emits
+/// `fonts-changed` events to the frontend with the faces that were added
+/// and removed. Fonts the app installs itself are not watched, since the
+/// commands that install them update the font database directly.
+pub struct FontWatcher {
+    _watcher: RecommendedWatcher,
+}
+
+/// Where the platform looks for installed fonts.
+fn font_dirs(home: Option<&Path>) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if cfg!(target_os = "macos") {
+        dirs.extend(["/System/Library/Fonts", "/Library/Fonts"].map(PathBuf::from));
+        dirs.extend(home.map(|home| home.join("Library/Fonts")));
+    } else if cfg!(target_os = "windows") {
+        let windows = std::env::var_os("WINDIR").map_or_else(|| PathBuf::from("C:\\Windows"), PathBuf::from);
+        dirs.push(windows.join("Fonts"));
+        dirs.extend(std::env::var_os("LOCALAPPDATA").map(|local| PathBuf::from(local).join("Microsoft\\Windows\\Fonts")));
+    } else {
+        dirs.extend(["/usr/share/fonts", "/usr/local/share/fonts"].map(PathBuf::from));
+        let data_home = std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home.map(|home| home.join(".local/share")));
+        dirs.extend(data_home.map(|data| data.join("fonts")));
+        dirs.extend(home.map(|home| home.join(".fonts")));
+    }
+    dirs
+}
+
+/// Font files, or directories that may hold them. Paths that no longer
+/// exist are kept so their faces are removed.
+fn is_font_path(path: &Path) -> bool {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => FONT_EXTENSIONS.iter().any(|f| ext.eq_ignore_ascii_case(f)) || path.is_dir(),
+        None => !path.is_file(),
+    }
+}
+
+/// Collect changed paths until a burst of events settles, then reload them
+/// and report what changed.
+fn reload_changes(app: AppHandle, events: Receiver<Vec<PathBuf>>) {
+    while let Ok(first) = events.recv() {
+        let mut paths: BTreeSet<PathBuf> = first.into_iter().collect();
+        loop {
+            match events.recv_timeout(SETTLE_TIME) {
+                Ok(more) => paths.extend(more),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+
+        let paths: Vec<PathBuf> = paths.into_iter().filter(|path| is_font_path(path)).collect();
+        if paths.is_empty() {
+            continue;
+        }
+        let change = reload_font_paths(&paths);
+        if !change.is_empty() {
+            let _ = app.emit(FONTS_CHANGED_EVENT, change);
+        }
+    }
+}
+
+impl FontWatcher {
+    pub fn new(app: AppHandle, home: Option<PathBuf>) -> Result<Self, String> {
+        let (sender, events) = channel();
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            if let Ok(event) = res {
+                if !matches!(event.kind, EventKind::Access(_)) {
+                    let _ = sender.send(event.paths);
+                }
+            }
+        })
+        .map_err(|e| format!("Failed to create font watcher: {}", e))?;
+
+        // Directories that do not exist, such as an unused user font
+        // directory, are skipped
+        for dir in font_dirs(home.as_deref()).into_iter().filter(|dir| dir.is_dir()) {
+            let _ = watcher.watch(&dir, RecursiveMode::Recursive);
+        }
+
+        thread::spawn(move || reload_changes(app, events));
+        Ok(FontWatcher { _watcher: watcher })
+    }
+}
@@ -0,0 +1,599 @@
+//! WOFF and WOFF2 webfonts: decoding them to plain OpenType so they can be
+//! installed and laid out, and packing fonts embedded in web exports as
+//! WOFF2, which browsers load in about half the bytes of TrueType.
+
+use brotli::enc::backward_references::BrotliEncoderMode;
+use brotli::enc::BrotliEncoderParams;
+use std::io::Read;
+
+/// Tags WOFF2 stores as an index into this list rather than spelled out.
+const KNOWN_TAGS: [&[u8; 4]; 63] = [
+    b"cmap", b"head", b"hhea", b"hmtx", b"maxp", b"name", b"OS/2", b"post", b"cvt ", b"fpgm", b"glyf", b"loca",
+    b"prep", b"CFF ", b"VORG", b"EBDT", b"EBLC", b"gasp", b"hdmx", b"kern", b"LTSH", b"PCLT", b"VDMX", b"vhea",
+    b"vmtx", b"BASE", b"GDEF", b"GPOS", b"GSUB", b"EBSC", b"JSTF", b"MATH", b"CBDT", b"CBLC", b"COLR", b"CPAL",
+    b"SVG ", b"sbix", b"acnt", b"avar", b"bdat", b"bloc", b"bsln", b"cvar", b"fdsc", b"feat", b"fmtx", b"fvar",
+    b"gvar", b"hsty", b"just", b"lcar", b"mort", b"morx", b"opbd", b"prop", b"trak", b"Zapf", b"Silf", b"Glat",
+    b"Gloc", b"Feat", b"Sill",
+];
+/// Decoded fonts larger than this are rejected rather than allocated.
+const MAX_SFNT_SIZE: u32 = 256 * 1024 * 1024;
+
+type Tag = [u8; 4];
+
+/// Big-endian reads that fail on truncated data.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let bytes = self.pos.checked_add(n).and_then(|end| self.data.get(self.pos..end));
+        let bytes = bytes.ok_or_else(|| "Truncated webfont".to_string())?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_be_bytes(self.bytes(2)?.try_into().expect("two bytes")))
+    }
+
+    fn i16(&mut self) -> Result<i16, String> {
+        Ok(self.u16()? as i16)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into().expect("four bytes")))
+    }
+
+    fn tag(&mut self) -> Result<Tag, String> {
+        Ok(self.bytes(4)?.try_into().expect("four bytes"))
+    }
+
+    /// WOFF2 `UIntBase128`: up to five bytes of seven bits, high bit set on
+    /// all but the last.
+    fn base128(&mut self) -> Result<u32, String> {
+        let mut value: u32 = 0;
+        for i in 0..5 {
+            let byte = self.u8()?;
+            if (i == 0 && byte == 0x80) || value & 0xfe00_0000 != 0 {
+                return Err("Invalid WOFF2 number".to_string());
+            }
+            value = (value << 7) | (byte & 0x7f) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("Invalid WOFF2 number".to_string())
+    }
+
+    /// WOFF2 `255UInt16`.
+    fn u255(&mut self) -> Result<u16, String> {
+        Ok(match self.u8()? {
+            253 => self.u16()?,
+            254 => self.u8()? as u16 + 253 * 2,
+            255 => self.u8()? as u16 + 253,
+            code => code as u16,
+        })
+    }
+}
+
+fn pad4(data: &mut Vec<u8>) {
+    data.resize(data.len().next_multiple_of(4), 0);
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+/// Whether `data` is a WOFF or WOFF2 file.
+pub fn is_webfont(data: &[u8]) -> bool {
+    matches!(data.get(..4), Some(b"wOFF" | b"wOF2"))
+}
+
+/// An OpenType file of `tables`, with checksums and the table directory
+/// search fields filled in.
+fn write_sfnt(flavor: u32, mut tables: Vec<(Tag, Vec<u8>)>) -> Vec<u8> {
+    tables.sort_by_key(|(tag, _)| *tag);
+    let count = tables.len() as u16;
+    let entry_selector = count.max(1).ilog2() as u16;
+    let search_range = (1u16 << entry_selector) * 16;
+
+    let mut out = Vec::new();
+    out.extend(flavor.to_be_bytes());
+    out.extend(count.to_be_bytes());
+    out.extend(search_range.to_be_bytes());
+    out.extend(entry_selector.to_be_bytes());
+    out.extend((count * 16 - search_range).to_be_bytes());
+
+    let mut offset = 12 + tables.len() * 16;
+    let mut head_offset = None;
+    for (tag, data) in &mut tables {
+        if tag == b"head" && data.len() >= 12 {
+            // checkSumAdjustment is worked out over the whole file below
+            data[8..12].fill(0);
+            head_offset = Some(offset);
+        }
+        out.extend(*tag);
+        out.extend(checksum(data).to_be_bytes());
+        out.extend((offset as u32).to_be_bytes());
+        out.extend((data.len() as u32).to_be_bytes());
+        offset += data.len().next_multiple_of(4);
+    }
+    for (_, data) in &tables {
+        out.extend(data);
+        pad4(&mut out);
+    }
+
+    if let Some(head) = head_offset {
+        let adjustment = 0xb1b0_afbau32.wrapping_sub(checksum(&out));
+        out[head + 8..head + 12].copy_from_slice(&adjustment.to_be_bytes());
+    }
+    out
+}
+
+fn decode_woff(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut header = Reader::new(data);
+    header.bytes(4)?;
+    let flavor = header.u32()?;
+    header.u32()?;
+    let count = header.u16()?;
+    header.bytes(30)?;
+
+    let mut tables = Vec::with_capacity(count as usize);
+    let mut total = 0u32;
+    for _ in 0..count {
+        let tag = header.tag()?;
+        let (offset, compressed_length, length) = (header.u32()? as usize, header.u32()?, header.u32()?);
+        header.u32()?;
+        total = total.saturating_add(length);
+        if total > MAX_SFNT_SIZE {
+            return Err("The webfont is too large".to_string());
+        }
+
+        let stored = Reader { data, pos: offset }.bytes(compressed_length as usize)?;
+        let table = if compressed_length < length {
+            miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(stored, length as usize)
+                .map_err(|e| format!("Failed to decompress WOFF table: {:?}", e.status))?
+        } else {
+            stored.to_vec()
+        };
+        if table.len() != length as usize {
+            return Err("Corrupt WOFF table".to_string());
+        }
+        tables.push((tag, table));
+    }
+    Ok(write_sfnt(flavor, tables))
+}
+
+/// Bounding box of a glyph, as stored in `glyf`.
+#[derive(Clone, Copy, Default)]
+struct Bounds {
+    x_min: i16,
+    y_min: i16,
+    x_max: i16,
+    y_max: i16,
+}
+
+impl Bounds {
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        Ok(Bounds { x_min: r.i16()?, y_min: r.i16()?, x_max: r.i16()?, y_max: r.i16()? })
+    }
+
+    fn write(self, out: &mut Vec<u8>) {
+        for v in [self.x_min, self.y_min, self.x_max, self.y_max] {
+            out.extend(v.to_be_bytes());
+        }
+    }
+}
+
+/// Point deltas from the WOFF2 glyph stream, coded by `flag` in one to
+/// four bytes.
+fn triplet(flag: u8, glyphs: &mut Reader) -> Result<(i32, i32), String> {
+    let sign = |bit: u8, v: i32| if bit & 1 != 0 { v } else { -v };
+    let flag = flag & 0x7f;
+    Ok(match flag {
+        0..10 => (0, sign(flag, (((flag & 14) as i32) << 7) + glyphs.u8()? as i32)),
+        10..20 => (sign(flag, ((((flag - 10) & 14) as i32) << 7) + glyphs.u8()? as i32), 0),
+        20..84 => {
+            let (b0, b1) = ((flag - 20) as i32, glyphs.u8()? as i32);
+            (sign(flag, 1 + (b0 & 0x30) + (b1 >> 4)), sign(flag >> 1, 1 + ((b0 & 0x0c) << 2) + (b1 & 0x0f)))
+        }
+        84..120 => {
+            let b0 = (flag - 84) as i32;
+            let (b1, b2) = (glyphs.u8()? as i32, glyphs.u8()? as i32);
+            (sign(flag, 1 + ((b0 / 12) << 8) + b1), sign(flag >> 1, 1 + (((b0 % 12) >> 2) << 8) + b2))
+        }
+        120..124 => {
+            let b = glyphs.bytes(3)?;
+            let (b0, b1, b2) = (b[0] as i32, b[1] as i32, b[2] as i32);
+            (sign(flag, (b0 << 4) + (b1 >> 4)), sign(flag >> 1, ((b1 & 0x0f) << 8) + b2))
+        }
+        _ => {
+            let b = glyphs.bytes(4)?;
+            (sign(flag, ((b[0] as i32) << 8) + b[1] as i32), sign(flag >> 1, ((b[2] as i32) << 8) + b[3] as i32))
+        }
+    })
+}
+
+/// A simple glyph in `glyf` format, with points as (dx, dy, on curve).
+fn write_simple_glyph(
+    out: &mut Vec<u8>,
+    bounds: Bounds,
+    end_points: &[u16],
+    instructions: &[u8],
+    points: &[(i32, i32, bool)],
+    overlap: bool,
+) {
+    out.extend((end_points.len() as i16).to_be_bytes());
+    bounds.write(out);
+    for end in end_points {
+        out.extend(end.to_be_bytes());
+    }
+    out.extend((instructions.len() as u16).to_be_bytes());
+    out.extend(instructions);
+
+    let (mut xs, mut ys) = (Vec::new(), Vec::new());
+    for (i, &(dx, dy, on_curve)) in points.iter().enumerate() {
+        let mut flag = on_curve as u8;
+        if i == 0 && overlap {
+            flag |= 0x40;
+        }
+        // Short deltas take one byte, with the sign in the flag
+        for (delta, short, same, bytes) in [(dx, 0x02, 0x10, &mut xs), (dy, 0x04, 0x20, &mut ys)] {
+            if delta == 0 {
+                flag |= same;
+            } else if delta.abs() < 256 {
+                flag |= short | if delta > 0 { same } else { 0 };
+                bytes.push(delta.unsigned_abs() as u8);
+            } else {
+                bytes.extend((delta as i16).to_be_bytes());
+            }
+        }
+        out.push(flag);
+    }
+    out.extend(xs);
+    out.extend(ys);
+}
+
+/// `glyf` and `loca` rebuilt from the WOFF2 glyph transform.
+struct Glyphs {
+    glyf: Vec<u8>,
+    loca: Vec<u8>,
+    /// Each glyph's left edge, for rebuilding `hmtx`.
+    x_mins: Vec<i16>,
+}
+
+fn decode_glyf(data: &[u8]) -> Result<Glyphs, String> {
+    let mut header = Reader::new(data);
+    header.u16()?;
+    let options = header.u16()?;
+    let num_glyphs = header.u16()? as usize;
+    let index_format = header.u16()?;
+    let mut sizes = [0; 7];
+    for size in &mut sizes {
+        *size = header.u32()? as usize;
+    }
+    let mut offset = 36;
+    let mut stream = |size: usize| {
+        let stream = Reader { data, pos: offset }.bytes(size).map(Reader::new);
+        offset += size;
+        stream
+    };
+    let mut contours = stream(sizes[0])?;
+    let mut point_counts = stream(sizes[1])?;
+    let mut flags = stream(sizes[2])?;
+    let mut glyphs = stream(sizes[3])?;
+    let mut composites = stream(sizes[4])?;
+    let mut boxes = stream(sizes[5])?;
+    let mut instructions = stream(sizes[6])?;
+    let bbox_bitmap = boxes.bytes(num_glyphs.div_ceil(32) * 4)?;
+    let overlap_bitmap =
+        if options & 1 != 0 { Some(Reader { data, pos: offset }.bytes(num_glyphs.div_ceil(8))?) } else { None };
+    let bit = |bitmap: &[u8], g: usize| bitmap[g >> 3] & (0x80 >> (g & 7)) != 0;
+
+    let mut glyf = Vec::new();
+    let mut offsets = Vec::with_capacity(num_glyphs + 1);
+    let mut x_mins = Vec::with_capacity(num_glyphs);
+    for g in 0..num_glyphs {
+        offsets.push(glyf.len());
+        let has_bounds = bit(bbox_bitmap, g);
+        match contours.i16()? {
+            0 if has_bounds => return Err("Corrupt WOFF2 glyph".to_string()),
+            0 => x_mins.push(0),
+            -1 => {
+                if !has_bounds {
+                    return Err("Corrupt WOFF2 glyph".to_string());
+                }
+                let bounds = Bounds::read(&mut boxes)?;
+                let start = composites.pos;
+                let mut has_instructions = false;
+                loop {
+                    let flags = composites.u16()?;
+                    let scale = match flags {
+                        f if f & 0x0008 != 0 => 2,
+                        f if f & 0x0040 != 0 => 4,
+                        f if f & 0x0080 != 0 => 8,
+                        _ => 0,
+                    };
+                    composites.bytes(2 + if flags & 0x0001 != 0 { 4 } else { 2 } + scale)?;
+                    has_instructions |= flags & 0x0100 != 0;
+                    if flags & 0x0020 == 0 {
+                        break;
+                    }
+                }
+                glyf.extend((-1i16).to_be_bytes());
+                bounds.write(&mut glyf);
+                glyf.extend(&composites.data[start..composites.pos]);
+                if has_instructions {
+                    let length = glyphs.u255()?;
+                    glyf.extend(length.to_be_bytes());
+                    glyf.extend(instructions.bytes(length as usize)?);
+                }
+                x_mins.push(bounds.x_min);
+            }
+            n if n > 0 => {
+                let mut end_points = Vec::with_capacity(n as usize);
+                let mut total: u32 = 0;
+                for _ in 0..n {
+                    total += point_counts.u255()? as u32;
+                    let end = total.checked_sub(1).filter(|&e| e <= u16::MAX as u32);
+                    end_points.push(end.ok_or_else(|| "Corrupt WOFF2 glyph".to_string())? as u16);
+                }
+
+                let mut points = Vec::with_capacity(total as usize);
+                let (mut x, mut y) = (0, 0);
+                let mut computed: Option<Bounds> = None;
+                for _ in 0..total {
+                    let flag = flags.u8()?;
+                    let (dx, dy) = triplet(flag, &mut glyphs)?;
+                    (x, y) = (x + dx, y + dy);
+                    let (px, py) = (x as i16, y as i16);
+                    computed = Some(match computed {
+                        Some(b) => Bounds {
+                            x_min: b.x_min.min(px),
+                            y_min: b.y_min.min(py),
+                            x_max: b.x_max.max(px),
+                            y_max: b.y_max.max(py),
+                        },
+                        None => Bounds { x_min: px, y_min: py, x_max: px, y_max: py },
+                    });
+                    points.push((dx, dy, flag & 0x80 == 0));
+                }
+                let length = glyphs.u255()?;
+                let code = instructions.bytes(length as usize)?;
+                let bounds = if has_bounds { Bounds::read(&mut boxes)? } else { computed.unwrap_or_default() };
+
+                let overlap = overlap_bitmap.is_some_and(|bitmap| bit(bitmap, g));
+                write_simple_glyph(&mut glyf, bounds, &end_points, code, &points, overlap);
+                x_mins.push(bounds.x_min);
+            }
+            _ => return Err("Corrupt WOFF2 glyph".to_string()),
+        }
+        pad4(&mut glyf);
+    }
+    offsets.push(glyf.len());
+
+    let mut loca = Vec::with_capacity(offsets.len() * 4);
+    for offset in offsets {
+        if index_format == 0 {
+            loca.extend(((offset / 2) as u16).to_be_bytes());
+        } else {
+            loca.extend((offset as u32).to_be_bytes());
+        }
+    }
+    Ok(Glyphs { glyf, loca, x_mins })
+}
+
+/// `hmtx` rebuilt from the WOFF2 transform, which can leave out side
+/// bearings that equal the glyph's left edge.
+fn decode_hmtx(data: &[u8], num_glyphs: usize, num_metrics: usize, x_mins: &[i16]) -> Result<Vec<u8>, String> {
+    let mut r = Reader::new(data);
+    let flags = r.u8()?;
+    let advances: Vec<u16> = (0..num_metrics).map(|_| r.u16()).collect::<Result<_, _>>()?;
+    let mut bearing = |g: usize, stored: bool| -> Result<i16, String> {
+        if stored {
+            r.i16()
+        } else {
+            x_mins.get(g).copied().ok_or_else(|| "Corrupt WOFF2 metrics".to_string())
+        }
+    };
+
+    let mut out = Vec::with_capacity(num_metrics * 4 + (num_glyphs - num_metrics) * 2);
+    let mut bearings = Vec::with_capacity(num_glyphs);
+    for g in 0..num_glyphs {
+        bearings.push(bearing(g, if g < num_metrics { flags & 1 == 0 } else { flags & 2 == 0 })?);
+    }
+    for (g, lsb) in bearings.iter().enumerate() {
+        if let Some(advance) = advances.get(g) {
+            out.extend(advance.to_be_bytes());
+        }
+        out.extend(lsb.to_be_bytes());
+    }
+    Ok(out)
+}
+
+fn decode_woff2(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut header = Reader::new(data);
+    header.bytes(4)?;
+    let flavor = header.u32()?;
+    if &flavor.to_be_bytes() == b"ttcf" {
+        return Err("WOFF2 font collections are not supported".to_string());
+    }
+    header.u32()?;
+    let count = header.u16()?;
+    header.u16()?;
+    let total_size = header.u32()?;
+    let compressed_size = header.u32()? as usize;
+    header.bytes(24)?;
+    if total_size > MAX_SFNT_SIZE {
+        return Err("The webfont is too large".to_string());
+    }
+
+    let mut entries = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let flags = header.u8()?;
+        let tag = match flags & 0x3f {
+            63 => header.tag()?,
+            index => *KNOWN_TAGS[index as usize],
+        };
+        let length = header.base128()?;
+        // glyf and loca are transformed unless marked otherwise; other tables
+        // only when marked
+        let version = flags >> 6;
+        let transformed = if matches!(&tag, b"glyf" | b"loca") { version != 3 } else { version != 0 };
+        let stored_length = if transformed { header.base128()? } else { length };
+        entries.push((tag, transformed, stored_length));
+    }
+
+    let stream_size: u64 = entries.iter().map(|(_, _, length)| *length as u64).sum();
+    if stream_size > MAX_SFNT_SIZE as u64 {
+        return Err("The webfont is too large".to_string());
+    }
+    let mut stream = Vec::with_capacity(stream_size as usize);
+    brotli::Decompressor::new(header.bytes(compressed_size)?, 4096)
+        .take(stream_size)
+        .read_to_end(&mut stream)
+        .map_err(|e| format!("Failed to decompress WOFF2 data: {}", e))?;
+    if stream.len() as u64 != stream_size {
+        return Err("Corrupt WOFF2 data".to_string());
+    }
+
+    let mut tables: Vec<(Tag, bool, &[u8])> = Vec::with_capacity(entries.len());
+    let mut offset = 0;
+    for (tag, transformed, length) in entries {
+        tables.push((tag, transformed, &stream[offset..offset + length as usize]));
+        offset += length as usize;
+    }
+    let find = |name: &Tag| tables.iter().find(|(tag, _, _)| tag == name).map(|(_, _, data)| *data);
+
+    let mut rebuilt: Option<Glyphs> = None;
+    let mut out = Vec::with_capacity(tables.len());
+    for &(tag, transformed, data) in &tables {
+        let table = match &tag {
+            _ if !transformed => data.to_vec(),
+            b"glyf" => {
+                let mut glyphs = decode_glyf(data)?;
+                let glyf = std::mem::take(&mut glyphs.glyf);
+                rebuilt = Some(glyphs);
+                glyf
+            }
+            // Rebuilt along with glyf, which comes first
+            b"loca" => continue,
+            b"hmtx" => {
+                let glyphs = rebuilt.as_ref().ok_or("WOFF2 metrics need transformed glyphs")?;
+                let count = |table: &Tag, at: usize| {
+                    find(table).and_then(|t| t.get(at..at + 2)).map(|b| u16::from_be_bytes([b[0], b[1]]) as usize)
+                };
+                let num_glyphs = count(b"maxp", 4).ok_or("Missing maxp table")?;
+                let num_metrics = count(b"hhea", 34).ok_or("Missing hhea table")?.min(num_glyphs);
+                decode_hmtx(data, num_glyphs, num_metrics, &glyphs.x_mins)?
+            }
+            _ => return Err(format!("Unknown WOFF2 transform of {}", String::from_utf8_lossy(&tag))),
+        };
+        out.push((tag, table));
+    }
+    if let Some(glyphs) = rebuilt {
+        out.push((*b"loca", glyphs.loca));
+    }
+    Ok(write_sfnt(flavor, out))
+}
+
+/// The OpenType font inside a WOFF or WOFF2 file.
+pub fn decode_webfont(data: &[u8]) -> Result<Vec<u8>, String> {
+    match data.get(..4) {
+        Some(b"wOFF") => decode_woff(data),
+        Some(b"wOF2") => decode_woff2(data),
+        _ => Err("Not a WOFF or WOFF2 font".to_string()),
+    }
+}
+
+/// Pack an OpenType font as WOFF2. Tables are compressed as they are, with
+/// no glyph transform, which every WOFF2 reader accepts.
+pub fn encode_woff2(sfnt: &[u8]) -> Result<Vec<u8>, String> {
+    let mut header = Reader::new(sfnt);
+    let flavor = header.u32()?;
+    if &flavor.to_be_bytes() == b"ttcf" {
+        return Err("Font collections cannot be packed as WOFF2".to_string());
+    }
+    let count = header.u16()?;
+    header.bytes(6)?;
+
+    let mut tables: Vec<(Tag, &[u8])> = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let tag = header.tag()?;
+        header.u32()?;
+        let (offset, length) = (header.u32()? as usize, header.u32()? as usize);
+        tables.push((tag, Reader { data: sfnt, pos: offset }.bytes(length)?));
+    }
+    tables.sort_by_key(|(tag, _)| *tag);
+
+    let mut directory = Vec::new();
+    let mut stream = Vec::new();
+    let mut sfnt_size = 12 + 16 * tables.len();
+    for (tag, data) in &tables {
+        let transform = if matches!(tag, b"glyf" | b"loca") { 3 << 6 } else { 0 };
+        match KNOWN_TAGS.iter().position(|known| *known == tag) {
+            Some(index) => directory.push(index as u8 | transform),
+            None => {
+                directory.push(63 | transform);
+                directory.extend(tag);
+            }
+        }
+        let mut length = data.len() as u32;
+        let mut bytes = [0u8; 5];
+        let mut n = 0;
+        loop {
+            bytes[n] = (length & 0x7f) as u8;
+            length >>= 7;
+            n += 1;
+            if length == 0 {
+                break;
+            }
+        }
+        directory.extend((0..n).rev().map(|i| bytes[i] | if i > 0 { 0x80 } else { 0 }));
+        stream.extend(*data);
+        sfnt_size += data.len().next_multiple_of(4);
+    }
+
+    let params = BrotliEncoderParams {
+        quality: 11,
+        lgwin: 22,
+        mode: BrotliEncoderMode::BROTLI_MODE_FONT,
+        size_hint: stream.len(),
+        ..Default::default()
+    };
+    let mut compressed = Vec::new();
+    brotli::BrotliCompress(&mut stream.as_slice(), &mut compressed, &params)
+        .map_err(|e| format!("Failed to compress font: {}", e))?;
+
+    let mut out = Vec::with_capacity(48 + directory.len() + compressed.len() + 3);
+    out.extend(b"wOF2");
+    out.extend(flavor.to_be_bytes());
+    out.extend([0; 4]);
+    out.extend(count.to_be_bytes());
+    out.extend([0; 2]);
+    out.extend((sfnt_size as u32).to_be_bytes());
+    out.extend((compressed.len() as u32).to_be_bytes());
+    out.extend(1u16.to_be_bytes());
+    out.extend(0u16.to_be_bytes());
+    out.extend([0; 20]);
+    out.extend(directory);
+    out.extend(compressed);
+    pad4(&mut out);
+    let length = out.len() as u32;
+    out[8..12].copy_from_slice(&length.to_be_bytes());
+    Ok(out)
+}
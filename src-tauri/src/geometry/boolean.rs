@@ -0,0 +1,429 @@
+//! Union, subtraction, intersection and exclusion of filled paths.
+//!
+//! Curves are flattened, the flattened outlines are split wherever they
+//! cross or touch, and each piece is kept if the result is filled on one
+//! side of it and not the other. Pieces are traced back to the curves they
+//! came from, so runs of a curve that survive whole come out as curves
+//! rather than polylines.
+
+use super::{flattening_steps, path_segments, Segment};
+use crate::model::{PathCommand, VectorPath, WindingRule};
+use kurbo::{ParamCurve, Point, Vec2};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use tauri::command;
+
+/// Largest distance between a curve and its flattened outline, in path units.
+pub const DEFAULT_TOLERANCE: f64 = 0.05;
+/// Points closer than this are treated as one, which absorbs rounding in
+/// intersections.
+const SNAP: f64 = 1e-6;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BooleanOp {
+    Union,
+    /// The first path minus the rest.
+    Subtract,
+    Intersect,
+    /// Areas covered by an odd number of the paths.
+    Exclude,
+}
+
+impl BooleanOp {
+    fn filled(self, inside: &[bool]) -> bool {
+        match self {
+            BooleanOp::Union => inside.iter().any(|i| *i),
+            BooleanOp::Subtract => inside.first() == Some(&true) && !inside[1..].iter().any(|i| *i),
+            BooleanOp::Intersect => !inside.is_empty() && inside.iter().all(|i| *i),
+            BooleanOp::Exclude => inside.iter().filter(|i| **i).count() % 2 == 1,
+        }
+    }
+}
+
+/// A flattened piece of an input segment, covering `t0..t1` of it.
+#[derive(Clone, Copy)]
+struct Edge {
+    from: Point,
+    to: Point,
+    source: usize,
+    t0: f64,
+    t1: f64,
+}
+
+/// Line pieces approximating `segment` within `tolerance`, with their
+/// parameter ranges.
+fn flatten(segment: &Segment, source: usize, tolerance: f64, out: &mut Vec<Edge>) {
+    match *segment {
+        Segment::Line(from, to) => out.push(Edge { from, to, source, t0: 0.0, t1: 1.0 }),
+        Segment::Cubic(curve) => {
+            let n = flattening_steps(&curve, tolerance);
+            let mut from = curve.p0;
+            for i in 1..=n {
+                let t = i as f64 / n as f64;
+                let to = if i == n { curve.p3 } else { curve.eval(t) };
+                out.push(Edge { from, to, source, t0: (i - 1) as f64 / n as f64, t1: t });
+                from = to;
+            }
+        }
+    }
+}
+
+fn cross(a: Vec2, b: Vec2) -> f64 {
+    a.x * b.y - a.y * b.x
+}
+
+/// Where `e` must be split for `other`: crossings, and the ends of either
+/// that touch the other, with the point given once so both pieces share it.
+fn split_points(e: &Edge, other: &Edge, splits: &mut [Vec<(f64, Point)>; 2]) {
+    let (r, s) = (e.to - e.from, other.to - other.from);
+    let (rr, ss) = (r.hypot2(), s.hypot2());
+    if rr == 0.0 || ss == 0.0 {
+        return;
+    }
+    let interior = |t: f64| t > 1e-9 && t < 1.0 - 1e-9;
+    let qp = other.from - e.from;
+    let denom = cross(r, s);
+
+    if denom.abs() > 1e-12 * (rr * ss).sqrt() {
+        let t = cross(qp, s) / denom;
+        let u = cross(qp, r) / denom;
+        if (-1e-9..=1.0 + 1e-9).contains(&t) && (-1e-9..=1.0 + 1e-9).contains(&u) {
+            // An end of one touching the other is taken as it is
+            let point = if !interior(u) {
+                if u < 0.5 { other.from } else { other.to }
+            } else if !interior(t) {
+                if t < 0.5 { e.from } else { e.to }
+            } else {
+                e.from + r * t
+            };
+            if interior(t) {
+                splits[0].push((t, point));
+            }
+            if interior(u) {
+                splits[1].push((u, point));
+            }
+        }
+        return;
+    }
+
+    // Parallel: only collinear overlaps split, at each other's ends
+    if cross(qp, r).abs() / rr.sqrt() > SNAP {
+        return;
+    }
+    for point in [other.from, other.to] {
+        let t = (point - e.from).dot(r) / rr;
+        if interior(t) {
+            splits[0].push((t, point));
+        }
+    }
+    for point in [e.from, e.to] {
+        let u = (point - other.from).dot(s) / ss;
+        if interior(u) {
+            splits[1].push((u, point));
+        }
+    }
+}
+
+/// An operand's edges bucketed into horizontal bands, so a winding number
+/// only looks at the edges level with its point.
+struct WindingIndex {
+    edges: Vec<Edge>,
+    top: f64,
+    band_height: f64,
+    bands: Vec<Vec<usize>>,
+}
+
+impl WindingIndex {
+    fn new(edges: Vec<Edge>) -> Self {
+        let top = edges.iter().map(|e| e.from.y.min(e.to.y)).fold(f64::INFINITY, f64::min);
+        let bottom = edges.iter().map(|e| e.from.y.max(e.to.y)).fold(f64::NEG_INFINITY, f64::max);
+        let count = ((edges.len() as f64).sqrt().ceil() as usize).max(1);
+        let band_height = ((bottom - top) / count as f64).max(f64::MIN_POSITIVE);
+        let mut index = WindingIndex { edges, top, band_height, bands: vec![Vec::new(); count] };
+        for (i, e) in index.edges.iter().enumerate() {
+            let (first, last) = (index.band(e.from.y.min(e.to.y)), index.band(e.from.y.max(e.to.y)));
+            for band in &mut index.bands[first..=last] {
+                band.push(i);
+            }
+        }
+        index
+    }
+
+    fn band(&self, y: f64) -> usize {
+        (((y - self.top) / self.band_height).max(0.0) as usize).min(self.bands.len() - 1)
+    }
+
+    /// Winding number of the edges around `p`.
+    fn winding(&self, p: Point) -> i32 {
+        if self.edges.is_empty() {
+            return 0;
+        }
+        winding(self.bands[self.band(p.y)].iter().map(|i| &self.edges[*i]), p)
+    }
+}
+
+/// Winding number of `edges` around `p`.
+fn winding<'a>(edges: impl Iterator<Item = &'a Edge>, p: Point) -> i32 {
+    let mut winding = 0;
+    for e in edges {
+        let side = cross(e.to - e.from, p - e.from);
+        if e.from.y <= p.y && e.to.y > p.y && side > 0.0 {
+            winding += 1;
+        } else if e.to.y <= p.y && e.from.y > p.y && side < 0.0 {
+            winding -= 1;
+        }
+    }
+    winding
+}
+
+fn snap_key(p: Point) -> (i64, i64) {
+    ((p.x / SNAP).round() as i64, (p.y / SNAP).round() as i64)
+}
+
+/// A kept piece, oriented with the result's fill on its left.
+struct Piece {
+    from: usize,
+    to: usize,
+    source: usize,
+    t0: f64,
+    t1: f64,
+}
+
+/// Combine filled `paths`, all in the same coordinate space, each taken
+/// with its own winding rule. Returns the outline of the result, with holes
+/// wound opposite to their outer contours so it fills the same with either
+/// rule.
+pub fn combine(paths: &[VectorPath], op: BooleanOp, tolerance: f64) -> VectorPath {
+    let mut segments = Vec::new();
+    let mut operands: Vec<Vec<Edge>> = Vec::with_capacity(paths.len());
+    for path in paths {
+        let mut edges = Vec::new();
+        for segment in path_segments(path, true) {
+            flatten(&segment, segments.len(), tolerance, &mut edges);
+            segments.push(segment);
+        }
+        operands.push(edges);
+    }
+    let rules: Vec<WindingRule> = paths.iter().map(|p| p.winding_rule).collect();
+    let edges: Vec<Edge> = operands.iter().flatten().copied().collect();
+    let operands: Vec<WindingIndex> = operands.into_iter().map(WindingIndex::new).collect();
+
+    // Split every edge where others cross or touch it, sweeping by x
+    let mut order: Vec<usize> = (0..edges.len()).collect();
+    let min_x = |e: &Edge| e.from.x.min(e.to.x);
+    order.sort_by(|a, b| min_x(&edges[*a]).total_cmp(&min_x(&edges[*b])));
+    let mut splits: Vec<Vec<(f64, Point)>> = vec![Vec::new(); edges.len()];
+    for (k, &i) in order.iter().enumerate() {
+        let e = &edges[i];
+        let (max_x, min_y, max_y) = (e.from.x.max(e.to.x), e.from.y.min(e.to.y), e.from.y.max(e.to.y));
+        for &j in &order[k + 1..] {
+            let other = &edges[j];
+            if min_x(other) > max_x + SNAP {
+                break;
+            }
+            if other.from.y.min(other.to.y) > max_y + SNAP || other.from.y.max(other.to.y) < min_y - SNAP {
+                continue;
+            }
+            let mut found = [Vec::new(), Vec::new()];
+            split_points(e, other, &mut found);
+            let [for_e, for_other] = found;
+            splits[i].extend(for_e);
+            splits[j].extend(for_other);
+        }
+    }
+
+    // Vertices are shared by position, so touching pieces link up
+    let mut vertices: Vec<Point> = Vec::new();
+    let mut ids: HashMap<(i64, i64), usize> = HashMap::new();
+    let mut vertex = |p: Point| {
+        *ids.entry(snap_key(p)).or_insert_with(|| {
+            vertices.push(p);
+            vertices.len() - 1
+        })
+    };
+
+    let mut pieces: Vec<Piece> = Vec::new();
+    let mut seen: HashSet<(usize, usize)> = HashSet::new();
+    for (e, mut cuts) in edges.iter().zip(splits) {
+        cuts.sort_by(|a, b| a.0.total_cmp(&b.0));
+        cuts.push((1.0, e.to));
+        let (mut t, mut point) = (0.0, e.from);
+        for (u, next) in cuts {
+            let (a, b) = (vertex(point), vertex(next));
+            // Overlapping outlines leave one piece for the shared stretch
+            if a != b && seen.insert((a.min(b), a.max(b))) {
+                let lerp = |u: f64| e.t0 + (e.t1 - e.t0) * u;
+                pieces.push(Piece { from: a, to: b, source: e.source, t0: lerp(t), t1: lerp(u) });
+            }
+            (t, point) = (u, next);
+        }
+    }
+
+    // Keep the pieces with the result filled on exactly one side
+    let inside = |p: Point| -> Vec<bool> {
+        operands
+            .iter()
+            .zip(&rules)
+            .map(|(index, rule)| {
+                let w = index.winding(p);
+                match rule {
+                    WindingRule::Nonzero => w != 0,
+                    WindingRule::Evenodd => w % 2 != 0,
+                }
+            })
+            .collect()
+    };
+    pieces.retain_mut(|piece| {
+        let (a, b) = (vertices[piece.from], vertices[piece.to]);
+        let direction = b - a;
+        let length = direction.hypot();
+        let normal = Vec2::new(-direction.y, direction.x) / length;
+        let offset = (length * 0.25).min(1e-4);
+        let mid = a.midpoint(b);
+        let left = op.filled(&inside(mid + normal * offset));
+        let right = op.filled(&inside(mid - normal * offset));
+        if left == right {
+            return false;
+        }
+        if right {
+            std::mem::swap(&mut piece.from, &mut piece.to);
+            std::mem::swap(&mut piece.t0, &mut piece.t1);
+        }
+        true
+    });
+
+    // Trace closed loops
+    let mut outgoing: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (i, piece) in pieces.iter().enumerate() {
+        outgoing.entry(piece.from).or_default().push(i);
+    }
+    let mut used = vec![false; pieces.len()];
+    let mut commands = Vec::new();
+    for start in 0..pieces.len() {
+        if used[start] {
+            continue;
+        }
+        let mut contour = Vec::new();
+        let mut current = start;
+        loop {
+            used[current] = true;
+            contour.push(current);
+            let end = pieces[current].to;
+            if end == pieces[start].from {
+                break;
+            }
+            let next = outgoing.get(&end).and_then(|candidates| candidates.iter().copied().find(|i| !used[*i]));
+            match next {
+                Some(next) => current = next,
+                None => break,
+            }
+        }
+        write_contour(&contour, &pieces, &vertices, &segments, &mut commands);
+    }
+
+    VectorPath { winding_rule: WindingRule::Nonzero, commands }
+}
+
+/// Whether `b` continues `a` along the same input segment.
+fn continues(a: &Piece, b: &Piece) -> bool {
+    a.source == b.source && (a.t1 - b.t0).abs() < 1e-9 && (a.t1 - a.t0).signum() == (b.t1 - b.t0).signum()
+}
+
+/// Path commands for a loop of pieces, with consecutive pieces of one curve
+/// joined back into one curve.
+fn write_contour(contour: &[usize], pieces: &[Piece], vertices: &[Point], segments: &[Segment], out: &mut Vec<PathCommand>) {
+    if contour.is_empty() {
+        return;
+    }
+    // Start where a run begins, so no run wraps around the start
+    let n = contour.len();
+    let first = (0..n)
+        .find(|&i| !continues(&pieces[contour[(i + n - 1) % n]], &pieces[contour[i]]))
+        .unwrap_or(0);
+    let ordered: Vec<&Piece> = (0..n).map(|i| &pieces[contour[(first + i) % n]]).collect();
+
+    let start = vertices[ordered[0].from];
+    let at = out.len();
+    out.push(PathCommand::MoveTo { x: start.x, y: start.y });
+    let mut i = 0;
+    while i < n {
+        let mut j = i;
+        while j + 1 < n && continues(ordered[j], ordered[j + 1]) {
+            j += 1;
+        }
+        let (run_start, run_end) = (ordered[i], ordered[j]);
+        let end = vertices[run_end.to];
+        match segments[run_start.source] {
+            Segment::Cubic(curve) => {
+                // Reversed ranges give the reversed curve
+                let part = curve.subsegment(run_start.t0..run_end.t1);
+                out.push(PathCommand::CurveTo {
+                    x1: part.p1.x,
+                    y1: part.p1.y,
+                    x2: part.p2.x,
+                    y2: part.p2.y,
+                    x: end.x,
+                    y: end.y,
+                });
+            }
+            Segment::Line(..) => line_to(out, end),
+        }
+        i = j + 1;
+    }
+    // Closing draws the last line back to the start
+    if matches!(out.last(), Some(PathCommand::LineTo { x, y }) if Point::new(*x, *y) == start) {
+        out.pop();
+    }
+    // and where that line runs on into the first, the start is not a corner
+    if let (Some(&PathCommand::LineTo { x, y }), Some(last)) = (out.get(at + 1), out.last().and_then(end_point)) {
+        let next = Point::new(x, y);
+        if out.len() - at > 3 && last != start && straight(last, start, next) {
+            out[at] = PathCommand::MoveTo { x, y };
+            out.remove(at + 1);
+        }
+    }
+    out.push(PathCommand::ClosePath);
+}
+
+/// Whether `via` lies on the way from `from` straight on to `to`.
+fn straight(from: Point, via: Point, to: Point) -> bool {
+    let (a, b) = (via - from, to - via);
+    cross(a, b).abs() <= SNAP * a.hypot().max(b.hypot()) && a.dot(b) > 0.0
+}
+
+fn end_point(cmd: &PathCommand) -> Option<Point> {
+    match *cmd {
+        PathCommand::MoveTo { x, y } | PathCommand::LineTo { x, y } | PathCommand::CurveTo { x, y, .. } => {
+            Some(Point::new(x, y))
+        }
+        PathCommand::ClosePath => None,
+    }
+}
+
+/// Append a line to `to`, extending the previous line instead where it
+/// continues straight on, as where two inputs' edges meet end to end.
+fn line_to(out: &mut Vec<PathCommand>, to: Point) {
+    if let [.., before, PathCommand::LineTo { x, y }] = out.as_slice() {
+        if end_point(before).is_some_and(|from| straight(from, Point::new(*x, *y), to)) {
+            out.pop();
+        }
+    }
+    out.push(PathCommand::LineTo { x: to.x, y: to.y });
+}
+
+/// Combine `paths` with `op`: `union`, `subtract` (the first minus the
+/// rest), `intersect` or `exclude`. Paths must share a coordinate space;
+/// open subpaths are closed, as they are when filled. `tolerance` is the
+/// largest distance curves may be moved where they are cut, in path units.
+#[command(async)]
+pub fn boolean_op(paths: Vec<VectorPath>, op: BooleanOp, tolerance: Option<f64>) -> Result<VectorPath, String> {
+    if paths.is_empty() {
+        return Err("No paths to combine".to_string());
+    }
+    let tolerance = tolerance.unwrap_or(DEFAULT_TOLERANCE);
+    if !(tolerance.is_finite() && tolerance > 0.0) {
+        return Err(format!("Invalid tolerance: {}", tolerance));
+    }
+    Ok(combine(&paths, op, tolerance))
+}
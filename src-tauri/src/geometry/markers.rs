@@ -0,0 +1,160 @@
+//! Arrowheads and other markers at the ends of open paths. Markers are
+//! worked out here once, as the stroke cut back to make room for them and
+//! the outlines of the heads, so the renderer and the SVG and PDF exports
+//! draw exactly the same shapes.
+
+use super::boolean::DEFAULT_TOLERANCE;
+use super::stroke::StrokeOptions;
+use super::{from_bez_path, to_bez_path};
+use crate::model::{Marker, NodeData, VectorPath, WindingRule};
+use kurbo::{BezPath, Circle, ParamCurve, ParamCurveArclen, PathEl, PathSeg, Point, Shape, StrokeOpts, Vec2};
+
+/// Accuracy of arc lengths along the path.
+const ACCURACY: f64 = 1e-3;
+
+/// A node's stroke with its markers, in local space.
+pub struct MarkedStroke {
+    /// The node's paths, with open ends cut back where a marker covers
+    /// them, to be stroked as usual.
+    pub paths: Vec<VectorPath>,
+    /// Outlines of the markers, filled with the stroke paints.
+    pub heads: VectorPath,
+}
+
+impl Marker {
+    /// How far back from the end the stroke stops, so its cap doesn't show
+    /// past a pointed head.
+    fn setback(self, weight: f64) -> f64 {
+        match self {
+            Marker::ArrowEquilateral => 5.0 * weight * 3f64.sqrt() / 2.0,
+            _ => 0.0,
+        }
+    }
+
+    /// The outline of the marker at `tip`, where the path leaves heading
+    /// `toward`, a unit vector.
+    fn outline(self, tip: Point, toward: Vec2, weight: f64, options: &StrokeOptions) -> BezPath {
+        let across = toward.turn_90();
+        let polygon = |points: &[Point]| {
+            let mut path = BezPath::new();
+            path.move_to(points[0]);
+            points[1..].iter().for_each(|p| path.line_to(*p));
+            path.close_path();
+            path
+        };
+        match self {
+            Marker::ArrowLines => {
+                let (back, spread) = (2.5 * weight, 2.5 * weight);
+                let mut chevron = BezPath::new();
+                chevron.move_to(tip - back * toward + spread * across);
+                chevron.line_to(tip);
+                chevron.line_to(tip - back * toward - spread * across);
+                kurbo::stroke(chevron, &options.style(weight), &StrokeOpts::default(), DEFAULT_TOLERANCE)
+            }
+            Marker::ArrowEquilateral | Marker::TriangleFilled => {
+                let (side, height) = (5.0 * weight, 5.0 * weight * 3f64.sqrt() / 2.0);
+                let (base, point) = match self {
+                    Marker::ArrowEquilateral => (tip - height * toward, tip),
+                    _ => (tip, tip + height * toward),
+                };
+                polygon(&[point, base + side / 2.0 * across, base - side / 2.0 * across])
+            }
+            Marker::CircleFilled => Circle::new(tip, 1.5 * weight).to_path(DEFAULT_TOLERANCE),
+            Marker::DiamondFilled => {
+                let half = 2.0 * weight;
+                polygon(&[tip + half * toward, tip + half * across, tip - half * toward, tip - half * across])
+            }
+        }
+    }
+}
+
+/// The direction `seg` is heading as it ends, if it goes anywhere.
+fn heading(seg: PathSeg) -> Option<Vec2> {
+    let end = seg.end();
+    let behind = match seg {
+        PathSeg::Line(l) => vec![l.p0],
+        PathSeg::Quad(q) => vec![q.p1, q.p0],
+        PathSeg::Cubic(c) => vec![c.p2, c.p1, c.p0],
+    };
+    behind.into_iter().map(|p| end - p).find(|v| v.hypot() > 1e-9).map(|v| v.normalize())
+}
+
+/// `seg` less its last `by` of length.
+fn trim_end(seg: PathSeg, by: f64) -> PathSeg {
+    if by <= 0.0 {
+        return seg;
+    }
+    let length = seg.arclen(ACCURACY);
+    let t = if length > by { seg.inv_arclen(length - by, ACCURACY) } else { 0.0 };
+    seg.subsegment(0.0..t)
+}
+
+fn subpaths(path: &BezPath) -> Vec<BezPath> {
+    let mut found: Vec<BezPath> = Vec::new();
+    for el in path.elements() {
+        match (el, found.last_mut()) {
+            (PathEl::MoveTo(_), _) | (_, None) => found.push(BezPath::from_vec(vec![*el])),
+            (_, Some(last)) => last.push(*el),
+        }
+    }
+    found
+}
+
+/// The stroke of `node` with its start and end markers, or `None` when it
+/// has none or no path to put them on.
+pub fn marked_stroke(node: &NodeData) -> Option<MarkedStroke> {
+    if node.start_marker.is_none() && node.end_marker.is_none() {
+        return None;
+    }
+    let vector_paths = node.fitted_vector_paths()?;
+    let weight = node.stroke_weight();
+    // Arrow lines are stroked like the path, but never dashed
+    let options = StrokeOptions {
+        stroke_cap: node.stroke_cap.clone(),
+        stroke_join: node.stroke_join.clone(),
+        stroke_miter_limit: node.stroke_miter_limit,
+        ..Default::default()
+    };
+
+    let mut heads = BezPath::new();
+    let mut add_head = |marker: Marker, tip: Point, toward: Vec2| {
+        // Heads wind the same way, so overlapping ones don't cancel out
+        let mut outline = marker.outline(tip, toward, weight, &options);
+        if outline.area() < 0.0 {
+            outline = outline.reverse_subpaths();
+        }
+        heads.extend(outline);
+    };
+
+    let paths = vector_paths
+        .iter()
+        .map(|path| {
+            let mut cut = BezPath::new();
+            for sub in subpaths(&to_bez_path(path)) {
+                let mut segments: Vec<PathSeg> = sub.segments().collect();
+                let closed = matches!(sub.elements().last(), Some(PathEl::ClosePath));
+                if closed || segments.is_empty() {
+                    cut.extend(sub);
+                    continue;
+                }
+                if let Some(marker) = node.start_marker {
+                    let first = segments[0].reverse();
+                    if let Some(toward) = heading(first) {
+                        add_head(marker, first.end(), toward);
+                        segments[0] = trim_end(first, marker.setback(weight)).reverse();
+                    }
+                }
+                if let Some(marker) = node.end_marker {
+                    let last = segments.len() - 1;
+                    if let Some(toward) = heading(segments[last]) {
+                        add_head(marker, segments[last].end(), toward);
+                        segments[last] = trim_end(segments[last], marker.setback(weight));
+                    }
+                }
+                cut.extend(BezPath::from_path_segments(segments.into_iter()));
+            }
+            from_bez_path(&cut, path.winding_rule)
+        })
+        .collect();
+    Some(MarkedStroke { paths, heads: from_bez_path(&heads, WindingRule::Nonzero) })
+}
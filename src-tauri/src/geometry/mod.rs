@@ -0,0 +1,275 @@
+pub mod boolean;
+pub mod markers;
+pub mod simplify;
+pub mod squircle;
+pub mod stroke;
+
+use crate::model::{PathCommand, VectorPath, WindingRule};
+use kurbo::{BezPath, CubicBez, PathEl, Point};
+use serde::{Deserialize, Serialize};
+
+/// Affine transform `[a, b, c, d, tx, ty]`, column-major like the frontend's
+/// `Matrix2x3`: `x' = a*x + c*y + tx`, `y' = b*x + d*y + ty`.
+pub type Matrix = [f64; 6];
+
+pub const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+pub fn multiply(m: &Matrix, n: &Matrix) -> Matrix {
+    [
+        m[0] * n[0] + m[2] * n[1],
+        m[1] * n[0] + m[3] * n[1],
+        m[0] * n[2] + m[2] * n[3],
+        m[1] * n[2] + m[3] * n[3],
+        m[0] * n[4] + m[2] * n[5] + m[4],
+        m[1] * n[4] + m[3] * n[5] + m[5],
+    ]
+}
+
+/// Translation, rotation (radians) and scale, matching `compose` in
+/// `src/core/math/matrix.ts`.
+pub fn compose(tx: f64, ty: f64, rotation: f64, sx: f64, sy: f64) -> Matrix {
+    let (sin, cos) = rotation.sin_cos();
+    [cos * sx, sin * sx, -sin * sy, cos * sy, tx, ty]
+}
+
+pub fn translate(tx: f64, ty: f64) -> Matrix {
+    [1.0, 0.0, 0.0, 1.0, tx, ty]
+}
+
+pub fn scale(sx: f64, sy: f64) -> Matrix {
+    [sx, 0.0, 0.0, sy, 0.0, 0.0]
+}
+
+pub fn invert(m: &Matrix) -> Option<Matrix> {
+    let det = m[0] * m[3] - m[1] * m[2];
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let inv = 1.0 / det;
+    Some([
+        m[3] * inv,
+        -m[1] * inv,
+        -m[2] * inv,
+        m[0] * inv,
+        (m[2] * m[5] - m[3] * m[4]) * inv,
+        (m[1] * m[4] - m[0] * m[5]) * inv,
+    ])
+}
+
+/// Average scale factor of `m`, for scaling lengths such as stroke widths.
+pub fn scale_factor(m: &Matrix) -> f64 {
+    (m[0] * m[3] - m[1] * m[2]).abs().sqrt()
+}
+
+pub fn apply(m: &Matrix, x: f64, y: f64) -> (f64, f64) {
+    (m[0] * x + m[2] * y + m[4], m[1] * x + m[3] * y + m[5])
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Rect {
+    pub fn new(x: f64, y: f64, width: f64, height: f64) -> Self {
+        Rect { x, y, width, height }
+    }
+
+    pub fn from_points(points: impl IntoIterator<Item = (f64, f64)>) -> Option<Self> {
+        let mut iter = points.into_iter();
+        let (x0, y0) = iter.next()?;
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (x0, y0, x0, y0);
+        for (x, y) in iter {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+        Some(Rect::new(min_x, min_y, max_x - min_x, max_y - min_y))
+    }
+
+    pub fn right(&self) -> f64 {
+        self.x + self.width
+    }
+
+    pub fn bottom(&self) -> f64 {
+        self.y + self.height
+    }
+
+    pub fn center(&self) -> (f64, f64) {
+        (self.x + self.width / 2.0, self.y + self.height / 2.0)
+    }
+
+    /// Whether the two overlap or touch.
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.x <= other.right() && other.x <= self.right() && self.y <= other.bottom() && other.y <= self.bottom()
+    }
+
+    pub fn contains(&self, other: &Rect) -> bool {
+        self.x <= other.x && self.y <= other.y && other.right() <= self.right() && other.bottom() <= self.bottom()
+    }
+
+    pub fn union(&self, other: &Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Rect::new(x, y, self.right().max(other.right()) - x, self.bottom().max(other.bottom()) - y)
+    }
+
+    pub fn transformed(&self, m: &Matrix) -> Rect {
+        let corners = [
+            apply(m, self.x, self.y),
+            apply(m, self.right(), self.y),
+            apply(m, self.x, self.bottom()),
+            apply(m, self.right(), self.bottom()),
+        ];
+        Rect::from_points(corners).unwrap_or(*self)
+    }
+}
+
+/// Bounds of the path's control polygon, which contains the curve itself.
+pub fn path_bounds(path: &VectorPath) -> Option<Rect> {
+    Rect::from_points(path.commands.iter().flat_map(|cmd| match *cmd {
+        PathCommand::MoveTo { x, y } | PathCommand::LineTo { x, y } => vec![(x, y)],
+        PathCommand::CurveTo { x1, y1, x2, y2, x, y } => vec![(x1, y1), (x2, y2), (x, y)],
+        PathCommand::ClosePath => vec![],
+    }))
+}
+
+pub fn transform_path(path: &VectorPath, m: &Matrix) -> VectorPath {
+    let commands = path
+        .commands
+        .iter()
+        .map(|cmd| match *cmd {
+            PathCommand::MoveTo { x, y } => {
+                let (x, y) = apply(m, x, y);
+                PathCommand::MoveTo { x, y }
+            }
+            PathCommand::LineTo { x, y } => {
+                let (x, y) = apply(m, x, y);
+                PathCommand::LineTo { x, y }
+            }
+            PathCommand::CurveTo { x1, y1, x2, y2, x, y } => {
+                let (x1, y1) = apply(m, x1, y1);
+                let (x2, y2) = apply(m, x2, y2);
+                let (x, y) = apply(m, x, y);
+                PathCommand::CurveTo { x1, y1, x2, y2, x, y }
+            }
+            PathCommand::ClosePath => PathCommand::ClosePath,
+        })
+        .collect();
+
+    VectorPath { winding_rule: path.winding_rule, commands }
+}
+
+/// A piece of a path between two anchor points.
+#[derive(Clone, Copy, Debug)]
+pub enum Segment {
+    Line(Point, Point),
+    Cubic(CubicBez),
+}
+
+/// How many equal steps in `t` approximate `curve` with lines no further
+/// than `tolerance` from it.
+pub fn flattening_steps(curve: &CubicBez, tolerance: f64) -> usize {
+    // The flattening error of n pieces falls with n² and is bounded by the
+    // control polygon's second differences
+    let dd = (curve.p0.to_vec2() - 2.0 * curve.p1.to_vec2() + curve.p2.to_vec2())
+        .hypot()
+        .max((curve.p1.to_vec2() - 2.0 * curve.p2.to_vec2() + curve.p3.to_vec2()).hypot());
+    ((0.75 * dd / tolerance).sqrt().ceil() as usize).clamp(1, 512)
+}
+
+/// The segments of `path`, in order. With `close`, subpaths left open get
+/// the line back to their start that filling would draw.
+pub fn path_segments(path: &VectorPath, close: bool) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let (mut start, mut current) = (Point::ZERO, Point::ZERO);
+    let mut open = false;
+    let close_subpath = |segments: &mut Vec<Segment>, current: Point, start: Point| {
+        if current != start {
+            segments.push(Segment::Line(current, start));
+        }
+    };
+    for cmd in &path.commands {
+        match *cmd {
+            PathCommand::MoveTo { x, y } => {
+                if open && close {
+                    close_subpath(&mut segments, current, start);
+                }
+                start = Point::new(x, y);
+                current = start;
+                open = false;
+            }
+            PathCommand::LineTo { x, y } => {
+                let to = Point::new(x, y);
+                segments.push(Segment::Line(current, to));
+                current = to;
+                open = true;
+            }
+            PathCommand::CurveTo { x1, y1, x2, y2, x, y } => {
+                let to = Point::new(x, y);
+                segments.push(Segment::Cubic(CubicBez::new(current, Point::new(x1, y1), Point::new(x2, y2), to)));
+                current = to;
+                open = true;
+            }
+            PathCommand::ClosePath => {
+                close_subpath(&mut segments, current, start);
+                current = start;
+                open = false;
+            }
+        }
+    }
+    if open && close {
+        close_subpath(&mut segments, current, start);
+    }
+    segments
+}
+
+pub fn to_bez_path(path: &VectorPath) -> BezPath {
+    let mut out = BezPath::new();
+    for cmd in &path.commands {
+        match *cmd {
+            PathCommand::MoveTo { x, y } => out.move_to((x, y)),
+            PathCommand::LineTo { x, y } => out.line_to((x, y)),
+            PathCommand::CurveTo { x1, y1, x2, y2, x, y } => out.curve_to((x1, y1), (x2, y2), (x, y)),
+            PathCommand::ClosePath => out.close_path(),
+        }
+    }
+    out
+}
+
+/// `path` as commands, with quadratic curves raised to cubics.
+pub fn from_bez_path(path: &BezPath, winding_rule: WindingRule) -> VectorPath {
+    let mut commands = Vec::new();
+    let mut last = Point::ZERO;
+
+    for el in path.elements() {
+        match *el {
+            PathEl::MoveTo(p) => {
+                last = p;
+                commands.push(PathCommand::MoveTo { x: p.x, y: p.y });
+            }
+            PathEl::LineTo(p) => {
+                last = p;
+                commands.push(PathCommand::LineTo { x: p.x, y: p.y });
+            }
+            PathEl::QuadTo(c, p) => {
+                // Degree-elevate to a cubic
+                let c1 = last + (c - last) * (2.0 / 3.0);
+                let c2 = p + (c - p) * (2.0 / 3.0);
+                last = p;
+                commands.push(PathCommand::CurveTo { x1: c1.x, y1: c1.y, x2: c2.x, y2: c2.y, x: p.x, y: p.y });
+            }
+            PathEl::CurveTo(c1, c2, p) => {
+                last = p;
+                commands.push(PathCommand::CurveTo { x1: c1.x, y1: c1.y, x2: c2.x, y2: c2.y, x: p.x, y: p.y });
+            }
+            PathEl::ClosePath => commands.push(PathCommand::ClosePath),
+        }
+    }
+
+    VectorPath { winding_rule, commands }
+}
@@ -0,0 +1,344 @@
+//! Fewer anchor points for paths drawn with the pencil or traced from
+//! images.
+//!
+//! Each subpath is flattened and reduced with Ramer–Douglas–Peucker. The
+//! points it keeps where the outline turns sharply stay as corners, and the
+//! runs between corners are refit with as few cubic curves as stay within
+//! the tolerance of the original outline.
+
+use super::flattening_steps;
+use crate::model::{PathCommand, VectorPath};
+use kurbo::{CubicBez, ParamCurve, ParamCurveDeriv, Point, Vec2};
+use tauri::command;
+
+/// Turn in degrees beyond which a point is kept as a corner, as in
+/// `simplifyPath` in `src/core/geometry/path-operations.ts`.
+const DEFAULT_CORNER_THRESHOLD: f64 = 30.0;
+/// Newton steps tried on a fit that is close to the tolerance before it is
+/// split instead.
+const MAX_REPARAMETERIZE: usize = 4;
+/// How far either side of a point its tangent is measured over, in
+/// multiples of the tolerance.
+const TANGENT_REACH: f64 = 4.0;
+
+/// The subpaths of `path` as polylines within `tolerance` of it, and
+/// whether each is closed.
+fn subpath_points(path: &VectorPath, tolerance: f64) -> Vec<(Vec<Point>, bool)> {
+    let mut subpaths: Vec<(Vec<Point>, bool)> = Vec::new();
+    let mut start = Point::ZERO;
+    let push = |subpaths: &mut Vec<(Vec<Point>, bool)>, start: Point, p: Point| {
+        if subpaths.last().is_none_or(|(_, closed)| *closed) {
+            subpaths.push((vec![start], false));
+        }
+        let (points, _) = subpaths.last_mut().expect("subpath was just started");
+        if points.last() != Some(&p) {
+            points.push(p);
+        }
+    };
+    for cmd in &path.commands {
+        match *cmd {
+            PathCommand::MoveTo { x, y } => {
+                start = Point::new(x, y);
+                subpaths.push((vec![start], false));
+            }
+            PathCommand::LineTo { x, y } => push(&mut subpaths, start, Point::new(x, y)),
+            PathCommand::CurveTo { x1, y1, x2, y2, x, y } => {
+                let from = subpaths.last().filter(|(_, closed)| !closed).and_then(|(points, _)| points.last().copied());
+                let curve = CubicBez::new(from.unwrap_or(start), Point::new(x1, y1), Point::new(x2, y2), Point::new(x, y));
+                let n = flattening_steps(&curve, tolerance);
+                for i in 1..=n {
+                    push(&mut subpaths, start, if i == n { curve.p3 } else { curve.eval(i as f64 / n as f64) });
+                }
+            }
+            PathCommand::ClosePath => {
+                if let Some((points, closed)) = subpaths.last_mut().filter(|(_, closed)| !closed) {
+                    if points.last() != Some(&start) {
+                        points.push(start);
+                    }
+                    *closed = true;
+                }
+            }
+        }
+    }
+    subpaths
+}
+
+fn distance_to_segment(p: Point, a: Point, b: Point) -> f64 {
+    let ab = b - a;
+    let length_sq = ab.hypot2();
+    if length_sq == 0.0 {
+        return (p - a).hypot();
+    }
+    let t = ((p - a).dot(ab) / length_sq).clamp(0.0, 1.0);
+    (p - (a + t * ab)).hypot()
+}
+
+/// Indices of the points Ramer–Douglas–Peucker keeps, in order, so that no
+/// point is further than `tolerance` from the polyline through them.
+fn reduce(points: &[Point], tolerance: f64) -> Vec<usize> {
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    // Worked from a stack, as traced outlines can run to many thousands of
+    // points
+    let mut spans = vec![(0, points.len() - 1)];
+    while let Some((first, last)) = spans.pop() {
+        let farthest = (first + 1..last)
+            .map(|i| (i, distance_to_segment(points[i], points[first], points[last])))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((i, _)) = farthest.filter(|(_, distance)| *distance > tolerance) {
+            keep[i] = true;
+            spans.push((first, i));
+            spans.push((i, last));
+        }
+    }
+    (0..points.len()).filter(|i| keep[*i]).collect()
+}
+
+/// Unit vector from `a` towards `b`, or zero where they coincide.
+fn direction(a: Point, b: Point) -> Vec2 {
+    let v = b - a;
+    let length = v.hypot();
+    if length > 0.0 {
+        v / length
+    } else {
+        Vec2::ZERO
+    }
+}
+
+/// The first of `points` at least `reach` from `from`, or the last if none
+/// is, for estimating tangents over a stretch long enough to even out
+/// jitter in hand-drawn input.
+fn reach_point<'a>(mut points: impl Iterator<Item = &'a Point>, from: Point, reach: f64) -> Point {
+    let mut last = from;
+    for p in points.by_ref() {
+        last = *p;
+        if (*p - from).hypot() >= reach {
+            break;
+        }
+    }
+    last
+}
+
+/// Whether the polyline turns by more than `threshold` radians at point
+/// `i`, measured over `reach` either side so that jitter is not taken for
+/// corners. Closed polylines wrap around.
+fn is_corner(points: &[Point], i: usize, closed: bool, reach: f64, threshold: f64) -> bool {
+    let at = points[i];
+    let wrap = if closed { points.len() - 1 } else { 0 };
+    let back = reach_point(points[..i].iter().rev().chain(points[..wrap].iter().rev()), at, reach);
+    let forward = reach_point(points[i + 1..].iter().chain(&points[1.min(wrap)..wrap]), at, reach);
+    let (a, b) = (at - back, forward - at);
+    a.cross(b).atan2(a.dot(b)).abs() > threshold
+}
+
+/// Where along the polyline each point lies, from 0 at the first to 1 at
+/// the last.
+fn chord_parameters(points: &[Point]) -> Vec<f64> {
+    let mut u = Vec::with_capacity(points.len());
+    let mut length = 0.0;
+    u.push(0.0);
+    for pair in points.windows(2) {
+        length += (pair[1] - pair[0]).hypot();
+        u.push(length);
+    }
+    if length > 0.0 {
+        u.iter_mut().for_each(|t| *t /= length);
+    }
+    u
+}
+
+/// The curve from the first to the last point, leaving along `start_tangent`
+/// and arriving against `end_tangent`, whose handle lengths fit the points
+/// at parameters `u` best in the least-squares sense.
+fn fit_handles(points: &[Point], u: &[f64], start_tangent: Vec2, end_tangent: Vec2) -> CubicBez {
+    let (first, last) = (points[0], points[points.len() - 1]);
+    let (mut c00, mut c01, mut c11, mut x0, mut x1) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for (p, &t) in points.iter().zip(u) {
+        let s = 1.0 - t;
+        let (b0, b1, b2, b3) = (s * s * s, 3.0 * s * s * t, 3.0 * s * t * t, t * t * t);
+        let (a0, a1) = (start_tangent * b1, end_tangent * b2);
+        let rest = *p - (first.to_vec2() * (b0 + b1) + last.to_vec2() * (b2 + b3));
+        c00 += a0.dot(a0);
+        c01 += a0.dot(a1);
+        c11 += a1.dot(a1);
+        x0 += a0.dot(rest.to_vec2());
+        x1 += a1.dot(rest.to_vec2());
+    }
+
+    let chord = (last - first).hypot();
+    let det = c00 * c11 - c01 * c01;
+    let (mut alpha0, mut alpha1) = if det.abs() > 1e-12 {
+        ((x0 * c11 - x1 * c01) / det, (c00 * x1 - c01 * x0) / det)
+    } else {
+        (0.0, 0.0)
+    };
+    // Handles that point backwards or vanish make loops and cusps, so fall
+    // back to the usual third of the chord
+    if alpha0 < 1e-6 * chord || alpha1 < 1e-6 * chord {
+        alpha0 = chord / 3.0;
+        alpha1 = chord / 3.0;
+    }
+    CubicBez::new(first, first + start_tangent * alpha0, last + end_tangent * alpha1, last)
+}
+
+/// The largest squared distance between the points and the curve at their
+/// parameters, and the point it is at.
+fn max_error(points: &[Point], u: &[f64], curve: &CubicBez) -> (f64, usize) {
+    let mut worst = (0.0, points.len() / 2);
+    for i in 1..points.len() - 1 {
+        let error = (curve.eval(u[i]) - points[i]).hypot2();
+        if error > worst.0 {
+            worst = (error, i);
+        }
+    }
+    worst
+}
+
+/// Parameters moved one Newton step towards where the curve comes closest
+/// to each point.
+fn reparameterize(points: &[Point], u: &mut [f64], curve: &CubicBez) {
+    let d1 = curve.deriv();
+    let d2 = d1.deriv();
+    for (p, t) in points.iter().zip(u.iter_mut()) {
+        let offset = curve.eval(*t) - *p;
+        let (first, second) = (d1.eval(*t).to_vec2(), d2.eval(*t).to_vec2());
+        let denominator = first.dot(first) + offset.dot(second);
+        if denominator.abs() > 1e-12 {
+            *t = (*t - offset.dot(first) / denominator).clamp(0.0, 1.0);
+        }
+    }
+}
+
+/// Curves through `points` within `tolerance`, split where one curve will
+/// not do, appended to `out`. Curves that are barely bent come out as lines.
+fn fit_curves(points: &[Point], start_tangent: Vec2, end_tangent: Vec2, tolerance: f64, out: &mut Vec<PathCommand>) {
+    let last = points[points.len() - 1];
+    let mut u = chord_parameters(points);
+    let mut curve = fit_handles(points, &u, start_tangent, end_tangent);
+    let (mut error, mut split) = max_error(points, &u, &curve);
+    let limit = tolerance * tolerance;
+    // Fits that are far off are split in the middle rather than at their
+    // worst point, which tends to lie near an end and leave slivers
+    let near = error < 16.0 * limit;
+    if error > limit && near {
+        for _ in 0..MAX_REPARAMETERIZE {
+            reparameterize(points, &mut u, &curve);
+            curve = fit_handles(points, &u, start_tangent, end_tangent);
+            (error, split) = max_error(points, &u, &curve);
+            if error <= limit {
+                break;
+            }
+        }
+    }
+
+    if error <= limit || points.len() < 3 {
+        let flat = distance_to_segment(curve.p1, curve.p0, curve.p3).max(distance_to_segment(curve.p2, curve.p0, curve.p3));
+        out.push(if flat <= tolerance {
+            PathCommand::LineTo { x: last.x, y: last.y }
+        } else {
+            PathCommand::CurveTo { x1: curve.p1.x, y1: curve.p1.y, x2: curve.p2.x, y2: curve.p2.y, x: last.x, y: last.y }
+        });
+        return;
+    }
+
+    // The curves on either side of the split share its tangent, so the
+    // join stays smooth
+    let split = if near { split } else { u.iter().position(|t| *t >= 0.5).unwrap_or(split) };
+    let split = split.clamp(1, points.len() - 2);
+    let at = points[split];
+    let reach = TANGENT_REACH * tolerance;
+    let tangent = direction(reach_point(points[split..].iter(), at, reach), reach_point(points[..split].iter().rev(), at, reach));
+    fit_curves(&points[..=split], start_tangent, tangent, tolerance, out);
+    fit_curves(&points[split..], -tangent, end_tangent, tolerance, out);
+}
+
+/// `points` simplified, as commands from a move to its start, which for a
+/// closed outline may be another of its points.
+fn simplify_points(points: &[Point], closed: bool, tolerance: f64, threshold: f64, out: &mut Vec<PathCommand>) {
+    let reach = TANGENT_REACH * tolerance;
+    let mut keep = reduce(points, tolerance);
+
+    // A closed outline starts over from its first corner, so that the
+    // point where it closes is not left as a kink
+    let mut points = points.to_vec();
+    let mut smooth_start = false;
+    if closed && keep.len() > 3 && !is_corner(&points, 0, closed, reach, threshold) {
+        match keep[1..keep.len() - 1].iter().find(|i| is_corner(&points, **i, closed, reach, threshold)) {
+            Some(&at) => {
+                points = points[at..].iter().chain(&points[1..=at]).copied().collect();
+                keep = reduce(&points, tolerance);
+            }
+            None => smooth_start = true,
+        }
+    }
+
+    let start = points[keep[0]];
+    out.push(PathCommand::MoveTo { x: start.x, y: start.y });
+    let mut corners = vec![0];
+    corners.extend((1..keep.len() - 1).filter(|k| is_corner(&points, keep[*k], closed, reach, threshold)));
+    corners.push(keep.len() - 1);
+    let end = keep.len() - 1;
+    for run in corners.windows(2) {
+        let (a, b) = (run[0], run[1]);
+        let to = points[keep[b]];
+        if b - a == 1 {
+            // Every point between two that are kept is within the
+            // tolerance of the line between them
+            out.push(PathCommand::LineTo { x: to.x, y: to.y });
+            continue;
+        }
+        let run = &points[keep[a]..=keep[b]];
+        let (first, last) = (run[0], run[run.len() - 1]);
+        let start_tangent = if a == 0 && smooth_start {
+            direction(reach_point(points.iter().rev(), first, reach), reach_point(points.iter(), first, reach))
+        } else {
+            direction(first, reach_point(run.iter(), first, reach))
+        };
+        let end_tangent = if b == end && smooth_start {
+            -start_tangent
+        } else {
+            direction(last, reach_point(run.iter().rev(), last, reach))
+        };
+        fit_curves(run, start_tangent, end_tangent, tolerance, out);
+    }
+}
+
+/// `path` with fewer anchor points, no further than `tolerance` from it.
+/// Points where it turns by more than `corner_threshold` degrees stay as
+/// corners.
+pub fn simplify(path: &VectorPath, tolerance: f64, corner_threshold: f64) -> VectorPath {
+    let threshold = corner_threshold.to_radians();
+    let mut commands = Vec::new();
+    for (points, closed) in subpath_points(path, tolerance * 0.1) {
+        if points.len() < 2 {
+            continue;
+        }
+        let start = commands.len();
+        simplify_points(&points, closed, tolerance, threshold, &mut commands);
+        if closed {
+            // Closing draws the last line back to the start
+            if commands.len() - start > 2 && matches!(commands.last(), Some(PathCommand::LineTo { .. })) {
+                commands.pop();
+            }
+            commands.push(PathCommand::ClosePath);
+        }
+    }
+    VectorPath { winding_rule: path.winding_rule, commands }
+}
+
+/// Reduce the anchor points of `path`, such as pencil strokes or traced
+/// outlines, keeping it within `tolerance` of the original in path units.
+/// `corner_threshold` is the turn in degrees beyond which a point is kept
+/// as a sharp corner rather than smoothed into a curve (default 30).
+#[command(async)]
+pub fn simplify_path(path: VectorPath, tolerance: f64, corner_threshold: Option<f64>) -> Result<VectorPath, String> {
+    if !(tolerance.is_finite() && tolerance > 0.0) {
+        return Err(format!("Invalid tolerance: {}", tolerance));
+    }
+    let corner_threshold = corner_threshold.unwrap_or(DEFAULT_CORNER_THRESHOLD);
+    if !(0.0..=180.0).contains(&corner_threshold) {
+        return Err(format!("Invalid corner threshold: {}", corner_threshold));
+    }
+    Ok(simplify(&path, tolerance, corner_threshold))
+}
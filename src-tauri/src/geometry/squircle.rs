@@ -0,0 +1,135 @@
+//! Rounded rectangles with smoothed corners, where the curvature eases in
+//! from the straight edges rather than jumping to that of a circular arc,
+//! as iOS draws continuous corners and Figma draws corner smoothing.
+//! Corners are built as Figma describes them: a shorter arc in the middle,
+//! with a cubic either side that stretches into the edge.
+
+use crate::model::{PathCommand, VectorPath, WindingRule};
+use kurbo::{Point, Vec2};
+use tauri::command;
+
+/// One corner, relative to the corner point as if it were the top right,
+/// arriving along the top edge and leaving down the right one.
+struct Corner {
+    radius: f64,
+    /// How far along each edge the corner starts.
+    p: f64,
+    a: f64,
+    b: f64,
+    c: f64,
+    d: f64,
+    /// Run and rise of the arc in the middle.
+    arc: f64,
+}
+
+impl Corner {
+    /// `smoothing` is from 0 to 1. Smoothing stretches the corner further
+    /// along the edges, and gives way where the edges are too short for it.
+    fn new(radius: f64, smoothing: f64, budget: f64) -> Self {
+        let radius = radius.min(budget);
+        let smoothing = if radius > 0.0 { smoothing.min(budget / radius - 1.0).max(0.0) } else { 0.0 };
+        let p = ((1.0 + smoothing) * radius).min(budget);
+
+        let arc_measure = 90.0 * (1.0 - smoothing);
+        let arc = (arc_measure / 2.0).to_radians().sin() * radius * std::f64::consts::SQRT_2;
+        let alpha = (90.0 - arc_measure) / 2.0;
+        let p3_to_p4 = radius * (alpha / 2.0).to_radians().tan();
+        let beta = (45.0 * smoothing).to_radians();
+        let c = p3_to_p4 * beta.cos();
+        let d = c * beta.tan();
+        let b = (p - arc - c - d) / 3.0;
+        Corner { radius, p, a: 2.0 * b, b, c, d, arc }
+    }
+
+    /// Append the corner at `corner` to `commands`, with `map` turning a
+    /// point given as for the top right into this corner's orientation.
+    fn draw(&self, commands: &mut Vec<PathCommand>, corner: Point, map: impl Fn(Vec2) -> Vec2) {
+        let at = |u: f64, v: f64| corner + map(Vec2::new(u, v));
+        let curve = |c1: Point, c2: Point, p: Point| PathCommand::CurveTo { x1: c1.x, y1: c1.y, x2: c2.x, y2: c2.y, x: p.x, y: p.y };
+        let line = |p: Point| PathCommand::LineTo { x: p.x, y: p.y };
+        let Corner { radius, p, a, b, c, d, arc } = *self;
+        if radius <= 0.0 {
+            commands.push(line(corner));
+            return;
+        }
+
+        let start = -p;
+        commands.push(line(at(start, 0.0)));
+        // Without smoothing the arc is the whole corner
+        let smoothed = p - arc > 1e-9;
+        let p1 = (start + a + b + c, d);
+        if smoothed {
+            commands.push(curve(at(start + a, 0.0), at(start + a + b, 0.0), at(p1.0, p1.1)));
+        }
+
+        // The arc, about the center of the circle both edges touch
+        let p2 = (p1.0 + arc, p1.1 + arc);
+        if arc > 1e-9 {
+            let center = (-radius, radius);
+            let theta0 = (p1.1 - center.1).atan2(p1.0 - center.0);
+            let theta1 = (p2.1 - center.1).atan2(p2.0 - center.0);
+            let k = 4.0 / 3.0 * ((theta1 - theta0) / 4.0).tan() * radius;
+            commands.push(curve(
+                at(p1.0 - k * theta0.sin(), p1.1 + k * theta0.cos()),
+                at(p2.0 + k * theta1.sin(), p2.1 - k * theta1.cos()),
+                at(p2.0, p2.1),
+            ));
+        }
+
+        if smoothed {
+            commands.push(curve(at(p2.0 + d, p2.1 + c), at(p2.0 + d, p2.1 + b + c), at(p2.0 + d, p2.1 + a + b + c)));
+        }
+    }
+}
+
+/// A `width` × `height` rectangle with corners of `radii`, top left first
+/// and clockwise, smoothed by `smoothing` from 0 to 1.
+pub fn smooth_rect(width: f64, height: f64, radii: [f64; 4], smoothing: f64) -> VectorPath {
+    let budget = width.min(height) / 2.0;
+    let [top_left, top_right, bottom_right, bottom_left] = radii.map(|r| Corner::new(r, smoothing, budget));
+
+    let mut commands = vec![PathCommand::MoveTo { x: width - top_right.p, y: 0.0 }];
+    top_right.draw(&mut commands, Point::new(width, 0.0), |v| v);
+    bottom_right.draw(&mut commands, Point::new(width, height), |v| Vec2::new(-v.y, v.x));
+    bottom_left.draw(&mut commands, Point::new(0.0, height), |v| -v);
+    top_left.draw(&mut commands, Point::new(0.0, 0.0), |v| Vec2::new(v.y, -v.x));
+    commands.push(PathCommand::ClosePath);
+
+    // Drop the lines of no length left between corners that meet
+    let mut last = Point::ZERO;
+    commands.retain(|cmd| {
+        let to = match *cmd {
+            PathCommand::MoveTo { x, y } | PathCommand::LineTo { x, y } | PathCommand::CurveTo { x, y, .. } => Point::new(x, y),
+            PathCommand::ClosePath => return true,
+        };
+        let keep = !matches!(cmd, PathCommand::LineTo { .. }) || to.distance(last) > 1e-9;
+        last = to;
+        keep
+    });
+    VectorPath { winding_rule: WindingRule::Nonzero, commands }
+}
+
+/// A rounded rectangle with continuous corners, as a vector path. Corners
+/// are `corner_radius`, or `corner_radii` from the top left clockwise, and
+/// `corner_smoothing` is a percentage: 0 gives circular corners, and about
+/// 60 matches iOS.
+#[command(async)]
+pub fn smooth_corner_path(
+    width: f64,
+    height: f64,
+    corner_radius: f64,
+    corner_smoothing: f64,
+    corner_radii: Option<[f64; 4]>,
+) -> Result<VectorPath, String> {
+    if !(width.is_finite() && height.is_finite() && width > 0.0 && height > 0.0) {
+        return Err(format!("Invalid size: {}×{}", width, height));
+    }
+    if !(0.0..=100.0).contains(&corner_smoothing) {
+        return Err(format!("Invalid corner smoothing: {}", corner_smoothing));
+    }
+    let radii = corner_radii.unwrap_or([corner_radius; 4]);
+    if let Some(r) = radii.iter().find(|r| !(r.is_finite() && **r >= 0.0)) {
+        return Err(format!("Invalid corner radius: {}", r));
+    }
+    Ok(smooth_rect(width, height, radii, corner_smoothing / 100.0))
+}
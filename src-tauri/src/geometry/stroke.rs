@@ -0,0 +1,98 @@
+//! Strokes expanded into the filled outlines they cover, for boolean
+//! operations on strokes and for formats that can only fill.
+
+use super::boolean::{combine, BooleanOp, DEFAULT_TOLERANCE};
+use super::{from_bez_path, to_bez_path};
+use crate::model::{VectorPath, WindingRule};
+use kurbo::{Cap, Join, Stroke, StrokeOpts};
+use serde::Deserialize;
+use tauri::command;
+
+/// How a path is stroked, with the same fields and defaults as a node's
+/// stroke properties, so a node can be passed as it is.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct StrokeOptions {
+    pub stroke_weight: Option<f64>,
+    /// `CENTER`, `INSIDE` or `OUTSIDE`.
+    pub stroke_align: Option<String>,
+    pub stroke_cap: Option<String>,
+    pub stroke_join: Option<String>,
+    pub stroke_miter_limit: Option<f64>,
+    pub dash_pattern: Option<Vec<f64>>,
+    pub dash_offset: Option<f64>,
+}
+
+/// The dashes and gaps `pattern` alternates between, repeated to an even
+/// length so each pass starts on a dash. Patterns with a bad length or
+/// nothing to repeat give `None`, to be drawn solid, the same in every
+/// output.
+pub fn dash_array(pattern: &[f64]) -> Option<Vec<f64>> {
+    if !(pattern.iter().all(|d| d.is_finite() && *d >= 0.0) && pattern.iter().sum::<f64>() > 0.0) {
+        return None;
+    }
+    let mut dashes = pattern.to_vec();
+    if dashes.len() % 2 == 1 {
+        dashes.extend_from_within(..);
+    }
+    Some(dashes)
+}
+
+impl StrokeOptions {
+    pub fn style(&self, width: f64) -> Stroke {
+        let cap = match self.stroke_cap.as_deref() {
+            Some("ROUND") => Cap::Round,
+            Some("SQUARE") => Cap::Square,
+            _ => Cap::Butt,
+        };
+        let join = match self.stroke_join.as_deref() {
+            Some("ROUND") => Join::Round,
+            Some("BEVEL") => Join::Bevel,
+            _ => Join::Miter,
+        };
+        let style = Stroke::new(width)
+            .with_caps(cap)
+            .with_join(join)
+            .with_miter_limit(self.stroke_miter_limit.unwrap_or(4.0));
+        match self.dash_pattern.as_deref().and_then(dash_array) {
+            Some(dashes) => style.with_dashes(self.dash_offset.unwrap_or(0.0), dashes),
+            None => style,
+        }
+    }
+}
+
+/// The outline `path` covers when stroked with `options`, as one path that
+/// fills the same area under either winding rule.
+pub fn outline(path: &VectorPath, options: &StrokeOptions, tolerance: f64) -> VectorPath {
+    let weight = options.stroke_weight.unwrap_or(1.0);
+    let align = options.stroke_align.as_deref().unwrap_or("CENTER");
+    // Inside and outside strokes are the outline of one twice as wide, cut
+    // to the side of the fill they are on
+    let width = if matches!(align, "INSIDE" | "OUTSIDE") { weight * 2.0 } else { weight };
+    let expanded = kurbo::stroke(to_bez_path(path).elements().iter().copied(), &options.style(width), &StrokeOpts::default(), tolerance);
+    let expanded = from_bez_path(&expanded, WindingRule::Nonzero);
+
+    // The expanded stroke overlaps itself where the path crosses itself and
+    // at the inside of each join, which combining merges away
+    match align {
+        "INSIDE" => combine(&[expanded, path.clone()], BooleanOp::Intersect, tolerance),
+        "OUTSIDE" => combine(&[expanded, path.clone()], BooleanOp::Subtract, tolerance),
+        _ => combine(&[expanded], BooleanOp::Union, tolerance),
+    }
+}
+
+/// Convert the stroke of `path` into a filled outline, honoring its caps,
+/// joins, miter limit, dashes and alignment. `tolerance` is the largest
+/// distance the outline may be from the exact one, in path units.
+#[command(async)]
+pub fn outline_stroke(path: VectorPath, stroke_options: StrokeOptions, tolerance: Option<f64>) -> Result<VectorPath, String> {
+    let weight = stroke_options.stroke_weight.unwrap_or(1.0);
+    if !(weight.is_finite() && weight > 0.0) {
+        return Err(format!("Invalid stroke weight: {}", weight));
+    }
+    let tolerance = tolerance.unwrap_or(DEFAULT_TOLERANCE);
+    if !(tolerance.is_finite() && tolerance > 0.0) {
+        return Err(format!("Invalid tolerance: {}", tolerance));
+    }
+    Ok(outline(&path, &stroke_options, tolerance))
+}
@@ -0,0 +1,236 @@
+use crate::atomic::write_atomic;
+use crate::bundle::now_millis;
+use crate::locks::FileLocks;
+use crate::mapped::MappedBundles;
+use crate::watcher::FileWatcher;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{command, State};
+
+const INDEX_FILE: &str = "versions.json";
+const CHUNKS_DIR: &str = "chunks";
+
+// Content-defined chunk bounds. Boundaries depend on the bytes rather than
+// offsets, so an edit only changes the chunks around it.
+const MIN_CHUNK: usize = 2 * 1024;
+const MAX_CHUNK: usize = 64 * 1024;
+const CHUNK_MASK: u64 = (1 << 13) - 1;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionEntry {
+    pub id: String,
+    pub label: Option<String>,
+    pub created_at: u64,
+    pub size: u64,
+    pub hash: String,
+    pub chunks: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct VersionIndex {
+    versions: Vec<VersionEntry>,
+}
+
+/// Version metadata returned to the frontend, without the chunk list.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionInfo {
+    pub id: String,
+    pub label: Option<String>,
+    pub created_at: u64,
+    pub size: u64,
+}
+
+impl From<&VersionEntry> for VersionInfo {
+    fn from(v: &VersionEntry) -> Self {
+        VersionInfo { id: v.id.clone(), label: v.label.clone(), created_at: v.created_at, size: v.size }
+    }
+}
+
+/// `dir/design.dlibre` keeps its history in `dir/.design.dlibre.history/`.
+pub fn history_dir(document: &Path) -> Result<PathBuf, String> {
+    let name = document
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| format!("Invalid document path: {}", document.display()))?;
+    let parent = document.parent().unwrap_or_else(|| Path::new("."));
+
+    Ok(parent.join(format!(".{}.history", name)))
+}
+
+pub fn hex_digest(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn gear_table() -> [u64; 256] {
+    // splitmix64 gives a fixed, well-mixed table without shipping 2KB of constants
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+    for slot in table.iter_mut() {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        *slot = z ^ (z >> 31);
+    }
+    table
+}
+
+/// Split `data` into content-defined chunks using a gear rolling hash.
+pub fn chunk_boundaries(data: &[u8]) -> Vec<&[u8]> {
+    let gear = gear_table();
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut hash: u64 = 0;
+
+    for (i, byte) in data.iter().enumerate() {
+        hash = (hash << 1).wrapping_add(gear[*byte as usize]);
+        let len = i + 1 - start;
+        if (len >= MIN_CHUNK && hash & CHUNK_MASK == 0) || len >= MAX_CHUNK {
+            chunks.push(&data[start..=i]);
+            start = i + 1;
+            hash = 0;
+        }
+    }
+
+    if start < data.len() {
+        chunks.push(&data[start..]);
+    }
+    chunks
+}
+
+fn load_index(dir: &Path) -> Result<VersionIndex, String> {
+    match fs::read(dir.join(INDEX_FILE)) {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .map_err(|e| format!("Invalid version history index: {}", e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(VersionIndex::default()),
+        Err(e) => Err(format!("Failed to read version history: {}", e)),
+    }
+}
+
+fn save_index(dir: &Path, index: &VersionIndex) -> Result<(), String> {
+    let json = serde_json::to_vec_pretty(index)
+        .map_err(|e| format!("Failed to encode version history: {}", e))?;
+    write_atomic(&dir.join(INDEX_FILE).to_string_lossy(), &json)?;
+    Ok(())
+}
+
+/// Store `data` as a new version, writing only chunks not already present.
+pub fn store_version(document: &Path, data: &[u8], label: Option<String>) -> Result<VersionEntry, String> {
+    let dir = history_dir(document)?;
+    let chunk_dir = dir.join(CHUNKS_DIR);
+    fs::create_dir_all(&chunk_dir)
+        .map_err(|e| format!("Failed to create history directory: {}", e))?;
+
+    let mut chunks = Vec::new();
+    for chunk in chunk_boundaries(data) {
+        let hash = hex_digest(chunk);
+        let chunk_path = chunk_dir.join(&hash);
+        if !chunk_path.exists() {
+            write_atomic(&chunk_path.to_string_lossy(), chunk)?;
+        }
+        chunks.push(hash);
+    }
+
+    let mut index = load_index(&dir)?;
+    let next = index
+        .versions
+        .iter()
+        .filter_map(|v| v.id.strip_prefix('v').and_then(|n| n.parse::<u64>().ok()))
+        .max()
+        .unwrap_or(0)
+        + 1;
+
+    let entry = VersionEntry {
+        id: format!("v{}", next),
+        label,
+        created_at: now_millis(),
+        size: data.len() as u64,
+        hash: hex_digest(data),
+        chunks,
+    };
+    index.versions.push(entry.clone());
+    save_index(&dir, &index)?;
+
+    Ok(entry)
+}
+
+/// Reassemble a stored version and verify it against its recorded hash.
+pub fn load_version(document: &Path, version_id: &str) -> Result<Vec<u8>, String> {
+    let dir = history_dir(document)?;
+    let index = load_index(&dir)?;
+    let entry = index
+        .versions
+        .iter()
+        .find(|v| v.id == version_id)
+        .ok_or_else(|| format!("Unknown version: {}", version_id))?;
+
+    let mut data = Vec::with_capacity(entry.size as usize);
+    for hash in &entry.chunks {
+        let chunk = fs::read(dir.join(CHUNKS_DIR).join(hash))
+            .map_err(|e| format!("Missing history chunk {}: {}", hash, e))?;
+        data.extend_from_slice(&chunk);
+    }
+
+    if hex_digest(&data) != entry.hash {
+        return Err(format!("Version {} is corrupted", version_id));
+    }
+    Ok(data)
+}
+
+/// Create a checkpoint from `content`, or from the file on disk when omitted.
+#[command(async)]
+pub fn create_checkpoint(path: String, label: Option<String>, content: Option<String>) -> Result<VersionInfo, String> {
+    let data = match content {
+        Some(content) => content.into_bytes(),
+        None => fs::read(&path).map_err(|e| format!("Failed to read file: {}", e))?,
+    };
+
+    store_version(Path::new(&path), &data, label).map(|v| VersionInfo::from(&v))
+}
+
+#[command(async)]
+pub fn list_versions(path: String) -> Result<Vec<VersionInfo>, String> {
+    let index = load_index(&history_dir(Path::new(&path))?)?;
+    let mut versions: Vec<VersionInfo> = index.versions.iter().map(VersionInfo::from).collect();
+    versions.sort_by_key(|v| std::cmp::Reverse(v.created_at));
+
+    Ok(versions)
+}
+
+/// Replace the document on disk with a stored version; the frontend reloads it
+/// afterwards. The current contents are checkpointed first so a restore can
+/// itself be undone.
+#[command(async)]
+pub fn restore_version(
+    watcher: State<'_, FileWatcher>,
+    locks: State<'_, FileLocks>,
+    mapped: State<'_, MappedBundles>,
+    path: String,
+    version_id: String,
+) -> Result<(), String> {
+    locks.check_writable(&path)?;
+    let document = Path::new(&path);
+    let data = load_version(document, &version_id)?;
+
+    if let Ok(current) = fs::read(document) {
+        store_version(document, &current, Some(format!("Before restoring {}", version_id)))?;
+    }
+    watcher.acknowledge(&path, &data);
+    write_atomic(&path, &data)?;
+    mapped.forget(&path);
+
+    Ok(())
+}
+
+#[command(async)]
+pub fn export_version(path: String, version_id: String, destination: String) -> Result<(), String> {
+    let data = load_version(Path::new(&path), &version_id)?;
+    write_atomic(&destination, &data)?;
+
+    Ok(())
+}
@@ -0,0 +1,540 @@
+//! Icons from Iconify, the open collection of icon sets such as Material
+//! Design Icons, Lucide and Tabler: listing the sets, searching them,
+//! and turning icons into SVG documents `import_svg_data` reads as it
+//! reads any other SVG. Whole sets can be downloaded, after which they are
+//! searched and drawn without a connection.
+
+use crate::atomic::write_atomic;
+use crate::bundle::now_millis;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use tauri::{command, State};
+use tauri_plugin_http::reqwest::{Client, Url};
+
+const API_URL: &str = "https://api.iconify.design";
+/// Complete sets, as the Iconify API is built from.
+const SET_URL: &str = "https://raw.githubusercontent.com/iconify/icon-sets/master/json";
+const COLLECTIONS_FILE: &str = "collections.json";
+const SET_FILE: &str = "icons.json";
+const RECORD_FILE: &str = "set.json";
+/// A cached collection list younger than this is used without asking.
+const COLLECTIONS_MAX_AGE_MS: u64 = 24 * 60 * 60 * 1000;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+/// Iconify's own default size for icons that don't give one.
+const DEFAULT_SIZE: f64 = 16.0;
+const DEFAULT_SEARCH_LIMIT: usize = 64;
+/// Most icons the search API returns at once.
+const MAX_SEARCH_LIMIT: usize = 999;
+/// How many aliases deep an icon may be before it is taken as a loop.
+const MAX_ALIAS_DEPTH: usize = 8;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct IconCollection {
+    /// What icons of the set are named by, as in `mdi:home`.
+    pub prefix: String,
+    pub name: String,
+    pub total: u32,
+    pub author: Option<String>,
+    /// License title, such as `Apache 2.0`.
+    pub license: Option<String>,
+    pub license_spdx: Option<String>,
+    pub category: Option<String>,
+    /// A few icon names to show for the set.
+    pub samples: Vec<String>,
+    /// Whether the icons have colors of their own rather than taking one.
+    pub multicolor: bool,
+    /// Filled in when listing; not cached.
+    #[serde(skip_deserializing, default)]
+    pub downloaded: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CachedCollections {
+    fetched_at: u64,
+    collections: Vec<IconCollection>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IconCollections {
+    pub collections: Vec<IconCollection>,
+    pub fetched_at: u64,
+    /// Iconify could not be reached and the cached list was returned.
+    pub offline: bool,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct IconSearchOptions {
+    /// Search only this set.
+    pub prefix: Option<String>,
+    /// 64 when unset.
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IconSearch {
+    /// Icon ids, as `prefix:name`.
+    pub icons: Vec<String>,
+    /// Searched in downloaded sets only, as Iconify could not be reached.
+    pub offline: bool,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct IconSvgOptions {
+    /// Height of the SVG; the icon's own when unset. Width follows.
+    pub size: Option<f64>,
+    /// CSS color for icons drawn in `currentColor`; black when unset.
+    pub color: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IconSvg {
+    pub id: String,
+    pub svg: String,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// Record of a downloaded set, kept next to it.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadedIconSet {
+    pub prefix: String,
+    pub name: String,
+    pub total: usize,
+    pub downloaded_at: u64,
+}
+
+// Shapes of Iconify's responses and sets
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct RemoteCollection {
+    name: String,
+    total: u32,
+    author: Option<RemoteAuthor>,
+    license: Option<RemoteLicense>,
+    category: Option<String>,
+    samples: Vec<String>,
+    palette: bool,
+}
+
+#[derive(Deserialize)]
+struct RemoteAuthor {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct RemoteLicense {
+    title: String,
+    spdx: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    icons: Vec<String>,
+}
+
+/// Placement of an icon in its box, and the flips and quarter turns that
+/// draw it; any of these an alias sets replaces its parent's, except the
+/// flips and turns, which add up.
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct IconProps {
+    left: Option<f64>,
+    top: Option<f64>,
+    width: Option<f64>,
+    height: Option<f64>,
+    rotate: u8,
+    h_flip: bool,
+    v_flip: bool,
+}
+
+#[derive(Deserialize)]
+struct IconData {
+    body: String,
+    #[serde(flatten)]
+    props: IconProps,
+}
+
+#[derive(Deserialize)]
+struct AliasData {
+    parent: String,
+    #[serde(flatten)]
+    props: IconProps,
+}
+
+#[derive(Deserialize)]
+struct IconSet {
+    prefix: String,
+    #[serde(default)]
+    info: Option<RemoteCollection>,
+    #[serde(default)]
+    icons: HashMap<String, IconData>,
+    #[serde(default)]
+    aliases: HashMap<String, AliasData>,
+    #[serde(flatten)]
+    defaults: IconProps,
+}
+
+/// Whether `name` is a valid set prefix or icon name: lowercase words of
+/// letters and digits joined by dashes, which also keeps it safe in paths
+/// and URLs.
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.split('-').all(|word| !word.is_empty() && word.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit()))
+}
+
+fn check_prefix(prefix: &str) -> Result<(), String> {
+    if valid_name(prefix) { Ok(()) } else { Err(format!("Invalid icon set: {}", prefix)) }
+}
+
+/// `prefix:name` split apart.
+fn parse_id(id: &str) -> Result<(&str, &str), String> {
+    match id.split_once(':') {
+        Some((prefix, name)) if valid_name(prefix) && valid_name(name) => Ok((prefix, name)),
+        _ => Err(format!("Invalid icon id: {}", id)),
+    }
+}
+
+/// Whether `color` is something to put in an attribute as it is.
+fn safe_color(color: &str) -> bool {
+    !color.is_empty() && color.chars().all(|c| c.is_ascii_alphanumeric() || "#(),.% ".contains(c))
+}
+
+fn collection(prefix: String, remote: RemoteCollection) -> IconCollection {
+    IconCollection {
+        prefix,
+        name: remote.name,
+        total: remote.total,
+        author: remote.author.map(|a| a.name),
+        license_spdx: remote.license.as_ref().and_then(|l| l.spdx.clone()),
+        license: remote.license.map(|l| l.title),
+        category: remote.category,
+        samples: remote.samples,
+        multicolor: remote.palette,
+        downloaded: false,
+    }
+}
+
+impl IconSet {
+    /// Icon `name` with its props, following aliases.
+    fn resolve(&self, name: &str) -> Option<(&str, IconProps)> {
+        let mut props = IconProps::default();
+        let mut name = name;
+        for _ in 0..MAX_ALIAS_DEPTH {
+            let (own, parent) = match (self.icons.get(name), self.aliases.get(name)) {
+                (Some(icon), _) => (icon.props, None),
+                (None, Some(alias)) => (alias.props, Some(alias.parent.as_str())),
+                (None, None) => return None,
+            };
+            props = IconProps {
+                left: props.left.or(own.left),
+                top: props.top.or(own.top),
+                width: props.width.or(own.width),
+                height: props.height.or(own.height),
+                rotate: (props.rotate + own.rotate) % 4,
+                h_flip: props.h_flip != own.h_flip,
+                v_flip: props.v_flip != own.v_flip,
+            };
+            match parent {
+                Some(parent) => name = parent,
+                None => return Some((&self.icons[name].body, props)),
+            }
+        }
+        None
+    }
+
+    /// Icon `name` as a standalone SVG document, flipped and turned as its
+    /// props say, the way Iconify's own renderer draws it.
+    fn svg(&self, name: &str, options: &IconSvgOptions) -> Option<IconSvg> {
+        let (body, props) = self.resolve(name)?;
+        let mut left = props.left.or(self.defaults.left).unwrap_or(0.0);
+        let mut top = props.top.or(self.defaults.top).unwrap_or(0.0);
+        let mut width = props.width.or(self.defaults.width).unwrap_or(DEFAULT_SIZE);
+        let mut height = props.height.or(self.defaults.height).unwrap_or(DEFAULT_SIZE);
+
+        let mut transforms = Vec::new();
+        let mut rotate = props.rotate;
+        match (props.h_flip, props.v_flip) {
+            (true, true) => rotate += 2,
+            (true, false) => {
+                transforms.push(format!("translate({} {}) scale(-1 1)", width + left, -top));
+                (left, top) = (0.0, 0.0);
+            }
+            (false, true) => {
+                transforms.push(format!("translate({} {}) scale(1 -1)", -left, height + top));
+                (left, top) = (0.0, 0.0);
+            }
+            (false, false) => {}
+        }
+        match rotate % 4 {
+            1 => transforms.insert(0, format!("rotate(90 {c} {c})", c = height / 2.0 + top)),
+            2 => transforms.insert(0, format!("rotate(180 {} {})", width / 2.0 + left, height / 2.0 + top)),
+            3 => transforms.insert(0, format!("rotate(-90 {c} {c})", c = width / 2.0 + left)),
+            _ => {}
+        }
+        if rotate % 2 == 1 {
+            (left, top) = (top, left);
+            (width, height) = (height, width);
+        }
+        let body = if transforms.is_empty() { body.to_string() } else { format!("<g transform=\"{}\">{}</g>", transforms.join(" "), body) };
+
+        let scale = options.size.filter(|s| *s > 0.0 && s.is_finite()).map_or(1.0, |size| size / height);
+        let color = options.color.as_deref().filter(|c| safe_color(c)).unwrap_or("#000");
+        let (out_width, out_height) = (width * scale, height * scale);
+        let svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" xmlns:xlink=\"http://www.w3.org/1999/xlink\" width=\"{}\" height=\"{}\" viewBox=\"{} {} {} {}\" color=\"{}\">{}</svg>",
+            out_width, out_height, left, top, width, height, color, body
+        );
+        Some(IconSvg { id: format!("{}:{}", self.prefix, name), svg, width: out_width, height: out_height })
+    }
+
+    /// Names of icons that have every word of `query` in them, aliases
+    /// included.
+    fn search(&self, query: &str) -> Vec<String> {
+        let words: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+        let mut names: Vec<&String> = self.icons.keys().chain(self.aliases.keys()).collect();
+        names.sort();
+        names.into_iter().filter(|name| words.iter().all(|word| name.contains(word.as_str()))).map(|name| format!("{}:{}", self.prefix, name)).collect()
+    }
+}
+
+/// Icon sets downloaded from Iconify, one directory per set under `dir`,
+/// along with the cached collection list.
+pub struct IconSets {
+    dir: PathBuf,
+    client: Client,
+}
+
+impl IconSets {
+    pub fn new(dir: PathBuf) -> Result<Self, String> {
+        let client = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+        Ok(IconSets { dir, client })
+    }
+
+    fn set_dir(&self, prefix: &str) -> PathBuf {
+        self.dir.join("sets").join(prefix)
+    }
+
+    async fn get(&self, url: Url) -> Result<Vec<u8>, String> {
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("Failed to reach Iconify: {}", e))?;
+        let body = response.bytes().await.map_err(|e| format!("Failed to download from Iconify: {}", e))?;
+        Ok(body.to_vec())
+    }
+
+    fn read_cache(&self) -> Option<CachedCollections> {
+        let bytes = fs::read(self.dir.join(COLLECTIONS_FILE)).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    async fn fetch_collections(&self) -> Result<CachedCollections, String> {
+        let url = Url::parse(&format!("{}/collections", API_URL)).map_err(|e| e.to_string())?;
+        let remote: BTreeMap<String, RemoteCollection> =
+            serde_json::from_slice(&self.get(url).await?).map_err(|e| format!("Unexpected response from Iconify: {}", e))?;
+        let collections = remote.into_iter().map(|(prefix, remote)| collection(prefix, remote)).collect();
+        let cached = CachedCollections { fetched_at: now_millis(), collections };
+
+        fs::create_dir_all(&self.dir).map_err(|e| format!("Failed to create icon directory: {}", e))?;
+        let json = serde_json::to_vec(&cached).map_err(|e| format!("Failed to encode icon sets: {}", e))?;
+        write_atomic(&self.dir.join(COLLECTIONS_FILE).to_string_lossy(), &json)?;
+        Ok(cached)
+    }
+
+    /// Sets downloaded so far, by prefix.
+    pub fn downloaded(&self) -> Vec<DownloadedIconSet> {
+        let mut sets: Vec<DownloadedIconSet> = fs::read_dir(self.dir.join("sets"))
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|entry| {
+                let bytes = fs::read(entry.path().join(RECORD_FILE)).ok()?;
+                serde_json::from_slice(&bytes).ok()
+            })
+            .collect();
+        sets.sort_by(|a, b| a.prefix.cmp(&b.prefix));
+        sets
+    }
+
+    fn downloaded_set(&self, prefix: &str) -> Option<IconSet> {
+        let bytes = fs::read(self.set_dir(prefix).join(SET_FILE)).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    pub async fn collections(&self, refresh: bool) -> Result<IconCollections, String> {
+        let cached = self.read_cache();
+        let fresh = cached.as_ref().is_some_and(|c| now_millis().saturating_sub(c.fetched_at) < COLLECTIONS_MAX_AGE_MS);
+
+        let (cached, offline) = match cached {
+            Some(cached) if fresh && !refresh => (cached, false),
+            cached => match (self.fetch_collections().await, cached) {
+                (Ok(fetched), _) => (fetched, false),
+                (Err(_), Some(cached)) => (cached, true),
+                (Err(e), None) => return Err(e),
+            },
+        };
+
+        let downloaded: Vec<String> = self.downloaded().into_iter().map(|set| set.prefix).collect();
+        let mut collections = cached.collections;
+        for collection in &mut collections {
+            collection.downloaded = downloaded.contains(&collection.prefix);
+        }
+        Ok(IconCollections { collections, fetched_at: cached.fetched_at, offline })
+    }
+
+    /// Icons matching `query`. A downloaded set is searched where it is;
+    /// otherwise Iconify is asked, falling back to the downloaded sets
+    /// when it can't be reached.
+    pub async fn search(&self, query: &str, options: &IconSearchOptions) -> Result<IconSearch, String> {
+        let limit = options.limit.unwrap_or(DEFAULT_SEARCH_LIMIT).clamp(1, MAX_SEARCH_LIMIT);
+        if let Some(prefix) = &options.prefix {
+            check_prefix(prefix)?;
+            if let Some(set) = self.downloaded_set(prefix) {
+                let icons = set.search(query).into_iter().take(limit).collect();
+                return Ok(IconSearch { icons, offline: false });
+            }
+        }
+
+        // The API returns no fewer than 32
+        let mut params = vec![("query", query.to_string()), ("limit", limit.max(32).to_string())];
+        if let Some(prefix) = &options.prefix {
+            params.push(("prefix", prefix.clone()));
+        }
+        let url = Url::parse_with_params(&format!("{}/search", API_URL), &params).map_err(|e| e.to_string())?;
+        match self.get(url).await {
+            Ok(body) => {
+                let found: SearchResponse = serde_json::from_slice(&body).map_err(|e| format!("Unexpected response from Iconify: {}", e))?;
+                Ok(IconSearch { icons: found.icons.into_iter().take(limit).collect(), offline: false })
+            }
+            Err(e) => {
+                let sets = self.downloaded();
+                if sets.is_empty() {
+                    return Err(e);
+                }
+                let icons = sets
+                    .iter()
+                    .filter_map(|set| self.downloaded_set(&set.prefix))
+                    .flat_map(|set| set.search(query))
+                    .take(limit)
+                    .collect();
+                Ok(IconSearch { icons, offline: true })
+            }
+        }
+    }
+
+    /// Icons `ids` as SVG, from downloaded sets where they are and from
+    /// Iconify otherwise, with one request for each other set. Icons that
+    /// don't exist are left out.
+    pub async fn svgs(&self, ids: &[String], options: &IconSvgOptions) -> Result<Vec<IconSvg>, String> {
+        let mut by_set: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for id in ids {
+            let (prefix, name) = parse_id(id)?;
+            by_set.entry(prefix).or_default().push(name);
+        }
+
+        let mut sets = HashMap::new();
+        for (prefix, names) in &by_set {
+            let set = match self.downloaded_set(prefix) {
+                Some(set) => set,
+                None => {
+                    let url = Url::parse_with_params(&format!("{}/{}.json", API_URL, prefix), &[("icons", names.join(","))])
+                        .map_err(|e| e.to_string())?;
+                    serde_json::from_slice(&self.get(url).await?).map_err(|e| format!("Unexpected response from Iconify: {}", e))?
+                }
+            };
+            sets.insert(*prefix, set);
+        }
+
+        let svgs = ids.iter().filter_map(|id| {
+            let (prefix, name) = parse_id(id).ok()?;
+            sets.get(prefix)?.svg(name, options)
+        });
+        Ok(svgs.collect())
+    }
+
+    /// Download the whole of set `prefix`, for searching and drawing its
+    /// icons offline.
+    pub async fn download(&self, prefix: &str) -> Result<DownloadedIconSet, String> {
+        check_prefix(prefix)?;
+        let url = Url::parse(&format!("{}/{}.json", SET_URL, prefix)).map_err(|e| e.to_string())?;
+        let bytes = self.get(url).await?;
+        let set: IconSet = serde_json::from_slice(&bytes).map_err(|e| format!("Invalid icon set {}: {}", prefix, e))?;
+        if set.prefix != prefix {
+            return Err(format!("Iconify sent set {} for {}", set.prefix, prefix));
+        }
+
+        let dir = self.set_dir(prefix);
+        fs::create_dir_all(&dir).map_err(|e| format!("Failed to create icon directory: {}", e))?;
+        write_atomic(&dir.join(SET_FILE).to_string_lossy(), &bytes)?;
+        let record = DownloadedIconSet {
+            prefix: prefix.to_string(),
+            name: set.info.map(|info| info.name).filter(|name| !name.is_empty()).unwrap_or_else(|| prefix.to_string()),
+            total: set.icons.len(),
+            downloaded_at: now_millis(),
+        };
+        let json = serde_json::to_vec_pretty(&record).map_err(|e| format!("Failed to encode icon set record: {}", e))?;
+        // The record goes last, so a set is only listed once it is whole
+        write_atomic(&dir.join(RECORD_FILE).to_string_lossy(), &json)?;
+        Ok(record)
+    }
+
+    pub fn remove(&self, prefix: &str) -> Result<(), String> {
+        check_prefix(prefix)?;
+        let dir = self.set_dir(prefix);
+        if !dir.join(RECORD_FILE).is_file() {
+            return Err(format!("Icon set not downloaded: {}", prefix));
+        }
+        fs::remove_dir_all(&dir).map_err(|e| format!("Failed to remove icon set: {}", e))
+    }
+}
+
+/// Iconify's icon sets, from a cache refreshed daily or when `refresh` is
+/// set. When offline the cached list is returned with `offline` set.
+#[command]
+pub async fn list_icon_collections(sets: State<'_, IconSets>, refresh: Option<bool>) -> Result<IconCollections, String> {
+    sets.collections(refresh.unwrap_or(false)).await
+}
+
+#[command]
+pub async fn search_icons(sets: State<'_, IconSets>, query: String, options: Option<IconSearchOptions>) -> Result<IconSearch, String> {
+    sets.search(&query, &options.unwrap_or_default()).await
+}
+
+/// Icons as SVG documents ready for `import_svg_data`, by `prefix:name` id.
+#[command]
+pub async fn get_icon_svgs(sets: State<'_, IconSets>, ids: Vec<String>, options: Option<IconSvgOptions>) -> Result<Vec<IconSvg>, String> {
+    sets.svgs(&ids, &options.unwrap_or_default()).await
+}
+
+#[command]
+pub async fn download_icon_set(sets: State<'_, IconSets>, prefix: String) -> Result<DownloadedIconSet, String> {
+    sets.download(&prefix).await
+}
+
+#[command]
+pub fn list_downloaded_icon_sets(sets: State<'_, IconSets>) -> Vec<DownloadedIconSet> {
+    sets.downloaded()
+}
+
+#[command]
+pub fn remove_icon_set(sets: State<'_, IconSets>, prefix: String) -> Result<(), String> {
+    sets.remove(&prefix)
+}
@@ -0,0 +1,799 @@
+use super::kiwi::{Schema, Value};
+use super::{place, unmapped_report, NodeBuilder, UnmappedFeature};
+use crate::fonts::weight_from_style;
+use crate::geometry::{invert, multiply, scale, translate, Matrix, Rect, IDENTITY};
+use crate::model::{
+    generate_node_id, GradientStop, NodeData, NodeType, Paint, PathCommand, Rgba, ScaleMode, SerializedDocument,
+    SerializedNode, TextStyleRange, VectorPath, WindingRule, FORMAT_VERSION,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::Path;
+use tauri::command;
+
+/// Instances are expanded from their component; this bounds runaway nesting.
+const MAX_INSTANCE_DEPTH: usize = 16;
+
+/// Layers copied in Figma, converted for pasting.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FigPaste {
+    /// The copied layers and their descendants; top-level layers have no
+    /// parent and keep their position on the source page.
+    pub nodes: Vec<SerializedNode>,
+    pub root_ids: Vec<String>,
+    /// Key of the file the layers came from, for fetching their images,
+    /// which the clipboard does not carry.
+    pub file_key: Option<String>,
+    pub unmapped: Vec<UnmappedFeature>,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FigImport {
+    pub document: SerializedDocument,
+    /// Figma features with no native equivalent, most common first.
+    pub unmapped: Vec<UnmappedFeature>,
+    pub warnings: Vec<String>,
+}
+
+/// The kiwi schema and message chunks of a `fig-kiwi` canvas.
+fn read_canvas(data: &[u8]) -> Result<(Vec<u8>, Vec<u8>), String> {
+    if data.len() < 12 || !(data.starts_with(b"fig-kiwi") || data.starts_with(b"fig-jam.")) {
+        return Err("Not a Figma file".to_string());
+    }
+
+    let mut chunks = Vec::new();
+    let mut pos = 12;
+    while pos + 4 <= data.len() {
+        let len = u32::from_le_bytes(data[pos..pos + 4].try_into().expect("slice is 4 bytes")) as usize;
+        pos += 4;
+        let chunk = data.get(pos..pos + len).ok_or("Truncated Figma file")?;
+        chunks.push(decompress(chunk)?);
+        pos += len;
+    }
+
+    let mut chunks = chunks.into_iter();
+    match (chunks.next(), chunks.next()) {
+        (Some(schema), Some(message)) => Ok((schema, message)),
+        _ => Err("Figma file is missing its document data".to_string()),
+    }
+}
+
+/// The decoded `Message` of a `fig-kiwi` canvas.
+fn decode_message(canvas: &[u8]) -> Result<Value, String> {
+    let (schema, message) = read_canvas(canvas)?;
+    let schema = Schema::decode(&schema)?;
+    schema.decode_message("Message", &message)
+}
+
+/// Chunks are zstd in current files and raw deflate in older ones.
+fn decompress(chunk: &[u8]) -> Result<Vec<u8>, String> {
+    if chunk.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        let mut out = Vec::new();
+        ruzstd::decoding::StreamingDecoder::new(chunk)
+            .map_err(|e| format!("Failed to decompress Figma data: {}", e))?
+            .read_to_end(&mut out)
+            .map_err(|e| format!("Failed to decompress Figma data: {}", e))?;
+        return Ok(out);
+    }
+    miniz_oxide::inflate::decompress_to_vec(chunk)
+        .or_else(|_| miniz_oxide::inflate::decompress_to_vec_zlib(chunk))
+        .map_err(|e| format!("Failed to decompress Figma data: {:?}", e))
+}
+
+fn mime_type(bytes: &[u8]) -> &'static str {
+    match bytes {
+        [0x89, b'P', b'N', b'G', ..] => "image/png",
+        [0xff, 0xd8, ..] => "image/jpeg",
+        [b'G', b'I', b'F', ..] => "image/gif",
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn guid(v: Option<&Value>) -> Option<String> {
+    let v = v?;
+    Some(format!("{}:{}", v.get("sessionID")?.as_u64()?, v.get("localID")?.as_u64()?))
+}
+
+fn num(v: &Value, key: &str) -> Option<f64> {
+    v.get(key)?.as_f64()
+}
+
+fn text(v: &Value, key: &str) -> Option<String> {
+    v.get(key)?.as_str().map(str::to_string)
+}
+
+fn flag(v: &Value, key: &str) -> Option<bool> {
+    v.get(key)?.as_bool()
+}
+
+fn color(v: Option<&Value>) -> Rgba {
+    let c = |key| v.and_then(|v| num(v, key));
+    Rgba { r: c("r").unwrap_or(0.0), g: c("g").unwrap_or(0.0), b: c("b").unwrap_or(0.0), a: c("a").unwrap_or(1.0) }
+}
+
+/// Figma's `Matrix { m00 .. m12 }` as `[a, b, c, d, tx, ty]`.
+fn matrix(v: Option<&Value>) -> Matrix {
+    let Some(v) = v else { return IDENTITY };
+    let m = |key, default| num(v, key).unwrap_or(default);
+    [m("m00", 1.0), m("m10", 0.0), m("m01", 0.0), m("m11", 1.0), m("m02", 0.0), m("m12", 0.0)]
+}
+
+/// Normalized box (0..1) to `bounds`.
+fn unit(bounds: Rect) -> Matrix {
+    multiply(&translate(bounds.x, bounds.y), &scale(bounds.width.max(1e-6), bounds.height.max(1e-6)))
+}
+
+/// A Figma `Number { value, units }` in pixels.
+fn pixels(v: Option<&Value>, font_size: f64) -> Option<f64> {
+    let v = v?;
+    let value = num(v, "value")?;
+    Some(match v.get("units").and_then(Value::as_str) {
+        Some("PERCENT") => value / 100.0 * font_size,
+        Some("RAW") => value * font_size,
+        _ => value,
+    })
+}
+
+fn floats<const N: usize>(blob: &[u8], pos: &mut usize) -> Option<[f64; N]> {
+    let mut out = [0.0; N];
+    for v in &mut out {
+        let bytes = blob.get(*pos..*pos + 4)?;
+        *v = f32::from_le_bytes(bytes.try_into().ok()?) as f64;
+        *pos += 4;
+    }
+    Some(out)
+}
+
+struct Converter<'a> {
+    changes: HashMap<String, &'a Value>,
+    children: HashMap<String, Vec<String>>,
+    blobs: Vec<&'a [u8]>,
+    images: HashMap<String, Vec<u8>>,
+    image_urls: HashMap<String, String>,
+    out: NodeBuilder,
+    unmapped: BTreeMap<&'static str, usize>,
+}
+
+impl<'a> Converter<'a> {
+    /// Index the live node changes of `message` by id and their children in
+    /// sibling order.
+    fn new(message: &'a Value, images: HashMap<String, Vec<u8>>) -> Self {
+        let blobs = message
+            .get("blobs")
+            .map(Value::as_array)
+            .unwrap_or_default()
+            .iter()
+            .map(|b| b.get("bytes").and_then(Value::as_bytes).unwrap_or_default())
+            .collect();
+
+        let mut changes = HashMap::new();
+        let mut ordered: HashMap<String, Vec<(String, String)>> = HashMap::new();
+        for change in message.get("nodeChanges").map(Value::as_array).unwrap_or_default() {
+            if change.get("phase").and_then(Value::as_str) == Some("REMOVED") {
+                continue;
+            }
+            let Some(id) = guid(change.get("guid")) else { continue };
+            if let Some(parent) = change.get("parentIndex") {
+                if let Some(parent_id) = guid(parent.get("guid")) {
+                    let position = text(parent, "position").unwrap_or_default();
+                    ordered.entry(parent_id).or_default().push((position, id.clone()));
+                }
+            }
+            changes.insert(id, change);
+        }
+
+        // Sibling order is a fractional index compared as a plain string
+        let children = ordered
+            .into_iter()
+            .map(|(parent, mut kids)| {
+                kids.sort();
+                (parent, kids.into_iter().map(|(_, id)| id).collect())
+            })
+            .collect();
+
+        Converter {
+            changes,
+            children,
+            blobs,
+            images,
+            image_urls: HashMap::new(),
+            out: NodeBuilder::default(),
+            unmapped: BTreeMap::new(),
+        }
+    }
+
+    fn unmapped(&mut self, feature: &'static str) {
+        *self.unmapped.entry(feature).or_default() += 1;
+    }
+
+    fn image_url(&mut self, hash: &str) -> Option<String> {
+        if let Some(url) = self.image_urls.get(hash) {
+            return Some(url.clone());
+        }
+        let bytes = self.images.get(hash)?;
+        let url = format!("data:{};base64,{}", mime_type(bytes), BASE64.encode(bytes));
+        self.image_urls.insert(hash.to_string(), url.clone());
+        Some(url)
+    }
+
+    /// Decode a geometry blob: a command byte followed by little-endian f32
+    /// coordinates.
+    fn path(&self, geometry: &Value) -> Option<VectorPath> {
+        let blob = self.blobs.get(geometry.get("commandsBlob")?.as_u64()? as usize)?;
+        let mut commands = Vec::new();
+        let mut current = (0.0, 0.0);
+        let mut pos = 0;
+
+        while let Some(&command) = blob.get(pos) {
+            pos += 1;
+            let command = match command {
+                0 => PathCommand::ClosePath,
+                1 => {
+                    let [x, y] = floats(blob, &mut pos)?;
+                    PathCommand::MoveTo { x, y }
+                }
+                2 => {
+                    let [x, y] = floats(blob, &mut pos)?;
+                    PathCommand::LineTo { x, y }
+                }
+                3 => {
+                    // Elevate quadratics, which the document model lacks
+                    let [cx, cy, x, y] = floats(blob, &mut pos)?;
+                    let (x0, y0) = current;
+                    PathCommand::CurveTo {
+                        x1: x0 + 2.0 / 3.0 * (cx - x0),
+                        y1: y0 + 2.0 / 3.0 * (cy - y0),
+                        x2: x + 2.0 / 3.0 * (cx - x),
+                        y2: y + 2.0 / 3.0 * (cy - y),
+                        x,
+                        y,
+                    }
+                }
+                4 => {
+                    let [x1, y1, x2, y2, x, y] = floats(blob, &mut pos)?;
+                    PathCommand::CurveTo { x1, y1, x2, y2, x, y }
+                }
+                _ => return None,
+            };
+            if let PathCommand::MoveTo { x, y } | PathCommand::LineTo { x, y } | PathCommand::CurveTo { x, y, .. } = command {
+                current = (x, y);
+            }
+            commands.push(command);
+        }
+
+        let winding_rule = match geometry.get("windingRule").and_then(Value::as_str) {
+            Some("ODD") => WindingRule::Evenodd,
+            _ => WindingRule::Nonzero,
+        };
+        Some(VectorPath { winding_rule, commands })
+    }
+
+    fn paths(&self, change: &Value, key: &str) -> Vec<VectorPath> {
+        change.get(key).map(Value::as_array).unwrap_or_default().iter().filter_map(|g| self.path(g)).collect()
+    }
+
+    /// `m` maps the Figma layer's local space to the imported node's, whose
+    /// box is `to`; `from` is the Figma layer box.
+    fn paint(&mut self, v: &Value, m: &Matrix, from: Rect, to: Rect) -> Option<Paint> {
+        let visible = flag(v, "visible").unwrap_or(true);
+        let opacity = num(v, "opacity").unwrap_or(1.0);
+        if v.get("blendMode").and_then(Value::as_str).is_some_and(|b| b != "NORMAL") {
+            self.unmapped("Paint blend modes");
+        }
+
+        // Figma stores the inverse of the paint's placement
+        let rebase = |t: Matrix| {
+            let placed = invert(&t).unwrap_or(IDENTITY);
+            let to_unit = invert(&unit(to)).unwrap_or(IDENTITY);
+            multiply(&to_unit, &multiply(m, &multiply(&unit(from), &placed)))
+        };
+        let stops = || {
+            v.get("stops")
+                .map(Value::as_array)
+                .unwrap_or_default()
+                .iter()
+                .map(|s| GradientStop { position: num(s, "position").unwrap_or(0.0), color: color(s.get("color")) })
+                .collect::<Vec<_>>()
+        };
+
+        match v.get("type")?.as_str()? {
+            "SOLID" => Some(Paint::Solid { visible, opacity, color: color(v.get("color")) }),
+            "GRADIENT_LINEAR" => Some(Paint::GradientLinear {
+                visible,
+                opacity,
+                gradient_stops: stops(),
+                gradient_transform: rebase(matrix(v.get("transform"))),
+            }),
+            kind @ ("GRADIENT_RADIAL" | "GRADIENT_ANGULAR" | "GRADIENT_DIAMOND") => {
+                if kind != "GRADIENT_RADIAL" {
+                    self.unmapped("Angular and diamond gradients (imported as radial)");
+                }
+                Some(Paint::GradientRadial {
+                    visible,
+                    opacity,
+                    gradient_stops: stops(),
+                    gradient_transform: rebase(matrix(v.get("transform"))),
+                })
+            }
+            "IMAGE" => {
+                let hash = hex(v.get("image")?.get("hash")?.as_bytes()?);
+                let Some(image_ref) = self.image_url(&hash) else {
+                    self.out.warn("Some images are missing from the file and were dropped");
+                    return None;
+                };
+                let scale_mode = match v.get("imageScaleMode").and_then(Value::as_str) {
+                    Some("FIT") => ScaleMode::Fit,
+                    Some("STRETCH") => {
+                        self.unmapped("Image crop positions");
+                        ScaleMode::Crop
+                    }
+                    Some("TILE") => ScaleMode::Tile,
+                    _ => ScaleMode::Fill,
+                };
+                Some(Paint::Image { visible, opacity, image_ref, scale_mode, image_transform: IDENTITY })
+            }
+            _ => {
+                self.unmapped("Video and emoji fills");
+                None
+            }
+        }
+    }
+
+    fn paints(&mut self, change: &Value, key: &str, m: &Matrix, from: Rect, to: Rect) -> Option<Vec<Paint>> {
+        let list = change.get(key)?.as_array();
+        Some(list.iter().filter_map(|p| self.paint(p, m, from, to)).collect())
+    }
+
+    fn effects(&mut self, change: &Value) -> Option<Vec<serde_json::Value>> {
+        let list = change.get("effects")?.as_array();
+        let mut effects = Vec::new();
+        for e in list {
+            let visible = flag(e, "visible").unwrap_or(true);
+            let radius = num(e, "radius").unwrap_or(0.0);
+            match e.get("type").and_then(Value::as_str) {
+                Some(kind @ ("DROP_SHADOW" | "INNER_SHADOW")) => {
+                    let c = color(e.get("color"));
+                    let offset = e.get("offset");
+                    effects.push(json!({
+                        "type": kind,
+                        "visible": visible,
+                        "color": { "r": c.r, "g": c.g, "b": c.b, "a": c.a },
+                        "offset": {
+                            "x": offset.and_then(|o| num(o, "x")).unwrap_or(0.0),
+                            "y": offset.and_then(|o| num(o, "y")).unwrap_or(0.0),
+                        },
+                        "radius": radius,
+                        "spread": num(e, "spread").unwrap_or(0.0),
+                    }));
+                }
+                Some("FOREGROUND_BLUR") => effects.push(json!({ "type": "BLUR", "visible": visible, "radius": radius })),
+                Some("BACKGROUND_BLUR") => {
+                    effects.push(json!({ "type": "BACKGROUND_BLUR", "visible": visible, "radius": radius }))
+                }
+                _ => self.unmapped("Other effects"),
+            }
+        }
+        Some(effects)
+    }
+
+    fn text_styles(&mut self, change: &Value, m: &Matrix, from: Rect, to: Rect) -> (String, Vec<TextStyleRange>) {
+        let data = change.get("textData");
+        let characters = data.and_then(|d| text(d, "characters")).unwrap_or_default();
+        let length = characters.chars().count();
+
+        let overrides: HashMap<u64, &Value> = data
+            .and_then(|d| d.get("styleOverrideTable"))
+            .map(Value::as_array)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|o| Some((o.get("styleID")?.as_u64()?, o)))
+            .collect();
+        let ids: Vec<u64> = data
+            .and_then(|d| d.get("characterStyleIDs"))
+            .map(Value::as_array)
+            .unwrap_or_default()
+            .iter()
+            .map(|v| v.as_u64().unwrap_or(0))
+            .collect();
+
+        let style = |converter: &mut Self, o: Option<&Value>, start: usize, end: usize| {
+            let pick = |key: &str| o.and_then(|o| o.get(key)).or_else(|| change.get(key));
+            let font = pick("fontName");
+            let family = font.and_then(|f| text(f, "family")).unwrap_or_else(|| "Inter".to_string());
+            let style_name = font.and_then(|f| text(f, "style")).unwrap_or_default();
+            if style_name.to_lowercase().contains("italic") {
+                converter.unmapped("Italic text");
+            }
+            let font_size = pick("fontSize").and_then(Value::as_f64).unwrap_or(12.0);
+            let line_height = match pick("lineHeight") {
+                Some(lh) if lh.get("units").and_then(Value::as_str) == Some("PERCENT") && num(lh, "value") == Some(100.0) => {
+                    Some(serde_json::Value::from("AUTO"))
+                }
+                lh => pixels(lh, font_size).map(serde_json::Value::from),
+            };
+            let source = o.filter(|o| o.get("fillPaints").is_some()).unwrap_or(change);
+            let fills = converter.paints(source, "fillPaints", m, from, to).unwrap_or_default();
+            let text_decoration = match pick("textDecoration").and_then(Value::as_str) {
+                Some(d @ ("UNDERLINE" | "STRIKETHROUGH")) => Some(d.to_string()),
+                _ => None,
+            };
+            if pick("textCase").and_then(Value::as_str).is_some_and(|c| c != "ORIGINAL") {
+                converter.unmapped("Text case transforms");
+            }
+            TextStyleRange {
+                start,
+                end,
+                font_family: family,
+                font_weight: weight_from_style(&style_name),
+                font_size,
+                fills,
+                text_decoration,
+                letter_spacing: pixels(pick("letterSpacing"), font_size).unwrap_or(0.0),
+                line_height,
+            }
+        };
+
+        let mut styles = Vec::new();
+        let mut start = 0;
+        while start < length.max(1) {
+            let id = ids.get(start).copied().unwrap_or(0);
+            let mut end = start + 1;
+            while end < length && ids.get(end).copied().unwrap_or(0) == id {
+                end += 1;
+            }
+            let end = end.min(length);
+            styles.push(style(self, overrides.get(&id).copied(), start, end));
+            start = end.max(start + 1);
+        }
+        (characters, styles)
+    }
+
+    fn node(&mut self, key: &str, parent: Option<&str>, depth: usize) {
+        let Some(change) = self.changes.get(key).copied() else { return };
+        let kind = change.get("type").and_then(Value::as_str).unwrap_or_default();
+        let name = text(change, "name").unwrap_or_else(|| kind.to_string());
+
+        if flag(change, "internalOnly") == Some(true) {
+            return;
+        }
+
+        let node_type = match kind {
+            "DOCUMENT" => NodeType::Document,
+            "CANVAS" => NodeType::Page,
+            "FRAME" if flag(change, "resizeToFit") == Some(true) => NodeType::Group,
+            "FRAME" | "SECTION" => NodeType::Frame,
+            "GROUP" => NodeType::Group,
+            "SYMBOL" => NodeType::Component,
+            "INSTANCE" => NodeType::Instance,
+            "RECTANGLE" | "ROUNDED_RECTANGLE" => NodeType::Rectangle,
+            "ELLIPSE" => NodeType::Ellipse,
+            "LINE" => NodeType::Line,
+            "STAR" => NodeType::Star,
+            "REGULAR_POLYGON" => NodeType::Polygon,
+            "VECTOR" => NodeType::Vector,
+            "BOOLEAN_OPERATION" => {
+                self.unmapped("Boolean operations (flattened to vectors)");
+                NodeType::Vector
+            }
+            "TEXT" => NodeType::Text,
+            "SLICE" => NodeType::Slice,
+            _ => {
+                self.unmapped("FigJam and widget layers");
+                return;
+            }
+        };
+
+        let size = change.get("size");
+        let from = Rect::new(
+            0.0,
+            0.0,
+            size.and_then(|s| num(s, "x")).unwrap_or(0.0),
+            size.and_then(|s| num(s, "y")).unwrap_or(0.0),
+        );
+        let t = matrix(change.get("transform"));
+
+        let mut data = NodeData::new(generate_node_id(), node_type, name);
+        data.visible = flag(change, "visible").unwrap_or(true);
+        data.locked = flag(change, "locked").unwrap_or(false);
+        data.opacity = num(change, "opacity").filter(|o| *o < 1.0);
+        data.blend_mode = text(change, "blendMode").filter(|b| b != "PASS_THROUGH" && b != "NORMAL");
+
+        let mut outlined_stroke = false;
+        let mut paths = None;
+        if matches!(node_type, NodeType::Vector | NodeType::Star | NodeType::Polygon | NodeType::Line) {
+            let mut geometry = self.paths(change, "fillGeometry");
+            if node_type == NodeType::Line {
+                geometry = vec![VectorPath {
+                    winding_rule: WindingRule::Nonzero,
+                    commands: vec![PathCommand::MoveTo { x: 0.0, y: 0.0 }, PathCommand::LineTo { x: from.width, y: 0.0 }],
+                }];
+            } else if geometry.is_empty() {
+                // Open paths only have outlined stroke geometry
+                geometry = self.paths(change, "strokeGeometry");
+                outlined_stroke = true;
+            }
+            paths = Some(geometry);
+        }
+
+        let mut m = IDENTITY;
+        let mut to = from;
+        if !matches!(node_type, NodeType::Document | NodeType::Page) {
+            let placement = place(&t, from, paths.as_mut());
+            if !placement.exact {
+                self.unmapped("Flipped or skewed layers");
+            }
+            (m, to) = (placement.to_local, placement.bounds);
+            data.x = Some(placement.x);
+            data.y = Some(placement.y);
+            data.rotation = placement.rotation;
+            data.width = Some(to.width);
+            data.height = Some(to.height);
+            data.vector_paths = paths;
+        }
+
+        if node_type == NodeType::Page {
+            data.background_color = change.get("backgroundColor").map(|c| color(Some(c)));
+        } else if node_type != NodeType::Document {
+            data.fills = self.paints(change, "fillPaints", &m, from, to);
+            data.strokes = self.paints(change, "strokePaints", &m, from, to);
+            if outlined_stroke {
+                // The stroke outline is the geometry, so its paint becomes the fill
+                data.fills = data.strokes.take();
+            } else {
+                data.stroke_weight = num(change, "strokeWeight");
+                data.stroke_align = text(change, "strokeAlign");
+                data.stroke_join = text(change, "strokeJoin");
+                data.stroke_miter_limit = num(change, "miterLimit");
+                data.stroke_cap = match change.get("strokeCap").and_then(Value::as_str) {
+                    Some(cap @ ("ROUND" | "SQUARE")) => Some(cap.to_string()),
+                    Some("NONE") | None => None,
+                    Some(_) => {
+                        self.unmapped("Arrowheads");
+                        None
+                    }
+                };
+                data.dash_pattern = change
+                    .get("dashPattern")
+                    .map(|d| d.as_array().iter().filter_map(Value::as_f64).collect::<Vec<_>>())
+                    .filter(|d| !d.is_empty());
+            }
+            data.effects = self.effects(change).filter(|e| !e.is_empty());
+
+            let radius = num(change, "cornerRadius").unwrap_or(0.0);
+            if flag(change, "rectangleCornerRadiiIndependent") == Some(true) {
+                let corners = ["rectangleTopLeftCornerRadius", "rectangleTopRightCornerRadius", "rectangleBottomLeftCornerRadius", "rectangleBottomRightCornerRadius"]
+                    .map(|k| num(change, k).unwrap_or(radius));
+                if corners.iter().any(|c| *c != corners[0]) {
+                    self.unmapped("Independent corner radii (imported as uniform)");
+                }
+                data.corner_radius = Some(corners[0]).filter(|r| *r > 0.0);
+            } else {
+                data.corner_radius = Some(radius).filter(|r| *r > 0.0);
+            }
+        }
+
+        match node_type {
+            NodeType::Frame | NodeType::Component | NodeType::Instance => {
+                data.clips_content = Some(flag(change, "frameMaskDisabled") != Some(true));
+                if change.get("stackMode").and_then(Value::as_str).is_some_and(|s| s != "NONE") {
+                    self.unmapped("Auto layout (imported as fixed positions)");
+                }
+            }
+            NodeType::Text => {
+                let (characters, styles) = self.text_styles(change, &m, from, to);
+                data.fills = styles.first().map(|s| s.fills.clone());
+                data.characters = Some(characters);
+                data.text_styles = Some(styles);
+                data.text_align_horizontal = text(change, "textAlignHorizontal");
+                data.text_align_vertical = text(change, "textAlignVertical");
+                if let Some(resize) = text(change, "textAutoResize") {
+                    data.extra.insert("textAutoResize".to_string(), resize.into());
+                }
+            }
+            _ => {}
+        }
+
+        if flag(change, "mask") == Some(true) {
+            self.unmapped("Masks (imported as regular layers)");
+        }
+        if change.get("prototypeInteractions").is_some_and(|p| !p.as_array().is_empty()) {
+            self.unmapped("Prototype interactions");
+        }
+        if change.get("styleIdForFill").is_some() || change.get("styleIdForText").is_some() {
+            self.unmapped("Shared styles (imported as local values)");
+        }
+
+        let id = self.out.add(parent, data);
+
+        // Instances carry overrides rather than children, so expand the
+        // component's own children
+        let mut children = self.children.get(key).cloned().unwrap_or_default();
+        if node_type == NodeType::Instance && children.is_empty() {
+            let symbol = change.get("symbolData");
+            if let Some(symbol_id) = guid(symbol.and_then(|s| s.get("symbolID"))) {
+                if depth >= MAX_INSTANCE_DEPTH {
+                    self.out.warn("Deeply nested instances were truncated");
+                    return;
+                }
+                children = self.children.get(&symbol_id).cloned().unwrap_or_default();
+            }
+            if symbol.and_then(|s| s.get("symbolOverrides")).is_some_and(|o| !o.as_array().is_empty()) {
+                self.unmapped("Instance overrides");
+            }
+        }
+        let depth = if node_type == NodeType::Instance { depth + 1 } else { depth };
+        for child in children {
+            self.node(&child, Some(&id), depth);
+        }
+    }
+}
+
+/// Convert a `.fig` file (a ZIP with `canvas.fig` and images, or a bare
+/// `fig-kiwi` canvas) into a native document.
+pub fn import_fig_data(data: &[u8], name: &str) -> Result<FigImport, String> {
+    let mut images = HashMap::new();
+    let mut name = name.to_string();
+    let canvas = if data.starts_with(b"PK") {
+        let mut archive = zip::ZipArchive::new(Cursor::new(data))
+            .map_err(|e| format!("Invalid Figma file: {}", e))?;
+        let mut canvas = Vec::new();
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i)
+                .map_err(|e| format!("Invalid Figma file: {}", e))?;
+            let entry_name = entry.name().to_string();
+            let mut bytes = Vec::new();
+            if entry_name == "canvas.fig" || entry_name.starts_with("images/") || entry_name == "meta.json" {
+                entry.read_to_end(&mut bytes)
+                    .map_err(|e| format!("Failed to read {}: {}", entry_name, e))?;
+            }
+            if entry_name == "canvas.fig" {
+                canvas = bytes;
+            } else if let Some(hash) = entry_name.strip_prefix("images/") {
+                images.insert(hash.to_lowercase(), bytes);
+            } else if entry_name == "meta.json" {
+                if let Some(file_name) = serde_json::from_slice::<serde_json::Value>(&bytes)
+                    .ok()
+                    .and_then(|m| m.get("file_name")?.as_str().map(str::to_string))
+                {
+                    name = file_name;
+                }
+            }
+        }
+        canvas
+    } else {
+        data.to_vec()
+    };
+
+    let message = decode_message(&canvas)?;
+    let mut converter = Converter::new(&message, images);
+    let root = converter
+        .changes
+        .iter()
+        .find(|(_, change)| change.get("type").and_then(Value::as_str) == Some("DOCUMENT"))
+        .map(|(id, _)| id.clone())
+        .ok_or("Figma file has no document")?;
+    converter.node(&root, None, 0);
+
+    let unmapped = unmapped_report(&converter.unmapped);
+    let (nodes, warnings) = converter.out.into_parts();
+    let root_id = nodes.first().map(|n| n.id.clone()).ok_or("Figma file has no document")?;
+
+    Ok(FigImport {
+        document: SerializedDocument {
+            version: "1.0.0".to_string(),
+            format_version: FORMAT_VERSION,
+            name,
+            created_at: String::new(),
+            updated_at: String::new(),
+            nodes,
+            root_id,
+        },
+        unmapped,
+        warnings,
+    })
+}
+
+/// The base64 text between `(<tag>)` and `(/<tag>)` in Figma's clipboard
+/// HTML, decoded. The markers sit in HTML comments inside `data-` attributes.
+fn clipboard_section(html: &str, tag: &str) -> Option<Vec<u8>> {
+    let open = format!("({})", tag);
+    let start = html.find(&open)? + open.len();
+    let end = start + html[start..].find(&format!("(/{})", tag))?;
+    let encoded: String = html[start..end].chars().filter(|c| !c.is_whitespace()).collect();
+    BASE64.decode(encoded).ok()
+}
+
+/// Convert the HTML Figma puts on the clipboard when copying layers, or
+/// `None` when it holds no Figma data.
+pub fn import_fig_clipboard(html: &str) -> Result<Option<FigPaste>, String> {
+    let Some(canvas) = clipboard_section(html, "figma") else { return Ok(None) };
+    let file_key = clipboard_section(html, "figmeta")
+        .and_then(|meta| serde_json::from_slice::<serde_json::Value>(&meta).ok())
+        .and_then(|meta| meta.get("fileKey")?.as_str().map(str::to_string));
+
+    let message = decode_message(&canvas)?;
+    let mut converter = Converter::new(&message, HashMap::new());
+
+    // The copied layers sit on a page; components their instances use are
+    // on an internal page, reached only through the instances
+    let kind = |id: &String| converter.changes.get(id).and_then(|c| c.get("type")?.as_str());
+    let mut pages: Vec<&String> = converter
+        .changes
+        .iter()
+        .filter(|(_, change)| {
+            change.get("type").and_then(Value::as_str) == Some("CANVAS") && flag(change, "internalOnly") != Some(true)
+        })
+        .map(|(id, _)| id)
+        .collect();
+    pages.sort();
+    let mut roots: Vec<String> = pages
+        .into_iter()
+        .flat_map(|page| converter.children.get(page).cloned().unwrap_or_default())
+        .collect();
+    if roots.is_empty() {
+        let mut orphans: Vec<String> = converter
+            .changes
+            .iter()
+            .filter(|(_, change)| guid(change.get("parentIndex").and_then(|p| p.get("guid"))).is_none_or(|p| !converter.changes.contains_key(&p)))
+            .map(|(id, _)| id.clone())
+            .filter(|id| !matches!(kind(id), Some("DOCUMENT" | "CANVAS")))
+            .collect();
+        orphans.sort();
+        roots = orphans;
+    }
+    if roots.is_empty() {
+        return Err("The Figma clipboard data holds no layers".to_string());
+    }
+
+    for root in &roots {
+        converter.node(root, None, 0);
+    }
+
+    let unmapped = unmapped_report(&converter.unmapped);
+    let (nodes, warnings) = converter.out.into_parts();
+    let root_ids = nodes.iter().filter(|n| n.parent_id.is_none()).map(|n| n.id.clone()).collect();
+    Ok(Some(FigPaste { nodes, root_ids, file_key, unmapped, warnings }))
+}
+
+/// Read layers copied in Figma. Figma keeps them as a `fig-kiwi` canvas
+/// inside the HTML it writes to the clipboard; `html` is that HTML when the
+/// webview's paste event already has it, otherwise the system clipboard is
+/// read. Resolves to `None` when the clipboard holds no Figma layers, so
+/// the caller can fall back to a regular paste.
+#[command(async)]
+pub fn paste_from_figma(html: Option<String>) -> Result<Option<FigPaste>, String> {
+    let html = match html {
+        Some(html) => html,
+        None => {
+            let mut clipboard = arboard::Clipboard::new()
+                .map_err(|e| format!("Failed to open clipboard: {}", e))?;
+            match clipboard.get().html() {
+                Ok(html) => html,
+                Err(arboard::Error::ContentNotAvailable) => return Ok(None),
+                Err(e) => return Err(format!("Failed to read clipboard: {}", e)),
+            }
+        }
+    };
+    import_fig_clipboard(&html)
+}
+
+#[command(async)]
+pub fn import_fig(path: String) -> Result<FigImport, String> {
+    let data = fs::read(&path)
+        .map_err(|e| format!("Failed to read Figma file: {}", e))?;
+
+    let name = Path::new(&path).file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_else(|| "Untitled".to_string());
+    import_fig_data(&data, &name)
+}
@@ -0,0 +1,308 @@
+//! Decoder for the kiwi binary format (github.com/evanw/kiwi), driven by a
+//! schema that is itself kiwi-encoded, as embedded in Figma files.
+
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    Bool(bool),
+    Int(i64),
+    Uint(u64),
+    Float(f64),
+    String(String),
+    /// Enum values decode to their variant name.
+    Enum(String),
+    Bytes(Vec<u8>),
+    Array(Vec<Value>),
+    Object(HashMap<String, Value>),
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields.get(key),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) | Value::Enum(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Value::Float(v) => Some(v),
+            Value::Int(v) => Some(v as f64),
+            Value::Uint(v) => Some(v as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Value::Uint(v) => Some(v),
+            Value::Int(v) => u64::try_from(v).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Bool(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> &[Value] {
+        match self {
+            Value::Array(items) => items,
+            _ => &[],
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Enum,
+    Struct,
+    Message,
+}
+
+#[derive(Clone, Copy)]
+enum FieldType {
+    Bool,
+    Byte,
+    Int,
+    Uint,
+    Float,
+    String,
+    Int64,
+    Uint64,
+    Defined(usize),
+}
+
+struct Field {
+    name: String,
+    field_type: FieldType,
+    is_array: bool,
+    /// Enum value, or field id in a message.
+    value: u64,
+}
+
+struct Definition {
+    name: String,
+    kind: Kind,
+    fields: Vec<Field>,
+}
+
+pub struct Schema {
+    definitions: Vec<Definition>,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, String> {
+        let byte = *self.data.get(self.pos).ok_or("Unexpected end of data")?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn var_uint(&mut self) -> Result<u64, String> {
+        let mut value = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 || shift >= 35 {
+                return Ok(value & 0xffff_ffff);
+            }
+        }
+    }
+
+    fn var_int(&mut self) -> Result<i64, String> {
+        let v = self.var_uint()? as u32;
+        Ok((if v & 1 != 0 { !(v >> 1) as i32 } else { (v >> 1) as i32 }) as i64)
+    }
+
+    fn var_uint64(&mut self) -> Result<u64, String> {
+        let mut value = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift >= 56 {
+                return Ok(value | ((byte as u64) << shift));
+            }
+            value |= ((byte & 0x7f) as u64) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn var_int64(&mut self) -> Result<i64, String> {
+        let v = self.var_uint64()?;
+        Ok(if v & 1 != 0 { !(v >> 1) as i64 } else { (v >> 1) as i64 })
+    }
+
+    /// Floats are stored with the exponent moved to the low byte so that
+    /// common values are short; zero is a single byte.
+    fn var_float(&mut self) -> Result<f64, String> {
+        let first = self.byte()?;
+        if first == 0 {
+            return Ok(0.0);
+        }
+        let bits = first as u32
+            | (self.byte()? as u32) << 8
+            | (self.byte()? as u32) << 16
+            | (self.byte()? as u32) << 24;
+        Ok(f32::from_bits(bits.rotate_left(23)) as f64)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let end = self.data[self.pos..]
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or("Unterminated string")?;
+        let s = String::from_utf8_lossy(&self.data[self.pos..self.pos + end]).into_owned();
+        self.pos += end + 1;
+        Ok(s)
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, String> {
+        let len = self.var_uint()? as usize;
+        let end = self.pos.checked_add(len).filter(|end| *end <= self.data.len()).ok_or("Unexpected end of data")?;
+        let bytes = self.data[self.pos..end].to_vec();
+        self.pos = end;
+        Ok(bytes)
+    }
+}
+
+impl Schema {
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        let mut r = Reader { data, pos: 0 };
+        let count = r.var_uint()?;
+        let mut definitions = Vec::new();
+
+        for _ in 0..count {
+            let name = r.string()?;
+            let kind = match r.byte()? {
+                0 => Kind::Enum,
+                1 => Kind::Struct,
+                2 => Kind::Message,
+                other => return Err(format!("Invalid schema definition kind {}", other)),
+            };
+            let field_count = r.var_uint()?;
+            let mut fields = Vec::new();
+            for _ in 0..field_count {
+                let name = r.string()?;
+                let field_type = match r.var_int()? {
+                    -1 => FieldType::Bool,
+                    -2 => FieldType::Byte,
+                    -3 => FieldType::Int,
+                    -4 => FieldType::Uint,
+                    -5 => FieldType::Float,
+                    -6 => FieldType::String,
+                    -7 => FieldType::Int64,
+                    -8 => FieldType::Uint64,
+                    index if index >= 0 && (index as u64) < count => FieldType::Defined(index as usize),
+                    other => return Err(format!("Invalid schema field type {}", other)),
+                };
+                let is_array = r.byte()? & 1 != 0;
+                let value = r.var_uint()?;
+                fields.push(Field { name, field_type, is_array, value });
+            }
+            definitions.push(Definition { name, kind, fields });
+        }
+
+        Ok(Schema { definitions })
+    }
+
+    /// Decode `data` as the definition named `root`.
+    pub fn decode_message(&self, root: &str, data: &[u8]) -> Result<Value, String> {
+        let index = self
+            .definitions
+            .iter()
+            .position(|d| d.name == root)
+            .ok_or_else(|| format!("Schema has no {} definition", root))?;
+        let mut r = Reader { data, pos: 0 };
+        self.definition(index, &mut r)
+    }
+
+    fn definition(&self, index: usize, r: &mut Reader) -> Result<Value, String> {
+        let def = &self.definitions[index];
+        match def.kind {
+            Kind::Enum => {
+                let value = r.var_uint()?;
+                let name = def.fields.iter().find(|f| f.value == value).map(|f| f.name.clone());
+                Ok(Value::Enum(name.unwrap_or_else(|| value.to_string())))
+            }
+            Kind::Struct => {
+                let mut fields = HashMap::with_capacity(def.fields.len());
+                for field in &def.fields {
+                    fields.insert(field.name.clone(), self.field(field, r)?);
+                }
+                Ok(Value::Object(fields))
+            }
+            Kind::Message => {
+                let mut fields = HashMap::new();
+                loop {
+                    let id = r.var_uint()?;
+                    if id == 0 {
+                        return Ok(Value::Object(fields));
+                    }
+                    let field = def
+                        .fields
+                        .iter()
+                        .find(|f| f.value == id)
+                        .ok_or_else(|| format!("Unknown field {} in {}", id, def.name))?;
+                    fields.insert(field.name.clone(), self.field(field, r)?);
+                }
+            }
+        }
+    }
+
+    fn field(&self, field: &Field, r: &mut Reader) -> Result<Value, String> {
+        if field.is_array {
+            if matches!(field.field_type, FieldType::Byte) {
+                return Ok(Value::Bytes(r.bytes()?));
+            }
+            let len = r.var_uint()?;
+            let mut items = Vec::with_capacity(len.min(4096) as usize);
+            for _ in 0..len {
+                items.push(self.value(field.field_type, r)?);
+            }
+            return Ok(Value::Array(items));
+        }
+        self.value(field.field_type, r)
+    }
+
+    fn value(&self, field_type: FieldType, r: &mut Reader) -> Result<Value, String> {
+        Ok(match field_type {
+            FieldType::Bool => Value::Bool(r.byte()? != 0),
+            FieldType::Byte => Value::Uint(r.byte()? as u64),
+            FieldType::Int => Value::Int(r.var_int()?),
+            FieldType::Uint => Value::Uint(r.var_uint()?),
+            FieldType::Float => Value::Float(r.var_float()?),
+            FieldType::String => Value::String(r.string()?),
+            FieldType::Int64 => Value::Int(r.var_int64()?),
+            FieldType::Uint64 => Value::Uint(r.var_uint64()?),
+            FieldType::Defined(index) => self.definition(index, r)?,
+        })
+    }
+}
@@ -0,0 +1,169 @@
+//! Importers that convert other design formats into serialized nodes the
+//! frontend can insert into a document.
+
+pub mod figma;
+mod kiwi;
+pub mod pdf;
+pub mod raster;
+pub mod sanitize;
+pub mod sketch;
+pub mod svg;
+pub mod trace;
+
+use crate::geometry::{apply, invert, multiply, path_bounds, scale, transform_path, translate, Matrix, Rect, IDENTITY};
+use crate::model::{NodeData, SerializedNode, VectorPath};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Serialize, Clone, Copy)]
+pub struct Dimensions {
+    pub width: f64,
+    pub height: f64,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportResult {
+    pub root_id: String,
+    /// Imported nodes in document order; the root has no parent.
+    pub nodes: Vec<SerializedNode>,
+    pub dimensions: Dimensions,
+    /// Source features that were dropped or approximated.
+    pub warnings: Vec<String>,
+}
+
+/// Where a layer lands in the document model, which only has a position,
+/// a clockwise rotation and a size.
+pub struct Placement {
+    pub x: f64,
+    pub y: f64,
+    pub rotation: Option<f64>,
+    /// Node-local box.
+    pub bounds: Rect,
+    /// Layer-local to node-local coordinates, for re-basing paints.
+    pub to_local: Matrix,
+    /// False when the layer's flip or skew could not be represented.
+    pub exact: bool,
+}
+
+/// Place a layer whose local-to-parent transform is `t` and local box is
+/// `size`. Vector `paths` absorb flips and skews and are moved so their
+/// bounds start at the node origin; other layers keep only the rotation.
+pub fn place(t: &Matrix, size: Rect, paths: Option<&mut Vec<VectorPath>>) -> Placement {
+    let det = t[0] * t[3] - t[1] * t[2];
+    let is_rotation = det > 0.0 && (t[0] - t[3]).abs() < 1e-6 && (t[1] + t[2]).abs() < 1e-6;
+
+    let mut to_local = IDENTITY;
+    let mut bounds = Rect::new(0.0, 0.0, size.width, size.height);
+    let exact = is_rotation || paths.is_some();
+    if let Some(paths) = paths {
+        let linear = if is_rotation { IDENTITY } else { [t[0], t[1], t[2], t[3], 0.0, 0.0] };
+        let baked: Vec<VectorPath> = paths.iter().map(|p| transform_path(p, &linear)).collect();
+        to_local = match baked.iter().filter_map(path_bounds).reduce(|a, b| a.union(&b)) {
+            Some(b) => {
+                bounds = Rect::new(0.0, 0.0, b.width, b.height);
+                multiply(&translate(-b.x, -b.y), &linear)
+            }
+            None => linear,
+        };
+        *paths = baked.iter().map(|p| transform_path(p, &translate(to_local[4], to_local[5]))).collect();
+    }
+
+    let origin = invert(&to_local).map(|inv| apply(&inv, 0.0, 0.0)).unwrap_or((0.0, 0.0));
+    let (x, y) = apply(t, origin.0, origin.1);
+    let rotation = if is_rotation { Some(t[1].atan2(t[0]).to_degrees()).filter(|r| r.abs() > 1e-6) } else { None };
+    Placement { x, y, rotation, bounds, to_local, exact }
+}
+
+/// Gradient paint space placed in layer pixels. Linear gradients run from
+/// `from` to `to`; radial ones are centered on `from` with `to` on the
+/// ellipse, whose other axis is `ratio` times as long.
+pub fn gradient_placement(from: (f64, f64), to: (f64, f64), radial: Option<f64>) -> Matrix {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    match radial {
+        // (0, 0.5) -> (1, 0.5) onto from -> to, perpendicular in pixels
+        None => [dx, dy, -dy, dx, from.0 + dy / 2.0, from.1 - dx / 2.0],
+        Some(ratio) => {
+            let radius = (dx * dx + dy * dy).sqrt();
+            let (sin, cos) = dy.atan2(dx).sin_cos();
+            multiply(
+                &multiply(&translate(from.0, from.1), &[cos, sin, -sin, cos, 0.0, 0.0]),
+                &multiply(&scale(radius * 2.0, radius * 2.0 * ratio), &translate(-0.5, -0.5)),
+            )
+        }
+    }
+}
+
+/// Re-base a paint placed in layer space onto the node box `to`, through
+/// the layer-to-node matrix `m`.
+pub fn paint_transform(placed: &Matrix, m: &Matrix, to: Rect) -> Option<Matrix> {
+    let to_unit = invert(&multiply(&translate(to.x, to.y), &scale(to.width.max(1e-6), to.height.max(1e-6))))?;
+    Some(multiply(&to_unit, &multiply(m, placed)))
+}
+
+/// A source format feature with no native equivalent.
+#[derive(Serialize, Clone)]
+pub struct UnmappedFeature {
+    pub feature: String,
+    /// Number of layers that used it.
+    pub count: usize,
+}
+
+/// Tallied features, most common first.
+pub fn unmapped_report(tally: &BTreeMap<&'static str, usize>) -> Vec<UnmappedFeature> {
+    let mut report: Vec<UnmappedFeature> = tally
+        .iter()
+        .map(|(feature, count)| UnmappedFeature { feature: feature.to_string(), count: *count })
+        .collect();
+    report.sort_by_key(|f| std::cmp::Reverse(f.count));
+    report
+}
+
+/// Collects nodes and parent/child links while an importer walks its source.
+#[derive(Default)]
+pub struct NodeBuilder {
+    nodes: Vec<SerializedNode>,
+    index: HashMap<String, usize>,
+    warnings: Vec<String>,
+}
+
+impl NodeBuilder {
+    /// Append `data` as the last child of `parent`, returning its id.
+    pub fn add(&mut self, parent: Option<&str>, mut data: NodeData) -> String {
+        let id = data.id.clone();
+        data.parent_id = parent.map(str::to_string);
+
+        let mut child_index = 0;
+        if let Some(parent) = parent.and_then(|p| self.index.get(p)) {
+            let parent = &mut self.nodes[*parent];
+            child_index = parent.data.child_ids.len();
+            parent.data.child_ids.push(id.clone());
+        }
+
+        self.index.insert(id.clone(), self.nodes.len());
+        self.nodes.push(SerializedNode {
+            id: id.clone(),
+            parent_id: data.parent_id.clone(),
+            child_index,
+            data,
+        });
+        id
+    }
+
+    /// Record a warning once, however many times it is hit.
+    pub fn warn(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        if !self.warnings.contains(&message) {
+            self.warnings.push(message);
+        }
+    }
+
+    pub fn finish(self, root_id: String, dimensions: Dimensions) -> ImportResult {
+        ImportResult { root_id, nodes: self.nodes, dimensions, warnings: self.warnings }
+    }
+
+    /// Nodes and warnings, for importers that produce a whole document.
+    pub fn into_parts(self) -> (Vec<SerializedNode>, Vec<String>) {
+        (self.nodes, self.warnings)
+    }
+}
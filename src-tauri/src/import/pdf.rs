@@ -0,0 +1,675 @@
+//! Import of PDF pages, PDF-compatible Illustrator files and EPS. A page is
+//! interpreted with hayro and its drawing calls are turned into native
+//! nodes; PostScript is converted to PDF by Ghostscript first, when it is
+//! installed.
+
+use super::{gradient_placement, paint_transform, Dimensions, ImportResult, NodeBuilder};
+use crate::geometry::{from_bez_path, path_bounds, transform_path, translate, Rect, IDENTITY};
+use crate::model::{generate_node_id, GradientStop, NodeData, NodeType, Paint, Rgba, ScaleMode, VectorPath, WindingRule};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use hayro_interpret::encode::{texture_dimensions, EncodedShadingType};
+use hayro_interpret::font::{Glyph, GlyphRun};
+use hayro_interpret::gradient::{SvgGradient, SvgGradientKind};
+use hayro_interpret::hayro_cmap::BfString;
+use hayro_interpret::hayro_syntax::{LoadPdfError, Pdf};
+use hayro_interpret::pattern::{Pattern, ShadingPattern};
+use hayro_interpret::{
+    interpret_page, BlendMode, ClipPath, Context, Device, DrawMode, DrawProps, FillRule, Image, ImageData,
+    ImageDrawProps, InterpreterCache, InterpreterSettings, InterpreterWarning, LumaData, SoftMask,
+    TransformExt,
+};
+use image::imageops::{self, FilterType};
+use image::{GrayImage, ImageFormat, RgbaImage};
+use kurbo::{Affine, BezPath, Cap, Join, PathEl, Shape, Vec2};
+use serde::Deserialize;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use tauri::command;
+
+/// Where Ghostscript is usually installed, for apps started from the Finder
+/// that do not see the shell's `PATH`.
+const GHOSTSCRIPT_LOCATIONS: [&str; 3] = ["/opt/homebrew/bin", "/usr/local/bin", "/usr/bin"];
+
+/// Pixels per point for shadings that have no native gradient equivalent.
+const RASTER_SCALE: f32 = 2.0;
+
+/// Longest side of a rasterized shading.
+const MAX_TEXTURE_SIZE: u32 = 4096;
+
+/// Largest color difference allowed when approximating a shading function
+/// with gradient stops.
+const GRADIENT_TOLERANCE: f32 = 0.01;
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfImportOptions {
+    /// 1-based page to import (default 1).
+    pub page: Option<usize>,
+    /// Uniform scale applied to the imported content (default 1).
+    pub scale: Option<f64>,
+    /// Ghostscript binary, needed for EPS; looked up on the `PATH` and in
+    /// the usual install locations when not set.
+    pub ghostscript_path: Option<String>,
+}
+
+fn is_postscript(bytes: &[u8]) -> bool {
+    // DOS EPS files start with a binary header pointing at the PostScript
+    bytes.starts_with(b"%!") || bytes.starts_with(&[0xc5, 0xd0, 0xd3, 0xc6])
+}
+
+fn find_ghostscript(explicit: Option<&str>) -> Result<PathBuf, String> {
+    if let Some(path) = explicit {
+        return Some(PathBuf::from(path))
+            .filter(|p| p.is_file())
+            .ok_or_else(|| format!("Ghostscript was not found at {}", path));
+    }
+    let name = if cfg!(windows) { "gswin64c.exe" } else { "gs" };
+    std::env::var_os("PATH")
+        .iter()
+        .flat_map(std::env::split_paths)
+        .chain(GHOSTSCRIPT_LOCATIONS.iter().map(PathBuf::from))
+        .map(|dir| dir.join(name))
+        .find(|p| p.is_file())
+        .ok_or_else(|| "EPS import needs Ghostscript, which was not found. Install it or set its location".to_string())
+}
+
+/// Convert a PostScript file to PDF, with the page cropped to the EPS
+/// bounding box.
+fn postscript_to_pdf(path: &str, ghostscript: &Path) -> Result<Vec<u8>, String> {
+    let mut command = Command::new(ghostscript);
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        // CREATE_NO_WINDOW: no console flashing up behind the app
+        command.creation_flags(0x0800_0000);
+    }
+    let output = command
+        .args(["-q", "-dNOPAUSE", "-dBATCH", "-dSAFER", "-dEPSCrop", "-sDEVICE=pdfwrite", "-sstdout=%stderr", "-sOutputFile=-"])
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Failed to start Ghostscript: {}", e))?;
+    if !output.status.success() || output.stdout.is_empty() {
+        let errors = String::from_utf8_lossy(&output.stderr);
+        let line = errors.lines().find(|l| !l.trim().is_empty()).unwrap_or("no output");
+        return Err(format!("Ghostscript failed to convert the file: {}", line.trim()));
+    }
+    Ok(output.stdout)
+}
+
+fn blend_mode(mode: BlendMode) -> Option<String> {
+    use BlendMode::*;
+    let name = match mode {
+        Normal => return None,
+        Multiply => "MULTIPLY",
+        Screen => "SCREEN",
+        Overlay => "OVERLAY",
+        Darken => "DARKEN",
+        Lighten => "LIGHTEN",
+        ColorDodge => "COLOR_DODGE",
+        ColorBurn => "COLOR_BURN",
+        HardLight => "HARD_LIGHT",
+        SoftLight => "SOFT_LIGHT",
+        Difference => "DIFFERENCE",
+        Exclusion => "EXCLUSION",
+        Hue => "HUE",
+        Saturation => "SATURATION",
+        Color => "COLOR",
+        Luminosity => "LUMINOSITY",
+    };
+    Some(name.to_string())
+}
+
+fn kurbo_rect(r: Rect) -> kurbo::Rect {
+    kurbo::Rect::new(r.x, r.y, r.right(), r.bottom())
+}
+
+fn vector_path(path: &BezPath, rule: FillRule) -> VectorPath {
+    from_bez_path(
+        path,
+        match rule {
+            FillRule::NonZero => WindingRule::Nonzero,
+            FillRule::EvenOdd => WindingRule::Evenodd,
+        },
+    )
+}
+
+/// `path` without subpaths that are only a move, which content streams
+/// leave behind and which would otherwise stretch its bounds.
+fn without_empty_subpaths(path: &BezPath) -> BezPath {
+    let mut out: Vec<PathEl> = Vec::with_capacity(path.elements().len());
+    for el in path.elements() {
+        let lone_move = matches!(out.last(), Some(PathEl::MoveTo(_)));
+        match el {
+            PathEl::MoveTo(_) | PathEl::ClosePath if lone_move => {
+                out.pop();
+            }
+            _ => {}
+        }
+        if !(lone_move && matches!(el, PathEl::ClosePath)) {
+            out.push(*el);
+        }
+    }
+    if matches!(out.last(), Some(PathEl::MoveTo(_))) {
+        out.pop();
+    }
+    BezPath::from_vec(out)
+}
+
+/// The rectangle a clip path outlines, when it is an axis-aligned one.
+fn clip_rect(path: &BezPath) -> Option<kurbo::Rect> {
+    let mut points = Vec::with_capacity(5);
+    for el in path.elements() {
+        match el {
+            PathEl::MoveTo(p) if points.is_empty() => points.push(*p),
+            PathEl::LineTo(p) if !points.is_empty() => points.push(*p),
+            PathEl::ClosePath => {}
+            _ => return None,
+        }
+    }
+    if points.len() == 5 && points[0].distance(points[4]) < 1e-6 {
+        points.pop();
+    }
+    if points.len() != 4 {
+        return None;
+    }
+    let straight = (0..4).all(|i| {
+        let (a, b) = (points[i], points[(i + 1) % 4]);
+        (a.x - b.x).abs() < 1e-6 || (a.y - b.y).abs() < 1e-6
+    });
+    straight.then(|| path.bounding_box())
+}
+
+fn png_data_url(image: &RgbaImage) -> Option<String> {
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png).ok()?;
+    Some(format!("data:image/png;base64,{}", BASE64.encode(png)))
+}
+
+/// Alpha resampled to the color's size; PDF soft masks may have their own.
+fn alpha_channel(alpha: LumaData, width: u32, height: u32) -> Option<Vec<u8>> {
+    let mask = GrayImage::from_raw(alpha.width, alpha.height, alpha.data)?;
+    if mask.dimensions() == (width, height) {
+        return Some(mask.into_raw());
+    }
+    Some(imageops::resize(&mask, width, height, FilterType::Triangle).into_raw())
+}
+
+fn raster_pixels(image: ImageData, alpha: Option<LumaData>) -> Option<RgbaImage> {
+    let (width, height) = (image.width(), image.height());
+    let mut rgba: Vec<u8> = match image {
+        ImageData::Rgb(rgb) => rgb.data.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+        ImageData::Luma(luma) => luma.data.iter().flat_map(|g| [*g, *g, *g, 255]).collect(),
+    };
+    if let Some(alpha) = alpha.and_then(|a| alpha_channel(a, width, height)) {
+        rgba.chunks_exact_mut(4).zip(alpha).for_each(|(p, a)| p[3] = a);
+    }
+    RgbaImage::from_raw(width, height, rgba)
+}
+
+/// A node collected in page space, positioned relative to its parent only
+/// once the whole page has been read.
+struct Item {
+    data: NodeData,
+    bounds: Rect,
+    children: Vec<Item>,
+}
+
+enum ScopeKind {
+    Page,
+    /// A transparency group, kept as a GROUP node when it changes opacity
+    /// or blending.
+    Group { opacity: f64, blend: Option<String> },
+    /// A clip path; rectangular ones become clipping frames.
+    Clip { bounds: kurbo::Rect, rect: bool },
+}
+
+struct Scope {
+    kind: ScopeKind,
+    items: Vec<Item>,
+}
+
+fn union_bounds(items: &[Item]) -> Option<Rect> {
+    items.iter().map(|i| i.bounds).reduce(|a, b| a.union(&b))
+}
+
+struct Converter {
+    out: NodeBuilder,
+    scopes: Vec<Scope>,
+}
+
+impl Converter {
+    fn push(&mut self, item: Item) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.items.push(item);
+        }
+    }
+
+    fn open(&mut self, kind: ScopeKind) {
+        self.scopes.push(Scope { kind, items: Vec::new() });
+    }
+
+    fn close(&mut self) {
+        if self.scopes.len() < 2 {
+            return;
+        }
+        let Some(Scope { kind, items }) = self.scopes.pop() else { return };
+        let Some(bounds) = union_bounds(&items) else { return };
+
+        let container = match kind {
+            ScopeKind::Page => None,
+            ScopeKind::Group { opacity, blend } if opacity < 1.0 || blend.is_some() => {
+                let mut data = NodeData::new(generate_node_id(), NodeType::Group, "Group");
+                data.opacity = (opacity < 1.0).then_some(opacity);
+                data.blend_mode = blend;
+                Some((data, bounds))
+            }
+            ScopeKind::Group { .. } => None,
+            ScopeKind::Clip { bounds: clip, rect } => {
+                let clip = Rect::new(clip.x0, clip.y0, clip.width(), clip.height());
+                let inside = bounds.x >= clip.x - 0.5
+                    && bounds.y >= clip.y - 0.5
+                    && bounds.right() <= clip.right() + 0.5
+                    && bounds.bottom() <= clip.bottom() + 0.5;
+                if inside {
+                    None
+                } else if rect {
+                    let mut data = NodeData::new(generate_node_id(), NodeType::Frame, "Clip");
+                    data.clips_content = Some(true);
+                    data.fills = Some(Vec::new());
+                    Some((data, clip))
+                } else {
+                    self.out.warn("Non-rectangular clip paths are not supported; clipped content was imported unclipped");
+                    None
+                }
+            }
+        };
+
+        match container {
+            Some((data, bounds)) => self.push(Item { data, bounds, children: items }),
+            None => {
+                if let Some(scope) = self.scopes.last_mut() {
+                    scope.items.extend(items);
+                }
+            }
+        }
+    }
+
+    /// A native gradient for an axial shading, or a radial one whose circles
+    /// share a center; the model has no focal point.
+    fn gradient(native: &SvgGradient, bounds: Rect) -> Option<Paint> {
+        let t = native.transform;
+        let stop = |position: f64, color: [f32; 4]| GradientStop {
+            position,
+            color: Rgba { r: color[0] as f64, g: color[1] as f64, b: color[2] as f64, a: color[3] as f64 },
+        };
+
+        match native.kind {
+            SvgGradientKind::Linear { start, end } => {
+                let (from, to) = (t * start, t * end);
+                let placed = gradient_placement((from.x, from.y), (to.x, to.y), None);
+                Some(Paint::GradientLinear {
+                    visible: true,
+                    opacity: 1.0,
+                    gradient_stops: native.stops.iter().map(|s| stop(s.offset as f64, s.color)).collect(),
+                    gradient_transform: paint_transform(&placed, &IDENTITY, bounds)?,
+                })
+            }
+            SvgGradientKind::Radial { start_center, start_radius, end_center, end_radius } => {
+                let (r0, r1) = (start_radius as f64, end_radius as f64);
+                let radius = r0.max(r1);
+                if radius <= 0.0 || start_center.distance(end_center) > radius * 1e-3 {
+                    return None;
+                }
+                let center = t * end_center;
+                let x_axis = t * (end_center + Vec2::new(radius, 0.0));
+                let y_axis = t * (end_center + Vec2::new(0.0, radius));
+                let ratio = center.distance(y_axis) / center.distance(x_axis).max(1e-9);
+                let placed = gradient_placement((center.x, center.y), (x_axis.x, x_axis.y), Some(ratio));
+
+                // Stop offsets run from the first circle to the second
+                let mut stops: Vec<GradientStop> = native
+                    .stops
+                    .iter()
+                    .map(|s| stop((r0 + (r1 - r0) * s.offset as f64) / radius, s.color))
+                    .collect();
+                stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+                Some(Paint::GradientRadial {
+                    visible: true,
+                    opacity: 1.0,
+                    gradient_stops: stops,
+                    gradient_transform: paint_transform(&placed, &IDENTITY, bounds)?,
+                })
+            }
+        }
+    }
+
+    fn shading(&mut self, pattern: &ShadingPattern, bounds: Rect) -> Option<Paint> {
+        let encoded = pattern.encode();
+        let bbox = kurbo_rect(bounds);
+        if let EncodedShadingType::RadialAxial(shading) = &encoded.shading_type {
+            if let Some(paint) = shading
+                .as_svg_gradient(&encoded, bbox, GRADIENT_TOLERANCE)
+                .and_then(|native| Self::gradient(&native, bounds))
+            {
+                return Some(paint);
+            }
+        }
+
+        self.out.warn("Shadings with no native gradient equivalent were rasterized");
+        let (width, height) = texture_dimensions(bbox, RASTER_SCALE);
+        let longest = width.max(height);
+        let scale = if longest > MAX_TEXTURE_SIZE { RASTER_SCALE * MAX_TEXTURE_SIZE as f32 / longest as f32 } else { RASTER_SCALE };
+        let texture = encoded.render_texture(bbox, scale);
+        let image = RgbaImage::from_raw(texture.width, texture.height, texture.data)?;
+        Some(Paint::Image {
+            visible: true,
+            opacity: 1.0,
+            image_ref: png_data_url(&image)?,
+            scale_mode: ScaleMode::Fill,
+            image_transform: IDENTITY,
+        })
+    }
+
+    /// Convert a PDF paint for a node whose page-space box is `bounds`.
+    fn paint(&mut self, paint: &hayro_interpret::Paint<'_>, bounds: Rect) -> Option<Paint> {
+        match paint {
+            hayro_interpret::Paint::Color(c) => {
+                let [r, g, b, a] = c.to_rgba().components().map(|v| v as f64);
+                Some(Paint::Solid { visible: true, opacity: a, color: Rgba { r, g, b, a: 1.0 } })
+            }
+            hayro_interpret::Paint::Pattern(p) => match p.as_ref() {
+                Pattern::Shading(s) => self.shading(s, bounds),
+                Pattern::Tiling(_) => {
+                    self.out.warn("Tiling patterns are not supported; shapes using them were imported without that paint");
+                    None
+                }
+            },
+        }
+    }
+
+    fn vector(&mut self, path: &BezPath, props: &DrawProps<'_>, mode: &DrawMode, name: &str) {
+        let (rule, stroke) = match mode {
+            DrawMode::Fill(rule) => (Some(*rule), None),
+            DrawMode::Stroke(stroke) => (None, Some(stroke)),
+            DrawMode::FillAndStroke(rule, stroke) => (Some(*rule), Some(stroke)),
+            DrawMode::Invisible => return,
+        };
+        if props.soft_mask.is_some() {
+            self.out.warn("Soft masks are not supported and were ignored");
+        }
+
+        let path = without_empty_subpaths(&(props.transform * path.clone()));
+        let absolute = vector_path(&path, rule.unwrap_or(FillRule::NonZero));
+        let Some(bounds) = path_bounds(&absolute) else { return };
+
+        let mut data = NodeData::new(generate_node_id(), NodeType::Vector, name);
+        data.vector_paths = Some(vec![transform_path(&absolute, &translate(-bounds.x, -bounds.y))]);
+        data.blend_mode = blend_mode(props.blend_mode);
+        data.fills = Some(Vec::new());
+        data.strokes = Some(Vec::new());
+        if rule.is_some() {
+            data.fills = Some(self.paint(&props.paint, bounds).into_iter().collect());
+        }
+
+        if let Some(stroke) = stroke {
+            let k = props.transform.determinant().abs().sqrt();
+            data.strokes = Some(self.paint(&props.paint, bounds).into_iter().collect());
+            // Zero asks for the thinnest line the device can show
+            data.stroke_weight = Some(if stroke.line_width > 0.0 { stroke.line_width as f64 * k } else { 1.0 });
+            data.stroke_align = Some("CENTER".to_string());
+            data.stroke_cap = Some(
+                match stroke.line_cap {
+                    Cap::Butt => "NONE",
+                    Cap::Round => "ROUND",
+                    Cap::Square => "SQUARE",
+                }
+                .to_string(),
+            );
+            data.stroke_join = Some(
+                match stroke.line_join {
+                    Join::Miter => "MITER",
+                    Join::Round => "ROUND",
+                    Join::Bevel => "BEVEL",
+                }
+                .to_string(),
+            );
+            data.stroke_miter_limit = Some(stroke.miter_limit as f64);
+            if !stroke.dash_array.is_empty() {
+                data.dash_pattern = Some(stroke.dash_array.iter().map(|d| *d as f64 * k).collect());
+                data.dash_offset = Some(stroke.dash_offset as f64 * k);
+            }
+        }
+
+        self.push(Item { data, bounds, children: Vec::new() });
+    }
+}
+
+impl<'a> Device<'a> for Converter {
+    fn draw_path(&mut self, path: &BezPath, props: DrawProps<'a>, draw_mode: &DrawMode) {
+        self.vector(path, &props, draw_mode, "Path");
+    }
+
+    fn push_clip_path(&mut self, clip_path: &ClipPath) {
+        let path = without_empty_subpaths(&clip_path.path);
+        let rect = clip_rect(&path);
+        let bounds = rect.unwrap_or_else(|| path.bounding_box());
+        self.open(ScopeKind::Clip { bounds, rect: rect.is_some() });
+    }
+
+    fn push_transparency_group(&mut self, opacity: f32, mask: Option<SoftMask<'a>>, blend_mode: BlendMode) {
+        if mask.is_some() {
+            self.out.warn("Soft masks are not supported and were ignored");
+        }
+        self.open(ScopeKind::Group { opacity: opacity as f64, blend: self::blend_mode(blend_mode) });
+    }
+
+    fn draw_glyph_run(&mut self, glyph_run: &GlyphRun<'_, 'a>, props: DrawProps<'a>, draw_mode: &DrawMode) {
+        if matches!(draw_mode, DrawMode::Invisible) {
+            return;
+        }
+        let mut outlines = BezPath::new();
+        let mut text = String::new();
+        for glyph in glyph_run.glyphs() {
+            match &**glyph {
+                Glyph::Outline(outline) => outlines.extend(glyph.transform() * outline.outline()),
+                // Drawn with PDF operators, which come back as paths and images
+                Glyph::Type3(type3) => type3.interpret(self, props.transform, glyph.transform(), &props.paint),
+            }
+            match glyph.as_unicode() {
+                Some(BfString::Char(c)) => text.push(c),
+                Some(BfString::String(s)) => text.push_str(&s),
+                None => {}
+            }
+        }
+        if outlines.elements().is_empty() {
+            return;
+        }
+
+        self.out.warn("Text was imported as vector outlines");
+        let name: String = text.trim().chars().take(40).collect();
+        self.vector(&outlines, &props, draw_mode, if name.is_empty() { "Text" } else { &name });
+    }
+
+    fn draw_image(&mut self, image: Image<'a, '_>, props: ImageDrawProps<'a>) {
+        if props.soft_mask.is_some() {
+            self.out.warn("Soft masks are not supported and were ignored");
+        }
+
+        let mut decoded = None;
+        let mut patterned = false;
+        match image {
+            Image::Raster(raster) => raster.with_rgba(
+                |image, alpha| {
+                    let factors = image.scale_factors();
+                    decoded = raster_pixels(image, alpha).map(|p| (p, factors));
+                },
+                None,
+            ),
+            // A 1-bit mask painted in the current color
+            Image::Stencil(stencil) => stencil.with_stencil(
+                |mask, paint| {
+                    let color = match paint {
+                        hayro_interpret::Paint::Color(c) => c.to_rgba().to_rgba8(),
+                        hayro_interpret::Paint::Pattern(_) => {
+                            patterned = true;
+                            [0, 0, 0, 255]
+                        }
+                    };
+                    let rgba = mask
+                        .data
+                        .iter()
+                        .flat_map(|m| [color[0], color[1], color[2], ((*m as u32 * color[3] as u32 + 127) / 255) as u8])
+                        .collect();
+                    decoded = RgbaImage::from_raw(mask.width, mask.height, rgba).map(|p| (p, mask.scale_factors));
+                },
+                None,
+            ),
+        }
+        if patterned {
+            self.out.warn("Image masks filled with patterns were imported in black");
+        }
+        let Some((mut pixels, (sx, sy))) = decoded else {
+            self.out.warn("Some images could not be decoded and were dropped");
+            return;
+        };
+
+        let m = (props.transform * Affine::scale_non_uniform(sx as f64, sy as f64)).as_coeffs();
+        if m[1].abs() > 1e-6 || m[2].abs() > 1e-6 {
+            self.out.warn("Rotated or skewed images were imported axis-aligned");
+        } else {
+            // Mirrored placements are baked into the pixels
+            if m[0] < 0.0 {
+                imageops::flip_horizontal_in_place(&mut pixels);
+            }
+            if m[3] < 0.0 {
+                imageops::flip_vertical_in_place(&mut pixels);
+            }
+        }
+        let (width, height) = pixels.dimensions();
+        let bounds = Rect::new(0.0, 0.0, width as f64, height as f64).transformed(&m);
+        let Some(image_ref) = png_data_url(&pixels) else { return };
+
+        let mut data = NodeData::new(generate_node_id(), NodeType::Image, "Image");
+        data.image_ref = Some(image_ref);
+        data.scale_mode = Some(ScaleMode::Fill);
+        data.blend_mode = blend_mode(props.blend_mode);
+        self.push(Item { data, bounds, children: Vec::new() });
+    }
+
+    fn pop_clip(&mut self) {
+        self.close();
+    }
+
+    fn pop_transparency_group(&mut self) {
+        self.close();
+    }
+}
+
+fn emit(out: &mut NodeBuilder, item: Item, parent: &str, origin: (f64, f64)) {
+    let Item { mut data, bounds, children } = item;
+    data.x = Some(bounds.x - origin.0);
+    data.y = Some(bounds.y - origin.1);
+    data.width = Some(bounds.width);
+    data.height = Some(bounds.height);
+    let id = out.add(Some(parent), data);
+    for child in children {
+        emit(out, child, &id, (bounds.x, bounds.y));
+    }
+}
+
+/// Convert one page of a PDF into nodes under a FRAME sized to the page's
+/// crop box.
+pub fn import_pdf_data(data: Vec<u8>, name: &str, options: &PdfImportOptions) -> Result<ImportResult, String> {
+    let pdf = Pdf::new(Arc::new(data)).map_err(|e| match e {
+        LoadPdfError::Decryption(_) => "The file is password-protected and cannot be imported".to_string(),
+        LoadPdfError::Invalid => "Invalid PDF: the file could not be read".to_string(),
+    })?;
+    let pages = pdf.pages();
+    let number = options.page.unwrap_or(1);
+    let page = number
+        .checked_sub(1)
+        .and_then(|i| pages.get(i))
+        .ok_or_else(|| format!("Page {} does not exist; the file has {} pages", number, pages.len()))?;
+
+    let k = options.scale.filter(|s| *s > 0.0 && s.is_finite()).unwrap_or(1.0);
+    let (width, height) = page.render_dimensions();
+    let dimensions = Dimensions { width: width as f64 * k, height: height as f64 * k };
+
+    // hayro reports problems through a callback that must be Send
+    let problems = Arc::new(Mutex::new(Vec::new()));
+    let sink = problems.clone();
+    let settings = InterpreterSettings {
+        warning_sink: Arc::new(move |warning| {
+            if let Ok(mut problems) = sink.lock() {
+                problems.push(warning);
+            }
+        }),
+        ..Default::default()
+    };
+
+    let mut converter = Converter { out: NodeBuilder::default(), scopes: Vec::new() };
+    if pages.len() > 1 {
+        converter.out.warn(format!("The file has {} pages; only page {} was imported", pages.len(), number));
+    }
+    converter.open(ScopeKind::Page);
+
+    let transform = Affine::scale(k) * page.initial_transform(true).to_kurbo();
+    let bbox = kurbo::Rect::new(0.0, 0.0, dimensions.width, dimensions.height);
+    let cache = InterpreterCache::new();
+    let mut context = Context::new(transform, bbox, &cache, page.xref(), settings);
+    interpret_page(page, &mut context, &mut converter);
+
+    for problem in problems.lock().map(|p| p.clone()).unwrap_or_default() {
+        match problem {
+            InterpreterWarning::UnsupportedFont => {
+                converter.out.warn("Some text uses fonts that could not be read and was dropped")
+            }
+            InterpreterWarning::ImageDecodeFailure => {
+                converter.out.warn("Some images could not be decoded and were dropped")
+            }
+            _ => {}
+        }
+    }
+
+    // Groups and clips left open by a malformed content stream
+    while converter.scopes.len() > 1 {
+        converter.close();
+    }
+    let items = converter.scopes.pop().map(|s| s.items).unwrap_or_default();
+
+    let mut root = NodeData::new(generate_node_id(), NodeType::Frame, name);
+    root.x = Some(0.0);
+    root.y = Some(0.0);
+    root.width = Some(dimensions.width);
+    root.height = Some(dimensions.height);
+    root.clips_content = Some(true);
+    root.fills = Some(Vec::new());
+    let mut out = converter.out;
+    let root_id = out.add(None, root);
+    for item in items {
+        emit(&mut out, item, &root_id, (0.0, 0.0));
+    }
+
+    Ok(out.finish(root_id, dimensions))
+}
+
+/// Import a PDF, a PDF-compatible Illustrator file or an EPS as native
+/// vector nodes. Shadings without a native equivalent are rasterized and
+/// other unsupported constructs listed in the warnings.
+#[command(async)]
+pub fn import_pdf(path: String, options: Option<PdfImportOptions>) -> Result<ImportResult, String> {
+    let options = options.unwrap_or_default();
+    let mut data = fs::read(&path)
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+    if is_postscript(&data) {
+        let ghostscript = find_ghostscript(options.ghostscript_path.as_deref())?;
+        data = postscript_to_pdf(&path, &ghostscript)?;
+    }
+
+    let name = Path::new(&path).file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_else(|| "PDF".to_string());
+    import_pdf_data(data, &name, &options)
+}
@@ -0,0 +1,242 @@
+//! Decoding of placed raster images into formats the webview can show.
+//! PNG, JPEG and WebP are decoded here too so EXIF orientation is applied
+//! the same way for every format, rather than by each browser engine.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Cursor;
+use tauri::command;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SourceFormat {
+    Png,
+    Jpeg,
+    Webp,
+    Heic,
+    Avif,
+    Jxl,
+}
+
+impl SourceFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            SourceFormat::Png => "png",
+            SourceFormat::Jpeg => "jpeg",
+            SourceFormat::Webp => "webp",
+            SourceFormat::Heic => "heic",
+            SourceFormat::Avif => "avif",
+            SourceFormat::Jxl => "jxl",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            SourceFormat::Png => "image/png",
+            SourceFormat::Jpeg => "image/jpeg",
+            SourceFormat::Webp => "image/webp",
+            SourceFormat::Heic => "image/heic",
+            SourceFormat::Avif => "image/avif",
+            SourceFormat::Jxl => "image/jxl",
+        }
+    }
+
+    /// Sniff the format from the file's first bytes; extensions are often
+    /// wrong for photos passed around by messaging apps.
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(&[0xff, 0x0a]) || bytes.starts_with(b"\0\0\0\x0cJXL \r\n\x87\n") {
+            return Some(SourceFormat::Jxl);
+        }
+        // HEIF containers: an `ftyp` box with the major brand, a minor
+        // version, then compatible brands
+        if bytes.get(4..8) == Some(b"ftyp") {
+            let size = u32::from_be_bytes(bytes[..4].try_into().ok()?) as usize;
+            let ftyp = bytes.get(8..size.clamp(16, 256).min(bytes.len()))?;
+            let major = ftyp.get(..4)?;
+            let brands = std::iter::once(major).chain(ftyp.get(8..).unwrap_or_default().chunks_exact(4));
+            let mut heif = false;
+            for brand in brands {
+                match brand {
+                    b"avif" | b"avis" => return Some(SourceFormat::Avif),
+                    b"heic" | b"heix" | b"hevc" | b"hevx" | b"heim" | b"heis" | b"hevm" | b"hevs" => heif = true,
+                    _ => {}
+                }
+            }
+            return heif.then_some(SourceFormat::Heic);
+        }
+        match image::guess_format(bytes).ok()? {
+            ImageFormat::Png => Some(SourceFormat::Png),
+            ImageFormat::Jpeg => Some(SourceFormat::Jpeg),
+            ImageFormat::WebP => Some(SourceFormat::Webp),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DecodeImageOptions {
+    /// Return straight (not premultiplied) RGBA pixels instead of a PNG.
+    pub rgba: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DecodedImage {
+    /// Base64-encoded PNG, or RGBA rows when `rgba` was requested.
+    pub data: String,
+    pub mime_type: &'static str,
+    /// Size after orientation is applied.
+    pub width: u32,
+    pub height: u32,
+    /// Format of the source file, such as `heic`.
+    pub format: &'static str,
+}
+
+/// PNG, JPEG and WebP, turned upright by their EXIF orientation.
+fn decode_common(bytes: &[u8]) -> Result<RgbaImage, String> {
+    let mut decoder = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| format!("Failed to read image: {}", e))?
+        .into_decoder()
+        .map_err(|e| format!("Failed to decode image: {}", e))?;
+    let orientation = decoder.orientation()
+        .map_err(|e| format!("Failed to read image orientation: {}", e))?;
+    let mut image = DynamicImage::from_decoder(decoder)
+        .map_err(|e| format!("Failed to decode image: {}", e))?;
+    image.apply_orientation(orientation);
+    Ok(image.into_rgba8())
+}
+
+/// JPEG XL carries its orientation in the codestream, which the decoder
+/// applies; any EXIF orientation is ignored, as the format requires.
+fn decode_jxl(bytes: &[u8]) -> Result<RgbaImage, String> {
+    let decoder = jxl_oxide::integration::JxlDecoder::new(Cursor::new(bytes))
+        .map_err(|e| format!("Failed to decode JPEG XL image: {}", e))?;
+    let image = DynamicImage::from_decoder(decoder)
+        .map_err(|e| format!("Failed to decode JPEG XL image: {}", e))?;
+    Ok(image.into_rgba8())
+}
+
+/// HEIC and AVIF through ImageIO, which holds the system's HEVC and AV1
+/// decoders. A thumbnail at full size is requested because that is the
+/// call that applies the container's rotation and mirroring.
+#[cfg(target_os = "macos")]
+fn decode_native(bytes: &[u8], format: SourceFormat) -> Result<RgbaImage, String> {
+    use objc2_core_foundation::{CFBoolean, CFData, CFDictionary, CFNumber, CFRetained, CFString, CFType, CGPoint, CGRect, CGSize};
+    use objc2_core_graphics::{CGBitmapContextCreate, CGColorSpace, CGContext, CGImage, CGImageAlphaInfo, CGImageByteOrderInfo};
+    use objc2_image_io::{
+        kCGImageSourceCreateThumbnailFromImageAlways, kCGImageSourceCreateThumbnailWithTransform,
+        kCGImageSourceThumbnailMaxPixelSize, CGImageSource,
+    };
+
+    let failed = || format!("Failed to decode {} image", format.name().to_uppercase());
+    let data = CFData::from_bytes(bytes);
+    let source = unsafe { CGImageSource::with_data(&data, None) }.ok_or_else(failed)?;
+    // Not decoded until drawn; only its size is needed here
+    let original = unsafe { source.image_at_index(0, None) }.ok_or_else(failed)?;
+    let longest = CGImage::width(Some(&original)).max(CGImage::height(Some(&original)));
+
+    let max_size = CFNumber::new_isize(longest as isize);
+    let keys: [&CFString; 3] = unsafe {
+        [
+            kCGImageSourceCreateThumbnailFromImageAlways,
+            kCGImageSourceCreateThumbnailWithTransform,
+            kCGImageSourceThumbnailMaxPixelSize,
+        ]
+    };
+    let values: [&CFType; 3] = [CFBoolean::new(true), CFBoolean::new(true), &max_size];
+    let options: CFRetained<CFDictionary<CFString, CFType>> = CFDictionary::from_slices(&keys, &values);
+    let image = unsafe { source.thumbnail_at_index(0, Some(options.as_opaque())) }.ok_or_else(failed)?;
+
+    let (width, height) = (CGImage::width(Some(&image)), CGImage::height(Some(&image)));
+    let mut pixels = vec![0u8; width * height * 4];
+    let space = CGColorSpace::with_name(Some(unsafe { objc2_core_graphics::kCGColorSpaceSRGB })).ok_or_else(failed)?;
+    let info = CGImageAlphaInfo::PremultipliedLast.0 | CGImageByteOrderInfo::Order32Big.0;
+    let context = unsafe {
+        CGBitmapContextCreate(pixels.as_mut_ptr().cast(), width, height, 8, width * 4, Some(&space), info)
+    }
+    .ok_or_else(failed)?;
+    let rect = CGRect::new(CGPoint::ZERO, CGSize::new(width as f64, height as f64));
+    CGContext::draw_image(Some(&context), rect, Some(&image));
+    drop(context);
+
+    for p in pixels.chunks_exact_mut(4) {
+        let a = p[3] as u32;
+        if a > 0 && a < 255 {
+            p[..3].iter_mut().for_each(|c| *c = ((*c as u32 * 255 + a / 2) / a).min(255) as u8);
+        }
+    }
+    RgbaImage::from_raw(width as u32, height as u32, pixels).ok_or_else(failed)
+}
+
+#[cfg(not(target_os = "macos"))]
+fn decode_native(_bytes: &[u8], format: SourceFormat) -> Result<RgbaImage, String> {
+    Err(format!(
+        "{} images can only be decoded on macOS; convert the image to PNG or JPEG first",
+        format.name().to_uppercase()
+    ))
+}
+
+fn decode_format(bytes: &[u8], format: SourceFormat) -> Result<RgbaImage, String> {
+    match format {
+        SourceFormat::Png | SourceFormat::Jpeg | SourceFormat::Webp => decode_common(bytes),
+        SourceFormat::Jxl => decode_jxl(bytes),
+        SourceFormat::Heic | SourceFormat::Avif => decode_native(bytes, format),
+    }
+}
+
+/// Decode image bytes in any supported format, upright.
+pub fn decode(bytes: &[u8]) -> Result<RgbaImage, String> {
+    let format = SourceFormat::detect(bytes).ok_or_else(|| "Unsupported image format".to_string())?;
+    decode_format(bytes, format)
+}
+
+/// Width and height of image bytes once upright. PNG, JPEG and WebP are
+/// sized from their headers; the other formats are decoded.
+pub fn dimensions(bytes: &[u8]) -> Result<(u32, u32), String> {
+    let format = SourceFormat::detect(bytes).ok_or_else(|| "Unsupported image format".to_string())?;
+    if !matches!(format, SourceFormat::Png | SourceFormat::Jpeg | SourceFormat::Webp) {
+        return decode_format(bytes, format).map(|image| image.dimensions());
+    }
+    let mut decoder = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| format!("Failed to read image: {}", e))?
+        .into_decoder()
+        .map_err(|e| format!("Failed to read image: {}", e))?;
+    let (width, height) = decoder.dimensions();
+    let orientation = decoder.orientation()
+        .map_err(|e| format!("Failed to read image orientation: {}", e))?;
+    let turned = matches!(
+        orientation,
+        Orientation::Rotate90 | Orientation::Rotate270 | Orientation::Rotate90FlipH | Orientation::Rotate270FlipH
+    );
+    Ok(if turned { (height, width) } else { (width, height) })
+}
+
+/// Decode an image file for placing it in a document: iPhone HEIC photos,
+/// AVIF and JPEG XL assets as well as the formats the webview reads itself.
+/// The result is upright whatever orientation the file was stored in.
+#[command(async)]
+pub fn decode_image(path: String, options: Option<DecodeImageOptions>) -> Result<DecodedImage, String> {
+    let options = options.unwrap_or_default();
+    let bytes = fs::read(&path)
+        .map_err(|e| format!("Failed to read image: {}", e))?;
+    let format = SourceFormat::detect(&bytes)
+        .ok_or_else(|| format!("Unsupported image format: {}", path))?;
+    let image = decode_format(&bytes, format)?;
+    let (width, height) = image.dimensions();
+
+    let (data, mime_type) = if options.rgba {
+        (image.into_raw(), "application/octet-stream")
+    } else {
+        let mut png = Vec::new();
+        image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .map_err(|e| format!("Failed to encode PNG: {}", e))?;
+        (png, "image/png")
+    };
+
+    Ok(DecodedImage { data: BASE64.encode(data), mime_type, width, height, format: format.name() })
+}
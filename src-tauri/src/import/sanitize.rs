@@ -0,0 +1,210 @@
+//! Removing active content from SVGs that come from outside: scripts,
+//! event handlers, `foreignObject` HTML, embedded frames and references
+//! to other files or servers. Such an SVG drawn inline in the webview
+//! would run with the app's privileges, and one fetched from an outside
+//! URL tells the server the file was opened.
+//!
+//! Content is cut from the source text rather than the document being
+//! rewritten, so everything that stays is byte for byte as it was.
+
+use roxmltree::{Attribute, Document, Node, NodeType, ParsingOptions};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use tauri::command;
+
+/// Elements that embed other documents, any of which can hold script.
+const EMBEDDING_ELEMENTS: [&str; 6] = ["iframe", "embed", "object", "applet", "audio", "video"];
+
+/// Animation elements, which can set an `href` to a script URL.
+const ANIMATION_ELEMENTS: [&str; 3] = ["set", "animate", "animateTransform"];
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub enum RemovedKind {
+    Script,
+    EventHandler,
+    ForeignObject,
+    EmbeddedContent,
+    ExternalReference,
+    Stylesheet,
+    ProcessingInstruction,
+}
+
+impl RemovedKind {
+    fn describe(self, count: usize) -> String {
+        let (one, many) = match self {
+            RemovedKind::Script => ("script", "scripts"),
+            RemovedKind::EventHandler => ("event handler", "event handlers"),
+            RemovedKind::ForeignObject => ("foreignObject", "foreignObjects"),
+            RemovedKind::EmbeddedContent => ("embedded document", "embedded documents"),
+            RemovedKind::ExternalReference => ("external reference", "external references"),
+            RemovedKind::Stylesheet => ("unsafe stylesheet", "unsafe stylesheets"),
+            RemovedKind::ProcessingInstruction => ("processing instruction", "processing instructions"),
+        };
+        format!("{} {}", count, if count == 1 { one } else { many })
+    }
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RemovedContent {
+    pub kind: RemovedKind,
+    pub count: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SanitizedSvg {
+    pub svg: String,
+    pub removed: Vec<RemovedContent>,
+}
+
+impl SanitizedSvg {
+    /// One line saying what was removed, for an import's warnings.
+    pub fn warning(&self) -> Option<String> {
+        if self.removed.is_empty() {
+            return None;
+        }
+        let parts: Vec<String> = self.removed.iter().map(|r| r.kind.describe(r.count)).collect();
+        Some(format!("Removed from the SVG for safety: {}", parts.join(", ")))
+    }
+}
+
+/// `value` as a browser reads it in a URL: without the whitespace and
+/// control characters it skips, lowercased.
+fn normalized(value: &str) -> String {
+    value.chars().filter(|c| !c.is_whitespace() && !c.is_control()).collect::<String>().to_lowercase()
+}
+
+/// Whether `href` on `element` stays inside the file: a fragment, inline
+/// image data, a web link on `<a>`, or for images a file next to the SVG.
+fn safe_href(element: &str, href: &str) -> bool {
+    let href = normalized(href);
+    if href.starts_with('#') || href.starts_with("data:image/") {
+        return true;
+    }
+    if element == "a" {
+        return ["http:", "https:", "mailto:"].iter().any(|scheme| href.starts_with(scheme));
+    }
+    let has_scheme = href.split(['/', '\\']).next().is_some_and(|first| first.contains(':'));
+    let relative = !has_scheme && !href.starts_with(['/', '\\']) && !href.split(['/', '\\']).any(|part| part == "..");
+    relative && matches!(element, "image" | "feImage")
+}
+
+/// Whether a `url(...)` in CSS or a presentation attribute points outside
+/// the file.
+fn external_url(value: &str) -> bool {
+    let value = normalized(value);
+    value.match_indices("url(").any(|(start, _)| {
+        let target = value[start + 4..].trim_start_matches(['"', '\'']);
+        !(target.starts_with('#') || target.starts_with("data:image/"))
+    })
+}
+
+/// Whether CSS can load or run anything.
+fn unsafe_css(css: &str) -> bool {
+    let lower = normalized(css);
+    ["@import", "javascript:", "expression(", "behavior:", "-moz-binding"].iter().any(|s| lower.contains(s)) || external_url(css)
+}
+
+fn element_kind(node: Node) -> Option<RemovedKind> {
+    let name = node.tag_name().name();
+    match name {
+        "script" | "handler" | "listener" => Some(RemovedKind::Script),
+        "foreignObject" => Some(RemovedKind::ForeignObject),
+        _ if EMBEDDING_ELEMENTS.contains(&name) => Some(RemovedKind::EmbeddedContent),
+        _ if ANIMATION_ELEMENTS.contains(&name) => {
+            let target = node.attributes().find(|a| a.name() == "attributeName").map(|a| a.value().to_lowercase());
+            target.filter(|t| t.ends_with("href") || t.starts_with("on")).map(|_| RemovedKind::Script)
+        }
+        "style" => node.text().filter(|css| unsafe_css(css)).map(|_| RemovedKind::Stylesheet),
+        _ => None,
+    }
+}
+
+fn attribute_kind(node: Node, attribute: &Attribute) -> Option<RemovedKind> {
+    let name = attribute.name();
+    if name.to_lowercase().starts_with("on") {
+        return Some(RemovedKind::EventHandler);
+    }
+    match name {
+        "href" if !safe_href(node.tag_name().name(), attribute.value()) => Some(RemovedKind::ExternalReference),
+        "style" if unsafe_css(attribute.value()) => Some(RemovedKind::Stylesheet),
+        _ if external_url(attribute.value()) => Some(RemovedKind::ExternalReference),
+        _ => None,
+    }
+}
+
+/// `svg` with active content cut out, and what was cut. Compressed SVGZ
+/// is unpacked first.
+pub fn sanitize(data: &[u8]) -> Result<SanitizedSvg, String> {
+    let data = if data.starts_with(&[0x1f, 0x8b]) {
+        usvg::decompress_svgz(data).map_err(|e| format!("Invalid SVG: {}", e))?
+    } else {
+        data.to_vec()
+    };
+    let text = std::str::from_utf8(&data).map_err(|_| "Invalid SVG: not UTF-8 text".to_string())?;
+    let options = ParsingOptions { allow_dtd: true, ..ParsingOptions::default() };
+    let document = Document::parse_with_options(text, options).map_err(|e| format!("Invalid SVG: {}", e))?;
+    // Markup in an entity is copied wherever the entity is used, past any
+    // cut made here
+    let prologue = &text[..document.root_element().range().start];
+    if prologue.split("<!ENTITY").skip(1).any(|entity| entity.split('>').next().is_some_and(|decl| decl.contains(['<', '&']))) {
+        return Err("SVGs that hide markup in entities can't be imported safely".to_string());
+    }
+
+    let mut cuts: Vec<Range<usize>> = Vec::new();
+    let mut tally: BTreeMap<RemovedKind, usize> = BTreeMap::new();
+    // End of the last element cut, whose descendants go with it
+    let mut cut_until = 0;
+    for node in document.descendants() {
+        if node.range().start < cut_until {
+            continue;
+        }
+        let kind = match node.node_type() {
+            NodeType::PI => Some(RemovedKind::ProcessingInstruction),
+            NodeType::Element => element_kind(node),
+            _ => None,
+        };
+        if let Some(kind) = kind {
+            *tally.entry(kind).or_default() += 1;
+            cut_until = node.range().end;
+            cuts.push(node.range());
+            continue;
+        }
+        for attribute in node.attributes() {
+            if let Some(kind) = attribute_kind(node, &attribute) {
+                *tally.entry(kind).or_default() += 1;
+                cuts.push(attribute.range());
+            }
+        }
+    }
+
+    let mut svg = String::with_capacity(text.len());
+    let mut at = 0;
+    cuts.sort_by_key(|cut| cut.start);
+    for cut in cuts {
+        // Entities expand to the same source text wherever they are used
+        if cut.start < at {
+            at = at.max(cut.end);
+            continue;
+        }
+        svg.push_str(&text[at..cut.start]);
+        at = cut.end;
+    }
+    svg.push_str(&text[at..]);
+    if !tally.is_empty() && Document::parse_with_options(&svg, options).is_err() {
+        return Err("The SVG could not be made safe to import".to_string());
+    }
+
+    let removed = tally.into_iter().map(|(kind, count)| RemovedContent { kind, count }).collect();
+    Ok(SanitizedSvg { svg, removed })
+}
+
+/// SVG markup with scripts, event handlers and external references
+/// removed, for showing SVG from outside the app inline.
+#[command(async)]
+pub fn sanitize_svg(svg: String) -> Result<SanitizedSvg, String> {
+    sanitize(svg.as_bytes())
+}
@@ -0,0 +1,640 @@
+use super::{gradient_placement, paint_transform, place, unmapped_report, NodeBuilder, UnmappedFeature};
+use crate::bundle::{BundleAsset, IMAGES_DIR};
+use crate::fonts::resolve_postscript_name;
+use crate::geometry::{multiply, scale, transform_path, translate, Matrix, Rect, IDENTITY};
+use crate::model::{
+    generate_node_id, GradientStop, NodeData, NodeType, Paint, PathCommand, Rgba, ScaleMode, SerializedDocument,
+    TextStyleRange, VectorPath, WindingRule, FORMAT_VERSION,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use tauri::command;
+use zip::ZipArchive;
+
+/// Symbol instances are expanded from their master; this bounds runaway
+/// nesting.
+const MAX_SYMBOL_DEPTH: usize = 16;
+
+const BLEND_MODES: [&str; 16] = [
+    "NORMAL",
+    "DARKEN",
+    "MULTIPLY",
+    "COLOR_BURN",
+    "LIGHTEN",
+    "SCREEN",
+    "COLOR_DODGE",
+    "OVERLAY",
+    "SOFT_LIGHT",
+    "HARD_LIGHT",
+    "DIFFERENCE",
+    "EXCLUSION",
+    "HUE",
+    "SATURATION",
+    "COLOR",
+    "LUMINOSITY",
+];
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SketchImport {
+    pub document: SerializedDocument,
+    /// Embedded bitmaps, referenced from nodes by their `path`.
+    pub assets: Vec<BundleAsset>,
+    /// Sketch features with no native equivalent, most common first.
+    pub unmapped: Vec<UnmappedFeature>,
+    pub warnings: Vec<String>,
+}
+
+fn num(v: &Value, key: &str) -> Option<f64> {
+    v.get(key)?.as_f64()
+}
+
+fn flag(v: &Value, key: &str) -> bool {
+    v.get(key).and_then(Value::as_bool).unwrap_or(false)
+}
+
+fn enabled(v: &&Value) -> bool {
+    v.get("isEnabled").and_then(Value::as_bool).unwrap_or(true)
+}
+
+fn color(v: Option<&Value>) -> Rgba {
+    let c = |key, default| v.and_then(|v| num(v, key)).unwrap_or(default);
+    Rgba { r: c("red", 0.0), g: c("green", 0.0), b: c("blue", 0.0), a: c("alpha", 1.0) }
+}
+
+/// A Sketch point string, `"{0.5, 1}"`.
+fn point(v: Option<&Value>) -> Option<(f64, f64)> {
+    let s = v?.as_str()?.trim().trim_start_matches('{').trim_end_matches('}');
+    let (x, y) = s.split_once(',')?;
+    Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+}
+
+fn frame(layer: &Value) -> Rect {
+    let f = layer.get("frame");
+    let n = |key| f.and_then(|f| num(f, key)).unwrap_or(0.0);
+    Rect::new(n("x"), n("y"), n("width"), n("height"))
+}
+
+/// Local-to-parent transform. Sketch rotates counter-clockwise and flips
+/// about the center of the frame.
+fn layer_transform(layer: &Value) -> Matrix {
+    let f = frame(layer);
+    let angle = -num(layer, "rotation").unwrap_or(0.0).to_radians();
+    let (sin, cos) = angle.sin_cos();
+    let sx = if flag(layer, "isFlippedHorizontal") { -1.0 } else { 1.0 };
+    let sy = if flag(layer, "isFlippedVertical") { -1.0 } else { 1.0 };
+
+    let center = translate(f.x + f.width / 2.0, f.y + f.height / 2.0);
+    let linear = multiply(&[cos, sin, -sin, cos, 0.0, 0.0], &scale(sx, sy));
+    multiply(&center, &multiply(&linear, &translate(-f.width / 2.0, -f.height / 2.0)))
+}
+
+/// A path point with its outgoing (`from`) and incoming (`to`) control
+/// points, all in pixels.
+struct CurvePoint {
+    point: (f64, f64),
+    from: (f64, f64),
+    to: (f64, f64),
+}
+
+/// Outline of a shape layer from its curve points, which are normalized to
+/// the layer frame.
+fn shape_path(layer: &Value, size: Rect, winding_rule: WindingRule) -> Option<VectorPath> {
+    let points = layer.get("points")?.as_array()?;
+    let at = |p: (f64, f64)| (p.0 * size.width, p.1 * size.height);
+    let point_or = |v, default| point(v).map(at).unwrap_or(default);
+    let corners: Vec<CurvePoint> = points
+        .iter()
+        .filter_map(|p| {
+            let point = at(point(p.get("point"))?);
+            let from = point_or(p.get("curveFrom"), point);
+            let to = point_or(p.get("curveTo"), point);
+            Some(CurvePoint { point, from, to })
+        })
+        .collect();
+    let first = corners.first()?.point;
+
+    let mut commands = vec![PathCommand::MoveTo { x: first.0, y: first.1 }];
+    let closed = flag(layer, "isClosed");
+    let segments = if closed { corners.len() } else { corners.len() - 1 };
+    for i in 0..segments {
+        let (start, end) = (&corners[i], &corners[(i + 1) % corners.len()]);
+        let (c1, c2, p) = (start.from, end.to, end.point);
+        if c1 != start.point || c2 != p {
+            commands.push(PathCommand::CurveTo { x1: c1.0, y1: c1.1, x2: c2.0, y2: c2.1, x: p.0, y: p.1 });
+        } else if i + 1 < corners.len() {
+            // The closing line is drawn by ClosePath
+            commands.push(PathCommand::LineTo { x: p.0, y: p.1 });
+        }
+    }
+    if closed {
+        commands.push(PathCommand::ClosePath);
+    }
+    Some(VectorPath { winding_rule, commands })
+}
+
+fn winding_rule(style: Option<&Value>) -> WindingRule {
+    match style.and_then(|s| s.get("windingRule")).and_then(Value::as_u64) {
+        Some(1) => WindingRule::Evenodd,
+        _ => WindingRule::Nonzero,
+    }
+}
+
+struct Converter {
+    archive: ZipArchive<File>,
+    symbols: HashMap<String, Value>,
+    /// Archive entry -> asset path.
+    images: HashMap<String, String>,
+    assets: Vec<BundleAsset>,
+    out: NodeBuilder,
+    unmapped: BTreeMap<&'static str, usize>,
+}
+
+impl Converter {
+    fn unmapped(&mut self, feature: &'static str) {
+        *self.unmapped.entry(feature).or_default() += 1;
+    }
+
+    /// Copy an embedded bitmap into the assets, returning its asset path.
+    fn image(&mut self, reference: &str) -> Option<String> {
+        if let Some(path) = self.images.get(reference) {
+            return Some(path.clone());
+        }
+
+        // Older files reference images without their extension
+        let mut bytes = Vec::new();
+        let entry = [reference.to_string(), format!("{}.png", reference)]
+            .into_iter()
+            .find(|name| self.archive.by_name(name).is_ok_and(|mut e| e.read_to_end(&mut bytes).is_ok()))?;
+
+        let file_name = Path::new(&entry).file_name()?.to_string_lossy().into_owned();
+        let path = format!("{}{}", IMAGES_DIR, file_name);
+        self.assets.push(BundleAsset { path: path.clone(), data: BASE64.encode(&bytes) });
+        self.images.insert(reference.to_string(), path.clone());
+        Some(path)
+    }
+
+    /// `m` maps layer-local space to the node's, whose box is `to`; `size`
+    /// is the layer frame that gradient points are normalized to.
+    fn paint(&mut self, v: &Value, m: &Matrix, size: Rect, to: Rect) -> Option<Paint> {
+        let opacity = v.get("contextSettings").and_then(|c| num(c, "opacity")).unwrap_or(1.0);
+
+        match v.get("fillType").and_then(Value::as_u64).unwrap_or(0) {
+            0 => Some(Paint::Solid { visible: true, opacity, color: color(v.get("color")) }),
+            1 => {
+                let g = v.get("gradient")?;
+                let at = |p: (f64, f64)| (p.0 * size.width, p.1 * size.height);
+                let from = at(point(g.get("from")).unwrap_or((0.5, 0.0)));
+                let end = at(point(g.get("to")).unwrap_or((0.5, 1.0)));
+                let gradient_stops = g
+                    .get("stops")
+                    .and_then(Value::as_array)
+                    .map(|stops| {
+                        stops
+                            .iter()
+                            .map(|s| GradientStop { position: num(s, "position").unwrap_or(0.0), color: color(s.get("color")) })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+
+                match g.get("gradientType").and_then(Value::as_u64).unwrap_or(0) {
+                    0 => {
+                        let gradient_transform = paint_transform(&gradient_placement(from, end, None), m, to)?;
+                        Some(Paint::GradientLinear { visible: true, opacity, gradient_stops, gradient_transform })
+                    }
+                    kind => {
+                        if kind != 1 {
+                            self.unmapped("Angular gradients (imported as radial)");
+                        }
+                        let ratio = num(g, "elipseLength").filter(|r| *r > 0.0).unwrap_or(1.0);
+                        let gradient_transform = paint_transform(&gradient_placement(from, end, Some(ratio)), m, to)?;
+                        Some(Paint::GradientRadial { visible: true, opacity, gradient_stops, gradient_transform })
+                    }
+                }
+            }
+            4 => {
+                let reference = v.get("image")?.get("_ref")?.as_str()?.to_string();
+                let Some(image_ref) = self.image(&reference) else {
+                    self.out.warn("Some images are missing from the file and were dropped");
+                    return None;
+                };
+                let scale_mode = match v.get("patternFillType").and_then(Value::as_u64) {
+                    Some(0) => ScaleMode::Tile,
+                    Some(2) => ScaleMode::Crop,
+                    Some(3) => ScaleMode::Fit,
+                    _ => ScaleMode::Fill,
+                };
+                Some(Paint::Image { visible: true, opacity, image_ref, scale_mode, image_transform: IDENTITY })
+            }
+            _ => {
+                self.unmapped("Noise fills");
+                None
+            }
+        }
+    }
+
+    fn paints(&mut self, list: Option<&Value>, m: &Matrix, size: Rect, to: Rect) -> Option<Vec<Paint>> {
+        let list = list?.as_array()?;
+        Some(list.iter().filter(enabled).filter_map(|p| self.paint(p, m, size, to)).collect())
+    }
+
+    fn effects(&mut self, style: &Value) -> Vec<Value> {
+        let mut effects = Vec::new();
+        for (key, kind) in [("shadows", "DROP_SHADOW"), ("innerShadows", "INNER_SHADOW")] {
+            for s in style.get(key).and_then(Value::as_array).into_iter().flatten().filter(enabled) {
+                let c = color(s.get("color"));
+                effects.push(json!({
+                    "type": kind,
+                    "visible": true,
+                    "color": { "r": c.r, "g": c.g, "b": c.b, "a": c.a },
+                    "offset": { "x": num(s, "offsetX").unwrap_or(0.0), "y": num(s, "offsetY").unwrap_or(0.0) },
+                    "radius": num(s, "blurRadius").unwrap_or(0.0),
+                    "spread": num(s, "spread").unwrap_or(0.0),
+                }));
+            }
+        }
+        if let Some(blur) = style.get("blur").filter(|b| flag(b, "isEnabled")) {
+            let radius = num(blur, "radius").unwrap_or(0.0);
+            match blur.get("type").and_then(Value::as_u64).unwrap_or(0) {
+                0 => effects.push(json!({ "type": "BLUR", "visible": true, "radius": radius })),
+                3 => effects.push(json!({ "type": "BACKGROUND_BLUR", "visible": true, "radius": radius })),
+                _ => self.unmapped("Motion and zoom blurs"),
+            }
+        }
+        effects
+    }
+
+    /// Fills, borders and effects from the layer style.
+    fn style(&mut self, data: &mut NodeData, style: Option<&Value>, m: &Matrix, size: Rect) {
+        let Some(style) = style else { return };
+        let to = Rect::new(0.0, 0.0, data.width.unwrap_or(0.0), data.height.unwrap_or(0.0));
+
+        if let Some(context) = style.get("contextSettings") {
+            data.opacity = num(context, "opacity").filter(|o| *o < 1.0);
+            data.blend_mode = context
+                .get("blendMode")
+                .and_then(Value::as_u64)
+                .and_then(|i| BLEND_MODES.get(i as usize))
+                .filter(|mode| **mode != "NORMAL")
+                .map(|mode| mode.to_string());
+        }
+
+        data.fills = self.paints(style.get("fills"), m, size, to);
+
+        let borders: Vec<&Value> = style.get("borders").and_then(Value::as_array).into_iter().flatten().filter(enabled).collect();
+        if let Some(first) = borders.first() {
+            if borders.iter().any(|b| num(b, "thickness") != num(first, "thickness") || b.get("position") != first.get("position")) {
+                self.unmapped("Multiple border widths (imported with the first)");
+            }
+            data.stroke_weight = num(first, "thickness");
+            data.stroke_align = Some(
+                match first.get("position").and_then(Value::as_u64) {
+                    Some(1) => "INSIDE",
+                    Some(2) => "OUTSIDE",
+                    _ => "CENTER",
+                }
+                .to_string(),
+            );
+            let strokes = borders.iter().filter_map(|b| self.paint(b, m, size, to)).collect();
+            data.strokes = Some(strokes);
+        }
+
+        if let Some(options) = style.get("borderOptions") {
+            data.stroke_cap = match options.get("lineCapStyle").and_then(Value::as_u64) {
+                Some(1) => Some("ROUND".to_string()),
+                Some(2) => Some("SQUARE".to_string()),
+                _ => None,
+            };
+            data.stroke_join = match options.get("lineJoinStyle").and_then(Value::as_u64) {
+                Some(1) => Some("ROUND".to_string()),
+                Some(2) => Some("BEVEL".to_string()),
+                _ => None,
+            };
+            data.dash_pattern = options
+                .get("dashPattern")
+                .and_then(Value::as_array)
+                .map(|d| d.iter().filter_map(Value::as_f64).collect::<Vec<_>>())
+                .filter(|d| !d.is_empty());
+        }
+        if style.get("startMarkerType").and_then(Value::as_u64).unwrap_or(0) != 0
+            || style.get("endMarkerType").and_then(Value::as_u64).unwrap_or(0) != 0
+        {
+            self.unmapped("Arrowheads");
+        }
+
+        let effects = self.effects(style);
+        data.effects = Some(effects).filter(|e| !e.is_empty());
+    }
+
+    fn text(&mut self, data: &mut NodeData, layer: &Value) {
+        let attributed = layer.get("attributedString");
+        let string = attributed.and_then(|a| a.get("string")).and_then(Value::as_str).unwrap_or_default();
+
+        // Attribute ranges count UTF-16 code units
+        let mut utf16_to_char = Vec::with_capacity(string.len() + 1);
+        for (i, c) in string.chars().enumerate() {
+            utf16_to_char.extend(std::iter::repeat_n(i, c.len_utf16()));
+        }
+        let length = string.chars().count();
+        utf16_to_char.push(length);
+        let to_char = |unit: u64| utf16_to_char.get(unit as usize).copied().unwrap_or(length);
+
+        let mut styles = Vec::new();
+        let mut align = None;
+        for run in attributed.and_then(|a| a.get("attributes")).and_then(Value::as_array).into_iter().flatten() {
+            let location = run.get("location").and_then(Value::as_u64).unwrap_or(0);
+            let run_length = run.get("length").and_then(Value::as_u64).unwrap_or(0);
+            let Some(attrs) = run.get("attributes") else { continue };
+
+            let font = attrs.pointer("/MSAttributedStringFontAttribute/attributes");
+            let font_size = font.and_then(|f| num(f, "size")).unwrap_or(12.0);
+            let (font_family, font_weight) = font
+                .and_then(|f| f.get("name"))
+                .and_then(Value::as_str)
+                .map(resolve_postscript_name)
+                .unwrap_or_else(|| ("Helvetica".to_string(), 400));
+            if font.and_then(|f| f.get("name")).and_then(Value::as_str).is_some_and(|n| n.to_lowercase().contains("italic")) {
+                self.unmapped("Italic text");
+            }
+
+            let paragraph = attrs.get("paragraphStyle");
+            align = align.or_else(|| paragraph.and_then(|p| p.get("alignment")).and_then(Value::as_u64));
+            let line_height = paragraph.and_then(|p| num(p, "maximumLineHeight")).filter(|h| *h > 0.0);
+            let text_decoration = if attrs.get("underlineStyle").and_then(Value::as_u64).unwrap_or(0) != 0 {
+                Some("UNDERLINE".to_string())
+            } else if attrs.get("strikethroughStyle").and_then(Value::as_u64).unwrap_or(0) != 0 {
+                Some("STRIKETHROUGH".to_string())
+            } else {
+                None
+            };
+            if attrs.get("MSAttributedStringTextTransformAttribute").and_then(Value::as_u64).unwrap_or(0) != 0 {
+                self.unmapped("Text case transforms");
+            }
+
+            // Runs without a color are black
+            let text_color = color(attrs.get("MSAttributedStringColorAttribute"));
+            styles.push(TextStyleRange {
+                start: to_char(location),
+                end: to_char(location + run_length),
+                font_family,
+                font_weight,
+                font_size,
+                fills: vec![Paint::Solid { visible: true, opacity: 1.0, color: text_color }],
+                text_decoration,
+                letter_spacing: num(attrs, "kerning").unwrap_or(0.0),
+                line_height: line_height.map(Value::from),
+            });
+        }
+
+        if data.fills.as_ref().is_some_and(|f| !f.is_empty()) {
+            self.unmapped("Text layer fills (imported as text color)");
+        }
+        data.fills = styles.first().map(|s| s.fills.clone());
+        data.characters = Some(string.to_string());
+        data.text_styles = Some(styles);
+        data.text_align_horizontal = Some(
+            match align {
+                Some(1) => "RIGHT",
+                Some(2) => "CENTER",
+                Some(3) => "JUSTIFIED",
+                _ => "LEFT",
+            }
+            .to_string(),
+        );
+        data.text_align_vertical = match layer.pointer("/style/textStyle/verticalAlignment").and_then(Value::as_u64) {
+            Some(1) => Some("CENTER".to_string()),
+            Some(2) => Some("BOTTOM".to_string()),
+            _ => None,
+        };
+        let resize = match layer.get("textBehaviour").and_then(Value::as_u64) {
+            Some(1) => "HEIGHT",
+            Some(2) => "NONE",
+            _ => "WIDTH_AND_HEIGHT",
+        };
+        data.extra.insert("textAutoResize".to_string(), resize.into());
+    }
+
+    fn layers(&mut self, parent_layer: &Value, parent: &str, depth: usize) {
+        for layer in parent_layer.get("layers").and_then(Value::as_array).into_iter().flatten() {
+            self.layer(layer, parent, depth);
+        }
+    }
+
+    fn layer(&mut self, layer: &Value, parent: &str, depth: usize) {
+        let class = layer.get("_class").and_then(Value::as_str).unwrap_or_default();
+        let node_type = match class {
+            "artboard" => NodeType::Frame,
+            "symbolMaster" => NodeType::Component,
+            "symbolInstance" => NodeType::Instance,
+            "group" => NodeType::Group,
+            "shapeGroup" | "shapePath" => NodeType::Vector,
+            "rectangle" => NodeType::Rectangle,
+            "oval" => NodeType::Ellipse,
+            "star" => NodeType::Star,
+            "polygon" | "triangle" => NodeType::Polygon,
+            "text" => NodeType::Text,
+            "bitmap" => NodeType::Image,
+            "slice" => NodeType::Slice,
+            "MSImmutableHotspotLayer" | "hotspot" => {
+                self.unmapped("Prototype hotspots");
+                return;
+            }
+            _ => {
+                self.unmapped("Other layer types");
+                return;
+            }
+        };
+
+        let name = layer.get("name").and_then(Value::as_str).unwrap_or(class);
+        let mut data = NodeData::new(generate_node_id(), node_type, name);
+        data.visible = layer.get("isVisible").and_then(Value::as_bool).unwrap_or(true);
+        data.locked = flag(layer, "isLocked");
+
+        let size = Rect::new(0.0, 0.0, frame(layer).width, frame(layer).height);
+        let rule = winding_rule(layer.get("style"));
+        let mut paths = match node_type {
+            // A shape group is one vector combining its child shapes
+            NodeType::Vector if class == "shapeGroup" => {
+                let mut paths = Vec::new();
+                for (i, child) in layer.get("layers").and_then(Value::as_array).into_iter().flatten().enumerate() {
+                    let op = child.get("booleanOperation").and_then(Value::as_i64).unwrap_or(-1);
+                    if i > 0 && op > 0 {
+                        self.unmapped("Boolean operations (combined as union)");
+                    }
+                    let child_size = Rect::new(0.0, 0.0, frame(child).width, frame(child).height);
+                    if let Some(path) = shape_path(child, child_size, rule) {
+                        paths.push(transform_path(&path, &layer_transform(child)));
+                    }
+                }
+                Some(paths)
+            }
+            NodeType::Vector | NodeType::Star | NodeType::Polygon => Some(shape_path(layer, size, rule).into_iter().collect()),
+            _ => None,
+        };
+
+        let placement = place(&layer_transform(layer), size, paths.as_mut());
+        // Rectangles and ovals look the same flipped
+        if !placement.exact && !matches!(node_type, NodeType::Rectangle | NodeType::Ellipse) {
+            self.unmapped("Flipped layers");
+        }
+        data.x = Some(placement.x);
+        data.y = Some(placement.y);
+        data.rotation = placement.rotation;
+        data.width = Some(placement.bounds.width);
+        data.height = Some(placement.bounds.height);
+        data.vector_paths = paths;
+        let m = placement.to_local;
+
+        self.style(&mut data, layer.get("style"), &m, size);
+
+        match node_type {
+            NodeType::Frame | NodeType::Component => {
+                data.clips_content = Some(true);
+                if flag(layer, "hasBackgroundColor") {
+                    let color = color(layer.get("backgroundColor"));
+                    data.fills = Some(vec![Paint::Solid { visible: true, opacity: 1.0, color }]);
+                }
+            }
+            NodeType::Rectangle => {
+                let radii: Vec<f64> = layer
+                    .get("points")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|p| num(p, "cornerRadius"))
+                    .collect();
+                if radii.windows(2).any(|w| w[0] != w[1]) {
+                    self.unmapped("Independent corner radii (imported as uniform)");
+                }
+                // Older files keep the radius on the layer rather than its points
+                let radius = radii.first().copied().filter(|r| *r > 0.0).or_else(|| num(layer, "fixedRadius"));
+                data.corner_radius = radius.filter(|r| *r > 0.0);
+            }
+            NodeType::Text => self.text(&mut data, layer),
+            NodeType::Image => {
+                let reference = layer.get("image").and_then(|i| i.get("_ref")).and_then(Value::as_str);
+                data.image_ref = reference.and_then(|r| self.image(r));
+                if data.image_ref.is_none() {
+                    self.out.warn("Some images are missing from the file and were dropped");
+                }
+            }
+            _ => {}
+        }
+
+        if flag(layer, "hasClippingMask") {
+            self.unmapped("Masks (imported as regular layers)");
+        }
+        if layer.get("sharedStyleID").is_some() {
+            self.unmapped("Shared styles (imported as local values)");
+        }
+
+        let id = self.out.add(Some(parent), data);
+
+        match node_type {
+            NodeType::Instance => {
+                if layer.get("overrideValues").and_then(Value::as_array).is_some_and(|o| !o.is_empty()) {
+                    self.unmapped("Symbol overrides");
+                }
+                let master = layer.get("symbolID").and_then(Value::as_str).and_then(|s| self.symbols.get(s)).cloned();
+                match master {
+                    Some(_) if depth >= MAX_SYMBOL_DEPTH => self.out.warn("Deeply nested symbols were truncated"),
+                    Some(master) => self.layers(&master, &id, depth + 1),
+                    None => self.out.warn("Some symbols are missing from the file and were left empty"),
+                }
+            }
+            NodeType::Vector if class == "shapeGroup" => {}
+            _ => self.layers(layer, &id, depth),
+        }
+    }
+}
+
+fn read_json(archive: &mut ZipArchive<File>, name: &str) -> Result<Value, String> {
+    let mut entry = archive.by_name(name)
+        .map_err(|_| format!("Missing {} in Sketch file", name))?;
+    let mut bytes = Vec::new();
+    entry.read_to_end(&mut bytes)
+        .map_err(|e| format!("Failed to read {}: {}", name, e))?;
+    serde_json::from_slice(&bytes)
+        .map_err(|e| format!("Invalid {}: {}", name, e))
+}
+
+/// Collect symbol masters from every page plus symbols imported from
+/// libraries, keyed by `symbolID`.
+fn collect_symbols(layer: &Value, symbols: &mut HashMap<String, Value>) {
+    for child in layer.get("layers").and_then(Value::as_array).into_iter().flatten() {
+        if child.get("_class").and_then(Value::as_str) == Some("symbolMaster") {
+            if let Some(id) = child.get("symbolID").and_then(Value::as_str) {
+                symbols.insert(id.to_string(), child.clone());
+            }
+        }
+        collect_symbols(child, symbols);
+    }
+}
+
+#[command(async)]
+pub fn import_sketch(path: String) -> Result<SketchImport, String> {
+    let file = File::open(&path)
+        .map_err(|e| format!("Failed to open Sketch file: {}", e))?;
+    let mut archive = ZipArchive::new(file)
+        .map_err(|_| "Not a Sketch file, or saved by Sketch 42 or earlier".to_string())?;
+
+    let document = read_json(&mut archive, "document.json")?;
+    let mut pages = Vec::new();
+    for page in document.get("pages").and_then(Value::as_array).into_iter().flatten() {
+        let Some(reference) = page.get("_ref").and_then(Value::as_str) else { continue };
+        let name = if reference.ends_with(".json") { reference.to_string() } else { format!("{}.json", reference) };
+        pages.push(read_json(&mut archive, &name)?);
+    }
+
+    let mut symbols = HashMap::new();
+    for page in &pages {
+        collect_symbols(page, &mut symbols);
+    }
+    for foreign in document.get("foreignSymbols").and_then(Value::as_array).into_iter().flatten() {
+        if let Some(master) = foreign.get("symbolMaster") {
+            if let Some(id) = master.get("symbolID").and_then(Value::as_str) {
+                symbols.entry(id.to_string()).or_insert_with(|| master.clone());
+            }
+        }
+    }
+
+    let name = Path::new(&path).file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_else(|| "Untitled".to_string());
+    let mut converter = Converter {
+        archive,
+        symbols,
+        images: HashMap::new(),
+        assets: Vec::new(),
+        out: NodeBuilder::default(),
+        unmapped: BTreeMap::new(),
+    };
+
+    let root_id = converter.out.add(None, NodeData::new(generate_node_id(), NodeType::Document, name.clone()));
+    for page in &pages {
+        let page_name = page.get("name").and_then(Value::as_str).unwrap_or("Page");
+        let page_id = converter.out.add(Some(&root_id), NodeData::new(generate_node_id(), NodeType::Page, page_name));
+        converter.layers(page, &page_id, 0);
+    }
+
+    let unmapped = unmapped_report(&converter.unmapped);
+    let assets = std::mem::take(&mut converter.assets);
+    let (nodes, warnings) = converter.out.into_parts();
+    Ok(SketchImport {
+        document: SerializedDocument {
+            version: "1.0.0".to_string(),
+            format_version: FORMAT_VERSION,
+            name,
+            created_at: String::new(),
+            updated_at: String::new(),
+            nodes,
+            root_id,
+        },
+        assets,
+        unmapped,
+        warnings,
+    })
+}
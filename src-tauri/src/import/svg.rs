@@ -0,0 +1,499 @@
+use super::sanitize::sanitize;
+use super::{Dimensions, ImportResult, NodeBuilder};
+use crate::fonts::emoji::font_resolver;
+use crate::fonts::font_database;
+use crate::geometry::{invert, multiply, path_bounds, scale, scale_factor, transform_path, translate, Matrix, Rect, IDENTITY};
+use crate::model::{
+    generate_node_id, GradientStop, NodeData, NodeType, Paint, PathCommand, Rgba, ScaleMode, TextStyleRange,
+    VectorPath, WindingRule,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::Deserialize;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::command;
+use usvg::tiny_skia_path::PathSegment;
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SvgImportOptions {
+    /// Uniform scale applied to the imported content (default 1).
+    pub scale: Option<f64>,
+    /// Hoist the children of groups into their parent instead of creating
+    /// GROUP nodes.
+    #[serde(default)]
+    pub flatten_groups: bool,
+    /// Convert text to vector outlines instead of editable TEXT nodes.
+    #[serde(default)]
+    pub text_as_paths: bool,
+}
+
+fn matrix(t: usvg::Transform) -> Matrix {
+    [t.sx as f64, t.ky as f64, t.kx as f64, t.sy as f64, t.tx as f64, t.ty as f64]
+}
+
+fn is_axis_aligned(m: &Matrix) -> bool {
+    m[1].abs() < 1e-6 && m[2].abs() < 1e-6
+}
+
+fn rect(r: usvg::Rect) -> Rect {
+    Rect::new(r.x() as f64, r.y() as f64, r.width() as f64, r.height() as f64)
+}
+
+fn rgba(c: usvg::Color, alpha: f32) -> Rgba {
+    Rgba {
+        r: c.red as f64 / 255.0,
+        g: c.green as f64 / 255.0,
+        b: c.blue as f64 / 255.0,
+        a: alpha as f64,
+    }
+}
+
+fn blend_mode(mode: usvg::BlendMode) -> Option<String> {
+    use usvg::BlendMode::*;
+    let name = match mode {
+        Normal => return None,
+        Multiply => "MULTIPLY",
+        Screen => "SCREEN",
+        Overlay => "OVERLAY",
+        Darken => "DARKEN",
+        Lighten => "LIGHTEN",
+        ColorDodge => "COLOR_DODGE",
+        ColorBurn => "COLOR_BURN",
+        HardLight => "HARD_LIGHT",
+        SoftLight => "SOFT_LIGHT",
+        Difference => "DIFFERENCE",
+        Exclusion => "EXCLUSION",
+        Hue => "HUE",
+        Saturation => "SATURATION",
+        Color => "COLOR",
+        Luminosity => "LUMINOSITY",
+    };
+    Some(name.to_string())
+}
+
+fn node_name(id: &str, fallback: &str) -> String {
+    if id.is_empty() { fallback.to_string() } else { id.to_string() }
+}
+
+fn vector_path(path: &usvg::tiny_skia_path::Path, winding_rule: WindingRule) -> VectorPath {
+    let mut commands = Vec::new();
+    let (mut last, mut start) = ((0.0, 0.0), (0.0, 0.0));
+
+    for segment in path.segments() {
+        match segment {
+            PathSegment::MoveTo(p) => {
+                last = (p.x as f64, p.y as f64);
+                start = last;
+                commands.push(PathCommand::MoveTo { x: last.0, y: last.1 });
+            }
+            PathSegment::LineTo(p) => {
+                last = (p.x as f64, p.y as f64);
+                commands.push(PathCommand::LineTo { x: last.0, y: last.1 });
+            }
+            PathSegment::QuadTo(c, p) => {
+                // Degree-elevate to a cubic
+                let (cx, cy) = (c.x as f64, c.y as f64);
+                let (x, y) = (p.x as f64, p.y as f64);
+                commands.push(PathCommand::CurveTo {
+                    x1: last.0 + 2.0 / 3.0 * (cx - last.0),
+                    y1: last.1 + 2.0 / 3.0 * (cy - last.1),
+                    x2: x + 2.0 / 3.0 * (cx - x),
+                    y2: y + 2.0 / 3.0 * (cy - y),
+                    x,
+                    y,
+                });
+                last = (x, y);
+            }
+            PathSegment::CubicTo(c1, c2, p) => {
+                last = (p.x as f64, p.y as f64);
+                commands.push(PathCommand::CurveTo {
+                    x1: c1.x as f64,
+                    y1: c1.y as f64,
+                    x2: c2.x as f64,
+                    y2: c2.y as f64,
+                    x: last.0,
+                    y: last.1,
+                });
+            }
+            PathSegment::Close => {
+                last = start;
+                commands.push(PathCommand::ClosePath);
+            }
+        }
+    }
+
+    VectorPath { winding_rule, commands }
+}
+
+fn family_name(families: &[usvg::FontFamily]) -> String {
+    match families.first() {
+        Some(usvg::FontFamily::Named(name)) => name.clone(),
+        Some(generic) => generic.to_string(),
+        None => "Inter".to_string(),
+    }
+}
+
+struct Converter {
+    out: NodeBuilder,
+    flatten_groups: bool,
+    text_as_paths: bool,
+    saw_text: bool,
+}
+
+impl Converter {
+    /// Convert an SVG paint. `m` maps the element's user space to import
+    /// space and `bounds` is the node's box there, which gradient transforms
+    /// are expressed relative to.
+    fn paint(&mut self, paint: &usvg::Paint, opacity: usvg::Opacity, m: &Matrix, bounds: Rect) -> Option<Paint> {
+        let opacity = opacity.get() as f64;
+        let gradient = |base_transform: usvg::Transform, paint_to_user: Matrix| {
+            let unit = multiply(
+                &translate(bounds.x, bounds.y),
+                &scale(bounds.width.max(1e-6), bounds.height.max(1e-6)),
+            );
+            let to_unit = invert(&unit).unwrap_or(IDENTITY);
+            multiply(&to_unit, &multiply(m, &multiply(&matrix(base_transform), &paint_to_user)))
+        };
+        let stops = |stops: &[usvg::Stop]| -> Vec<GradientStop> {
+            stops
+                .iter()
+                .map(|s| GradientStop { position: s.offset().get() as f64, color: rgba(s.color(), s.opacity().get()) })
+                .collect()
+        };
+
+        match paint {
+            usvg::Paint::Color(c) => Some(Paint::Solid { visible: true, opacity, color: rgba(*c, 1.0) }),
+            usvg::Paint::LinearGradient(g) => {
+                if g.spread_method() != usvg::SpreadMethod::Pad {
+                    self.out.warn("Gradient spread methods other than pad are not supported");
+                }
+                let (x1, y1) = (g.x1() as f64, g.y1() as f64);
+                let (dx, dy) = (g.x2() as f64 - x1, g.y2() as f64 - y1);
+                // Maps (0, 0.5)-(1, 0.5) onto the gradient vector, keeping isolines perpendicular
+                let paint_to_user = [dx, dy, -dy, dx, x1 + 0.5 * dy, y1 - 0.5 * dx];
+                Some(Paint::GradientLinear {
+                    visible: true,
+                    opacity,
+                    gradient_stops: stops(g.stops()),
+                    gradient_transform: gradient(g.transform(), paint_to_user),
+                })
+            }
+            usvg::Paint::RadialGradient(g) => {
+                if g.spread_method() != usvg::SpreadMethod::Pad {
+                    self.out.warn("Gradient spread methods other than pad are not supported");
+                }
+                if g.fx() != g.cx() || g.fy() != g.cy() {
+                    self.out.warn("Radial gradient focal points are not supported; gradients were centered");
+                }
+                let (cx, cy, r) = (g.cx() as f64, g.cy() as f64, g.r().get() as f64);
+                let paint_to_user = [2.0 * r, 0.0, 0.0, 2.0 * r, cx - r, cy - r];
+                Some(Paint::GradientRadial {
+                    visible: true,
+                    opacity,
+                    gradient_stops: stops(g.stops()),
+                    gradient_transform: gradient(g.transform(), paint_to_user),
+                })
+            }
+            usvg::Paint::Pattern(_) => {
+                self.out.warn("Pattern paints are not supported and were dropped");
+                None
+            }
+        }
+    }
+
+    fn children(&mut self, group: &usvg::Group, parent: &str, origin: (f64, f64), base: &Matrix) {
+        for child in group.children() {
+            match child {
+                usvg::Node::Group(g) => self.group(g, None, parent, origin, base),
+                usvg::Node::Path(p) => self.path(p, parent, origin, base),
+                usvg::Node::Image(i) => self.image(i, parent, origin, base),
+                usvg::Node::Text(t) => self.text(t, parent, origin, base),
+            }
+        }
+    }
+
+    fn group(&mut self, g: &usvg::Group, name: Option<String>, parent: &str, origin: (f64, f64), base: &Matrix) {
+        if !g.filters().is_empty() {
+            self.out.warn("Filters are not supported and were ignored");
+        }
+        if g.clip_path().is_some() {
+            self.out.warn("Clip paths are not supported; clipped content was imported unclipped");
+        }
+        if g.mask().is_some() {
+            self.out.warn("Masks are not supported and were ignored");
+        }
+        if !g.has_children() {
+            return;
+        }
+
+        let opacity = g.opacity().get() as f64;
+        let blend = blend_mode(g.blend_mode());
+
+        // usvg adds anonymous groups for transforms, which are already baked into paths
+        let anonymous = name.is_none() && g.id().is_empty() && opacity >= 1.0 && blend.is_none();
+        if anonymous || self.flatten_groups {
+            if !anonymous {
+                self.out.warn("Group opacity and blend modes are lost when flattening groups");
+            }
+            return self.children(g, parent, origin, base);
+        }
+
+        let bounds = rect(g.abs_bounding_box()).transformed(base);
+        let mut data = NodeData::new(generate_node_id(), NodeType::Group, name.unwrap_or_else(|| node_name(g.id(), "Group")));
+        data.x = Some(bounds.x - origin.0);
+        data.y = Some(bounds.y - origin.1);
+        data.width = Some(bounds.width);
+        data.height = Some(bounds.height);
+        data.opacity = (opacity < 1.0).then_some(opacity);
+        data.blend_mode = blend;
+
+        let id = self.out.add(Some(parent), data);
+        self.children(g, &id, (bounds.x, bounds.y), base);
+    }
+
+    fn path(&mut self, p: &usvg::Path, parent: &str, origin: (f64, f64), base: &Matrix) {
+        let m = multiply(base, &matrix(p.abs_transform()));
+        let rule = match p.fill().map(|f| f.rule()) {
+            Some(usvg::FillRule::EvenOdd) => WindingRule::Evenodd,
+            _ => WindingRule::Nonzero,
+        };
+
+        let absolute = transform_path(&vector_path(p.data(), rule), &m);
+        let Some(bounds) = path_bounds(&absolute) else { return };
+
+        if p.paint_order() == usvg::PaintOrder::StrokeAndFill {
+            self.out.warn("paint-order is not supported; strokes are drawn above fills");
+        }
+
+        let mut data = NodeData::new(generate_node_id(), NodeType::Vector, node_name(p.id(), "Path"));
+        data.visible = p.is_visible();
+        data.x = Some(bounds.x - origin.0);
+        data.y = Some(bounds.y - origin.1);
+        data.width = Some(bounds.width);
+        data.height = Some(bounds.height);
+        data.vector_paths = Some(vec![transform_path(&absolute, &translate(-bounds.x, -bounds.y))]);
+        data.fills = Some(
+            p.fill()
+                .and_then(|f| self.paint(f.paint(), f.opacity(), &m, bounds))
+                .into_iter()
+                .collect(),
+        );
+        data.strokes = Some(Vec::new());
+
+        if let Some(stroke) = p.stroke() {
+            let k = scale_factor(&m);
+            data.strokes = Some(self.paint(stroke.paint(), stroke.opacity(), &m, bounds).into_iter().collect());
+            data.stroke_weight = Some(stroke.width().get() as f64 * k);
+            data.stroke_align = Some("CENTER".to_string());
+            data.stroke_cap = Some(
+                match stroke.linecap() {
+                    usvg::LineCap::Butt => "NONE",
+                    usvg::LineCap::Round => "ROUND",
+                    usvg::LineCap::Square => "SQUARE",
+                }
+                .to_string(),
+            );
+            data.stroke_join = Some(
+                match stroke.linejoin() {
+                    usvg::LineJoin::Miter | usvg::LineJoin::MiterClip => "MITER",
+                    usvg::LineJoin::Round => "ROUND",
+                    usvg::LineJoin::Bevel => "BEVEL",
+                }
+                .to_string(),
+            );
+            data.stroke_miter_limit = Some(stroke.miterlimit().get() as f64);
+            if let Some(dashes) = stroke.dasharray() {
+                data.dash_pattern = Some(dashes.iter().map(|d| *d as f64 * k).collect());
+                data.dash_offset = Some(stroke.dashoffset() as f64 * k);
+            }
+        }
+
+        self.out.add(Some(parent), data);
+    }
+
+    fn image(&mut self, image: &usvg::Image, parent: &str, origin: (f64, f64), base: &Matrix) {
+        let m = multiply(base, &matrix(image.abs_transform()));
+        let size = image.size();
+
+        let (mime, bytes) = match image.kind() {
+            usvg::ImageKind::SVG(tree) => {
+                // Nested SVG documents are imported inline as a group
+                let fit = scale(
+                    size.width() as f64 / tree.size().width() as f64,
+                    size.height() as f64 / tree.size().height() as f64,
+                );
+                let name = node_name(image.id(), "Image");
+                return self.group(tree.root(), Some(name), parent, origin, &multiply(&m, &fit));
+            }
+            usvg::ImageKind::PNG(data) => ("image/png", data),
+            usvg::ImageKind::JPEG(data) => ("image/jpeg", data),
+            usvg::ImageKind::GIF(data) => ("image/gif", data),
+            usvg::ImageKind::WEBP(data) => ("image/webp", data),
+        };
+
+        if !is_axis_aligned(&m) {
+            self.out.warn("Rotated or skewed images were imported axis-aligned");
+        }
+        let bounds = Rect::new(0.0, 0.0, size.width() as f64, size.height() as f64).transformed(&m);
+
+        let mut data = NodeData::new(generate_node_id(), NodeType::Image, node_name(image.id(), "Image"));
+        data.visible = image.is_visible();
+        data.x = Some(bounds.x - origin.0);
+        data.y = Some(bounds.y - origin.1);
+        data.width = Some(bounds.width);
+        data.height = Some(bounds.height);
+        data.image_ref = Some(format!("data:{};base64,{}", mime, BASE64.encode(bytes.as_slice())));
+        data.scale_mode = Some(ScaleMode::Fill);
+
+        self.out.add(Some(parent), data);
+    }
+
+    fn text(&mut self, text: &usvg::Text, parent: &str, origin: (f64, f64), base: &Matrix) {
+        self.saw_text = true;
+        let characters = text.chunks().iter().map(usvg::TextChunk::text).collect::<Vec<_>>().join("\n");
+        let name = if text.id().is_empty() {
+            characters.lines().next().unwrap_or("Text").chars().take(40).collect()
+        } else {
+            text.id().to_string()
+        };
+
+        if self.text_as_paths {
+            return self.group(text.flattened(), Some(name), parent, origin, base);
+        }
+
+        let m = multiply(base, &matrix(text.abs_transform()));
+        if !is_axis_aligned(&m) {
+            self.out.warn("Rotated or skewed text was imported axis-aligned");
+        }
+        if text.writing_mode() != usvg::WritingMode::LeftToRight {
+            self.out.warn("Vertical text was imported as horizontal text");
+        }
+        if [text.dx(), text.dy(), text.rotate()].iter().any(|v| v.iter().any(|d| *d != 0.0)) {
+            self.out.warn("Per-character text positioning was dropped");
+        }
+
+        let bounds = rect(text.abs_bounding_box()).transformed(base);
+        let k = scale_factor(&m);
+        let mut styles = Vec::new();
+        let mut offset = 0;
+
+        for chunk in text.chunks() {
+            if matches!(chunk.text_flow(), usvg::TextFlow::Path(_)) {
+                self.out.warn("Text on a path was imported as plain text");
+            }
+            let source = chunk.text();
+            for span in chunk.spans() {
+                if span.stroke().is_some() {
+                    self.out.warn("Text strokes are not supported and were dropped");
+                }
+                let decoration = span.decoration();
+                if decoration.overline().is_some() {
+                    self.out.warn("Overlines are not supported and were dropped");
+                }
+                let text_decoration = if decoration.underline().is_some() {
+                    Some("UNDERLINE")
+                } else if decoration.line_through().is_some() {
+                    Some("STRIKETHROUGH")
+                } else {
+                    None
+                };
+
+                styles.push(TextStyleRange {
+                    start: offset + source[..span.start()].chars().count(),
+                    end: offset + source[..span.end()].chars().count(),
+                    font_family: family_name(span.font().families()),
+                    font_weight: span.font().weight(),
+                    font_size: span.font_size().get() as f64 * k,
+                    fills: span
+                        .fill()
+                        .and_then(|f| self.paint(f.paint(), f.opacity(), &m, bounds))
+                        .into_iter()
+                        .collect(),
+                    text_decoration: text_decoration.map(str::to_string),
+                    letter_spacing: span.letter_spacing() as f64 * k,
+                    line_height: None,
+                });
+            }
+            offset += source.chars().count() + 1;
+        }
+
+        let align = match text.chunks().first().map(usvg::TextChunk::anchor) {
+            Some(usvg::TextAnchor::Middle) => "CENTER",
+            Some(usvg::TextAnchor::End) => "RIGHT",
+            _ => "LEFT",
+        };
+
+        let mut data = NodeData::new(generate_node_id(), NodeType::Text, name);
+        data.x = Some(bounds.x - origin.0);
+        data.y = Some(bounds.y - origin.1);
+        data.width = Some(bounds.width);
+        data.height = Some(bounds.height);
+        data.fills = styles.first().map(|s| s.fills.clone());
+        data.characters = Some(characters);
+        data.text_styles = Some(styles);
+        data.text_align_horizontal = Some(align.to_string());
+        data.extra.insert("textAutoResize".to_string(), Value::from("WIDTH_AND_HEIGHT"));
+
+        self.out.add(Some(parent), data);
+    }
+}
+
+/// Convert SVG source into nodes under a FRAME sized to the SVG viewport.
+/// Scripts and other active content are removed first, with a warning.
+pub fn import_svg_data(
+    data: &[u8],
+    name: &str,
+    resources_dir: Option<PathBuf>,
+    options: &SvgImportOptions,
+) -> Result<ImportResult, String> {
+    let sanitized = sanitize(data)?;
+    let usvg_options = usvg::Options { resources_dir, fontdb: font_database(), font_resolver: font_resolver(), ..Default::default() };
+    let tree = usvg::Tree::from_str(&sanitized.svg, &usvg_options)
+        .map_err(|e| format!("Invalid SVG: {}", e))?;
+
+    let k = options.scale.filter(|s| *s > 0.0 && s.is_finite()).unwrap_or(1.0);
+    let dimensions = Dimensions {
+        width: tree.size().width() as f64 * k,
+        height: tree.size().height() as f64 * k,
+    };
+
+    let mut converter = Converter {
+        out: NodeBuilder::default(),
+        flatten_groups: options.flatten_groups,
+        text_as_paths: options.text_as_paths,
+        saw_text: false,
+    };
+
+    let mut root = NodeData::new(generate_node_id(), NodeType::Frame, name);
+    root.x = Some(0.0);
+    root.y = Some(0.0);
+    root.width = Some(dimensions.width);
+    root.height = Some(dimensions.height);
+    root.clips_content = Some(true);
+    root.fills = Some(Vec::new());
+    let root_id = converter.out.add(None, root);
+    if let Some(warning) = sanitized.warning() {
+        converter.out.warn(warning);
+    }
+
+    converter.children(tree.root(), &root_id, (0.0, 0.0), &scale(k, k));
+
+    // usvg drops text it cannot lay out, which happens when no font matches
+    if !converter.saw_text && data.windows(5).any(|w| w == b"<text") {
+        converter.out.warn("Some text was dropped because no matching fonts are installed");
+    }
+
+    Ok(converter.out.finish(root_id, dimensions))
+}
+
+#[command(async)]
+pub fn import_svg(path: String, options: Option<SvgImportOptions>) -> Result<ImportResult, String> {
+    let data = fs::read(&path)
+        .map_err(|e| format!("Failed to read SVG: {}", e))?;
+
+    let path = Path::new(&path);
+    let name = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_else(|| "SVG".to_string());
+    import_svg_data(&data, &name, path.parent().map(Path::to_path_buf), &options.unwrap_or_default())
+}
@@ -0,0 +1,258 @@
+//! Tracing of raster images into filled vector shapes, for vectorizing
+//! logos and sketches.
+//!
+//! As in potrace, the image is reduced to one or more bitmaps, the
+//! boundaries of each bitmap's regions are followed along pixel edges, and
+//! the resulting outlines are smoothed into curves with corners kept where
+//! the outline turns sharply.
+
+mod outline;
+
+use super::raster::decode;
+use super::{Dimensions, ImportResult, NodeBuilder};
+use crate::geometry::{path_bounds, transform_path, translate};
+use crate::model::{generate_node_id, NodeData, NodeType, Paint, Rgba, VectorPath, WindingRule};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use image::RgbaImage;
+use outline::{outlines, trace_outline, Bitmap};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use tauri::command;
+
+/// Pixels at least this opaque are traced; the rest are background.
+const OPAQUE: u8 = 128;
+/// Pixels sampled when choosing colors, which is plenty to find a palette.
+const PALETTE_SAMPLES: usize = 20_000;
+const PALETTE_ITERATIONS: usize = 12;
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TraceMode {
+    /// Dark pixels are traced into one black shape.
+    #[default]
+    Monochrome,
+    /// The image is posterized to `colors` colors, each traced into a shape
+    /// of its own.
+    Color,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TraceOptions {
+    pub mode: TraceMode,
+    /// Luminance from 0 to 1 below which pixels are traced, in monochrome
+    /// mode.
+    pub threshold: f64,
+    /// Trace light pixels rather than dark ones, in monochrome mode.
+    pub invert: bool,
+    /// How many colors the image is reduced to, in color mode.
+    pub colors: usize,
+    /// Regions and holes of fewer pixels than this are dropped as specks.
+    pub speckle: f64,
+    /// How readily corners are rounded, from 0 for none to 4/3 for all of
+    /// them.
+    pub smoothness: f64,
+}
+
+impl Default for TraceOptions {
+    fn default() -> Self {
+        TraceOptions {
+            mode: TraceMode::Monochrome,
+            threshold: 0.5,
+            invert: false,
+            colors: 6,
+            speckle: 3.0,
+            smoothness: 1.0,
+        }
+    }
+}
+
+/// Smooth curves around the regions of `bitmap`, without specks smaller
+/// than `options.speckle`, as one path for the nonzero rule.
+fn trace_bitmap(bitmap: &Bitmap, options: &TraceOptions) -> VectorPath {
+    let mut path = VectorPath { winding_rule: WindingRule::Nonzero, commands: Vec::new() };
+    for outline in outlines(bitmap) {
+        if outline.area().abs() >= options.speckle {
+            trace_outline(&outline, options.smoothness, &mut path.commands);
+        }
+    }
+    path
+}
+
+fn luminance(c: [f64; 3]) -> f64 {
+    (0.2126 * c[0] + 0.7152 * c[1] + 0.0722 * c[2]) / 255.0
+}
+
+/// D65 white, which sRGB is relative to.
+const WHITE: [f64; 3] = [0.950_47, 1.0, 1.088_83];
+
+/// CIELAB coordinates of an sRGB color, where distances follow how
+/// different colors look, so anti-aliased edges match the colors
+/// either side of them rather than whichever is nearest numerically.
+fn to_lab(c: [f64; 3]) -> [f64; 3] {
+    let [r, g, b] = c.map(|v| {
+        let v = v / 255.0;
+        if v <= 0.040_45 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) }
+    });
+    let xyz = [
+        0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b,
+        0.212_672_9 * r + 0.715_152_2 * g + 0.072_175_0 * b,
+        0.019_333_9 * r + 0.119_192_0 * g + 0.950_304_1 * b,
+    ];
+    let [fx, fy, fz] = [0, 1, 2].map(|i| {
+        let t = xyz[i] / WHITE[i];
+        if t > 216.0 / 24389.0 { t.cbrt() } else { (24389.0 / 27.0 * t + 16.0) / 116.0 }
+    });
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+fn from_lab(lab: [f64; 3]) -> Rgba {
+    let fy = (lab[0] + 16.0) / 116.0;
+    let f = [fy + lab[1] / 500.0, fy, fy - lab[2] / 200.0];
+    let [x, y, z] = [0, 1, 2].map(|i| {
+        let t = if f[i] > 6.0 / 29.0 { f[i].powi(3) } else { (116.0 * f[i] - 16.0) * 27.0 / 24389.0 };
+        t * WHITE[i]
+    });
+    let [r, g, b] = [
+        3.240_454_2 * x - 1.537_138_5 * y - 0.498_531_4 * z,
+        -0.969_266_0 * x + 1.876_010_8 * y + 0.041_556_0 * z,
+        0.055_643_4 * x - 0.204_025_9 * y + 1.057_225_2 * z,
+    ]
+    .map(|v: f64| {
+        let v = v.clamp(0.0, 1.0);
+        if v <= 0.003_130_8 { v * 12.92 } else { 1.055 * v.powf(1.0 / 2.4) - 0.055 }
+    });
+    Rgba { r, g, b, a: 1.0 }
+}
+
+fn distance_sq(a: [f64; 3], b: [f64; 3]) -> f64 {
+    (0..3).map(|i| (a[i] - b[i]) * (a[i] - b[i])).sum()
+}
+
+fn nearest(palette: &[[f64; 3]], c: [f64; 3]) -> usize {
+    (0..palette.len()).min_by(|a, b| distance_sq(palette[*a], c).total_cmp(&distance_sq(palette[*b], c))).unwrap_or(0)
+}
+
+/// Up to `count` colors that represent `colors` well, by k-means from
+/// colors chosen far apart, so that small areas of a distinct color, such
+/// as a logo's accent, keep a color of their own.
+fn palette(colors: &[[f64; 3]], count: usize) -> Vec<[f64; 3]> {
+    let step = colors.len().div_ceil(PALETTE_SAMPLES).max(1);
+    let samples: Vec<[f64; 3]> = colors.iter().step_by(step).copied().collect();
+    let Some(&first) = samples.first() else { return Vec::new() };
+
+    let mut centers = vec![first];
+    while centers.len() < count {
+        let farthest = samples
+            .iter()
+            .map(|c| (*c, centers.iter().map(|center| distance_sq(*center, *c)).fold(f64::MAX, f64::min)))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        match farthest {
+            Some((c, d)) if d > 0.0 => centers.push(c),
+            _ => break,
+        }
+    }
+
+    for _ in 0..PALETTE_ITERATIONS {
+        let mut sums = vec![([0.0; 3], 0usize); centers.len()];
+        for c in &samples {
+            let (sum, n) = &mut sums[nearest(&centers, *c)];
+            (0..3).for_each(|i| sum[i] += c[i]);
+            *n += 1;
+        }
+        centers = sums.into_iter().filter(|(_, n)| *n > 0).map(|(sum, n)| sum.map(|v| v / n as f64)).collect();
+    }
+    centers
+}
+
+/// A VECTOR node filling `path` with `color`, placed at its bounds.
+fn shape_node(path: VectorPath, color: Rgba, name: &str) -> Option<NodeData> {
+    let bounds = path_bounds(&path)?;
+    let mut data = NodeData::new(generate_node_id(), NodeType::Vector, name);
+    data.x = Some(bounds.x);
+    data.y = Some(bounds.y);
+    data.width = Some(bounds.width);
+    data.height = Some(bounds.height);
+    data.vector_paths = Some(vec![transform_path(&path, &translate(-bounds.x, -bounds.y))]);
+    data.fills = Some(vec![Paint::Solid { visible: true, opacity: 1.0, color }]);
+    data.strokes = Some(Vec::new());
+    Some(data)
+}
+
+/// Trace `image` into shapes under a FRAME the size of the image, one
+/// pixel to a unit.
+pub fn trace(image: &RgbaImage, name: &str, options: &TraceOptions) -> ImportResult {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let dimensions = Dimensions { width: width as f64, height: height as f64 };
+    let mut out = NodeBuilder::default();
+    let mut root = NodeData::new(generate_node_id(), NodeType::Frame, name);
+    root.x = Some(0.0);
+    root.y = Some(0.0);
+    root.width = Some(dimensions.width);
+    root.height = Some(dimensions.height);
+    root.fills = Some(Vec::new());
+    let root_id = out.add(None, root);
+
+    let pixels: Vec<Option<[f64; 3]>> = image
+        .pixels()
+        .map(|p| (p[3] >= OPAQUE).then(|| [p[0] as f64, p[1] as f64, p[2] as f64]))
+        .collect();
+
+    let mut layers: Vec<(Bitmap, Rgba, String)> = Vec::new();
+    match options.mode {
+        TraceMode::Monochrome => {
+            let filled = pixels.iter().map(|c| c.is_some_and(|c| (luminance(c) < options.threshold) != options.invert)).collect();
+            layers.push((Bitmap { width, height, filled }, Rgba { r: 0.0, g: 0.0, b: 0.0, a: 1.0 }, "Trace".to_string()));
+        }
+        TraceMode::Color => {
+            let pixels: Vec<Option<[f64; 3]>> = pixels.iter().map(|c| c.map(to_lab)).collect();
+            let opaque: Vec<[f64; 3]> = pixels.iter().flatten().copied().collect();
+            let mut colors = palette(&opaque, options.colors.max(1));
+            // Lighter colors go underneath and reach under the darker ones
+            // above them, so no seams show between neighbouring colors
+            colors.sort_by(|a, b| b[0].total_cmp(&a[0]));
+            let ranks: Vec<Option<usize>> = pixels.iter().map(|c| c.map(|c| nearest(&colors, c))).collect();
+            for (rank, color) in colors.iter().enumerate() {
+                let filled = ranks.iter().map(|r| r.is_some_and(|r| r >= rank)).collect();
+                let color = from_lab(*color);
+                let name = format!("#{:02X}{:02X}{:02X}", (color.r * 255.0).round() as u8, (color.g * 255.0).round() as u8, (color.b * 255.0).round() as u8);
+                layers.push((Bitmap { width, height, filled }, color, name));
+            }
+        }
+    }
+
+    let mut traced = false;
+    for (bitmap, color, name) in layers {
+        if let Some(node) = shape_node(trace_bitmap(&bitmap, options), color, &name) {
+            out.add(Some(&root_id), node);
+            traced = true;
+        }
+    }
+    if !traced {
+        out.warn("Nothing in the image matched the trace settings");
+    }
+    out.finish(root_id, dimensions)
+}
+
+/// Trace an image file at `path`, or base64 image `data`, into vector
+/// shapes: black shapes for the dark areas, or one shape for each color
+/// of the posterized image.
+#[command(async)]
+pub fn trace_image(path: Option<String>, data: Option<String>, options: Option<TraceOptions>) -> Result<ImportResult, String> {
+    let options = options.unwrap_or_default();
+    if !(options.smoothness.is_finite() && options.smoothness >= 0.0) {
+        return Err(format!("Invalid smoothness: {}", options.smoothness));
+    }
+    let (bytes, name) = match (path, data) {
+        (Some(path), _) => {
+            let bytes = fs::read(&path).map_err(|e| format!("Failed to read image: {}", e))?;
+            let name = Path::new(&path).file_stem().map(|s| s.to_string_lossy().into_owned());
+            (bytes, name)
+        }
+        (None, Some(data)) => (BASE64.decode(data).map_err(|e| format!("Failed to decode image data: {}", e))?, None),
+        (None, None) => return Err("No image to trace".to_string()),
+    };
+    let image = decode(&bytes)?;
+    Ok(trace(&image, &name.unwrap_or_else(|| "Traced image".to_string()), &options))
+}
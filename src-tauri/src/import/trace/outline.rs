@@ -0,0 +1,527 @@
+//! Outlines of the regions of a bitmap as curves, after potrace: each
+//! boundary is followed along pixel edges, reduced to the polygon with the
+//! fewest sides that stays within half a pixel of it, and the polygon's
+//! corners are then rounded off or kept sharp depending on how sharply it
+//! turns there.
+
+use crate::model::PathCommand;
+use kurbo::{Point, Vec2};
+
+/// The pixels to trace, row by row.
+pub struct Bitmap {
+    pub width: usize,
+    pub height: usize,
+    pub filled: Vec<bool>,
+}
+
+impl Bitmap {
+    fn at(&self, x: i64, y: i64) -> bool {
+        x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height && self.filled[y as usize * self.width + x as usize]
+    }
+}
+
+/// Steps along pixel edges: east, south, west and north, so that adding
+/// one turns clockwise on screen.
+const STEPS: [(i64, i64); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+/// Stands in for an unbounded distance along a boundary.
+const UNBOUNDED: i64 = 10_000_000;
+
+type Corner = (i64, i64);
+
+/// A closed boundary between filled and empty pixels, as the pixel corners
+/// it passes, one unit step apart. Filled pixels are on the right.
+pub struct Outline {
+    pub corners: Vec<Corner>,
+}
+
+impl Outline {
+    /// Enclosed area in pixels, the sign depending on whether it runs
+    /// around a region or a hole.
+    pub fn area(&self) -> f64 {
+        let n = self.corners.len();
+        let twice: i64 = (0..n)
+            .map(|i| {
+                let (a, b) = (self.corners[i], self.corners[(i + 1) % n]);
+                a.0 * b.1 - b.0 * a.1
+            })
+            .sum();
+        twice as f64 / 2.0
+    }
+}
+
+/// Every boundary in `bitmap`. Pixels that touch only at a corner are
+/// joined, so thin diagonal lines stay in one piece.
+pub fn outlines(bitmap: &Bitmap) -> Vec<Outline> {
+    let stride = bitmap.width + 1;
+    let corner = |x: i64, y: i64| y as usize * stride + x as usize;
+    // Directions of the boundary steps leaving each pixel corner, as bits
+    let mut leaving = vec![0u8; stride * (bitmap.height + 1)];
+    for y in 0..bitmap.height as i64 {
+        for x in 0..bitmap.width as i64 {
+            if !bitmap.at(x, y) {
+                continue;
+            }
+            if !bitmap.at(x, y - 1) {
+                leaving[corner(x, y)] |= 1 << 0;
+            }
+            if !bitmap.at(x + 1, y) {
+                leaving[corner(x + 1, y)] |= 1 << 1;
+            }
+            if !bitmap.at(x, y + 1) {
+                leaving[corner(x + 1, y + 1)] |= 1 << 2;
+            }
+            if !bitmap.at(x - 1, y) {
+                leaving[corner(x, y + 1)] |= 1 << 3;
+            }
+        }
+    }
+
+    // Where two boundaries meet at a corner, turning left keeps to the
+    // pixel across the corner
+    let next = |bits: u8, arriving: u8| [3, 0, 1].map(|turn| (arriving + turn) % 4).into_iter().find(|d| bits & (1 << d) != 0);
+    let mut unused = leaving.clone();
+    let mut found = Vec::new();
+    for y in 0..=bitmap.height as i64 {
+        for x in 0..=bitmap.width as i64 {
+            while unused[corner(x, y)] != 0 {
+                let first = unused[corner(x, y)].trailing_zeros() as u8;
+                let mut corners = Vec::new();
+                let (mut at, mut dir) = ((x, y), first);
+                loop {
+                    unused[corner(at.0, at.1)] &= !(1 << dir);
+                    corners.push(at);
+                    at = (at.0 + STEPS[dir as usize].0, at.1 + STEPS[dir as usize].1);
+                    match next(leaving[corner(at.0, at.1)], dir) {
+                        Some(d) if at == (x, y) && d == first => break,
+                        Some(d) if unused[corner(at.0, at.1)] & (1 << d) != 0 => dir = d,
+                        _ => break,
+                    }
+                }
+                found.push(Outline { corners });
+            }
+        }
+    }
+    found
+}
+
+fn cross(a: Corner, b: Corner) -> i64 {
+    a.0 * b.1 - a.1 * b.0
+}
+
+/// Whether `b` is in the cyclic range from `a` up to but not including `c`.
+fn cyclic(a: usize, b: usize, c: usize) -> bool {
+    if a <= c {
+        a <= b && b < c
+    } else {
+        a <= b || b < c
+    }
+}
+
+/// Running sums of the corners' coordinates, their squares and products,
+/// relative to the first corner, for fitting lines to any stretch of the
+/// outline in constant time.
+struct Sums {
+    origin: Corner,
+    /// `[x, y, x², xy, y²]` over the corners before each index.
+    totals: Vec<[f64; 5]>,
+}
+
+impl Sums {
+    fn new(corners: &[Corner]) -> Self {
+        let origin = corners[0];
+        let mut totals = Vec::with_capacity(corners.len() + 1);
+        let mut total = [0.0; 5];
+        totals.push(total);
+        for p in corners {
+            let (x, y) = ((p.0 - origin.0) as f64, (p.1 - origin.1) as f64);
+            for (t, v) in total.iter_mut().zip([x, y, x * x, x * y, y * y]) {
+                *t += v;
+            }
+            totals.push(total);
+        }
+        Sums { origin, totals }
+    }
+
+    /// Totals over corners `i..=j`, where `j` may have wrapped around the
+    /// outline up to `laps` times, and how many corners that is.
+    fn range(&self, i: usize, j: usize, laps: usize) -> ([f64; 5], f64) {
+        let n = self.totals.len() - 1;
+        let mut total = [0.0; 5];
+        for (c, t) in total.iter_mut().enumerate() {
+            *t = self.totals[j + 1][c] - self.totals[i][c] + laps as f64 * self.totals[n][c];
+        }
+        (total, (j + 1 + laps * n - i) as f64)
+    }
+}
+
+/// For each corner, the furthest corner the outline can run straight to,
+/// meaning within half a pixel of a line and without turning back.
+fn straight_reach(corners: &[Corner]) -> Vec<usize> {
+    let n = corners.len();
+    let sign = |v: i64| v.signum();
+
+    // The next corner that differs from each in both coordinates
+    let mut next_turn = vec![0; n];
+    let mut k = 0;
+    for i in (0..n).rev() {
+        if corners[i].0 != corners[k].0 && corners[i].1 != corners[k].1 {
+            k = i + 1;
+        }
+        next_turn[i] = k;
+    }
+
+    let mut pivot = vec![0; n];
+    for i in (0..n).rev() {
+        // Which of the four directions have been stepped in; a stretch that
+        // has gone all four ways has turned back on itself
+        let mut stepped = [false; 4];
+        let step = |a: Corner, b: Corner| ((3 + 3 * sign(b.0 - a.0) + sign(b.1 - a.1)) / 2) as usize;
+        stepped[step(corners[i], corners[(i + 1) % n])] = true;
+        let mut constraint = [(0, 0); 2];
+        let (mut k, mut k1) = (next_turn[i], i);
+        let turned_back = loop {
+            stepped[step(corners[k1], corners[k])] = true;
+            if stepped.iter().all(|s| *s) {
+                break true;
+            }
+            let cur = (corners[k].0 - corners[i].0, corners[k].1 - corners[i].1);
+            if cross(constraint[0], cur) < 0 || cross(constraint[1], cur) > 0 {
+                break false;
+            }
+            // Narrow the cone of directions a straight line from `i` may
+            // take to pass within half a pixel of every corner so far
+            if cur.0.abs() > 1 || cur.1.abs() > 1 {
+                let off = (
+                    cur.0 + if cur.1 >= 0 && (cur.1 > 0 || cur.0 < 0) { 1 } else { -1 },
+                    cur.1 + if cur.0 <= 0 && (cur.0 < 0 || cur.1 < 0) { 1 } else { -1 },
+                );
+                if cross(constraint[0], off) >= 0 {
+                    constraint[0] = off;
+                }
+                let off = (
+                    cur.0 + if cur.1 <= 0 && (cur.1 < 0 || cur.0 < 0) { 1 } else { -1 },
+                    cur.1 + if cur.0 >= 0 && (cur.0 > 0 || cur.1 < 0) { 1 } else { -1 },
+                );
+                if cross(constraint[1], off) <= 0 {
+                    constraint[1] = off;
+                }
+            }
+            k1 = k;
+            k = next_turn[k1];
+            if !cyclic(k, i, k1) {
+                break false;
+            }
+        };
+        if turned_back {
+            pivot[i] = k1;
+            continue;
+        }
+
+        // The line leaves the cone somewhere between `k1` and `k`
+        let dk = (sign(corners[k].0 - corners[k1].0), sign(corners[k].1 - corners[k1].1));
+        let cur = (corners[k1].0 - corners[i].0, corners[k1].1 - corners[i].1);
+        let (a, b) = (cross(constraint[0], cur), cross(constraint[0], dk));
+        let (c, d) = (cross(constraint[1], cur), cross(constraint[1], dk));
+        let mut j = UNBOUNDED;
+        if b < 0 {
+            j = a.div_euclid(-b);
+        }
+        if d > 0 {
+            j = j.min((-c).div_euclid(d));
+        }
+        pivot[i] = (k1 as i64 + j).rem_euclid(n as i64) as usize;
+    }
+
+    // Every corner of a straight stretch can reach at least as far as the
+    // ones after it
+    let mut reach = vec![0; n];
+    let mut j = pivot[n - 1];
+    reach[n - 1] = j;
+    for i in (0..n - 1).rev() {
+        if cyclic(i + 1, pivot[i], j) {
+            j = pivot[i];
+        }
+        reach[i] = j;
+    }
+    let mut i = n - 1;
+    while cyclic((i + 1) % n, j, reach[i]) {
+        reach[i] = j;
+        if i == 0 {
+            break;
+        }
+        i -= 1;
+    }
+    reach
+}
+
+/// How far the corners `i..=j` stray from the line between the two, in a
+/// least-squares sense. `j` may be past the end, wrapping around.
+fn penalty(corners: &[Corner], sums: &Sums, i: usize, j: usize) -> f64 {
+    let n = corners.len();
+    let (j, laps) = if j >= n { (j - n, 1) } else { (j, 0) };
+    let (s, k) = sums.range(i, j, laps);
+    let px = (corners[i].0 + corners[j].0) as f64 / 2.0 - sums.origin.0 as f64;
+    let py = (corners[i].1 + corners[j].1) as f64 / 2.0 - sums.origin.1 as f64;
+    let ey = (corners[j].0 - corners[i].0) as f64;
+    let ex = -(corners[j].1 - corners[i].1) as f64;
+    let a = (s[2] - 2.0 * s[0] * px) / k + px * px;
+    let b = (s[3] - s[0] * py - s[1] * px) / k + px * py;
+    let c = (s[4] - 2.0 * s[1] * py) / k + py * py;
+    (ex * ex * a + 2.0 * ex * ey * b + ey * ey * c).max(0.0).sqrt()
+}
+
+/// The corners the polygon with the fewest sides passes through, the
+/// closest fit among those with as few.
+fn best_polygon(corners: &[Corner], sums: &Sums) -> Vec<usize> {
+    let n = corners.len();
+    let reach = straight_reach(corners);
+
+    // The furthest each corner can go in one side, and back
+    let mut forward = vec![0; n];
+    for i in 0..n {
+        let mut c = (reach[(i + n - 1) % n] + n - 1) % n;
+        if c == i {
+            c = (i + 1) % n;
+        }
+        forward[i] = if c < i { n } else { c };
+    }
+    let mut backward = vec![0; n + 1];
+    let mut j = 1;
+    for (i, &f) in forward.iter().enumerate() {
+        while j <= f {
+            backward[j] = i;
+            j += 1;
+        }
+    }
+
+    // The furthest from the start each number of sides gets, which bounds
+    // where the polygon's corners can be
+    let mut furthest = Vec::new();
+    let mut i = 0;
+    while i < n {
+        furthest.push(i);
+        i = forward[i];
+    }
+    furthest.push(n);
+    let sides = furthest.len() - 1;
+    let mut nearest = vec![0; sides + 1];
+    let mut i = n;
+    for j in (1..=sides).rev() {
+        nearest[j] = i;
+        i = backward[i];
+    }
+
+    let mut cost = vec![0.0; n + 1];
+    let mut prev = vec![0; n + 1];
+    for j in 1..=sides {
+        for i in nearest[j]..=furthest[j] {
+            let mut best = f64::INFINITY;
+            for k in (backward[i]..=furthest[j - 1]).rev() {
+                let this = penalty(corners, sums, k, i) + cost[k];
+                if this < best {
+                    prev[i] = k;
+                    best = this;
+                }
+            }
+            cost[i] = best;
+        }
+    }
+
+    let mut polygon = vec![0; sides];
+    let mut i = n;
+    for j in (0..sides).rev() {
+        i = prev[i];
+        polygon[j] = i;
+    }
+    polygon
+}
+
+/// Center and direction of the line that best fits corners `i..=j`,
+/// relative to the first corner. `j` may be past the end, wrapping around.
+fn fit_line(sums: &Sums, i: usize, j: usize) -> (Point, Vec2) {
+    let n = sums.totals.len() - 1;
+    let (s, k) = sums.range(i, j % n, j / n);
+    let center = Point::new(s[0] / k, s[1] / k);
+    let mut a = (s[2] - s[0] * s[0] / k) / k;
+    let b = (s[3] - s[0] * s[1] / k) / k;
+    let mut c = (s[4] - s[1] * s[1] / k) / k;
+
+    // The direction is the eigenvector of the larger eigenvalue
+    let larger = (a + c + ((a - c) * (a - c) + 4.0 * b * b).sqrt()) / 2.0;
+    a -= larger;
+    c -= larger;
+    let direction = if a.abs() >= c.abs() {
+        let l = a.hypot(b);
+        if l != 0.0 { Vec2::new(-b / l, a / l) } else { Vec2::ZERO }
+    } else {
+        let l = c.hypot(b);
+        if l != 0.0 { Vec2::new(-c / l, b / l) } else { Vec2::ZERO }
+    };
+    (center, direction)
+}
+
+/// A symmetric 3×3 matrix giving the squared distance of a point from a
+/// line, or from several lines when added together.
+type Quadratic = [[f64; 3]; 3];
+
+fn line_quadratic(center: Point, direction: Vec2) -> Quadratic {
+    let mut q = [[0.0; 3]; 3];
+    let d = direction.hypot2();
+    if d != 0.0 {
+        let v = [direction.y, -direction.x, direction.x * center.y - direction.y * center.x];
+        for (row, a) in q.iter_mut().zip(v) {
+            for (cell, b) in row.iter_mut().zip(v) {
+                *cell = a * b / d;
+            }
+        }
+    }
+    q
+}
+
+fn evaluate(q: &Quadratic, p: Point) -> f64 {
+    let v = [p.x, p.y, 1.0];
+    (0..3).map(|i| (0..3).map(|j| v[i] * q[i][j] * v[j]).sum::<f64>()).sum()
+}
+
+/// The polygon's vertices placed where the lines fitted to the sides
+/// either side of each meet, kept within the pixel around the corner it
+/// started at.
+fn adjust_vertices(corners: &[Corner], sums: &Sums, polygon: &[usize]) -> Vec<Point> {
+    let (n, m) = (corners.len(), polygon.len());
+    let lines: Vec<Quadratic> = (0..m)
+        .map(|i| {
+            let (from, to) = (polygon[i], polygon[(i + 1) % m]);
+            let to = if to <= from { to + n } else { to };
+            let (center, direction) = fit_line(sums, from, to);
+            line_quadratic(center, direction)
+        })
+        .collect();
+
+    let origin = Vec2::new(sums.origin.0 as f64, sums.origin.1 as f64);
+    (0..m)
+        .map(|i| {
+            let corner = corners[polygon[i]];
+            let s = Point::new((corner.0 - sums.origin.0) as f64, (corner.1 - sums.origin.1) as f64);
+            let mut q = [[0.0; 3]; 3];
+            for (r, row) in q.iter_mut().enumerate() {
+                for (c, cell) in row.iter_mut().enumerate() {
+                    *cell = lines[(i + m - 1) % m][r][c] + lines[i][r][c];
+                }
+            }
+
+            let meeting = loop {
+                let det = q[0][0] * q[1][1] - q[0][1] * q[1][0];
+                if det != 0.0 {
+                    break Point::new(
+                        (-q[0][2] * q[1][1] + q[1][2] * q[0][1]) / det,
+                        (q[0][2] * q[1][0] - q[1][2] * q[0][0]) / det,
+                    );
+                }
+                // Parallel sides never meet, so a line across them through
+                // the corner is added
+                let v = if q[0][0] > q[1][1] {
+                    [-q[0][1], q[0][0]]
+                } else if q[1][1] != 0.0 {
+                    [-q[1][1], q[1][0]]
+                } else {
+                    [1.0, 0.0]
+                };
+                let d = v[0] * v[0] + v[1] * v[1];
+                let v = [v[0], v[1], -v[1] * s.y - v[0] * s.x];
+                for (r, row) in q.iter_mut().enumerate() {
+                    for (c, cell) in row.iter_mut().enumerate() {
+                        *cell += v[r] * v[c] / d;
+                    }
+                }
+            };
+            if (meeting.x - s.x).abs() <= 0.5 && (meeting.y - s.y).abs() <= 0.5 {
+                return meeting + origin;
+            }
+
+            // Otherwise the closest point on the edge of that pixel
+            let mut best = (evaluate(&q, s), s);
+            let mut consider = |p: Point| {
+                let value = evaluate(&q, p);
+                if value < best.0 {
+                    best = (value, p);
+                }
+            };
+            for z in [-0.5, 0.5] {
+                if q[0][0] != 0.0 {
+                    let y = s.y + z;
+                    let x = -(q[0][1] * y + q[0][2]) / q[0][0];
+                    if (x - s.x).abs() <= 0.5 {
+                        consider(Point::new(x, y));
+                    }
+                }
+                if q[1][1] != 0.0 {
+                    let x = s.x + z;
+                    let y = -(q[1][0] * x + q[1][2]) / q[1][1];
+                    if (y - s.y).abs() <= 0.5 {
+                        consider(Point::new(x, y));
+                    }
+                }
+            }
+            for (dx, dy) in [(-0.5, -0.5), (-0.5, 0.5), (0.5, -0.5), (0.5, 0.5)] {
+                consider(Point::new(s.x + dx, s.y + dy));
+            }
+            best.1 + origin
+        })
+        .collect()
+}
+
+fn sign(v: f64) -> f64 {
+    if v > 0.0 {
+        1.0
+    } else if v < 0.0 {
+        -1.0
+    } else {
+        0.0
+    }
+}
+
+/// Path commands for the polygon with its corners rounded into curves,
+/// except where it turns so sharply that `smoothness` (potrace's
+/// `alphamax`) keeps them pointed.
+fn round_corners(vertices: &[Point], smoothness: f64, out: &mut Vec<PathCommand>) {
+    let m = vertices.len();
+    let mid = |i: usize| vertices[i].midpoint(vertices[(i + 1) % m]);
+    let start = mid(m - 1);
+    out.push(PathCommand::MoveTo { x: start.x, y: start.y });
+    for j in 0..m {
+        let (prev, at, next) = (vertices[(j + m - 1) % m], vertices[j], vertices[(j + 1) % m]);
+        let end = mid(j);
+        // How far the corner sticks out from the line between its
+        // neighbours, relative to the largest it could within its pixel
+        let r = Vec2::new(-sign(next.y - prev.y), sign(next.x - prev.x));
+        let denominator = r.y * (next.x - prev.x) - r.x * (next.y - prev.y);
+        let alpha = if denominator != 0.0 {
+            let dd = ((at - prev).cross(next - prev) / denominator).abs();
+            (if dd > 1.0 { 1.0 - 1.0 / dd } else { 0.0 }) / 0.75
+        } else {
+            4.0 / 3.0
+        };
+
+        if alpha >= smoothness {
+            out.push(PathCommand::LineTo { x: at.x, y: at.y });
+            out.push(PathCommand::LineTo { x: end.x, y: end.y });
+        } else {
+            let alpha = alpha.clamp(0.55, 1.0);
+            let (c1, c2) = (prev.lerp(at, 0.5 + 0.5 * alpha), next.lerp(at, 0.5 + 0.5 * alpha));
+            out.push(PathCommand::CurveTo { x1: c1.x, y1: c1.y, x2: c2.x, y2: c2.y, x: end.x, y: end.y });
+        }
+    }
+    out.push(PathCommand::ClosePath);
+}
+
+/// Commands for `outline` as curves, appended to `out`.
+pub fn trace_outline(outline: &Outline, smoothness: f64, out: &mut Vec<PathCommand>) {
+    let sums = Sums::new(&outline.corners);
+    let polygon = best_polygon(&outline.corners, &sums);
+    if polygon.len() < 3 {
+        return;
+    }
+    let vertices = adjust_vertices(&outline.corners, &sums, &polygon);
+    round_corners(&vertices, smoothness, out);
+}
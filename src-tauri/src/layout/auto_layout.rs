@@ -0,0 +1,267 @@
+//! Auto layout: a frame's children stacked in a row or column with padding
+//! and gaps, wrapped onto further rows, aligned, stretched or grown to fill
+//! the frame, and frames that hug their contents. Each frame becomes a
+//! flex container for taffy, with the subset of flexbox that
+//! `src/layout/auto-layout/auto-layout.ts` implements and the same
+//! defaults, so results match the webview's.
+
+use super::{compute, Layout, Placement, ResolvedLayout};
+use crate::model::{DocumentTree, NodeData};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use taffy::{
+    AlignContent, AlignItems, AlignSelf, AvailableSpace, Dimension, Display, FlexDirection, FlexWrap, JustifyContent,
+    LengthPercentage, NodeId, Rect, Size, Style, TaffyError,
+};
+use tauri::command;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum LayoutMode {
+    #[default]
+    None,
+    Horizontal,
+    Vertical,
+}
+
+/// Alignment along either axis. `SPACE_BETWEEN` only applies along the
+/// primary axis, and `BASELINE`, which needs text metrics, aligns to the
+/// start as the webview does.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Align {
+    #[default]
+    Min,
+    Center,
+    Max,
+    SpaceBetween,
+    Baseline,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SizingMode {
+    #[default]
+    Fixed,
+    /// Hug the contents.
+    Auto,
+}
+
+/// A frame's `autoLayout` (`AutoLayoutProps` in `src/core/types/common.ts`).
+#[derive(Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AutoLayout {
+    pub mode: LayoutMode,
+    pub item_spacing: f64,
+    /// Gap between wrapped rows; `item_spacing` when unset.
+    pub counter_axis_spacing: Option<f64>,
+    pub padding_top: f64,
+    pub padding_right: f64,
+    pub padding_bottom: f64,
+    pub padding_left: f64,
+    pub primary_axis_align_items: Align,
+    pub counter_axis_align_items: Align,
+    pub primary_axis_sizing_mode: SizingMode,
+    pub counter_axis_sizing_mode: SizingMode,
+    pub wrap: bool,
+}
+
+impl AutoLayout {
+    pub fn of(node: &NodeData) -> Option<Self> {
+        let layout: AutoLayout = serde_json::from_value(node.extra.get("autoLayout")?.clone()).ok()?;
+        (layout.mode != LayoutMode::None).then_some(layout)
+    }
+
+    fn horizontal(&self) -> bool {
+        self.mode == LayoutMode::Horizontal
+    }
+
+    /// `(width, height)` as primary and counter axis lengths, or back.
+    fn axes<T>(&self, (a, b): (T, T)) -> (T, T) {
+        if self.horizontal() { (a, b) } else { (b, a) }
+    }
+
+    /// The flex container a frame with this layout is to taffy. Wrapped
+    /// rows are packed from the start, `counter_axis_spacing` apart, and
+    /// each is as deep as its deepest item.
+    fn container(&self, style: &mut Style) {
+        let (column_gap, row_gap) = self.axes((self.item_spacing, self.counter_axis_spacing.unwrap_or(self.item_spacing)));
+        style.display = Display::Flex;
+        style.flex_direction = if self.horizontal() { FlexDirection::Row } else { FlexDirection::Column };
+        style.flex_wrap = if self.wrap { FlexWrap::Wrap } else { FlexWrap::NoWrap };
+        style.gap = Size { width: length(column_gap), height: length(row_gap) };
+        style.padding = Rect {
+            left: length(self.padding_left),
+            right: length(self.padding_right),
+            top: length(self.padding_top),
+            bottom: length(self.padding_bottom),
+        };
+        style.justify_content = Some(justify(self.primary_axis_align_items));
+        style.align_items = Some(align(self.counter_axis_align_items));
+        style.align_content = Some(AlignContent::FlexStart);
+    }
+}
+
+/// Hidden children and those positioned absolutely keep out of the flow.
+fn in_flow(node: &NodeData) -> bool {
+    node.visible && node.extra.get("layoutPositioning").and_then(Value::as_str) != Some("ABSOLUTE")
+}
+
+fn justify(align: Align) -> JustifyContent {
+    match align {
+        Align::Center => JustifyContent::Center,
+        Align::Max => JustifyContent::FlexEnd,
+        Align::SpaceBetween => JustifyContent::SpaceBetween,
+        Align::Min | Align::Baseline => JustifyContent::FlexStart,
+    }
+}
+
+fn align(align: Align) -> AlignItems {
+    match align {
+        Align::Center => AlignItems::Center,
+        Align::Max => AlignItems::FlexEnd,
+        Align::Min | Align::SpaceBetween | Align::Baseline => AlignItems::FlexStart,
+    }
+}
+
+fn length(value: f64) -> LengthPercentage {
+    LengthPercentage::length(value as f32)
+}
+
+impl<'a> Layout<'a> {
+    /// How taffy is to size `node`, in the flow of `parent` when given.
+    /// Items keep their length rather than shrinking to fit, and growing
+    /// ones share whatever the others leave regardless of their own length.
+    fn style(&self, node: &NodeData, parent: Option<&AutoLayout>) -> Style {
+        let mut style = Style::default();
+        let (width, height) = self.size(node);
+        let mut size = Size { width: Dimension::length(width as f32), height: Dimension::length(height as f32) };
+        if let Some(layout) = AutoLayout::of(node) {
+            layout.container(&mut style);
+            let (hug_width, hug_height) = layout.axes((
+                layout.primary_axis_sizing_mode == SizingMode::Auto,
+                layout.counter_axis_sizing_mode == SizingMode::Auto,
+            ));
+            if hug_width {
+                size.width = Dimension::auto();
+            }
+            if hug_height {
+                size.height = Dimension::auto();
+            }
+        }
+        if let Some(parent) = parent {
+            let grow = node.extra.get("layoutGrow").and_then(Value::as_f64).unwrap_or(0.0).max(0.0);
+            style.flex_grow = grow as f32;
+            style.flex_shrink = 0.0;
+            if grow > 0.0 {
+                style.flex_basis = Dimension::length(0.0);
+                style.min_size = Size { width: Dimension::length(0.0), height: Dimension::length(0.0) };
+            }
+            if node.extra.get("layoutAlign").and_then(Value::as_str) == Some("STRETCH") {
+                style.align_self = Some(AlignSelf::Stretch);
+                if parent.horizontal() {
+                    size.height = Dimension::auto();
+                } else {
+                    size.width = Dimension::auto();
+                }
+            }
+        }
+        style.size = size;
+        style
+    }
+
+    /// Add `id`, in the flow of `parent` when given, to the taffy tree with
+    /// the children in its flow when it's an auto layout frame.
+    fn build(&mut self, id: &'a str, parent: Option<&AutoLayout>) -> Result<NodeId, String> {
+        if let Some(&node_id) = self.nodes.get(id) {
+            return Ok(node_id);
+        }
+        let tree = self.tree;
+        let node = tree.get(id).ok_or_else(|| format!("Unknown node: {}", id))?;
+        let layout = AutoLayout::of(node);
+        let mut children = Vec::new();
+        if let Some(layout) = &layout {
+            for child in tree.children(id) {
+                if tree.get(child).is_some_and(in_flow) {
+                    children.push(self.build(child, Some(layout))?);
+                }
+            }
+        }
+        let node_id = self
+            .flow
+            .new_with_children(self.style(node, parent), &children)
+            .map_err(|e| format!("Failed to lay out {}: {}", id, e))?;
+        self.nodes.insert(id, node_id);
+        Ok(node_id)
+    }
+
+    /// Work out the sizes of hugging frames under `id`, deepest first. Only
+    /// those outside any flow are measured here; taffy sizes the rest as it
+    /// lays out the frame around them.
+    pub fn measure(&mut self, id: &'a str, flowing: bool) -> Result<(), String> {
+        let tree = self.tree;
+        let Some(node) = tree.get(id) else { return Ok(()) };
+        let layout = AutoLayout::of(node);
+        for child in tree.children(id) {
+            let flows = layout.is_some() && tree.get(child).is_some_and(in_flow);
+            self.measure(child, flows)?;
+        }
+        let Some(layout) = layout else { return Ok(()) };
+        let hugs = layout.primary_axis_sizing_mode == SizingMode::Auto || layout.counter_axis_sizing_mode == SizingMode::Auto;
+        if flowing || !hugs {
+            return Ok(());
+        }
+
+        let root = self.build(id, None)?;
+        let available = Size { width: AvailableSpace::MaxContent, height: AvailableSpace::MaxContent };
+        self.flow.compute_layout(root, available).map_err(|e| format!("Failed to lay out {}: {}", id, e))?;
+        let measured = self.flow.layout(root).map_err(|e| format!("Failed to lay out {}: {}", id, e))?.size;
+        self.measured.insert(id, (measured.width as f64, measured.height as f64));
+        Ok(())
+    }
+
+    /// Positions and sizes of the children in the flow of `id`, an auto
+    /// layout frame of `size`. Those of the auto layout frames nested in
+    /// its flow are kept in `flowed` for when they're placed.
+    pub fn arrange(&mut self, id: &'a str, size: (f64, f64)) -> Result<HashMap<&'a str, Placement>, String> {
+        let root = self.build(id, None)?;
+        let failed = |e: TaffyError| format!("Failed to lay out {}: {}", id, e);
+        let mut style = self.flow.style(root).map_err(failed)?.clone();
+        style.size = Size { width: Dimension::length(size.0 as f32), height: Dimension::length(size.1 as f32) };
+        self.flow.set_style(root, style).map_err(failed)?;
+        let available = Size { width: AvailableSpace::Definite(size.0 as f32), height: AvailableSpace::Definite(size.1 as f32) };
+        self.flow.compute_layout(root, available).map_err(failed)?;
+        self.collect(id)
+    }
+
+    /// Where taffy put the children in the flow of `id`, keeping those of
+    /// nested auto layout frames in `flowed`.
+    fn collect(&mut self, id: &'a str) -> Result<HashMap<&'a str, Placement>, String> {
+        let tree = self.tree;
+        let mut placed = HashMap::new();
+        for child in tree.children(id) {
+            let Some(&node_id) = self.nodes.get(child.as_str()) else { continue };
+            if !tree.get(child).is_some_and(in_flow) {
+                continue;
+            }
+            let layout = self.flow.layout(node_id).map_err(|e| format!("Failed to lay out {}: {}", child, e))?;
+            let (x, y) = (layout.location.x as f64, layout.location.y as f64);
+            placed.insert(child.as_str(), (x, y, (layout.size.width as f64, layout.size.height as f64)));
+            if tree.get(child).and_then(AutoLayout::of).is_some() {
+                let nested = self.collect(child)?;
+                self.flowed.insert(child.as_str(), nested);
+            }
+        }
+        Ok(placed)
+    }
+}
+
+/// Lay out the auto layout frames in `subtree_json`, a serialized document
+/// of the nodes to lay out, and return the position, relative to the
+/// parent, and size of each of its nodes.
+#[command(async)]
+pub fn compute_layout(subtree_json: String) -> Result<Vec<ResolvedLayout>, String> {
+    let tree = DocumentTree::parse(&subtree_json)?;
+    compute(&tree, None)
+}
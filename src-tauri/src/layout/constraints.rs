@@ -0,0 +1,82 @@
+//! Constraints: how a child moves and resizes with a parent that is not
+//! laid out automatically, as in `src/layout/constraints/layout-constraints.ts`.
+
+use super::{compute, ResolvedLayout};
+use crate::model::{DocumentTree, NodeData};
+use serde::Deserialize;
+use tauri::command;
+
+/// How a child follows its parent along one axis. Figma's names are
+/// accepted too.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Constraint {
+    /// Keep the distance from the left or top.
+    #[default]
+    #[serde(alias = "LEFT", alias = "TOP")]
+    Min,
+    /// Keep the distance from the right or bottom.
+    #[serde(alias = "RIGHT", alias = "BOTTOM")]
+    Max,
+    /// Keep the offset from the parent's center.
+    Center,
+    /// Keep the distances from both sides, resizing.
+    #[serde(alias = "LEFT_RIGHT", alias = "TOP_BOTTOM")]
+    Stretch,
+    /// Keep position and size in proportion to the parent.
+    Scale,
+}
+
+impl Constraint {
+    /// Start and length of a child at `start` of `length` once its parent
+    /// goes from `old` long to `new`.
+    fn resolve(self, start: f64, length: f64, old: f64, new: f64) -> (f64, f64) {
+        let delta = new - old;
+        match self {
+            Constraint::Min => (start, length),
+            Constraint::Max => (start + delta, length),
+            Constraint::Center => (start + delta / 2.0, length),
+            Constraint::Stretch => (start, (length + delta).max(0.0)),
+            Constraint::Scale if old > 0.0 => (start * new / old, length * new / old),
+            Constraint::Scale => (start, length),
+        }
+    }
+}
+
+/// A node's `constraints` (`LayoutConstraints` in `src/core/types/common.ts`).
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(default)]
+pub struct Constraints {
+    pub horizontal: Constraint,
+    pub vertical: Constraint,
+}
+
+/// Position and size of `child`, currently `size`, once its parent goes
+/// from `old` to `new`. Children of groups scale with the group.
+pub fn constrain(child: &NodeData, in_group: bool, old: (f64, f64), new: (f64, f64), size: (f64, f64)) -> (f64, f64, (f64, f64)) {
+    let constraints = if in_group {
+        Constraints { horizontal: Constraint::Scale, vertical: Constraint::Scale }
+    } else {
+        child
+            .extra
+            .get("constraints")
+            .and_then(|c| serde_json::from_value(c.clone()).ok())
+            .unwrap_or_default()
+    };
+    let (x, width) = constraints.horizontal.resolve(child.x.unwrap_or(0.0), size.0, old.0, new.0);
+    let (y, height) = constraints.vertical.resolve(child.y.unwrap_or(0.0), size.1, old.1, new.1);
+    (x, y, (width, height))
+}
+
+/// Resize the root of `subtree_json`, a serialized document of the nodes
+/// under it, to `width` × `height`, and return the position, relative to
+/// the parent, and size every node ends up with as its children follow
+/// their constraints and auto layout frames relay out.
+#[command(async)]
+pub fn resize_with_constraints(subtree_json: String, width: f64, height: f64) -> Result<Vec<ResolvedLayout>, String> {
+    if !(width.is_finite() && height.is_finite() && width >= 0.0 && height >= 0.0) {
+        return Err(format!("Invalid size: {}×{}", width, height));
+    }
+    let tree = DocumentTree::parse(&subtree_json)?;
+    compute(&tree, Some((width, height)))
+}
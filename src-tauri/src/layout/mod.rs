@@ -0,0 +1,97 @@
+//! Layout of nodes within their frames: auto layout frames arrange their
+//! children, and other frames move and resize theirs by their constraints.
+//! Computed here so resizing and relaying out deep trees does not hold up
+//! the webview.
+
+pub mod auto_layout;
+pub mod constraints;
+
+use crate::model::{DocumentTree, NodeData, NodeType};
+use auto_layout::AutoLayout;
+use constraints::constrain;
+use serde::Serialize;
+use std::collections::HashMap;
+use taffy::{NodeId, TaffyTree};
+
+/// Where layout puts a node, relative to its parent.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvedLayout {
+    pub node_id: String,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// Where a child goes and how big it is, relative to its parent.
+type Placement = (f64, f64, (f64, f64));
+
+struct Layout<'a> {
+    tree: &'a DocumentTree,
+    /// Auto layout frames and the children in their flow, nested frames
+    /// and all, as taffy lays them out.
+    flow: TaffyTree,
+    nodes: HashMap<&'a str, NodeId>,
+    /// Sizes of outermost frames that hug their contents, worked out
+    /// before placing anything since hugging goes from the leaves up.
+    measured: HashMap<&'a str, (f64, f64)>,
+    /// Children of nested auto layout frames, placed along with the
+    /// outermost frame around them.
+    flowed: HashMap<&'a str, HashMap<&'a str, Placement>>,
+    resolved: Vec<ResolvedLayout>,
+}
+
+impl<'a> Layout<'a> {
+    fn size(&self, node: &NodeData) -> (f64, f64) {
+        self.measured.get(node.id.as_str()).copied().unwrap_or((node.width.unwrap_or(0.0), node.height.unwrap_or(0.0)))
+    }
+
+    /// Record `id` at `x`, `y` with the given size, and lay out its
+    /// children within it.
+    fn place(&mut self, id: &'a str, x: f64, y: f64, size: (f64, f64)) -> Result<(), String> {
+        let tree = self.tree;
+        let Some(node) = tree.get(id) else { return Ok(()) };
+        if !matches!(node.node_type, NodeType::Document | NodeType::Page) {
+            self.resolved.push(ResolvedLayout { node_id: id.to_string(), x, y, width: size.0, height: size.1 });
+        }
+
+        let mut placed = match (self.flowed.remove(id), AutoLayout::of(node)) {
+            (Some(placed), _) => placed,
+            (None, Some(_)) => self.arrange(id, size)?,
+            (None, None) => HashMap::new(),
+        };
+        // Children out of the flow follow their constraints as the node
+        // changes from its saved size
+        let saved = (node.width.unwrap_or(0.0), node.height.unwrap_or(0.0));
+        let in_group = node.node_type == NodeType::Group;
+        for child in tree.children(id) {
+            let Some(child_node) = tree.get(child) else { continue };
+            let (x, y, child_size) = placed
+                .remove(child.as_str())
+                .unwrap_or_else(|| constrain(child_node, in_group, saved, size, self.size(child_node)));
+            self.place(child, x, y, child_size)?;
+        }
+        Ok(())
+    }
+}
+
+/// Lay out everything in `tree`, with its root at `size` when given, and
+/// return where each node ends up.
+pub fn compute(tree: &DocumentTree, size: Option<(f64, f64)>) -> Result<Vec<ResolvedLayout>, String> {
+    let root = tree.get(&tree.root_id).ok_or_else(|| format!("Unknown node: {}", tree.root_id))?;
+    let mut flow = TaffyTree::new();
+    flow.disable_rounding();
+    let mut layout = Layout {
+        tree,
+        flow,
+        nodes: HashMap::new(),
+        measured: HashMap::new(),
+        flowed: HashMap::new(),
+        resolved: Vec::new(),
+    };
+    layout.measure(&tree.root_id, false)?;
+    let size = size.unwrap_or_else(|| layout.size(root));
+    layout.place(&tree.root_id, root.x.unwrap_or(0.0), root.y.unwrap_or(0.0), size)?;
+    Ok(layout.resolved)
+}
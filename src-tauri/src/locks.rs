@@ -0,0 +1,287 @@
+use crate::atomic::write_atomic;
+use crate::bundle::now_millis;
+use crate::error::{FileError, FileErrorKind};
+use crate::watcher::normalize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+use tauri::{command, AppHandle, Emitter, State};
+
+pub const FILE_LOCK_LOST_EVENT: &str = "file-lock-lost";
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+/// A lock whose heartbeat is older than this is left over from an instance
+/// that quit or crashed, and can be taken without asking.
+const LOCK_STALE_MS: u64 = 45_000;
+
+/// Contents of a lock file: who holds the document open for editing.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LockOwner {
+    pub instance_id: String,
+    pub pid: u32,
+    pub host: String,
+    pub user: String,
+    pub acquired_at: u64,
+    pub heartbeat_at: u64,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum LockState {
+    Unlocked,
+    HeldByUs,
+    HeldByOther,
+    /// Held by an instance that stopped sending heartbeats.
+    Stale,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LockStatus {
+    pub path: String,
+    pub state: LockState,
+    pub owner: Option<LockOwner>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FileLockLostEvent {
+    pub path: String,
+    /// The instance that took the lock over, if it could be read.
+    pub owner: Option<LockOwner>,
+}
+
+struct Shared {
+    app: AppHandle,
+    instance_id: String,
+    host: String,
+    user: String,
+    /// Documents this instance holds locks on.
+    held: Mutex<HashSet<PathBuf>>,
+}
+
+/// Advisory locks on open documents, so that instances on different machines
+/// sharing a drive do not overwrite each other's saves.
+///
+/// `dir/design.dlibre` is locked by `dir/.design.dlibre.lock`. A background
+/// thread refreshes the heartbeat of every held lock, and emits
+/// `file-lock-lost` when another instance has taken one over.
+pub struct FileLocks {
+    shared: Arc<Shared>,
+}
+
+pub fn lock_path(document: &Path) -> Result<PathBuf, String> {
+    let name = document
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| format!("Invalid document path: {}", document.display()))?;
+    let parent = document.parent().unwrap_or_else(|| Path::new("."));
+
+    Ok(parent.join(format!(".{}.lock", name)))
+}
+
+fn read_lock(lock: &Path) -> Option<LockOwner> {
+    let bytes = fs::read(lock).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+fn host_name() -> String {
+    std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .ok()
+        .or_else(|| {
+            let output = std::process::Command::new("hostname").output().ok()?;
+            String::from_utf8(output.stdout).ok().map(|s| s.trim().to_string())
+        })
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn user_name() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+fn lock_held(shared: &Shared) -> MutexGuard<'_, HashSet<PathBuf>> {
+    shared.held.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Refresh our held locks, dropping those now owned by another instance.
+fn heartbeat(shared: &Shared) {
+    let held: Vec<PathBuf> = lock_held(shared).iter().cloned().collect();
+    let now = now_millis();
+
+    for document in held {
+        let Ok(lock) = lock_path(&document) else { continue };
+        match read_lock(&lock) {
+            Some(owner) if owner.instance_id != shared.instance_id => {
+                lock_held(shared).remove(&document);
+                let _ = shared.app.emit(FILE_LOCK_LOST_EVENT, FileLockLostEvent {
+                    path: document.to_string_lossy().into_owned(),
+                    owner: Some(owner),
+                });
+            }
+            Some(mut owner) => {
+                owner.heartbeat_at = now;
+                if let Ok(json) = serde_json::to_vec(&owner) {
+                    let _ = write_atomic(&lock.to_string_lossy(), &json);
+                }
+            }
+            // Removed by hand or by a cleanup tool; the document is still open here
+            None => {
+                let _ = create_lock(&lock, &owner_record(shared, now));
+            }
+        }
+    }
+}
+
+fn owner_record(shared: &Shared, now: u64) -> LockOwner {
+    LockOwner {
+        instance_id: shared.instance_id.clone(),
+        pid: std::process::id(),
+        host: shared.host.clone(),
+        user: shared.user.clone(),
+        acquired_at: now,
+        heartbeat_at: now,
+    }
+}
+
+/// Create `lock` only if no lock file exists, so two instances opening the
+/// document at the same moment cannot both win.
+fn create_lock(lock: &Path, owner: &LockOwner) -> std::io::Result<()> {
+    let json = serde_json::to_vec(owner).map_err(std::io::Error::other)?;
+    let mut file = fs::OpenOptions::new().write(true).create_new(true).open(lock)?;
+    file.write_all(&json)?;
+    file.sync_all()
+}
+
+impl FileLocks {
+    pub fn new(app: AppHandle, instance_id: String) -> Result<Self, String> {
+        let shared = Arc::new(Shared {
+            app,
+            instance_id,
+            host: host_name(),
+            user: user_name(),
+            held: Mutex::new(HashSet::new()),
+        });
+
+        let worker = Arc::clone(&shared);
+        thread::Builder::new()
+            .name("file-lock-heartbeat".into())
+            .spawn(move || loop {
+                thread::sleep(HEARTBEAT_INTERVAL);
+                heartbeat(&worker);
+            })
+            .map_err(|e| format!("Failed to spawn lock heartbeat thread: {}", e))?;
+
+        Ok(FileLocks { shared })
+    }
+
+    fn state_of(&self, owner: &LockOwner) -> LockState {
+        if owner.instance_id == self.shared.instance_id {
+            LockState::HeldByUs
+        } else if now_millis().saturating_sub(owner.heartbeat_at) >= LOCK_STALE_MS {
+            LockState::Stale
+        } else {
+            LockState::HeldByOther
+        }
+    }
+
+    pub fn status(&self, path: &str) -> Result<LockStatus, String> {
+        let document = normalize(Path::new(path));
+        let owner = read_lock(&lock_path(&document)?);
+        let state = owner.as_ref().map_or(LockState::Unlocked, |o| self.state_of(o));
+
+        Ok(LockStatus { path: document.to_string_lossy().into_owned(), state, owner })
+    }
+
+    /// Lock `path` for this instance. A live lock of another instance is left
+    /// alone unless `take_over` is set; the returned status then names its
+    /// owner so the user can decide.
+    pub fn acquire(&self, path: &str, take_over: bool) -> Result<LockStatus, String> {
+        let document = normalize(Path::new(path));
+        let lock = lock_path(&document)?;
+        let owner = owner_record(&self.shared, now_millis());
+
+        let acquired = match read_lock(&lock) {
+            None => match create_lock(&lock, &owner) {
+                Ok(()) => true,
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => false,
+                Err(e) => return Err(format!("Failed to create lock file: {}", e)),
+            },
+            Some(existing) if existing.instance_id == self.shared.instance_id => true,
+            Some(existing) if take_over || self.state_of(&existing) == LockState::Stale => {
+                let json = serde_json::to_vec(&owner)
+                    .map_err(|e| format!("Failed to encode lock: {}", e))?;
+                write_atomic(&lock.to_string_lossy(), &json)?;
+                true
+            }
+            Some(_) => false,
+        };
+        if acquired {
+            lock_held(&self.shared).insert(document.clone());
+        }
+
+        self.status(&document.to_string_lossy())
+    }
+
+    /// Remove our lock on `path`; locks held by others are never removed.
+    pub fn release(&self, path: &str) -> Result<(), String> {
+        let document = normalize(Path::new(path));
+        let lock = lock_path(&document)?;
+        lock_held(&self.shared).remove(&document);
+
+        if read_lock(&lock).is_some_and(|o| o.instance_id == self.shared.instance_id) {
+            fs::remove_file(&lock).map_err(|e| format!("Failed to remove lock file: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// Refuse to overwrite `path` while another live instance holds its lock.
+    /// Unlocked documents can be written, as can those with a stale lock.
+    pub fn check_writable(&self, path: &str) -> Result<(), FileError> {
+        let Ok(lock) = lock_path(&normalize(Path::new(path))) else { return Ok(()) };
+        match read_lock(&lock) {
+            Some(owner) if self.state_of(&owner) == LockState::HeldByOther => Err(FileError::new(
+                FileErrorKind::Locked,
+                path,
+                format!("The file is being edited by {} on {}", owner.user, owner.host),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Lock a document when it is opened. Check `state` in the result: a
+/// document held by another instance should be opened read-only, or taken
+/// over with `take_over_file_lock`.
+#[command]
+pub fn acquire_file_lock(locks: State<'_, FileLocks>, path: String) -> Result<LockStatus, String> {
+    locks.acquire(&path, false)
+}
+
+/// Report who holds the lock on a document, if anyone.
+#[command]
+pub fn get_file_lock_status(locks: State<'_, FileLocks>, path: String) -> Result<LockStatus, String> {
+    locks.status(&path)
+}
+
+/// Take the lock from another instance, e.g. one left running on a machine
+/// nobody can get to. That instance receives `file-lock-lost` and its saves
+/// are refused from then on; its unsaved changes are not merged.
+#[command]
+pub fn take_over_file_lock(locks: State<'_, FileLocks>, path: String) -> Result<LockStatus, String> {
+    locks.acquire(&path, true)
+}
+
+#[command]
+pub fn release_file_lock(locks: State<'_, FileLocks>, path: String) -> Result<(), String> {
+    locks.release(&path)
+}
@@ -1,20 +1,296 @@
 // Prevents additional console window on Windows in release
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod assets;
+mod atomic;
+mod autosave;
+mod backups;
+mod bundle;
+mod clipboard;
 mod commands;
+mod compression;
+mod converters;
+mod deep_link;
+mod document;
+mod document_windows;
+mod encryption;
+mod error;
+mod export;
+mod eyedropper;
+mod file_drop;
+mod fonts;
+mod geometry;
+mod history;
+mod iconify;
+mod import;
+mod layout;
+mod locks;
+mod mapped;
+mod menu;
+mod model;
+mod open_files;
+mod print;
+mod project_search;
+mod recent_files;
+mod recovery;
+mod render;
+mod spatial;
+mod stream;
+mod tasks;
+mod thumbnails;
+mod watcher;
+
+use std::sync::Arc;
+use tauri::Manager;
 
 fn main() {
-    tauri::Builder::default()
+    let builder = tauri::Builder::default();
+    // Registered first, so a second launch exits before anything else starts
+    #[cfg(desktop)]
+    let builder = builder.plugin(tauri_plugin_single_instance::init(|app, args, cwd| {
+        deep_link::handle_second_instance(app, &args);
+        open_files::handle_second_instance(app, &args, &cwd);
+    }));
+    builder
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_clipboard_manager::init())
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_http::init())
+        .setup(|app| {
+            let data_dir = app.path().app_data_dir()?;
+            let recovery_dir = data_dir.join("recovery");
+            let session = recovery::Session::start(recovery_dir.clone())?;
+            let args: Vec<String> = std::env::args().collect();
+            let cwd = std::env::current_dir().unwrap_or_default();
+            app.manage(open_files::OpenedFiles::new(open_files::file_arguments(args.iter().cloned(), &cwd)));
+            app.manage(deep_link::DeepLinks::new(deep_link::link_arguments(&args)));
+            app.manage(autosave::AutosaveManager::new(recovery_dir, session.id.clone()));
+            app.manage(locks::FileLocks::new(app.handle().clone(), session.id.clone())?);
+            app.manage(session);
+            app.manage(recent_files::RecentFiles::load(data_dir.join("recent-files.json")));
+            app.manage(document_windows::DocumentWindows::default());
+            menu::install(app.handle())?;
+            app.manage(backups::BackupManager::load(app.path().app_config_dir()?.join("backup-settings.json"), data_dir.join("backups")));
+            app.manage(export::presets::ExportPresets::load(app.path().app_config_dir()?.join("export-presets.json")));
+            app.manage(watcher::FileWatcher::new(app.handle().clone())?);
+            app.manage(Arc::new(stream::ReadStreams::default()));
+            app.manage(Arc::new(tasks::TaskPool::default()));
+            app.manage(Arc::new(export::video::VideoExports::default()));
+            app.manage(fonts::remote::GoogleFonts::new(data_dir.join("fonts").join("google"))?);
+            app.manage(fonts::user::UserFonts::new(data_dir.join("fonts").join("user")));
+            app.manage(fonts::watch::FontWatcher::new(app.handle().clone(), app.path().home_dir().ok())?);
+            app.manage(fonts::preview::FontPreviews::new(app.path().app_cache_dir()?.join("font-previews")));
+            app.manage(iconify::IconSets::new(data_dir.join("icons"))?);
+            app.manage(mapped::MappedBundles::default());
+            app.manage(project_search::ProjectIndex::default());
+            app.manage(document::store::DocumentStores::new(data_dir.join("undo")));
+            app.manage(assets::AssetStore::new(data_dir.join("assets")));
+            app.manage(assets::stock::StockPhotos::load(app.path().app_config_dir()?.join("stock-photos.json"))?);
+            app.manage(spatial::index::SpatialIndex::default());
+            app.manage(thumbnails::ThumbnailCache::new(app.path().app_cache_dir()?.join("thumbnails")));
+            #[cfg(feature = "gpu")]
+            app.manage(render::gpu::GpuCanvas::default());
+            Ok(())
+        })
+        .on_window_event(|window, event| {
+            file_drop::handle_window_event(window, event);
+            document_windows::handle_window_event(window, event);
+        })
+        .on_menu_event(menu::handle_menu_event)
         .invoke_handler(tauri::generate_handler![
             commands::read_design_file,
             commands::write_design_file,
-            commands::get_system_fonts,
+            commands::read_design_file_binary,
+            commands::write_design_file_binary,
+            commands::get_design_file_size,
+            commands::set_design_file_compression,
+            document::store::open_document,
+            document::store::close_document,
+            document::store::get_document,
+            document::store::get_document_info,
+            document::store::get_nodes,
+            document::store::insert_node,
+            document::store::set_node_props,
+            document::store::reparent_node,
+            document::store::delete_node,
+            document::store::apply_document_ops,
+            document::crdt::get_state_vector,
+            document::crdt::encode_document_update,
+            document::crdt::apply_document_update,
+            document::deltas::open_document_file,
+            document::deltas::save_document,
+            document::search::search_document,
+            document::stats::get_document_stats,
+            document::validate::validate_document,
+            document::migrate::migrate_document,
+            document::diff::diff_documents,
+            document::merge::merge_documents,
+            document::libraries::publish_library,
+            document::libraries::subscribe_library,
+            document::libraries::unsubscribe_library,
+            document::libraries::list_library_subscriptions,
+            document::libraries::import_library_component,
+            document::libraries::check_library_updates,
+            document::libraries::apply_library_updates,
+            document::links::check_image_links,
+            document::links::relink_images,
+            document::overrides::list_instance_overrides,
+            document::overrides::resolve_instance,
+            document::overrides::set_instance_overrides,
+            document::overrides::reset_instance_overrides,
+            document::overrides::push_overrides_to_main,
+            document::undo::undo,
+            document::undo::redo,
+            document::undo::get_history,
+            document::undo::clear_history,
+            geometry::boolean::boolean_op,
+            geometry::simplify::simplify_path,
+            geometry::squircle::smooth_corner_path,
+            geometry::stroke::outline_stroke,
+            layout::auto_layout::compute_layout,
+            layout::constraints::resize_with_constraints,
+            render::effects::apply_effects,
+            #[cfg(feature = "gpu")]
+            render::gpu::gpu_load_scene,
+            #[cfg(feature = "gpu")]
+            render::gpu::gpu_update_scene,
+            #[cfg(feature = "gpu")]
+            render::gpu::gpu_render_viewport,
+            #[cfg(feature = "gpu")]
+            render::gpu::gpu_release_scene,
+            spatial::index::set_spatial_index,
+            spatial::index::update_spatial_index,
+            spatial::index::nodes_at_point,
+            spatial::index::nodes_in_rect,
+            spatial::snapping::snap_candidates,
+            fonts::get_system_fonts,
+            fonts::load_font,
+            fonts::emoji::render_emoji,
+            fonts::fallback::resolve_fallback,
+            fonts::features::get_font_features,
+            fonts::license::get_font_license,
+            fonts::metrics::get_glyph_metrics,
+            fonts::missing::find_missing_fonts,
+            fonts::outlines::text_to_outlines,
+            fonts::preview::render_font_preview,
+            fonts::remote::get_google_fonts_catalog,
+            fonts::remote::download_google_font,
+            fonts::remote::list_downloaded_google_fonts,
+            fonts::remote::remove_google_font,
+            fonts::shaping::shape_text,
+            fonts::shaping::shape_paragraphs,
+            fonts::user::install_user_font,
+            fonts::user::uninstall_user_font,
+            fonts::user::list_user_fonts,
+            fonts::variations::get_font_variations,
+            fonts::variations::instance_font,
+            fonts::vertical::shape_vertical_text,
+            iconify::list_icon_collections,
+            iconify::search_icons,
+            iconify::get_icon_svgs,
+            iconify::download_icon_set,
+            iconify::list_downloaded_icon_sets,
+            iconify::remove_icon_set,
+            bundle::open_bundle,
+            bundle::save_bundle,
+            bundle::list_bundle_entries,
+            bundle::read_bundle_entry,
+            bundle::collect_unused_assets,
+            mapped::open_mapped_bundle,
+            mapped::read_bundle_page,
+            mapped::close_mapped_bundle,
+            project_search::index_project,
+            project_search::search_project,
+            project_search::close_project_index,
+            autosave::autosave_snapshot,
+            autosave::discard_autosave,
+            autosave::set_autosave_enabled,
+            autosave::set_autosave_interval,
+            autosave::get_autosave_status,
+            recovery::list_recoverable_documents,
+            recovery::restore_recovered_document,
+            recovery::discard_recovered_document,
+            history::create_checkpoint,
+            history::list_versions,
+            history::restore_version,
+            history::export_version,
+            backups::list_backups,
+            backups::restore_backup,
+            backups::get_backup_status,
+            backups::set_backup_settings,
+            open_files::take_opened_files,
+            deep_link::take_deep_links,
+            document_windows::new_window,
+            document_windows::get_window_document,
+            document_windows::set_window_document,
+            document_windows::list_document_windows,
+            document_windows::focus_window,
+            document_windows::close_window,
+            recent_files::add_recent_file,
+            recent_files::list_recent_files,
+            recent_files::pin_recent_file,
+            recent_files::remove_recent_file,
+            recent_files::clear_recent_files,
+            watcher::start_watching,
+            watcher::stop_watching,
+            locks::acquire_file_lock,
+            locks::get_file_lock_status,
+            locks::take_over_file_lock,
+            locks::release_file_lock,
+            stream::read_design_file_stream,
+            stream::cancel_read_stream,
+            tasks::start_task,
+            tasks::cancel_task,
+            tasks::list_tasks,
+            thumbnails::get_thumbnail,
+            thumbnails::invalidate_thumbnail,
+            import::svg::import_svg,
+            import::sanitize::sanitize_svg,
+            import::figma::import_fig,
+            import::figma::paste_from_figma,
+            import::raster::decode_image,
+            assets::import_asset,
+            assets::get_asset_info,
+            assets::proxies::create_asset_proxy,
+            assets::read_asset,
+            clipboard::paste_clipboard_image,
+            assets::stock::list_stock_photo_providers,
+            assets::stock::set_stock_photo_key,
+            assets::stock::search_stock_photos,
+            assets::stock::fetch_stock_thumbnail,
+            assets::stock::download_stock_photo,
+            import::trace::trace_image,
+            import::pdf::import_pdf,
+            import::sketch::import_sketch,
+            export::svg::export_svg,
+            export::pdf::export_pdf,
+            print::render_print_preview,
+            print::print_frames,
+            export::raster::export_raster,
+            clipboard::copy_image_to_clipboard,
+            clipboard::copy_svg_to_clipboard,
+            clipboard::copy_code_to_clipboard,
+            eyedropper::pick_screen_color,
+            export::batch::export_batch,
+            export::animation::export_animation,
+            export::video::export_video,
+            export::video::cancel_video_export,
+            export::video::video_export_support,
+            export::icons::export_app_icons,
+            export::presets::list_export_presets,
+            export::presets::save_export_preset,
+            export::presets::delete_export_preset,
+            export::presets::expand_export_preset,
+            converters::penpot::import_penpot,
+            converters::penpot::export_penpot,
+            converters::lottie::import_lottie,
+            converters::lottie::export_lottie,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running DesignLibre");
+        .build(tauri::generate_context!())
+        .expect("error while running DesignLibre")
+        .run(|app, event| {
+            deep_link::handle_run_event(app, &event);
+            open_files::handle_run_event(app, &event);
+        });
 }
@@ -0,0 +1,168 @@
+//! Opening large bundles without reading them whole. The bundle is mapped
+//! into memory and only its zip directory, manifest and top-level document
+//! are read when it's opened; the nodes of each page are parsed when the
+//! page is asked for, straight out of the mapping.
+
+use crate::bundle::{check_page_entry, read_entry, read_manifest, BundleManifest};
+use crate::model::SerializedNode;
+use crate::watcher::normalize;
+use memmap2::Mmap;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::{self, File, Metadata};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::SystemTime;
+use tauri::{command, State};
+use zip::{CompressionMethod, ZipArchive};
+
+/// Where a page's entry lies in the mapped bundle.
+struct PageEntry {
+    start: usize,
+    size: usize,
+    /// Deflated entries are inflated to this size; stored ones are used as
+    /// they are.
+    deflated: Option<usize>,
+}
+
+/// The size and modification time of a bundle file, to tell whether it is
+/// still the file that was mapped.
+#[derive(PartialEq)]
+struct FileStamp {
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+impl FileStamp {
+    fn of(metadata: &Metadata) -> Self {
+        FileStamp { size: metadata.len(), modified: metadata.modified().ok() }
+    }
+}
+
+struct MappedBundle {
+    map: Mmap,
+    stamp: FileStamp,
+    pages: HashMap<String, PageEntry>,
+}
+
+impl MappedBundle {
+    fn page(&self, id: &str) -> Result<Cow<'_, [u8]>, String> {
+        let entry = self.pages.get(id).ok_or_else(|| format!("No page {} in bundle", id))?;
+        let bytes = self.map.get(entry.start..entry.start + entry.size)
+            .ok_or_else(|| format!("Page {} lies outside the bundle", id))?;
+
+        match entry.deflated {
+            None => Ok(Cow::Borrowed(bytes)),
+            Some(size) => miniz_oxide::inflate::decompress_to_vec_with_limit(bytes, size)
+                .map(Cow::Owned)
+                .map_err(|e| format!("Failed to inflate page {}: {}", id, e)),
+        }
+    }
+}
+
+/// Bundles open for reading page by page, by path.
+#[derive(Default)]
+pub struct MappedBundles {
+    open: Mutex<HashMap<PathBuf, MappedBundle>>,
+}
+
+impl MappedBundles {
+    fn lock(&self) -> MutexGuard<'_, HashMap<PathBuf, MappedBundle>> {
+        self.open.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Drop the mapping of `path`, as on closing it and after every save to
+    /// it: saves rename a new file over the old one, which the mapping would
+    /// go on reading.
+    pub fn forget(&self, path: &str) {
+        self.lock().remove(&normalize(Path::new(path)));
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MappedBundleInfo {
+    pub path: String,
+    /// Lists the pages that can be read with `read_bundle_page`.
+    pub manifest: BundleManifest,
+    /// The document without the nodes under its pages.
+    pub document: String,
+}
+
+fn map_bundle(path: &Path) -> Result<(MappedBundle, BundleManifest, String), String> {
+    let file = File::open(path)
+        .map_err(|e| format!("Failed to open bundle: {}", e))?;
+    let stamp = file.metadata()
+        .map(|metadata| FileStamp::of(&metadata))
+        .map_err(|e| format!("Failed to open bundle: {}", e))?;
+
+    // Another program truncating the file in place would leave pages of the
+    // mapping without anything behind them, and reading those faults, so
+    // each read first checks the file is as it was mapped
+    let map = unsafe { Mmap::map(&file) }
+        .map_err(|e| format!("Failed to map bundle: {}", e))?;
+
+    let mut archive = ZipArchive::new(Cursor::new(&map[..]))
+        .map_err(|e| format!("Invalid bundle: {}", e))?;
+    let manifest = read_manifest(&mut archive)?;
+    let document = String::from_utf8(read_entry(&mut archive, &manifest.document)?)
+        .map_err(|e| format!("Bundle document is not valid UTF-8: {}", e))?;
+
+    let mut pages = HashMap::with_capacity(manifest.pages.len());
+    for page in &manifest.pages {
+        check_page_entry(&page.entry)?;
+        let entry = archive.by_name(&page.entry)
+            .map_err(|e| format!("Missing bundle entry {}: {}", page.entry, e))?;
+
+        let deflated = match entry.compression() {
+            CompressionMethod::Stored => None,
+            CompressionMethod::Deflated => Some(entry.size() as usize),
+            method => return Err(format!("Unsupported compression for {}: {}", page.entry, method)),
+        };
+        pages.insert(page.id.clone(), PageEntry {
+            start: entry.data_start() as usize,
+            size: entry.compressed_size() as usize,
+            deflated,
+        });
+    }
+    drop(archive);
+
+    Ok((MappedBundle { map, stamp, pages }, manifest, document))
+}
+
+/// Open the bundle at `path` for reading its pages one at a time. Bundles
+/// written before pages were kept apart have none, and their document is
+/// whole.
+#[command(async)]
+pub fn open_mapped_bundle(bundles: State<'_, MappedBundles>, path: String) -> Result<MappedBundleInfo, String> {
+    let (bundle, manifest, document) = map_bundle(Path::new(&path))?;
+    bundles.lock().insert(normalize(Path::new(&path)), bundle);
+
+    Ok(MappedBundleInfo { path, manifest, document })
+}
+
+/// The nodes under page `page_id` of the mapped bundle at `path`. Fails
+/// once the bundle has been saved or changed on disk since it was opened,
+/// and it's closed so that it can be opened again.
+#[command(async)]
+pub fn read_bundle_page(bundles: State<'_, MappedBundles>, path: String, page_id: String) -> Result<Vec<SerializedNode>, String> {
+    let key = normalize(Path::new(&path));
+    let mut open = bundles.lock();
+    let bundle = open.get(&key)
+        .ok_or_else(|| format!("Bundle is not open: {}", path))?;
+    if fs::metadata(&key).map(|metadata| FileStamp::of(&metadata)).ok().as_ref() != Some(&bundle.stamp) {
+        open.remove(&key);
+        return Err(format!("Bundle has changed on disk since it was opened: {}", path));
+    }
+
+    serde_json::from_slice(&bundle.page(&page_id)?)
+        .map_err(|e| format!("Invalid bundle page {}: {}", page_id, e))
+}
+
+#[command]
+pub fn close_mapped_bundle(bundles: State<'_, MappedBundles>, path: String) -> Result<(), String> {
+    bundles.forget(&path);
+    Ok(())
+}
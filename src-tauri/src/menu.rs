@@ -0,0 +1,283 @@
+//! The native menu bar. Items carry the ids of the frontend's own menu
+//! bar, and choosing one sends `menu` with that id for the frontend to run
+//! it, so both menus stay one set of actions. Clipboard items and the
+//! macOS app and window items are the system's own, so they also work in
+//! text fields.
+//!
+//! Open Recent lists the recent-files store and is rebuilt whenever the
+//! store changes, and the Window menu lists the document windows. Items are
+//! sent to the focused window.
+
+use crate::document_windows::{DocumentWindow, DocumentWindows};
+use crate::recent_files::{RecentFile, RecentFiles};
+use serde::Serialize;
+use tauri::menu::{Menu, MenuEvent, MenuItemBuilder, PredefinedMenuItem, Submenu, SubmenuBuilder};
+use tauri::{AppHandle, Emitter, Manager, Wry};
+
+pub const MENU_EVENT: &str = "menu";
+
+/// Recent file items have ids of this followed by the file's path.
+const RECENT_PREFIX: &str = "open-recent:";
+
+const CLEAR_RECENT: &str = "clear-recent";
+
+/// Window menu items have ids of this followed by the window's label.
+const WINDOW_PREFIX: &str = "window:";
+
+/// Items at the top of the Window menu, before the windows.
+const WINDOW_ITEMS: usize = 2;
+
+/// Files listed under Open Recent, most recent first.
+const RECENT_SHOWN: usize = 10;
+
+#[cfg(target_os = "macos")]
+const REDO: &str = "Cmd+Shift+Z";
+#[cfg(not(target_os = "macos"))]
+const REDO: &str = "Ctrl+Y";
+
+/// An item's id, label and accelerator, or a separator where unset.
+type Entry = Option<(&'static str, &'static str, Option<&'static str>)>;
+
+// Accelerators that are a bare key or Shift and a letter are left to the
+// frontend, as a menu would take them from text fields
+
+const IMPORT: &[Entry] = &[
+    Some(("import-svg", "SVG…", None)),
+    Some(("import-image", "Image…", None)),
+    Some(("import-figma", "Figma File…", None)),
+    Some(("import-sketch", "Sketch File…", None)),
+];
+
+const EXPORT: &[Entry] = &[
+    Some(("export-png", "PNG…", Some("CmdOrCtrl+Shift+E"))),
+    Some(("export-svg", "SVG…", None)),
+    Some(("export-pdf", "PDF…", None)),
+];
+
+const VIEW: &[Entry] = &[
+    Some(("zoom-in", "Zoom In", Some("CmdOrCtrl+="))),
+    Some(("zoom-out", "Zoom Out", Some("CmdOrCtrl+-"))),
+    Some(("zoom-fit", "Zoom to Fit", Some("CmdOrCtrl+1"))),
+    Some(("zoom-selection", "Zoom to Selection", Some("CmdOrCtrl+2"))),
+    Some(("zoom-100", "Actual Size", Some("CmdOrCtrl+0"))),
+    None,
+    Some(("show-grid", "Grid", Some("CmdOrCtrl+'"))),
+    Some(("show-rulers", "Rulers", Some("CmdOrCtrl+R"))),
+];
+
+const OBJECT: &[Entry] = &[
+    Some(("group", "Group", Some("CmdOrCtrl+G"))),
+    Some(("ungroup", "Ungroup", Some("CmdOrCtrl+Shift+G"))),
+    None,
+    Some(("create-component", "Create Component", Some("CmdOrCtrl+Alt+K"))),
+    Some(("detach-instance", "Detach Instance", None)),
+    None,
+    Some(("bring-front", "Bring to Front", Some("CmdOrCtrl+Shift+]"))),
+    Some(("bring-forward", "Bring Forward", Some("CmdOrCtrl+]"))),
+    Some(("send-backward", "Send Backward", Some("CmdOrCtrl+["))),
+    Some(("send-back", "Send to Back", Some("CmdOrCtrl+Shift+["))),
+    None,
+    Some(("flip-h", "Flip Horizontal", None)),
+    Some(("flip-v", "Flip Vertical", None)),
+    None,
+    Some(("lock", "Lock", Some("CmdOrCtrl+Shift+L"))),
+    Some(("unlock-all", "Unlock All", None)),
+    Some(("hide", "Hide", Some("CmdOrCtrl+Shift+H"))),
+    Some(("show-all", "Show All", None)),
+];
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MenuCommand {
+    /// The frontend menu id of the item chosen.
+    pub id: String,
+    /// The file of an Open Recent item.
+    pub path: Option<String>,
+}
+
+/// The parts of the menu changed after it is built.
+pub struct AppMenu {
+    recent: Submenu<Wry>,
+    window: Submenu<Wry>,
+}
+
+fn add_entries<'m>(
+    mut builder: SubmenuBuilder<'m, Wry, AppHandle>,
+    app: &AppHandle,
+    entries: &[Entry],
+) -> tauri::Result<SubmenuBuilder<'m, Wry, AppHandle>> {
+    for entry in entries {
+        builder = match entry {
+            Some((id, label, accelerator)) => {
+                let mut item = MenuItemBuilder::with_id(*id, *label);
+                if let Some(accelerator) = accelerator {
+                    item = item.accelerator(accelerator);
+                }
+                builder.item(&item.build(app)?)
+            }
+            None => builder.separator(),
+        };
+    }
+    Ok(builder)
+}
+
+fn submenu(app: &AppHandle, label: &str, entries: &[Entry]) -> tauri::Result<Submenu<Wry>> {
+    add_entries(SubmenuBuilder::new(app, label), app, entries)?.build()
+}
+
+/// Replace the items of Open Recent with `files`.
+fn fill_recent(app: &AppHandle, recent: &Submenu<Wry>, files: &[RecentFile]) -> tauri::Result<()> {
+    while recent.remove_at(0)?.is_some() {}
+    let shown: Vec<&RecentFile> = files.iter().filter(|file| file.exists).take(RECENT_SHOWN).collect();
+    if shown.is_empty() {
+        recent.append(&MenuItemBuilder::new("No Recent Files").enabled(false).build(app)?)?;
+        return Ok(());
+    }
+    for file in shown {
+        let id = format!("{}{}", RECENT_PREFIX, file.path);
+        recent.append(&MenuItemBuilder::with_id(id, &file.name).build(app)?)?;
+    }
+    recent.append(&PredefinedMenuItem::separator(app)?)?;
+    recent.append(&MenuItemBuilder::with_id(CLEAR_RECENT, "Clear Menu").build(app)?)
+}
+
+/// List `windows` in the Window menu. macOS lists windows there itself.
+fn fill_windows(app: &AppHandle, window: &Submenu<Wry>, windows: &[DocumentWindow]) -> tauri::Result<()> {
+    if cfg!(target_os = "macos") {
+        return Ok(());
+    }
+    while window.remove_at(WINDOW_ITEMS)?.is_some() {}
+    if windows.is_empty() {
+        return Ok(());
+    }
+    window.append(&PredefinedMenuItem::separator(app)?)?;
+    for document in windows {
+        let id = format!("{}{}", WINDOW_PREFIX, document.label);
+        window.append(&MenuItemBuilder::with_id(id, &document.title).build(app)?)?;
+    }
+    Ok(())
+}
+
+fn build(app: &AppHandle) -> tauri::Result<AppMenu> {
+    let recent = SubmenuBuilder::with_id(app, "open-recent", "Open Recent").build()?;
+    fill_recent(app, &recent, &app.state::<RecentFiles>().list())?;
+
+    let file = SubmenuBuilder::new(app, "File")
+        .item(&MenuItemBuilder::with_id("new", "New").accelerator("CmdOrCtrl+N").build(app)?)
+        .item(&MenuItemBuilder::with_id("open", "Open…").accelerator("CmdOrCtrl+O").build(app)?)
+        .item(&recent)
+        .separator()
+        .item(&MenuItemBuilder::with_id("save", "Save").accelerator("CmdOrCtrl+S").build(app)?)
+        .item(&MenuItemBuilder::with_id("save-as", "Save As…").accelerator("CmdOrCtrl+Shift+S").build(app)?)
+        .separator()
+        .item(&submenu(app, "Import", IMPORT)?)
+        .item(&submenu(app, "Export", EXPORT)?)
+        .separator()
+        .item(&MenuItemBuilder::with_id("print", "Print…").accelerator("CmdOrCtrl+P").build(app)?)
+        .separator()
+        .item(&MenuItemBuilder::with_id("close", "Close").accelerator("CmdOrCtrl+W").build(app)?);
+    #[cfg(not(target_os = "macos"))]
+    let file = file.quit_with_text("Exit");
+
+    // Undo and redo are the document's; the frontend passes them on to a
+    // focused text field
+    let edit = SubmenuBuilder::new(app, "Edit")
+        .item(&MenuItemBuilder::with_id("undo", "Undo").accelerator("CmdOrCtrl+Z").build(app)?)
+        .item(&MenuItemBuilder::with_id("redo", "Redo").accelerator(REDO).build(app)?)
+        .separator()
+        .cut()
+        .copy()
+        .paste()
+        .item(&MenuItemBuilder::with_id("paste-in-place", "Paste in Place").accelerator("CmdOrCtrl+Shift+V").build(app)?)
+        .item(&MenuItemBuilder::with_id("delete", "Delete").build(app)?)
+        .item(&MenuItemBuilder::with_id("duplicate", "Duplicate").accelerator("CmdOrCtrl+D").build(app)?)
+        .separator()
+        .select_all()
+        .separator()
+        .item(&MenuItemBuilder::with_id("find-replace", "Find and Replace…").accelerator("CmdOrCtrl+F").build(app)?);
+    #[cfg(not(target_os = "macos"))]
+    let edit = edit
+        .separator()
+        .item(&MenuItemBuilder::with_id("preferences", "Preferences…").accelerator("Ctrl+,").build(app)?);
+
+    let view = add_entries(SubmenuBuilder::new(app, "View"), app, VIEW)?.separator();
+    #[cfg(target_os = "macos")]
+    let view = view.fullscreen();
+    #[cfg(not(target_os = "macos"))]
+    let view = view.item(&MenuItemBuilder::with_id("fullscreen", "Full Screen").accelerator("F11").build(app)?);
+
+    let window = SubmenuBuilder::new(app, "Window").minimize().maximize().build()?;
+    fill_windows(app, &window, &app.state::<DocumentWindows>().list())?;
+
+    let menu = Menu::new(app)?;
+    #[cfg(target_os = "macos")]
+    menu.append(
+        &SubmenuBuilder::new(app, "DesignLibre")
+            .about(None)
+            .separator()
+            .item(&MenuItemBuilder::with_id("preferences", "Settings…").accelerator("Cmd+,").build(app)?)
+            .separator()
+            .services()
+            .separator()
+            .hide()
+            .hide_others()
+            .show_all()
+            .separator()
+            .quit()
+            .build()?,
+    )?;
+    menu.append_items(&[&file.build()?, &edit.build()?, &view.build()?, &submenu(app, "Object", OBJECT)?, &window])?;
+    #[cfg(target_os = "macos")]
+    window.set_as_windows_menu_for_nsapp()?;
+    app.set_menu(menu)?;
+    Ok(AppMenu { recent, window })
+}
+
+/// Build the menu and set it for the app.
+pub fn install(app: &AppHandle) -> tauri::Result<()> {
+    let menu = build(app)?;
+    app.manage(menu);
+    Ok(())
+}
+
+/// Rebuild Open Recent from the recent-files store.
+pub fn refresh_recent(app: &AppHandle) {
+    if let Some(menu) = app.try_state::<AppMenu>() {
+        let _ = fill_recent(app, &menu.recent, &app.state::<RecentFiles>().list());
+    }
+}
+
+/// Rebuild the Window menu's list of windows.
+pub fn refresh_windows(app: &AppHandle, windows: &[DocumentWindow]) {
+    if let Some(menu) = app.try_state::<AppMenu>() {
+        let _ = fill_windows(app, &menu.window, windows);
+    }
+}
+
+/// Send a chosen item to the focused window, or to every window when none
+/// has focus. Clearing Open Recent is done here, and sent so the
+/// frontend's own list follows; choosing a window focuses it.
+pub fn handle_menu_event(app: &AppHandle, event: MenuEvent) {
+    let id = event.id().as_ref();
+    if let Some(label) = id.strip_prefix(WINDOW_PREFIX) {
+        if let Some(window) = app.get_webview_window(label) {
+            let _ = window.unminimize();
+            let _ = window.set_focus();
+        }
+        return;
+    }
+    let command = if let Some(path) = id.strip_prefix(RECENT_PREFIX) {
+        MenuCommand { id: "open-recent".to_string(), path: Some(path.to_string()) }
+    } else {
+        if id == CLEAR_RECENT {
+            let _ = app.state::<RecentFiles>().clear(false);
+            refresh_recent(app);
+        }
+        MenuCommand { id: id.to_string(), path: None }
+    };
+    let focused = app.webview_windows().into_values().find(|window| window.is_focused().unwrap_or(false));
+    let _ = match focused {
+        Some(window) => app.emit_to(window.label(), MENU_EVENT, command),
+        None => app.emit(MENU_EVENT, command),
+    };
+}
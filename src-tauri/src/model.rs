@@ -0,0 +1,706 @@
+//! Rust mirror of the frontend's serialized document format
+//! (`src/persistence/serialization/document-serializer.ts`).
+//!
+//! Only the properties the backend interprets are typed; everything else is
+//! kept in `extra` so documents round-trip without losing data.
+
+use crate::bundle::now_millis;
+use crate::geometry::{compose, multiply, path_bounds, scale, transform_path, Matrix, Rect, IDENTITY};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasher, Hasher, RandomState};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A UUID v7 node id, in the same format as `generateNodeId` in
+/// `src/core/utils/uuid.ts`.
+pub fn generate_node_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    // RandomState is seeded randomly per process and per instance
+    let random = |salt: u64| {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed) ^ salt);
+        hasher.finish()
+    };
+    let (r1, r2) = (random(0), random(u64::MAX));
+    let millis = now_millis() & 0xffff_ffff_ffff;
+
+    format!(
+        "{:08x}-{:04x}-7{:03x}-{:04x}-{:012x}",
+        millis >> 16,
+        millis & 0xffff,
+        r1 & 0xfff,
+        0x8000 | ((r1 >> 12) & 0x3fff),
+        r2 & 0xffff_ffff_ffff,
+    )
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_one() -> f64 {
+    1.0
+}
+
+fn default_identity() -> Matrix {
+    IDENTITY
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Rgba {
+    pub r: f64,
+    pub g: f64,
+    pub b: f64,
+    #[serde(default = "default_one")]
+    pub a: f64,
+}
+
+impl Rgba {
+    /// `#rrggbb`, ignoring alpha.
+    pub fn hex(self) -> String {
+        let c = |v: f64| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+        format!("#{:02x}{:02x}{:02x}", c(self.r), c(self.g), c(self.b))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct GradientStop {
+    pub position: f64,
+    pub color: Rgba,
+}
+
+/// Fill or stroke paint.
+///
+/// Gradient and image transforms map paint space onto the node's normalized
+/// bounding box (0..1 on both axes). With the identity transform a linear
+/// gradient runs from (0, 0.5) to (1, 0.5) and a radial gradient is centered
+/// at (0.5, 0.5) with radius 0.5.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE", rename_all_fields = "camelCase")]
+pub enum Paint {
+    Solid {
+        #[serde(default = "default_true")]
+        visible: bool,
+        #[serde(default = "default_one")]
+        opacity: f64,
+        color: Rgba,
+    },
+    GradientLinear {
+        #[serde(default = "default_true")]
+        visible: bool,
+        #[serde(default = "default_one")]
+        opacity: f64,
+        gradient_stops: Vec<GradientStop>,
+        #[serde(default = "default_identity")]
+        gradient_transform: Matrix,
+    },
+    GradientRadial {
+        #[serde(default = "default_true")]
+        visible: bool,
+        #[serde(default = "default_one")]
+        opacity: f64,
+        gradient_stops: Vec<GradientStop>,
+        #[serde(default = "default_identity")]
+        gradient_transform: Matrix,
+    },
+    Image {
+        #[serde(default = "default_true")]
+        visible: bool,
+        #[serde(default = "default_one")]
+        opacity: f64,
+        image_ref: String,
+        #[serde(default = "ScaleMode::default")]
+        scale_mode: ScaleMode,
+        #[serde(default = "default_identity")]
+        image_transform: Matrix,
+    },
+    /// Another node, `source_node_id`, repeated across the shape from its
+    /// top left.
+    Pattern {
+        #[serde(default = "default_true")]
+        visible: bool,
+        #[serde(default = "default_one")]
+        opacity: f64,
+        source_node_id: String,
+        #[serde(default)]
+        tile_type: TileType,
+        /// Size of each tile relative to the source node.
+        #[serde(default = "default_one")]
+        scaling_factor: f64,
+        /// Gap between tiles, in the node's units.
+        #[serde(default)]
+        spacing: Spacing,
+        /// Degrees, clockwise.
+        #[serde(default)]
+        rotation: f64,
+    },
+}
+
+impl Paint {
+    pub fn visible(&self) -> bool {
+        match self {
+            Paint::Solid { visible, .. }
+            | Paint::GradientLinear { visible, .. }
+            | Paint::GradientRadial { visible, .. }
+            | Paint::Image { visible, .. }
+            | Paint::Pattern { visible, .. } => *visible,
+        }
+    }
+
+    pub fn opacity(&self) -> f64 {
+        match self {
+            Paint::Solid { opacity, .. }
+            | Paint::GradientLinear { opacity, .. }
+            | Paint::GradientRadial { opacity, .. }
+            | Paint::Image { opacity, .. }
+            | Paint::Pattern { opacity, .. } => *opacity,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ScaleMode {
+    #[default]
+    Fill,
+    Fit,
+    Crop,
+    Tile,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TileType {
+    #[default]
+    Rectangular,
+    /// Rows shifted by half a tile, alternately.
+    HorizontalHexagonal,
+    /// Columns shifted by half a tile, alternately.
+    VerticalHexagonal,
+}
+
+impl TileType {
+    /// The repeating cell of tiles `size` apart, and where tiles sit in it.
+    pub fn layout(self, (width, height): (f64, f64)) -> ((f64, f64), Vec<(f64, f64)>) {
+        match self {
+            TileType::Rectangular => ((width, height), vec![(0.0, 0.0)]),
+            TileType::HorizontalHexagonal => {
+                ((width, 2.0 * height), vec![(0.0, 0.0), (width / 2.0, height), (-width / 2.0, height)])
+            }
+            TileType::VerticalHexagonal => {
+                ((2.0 * width, height), vec![(0.0, 0.0), (width, height / 2.0), (width, -height / 2.0)])
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub struct Spacing {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// A shape drawn at an open end of a stroked path, sized by the stroke
+/// weight and pointing the way the path leaves.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Marker {
+    /// Two lines back from the end, stroked like the path.
+    ArrowLines,
+    /// A filled triangle with its point at the end.
+    ArrowEquilateral,
+    /// A filled triangle standing on the end, pointing away.
+    TriangleFilled,
+    CircleFilled,
+    DiamondFilled,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum WindingRule {
+    #[default]
+    Nonzero,
+    Evenodd,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "type")]
+pub enum PathCommand {
+    #[serde(rename = "M")]
+    MoveTo { x: f64, y: f64 },
+    #[serde(rename = "L")]
+    LineTo { x: f64, y: f64 },
+    #[serde(rename = "C")]
+    CurveTo { x1: f64, y1: f64, x2: f64, y2: f64, x: f64, y: f64 },
+    #[serde(rename = "Z")]
+    ClosePath,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct VectorPath {
+    #[serde(default)]
+    pub winding_rule: WindingRule,
+    pub commands: Vec<PathCommand>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TextStyleRange {
+    pub start: usize,
+    pub end: usize,
+    #[serde(default = "TextStyleRange::default_family")]
+    pub font_family: String,
+    #[serde(default = "TextStyleRange::default_weight")]
+    pub font_weight: u16,
+    #[serde(default = "TextStyleRange::default_size")]
+    pub font_size: f64,
+    #[serde(default)]
+    pub fills: Vec<Paint>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text_decoration: Option<String>,
+    #[serde(default)]
+    pub letter_spacing: f64,
+    /// Absolute line height in pixels, or `"AUTO"`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line_height: Option<Value>,
+}
+
+impl TextStyleRange {
+    fn default_family() -> String {
+        "Inter".to_string()
+    }
+
+    fn default_weight() -> u16 {
+        400
+    }
+
+    fn default_size() -> f64 {
+        14.0
+    }
+
+    /// Line height in pixels, falling back to 1.2em for `"AUTO"`.
+    pub fn line_height_px(&self) -> f64 {
+        self.line_height
+            .as_ref()
+            .and_then(Value::as_f64)
+            .unwrap_or(self.font_size * 1.2)
+    }
+
+    /// The style of `len` characters of text without style ranges.
+    pub fn unstyled(len: usize) -> Self {
+        TextStyleRange {
+            start: 0,
+            end: len,
+            font_family: Self::default_family(),
+            font_weight: Self::default_weight(),
+            font_size: Self::default_size(),
+            fills: Vec::new(),
+            text_decoration: None,
+            letter_spacing: 0.0,
+            line_height: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum NodeType {
+    Document,
+    Page,
+    Frame,
+    Group,
+    Vector,
+    Text,
+    Image,
+    Component,
+    Instance,
+    BooleanOperation,
+    Slice,
+    Rectangle,
+    Ellipse,
+    Line,
+    Polygon,
+    Star,
+}
+
+/// Node properties (`NodeData` in `src/scene/nodes/base-node.ts`).
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeData {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub node_type: NodeType,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default = "default_true")]
+    pub visible: bool,
+    #[serde(default)]
+    pub locked: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<String>,
+    #[serde(default)]
+    pub child_ids: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub y: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<f64>,
+    /// Degrees, clockwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<f64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opacity: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blend_mode: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fills: Option<Vec<Paint>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strokes: Option<Vec<Paint>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stroke_weight: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stroke_align: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stroke_cap: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stroke_join: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stroke_miter_limit: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dash_pattern: Option<Vec<f64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dash_offset: Option<f64>,
+    /// Markers at the first and last points of each open subpath.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_marker: Option<Marker>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_marker: Option<Marker>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub effects: Option<Vec<Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clips_content: Option<bool>,
+    /// Masks the siblings above it instead of being drawn.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub is_mask: Option<bool>,
+    /// `ALPHA`, the default, `VECTOR` for the shape's coverage, or
+    /// `LUMINANCE`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mask_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub corner_radius: Option<f64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vector_paths: Option<Vec<VectorPath>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub characters: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text_styles: Option<Vec<TextStyleRange>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text_align_horizontal: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text_align_vertical: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_ref: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale_mode: Option<ScaleMode>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub background_color: Option<Rgba>,
+
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl NodeData {
+    /// A bare node of `node_type` with every optional property unset.
+    pub fn new(id: impl Into<String>, node_type: NodeType, name: impl Into<String>) -> Self {
+        NodeData {
+            id: id.into(),
+            node_type,
+            name: name.into(),
+            visible: true,
+            locked: false,
+            parent_id: None,
+            child_ids: Vec::new(),
+            x: None,
+            y: None,
+            width: None,
+            height: None,
+            rotation: None,
+            opacity: None,
+            blend_mode: None,
+            fills: None,
+            strokes: None,
+            stroke_weight: None,
+            stroke_align: None,
+            stroke_cap: None,
+            stroke_join: None,
+            stroke_miter_limit: None,
+            dash_pattern: None,
+            dash_offset: None,
+            start_marker: None,
+            end_marker: None,
+            effects: None,
+            clips_content: None,
+            is_mask: None,
+            mask_type: None,
+            corner_radius: None,
+            vector_paths: None,
+            characters: None,
+            text_styles: None,
+            text_align_horizontal: None,
+            text_align_vertical: None,
+            image_ref: None,
+            scale_mode: None,
+            background_color: None,
+            extra: Map::new(),
+        }
+    }
+
+    pub fn opacity(&self) -> f64 {
+        self.opacity.unwrap_or(1.0)
+    }
+
+    pub fn stroke_weight(&self) -> f64 {
+        self.stroke_weight.unwrap_or(1.0)
+    }
+
+    pub fn fills(&self) -> &[Paint] {
+        self.fills.as_deref().unwrap_or(&[])
+    }
+
+    pub fn strokes(&self) -> &[Paint] {
+        self.strokes.as_deref().unwrap_or(&[])
+    }
+
+    pub fn has_transform(&self) -> bool {
+        self.x.is_some() && self.y.is_some()
+    }
+
+    /// Parent-relative transform, as computed by `SceneGraph.getWorldTransform`.
+    pub fn local_transform(&self) -> Matrix {
+        if !self.has_transform() {
+            return IDENTITY;
+        }
+        let rotation = self.rotation.unwrap_or(0.0).to_radians();
+        compose(self.x.unwrap_or(0.0), self.y.unwrap_or(0.0), rotation, 1.0, 1.0)
+    }
+
+    /// Vector paths stretched so their bounds match the node size, as the
+    /// webview renderer draws them.
+    pub fn fitted_vector_paths(&self) -> Option<Vec<VectorPath>> {
+        let paths = self.vector_paths.as_ref().filter(|p| !p.is_empty())?;
+        let Some(bounds) = paths.iter().filter_map(path_bounds).reduce(|a, b| a.union(&b)) else {
+            return Some(paths.clone());
+        };
+
+        let fit = |size: Option<f64>, extent: f64| match size {
+            Some(size) if extent > 0.0 => size / extent,
+            _ => 1.0,
+        };
+        let (sx, sy) = (fit(self.width, bounds.width), fit(self.height, bounds.height));
+        if (sx - 1.0).abs() <= 1e-3 && (sy - 1.0).abs() <= 1e-3 {
+            return Some(paths.clone());
+        }
+
+        let m = scale(sx, sy);
+        Some(paths.iter().map(|p| transform_path(p, &m)).collect())
+    }
+
+    /// Local-space bounds: `(0, 0)` to `(width, height)`.
+    pub fn local_bounds(&self) -> Option<Rect> {
+        Some(Rect::new(0.0, 0.0, self.width?, self.height?))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SerializedNode {
+    pub id: String,
+    pub parent_id: Option<String>,
+    #[serde(default)]
+    pub child_index: usize,
+    pub data: NodeData,
+}
+
+/// Layout of the document JSON the backend writes. Files from before
+/// `formatVersion` was recorded count as version 0; each step between
+/// versions is a migration in `document::migrate`.
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SerializedDocument {
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub format_version: u32,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub created_at: String,
+    #[serde(default)]
+    pub updated_at: String,
+    pub nodes: Vec<SerializedNode>,
+    pub root_id: String,
+}
+
+/// Deepest a node may be nested below a node without a parent. Renders and
+/// exports walk the tree recursively, so this bounds the stack they use.
+const MAX_DEPTH: usize = 256;
+
+/// `parents` without the links that don't lead up to a node without a
+/// parent within `MAX_DEPTH` steps: those in a cycle, under one, or too
+/// deep. Nodes cut off that way are left out of every walk from the root.
+fn valid_parents(mut parents: HashMap<String, String>) -> HashMap<String, String> {
+    let mut depths: HashMap<&str, Option<usize>> = HashMap::with_capacity(parents.len());
+    for start in parents.keys() {
+        let mut path = Vec::new();
+        let mut on_path = HashSet::new();
+        let mut current = start.as_str();
+        // The depth of `current`, or none when it can't be reached
+        let top = loop {
+            if let Some(depth) = depths.get(current) {
+                break *depth;
+            }
+            let Some(parent) = parents.get(current) else { break Some(0) };
+            if !on_path.insert(current) {
+                break None;
+            }
+            path.push(current);
+            current = parent;
+        };
+        depths.insert(current, top);
+        let mut depth = top;
+        for id in path.into_iter().rev() {
+            depth = depth.map(|d| d + 1).filter(|d| *d <= MAX_DEPTH);
+            depths.insert(id, depth);
+        }
+    }
+    let cut: Vec<String> = depths.into_iter().filter(|(_, depth)| depth.is_none()).map(|(id, _)| id.to_string()).collect();
+    for id in cut {
+        parents.remove(&id);
+    }
+    parents
+}
+
+/// Indexed view over a serialized document for tree walks.
+pub struct DocumentTree {
+    pub root_id: String,
+    nodes: HashMap<String, NodeData>,
+    children: HashMap<String, Vec<String>>,
+    parents: HashMap<String, String>,
+}
+
+impl DocumentTree {
+    /// Index `doc`. A node whose id was already seen is dropped, and so are
+    /// parent links that loop or nest deeper than `MAX_DEPTH`, which files
+    /// from elsewhere can have.
+    pub fn from_serialized(doc: SerializedDocument) -> Self {
+        let mut parents = HashMap::new();
+        let mut indexes = HashMap::new();
+        let mut nodes = HashMap::with_capacity(doc.nodes.len());
+
+        for (position, node) in doc.nodes.into_iter().enumerate() {
+            if nodes.contains_key(&node.id) {
+                continue;
+            }
+            if let Some(parent) = node.parent_id {
+                parents.insert(node.id.clone(), parent);
+                // Siblings without an index keep the file's order
+                indexes.insert(node.id.clone(), (node.child_index, position));
+            }
+            nodes.insert(node.id, node.data);
+        }
+        // The root is at the top however the file has it
+        parents.remove(&doc.root_id);
+        let parents = valid_parents(parents);
+
+        let mut ordered: HashMap<String, Vec<(usize, usize, String)>> = HashMap::new();
+        for (id, parent) in &parents {
+            let (index, position) = indexes[id];
+            ordered.entry(parent.clone()).or_default().push((index, position, id.clone()));
+        }
+        let children = ordered
+            .into_iter()
+            .map(|(parent, mut kids)| {
+                kids.sort_by_key(|(index, position, _)| (*index, *position));
+                (parent, kids.into_iter().map(|(_, _, id)| id).collect())
+            })
+            .collect();
+
+        DocumentTree { root_id: doc.root_id, nodes, children, parents }
+    }
+
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let doc: SerializedDocument = serde_json::from_str(json)
+            .map_err(|e| format!("Invalid document: {}", e))?;
+        Ok(DocumentTree::from_serialized(doc))
+    }
+
+    pub fn get(&self, id: &str) -> Option<&NodeData> {
+        self.nodes.get(id)
+    }
+
+    /// Every node, in no particular order.
+    pub fn nodes(&self) -> impl Iterator<Item = &NodeData> {
+        self.nodes.values()
+    }
+
+    pub fn children(&self, id: &str) -> &[String] {
+        self.children.get(id).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn parent(&self, id: &str) -> Option<&str> {
+        self.parents.get(id).map(String::as_str)
+    }
+
+    pub fn pages(&self) -> Vec<&NodeData> {
+        self.children(&self.root_id)
+            .iter()
+            .filter_map(|id| self.get(id))
+            .filter(|n| n.node_type == NodeType::Page)
+            .collect()
+    }
+
+    /// Product of the local transforms from the root down to `id`.
+    pub fn world_transform(&self, id: &str) -> Matrix {
+        let mut chain = Vec::new();
+        let mut visited = HashSet::new();
+        let mut current = Some(id);
+        while let Some(node_id) = current.filter(|node_id| visited.insert(*node_id)) {
+            if let Some(node) = self.get(node_id) {
+                chain.push(node.local_transform());
+            }
+            current = self.parent(node_id);
+        }
+
+        chain.iter().rev().fold(IDENTITY, |acc, m| multiply(&acc, m))
+    }
+
+    /// Axis-aligned bounds of `id` in world space. Pages, and nodes without a
+    /// size, use the union of their children.
+    pub fn world_bounds(&self, id: &str) -> Option<Rect> {
+        let node = self.get(id)?;
+        if !matches!(node.node_type, NodeType::Document | NodeType::Page) {
+            if let Some(local) = node.local_bounds() {
+                return Some(local.transformed(&self.world_transform(id)));
+            }
+        }
+
+        self.children(id)
+            .iter()
+            .filter_map(|child| self.world_bounds(child))
+            .reduce(|a, b| a.union(&b))
+    }
+}
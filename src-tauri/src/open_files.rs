@@ -0,0 +1,114 @@
+//! Documents the OS asks the app to open: double-clicked in a file manager,
+//! dropped on the dock icon or chosen with "Open With". Windows and Linux
+//! start the app with them as arguments; when it is already running the
+//! new copy hands its arguments to the running one through the
+//! single-instance plugin and exits. macOS sends an open-documents event to
+//! the running app instead. Files asked for before the frontend is ready
+//! are kept until it takes them.
+
+use crate::document_windows::MAIN_WINDOW;
+use serde::Serialize;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use tauri::{command, AppHandle, Emitter, Manager, RunEvent, State};
+
+pub const OPEN_FILES_EVENT: &str = "open-files";
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenFilesEvent {
+    pub paths: Vec<String>,
+}
+
+struct Pending {
+    /// Whether the frontend has taken the files from launch, after which
+    /// files are sent to it as they come.
+    ready: bool,
+    paths: Vec<String>,
+}
+
+pub struct OpenedFiles {
+    pending: Mutex<Pending>,
+}
+
+/// The files among a launch's arguments, made absolute against `cwd`, the
+/// directory it was started in. The first argument is the executable and
+/// flags are left out.
+pub fn file_arguments(args: impl IntoIterator<Item = String>, cwd: &Path) -> Vec<String> {
+    args.into_iter()
+        .skip(1)
+        .filter(|arg| !arg.starts_with('-'))
+        .map(|arg| cwd.join(arg))
+        .filter(|path| path.is_file())
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect()
+}
+
+/// Show a window over other apps, for a request to open something: the
+/// main window, or another when it has been closed.
+pub fn bring_to_front(app: &AppHandle) {
+    let window = app.get_webview_window(MAIN_WINDOW).or_else(|| app.webview_windows().into_values().next());
+    if let Some(window) = window {
+        let _ = window.unminimize();
+        let _ = window.set_focus();
+    }
+}
+
+impl OpenedFiles {
+    pub fn new(paths: Vec<String>) -> Self {
+        OpenedFiles { pending: Mutex::new(Pending { ready: false, paths }) }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Pending> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Send `paths` to the frontend, or keep them until it is ready, and
+    /// bring the app to the front.
+    pub fn open(&self, app: &AppHandle, paths: Vec<String>) {
+        if paths.is_empty() {
+            return;
+        }
+        bring_to_front(app);
+        let mut pending = self.lock();
+        if pending.ready {
+            drop(pending);
+            let _ = app.emit(OPEN_FILES_EVENT, OpenFilesEvent { paths });
+        } else {
+            pending.paths.extend(paths);
+        }
+    }
+}
+
+/// Open the files another launch of the app was given, in its working
+/// directory `cwd`.
+pub fn handle_second_instance(app: &AppHandle, args: &[String], cwd: &str) {
+    let paths = file_arguments(args.iter().cloned(), Path::new(cwd));
+    app.state::<OpenedFiles>().open(app, paths);
+}
+
+/// Open the files macOS asks the running app to open.
+pub fn handle_run_event(app: &AppHandle, event: &RunEvent) {
+    #[cfg(target_os = "macos")]
+    if let RunEvent::Opened { urls } = event {
+        let paths = urls
+            .iter()
+            .filter_map(|url| url.to_file_path().ok())
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+        if let Some(opened) = app.try_state::<OpenedFiles>() {
+            opened.open(app, paths);
+        }
+    }
+    #[cfg(not(target_os = "macos"))]
+    let _ = (app, event);
+}
+
+/// Files the app was asked to open before the frontend was listening for
+/// `open-files`, to call once it is. Later files come as events.
+#[command]
+pub fn take_opened_files(opened: State<'_, OpenedFiles>) -> Vec<String> {
+    let mut pending = opened.lock();
+    pending.ready = true;
+    std::mem::take(&mut pending.paths)
+}
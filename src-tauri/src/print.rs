@@ -0,0 +1,603 @@
+//! Printing frames. The webview can only print the page it shows, which for
+//! the canvas is the editor around it, so frames are laid out on sheets of
+//! paper here and written as a PDF, one sheet per frame, which then goes to
+//! the system's print dialog: PDFKit's on macOS, the desktop portal's on
+//! Linux (which asks the desktop, so it works under Wayland and in Flatpak
+//! too). Windows has no PDF printing of its own, so there the common print
+//! dialog picks the printer and each sheet is rasterized onto it.
+//!
+//! The sheet is already what gets printed, so the dialogs are asked not to
+//! scale it again; they are only for choosing a printer and copies.
+
+use crate::export::pdf::{render_pdf_placed, PagePlacement, PdfExport, PdfExportOptions, RenderedPdf};
+use crate::model::DocumentTree;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use pdf_writer::Rect;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+use std::fs;
+use tauri::{command, WebviewWindow};
+
+/// Points per millimetre.
+const MM: f64 = 72.0 / 25.4;
+
+/// Points per canvas pixel at 100%: a pixel prints at 1/96 in, as browsers
+/// print CSS pixels.
+const POINTS_PER_PIXEL: f64 = 0.75;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE", rename_all_fields = "camelCase")]
+pub enum PaperSize {
+    #[default]
+    A4,
+    A3,
+    A5,
+    Letter,
+    Legal,
+    Tabloid,
+    /// In millimetres, portrait.
+    Custom { width: f64, height: f64 },
+}
+
+impl PaperSize {
+    /// Width and height in millimetres, portrait.
+    fn millimetres(self) -> (f64, f64) {
+        match self {
+            PaperSize::A4 => (210.0, 297.0),
+            PaperSize::A3 => (297.0, 420.0),
+            PaperSize::A5 => (148.0, 210.0),
+            PaperSize::Letter => (215.9, 279.4),
+            PaperSize::Legal => (215.9, 355.6),
+            PaperSize::Tabloid => (279.4, 431.8),
+            PaperSize::Custom { width, height } => (width.min(height), width.max(height)),
+        }
+    }
+
+    /// The PWG name print dialogs know the size by.
+    #[cfg(target_os = "linux")]
+    fn pwg_name(self) -> Option<&'static str> {
+        match self {
+            PaperSize::A4 => Some("iso_a4"),
+            PaperSize::A3 => Some("iso_a3"),
+            PaperSize::A5 => Some("iso_a5"),
+            PaperSize::Letter => Some("na_letter"),
+            PaperSize::Legal => Some("na_legal"),
+            PaperSize::Tabloid => Some("na_ledger"),
+            PaperSize::Custom { .. } => None,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Orientation {
+    /// Landscape for frames wider than they are tall, portrait otherwise.
+    #[default]
+    Auto,
+    Portrait,
+    Landscape,
+}
+
+/// Space left blank around the printed frame, in millimetres. Most printers
+/// can't print the outer few millimetres of a sheet.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Margins {
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+    pub left: f64,
+}
+
+impl Default for Margins {
+    fn default() -> Self {
+        Margins { top: 10.0, right: 10.0, bottom: 10.0, left: 10.0 }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE", rename_all_fields = "camelCase")]
+pub enum Scaling {
+    /// As large as fits within the margins.
+    #[default]
+    Fit,
+    /// At 100%, or smaller when that doesn't fit.
+    ShrinkToFit,
+    /// At 100%, cut off at the margins when it doesn't fit.
+    ActualSize,
+    Percent { percent: f64 },
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PrintOptions {
+    /// Frames to print, one sheet each. Defaults to every top-level frame,
+    /// as for PDF export.
+    pub node_ids: Option<Vec<String>>,
+    pub paper: PaperSize,
+    pub orientation: Orientation,
+    pub margins: Margins,
+    pub scaling: Scaling,
+    /// The print job's name, shown in the dialog and the printer queue.
+    pub title: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrintResult {
+    /// True once the sheets have gone to the printer, false when the print
+    /// dialog was cancelled.
+    pub printed: bool,
+    pub sheet_count: usize,
+    pub warnings: Vec<String>,
+}
+
+impl PrintOptions {
+    fn title(&self) -> String {
+        self.title.clone().filter(|title| !title.trim().is_empty()).unwrap_or_else(|| "DesignLibre".to_string())
+    }
+
+    /// The sheet for a frame of `width` by `height` pixels, in points.
+    fn sheet(&self, width: f64, height: f64) -> (f64, f64) {
+        let (short, long) = self.paper.millimetres();
+        let landscape = match self.orientation {
+            Orientation::Auto => width > height,
+            Orientation::Portrait => false,
+            Orientation::Landscape => true,
+        };
+        let (w, h) = if landscape { (long, short) } else { (short, long) };
+        (w * MM, h * MM)
+    }
+
+    /// Points per pixel for a frame of `width` by `height` pixels drawn in
+    /// a box of `room` points.
+    fn scale(&self, width: f64, height: f64, room: (f64, f64)) -> f64 {
+        let fit = (room.0 / width).min(room.1 / height);
+        match self.scaling {
+            Scaling::Fit => fit,
+            Scaling::ShrinkToFit => fit.min(POINTS_PER_PIXEL),
+            Scaling::ActualSize => POINTS_PER_PIXEL,
+            Scaling::Percent { percent } => POINTS_PER_PIXEL * percent / 100.0,
+        }
+    }
+
+    /// Where a frame of `width` by `height` pixels goes on its sheet: scaled
+    /// as asked, centered within the margins and cut off at them. Also gives
+    /// whether it had to be cut off.
+    fn place(&self, width: f64, height: f64) -> (PagePlacement, bool) {
+        let (width, height) = (width.max(1.0), height.max(1.0));
+        let (sheet_width, sheet_height) = self.sheet(width, height);
+        let margins = self.margins;
+        let (left, bottom) = (margins.left * MM, margins.bottom * MM);
+        let room = (sheet_width - left - margins.right * MM, sheet_height - bottom - margins.top * MM);
+        let scale = self.scale(width, height, room);
+        let (drawn_width, drawn_height) = (width * scale, height * scale);
+        let overflows = drawn_width > room.0 + 0.01 || drawn_height > room.1 + 0.01;
+        let (x, y) = (left + (room.0 - drawn_width) / 2.0, bottom + (room.1 - drawn_height) / 2.0);
+        let placement = PagePlacement {
+            width: sheet_width as f32,
+            height: sheet_height as f32,
+            drawing: Rect::new(x as f32, y as f32, (x + drawn_width) as f32, (y + drawn_height) as f32),
+            clip: Some(Rect::new(left as f32, bottom as f32, (left + room.0) as f32, (bottom + room.1) as f32)),
+        };
+        (placement, overflows)
+    }
+
+    fn pdf_options(&self) -> PdfExportOptions {
+        PdfExportOptions { node_ids: self.node_ids.clone(), title: self.title.clone(), ..Default::default() }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let (short, long) = self.paper.millimetres();
+        if !(short > 0.0 && long.is_finite()) {
+            return Err("The paper size must be positive".to_string());
+        }
+        let Margins { top, right, bottom, left } = self.margins;
+        if [top, right, bottom, left].iter().any(|margin| !margin.is_finite() || *margin < 0.0) {
+            return Err("Margins can't be negative".to_string());
+        }
+        if left + right >= short || top + bottom >= short {
+            return Err("The margins leave no room to print on".to_string());
+        }
+        if let Scaling::Percent { percent } = self.scaling {
+            if !(percent > 0.0 && percent.is_finite()) {
+                return Err("The print scale must be a positive percentage".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Lay frames out on sheets of paper: each is scaled as asked and centered
+/// within the margins, and anything beyond the margins is cut off.
+pub fn render_print_pdf(tree: &DocumentTree, options: &PrintOptions) -> Result<RenderedPdf, String> {
+    options.validate()?;
+    let overflows = Cell::new(false);
+    let mut pdf = render_pdf_placed(tree, &options.pdf_options(), |width, height| {
+        let (placement, overflow) = options.place(width as f64, height as f64);
+        if overflow {
+            overflows.set(true);
+        }
+        placement
+    })?;
+    if overflows.get() {
+        pdf.warnings.push("Some frames are larger than the space within the margins and were cut off".to_string());
+    }
+    Ok(pdf)
+}
+
+/// The sheets `print_frames` would print, as a base64-encoded PDF, for a
+/// print preview or to save.
+#[command(async)]
+pub fn render_print_preview(node_json: String, options: Option<PrintOptions>) -> Result<PdfExport, String> {
+    let options = options.unwrap_or_default();
+    let tree = DocumentTree::parse(&node_json)?;
+    let pdf = render_print_pdf(&tree, &options)?;
+    Ok(PdfExport { data: BASE64.encode(pdf.bytes), page_count: pdf.page_count, warnings: pdf.warnings })
+}
+
+/// Lay frames out on paper and show the system's print dialog for them, in
+/// front of the window asking.
+#[command]
+pub async fn print_frames(window: WebviewWindow, node_json: String, options: Option<PrintOptions>) -> Result<PrintResult, String> {
+    let options = options.unwrap_or_default();
+    let tree = DocumentTree::parse(&node_json)?;
+    let pdf = render_print_pdf(&tree, &options)?;
+    let (sheet_count, warnings) = (pdf.page_count, pdf.warnings.clone());
+
+    let printed = show_print_dialog(&window, pdf, tree, options).await?;
+    Ok(PrintResult { printed, sheet_count, warnings })
+}
+
+/// Write the sheets to the cache for the print dialog to read. They stay
+/// there until the next print.
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn write_print_file(window: &WebviewWindow, title: &str, pdf: &RenderedPdf) -> Result<std::path::PathBuf, String> {
+    use tauri::Manager;
+
+    let dir = window
+        .path()
+        .app_cache_dir()
+        .map_err(|e| format!("Failed to find cache directory: {}", e))?
+        .join("print");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create print directory: {}", e))?;
+    let name: String = title.chars().map(|c| if c.is_alphanumeric() || " -_".contains(c) { c } else { '_' }).collect();
+    let path = dir.join(format!("{}.pdf", name.trim()));
+    fs::write(&path, &pdf.bytes).map_err(|e| format!("Failed to write print file: {}", e))?;
+    Ok(path)
+}
+
+/// PDFKit's print operation, run on the main thread as AppKit needs. Sheets
+/// of another orientation than the chosen paper are turned to fit it.
+#[cfg(target_os = "macos")]
+async fn show_print_dialog(window: &WebviewWindow, pdf: RenderedPdf, _tree: DocumentTree, options: PrintOptions) -> Result<bool, String> {
+    let title = options.title();
+    let path = write_print_file(window, &title, &pdf)?;
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+    let (width, height) = options.paper.millimetres();
+    window.run_on_main_thread(move || {
+        let _ = sender.send(macos::run_print_operation(&path, &title, (width * MM, height * MM)));
+    })
+    .map_err(|e| format!("Failed to show print dialog: {}", e))?;
+    receiver.await.map_err(|_| "The print dialog closed unexpectedly".to_string())?
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use objc2::rc::{Allocated, Retained};
+    use objc2::runtime::{AnyClass, AnyObject};
+    use objc2::msg_send;
+    use objc2_app_kit::{NSPrintInfo, NSPrintOperation};
+    use objc2_foundation::{NSCopying, NSSize, NSString, NSURL};
+    use std::path::Path;
+
+    #[link(name = "PDFKit", kind = "framework")]
+    extern "C" {}
+
+    /// `kPDFPrintPageScaleNone`: the sheets are already the paper's size.
+    const NO_SCALING: isize = 0;
+
+    pub fn run_print_operation(path: &Path, title: &str, paper: (f64, f64)) -> Result<bool, String> {
+        let failed = || "Failed to prepare the document for printing".to_string();
+        let url = NSURL::fileURLWithPath(&NSString::from_str(&path.to_string_lossy()));
+        let class = AnyClass::get(c"PDFDocument").ok_or_else(failed)?;
+        let document: Option<Retained<AnyObject>> = unsafe {
+            let allocated: Allocated<AnyObject> = msg_send![class, alloc];
+            msg_send![allocated, initWithURL: &*url]
+        };
+        let document = document.ok_or_else(failed)?;
+
+        let info = NSPrintInfo::sharedPrintInfo().copy();
+        info.setPaperSize(NSSize::new(paper.0, paper.1));
+        info.setTopMargin(0.0);
+        info.setRightMargin(0.0);
+        info.setBottomMargin(0.0);
+        info.setLeftMargin(0.0);
+        let operation: Option<Retained<NSPrintOperation>> = unsafe {
+            msg_send![&*document, printOperationForPrintInfo: &*info, scalingMode: NO_SCALING, autoRotate: true]
+        };
+        let operation = operation.ok_or_else(failed)?;
+        operation.setJobTitle(Some(&NSString::from_str(title)));
+        operation.setShowsPrintPanel(true);
+        operation.setShowsProgressPanel(true);
+        Ok(operation.runOperation())
+    }
+}
+
+/// The desktop portal's print dialog. The paper and orientation chosen here
+/// are suggested to it, and the PDF is handed over as an open file.
+#[cfg(target_os = "linux")]
+async fn show_print_dialog(window: &WebviewWindow, pdf: RenderedPdf, _tree: DocumentTree, options: PrintOptions) -> Result<bool, String> {
+    use ashpd::desktop::print::{Orientation as PortalOrientation, PageSetup, PrintProxy, Settings};
+    use ashpd::desktop::ResponseError;
+    use std::os::fd::AsFd;
+
+    let title = &options.title();
+    let path = write_print_file(window, title, &pdf)?;
+    let failed = |e: ashpd::Error| format!("Failed to print: {}", e);
+    let (width, height) = options.paper.millimetres();
+    let orientation = match options.orientation {
+        Orientation::Landscape => PortalOrientation::Landscape,
+        Orientation::Auto | Orientation::Portrait => PortalOrientation::Portrait,
+    };
+    let settings = Settings::default().paper_format(options.paper.pwg_name()).orientation(orientation);
+    let page_setup = PageSetup::default()
+        .width(width)
+        .height(height)
+        .orientation(orientation)
+        .margin_top(0.0)
+        .margin_right(0.0)
+        .margin_bottom(0.0)
+        .margin_left(0.0);
+
+    let proxy = PrintProxy::new().await.map_err(failed)?;
+    let prepared = proxy.prepare_print(None, title, settings, page_setup, None, true).await.and_then(|request| request.response());
+    let prepared = match prepared {
+        Ok(prepared) => prepared,
+        Err(ashpd::Error::Response(ResponseError::Cancelled)) => return Ok(false),
+        Err(e) => return Err(failed(e)),
+    };
+    let file = fs::File::open(&path).map_err(|e| format!("Failed to open print file: {}", e))?;
+    proxy
+        .print(None, title, &file.as_fd(), Some(prepared.token), true)
+        .await
+        .and_then(|request| request.response())
+        .map_err(failed)?;
+    Ok(true)
+}
+
+/// The common print dialog, then each sheet drawn onto the chosen printer.
+/// The dialog is modal and blocks until it's closed, so it runs on a thread
+/// of its own.
+#[cfg(windows)]
+async fn show_print_dialog(window: &WebviewWindow, _pdf: RenderedPdf, tree: DocumentTree, options: PrintOptions) -> Result<bool, String> {
+    let owner = window.hwnd().map_err(|e| format!("Failed to show print dialog: {}", e))?.0 as isize;
+    tauri::async_runtime::spawn_blocking(move || windows::print(owner, &tree, &options))
+        .await
+        .map_err(|e| format!("Failed to print: {}", e))?
+}
+
+#[cfg(windows)]
+mod windows {
+    use super::PrintOptions;
+    use crate::export::pdf::{page_ids, PagePlacement};
+    use crate::export::raster::render_pixmap;
+    use crate::export::resolve_scope;
+    use crate::model::DocumentTree;
+    use std::iter::repeat_n;
+    use std::mem::{size_of, zeroed};
+    use std::ptr::{null, null_mut};
+    use tiny_skia::Color;
+    use windows_sys::Win32::Foundation::{GlobalFree, HGLOBAL, HWND};
+    use windows_sys::Win32::Graphics::Gdi::{
+        DeleteDC, GetDeviceCaps, IntersectClipRect, ResetDCW, RestoreDC, SaveDC, SetBrushOrgEx, SetStretchBltMode,
+        StretchDIBits, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, DEVMODEW, DIB_RGB_COLORS, DMORIENT_LANDSCAPE,
+        DMORIENT_PORTRAIT, DM_ORIENTATION, DM_PAPERLENGTH, DM_PAPERWIDTH, GET_DEVICE_CAPS_INDEX, HALFTONE, HDC,
+        LOGPIXELSX, LOGPIXELSY, PHYSICALHEIGHT, PHYSICALOFFSETX, PHYSICALOFFSETY, PHYSICALWIDTH, SRCCOPY,
+    };
+    use windows_sys::Win32::Storage::Xps::{AbortDoc, EndDoc, EndPage, StartDocW, StartPage, DOCINFOW};
+    use windows_sys::Win32::System::Com::{CoInitializeEx, CoUninitialize, COINIT_APARTMENTTHREADED};
+    use windows_sys::Win32::System::Memory::{GlobalAlloc, GlobalLock, GlobalUnlock, GHND};
+    use windows_sys::Win32::UI::Controls::Dialogs::{
+        PrintDlgExW, PD_COLLATE, PD_NOCURRENTPAGE, PD_NOPAGENUMS, PD_NOSELECTION, PD_RESULT_PRINT, PD_RETURNDC,
+        PD_USEDEVMODECOPIESANDCOLLATE, PRINTDLGEXW, START_PAGE_GENERAL,
+    };
+
+    /// Frames are rasterized at up to this many dots per inch, as sharp as
+    /// print shows while keeping a sheet to tens of megabytes.
+    const MAX_DPI: f64 = 300.0;
+
+    fn failed() -> String {
+        "Failed to print".to_string()
+    }
+
+    /// Change the printer settings in `dev_mode`, if there are any.
+    fn edit_dev_mode(dev_mode: HGLOBAL, edit: impl FnOnce(&mut DEVMODEW)) {
+        if let Some(mode) = unsafe { GlobalLock(dev_mode).cast::<DEVMODEW>().as_mut() } {
+            edit(mode);
+            unsafe { GlobalUnlock(dev_mode) };
+        }
+    }
+
+    pub fn print(owner: isize, tree: &DocumentTree, options: &PrintOptions) -> Result<bool, String> {
+        // The dialog is built from COM objects, which need an apartment
+        let com = unsafe { CoInitializeEx(null(), COINIT_APARTMENTTHREADED as u32) } >= 0;
+        let printed = print_with_dialog(owner as HWND, tree, options);
+        if com {
+            unsafe { CoUninitialize() };
+        }
+        printed
+    }
+
+    fn print_with_dialog(owner: HWND, tree: &DocumentTree, options: &PrintOptions) -> Result<bool, String> {
+        // The paper chosen here is suggested to the dialog, in tenths of a
+        // millimetre
+        let (width, length) = options.paper.millimetres();
+        let dev_mode = unsafe { GlobalAlloc(GHND, size_of::<DEVMODEW>()) };
+        edit_dev_mode(dev_mode, |mode| {
+            mode.dmSize = size_of::<DEVMODEW>() as u16;
+            mode.dmFields = DM_PAPERWIDTH | DM_PAPERLENGTH;
+            mode.Anonymous1.Anonymous1.dmPaperWidth = (width * 10.0).round() as i16;
+            mode.Anonymous1.Anonymous1.dmPaperLength = (length * 10.0).round() as i16;
+        });
+
+        let mut dialog: PRINTDLGEXW = unsafe { zeroed() };
+        dialog.lStructSize = size_of::<PRINTDLGEXW>() as u32;
+        dialog.hwndOwner = owner;
+        dialog.hDevMode = dev_mode;
+        dialog.Flags = PD_RETURNDC | PD_NOPAGENUMS | PD_NOSELECTION | PD_NOCURRENTPAGE | PD_USEDEVMODECOPIESANDCOLLATE;
+        dialog.nCopies = 1;
+        dialog.nStartPage = START_PAGE_GENERAL;
+        let shown = unsafe { PrintDlgExW(&mut dialog) };
+
+        let printed = if shown < 0 {
+            Err(format!("Failed to show print dialog (error {:#010x})", shown))
+        } else if dialog.dwResultAction != PD_RESULT_PRINT {
+            Ok(false)
+        } else {
+            print_sheets(&dialog, tree, options).map(|()| true)
+        };
+        // The dialog may have swapped the settings for its own
+        unsafe {
+            if !dialog.hDC.is_null() {
+                DeleteDC(dialog.hDC);
+            }
+            for memory in [dialog.hDevMode, dialog.hDevNames] {
+                if !memory.is_null() {
+                    GlobalFree(memory);
+                }
+            }
+        }
+        printed
+    }
+
+    fn print_sheets(dialog: &PRINTDLGEXW, tree: &DocumentTree, options: &PrintOptions) -> Result<(), String> {
+        let title: Vec<u16> = options.title().encode_utf16().chain([0]).collect();
+        let job = DOCINFOW {
+            cbSize: size_of::<DOCINFOW>() as i32,
+            lpszDocName: title.as_ptr(),
+            lpszOutput: null(),
+            lpszDatatype: null(),
+            fwType: 0,
+        };
+        if unsafe { StartDocW(dialog.hDC, &job) } <= 0 {
+            return Err(failed());
+        }
+
+        // Copies the printer can't make itself are left to us
+        let ids = page_ids(tree, &options.pdf_options());
+        let copies = dialog.nCopies.max(1) as usize;
+        let sheets: Vec<&String> = if dialog.Flags & PD_COLLATE != 0 {
+            repeat_n(&ids, copies).flatten().collect()
+        } else {
+            ids.iter().flat_map(|id| repeat_n(id, copies)).collect()
+        };
+        let printed = sheets.into_iter().try_for_each(|id| print_sheet(dialog, tree, options, id));
+
+        match printed {
+            Ok(()) if unsafe { EndDoc(dialog.hDC) } > 0 => Ok(()),
+            Ok(()) => Err(failed()),
+            Err(e) => {
+                unsafe { AbortDoc(dialog.hDC) };
+                Err(e)
+            }
+        }
+    }
+
+    fn print_sheet(dialog: &PRINTDLGEXW, tree: &DocumentTree, options: &PrintOptions, id: &str) -> Result<(), String> {
+        let bounds = resolve_scope(tree, Some(vec![id.to_string()]), 0.0)?.bounds;
+        let (placement, _) = options.place(bounds.width, bounds.height);
+
+        // Each sheet is turned its own way before its page starts
+        edit_dev_mode(dialog.hDevMode, |mode| {
+            let landscape = placement.width > placement.height;
+            mode.dmFields |= DM_ORIENTATION;
+            mode.Anonymous1.Anonymous1.dmOrientation = if landscape { DMORIENT_LANDSCAPE } else { DMORIENT_PORTRAIT } as i16;
+            unsafe { ResetDCW(dialog.hDC, mode) };
+        });
+        if unsafe { StartPage(dialog.hDC) } <= 0 {
+            return Err(failed());
+        }
+        let drawn = draw_sheet(dialog.hDC, tree, id, bounds.width, &placement);
+        if unsafe { EndPage(dialog.hDC) } <= 0 {
+            return drawn.and(Err(failed()));
+        }
+        drawn
+    }
+
+    /// Rasterize the frame and copy it into its place on the page.
+    fn draw_sheet(hdc: HDC, tree: &DocumentTree, id: &str, frame_width: f64, placement: &PagePlacement) -> Result<(), String> {
+        let caps = |index: GET_DEVICE_CAPS_INDEX| unsafe { GetDeviceCaps(hdc, index as i32) } as f64;
+        let (dpi_x, dpi_y) = (caps(LOGPIXELSX), caps(LOGPIXELSY));
+        let (offset_x, offset_y) = (caps(PHYSICALOFFSETX), caps(PHYSICALOFFSETY));
+        let (sheet_width, sheet_height) = (placement.width as f64, placement.height as f64);
+        // Smaller paper chosen in the dialog gets the sheet shrunk onto it
+        let fit = (caps(PHYSICALWIDTH) / (sheet_width / 72.0 * dpi_x))
+            .min(caps(PHYSICALHEIGHT) / (sheet_height / 72.0 * dpi_y))
+            .min(1.0);
+        // Points up from the sheet's bottom left to pixels down from the
+        // printable area's top left
+        let device = |x: f32, y: f32| {
+            (
+                (x as f64 / 72.0 * dpi_x * fit - offset_x).round() as i32,
+                ((sheet_height - y as f64) / 72.0 * dpi_y * fit - offset_y).round() as i32,
+            )
+        };
+
+        let drawing = placement.drawing;
+        let (left, top) = device(drawing.x1, drawing.y2);
+        let (right, bottom) = device(drawing.x2, drawing.y1);
+        let scale = (drawing.x2 - drawing.x1) as f64 / frame_width.max(1.0) * (dpi_x * fit).min(MAX_DPI) / 72.0;
+        let (pixmap, _) = render_pixmap(tree, scale, Some(vec![id.to_string()]), 0.0, Some(Color::WHITE))?;
+        // Over white every pixel is opaque, so premultiplied RGBA only needs
+        // its channels swapped for a DIB
+        let bits: Vec<u8> = pixmap.data().chunks_exact(4).flat_map(|p| [p[2], p[1], p[0], 255]).collect();
+        let mut info: BITMAPINFO = unsafe { zeroed() };
+        info.bmiHeader.biSize = size_of::<BITMAPINFOHEADER>() as u32;
+        info.bmiHeader.biWidth = pixmap.width() as i32;
+        // Negative for rows from the top down
+        info.bmiHeader.biHeight = -(pixmap.height() as i32);
+        info.bmiHeader.biPlanes = 1;
+        info.bmiHeader.biBitCount = 32;
+        info.bmiHeader.biCompression = BI_RGB;
+
+        let copied = unsafe {
+            let saved = SaveDC(hdc);
+            if let Some(clip) = placement.clip {
+                let (clip_left, clip_top) = device(clip.x1, clip.y2);
+                let (clip_right, clip_bottom) = device(clip.x2, clip.y1);
+                IntersectClipRect(hdc, clip_left, clip_top, clip_right, clip_bottom);
+            }
+            SetStretchBltMode(hdc, HALFTONE);
+            SetBrushOrgEx(hdc, 0, 0, null_mut());
+            let copied = StretchDIBits(
+                hdc,
+                left,
+                top,
+                right - left,
+                bottom - top,
+                0,
+                0,
+                pixmap.width() as i32,
+                pixmap.height() as i32,
+                bits.as_ptr().cast(),
+                &info,
+                DIB_RGB_COLORS,
+                SRCCOPY,
+            );
+            RestoreDC(hdc, saved);
+            copied
+        };
+        if copied == 0 {
+            return Err(failed());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux", windows)))]
+async fn show_print_dialog(_window: &WebviewWindow, _pdf: RenderedPdf, _tree: DocumentTree, _options: PrintOptions) -> Result<bool, String> {
+    Err("Printing is not supported on this platform".to_string())
+}
@@ -0,0 +1,434 @@
+//! Search across every design file in a project folder. The folder is
+//! crawled once into an inverted index of the words in text layers and
+//! component names and the colors used in paints; after that the folder is
+//! watched, and files that change are indexed again on their own.
+
+use crate::bundle::{is_bundle, load_bundle_document};
+use crate::commands::read_contents;
+use crate::model::{DocumentTree, NodeData, NodeType, Paint, Rgba};
+use crate::watcher::{file_mtime, normalize};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+use tauri::{command, AppHandle, Emitter, State};
+
+pub const PROJECT_INDEX_CHANGED_EVENT: &str = "project-index-changed";
+
+const DESIGN_EXTENSIONS: &[&str] = &["designlibre"];
+
+/// How long to wait for a burst of changes to settle before reindexing, as
+/// saves write a file in several steps.
+const SETTLE_TIME: Duration = Duration::from_millis(500);
+
+/// Longest text returned with a hit; the rest is cut.
+const MAX_EXCERPT_CHARS: usize = 200;
+
+const DEFAULT_LIMIT: usize = 200;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum HitKind {
+    Text,
+    Component,
+    Color,
+}
+
+/// Something in a file that can be found.
+struct Item {
+    node_id: String,
+    node_name: String,
+    kind: HitKind,
+    /// The text, component name or `#rrggbb` color.
+    value: String,
+}
+
+impl Item {
+    fn terms(&self) -> Vec<String> {
+        match self.kind {
+            HitKind::Color => vec![self.value.clone()],
+            _ => words(&self.value),
+        }
+    }
+}
+
+struct IndexedFile {
+    path: PathBuf,
+    mtime: Option<u64>,
+    items: Vec<Item>,
+}
+
+#[derive(Default)]
+struct Index {
+    root: Option<PathBuf>,
+    /// File slots; a removed file leaves a hole for the next one.
+    files: Vec<Option<IndexedFile>>,
+    slots: HashMap<PathBuf, usize>,
+    /// Term -> the (file, item) pairs it occurs in. Sorted, so words can be
+    /// looked up by prefix.
+    terms: BTreeMap<String, HashSet<(usize, usize)>>,
+}
+
+/// Lowercased runs of letters and digits.
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// `#rgb` or `#rrggbb` as `#rrggbb`.
+fn parse_color(text: &str) -> Option<String> {
+    let hex = text.strip_prefix('#')?;
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    match hex.len() {
+        3 => Some(format!("#{}", hex.chars().flat_map(|c| [c, c]).collect::<String>().to_lowercase())),
+        6 => Some(format!("#{}", hex.to_lowercase())),
+        _ => None,
+    }
+}
+
+fn paint_colors(paint: &Paint) -> Vec<Rgba> {
+    match paint {
+        Paint::Solid { color, .. } => vec![*color],
+        Paint::GradientLinear { gradient_stops, .. } | Paint::GradientRadial { gradient_stops, .. } => {
+            gradient_stops.iter().map(|stop| stop.color).collect()
+        }
+        Paint::Image { .. } | Paint::Pattern { .. } => Vec::new(),
+    }
+}
+
+fn node_items(node: &NodeData) -> Vec<Item> {
+    let item = |kind, value: String| Item { node_id: node.id.clone(), node_name: node.name.clone(), kind, value };
+    let mut items = Vec::new();
+    if let Some(text) = node.characters.as_ref().filter(|t| !t.trim().is_empty()) {
+        items.push(item(HitKind::Text, text.clone()));
+    }
+    if node.node_type == NodeType::Component && !node.name.is_empty() {
+        items.push(item(HitKind::Component, node.name.clone()));
+    }
+
+    let text_fills = node.text_styles.iter().flatten().flat_map(|style| &style.fills);
+    let paints = node.fills.iter().chain(&node.strokes).flatten().chain(text_fills);
+    let colors: BTreeSet<String> = paints.flat_map(paint_colors).map(Rgba::hex).collect();
+    items.extend(colors.into_iter().map(|hex| item(HitKind::Color, hex)));
+    items
+}
+
+/// What can be found in the design file at `path`, or `None` if it can't be
+/// read, as with encrypted files.
+fn read_items(path: &Path) -> Option<Vec<Item>> {
+    let display = path.to_string_lossy();
+    let contents = read_contents(&display, None).ok()?;
+    let json = if is_bundle(&contents.bytes) {
+        load_bundle_document(&display).ok()?
+    } else {
+        String::from_utf8(contents.bytes).ok()?
+    };
+    let tree = DocumentTree::parse(&json).ok()?;
+    Some(tree.nodes().flat_map(node_items).collect())
+}
+
+fn is_design_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|ext| DESIGN_EXTENSIONS.iter().any(|d| ext.eq_ignore_ascii_case(d)))
+}
+
+/// Whether `path` is in a hidden folder under `root` or is hidden itself,
+/// which the crawl skips.
+fn is_hidden(root: &Path, path: &Path) -> bool {
+    path.strip_prefix(root)
+        .map_or(true, |relative| relative.components().any(|c| c.as_os_str().to_string_lossy().starts_with('.')))
+}
+
+/// Design files under `dir`, skipping hidden files and folders.
+fn crawl(dir: &Path, found: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        match entry.file_type() {
+            Ok(kind) if kind.is_dir() => crawl(&path, found),
+            Ok(kind) if kind.is_file() && is_design_file(&path) => found.push(path),
+            _ => {}
+        }
+    }
+}
+
+impl Index {
+    fn remove(&mut self, path: &Path) {
+        let Some(slot) = self.slots.remove(path) else { return };
+        let Some(file) = self.files[slot].take() else { return };
+        for term in file.items.iter().flat_map(Item::terms) {
+            if let Some(postings) = self.terms.get_mut(&term) {
+                postings.retain(|&(f, _)| f != slot);
+                if postings.is_empty() {
+                    self.terms.remove(&term);
+                }
+            }
+        }
+    }
+
+    fn insert(&mut self, path: PathBuf, mtime: Option<u64>, items: Vec<Item>) {
+        self.remove(&path);
+        let slot = match self.files.iter().position(Option::is_none) {
+            Some(free) => free,
+            None => {
+                self.files.push(None);
+                self.files.len() - 1
+            }
+        };
+        for (i, item) in items.iter().enumerate() {
+            for term in item.terms() {
+                self.terms.entry(term).or_default().insert((slot, i));
+            }
+        }
+        self.slots.insert(path.clone(), slot);
+        self.files[slot] = Some(IndexedFile { path, mtime, items });
+    }
+
+    /// Whether `path`, last modified at `mtime`, is in the project and not
+    /// hidden, and differs from what was indexed.
+    fn is_stale(&self, path: &Path, mtime: Option<u64>) -> bool {
+        let in_project = self.root.as_ref().is_some_and(|root| path.starts_with(root) && !is_hidden(root, path));
+        let known = self.slots.get(path).and_then(|&slot| self.files[slot].as_ref()).map(|file| file.mtime);
+        in_project && (known != Some(mtime) || mtime.is_none())
+    }
+
+    /// Index `path` again with `items`, or drop it if it couldn't be read.
+    /// Returns whether anything changed.
+    fn update(&mut self, path: &Path, mtime: Option<u64>, items: Option<Vec<Item>>) -> bool {
+        match items {
+            Some(items) => self.insert(path.to_path_buf(), mtime, items),
+            None if self.slots.contains_key(path) => self.remove(path),
+            None => return false,
+        }
+        true
+    }
+
+    /// Indexed files at or under `path`.
+    fn indexed_under(&self, path: &Path) -> Vec<PathBuf> {
+        self.slots.keys().filter(|indexed| indexed.starts_with(path)).cloned().collect()
+    }
+
+    /// (file, item) pairs with a term starting with `prefix`.
+    fn with_prefix(&self, prefix: &str) -> HashSet<(usize, usize)> {
+        self.terms
+            .range(prefix.to_string()..)
+            .take_while(|(term, _)| term.starts_with(prefix))
+            .flat_map(|(_, postings)| postings.iter().copied())
+            .collect()
+    }
+}
+
+/// The index of the open project's design files, managed as app state.
+#[derive(Default)]
+pub struct ProjectIndex {
+    index: Arc<Mutex<Index>>,
+    watcher: Mutex<Option<RecommendedWatcher>>,
+}
+
+fn lock(index: &Mutex<Index>) -> MutexGuard<'_, Index> {
+    index.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectIndexChangedEvent {
+    /// Files indexed again or dropped from the index.
+    pub paths: Vec<String>,
+}
+
+/// The files to look at again for a change to `path`. Renaming a folder
+/// only reports the folder, so the files under it are crawled where it went
+/// and dropped from where it was.
+fn affected(index: &Mutex<Index>, path: PathBuf) -> Vec<PathBuf> {
+    if path.is_dir() {
+        let mut found = Vec::new();
+        if lock(index).root.as_ref().is_some_and(|root| !is_hidden(root, &path)) {
+            crawl(&path, &mut found);
+        }
+        found
+    } else if path.exists() {
+        vec![path]
+    } else {
+        let mut gone = lock(index).indexed_under(&path);
+        if !gone.contains(&path) {
+            gone.push(path);
+        }
+        gone
+    }
+}
+
+/// Collect changed paths until a burst of events settles, then index them
+/// again and report which changed.
+fn reindex_changes(app: AppHandle, index: Arc<Mutex<Index>>, events: Receiver<Vec<PathBuf>>) {
+    while let Ok(first) = events.recv() {
+        let mut paths: BTreeSet<PathBuf> = first.into_iter().collect();
+        loop {
+            match events.recv_timeout(SETTLE_TIME) {
+                Ok(more) => paths.extend(more),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+
+        let paths: BTreeSet<PathBuf> = paths
+            .iter()
+            .flat_map(|path| affected(&index, normalize(path)))
+            .collect();
+        let changed: Vec<String> = paths
+            .into_iter()
+            .filter(|path| is_design_file(path))
+            .filter(|path| {
+                // Files are read without holding the index, so searches
+                // carry on meanwhile
+                let mtime = file_mtime(path);
+                lock(&index).is_stale(path, mtime) && {
+                    let items = read_items(path);
+                    lock(&index).update(path, mtime, items)
+                }
+            })
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+        if !changed.is_empty() {
+            let _ = app.emit(PROJECT_INDEX_CHANGED_EVENT, ProjectIndexChangedEvent { paths: changed });
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectIndexInfo {
+    pub root: String,
+    pub files: usize,
+    /// Files that couldn't be read, such as encrypted ones.
+    pub skipped: Vec<String>,
+}
+
+/// Index every design file under `root` and keep the index up to date as
+/// they change, replacing any project indexed before.
+#[command(async)]
+pub fn index_project(app: AppHandle, project: State<'_, ProjectIndex>, root: String) -> Result<ProjectIndexInfo, String> {
+    let dir = normalize(Path::new(&root));
+    if !dir.is_dir() {
+        return Err(format!("Not a folder: {}", root));
+    }
+    // Held until the new index is in place, so projects indexed at the same
+    // time don't end up with one's watcher and the other's index
+    let mut current = project.watcher.lock().unwrap_or_else(|e| e.into_inner());
+    // Changes that land during the crawl are picked up by the watcher
+    let (sender, events) = channel();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        if let Ok(event) = res {
+            if !matches!(event.kind, EventKind::Access(_)) {
+                let _ = sender.send(event.paths);
+            }
+        }
+    })
+    .map_err(|e| format!("Failed to create project watcher: {}", e))?;
+    watcher
+        .watch(&dir, RecursiveMode::Recursive)
+        .map_err(|e| format!("Failed to watch {}: {}", dir.display(), e))?;
+
+    let mut paths = Vec::new();
+    crawl(&dir, &mut paths);
+    let mut index = Index { root: Some(dir.clone()), ..Default::default() };
+    let mut skipped = Vec::new();
+    for path in paths {
+        match read_items(&path) {
+            Some(items) => index.insert(path.clone(), file_mtime(&path), items),
+            None => skipped.push(path.to_string_lossy().into_owned()),
+        }
+    }
+    let files = index.slots.len();
+    *lock(&project.index) = index;
+
+    let shared = Arc::clone(&project.index);
+    thread::spawn(move || reindex_changes(app, shared, events));
+    // Replacing the old watcher ends its reindexing thread
+    *current = Some(watcher);
+
+    Ok(ProjectIndexInfo { root: dir.to_string_lossy().into_owned(), files, skipped })
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ProjectSearchOptions {
+    /// What to look for; everything when unset.
+    pub kinds: Option<Vec<HitKind>>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectHit {
+    pub path: String,
+    pub node_id: String,
+    pub node_name: String,
+    pub kind: HitKind,
+    /// The text, cut to a couple of hundred characters, the component name or
+    /// the `#rrggbb` color.
+    pub value: String,
+}
+
+/// Find `query` in the indexed project. A color such as `#0af` finds paints
+/// of exactly that color; otherwise every word of the query must start a
+/// word of the text or component name.
+#[command]
+pub fn search_project(project: State<'_, ProjectIndex>, query: String, options: Option<ProjectSearchOptions>) -> Result<Vec<ProjectHit>, String> {
+    let options = options.unwrap_or_default();
+    let index = lock(&project.index);
+    if index.root.is_none() {
+        return Err("No project is indexed".to_string());
+    }
+
+    let found: HashSet<(usize, usize)> = match parse_color(query.trim()) {
+        Some(color) => index.terms.get(&color).cloned().unwrap_or_default(),
+        None => {
+            let terms = words(&query);
+            let mut sets = terms.iter().map(|term| index.with_prefix(term));
+            let first = sets.next().ok_or("Search query is empty")?;
+            sets.fold(first, |found, set| found.intersection(&set).copied().collect())
+        }
+    };
+
+    let mut hits: Vec<ProjectHit> = found
+        .into_iter()
+        .filter_map(|(slot, i)| {
+            let file = index.files[slot].as_ref()?;
+            let item = &file.items[i];
+            let kinds = options.kinds.as_deref();
+            if kinds.is_some_and(|kinds| !kinds.contains(&item.kind)) {
+                return None;
+            }
+            Some(ProjectHit {
+                path: file.path.to_string_lossy().into_owned(),
+                node_id: item.node_id.clone(),
+                node_name: item.node_name.clone(),
+                kind: item.kind,
+                value: item.value.chars().take(MAX_EXCERPT_CHARS).collect(),
+            })
+        })
+        .collect();
+    hits.sort_by(|a, b| (&a.path, a.kind, &a.node_name, &a.node_id).cmp(&(&b.path, b.kind, &b.node_name, &b.node_id)));
+    hits.truncate(options.limit.unwrap_or(DEFAULT_LIMIT));
+    Ok(hits)
+}
+
+/// Stop watching the project and drop its index.
+#[command]
+pub fn close_project_index(project: State<'_, ProjectIndex>) {
+    project.watcher.lock().unwrap_or_else(|e| e.into_inner()).take();
+    *lock(&project.index) = Index::default();
+}
@@ -0,0 +1,168 @@
+use crate::atomic::write_atomic;
+use crate::bundle::now_millis;
+use crate::menu::refresh_recent;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use tauri::{command, AppHandle, State};
+
+/// Unpinned entries beyond this are dropped, oldest first.
+const MAX_RECENT: usize = 20;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentFile {
+    pub path: String,
+    pub name: String,
+    pub last_opened: u64,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub thumbnail_path: Option<String>,
+    /// Filled in when listing; not persisted.
+    #[serde(skip_deserializing, default)]
+    pub exists: bool,
+}
+
+/// Recent-files list persisted as JSON in the app data directory.
+pub struct RecentFiles {
+    store: PathBuf,
+    entries: Mutex<Vec<RecentFile>>,
+}
+
+impl RecentFiles {
+    pub fn load(store: PathBuf) -> Self {
+        let entries = std::fs::read(&store)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+
+        RecentFiles { store, entries: Mutex::new(entries) }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<RecentFile>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn persist(&self, entries: &[RecentFile]) -> Result<(), String> {
+        if let Some(dir) = self.store.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create app data directory: {}", e))?;
+        }
+        let json = serde_json::to_vec_pretty(entries)
+            .map_err(|e| format!("Failed to encode recent files: {}", e))?;
+        write_atomic(&self.store.to_string_lossy(), &json)?;
+        Ok(())
+    }
+
+    /// Apply `f` to the list, then re-sort, trim and save it.
+    fn update<F: FnOnce(&mut Vec<RecentFile>)>(&self, f: F) -> Result<Vec<RecentFile>, String> {
+        let mut entries = self.lock();
+        f(&mut entries);
+
+        entries.sort_by(|a, b| b.pinned.cmp(&a.pinned).then(b.last_opened.cmp(&a.last_opened)));
+        let mut unpinned = 0;
+        entries.retain(|e| {
+            if e.pinned {
+                return true;
+            }
+            unpinned += 1;
+            unpinned <= MAX_RECENT
+        });
+
+        self.persist(&entries)?;
+        Ok(entries.clone())
+    }
+
+    pub fn list(&self) -> Vec<RecentFile> {
+        self.lock()
+            .iter()
+            .cloned()
+            .map(|mut e| {
+                e.exists = Path::new(&e.path).exists();
+                e
+            })
+            .collect()
+    }
+
+    pub fn add(&self, path: String, thumbnail_path: Option<String>) -> Result<Vec<RecentFile>, String> {
+        let name = Path::new(&path)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.clone());
+
+        self.update(|entries| {
+            let now = now_millis();
+            if let Some(entry) = entries.iter_mut().find(|e| e.path == path) {
+                entry.last_opened = now;
+                if thumbnail_path.is_some() {
+                    entry.thumbnail_path = thumbnail_path;
+                }
+            } else {
+                entries.push(RecentFile {
+                    path,
+                    name,
+                    last_opened: now,
+                    pinned: false,
+                    thumbnail_path,
+                    exists: true,
+                });
+            }
+        })
+    }
+
+    /// Empty the list, keeping pinned entries unless `include_pinned`.
+    pub fn clear(&self, include_pinned: bool) -> Result<(), String> {
+        self.update(|entries| entries.retain(|e| e.pinned && !include_pinned))?;
+        Ok(())
+    }
+}
+
+#[command]
+pub fn add_recent_file(
+    app: AppHandle,
+    recent: State<'_, RecentFiles>,
+    path: String,
+    thumbnail_path: Option<String>,
+) -> Result<Vec<RecentFile>, String> {
+    recent.add(path, thumbnail_path)?;
+    refresh_recent(&app);
+    Ok(recent.list())
+}
+
+#[command]
+pub fn list_recent_files(recent: State<'_, RecentFiles>) -> Vec<RecentFile> {
+    recent.list()
+}
+
+#[command]
+pub fn pin_recent_file(app: AppHandle, recent: State<'_, RecentFiles>, path: String, pinned: bool) -> Result<Vec<RecentFile>, String> {
+    let mut found = false;
+    recent.update(|entries| {
+        if let Some(entry) = entries.iter_mut().find(|e| e.path == path) {
+            entry.pinned = pinned;
+            found = true;
+        }
+    })?;
+
+    if !found {
+        return Err(format!("Not in recent files: {}", path));
+    }
+    refresh_recent(&app);
+    Ok(recent.list())
+}
+
+#[command]
+pub fn remove_recent_file(app: AppHandle, recent: State<'_, RecentFiles>, path: String) -> Result<Vec<RecentFile>, String> {
+    recent.update(|entries| entries.retain(|e| e.path != path))?;
+    refresh_recent(&app);
+    Ok(recent.list())
+}
+
+/// Clear the list. Pinned entries survive unless `include_pinned` is set.
+#[command]
+pub fn clear_recent_files(app: AppHandle, recent: State<'_, RecentFiles>, include_pinned: Option<bool>) -> Result<(), String> {
+    recent.clear(include_pinned.unwrap_or(false))?;
+    refresh_recent(&app);
+    Ok(())
+}
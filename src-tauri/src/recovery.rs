@@ -0,0 +1,235 @@
+use crate::atomic::write_atomic;
+use crate::autosave::{sanitize_id, AutosaveMeta, META_FILE, SNAPSHOT_FILE};
+use crate::bundle::now_millis;
+use crate::commands::read_contents;
+use crate::error::{FileError, FileErrorKind};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use tauri::{command, State};
+
+const SESSIONS_DIR: &str = "sessions";
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+/// A session whose heartbeat is older than this is treated as dead.
+const SESSION_STALE_MS: u64 = 60_000;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionRecord {
+    pub session_id: String,
+    pub pid: u32,
+    pub started_at: u64,
+    pub heartbeat_at: u64,
+}
+
+/// The running process's entry in the session registry. Autosave snapshots are
+/// stamped with its id so that snapshots left behind by a dead process can be
+/// told apart from those of another live instance.
+pub struct Session {
+    pub id: String,
+    dir: PathBuf,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoveryPreview {
+    pub name: Option<String>,
+    pub node_count: Option<usize>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoverableDocument {
+    pub document_id: String,
+    pub source_path: Option<String>,
+    pub saved_at: u64,
+    pub size: u64,
+    /// Restoring it needs the document's passphrase; there's no preview.
+    pub encrypted: bool,
+    pub preview: RecoveryPreview,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoveredDocument {
+    pub document_id: String,
+    pub source_path: Option<String>,
+    pub content: String,
+}
+
+fn session_path(dir: &Path, session_id: &str) -> PathBuf {
+    dir.join(SESSIONS_DIR).join(format!("{}.json", sanitize_id(session_id)))
+}
+
+fn write_session(dir: &Path, record: &SessionRecord) -> Result<(), String> {
+    let json = serde_json::to_vec(record)
+        .map_err(|e| format!("Failed to encode session: {}", e))?;
+    write_atomic(&session_path(dir, &record.session_id).to_string_lossy(), &json)?;
+    Ok(())
+}
+
+fn read_session(dir: &Path, session_id: &str) -> Option<SessionRecord> {
+    let bytes = fs::read(session_path(dir, session_id)).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+fn is_session_alive(dir: &Path, session_id: &str, now: u64) -> bool {
+    read_session(dir, session_id)
+        .is_some_and(|s| now.saturating_sub(s.heartbeat_at) < SESSION_STALE_MS)
+}
+
+impl Session {
+    /// Register this process in `dir` and keep its heartbeat fresh from a
+    /// background thread for the lifetime of the app.
+    pub fn start(dir: PathBuf) -> Result<Self, String> {
+        fs::create_dir_all(dir.join(SESSIONS_DIR))
+            .map_err(|e| format!("Failed to create session directory: {}", e))?;
+
+        let started_at = now_millis();
+        let pid = std::process::id();
+        let mut record = SessionRecord {
+            session_id: format!("{}-{}", pid, started_at),
+            pid,
+            started_at,
+            heartbeat_at: started_at,
+        };
+        write_session(&dir, &record)?;
+
+        let session = Session { id: record.session_id.clone(), dir: dir.clone() };
+
+        thread::Builder::new()
+            .name("session-heartbeat".into())
+            .spawn(move || loop {
+                thread::sleep(HEARTBEAT_INTERVAL);
+                record.heartbeat_at = now_millis();
+                let _ = write_session(&dir, &record);
+            })
+            .map_err(|e| format!("Failed to spawn heartbeat thread: {}", e))?;
+
+        Ok(session)
+    }
+
+    /// Remove registry entries of sessions that stopped sending heartbeats.
+    fn prune_dead_sessions(&self, now: u64) {
+        let Ok(entries) = fs::read_dir(self.dir.join(SESSIONS_DIR)) else { return };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let alive = fs::read(&path)
+                .ok()
+                .and_then(|b| serde_json::from_slice::<SessionRecord>(&b).ok())
+                .is_some_and(|s| s.session_id == self.id || now.saturating_sub(s.heartbeat_at) < SESSION_STALE_MS);
+            if !alive {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+}
+
+fn read_meta(doc_dir: &Path) -> Option<AutosaveMeta> {
+    let bytes = fs::read(doc_dir.join(META_FILE)).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+fn preview(content: &str) -> RecoveryPreview {
+    let parsed: Option<serde_json::Value> = serde_json::from_str(content).ok();
+    RecoveryPreview {
+        name: parsed
+            .as_ref()
+            .and_then(|v| v.get("name"))
+            .and_then(|n| n.as_str())
+            .map(str::to_string),
+        node_count: parsed
+            .as_ref()
+            .and_then(|v| v.get("nodes"))
+            .and_then(|n| n.as_array())
+            .map(|n| n.len()),
+    }
+}
+
+/// Snapshot directories whose owning session is gone.
+fn orphaned(session: &Session) -> Vec<(PathBuf, AutosaveMeta)> {
+    let now = now_millis();
+    let Ok(entries) = fs::read_dir(&session.dir) else { return Vec::new() };
+
+    entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_dir() && p.file_name().is_some_and(|n| n != SESSIONS_DIR))
+        .filter_map(|p| read_meta(&p).map(|m| (p, m)))
+        .filter(|(_, m)| m.session_id != session.id && !is_session_alive(&session.dir, &m.session_id, now))
+        .collect()
+}
+
+fn find_orphan(session: &Session, document_id: &str) -> Result<(PathBuf, AutosaveMeta), String> {
+    orphaned(session)
+        .into_iter()
+        .find(|(_, m)| m.document_id == document_id)
+        .ok_or_else(|| format!("No recoverable snapshot for document {}", document_id))
+}
+
+#[command]
+pub fn list_recoverable_documents(session: State<'_, Session>) -> Vec<RecoverableDocument> {
+    session.prune_dead_sessions(now_millis());
+
+    let mut docs: Vec<RecoverableDocument> = orphaned(&session)
+        .into_iter()
+        .map(|(dir, meta)| {
+            let content = read_contents(&dir.join(SNAPSHOT_FILE).to_string_lossy(), None)
+                .ok()
+                .and_then(|contents| String::from_utf8(contents.bytes).ok())
+                .unwrap_or_default();
+            RecoverableDocument {
+                document_id: meta.document_id,
+                source_path: meta.source_path,
+                saved_at: meta.saved_at,
+                size: meta.size,
+                encrypted: meta.encrypted,
+                preview: preview(&content),
+            }
+        })
+        .collect();
+
+    docs.sort_by_key(|d| std::cmp::Reverse(d.saved_at));
+    docs
+}
+
+/// Return the snapshot contents and adopt it into this session so that it is
+/// no longer offered for recovery while this instance keeps running. An
+/// encrypted snapshot needs the document's `passphrase`.
+#[command(async)]
+pub fn restore_recovered_document(
+    session: State<'_, Session>,
+    document_id: String,
+    passphrase: Option<String>,
+) -> Result<RecoveredDocument, FileError> {
+    let (dir, mut meta) = find_orphan(&session, &document_id)
+        .map_err(|e| FileError::new(FileErrorKind::NotFound, &session.dir.to_string_lossy(), e))?;
+
+    let snapshot_path = dir.join(SNAPSHOT_FILE).to_string_lossy().into_owned();
+    let contents = read_contents(&snapshot_path, passphrase.as_deref())?;
+    let content = String::from_utf8(contents.bytes)
+        .map_err(|e| FileError::new(FileErrorKind::Io, &snapshot_path, format!("Failed to read recovered document: {}", e)))?;
+
+    meta.session_id = session.id.clone();
+    meta.pid = std::process::id();
+    let meta_json = serde_json::to_vec_pretty(&meta)
+        .map_err(|e| FileError::new(FileErrorKind::Io, &snapshot_path, format!("Failed to encode autosave metadata: {}", e)))?;
+    write_atomic(&dir.join(META_FILE).to_string_lossy(), &meta_json)?;
+
+    Ok(RecoveredDocument {
+        document_id: meta.document_id,
+        source_path: meta.source_path,
+        content,
+    })
+}
+
+#[command]
+pub fn discard_recovered_document(session: State<'_, Session>, document_id: String) -> Result<(), String> {
+    let (dir, _) = find_orphan(&session, &document_id)?;
+
+    fs::remove_dir_all(&dir)
+        .map_err(|e| format!("Failed to discard recovered document: {}", e))
+}
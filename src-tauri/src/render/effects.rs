@@ -0,0 +1,390 @@
+//! Shadows and blurs on rendered layers, matching the filters SVG export
+//! writes for the same effects. Used by the renderer, and by the canvas
+//! through `apply_effects` so previews match exports.
+
+use super::MAX_DIMENSION;
+use crate::export::raster::demultiply;
+use crate::geometry::{scale, Matrix};
+use crate::model::Rgba;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use fearless_simd::{dispatch, f32x4, prelude::*, Level};
+use serde::Serialize;
+use serde_json::Value;
+use tauri::command;
+use tiny_skia::{BlendMode, ColorU8, Mask, MaskType, Pixmap, PixmapPaint, PremultipliedColorU8, Transform};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum EffectKind {
+    DropShadow,
+    InnerShadow,
+    LayerBlur,
+    BackgroundBlur,
+}
+
+/// A visible effect from a node's `effects`, in the node's units.
+#[derive(Clone, Copy)]
+pub struct Effect {
+    pub kind: EffectKind,
+    pub radius: f64,
+    pub spread: f64,
+    pub offset: (f64, f64),
+    pub color: Rgba,
+}
+
+/// The effects that can be drawn, in order, skipping hidden and unknown ones.
+pub fn parse_effects(effects: &[Value]) -> Vec<Effect> {
+    effects
+        .iter()
+        .filter(|effect| effect.get("visible").and_then(Value::as_bool) != Some(false))
+        .filter_map(|effect| {
+            let kind = match effect.get("type").and_then(Value::as_str)? {
+                "DROP_SHADOW" => EffectKind::DropShadow,
+                "INNER_SHADOW" => EffectKind::InnerShadow,
+                "BLUR" | "LAYER_BLUR" => EffectKind::LayerBlur,
+                "BACKGROUND_BLUR" => EffectKind::BackgroundBlur,
+                _ => return None,
+            };
+            let number = |pointer: &str| effect.pointer(pointer).and_then(Value::as_f64).unwrap_or(0.0);
+            let color = effect
+                .get("color")
+                .and_then(|c| serde_json::from_value(c.clone()).ok())
+                .unwrap_or(Rgba { r: 0.0, g: 0.0, b: 0.0, a: 0.25 });
+            Some(Effect {
+                kind,
+                radius: number("/radius").max(0.0),
+                spread: number("/spread"),
+                offset: (number("/offset/x"), number("/offset/y")),
+                color,
+            })
+        })
+        .collect()
+}
+
+/// `effect` in device pixels under `world`: the offset turned and scaled
+/// with the node, and the radius and spread scaled.
+pub fn to_device(effect: &Effect, world: &Matrix) -> Effect {
+    let scale = (world[0] * world[3] - world[1] * world[2]).abs().sqrt();
+    let (dx, dy) = effect.offset;
+    Effect {
+        radius: effect.radius * scale,
+        spread: effect.spread * scale,
+        offset: (world[0] * dx + world[2] * dy, world[1] * dx + world[3] * dy),
+        ..*effect
+    }
+}
+
+/// Widths of three box blurs that together approximate a gaussian with
+/// standard deviation `sigma`.
+fn box_radii(sigma: f64) -> [usize; 3] {
+    let variance = 12.0 * sigma * sigma;
+    let mut lower = ((variance / 3.0 + 1.0).sqrt().floor() as usize).max(1);
+    if lower.is_multiple_of(2) {
+        lower -= 1;
+    }
+    let upper = lower + 2;
+    let l = lower as f64;
+    let lower_count = ((variance - 3.0 * l * l - 12.0 * l - 9.0) / (-4.0 * l - 4.0)).round().clamp(0.0, 3.0) as usize;
+    [0, 1, 2].map(|i| (if i < lower_count { lower } else { upper } - 1) / 2)
+}
+
+/// One box blur of `radius` along each row, with transparent pixels beyond
+/// the ends, a pixel's four channels at a time.
+#[inline(always)]
+fn blur_rows<S: Simd>(simd: S, src: &[f32], dst: &mut [f32], width: usize, radius: usize) {
+    let scale = 1.0 / (2 * radius + 1) as f32;
+    for (row, out) in src.chunks_exact(width * 4).zip(dst.chunks_exact_mut(width * 4)) {
+        let pixel = |i: usize| f32x4::from_slice(simd, &row[i * 4..i * 4 + 4]);
+        let mut sum = f32x4::splat(simd, 0.0);
+        for i in 0..radius.min(width) {
+            sum += pixel(i);
+        }
+        for i in 0..width {
+            if i + radius < width {
+                sum += pixel(i + radius);
+            }
+            if i > radius {
+                sum -= pixel(i - radius - 1);
+            }
+            (sum * scale).store_slice(&mut out[i * 4..i * 4 + 4]);
+        }
+    }
+}
+
+/// `sums` plus `row` times `sign`, lane by lane.
+#[inline(always)]
+fn accumulate<S: Simd>(simd: S, sums: &mut [f32], row: &[f32], sign: f32) {
+    for (sum, v) in sums.chunks_exact_mut(4).zip(row.chunks_exact(4)) {
+        (f32x4::from_slice(simd, sum) + f32x4::from_slice(simd, v) * sign).store_slice(sum);
+    }
+}
+
+/// One box blur of `radius` down each column, with transparent pixels
+/// beyond the ends. Whole rows are summed at once, so memory is read in
+/// order however tall the image is.
+#[inline(always)]
+fn blur_columns<S: Simd>(simd: S, src: &[f32], dst: &mut [f32], width: usize, height: usize, radius: usize) {
+    let stride = width * 4;
+    let scale = 1.0 / (2 * radius + 1) as f32;
+    let row = |y: usize| &src[y * stride..(y + 1) * stride];
+    let mut sums = vec![0.0; stride];
+    for y in 0..radius.min(height) {
+        accumulate(simd, &mut sums, row(y), 1.0);
+    }
+    for (y, out) in dst.chunks_exact_mut(stride).enumerate() {
+        if y + radius < height {
+            accumulate(simd, &mut sums, row(y + radius), 1.0);
+        }
+        if y > radius {
+            accumulate(simd, &mut sums, row(y - radius - 1), -1.0);
+        }
+        for (o, v) in out.chunks_exact_mut(4).zip(sums.chunks_exact(4)) {
+            (f32x4::from_slice(simd, v) * scale).store_slice(o);
+        }
+    }
+}
+
+#[inline(always)]
+fn box_blurs<S: Simd>(simd: S, data: &mut [f32], width: usize, height: usize, radii: [usize; 3]) {
+    let mut scratch = vec![0.0; data.len()];
+    for radius in radii {
+        blur_rows(simd, data, &mut scratch, width, radius);
+        blur_columns(simd, &scratch, data, width, height, radius);
+    }
+}
+
+/// Gaussian blur of the premultiplied pixels, with `radius` as in the
+/// document, twice the standard deviation. Runs on the widest vector
+/// instructions the CPU has, so its cost does not grow with the radius.
+pub fn blur(pixmap: &mut Pixmap, radius: f64) {
+    if radius < 0.5 {
+        return;
+    }
+    let (width, height) = (pixmap.width() as usize, pixmap.height() as usize);
+    let mut data: Vec<f32> = pixmap.data().iter().map(|v| *v as f32).collect();
+    let radii = box_radii(radius / 2.0);
+    dispatch!(Level::new(), simd => box_blurs(simd, &mut data, width, height, radii));
+    for (out, v) in pixmap.data_mut().iter_mut().zip(&data) {
+        *out = v.round() as u8;
+    }
+}
+
+/// Grow (positive `amount`) or shrink the coverage in `alpha` by whole
+/// pixels, as shadow spread does.
+fn spread(alpha: &mut [u8], width: usize, height: usize, amount: f64) {
+    let radius = amount.abs().round() as usize;
+    if radius == 0 {
+        return;
+    }
+    let pick = if amount > 0.0 { u8::max } else { u8::min };
+    let edge = if amount > 0.0 { 0 } else { 0xFF };
+    let pass = |src: &[u8], dst: &mut [u8], lines: usize, length: usize, line_step: usize, step: usize| {
+        for line in 0..lines {
+            let start = line * line_step;
+            for i in 0..length {
+                let mut value = src[start + i * step];
+                for j in i.saturating_sub(radius)..=(i + radius) {
+                    value = pick(value, if j < length { src[start + j * step] } else { edge });
+                }
+                if i < radius {
+                    value = pick(value, edge);
+                }
+                dst[start + i * step] = value;
+            }
+        }
+    };
+    let mut scratch = vec![0; alpha.len()];
+    pass(alpha, &mut scratch, height, width, width, 1);
+    pass(&scratch, alpha, width, height, 1, width);
+}
+
+/// Coverage of `layer` moved by `offset` pixels, with `outside` shifted in
+/// at the edges.
+fn shifted_alpha(layer: &Pixmap, offset: (f64, f64), outside: u8) -> Vec<u8> {
+    let (width, height) = (layer.width() as i64, layer.height() as i64);
+    let (dx, dy) = (offset.0.round() as i64, offset.1.round() as i64);
+    let pixels = layer.pixels();
+    let mut alpha = vec![outside; pixels.len()];
+    for y in 0..height {
+        let sy = y - dy;
+        if !(0..height).contains(&sy) {
+            continue;
+        }
+        for x in 0..width {
+            let sx = x - dx;
+            if (0..width).contains(&sx) {
+                alpha[(y * width + x) as usize] = pixels[(sy * width + sx) as usize].alpha();
+            }
+        }
+    }
+    alpha
+}
+
+/// A layer of `color` with `alpha` as its coverage.
+fn tinted(alpha: &[u8], width: u32, height: u32, color: Rgba) -> Option<Pixmap> {
+    let mut pixmap = Pixmap::new(width, height)?;
+    let channel = |v: f64| (v.clamp(0.0, 1.0) * 255.0).round() as u32;
+    let (r, g, b, a) = (channel(color.r), channel(color.g), channel(color.b), channel(color.a));
+    for (pixel, coverage) in pixmap.pixels_mut().iter_mut().zip(alpha) {
+        let alpha = a * *coverage as u32 / 255;
+        let premultiply = |c: u32| (c * alpha / 255) as u8;
+        if let Some(p) = PremultipliedColorU8::from_rgba(premultiply(r), premultiply(g), premultiply(b), alpha as u8) {
+            *pixel = p;
+        }
+    }
+    Some(pixmap)
+}
+
+/// The shadow `layer` casts, `effect` being in device pixels, to draw
+/// under it.
+pub fn drop_shadow(layer: &Pixmap, effect: &Effect) -> Option<Pixmap> {
+    let (width, height) = (layer.width(), layer.height());
+    let mut alpha = shifted_alpha(layer, effect.offset, 0);
+    spread(&mut alpha, width as usize, height as usize, effect.spread);
+    let mut shadow = tinted(&alpha, width, height, effect.color)?;
+    blur(&mut shadow, effect.radius);
+    Some(shadow)
+}
+
+/// The shadow cast inside `layer` by its own edges, `effect` being in
+/// device pixels, to draw over it.
+pub fn inner_shadow(layer: &Pixmap, effect: &Effect) -> Option<Pixmap> {
+    let (width, height) = (layer.width(), layer.height());
+    // The shadow comes from outside the shape, so spread grows the outside
+    let mut outside: Vec<u8> = shifted_alpha(layer, effect.offset, 0xFF).iter().map(|a| 0xFF - a).collect();
+    spread(&mut outside, width as usize, height as usize, effect.spread);
+    let mut shadow = tinted(&outside, width, height, effect.color)?;
+    blur(&mut shadow, effect.radius);
+
+    // Kept only where the layer is
+    for (pixel, inside) in shadow.pixels_mut().iter_mut().zip(layer.pixels()) {
+        let scale = |c: u8| (c as u32 * inside.alpha() as u32 / 255) as u8;
+        if let Some(p) = PremultipliedColorU8::from_rgba(
+            scale(pixel.red()),
+            scale(pixel.green()),
+            scale(pixel.blue()),
+            scale(pixel.alpha()),
+        ) {
+            *pixel = p;
+        }
+    }
+    Some(shadow)
+}
+
+/// `layer` with `effects`, in device pixels, applied: shadows under and
+/// over it and the layer blurred. Background blurs need what is behind the
+/// layer and are left to the caller.
+pub fn composite(mut layer: Pixmap, effects: &[Effect]) -> Pixmap {
+    let under: Vec<Pixmap> = effects
+        .iter()
+        .filter(|e| e.kind == EffectKind::DropShadow)
+        .filter_map(|e| drop_shadow(&layer, e))
+        .collect();
+    let over: Vec<Pixmap> = effects
+        .iter()
+        .filter(|e| e.kind == EffectKind::InnerShadow)
+        .filter_map(|e| inner_shadow(&layer, e))
+        .collect();
+    for effect in effects.iter().filter(|e| e.kind == EffectKind::LayerBlur) {
+        blur(&mut layer, effect.radius);
+    }
+    if under.is_empty() && over.is_empty() {
+        return layer;
+    }
+
+    let Some(mut result) = Pixmap::new(layer.width(), layer.height()) else { return layer };
+    for pixmap in under.iter().chain(std::iter::once(&layer)).chain(&over) {
+        result.draw_pixmap(0, 0, pixmap.as_ref(), &PixmapPaint::default(), Transform::identity(), None);
+    }
+    result
+}
+
+/// How many pixels `effect`, in device pixels, can reach beyond the layer.
+fn reach(effect: &Effect) -> u32 {
+    let blur: usize = if effect.radius < 0.5 { 0 } else { box_radii(effect.radius / 2.0).iter().sum() };
+    let extent = match effect.kind {
+        EffectKind::DropShadow => blur as f64 + effect.spread.max(0.0) + effect.offset.0.abs().max(effect.offset.1.abs()),
+        EffectKind::LayerBlur => blur as f64,
+        EffectKind::InnerShadow | EffectKind::BackgroundBlur => 0.0,
+    };
+    extent.ceil() as u32
+}
+
+/// Straight-alpha RGBA pixels, base64-encoded, as a canvas's `ImageData`
+/// holds them.
+fn decode_pixels(pixels: &str, width: u32, height: u32, left: u32, top: u32, padded: (u32, u32)) -> Result<Pixmap, String> {
+    let bytes = BASE64.decode(pixels).map_err(|e| format!("Failed to decode pixels: {}", e))?;
+    if bytes.len() as u64 != width as u64 * height as u64 * 4 {
+        return Err(format!("Expected {} bytes of pixels for {}×{}, got {}", width as u64 * height as u64 * 4, width, height, bytes.len()));
+    }
+    let mut pixmap = Pixmap::new(padded.0, padded.1).ok_or("Invalid image size")?;
+    let stride = padded.0 as usize;
+    let pixmap_pixels = pixmap.pixels_mut();
+    for (i, p) in bytes.chunks_exact(4).enumerate() {
+        let (x, y) = (i % width as usize + left as usize, i / width as usize + top as usize);
+        pixmap_pixels[y * stride + x] = ColorU8::from_rgba(p[0], p[1], p[2], p[3]).premultiply();
+    }
+    Ok(pixmap)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EffectsImage {
+    /// Base64-encoded straight-alpha RGBA.
+    pub pixels: String,
+    pub width: u32,
+    pub height: u32,
+    /// Where the result's top left corner is relative to the input's, as
+    /// far as the effects reach past its edges.
+    pub x: i32,
+    pub y: i32,
+}
+
+/// Apply a node's `effects` to a rendered layer's `pixels`, at
+/// `pixel_ratio` pixels per unit, so canvas previews blur and shade the same way as
+/// exports. `backdrop`, the same size as the layer, is what lies behind it
+/// for background blurs. Runs off the interface thread, so large blurs do
+/// not block it.
+#[command]
+pub async fn apply_effects(
+    pixels: String,
+    width: u32,
+    height: u32,
+    effects: Vec<Value>,
+    pixel_ratio: Option<f64>,
+    backdrop: Option<String>,
+) -> Result<EffectsImage, String> {
+    let ratio = pixel_ratio.unwrap_or(1.0);
+    if !(ratio.is_finite() && ratio > 0.0) {
+        return Err(format!("Invalid pixel ratio: {}", ratio));
+    }
+    let world = scale(ratio, ratio);
+    let effects: Vec<Effect> = parse_effects(&effects).iter().map(|e| to_device(e, &world)).collect();
+    let margin = effects.iter().map(reach).max().unwrap_or(0);
+    let padded = (width.saturating_add(2 * margin), height.saturating_add(2 * margin));
+    if width == 0 || height == 0 || padded.0 > MAX_DIMENSION || padded.1 > MAX_DIMENSION {
+        return Err(format!("Invalid image size: {}×{}", width, height));
+    }
+
+    let layer = decode_pixels(&pixels, width, height, margin, margin, padded)?;
+    let mut result = composite(layer.clone(), &effects);
+    let backdrop_radius = effects.iter().filter(|e| e.kind == EffectKind::BackgroundBlur).map(|e| e.radius).fold(0.0, f64::max);
+    if let (Some(backdrop), true) = (backdrop, backdrop_radius > 0.0) {
+        // The blurred backdrop shows through where the layer is
+        let mut backdrop = decode_pixels(&backdrop, width, height, margin, margin, padded)?;
+        blur(&mut backdrop, backdrop_radius);
+        let coverage = Mask::from_pixmap(layer.as_ref(), MaskType::Alpha);
+        let mut under = Pixmap::new(padded.0, padded.1).ok_or("Invalid image size")?;
+        let paint = PixmapPaint { blend_mode: BlendMode::Source, ..Default::default() };
+        under.draw_pixmap(0, 0, backdrop.as_ref(), &paint, Transform::identity(), Some(&coverage));
+        under.draw_pixmap(0, 0, result.as_ref(), &PixmapPaint::default(), Transform::identity(), None);
+        result = under;
+    }
+
+    Ok(EffectsImage {
+        pixels: BASE64.encode(demultiply(&result)),
+        width: result.width(),
+        height: result.height(),
+        x: -(margin as i32),
+        y: -(margin as i32),
+    })
+}
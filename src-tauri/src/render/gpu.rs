@@ -0,0 +1,823 @@
+//! Experimental canvas renderer on the GPU, built with the `gpu` feature.
+//!
+//! A page is tessellated once into triangles in canvas coordinates and kept
+//! on the GPU, so panning and zooming only redraws it under a new viewport
+//! rather than rasterizing every node again. Each frame is rendered
+//! offscreen with 4x multisampling and read back as RGBA for the webview to
+//! put on a `<canvas>`.
+//!
+//! Fills and strokes with solid and gradient paints, text outlines, opacity
+//! and clipping to frame bounds are drawn. Image and pattern paints,
+//! masks, effects and blend modes are left to the CPU renderer. Strokes are
+//! drawn centered on the outline whatever their alignment.
+
+use super::{node_geometry, stroke_style, unit_transform};
+use crate::fonts::outlines::outline_text;
+use crate::geometry::markers::marked_stroke;
+use crate::geometry::{invert, multiply, Matrix, Rect, IDENTITY};
+use crate::model::{DocumentTree, NodeData, NodeType, Paint, Rgba};
+use bytemuck::{Pod, Zeroable};
+use lyon_tessellation::math::point;
+use lyon_tessellation::{BuffersBuilder, FillOptions, FillRule as LyonFillRule, FillTessellator, FillVertex, VertexBuffers};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tauri::ipc::Response;
+use tauri::{command, State};
+use tiny_skia::{FillRule, Path, PathSegment, Transform};
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+/// Stops a gradient keeps; any past these are dropped.
+const MAX_STOPS: usize = 8;
+/// How far a tessellated curve may stray from the real one, in pixels.
+const TOLERANCE: f64 = 0.25;
+/// Samples per pixel along edges.
+const SAMPLES: u32 = 4;
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+const SHADER: &str = r#"
+struct View {
+    // Canvas to clip space: scale, then offset
+    transform: vec4<f32>,
+}
+
+struct PaintData {
+    // Kind (0 solid, 1 linear, 2 radial) and stop count
+    kind: vec4<u32>,
+    // Canvas to the gradient's unit square, as a b c d, then tx ty
+    to_unit: vec4<f32>,
+    to_unit_offset: vec4<f32>,
+    // Drawn only inside, as min x, min y, max x, max y
+    clip: vec4<f32>,
+    offsets: array<vec4<f32>, 2>,
+    colors: array<vec4<f32>, 8>,
+}
+
+@group(0) @binding(0) var<uniform> view: View;
+@group(0) @binding(1) var<storage, read> paints: array<PaintData>;
+
+struct Varyings {
+    @builtin(position) position: vec4<f32>,
+    @location(0) canvas: vec2<f32>,
+    @location(1) @interpolate(flat) paint: u32,
+}
+
+@vertex
+fn vs_main(@location(0) position: vec2<f32>, @location(1) paint: u32) -> Varyings {
+    var out: Varyings;
+    out.position = vec4<f32>(position * view.transform.xy + view.transform.zw, 0.0, 1.0);
+    out.canvas = position;
+    out.paint = paint;
+    return out;
+}
+
+fn stop_offset(paint: PaintData, i: u32) -> f32 {
+    return paint.offsets[i / 4u][i % 4u];
+}
+
+@fragment
+fn fs_main(in: Varyings) -> @location(0) vec4<f32> {
+    let paint = paints[in.paint];
+    let p = in.canvas;
+    if (p.x < paint.clip.x || p.y < paint.clip.y || p.x > paint.clip.z || p.y > paint.clip.w) {
+        discard;
+    }
+
+    var color = paint.colors[0];
+    let count = paint.kind.y;
+    if (paint.kind.x != 0u && count > 1u) {
+        let unit = vec2<f32>(
+            dot(paint.to_unit.xz, p) + paint.to_unit_offset.x,
+            dot(paint.to_unit.yw, p) + paint.to_unit_offset.y,
+        );
+        var t = unit.x;
+        if (paint.kind.x == 2u) {
+            t = distance(unit, vec2<f32>(0.5, 0.5)) * 2.0;
+        }
+        t = clamp(t, 0.0, 1.0);
+        for (var i = 1u; i < count; i++) {
+            let start = stop_offset(paint, i - 1u);
+            if (t > start) {
+                let span = max(stop_offset(paint, i) - start, 0.000001);
+                color = mix(paint.colors[i - 1u], paint.colors[i], clamp((t - start) / span, 0.0, 1.0));
+            }
+        }
+    }
+    return vec4<f32>(color.rgb * color.a, color.a);
+}
+"#;
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Vertex {
+    position: [f32; 2],
+    paint: u32,
+}
+
+/// A paint as the fragment shader reads it, laid out to match `PaintData`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct PaintData {
+    kind: [u32; 4],
+    to_unit: [f32; 4],
+    to_unit_offset: [f32; 4],
+    clip: [f32; 4],
+    offsets: [f32; MAX_STOPS],
+    colors: [[f32; 4]; MAX_STOPS],
+}
+
+/// Triangles of one node, with paint indices local to it.
+#[derive(Default)]
+struct Mesh {
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+    paints: Vec<PaintData>,
+}
+
+/// What a node's mesh was tessellated from; the mesh is reused for as long
+/// as none of it changes.
+struct Cached {
+    node: NodeData,
+    world: Matrix,
+    opacity: f64,
+    clip: Option<Rect>,
+    detail: i32,
+    mesh: Arc<Mesh>,
+}
+
+fn color(c: Rgba, opacity: f64) -> [f32; 4] {
+    [c.r as f32, c.g as f32, c.b as f32, (c.a * opacity) as f32]
+}
+
+impl Mesh {
+    /// Add `paint` for the area inside `geometry`, in canvas coordinates.
+    fn fill(&mut self, geometry: &[(Path, FillRule)], paint: &Paint, target: &Target, tolerance: f64) {
+        let Some(data) = paint_data(paint, target) else { return };
+        let index = self.paints.len() as u32;
+        let mut buffers: VertexBuffers<Vertex, u32> = VertexBuffers::new();
+        let mut tessellator = FillTessellator::new();
+        for (path, rule) in geometry {
+            let Some(path) = path.clone().transform(to_transform(&target.world)) else { continue };
+            let rule = match rule {
+                FillRule::Winding => LyonFillRule::NonZero,
+                FillRule::EvenOdd => LyonFillRule::EvenOdd,
+            };
+            let options = FillOptions::tolerance(tolerance as f32).with_fill_rule(rule);
+            let mut output = BuffersBuilder::new(&mut buffers, |vertex: FillVertex| Vertex {
+                position: vertex.position().to_array(),
+                paint: index,
+            });
+            // A path the tessellator can't make sense of is left out, as
+            // tiny-skia leaves out paths it can't fill
+            let _ = tessellator.tessellate_path(&lyon_path(&path), &options, &mut output);
+        }
+        if buffers.indices.is_empty() {
+            return;
+        }
+        let base = self.vertices.len() as u32;
+        self.vertices.extend(buffers.vertices);
+        self.indices.extend(buffers.indices.iter().map(|i| i + base));
+        self.paints.push(data);
+    }
+}
+
+fn to_transform(m: &Matrix) -> Transform {
+    Transform::from_row(m[0] as f32, m[1] as f32, m[2] as f32, m[3] as f32, m[4] as f32, m[5] as f32)
+}
+
+fn lyon_path(path: &Path) -> lyon_tessellation::path::Path {
+    let mut builder = lyon_tessellation::path::Path::builder();
+    let mut open = false;
+    for segment in path.segments() {
+        match segment {
+            PathSegment::MoveTo(p) => {
+                if open {
+                    builder.end(false);
+                }
+                builder.begin(point(p.x, p.y));
+                open = true;
+            }
+            PathSegment::LineTo(p) if open => {
+                builder.line_to(point(p.x, p.y));
+            }
+            PathSegment::QuadTo(c, p) if open => {
+                builder.quadratic_bezier_to(point(c.x, c.y), point(p.x, p.y));
+            }
+            PathSegment::CubicTo(c1, c2, p) if open => {
+                builder.cubic_bezier_to(point(c1.x, c1.y), point(c2.x, c2.y), point(p.x, p.y));
+            }
+            PathSegment::Close if open => {
+                builder.end(true);
+                open = false;
+            }
+            _ => {}
+        }
+    }
+    if open {
+        builder.end(false);
+    }
+    builder.build()
+}
+
+/// Node bounds, transform, opacity and clip shared by every paint of a node.
+struct Target {
+    bounds: Rect,
+    world: Matrix,
+    opacity: f64,
+    clip: Option<Rect>,
+}
+
+fn paint_data(paint: &Paint, target: &Target) -> Option<PaintData> {
+    if !paint.visible() {
+        return None;
+    }
+    let alpha = paint.opacity() * target.opacity;
+    let clip = target.clip.map_or([f32::MIN, f32::MIN, f32::MAX, f32::MAX], |c| {
+        [c.x as f32, c.y as f32, c.right() as f32, c.bottom() as f32]
+    });
+    let mut data = PaintData { clip, ..Zeroable::zeroed() };
+    let (kind, stops, gradient_transform) = match paint {
+        Paint::Solid { color: c, .. } => {
+            data.kind = [0, 1, 0, 0];
+            data.colors[0] = color(*c, alpha);
+            return Some(data);
+        }
+        Paint::GradientLinear { gradient_stops, gradient_transform, .. } => (1, gradient_stops, gradient_transform),
+        Paint::GradientRadial { gradient_stops, gradient_transform, .. } => (2, gradient_stops, gradient_transform),
+        Paint::Image { .. } | Paint::Pattern { .. } => return None,
+    };
+    let mut stops: Vec<_> = stops.iter().take(MAX_STOPS).collect();
+    if stops.is_empty() {
+        return None;
+    }
+    stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+    let to_unit = invert(&multiply(&target.world, &multiply(&unit_transform(target.bounds), gradient_transform)))?;
+    data.kind = [kind, stops.len() as u32, 0, 0];
+    data.to_unit = [to_unit[0] as f32, to_unit[1] as f32, to_unit[2] as f32, to_unit[3] as f32];
+    data.to_unit_offset = [to_unit[4] as f32, to_unit[5] as f32, 0.0, 0.0];
+    for (i, stop) in stops.iter().enumerate() {
+        data.offsets[i] = stop.position.clamp(0.0, 1.0) as f32;
+        data.colors[i] = color(stop.color, alpha);
+    }
+    Some(data)
+}
+
+/// A shape's fills and strokes, as `Painter::paint_node` draws them.
+fn shape_mesh(mesh: &mut Mesh, node: &NodeData, geometry: &[(Path, FillRule)], target: &Target, zoom: f64) {
+    let tolerance = TOLERANCE / zoom;
+    for paint in node.fills() {
+        mesh.fill(geometry, paint, target, tolerance);
+    }
+
+    let weight = node.stroke_weight();
+    if weight <= 0.0 || node.strokes().is_empty() {
+        return;
+    }
+    let stroke = stroke_style(node, weight);
+    let marked = marked_stroke(node).map(|m| {
+        let lines: Vec<Path> = m.paths.iter().filter_map(super::build_path).collect();
+        (lines, super::build_path(&m.heads))
+    });
+    let lines: Vec<&Path> = match &marked {
+        Some((lines, _)) => lines.iter().collect(),
+        None => geometry.iter().map(|(path, _)| path).collect(),
+    };
+    // Strokes are filled as their outlines, dashes and all
+    let resolution = (crate::geometry::scale_factor(&target.world) * zoom) as f32;
+    let mut outlines: Vec<(Path, FillRule)> =
+        lines.iter().filter_map(|path| path.stroke(&stroke, resolution)).map(|path| (path, FillRule::Winding)).collect();
+    if let Some((_, Some(heads))) = &marked {
+        outlines.push((heads.clone(), FillRule::Winding));
+    }
+    for paint in node.strokes() {
+        mesh.fill(&outlines, paint, target, tolerance);
+    }
+}
+
+/// The triangles of `node` alone, placed by `world`.
+fn node_mesh(node: &NodeData, world: &Matrix, opacity: f64, clip: Option<Rect>, zoom: f64) -> Mesh {
+    let mut mesh = Mesh::default();
+    if node.node_type == NodeType::Text {
+        // Text whose fonts are missing is left out, as on the CPU
+        let Ok(outlined) = outline_text(node) else { return mesh };
+        for vector in outlined.nodes.iter().filter(|n| n.node_type == NodeType::Vector) {
+            let placed = multiply(world, &vector.local_transform());
+            let geometry = node_geometry(vector);
+            let target = Target { bounds: bounds(vector, &geometry), world: placed, opacity, clip };
+            shape_mesh(&mut mesh, vector, &geometry, &target, zoom);
+        }
+        return mesh;
+    }
+    let geometry = node_geometry(node);
+    let target = Target { bounds: bounds(node, &geometry), world: *world, opacity, clip };
+    shape_mesh(&mut mesh, node, &geometry, &target, zoom);
+    mesh
+}
+
+fn bounds(node: &NodeData, geometry: &[(Path, FillRule)]) -> Rect {
+    node.local_bounds()
+        .or_else(|| {
+            Rect::from_points(geometry.iter().flat_map(|(p, _)| {
+                let b = p.bounds();
+                [(b.left() as f64, b.top() as f64), (b.right() as f64, b.bottom() as f64)]
+            }))
+        })
+        .unwrap_or(Rect::new(0.0, 0.0, 1.0, 1.0))
+}
+
+fn intersection(a: Rect, b: Option<Rect>) -> Rect {
+    let Some(b) = b else { return a };
+    let (x, y) = (a.x.max(b.x), a.y.max(b.y));
+    Rect::new(x, y, (a.right().min(b.right()) - x).max(0.0), (a.bottom().min(b.bottom()) - y).max(0.0))
+}
+
+/// A page as triangles on the GPU, with the meshes it was put together
+/// from so an edit only tessellates the nodes it changed.
+struct Scene {
+    tree: DocumentTree,
+    page_id: String,
+    background: Option<Rgba>,
+    cache: HashMap<String, Cached>,
+    /// Zoom the meshes are fine enough for, as a power of two.
+    detail: i32,
+    buffers: Option<SceneBuffers>,
+    node_count: usize,
+}
+
+struct SceneBuffers {
+    vertices: wgpu::Buffer,
+    indices: wgpu::Buffer,
+    index_count: u32,
+    paints: wgpu::Buffer,
+}
+
+/// Walks a page in paint order, reusing meshes from the last pass.
+struct Builder<'a> {
+    tree: &'a DocumentTree,
+    previous: HashMap<String, Cached>,
+    cache: HashMap<String, Cached>,
+    order: Vec<String>,
+    detail: i32,
+}
+
+impl Builder<'_> {
+    fn visit(&mut self, id: &str, parent: &Matrix, opacity: f64, clip: Option<Rect>) {
+        let Some(node) = self.tree.get(id) else { return };
+        // Mask nodes only shape what's above them, which isn't drawn here
+        if !node.visible || node.is_mask == Some(true) {
+            return;
+        }
+        let world = multiply(parent, &node.local_transform());
+        let opacity = opacity * node.opacity();
+
+        let reused = self.previous.remove(id).filter(|cached| {
+            cached.detail == self.detail
+                && cached.world == world
+                && cached.opacity == opacity
+                && cached.clip == clip
+                && cached.node == *node
+        });
+        let cached = reused.unwrap_or_else(|| Cached {
+            node: node.clone(),
+            world,
+            opacity,
+            clip,
+            detail: self.detail,
+            mesh: Arc::new(node_mesh(node, &world, opacity, clip, 2f64.powi(self.detail))),
+        });
+        self.cache.insert(id.to_string(), cached);
+        self.order.push(id.to_string());
+
+        let clip = if node.clips_content.unwrap_or(false) {
+            let frame = Rect::new(0.0, 0.0, node.width.unwrap_or(0.0), node.height.unwrap_or(0.0));
+            Some(intersection(frame.transformed(&world), clip))
+        } else {
+            clip
+        };
+        for child in self.tree.children(id) {
+            self.visit(child, &world, opacity, clip);
+        }
+    }
+}
+
+impl Scene {
+    fn new(tree: DocumentTree, page_id: String) -> Self {
+        Scene { tree, page_id, background: None, cache: HashMap::new(), detail: 0, buffers: None, node_count: 0 }
+    }
+
+    /// Tessellate what changed on the page since the last pass, at a detail
+    /// of at least `zoom`, and upload the page.
+    fn build(&mut self, gpu: &Gpu, zoom: f64) -> Result<(), String> {
+        let tree = &self.tree;
+        let page = tree.get(&self.page_id).ok_or_else(|| format!("Unknown node: {}", self.page_id))?;
+        self.background = page.background_color;
+        self.detail = self.detail.max(zoom.log2().ceil() as i32);
+        let mut builder = Builder {
+            tree,
+            previous: std::mem::take(&mut self.cache),
+            cache: HashMap::new(),
+            order: Vec::new(),
+            detail: self.detail,
+        };
+        let parent = tree.parent(&self.page_id).map(|p| tree.world_transform(p)).unwrap_or(IDENTITY);
+        let world = multiply(&parent, &page.local_transform());
+        for child in tree.children(&self.page_id) {
+            builder.visit(child, &world, 1.0, None);
+        }
+
+        let mut mesh = Mesh::default();
+        for id in &builder.order {
+            let part = &builder.cache[id].mesh;
+            let (base, paint_base) = (mesh.vertices.len() as u32, mesh.paints.len() as u32);
+            mesh.vertices.extend(part.vertices.iter().map(|v| Vertex { position: v.position, paint: v.paint + paint_base }));
+            mesh.indices.extend(part.indices.iter().map(|i| i + base));
+            mesh.paints.extend_from_slice(&part.paints);
+        }
+        self.node_count = builder.order.len();
+        self.cache = builder.cache;
+        self.buffers = gpu.upload(&mesh);
+        Ok(())
+    }
+}
+
+/// The device and the pipeline every scene is drawn with.
+struct Gpu {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::RenderPipeline,
+    layout: wgpu::BindGroupLayout,
+    max_size: u32,
+    /// Render and readback targets of the last viewport size.
+    targets: Option<Targets>,
+}
+
+struct Targets {
+    width: u32,
+    height: u32,
+    multisampled: wgpu::TextureView,
+    resolved: wgpu::Texture,
+    resolved_view: wgpu::TextureView,
+    readback: wgpu::Buffer,
+    /// Bytes to a row of `readback`, padded as copies need.
+    row_bytes: u32,
+}
+
+impl Gpu {
+    async fn new() -> Result<Self, String> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::from_env_or_default());
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| format!("No GPU is available: {}", e))?;
+        let limits = wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits());
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor {
+                label: Some("canvas"),
+                required_limits: limits.clone(),
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| format!("Failed to open the GPU: {}", e))?;
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("canvas"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("canvas"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("canvas"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("canvas"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: size_of::<Vertex>() as u64,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Uint32],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: FORMAT,
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState { count: SAMPLES, ..Default::default() },
+            multiview: None,
+            cache: None,
+        });
+        Ok(Gpu { device, queue, pipeline, layout, max_size: limits.max_texture_dimension_2d, targets: None })
+    }
+
+    fn upload(&self, mesh: &Mesh) -> Option<SceneBuffers> {
+        if mesh.indices.is_empty() {
+            return None;
+        }
+        let buffer = |label, contents, usage| self.device.create_buffer_init(&BufferInitDescriptor { label: Some(label), contents, usage });
+        Some(SceneBuffers {
+            vertices: buffer("vertices", bytemuck::cast_slice(&mesh.vertices), wgpu::BufferUsages::VERTEX),
+            indices: buffer("indices", bytemuck::cast_slice(&mesh.indices), wgpu::BufferUsages::INDEX),
+            index_count: mesh.indices.len() as u32,
+            paints: buffer("paints", bytemuck::cast_slice(&mesh.paints), wgpu::BufferUsages::STORAGE),
+        })
+    }
+
+    /// Make the render and readback targets `width` × `height`.
+    fn resize(&mut self, width: u32, height: u32) {
+        if self.targets.as_ref().is_some_and(|t| t.width == width && t.height == height) {
+            return;
+        }
+        let size = wgpu::Extent3d { width, height, depth_or_array_layers: 1 };
+        let texture = |label, sample_count, usage| {
+            self.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size,
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format: FORMAT,
+                usage,
+                view_formats: &[],
+            })
+        };
+        let multisampled = texture("frame", SAMPLES, wgpu::TextureUsages::RENDER_ATTACHMENT);
+        let resolved = texture("resolved", 1, wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC);
+        let row_bytes = (width * 4).div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let readback = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback"),
+            size: row_bytes as u64 * height as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        self.targets = Some(Targets {
+            width,
+            height,
+            multisampled: multisampled.create_view(&Default::default()),
+            resolved_view: resolved.create_view(&Default::default()),
+            resolved,
+            readback,
+            row_bytes,
+        });
+    }
+
+    /// Draw `scene` under `viewport` and read it back as straight-alpha
+    /// RGBA rows.
+    fn render(&mut self, scene: &Scene, viewport: &Viewport, background: Option<Rgba>) -> Result<Vec<u8>, String> {
+        let (width, height) = (viewport.width, viewport.height);
+        // Canvas coordinates to clip space, y up
+        let (sx, sy) = (2.0 * viewport.zoom / width as f64, -2.0 * viewport.zoom / height as f64);
+        let view = [sx as f32, sy as f32, (-viewport.x * sx - 1.0) as f32, (-viewport.y * sy + 1.0) as f32];
+        let view = self.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("view"),
+            contents: bytemuck::cast_slice(&view),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = scene.buffers.as_ref().map(|buffers| {
+            self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("canvas"),
+                layout: &self.layout,
+                entries: &[
+                    wgpu::BindGroupEntry { binding: 0, resource: view.as_entire_binding() },
+                    wgpu::BindGroupEntry { binding: 1, resource: buffers.paints.as_entire_binding() },
+                ],
+            })
+        });
+        let clear = background.map_or(wgpu::Color::TRANSPARENT, |bg| wgpu::Color {
+            r: bg.r * bg.a,
+            g: bg.g * bg.a,
+            b: bg.b * bg.a,
+            a: bg.a,
+        });
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("canvas") });
+        self.resize(width, height);
+        let (device, queue, pipeline) = (&self.device, &self.queue, &self.pipeline);
+        let Some(targets) = self.targets.as_ref() else { return Err("Failed to allocate render target".to_string()) };
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("canvas"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &targets.multisampled,
+                    depth_slice: None,
+                    resolve_target: Some(&targets.resolved_view),
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Clear(clear), store: wgpu::StoreOp::Discard },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            if let (Some(buffers), Some(bind_group)) = (&scene.buffers, &bind_group) {
+                pass.set_pipeline(pipeline);
+                pass.set_bind_group(0, bind_group, &[]);
+                pass.set_vertex_buffer(0, buffers.vertices.slice(..));
+                pass.set_index_buffer(buffers.indices.slice(..), wgpu::IndexFormat::Uint32);
+                pass.draw_indexed(0..buffers.index_count, 0, 0..1);
+            }
+        }
+        encoder.copy_texture_to_buffer(
+            targets.resolved.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &targets.readback,
+                layout: wgpu::TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(targets.row_bytes), rows_per_image: None },
+            },
+            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        );
+        queue.submit([encoder.finish()]);
+
+        let slice = targets.readback.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |mapped| {
+            let _ = sender.send(mapped);
+        });
+        device.poll(wgpu::PollType::wait_indefinitely()).map_err(|e| format!("Failed to render: {}", e))?;
+        receiver
+            .recv()
+            .map_err(|e| format!("Failed to render: {}", e))?
+            .map_err(|e| format!("Failed to render: {}", e))?;
+
+        let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+        {
+            let mapped = slice.get_mapped_range();
+            for row in mapped.chunks_exact(targets.row_bytes as usize) {
+                pixels.extend_from_slice(&row[..width as usize * 4]);
+            }
+        }
+        targets.readback.unmap();
+        // The webview's ImageData wants straight alpha
+        for pixel in pixels.chunks_exact_mut(4) {
+            let alpha = pixel[3] as u32;
+            if alpha != 0 && alpha != 255 {
+                for channel in &mut pixel[..3] {
+                    *channel = ((*channel as u32 * 255 + alpha / 2) / alpha).min(255) as u8;
+                }
+            }
+        }
+        Ok(pixels)
+    }
+}
+
+#[derive(Default)]
+struct Canvas {
+    /// Opened on first use, so builds with the feature start as quickly.
+    gpu: Option<Gpu>,
+    scenes: HashMap<u64, Scene>,
+    next_id: u64,
+}
+
+/// Scenes loaded onto the GPU.
+#[derive(Default)]
+pub struct GpuCanvas {
+    inner: Mutex<Canvas>,
+}
+
+impl GpuCanvas {
+    fn lock(&self) -> MutexGuard<'_, Canvas> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The part of the canvas to draw: `width` × `height` pixels with
+/// canvas point `x`, `y` at the top left, `zoom` pixels to a unit.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Viewport {
+    pub x: f64,
+    pub y: f64,
+    pub zoom: f64,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GpuScene {
+    pub scene_id: u64,
+    /// Nodes drawn, text outlines counting as one.
+    pub node_count: usize,
+}
+
+fn page_of(tree: &DocumentTree, page_id: Option<String>) -> Result<String, String> {
+    match page_id {
+        Some(id) if tree.get(&id).is_some() => Ok(id),
+        Some(id) => Err(format!("Unknown node: {}", id)),
+        None => Ok(tree.pages().first().map_or_else(|| tree.root_id.clone(), |page| page.id.clone())),
+    }
+}
+
+/// Tessellate the page `page_id` of `document_json`, or its first page,
+/// onto the GPU for `gpu_render_viewport`.
+#[command]
+pub async fn gpu_load_scene(canvas: State<'_, GpuCanvas>, document_json: String, page_id: Option<String>) -> Result<GpuScene, String> {
+    let tree = DocumentTree::parse(&document_json)?;
+    let page_id = page_of(&tree, page_id)?;
+    // Opening the GPU is awaited, so it happens without the lock
+    let opened = canvas.lock().gpu.is_some();
+    let new_gpu = if opened { None } else { Some(Gpu::new().await?) };
+    let mut canvas = canvas.lock();
+    let Canvas { gpu, scenes, next_id } = &mut *canvas;
+    let gpu = match new_gpu {
+        Some(new_gpu) => gpu.get_or_insert(new_gpu),
+        None => gpu.as_mut().ok_or_else(|| "No GPU is available".to_string())?,
+    };
+    let mut scene = Scene::new(tree, page_id);
+    scene.build(gpu, 1.0)?;
+    *next_id += 1;
+    let scene_id = *next_id;
+    let node_count = scene.node_count;
+    scenes.insert(scene_id, scene);
+    Ok(GpuScene { scene_id, node_count })
+}
+
+/// Replace the document behind a scene after an edit. Only nodes that
+/// changed, moved or were added are tessellated again.
+#[command(async)]
+pub fn gpu_update_scene(canvas: State<'_, GpuCanvas>, scene_id: u64, document_json: String) -> Result<GpuScene, String> {
+    let tree = DocumentTree::parse(&document_json)?;
+    let mut canvas = canvas.lock();
+    let Canvas { gpu, scenes, .. } = &mut *canvas;
+    let scene = scenes.get_mut(&scene_id).ok_or_else(|| format!("Unknown scene: {}", scene_id))?;
+    let gpu = gpu.as_ref().ok_or_else(|| "No GPU is available".to_string())?;
+    if tree.get(&scene.page_id).is_none() {
+        return Err(format!("Unknown node: {}", scene.page_id));
+    }
+    scene.tree = tree;
+    scene.build(gpu, 1.0)?;
+    Ok(GpuScene { scene_id, node_count: scene.node_count })
+}
+
+/// Draw a scene under `viewport` over `background`, or the page's
+/// background color, and return its pixels as straight-alpha RGBA rows,
+/// top first, for an `ImageData`.
+#[command(async)]
+pub fn gpu_render_viewport(
+    canvas: State<'_, GpuCanvas>,
+    scene_id: u64,
+    viewport: Viewport,
+    background: Option<Rgba>,
+) -> Result<Response, String> {
+    if !(viewport.zoom.is_finite() && viewport.zoom > 0.0 && viewport.x.is_finite() && viewport.y.is_finite()) {
+        return Err(format!("Invalid viewport zoom: {}", viewport.zoom));
+    }
+    let mut canvas = canvas.lock();
+    let Canvas { gpu, scenes, .. } = &mut *canvas;
+    let scene = scenes.get_mut(&scene_id).ok_or_else(|| format!("Unknown scene: {}", scene_id))?;
+    let gpu = gpu.as_mut().ok_or_else(|| "No GPU is available".to_string())?;
+    if viewport.width == 0 || viewport.height == 0 || viewport.width > gpu.max_size || viewport.height > gpu.max_size {
+        return Err(format!(
+            "Viewport of {}x{} is outside the GPU's limit of {}px",
+            viewport.width, viewport.height, gpu.max_size
+        ));
+    }
+    // Zooming in past what the meshes were tessellated for makes curves
+    // look faceted, so they're tessellated again finer
+    if viewport.zoom.log2().ceil() as i32 > scene.detail {
+        scene.build(gpu, viewport.zoom)?;
+    }
+    let background = background.or(scene.background);
+    gpu.render(scene, &viewport, background).map(Response::new)
+}
+
+/// Free a scene's GPU memory.
+#[command]
+pub fn gpu_release_scene(canvas: State<'_, GpuCanvas>, scene_id: u64) -> bool {
+    canvas.lock().scenes.remove(&scene_id).is_some()
+}
@@ -0,0 +1,664 @@
+//! CPU rasterizer for documents, built on tiny-skia.
+//!
+//! Mirrors the webview renderer closely enough for previews and exports,
+//! without needing the webview: frames, vectors, ellipses, images and text
+//! with solid, gradient, image and pattern paints, strokes, clipping,
+//! masks, layer opacity, blend modes, shadows and blurs.
+
+pub mod effects;
+#[cfg(feature = "gpu")]
+pub mod gpu;
+
+use crate::document::links::linked_path;
+use crate::fonts::outlines::outline_text;
+use crate::geometry::markers::marked_stroke;
+use crate::geometry::stroke::dash_array;
+use crate::geometry::{compose, multiply, scale, scale_factor, translate, Matrix, Rect, IDENTITY};
+use crate::import::raster;
+use crate::model::{DocumentTree, NodeData, NodeType, Paint, PathCommand, Rgba, ScaleMode, TileType, VectorPath, WindingRule};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use effects::{blur, composite, parse_effects, to_device, Effect, EffectKind};
+use std::collections::HashMap;
+use std::fs;
+use tiny_skia::{
+    BlendMode, FillRule, FilterQuality, GradientStop, IntSize, LineCap, LineJoin, LinearGradient, Mask, MaskType,
+    Path, PathBuilder, Pattern, Pixmap, PixmapPaint, Point, RadialGradient, Shader, SpreadMode, Stroke, StrokeDash,
+    Transform,
+};
+
+/// Largest raster the renderer will allocate, per side.
+pub const MAX_DIMENSION: u32 = 16_384;
+
+/// Resolves `imageRef` values to decoded images.
+pub trait ImageSource {
+    fn image(&self, image_ref: &str) -> Option<Pixmap>;
+}
+
+/// Images from `data:` URLs, linked files and, for bundles, embedded asset
+/// bytes keyed by their bundle path.
+#[derive(Default)]
+pub struct AssetImages {
+    pub assets: HashMap<String, Vec<u8>>,
+}
+
+impl ImageSource for AssetImages {
+    fn image(&self, image_ref: &str) -> Option<Pixmap> {
+        let bytes = match image_ref.strip_prefix("data:") {
+            Some(rest) => {
+                let (meta, data) = rest.split_once(',')?;
+                if !meta.ends_with(";base64") {
+                    return None;
+                }
+                BASE64.decode(data.as_bytes()).ok()?
+            }
+            None => match linked_path(image_ref) {
+                Some(path) => fs::read(path).ok()?,
+                None => self.assets.get(image_ref)?.clone(),
+            },
+        };
+        if let Ok(pixmap) = Pixmap::decode_png(&bytes) {
+            return Some(pixmap);
+        }
+        // Linked photos are as often JPEG or HEIC as PNG
+        let image = raster::decode(&bytes).ok()?;
+        let size = IntSize::from_wh(image.width(), image.height())?;
+        let mut data = image.into_raw();
+        for p in data.chunks_exact_mut(4) {
+            let a = p[3] as u32;
+            p[..3].iter_mut().for_each(|c| *c = ((*c as u32 * a + 127) / 255) as u8);
+        }
+        Pixmap::from_vec(data, size)
+    }
+}
+
+fn to_transform(m: &Matrix) -> Transform {
+    Transform::from_row(m[0] as f32, m[1] as f32, m[2] as f32, m[3] as f32, m[4] as f32, m[5] as f32)
+}
+
+fn color(c: Rgba, opacity: f64) -> tiny_skia::Color {
+    tiny_skia::Color::from_rgba(
+        c.r.clamp(0.0, 1.0) as f32,
+        c.g.clamp(0.0, 1.0) as f32,
+        c.b.clamp(0.0, 1.0) as f32,
+        (c.a * opacity).clamp(0.0, 1.0) as f32,
+    )
+    .unwrap_or(tiny_skia::Color::TRANSPARENT)
+}
+
+pub fn build_path(path: &VectorPath) -> Option<Path> {
+    let mut pb = PathBuilder::new();
+    for cmd in &path.commands {
+        match *cmd {
+            PathCommand::MoveTo { x, y } => pb.move_to(x as f32, y as f32),
+            PathCommand::LineTo { x, y } => pb.line_to(x as f32, y as f32),
+            PathCommand::CurveTo { x1, y1, x2, y2, x, y } => {
+                pb.cubic_to(x1 as f32, y1 as f32, x2 as f32, y2 as f32, x as f32, y as f32)
+            }
+            PathCommand::ClosePath => pb.close(),
+        }
+    }
+    pb.finish()
+}
+
+fn fill_rule(rule: WindingRule) -> FillRule {
+    match rule {
+        WindingRule::Nonzero => FillRule::Winding,
+        WindingRule::Evenodd => FillRule::EvenOdd,
+    }
+}
+
+/// tiny-skia's blend mode for a node's `blendMode`, or `None` for normal
+/// compositing. Linear burn has no equivalent and is drawn normally.
+fn blend_mode(mode: Option<&str>) -> Option<BlendMode> {
+    Some(match mode? {
+        "MULTIPLY" => BlendMode::Multiply,
+        "SCREEN" => BlendMode::Screen,
+        "OVERLAY" => BlendMode::Overlay,
+        "DARKEN" => BlendMode::Darken,
+        "LIGHTEN" => BlendMode::Lighten,
+        "COLOR_DODGE" => BlendMode::ColorDodge,
+        "COLOR_BURN" => BlendMode::ColorBurn,
+        "HARD_LIGHT" => BlendMode::HardLight,
+        "SOFT_LIGHT" => BlendMode::SoftLight,
+        "DIFFERENCE" => BlendMode::Difference,
+        "EXCLUSION" => BlendMode::Exclusion,
+        "HUE" => BlendMode::Hue,
+        "SATURATION" => BlendMode::Saturation,
+        "COLOR" => BlendMode::Color,
+        "LUMINOSITY" => BlendMode::Luminosity,
+        "LINEAR_DODGE" | "PLUS_LIGHTER" => BlendMode::Plus,
+        _ => return None,
+    })
+}
+
+/// Limit `mask` to where `clip` is set.
+fn intersect(mask: &mut Mask, clip: Option<&Mask>) {
+    if let Some(clip) = clip {
+        for (m, c) in mask.data_mut().iter_mut().zip(clip.data()) {
+            *m = ((*m as u16 * *c as u16) / 255) as u8;
+        }
+    }
+}
+
+fn rounded_rect(width: f64, height: f64, radius: f64) -> Option<Path> {
+    let r = radius.min(width / 2.0).min(height / 2.0).max(0.0) as f32;
+    let (w, h) = (width as f32, height as f32);
+    if r <= 0.0 {
+        return Some(PathBuilder::from_rect(tiny_skia::Rect::from_xywh(0.0, 0.0, w, h)?));
+    }
+
+    // Cubic approximation of a quarter circle
+    let k = r * 0.552_284_8;
+    let mut pb = PathBuilder::new();
+    pb.move_to(r, 0.0);
+    pb.line_to(w - r, 0.0);
+    pb.cubic_to(w - r + k, 0.0, w, r - k, w, r);
+    pb.line_to(w, h - r);
+    pb.cubic_to(w, h - r + k, w - r + k, h, w - r, h);
+    pb.line_to(r, h);
+    pb.cubic_to(r - k, h, 0.0, h - r + k, 0.0, h - r);
+    pb.line_to(0.0, r);
+    pb.cubic_to(0.0, r - k, r - k, 0.0, r, 0.0);
+    pb.close();
+    pb.finish()
+}
+
+/// Geometry of a node in its local coordinate space, with the fill rule.
+pub fn node_geometry(node: &NodeData) -> Vec<(Path, FillRule)> {
+    let (width, height) = (node.width.unwrap_or(0.0), node.height.unwrap_or(0.0));
+
+    if let Some(paths) = node.fitted_vector_paths() {
+        return paths
+            .iter()
+            .filter_map(|vp| Some((build_path(vp)?, fill_rule(vp.winding_rule))))
+            .collect();
+    }
+
+    if width <= 0.0 || height <= 0.0 {
+        return Vec::new();
+    }
+
+    let path = match node.node_type {
+        NodeType::Ellipse => tiny_skia::Rect::from_xywh(0.0, 0.0, width as f32, height as f32)
+            .and_then(PathBuilder::from_oval),
+        NodeType::Frame
+        | NodeType::Component
+        | NodeType::Instance
+        | NodeType::Rectangle
+        | NodeType::Image => rounded_rect(width, height, node.corner_radius.unwrap_or(0.0)),
+        _ => None,
+    };
+
+    path.map(|p| vec![(p, FillRule::Winding)]).unwrap_or_default()
+}
+
+fn gradient_stops(stops: &[crate::model::GradientStop], opacity: f64) -> Vec<GradientStop> {
+    stops
+        .iter()
+        .map(|s| GradientStop::new(s.position.clamp(0.0, 1.0) as f32, color(s.color, opacity)))
+        .collect()
+}
+
+/// Transform from the node's normalized bounding box (0..1) to local space.
+/// tiny-skia applies the draw transform to shaders itself.
+fn unit_transform(bounds: Rect) -> Matrix {
+    multiply(&translate(bounds.x, bounds.y), &scale(bounds.width, bounds.height))
+}
+
+/// Image placement inside `bounds` for a scale mode, in local space.
+fn image_placement(mode: ScaleMode, bounds: Rect, iw: f64, ih: f64) -> Matrix {
+    let (sx, sy) = (bounds.width / iw, bounds.height / ih);
+    let s = match mode {
+        ScaleMode::Fill | ScaleMode::Crop => sx.max(sy),
+        ScaleMode::Fit => sx.min(sy),
+        ScaleMode::Tile => return translate(bounds.x, bounds.y),
+    };
+    [s, 0.0, 0.0, s, bounds.x + (bounds.width - iw * s) / 2.0, bounds.y + (bounds.height - ih * s) / 2.0]
+}
+
+fn stroke_style(node: &NodeData, width: f64) -> Stroke {
+    let mut stroke = Stroke {
+        width: width as f32,
+        miter_limit: node.stroke_miter_limit.unwrap_or(4.0) as f32,
+        line_cap: match node.stroke_cap.as_deref() {
+            Some("ROUND") => LineCap::Round,
+            Some("SQUARE") => LineCap::Square,
+            _ => LineCap::Butt,
+        },
+        line_join: match node.stroke_join.as_deref() {
+            Some("ROUND") => LineJoin::Round,
+            Some("BEVEL") => LineJoin::Bevel,
+            _ => LineJoin::Miter,
+        },
+        dash: None,
+    };
+
+    if let Some(dashes) = node.dash_pattern.as_deref().and_then(dash_array) {
+        stroke.dash = StrokeDash::new(dashes.iter().map(|d| *d as f32).collect(), node.dash_offset.unwrap_or(0.0) as f32);
+    }
+    stroke
+}
+
+/// Node bounds, transform and opacity shared by every paint of a node.
+#[derive(Clone, Copy)]
+struct Target {
+    bounds: Rect,
+    world: Matrix,
+    opacity: f64,
+}
+
+/// How a piece of geometry is drawn with a paint.
+enum Draw<'a> {
+    Fill(FillRule),
+    Stroke(&'a Stroke),
+}
+
+/// A pattern cell as drawn, with the resolution it was drawn at.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct TileKey {
+    source: String,
+    tile_type: TileType,
+    width: u32,
+    height: u32,
+    scaling_factor: u64,
+    spacing: (u64, u64),
+}
+
+struct Painter<'a> {
+    pixmap: Pixmap,
+    images: &'a dyn ImageSource,
+    image_cache: HashMap<String, Option<Pixmap>>,
+    /// Pattern cells, drawn once for every node filled with them.
+    tiles: HashMap<TileKey, Option<Pixmap>>,
+    /// Pattern sources being drawn, so one can't contain itself.
+    tiling: Vec<String>,
+}
+
+impl Painter<'_> {
+    fn cached_image(&mut self, image_ref: &str) -> Option<Pixmap> {
+        if !self.image_cache.contains_key(image_ref) {
+            let decoded = self.images.image(image_ref);
+            self.image_cache.insert(image_ref.to_string(), decoded);
+        }
+        self.image_cache.get(image_ref).cloned().flatten()
+    }
+
+    /// One cell of the pattern `paint` fills `bounds` with, drawn at the
+    /// resolution `world` puts it on the canvas, and the transform from its
+    /// pixels to the node's local space.
+    fn pattern_tile(&mut self, tree: &DocumentTree, paint: &Paint, bounds: Rect, world: &Matrix) -> Option<(Pixmap, Matrix)> {
+        let Paint::Pattern { source_node_id: source, tile_type, scaling_factor, spacing, rotation, .. } = paint else {
+            return None;
+        };
+        let source_bounds = tree.world_bounds(source).filter(|b| b.width > 0.0 && b.height > 0.0)?;
+        let tile = (source_bounds.width * scaling_factor, source_bounds.height * scaling_factor);
+        let (cell, offsets) = tile_type.layout((tile.0 + spacing.x, tile.1 + spacing.y));
+        if !(cell.0 > 0.0 && cell.1 > 0.0) {
+            return None;
+        }
+        // Whole pixels to a cell, so repeats meet exactly
+        let resolution = scale_factor(world);
+        let (width, height) = ((cell.0 * resolution).round().max(1.0), (cell.1 * resolution).round().max(1.0));
+        if width > MAX_DIMENSION as f64 || height > MAX_DIMENSION as f64 {
+            return None;
+        }
+        let (sx, sy) = (width / cell.0, height / cell.1);
+        let to_local = multiply(&compose(bounds.x, bounds.y, rotation.to_radians(), 1.0, 1.0), &scale(1.0 / sx, 1.0 / sy));
+
+        let key = TileKey {
+            source: source.clone(),
+            tile_type: *tile_type,
+            width: width as u32,
+            height: height as u32,
+            scaling_factor: scaling_factor.to_bits(),
+            spacing: (spacing.x.to_bits(), spacing.y.to_bits()),
+        };
+        if !self.tiles.contains_key(&key) {
+            let drawn = if self.tiling.contains(source) { None } else { Pixmap::new(key.width, key.height) };
+            let drawn = drawn.map(|layer| {
+                let outer = std::mem::replace(&mut self.pixmap, layer);
+                let parent = tree.parent(source).map(|p| tree.world_transform(p)).unwrap_or(IDENTITY);
+                let placed = multiply(&scale(*scaling_factor, *scaling_factor), &translate(-source_bounds.x, -source_bounds.y));
+                self.tiling.push(source.clone());
+                for (x, y) in offsets {
+                    let base = multiply(&multiply(&scale(sx, sy), &translate(x, y)), &placed);
+                    self.draw_subtree(tree, source, &multiply(&base, &parent), None);
+                }
+                self.tiling.pop();
+                std::mem::replace(&mut self.pixmap, outer)
+            });
+            self.tiles.insert(key.clone(), drawn);
+        }
+        Some((self.tiles.get(&key).cloned().flatten()?, to_local))
+    }
+
+    fn draw(&mut self, path: &Path, draw: &Draw, shader: Shader, world: &Matrix, mask: Option<&Mask>) {
+        let paint = tiny_skia::Paint { shader, anti_alias: true, ..Default::default() };
+        let transform = to_transform(world);
+        match draw {
+            Draw::Fill(rule) => self.pixmap.fill_path(path, &paint, *rule, transform, mask),
+            Draw::Stroke(stroke) => self.pixmap.stroke_path(path, &paint, stroke, transform, mask),
+        }
+    }
+
+    fn paint_path(&mut self, tree: &DocumentTree, path: &Path, draw: Draw, paint: &Paint, target: &Target, mask: Option<&Mask>) {
+        if !paint.visible() {
+            return;
+        }
+        let Target { bounds, world, opacity } = *target;
+        let world = &world;
+        let alpha = paint.opacity() * opacity;
+        let unit = unit_transform(bounds);
+
+        let shader = match paint {
+            Paint::Solid { color: c, .. } => Some(Shader::SolidColor(color(*c, alpha))),
+            Paint::GradientLinear { gradient_stops: stops, gradient_transform, .. } => LinearGradient::new(
+                Point::from_xy(0.0, 0.5),
+                Point::from_xy(1.0, 0.5),
+                gradient_stops(stops, alpha),
+                SpreadMode::Pad,
+                to_transform(&multiply(&unit, gradient_transform)),
+            ),
+            Paint::GradientRadial { gradient_stops: stops, gradient_transform, .. } => RadialGradient::new(
+                Point::from_xy(0.5, 0.5),
+                Point::from_xy(0.5, 0.5),
+                0.5,
+                gradient_stops(stops, alpha),
+                SpreadMode::Pad,
+                to_transform(&multiply(&unit, gradient_transform)),
+            ),
+            Paint::Image { image_ref, scale_mode, image_transform, .. } => {
+                // Patterns borrow their pixmap, so image and pattern paints
+                // are drawn here directly
+                let Some(image) = self.cached_image(image_ref) else { return };
+                let placement = image_placement(*scale_mode, bounds, image.width() as f64, image.height() as f64);
+                let spread = if *scale_mode == ScaleMode::Tile { SpreadMode::Repeat } else { SpreadMode::Pad };
+                let to_local = multiply(image_transform, &placement);
+                let shader = Pattern::new(
+                    image.as_ref(),
+                    spread,
+                    FilterQuality::Bicubic,
+                    alpha as f32,
+                    to_transform(&to_local),
+                );
+                self.draw(path, &draw, shader, world, mask);
+                return;
+            }
+            Paint::Pattern { .. } => {
+                let Some((tile, to_local)) = self.pattern_tile(tree, paint, bounds, world) else { return };
+                let shader = Pattern::new(tile.as_ref(), SpreadMode::Repeat, FilterQuality::Bicubic, alpha as f32, to_transform(&to_local));
+                self.draw(path, &draw, shader, world, mask);
+                return;
+            }
+        };
+
+        if let Some(shader) = shader {
+            self.draw(path, &draw, shader, world, mask);
+        }
+    }
+
+    fn geometry_mask(&self, geometry: &[(Path, FillRule)], world: &Matrix, clip: Option<&Mask>) -> Option<Mask> {
+        let mut mask = Mask::new(self.pixmap.width(), self.pixmap.height())?;
+        for (path, rule) in geometry {
+            mask.fill_path(path, *rule, true, to_transform(world));
+        }
+        intersect(&mut mask, clip);
+        Some(mask)
+    }
+
+    fn paint_node(&mut self, tree: &DocumentTree, node: &NodeData, geometry: &[(Path, FillRule)], world: &Matrix, opacity: f64, clip: Option<&Mask>) {
+        let bounds = node
+            .local_bounds()
+            .or_else(|| {
+                Rect::from_points(geometry.iter().flat_map(|(p, _)| {
+                    let b = p.bounds();
+                    [(b.left() as f64, b.top() as f64), (b.right() as f64, b.bottom() as f64)]
+                }))
+            })
+            .unwrap_or(Rect::new(0.0, 0.0, 1.0, 1.0));
+        let target = Target { bounds, world: *world, opacity };
+
+        for paint in node.fills() {
+            for (path, rule) in geometry {
+                self.paint_path(tree, path, Draw::Fill(*rule), paint, &target, clip);
+            }
+        }
+
+        let weight = node.stroke_weight();
+        if weight <= 0.0 || node.strokes().is_empty() {
+            return;
+        }
+
+        // Inside/outside strokes are drawn at double width and masked to one side of the outline
+        let (stroke, side_mask) = match node.stroke_align.as_deref() {
+            Some("INSIDE") => (stroke_style(node, weight * 2.0), self.geometry_mask(geometry, world, clip)),
+            Some("OUTSIDE") => {
+                let mut mask = self.geometry_mask(geometry, world, None);
+                if let Some(mask) = mask.as_mut() {
+                    mask.invert();
+                    intersect(mask, clip);
+                }
+                (stroke_style(node, weight * 2.0), mask)
+            }
+            _ => (stroke_style(node, weight), None),
+        };
+        let mask = side_mask.as_ref().or(clip);
+
+        // Markers cut the stroke back and are filled with its paints
+        let marked = marked_stroke(node).map(|m| {
+            let lines: Vec<Path> = m.paths.iter().filter_map(build_path).collect();
+            (lines, build_path(&m.heads))
+        });
+        let lines: Vec<&Path> = match &marked {
+            Some((lines, _)) => lines.iter().collect(),
+            None => geometry.iter().map(|(path, _)| path).collect(),
+        };
+
+        for paint in node.strokes() {
+            for path in &lines {
+                self.paint_path(tree, path, Draw::Stroke(&stroke), paint, &target, mask);
+            }
+            if let Some((_, Some(heads))) = &marked {
+                self.paint_path(tree, heads, Draw::Fill(FillRule::Winding), paint, &target, clip);
+            }
+        }
+    }
+
+    /// Blur what is already drawn behind `node`, within its shape.
+    fn blur_backdrop(&mut self, node: &NodeData, world: &Matrix, radius: f64, clip: Option<&Mask>) {
+        let Some(mask) = self.geometry_mask(&node_geometry(node), world, clip) else { return };
+        let mut backdrop = self.pixmap.clone();
+        blur(&mut backdrop, radius);
+        let paint = PixmapPaint { blend_mode: BlendMode::Source, ..Default::default() };
+        self.pixmap.draw_pixmap(0, 0, backdrop.as_ref(), &paint, Transform::identity(), Some(&mask));
+    }
+
+    /// Coverage of a mask node for the siblings above it.
+    fn node_mask(&mut self, tree: &DocumentTree, node: &NodeData, parent: &Matrix, clip: Option<&Mask>) -> Option<Mask> {
+        let world = multiply(parent, &node.local_transform());
+        let mask_type = match node.mask_type.as_deref() {
+            Some("VECTOR") => return self.geometry_mask(&node_geometry(node), &world, clip),
+            Some("LUMINANCE") => MaskType::Luminance,
+            _ => MaskType::Alpha,
+        };
+        let layer = Pixmap::new(self.pixmap.width(), self.pixmap.height())?;
+        let outer = std::mem::replace(&mut self.pixmap, layer);
+        self.draw_node(tree, node, &world, None);
+        let layer = std::mem::replace(&mut self.pixmap, outer);
+        let mut mask = Mask::from_pixmap(layer.as_ref(), mask_type);
+        intersect(&mut mask, clip);
+        Some(mask)
+    }
+
+    fn draw_subtree(&mut self, tree: &DocumentTree, id: &str, parent: &Matrix, clip: Option<&Mask>) {
+        let Some(node) = tree.get(id) else { return };
+        if !node.visible {
+            return;
+        }
+        let world = multiply(parent, &node.local_transform());
+        self.draw_node(tree, node, &world, clip);
+    }
+
+    fn draw_node(&mut self, tree: &DocumentTree, node: &NodeData, world: &Matrix, clip: Option<&Mask>) {
+        let children = tree.children(&node.id);
+        let opacity = node.opacity();
+        let effects: Vec<Effect> =
+            parse_effects(node.effects.as_deref().unwrap_or_default()).iter().map(|e| to_device(e, world)).collect();
+        let blend = blend_mode(node.blend_mode.as_deref());
+
+        for effect in effects.iter().filter(|e| e.kind == EffectKind::BackgroundBlur) {
+            self.blur_backdrop(node, world, effect.radius, clip);
+        }
+
+        // Containers with partial opacity, blended nodes and nodes with
+        // effects are composited as a single layer
+        let layered = effects.iter().any(|e| e.kind != EffectKind::BackgroundBlur);
+        if (opacity < 1.0 && !children.is_empty()) || blend.is_some() || layered {
+            let Some(layer) = Pixmap::new(self.pixmap.width(), self.pixmap.height()) else { return };
+            let outer = std::mem::replace(&mut self.pixmap, layer);
+            let mut plain = node.clone();
+            plain.opacity = None;
+            self.draw_contents(tree, &plain, children, world, clip);
+            let layer = std::mem::replace(&mut self.pixmap, outer);
+            let layer = composite(layer, &effects);
+            let paint = PixmapPaint {
+                opacity: opacity as f32,
+                blend_mode: blend.unwrap_or(BlendMode::SourceOver),
+                ..Default::default()
+            };
+            self.pixmap.draw_pixmap(0, 0, layer.as_ref(), &paint, Transform::identity(), clip);
+            return;
+        }
+
+        self.draw_contents(tree, node, children, world, clip);
+    }
+
+    /// Text drawn with the outlines of its fonts, one vector per fill.
+    fn draw_text(&mut self, tree: &DocumentTree, node: &NodeData, world: &Matrix, opacity: f64, clip: Option<&Mask>) {
+        // Text whose fonts are missing is left out, as in SVG export's outlines
+        let Ok(outlined) = outline_text(node) else { return };
+        for vector in outlined.nodes.iter().filter(|n| n.node_type == NodeType::Vector) {
+            let placed = multiply(world, &vector.local_transform());
+            let geometry = node_geometry(vector);
+            self.paint_node(tree, vector, &geometry, &placed, opacity, clip);
+        }
+    }
+
+    fn draw_contents(
+        &mut self,
+        tree: &DocumentTree,
+        node: &NodeData,
+        children: &[String],
+        world: &Matrix,
+        clip: Option<&Mask>,
+    ) {
+        let opacity = node.opacity();
+        if node.node_type == NodeType::Text {
+            self.draw_text(tree, node, world, opacity, clip);
+        } else if node.node_type == NodeType::Image && node.fills().is_empty() {
+            if let Some(image_ref) = &node.image_ref {
+                let mut image_node = node.clone();
+                image_node.fills = Some(vec![Paint::Image {
+                    visible: true,
+                    opacity: 1.0,
+                    image_ref: image_ref.clone(),
+                    scale_mode: node.scale_mode.unwrap_or_default(),
+                    image_transform: IDENTITY,
+                }]);
+                let geometry = node_geometry(&image_node);
+                self.paint_node(tree, &image_node, &geometry, world, opacity, clip);
+            }
+        } else {
+            let geometry = node_geometry(node);
+            self.paint_node(tree, node, &geometry, world, opacity, clip);
+        }
+
+        if children.is_empty() {
+            return;
+        }
+
+        let child_clip = if node.clips_content.unwrap_or(false) {
+            let geometry = node_geometry(node);
+            self.geometry_mask(&geometry, world, clip)
+        } else {
+            None
+        };
+        let clip = child_clip.as_ref().or(clip);
+
+        // A mask hides what is outside it in the siblings above, up to the
+        // next mask
+        let mut mask: Option<Mask> = None;
+        for child in children {
+            match tree.get(child) {
+                Some(node) if node.is_mask == Some(true) => {
+                    mask = if node.visible { self.node_mask(tree, node, world, clip) } else { None };
+                }
+                _ => self.draw_subtree(tree, child, world, mask.as_ref().or(clip)),
+            }
+        }
+    }
+}
+
+pub struct RenderOptions {
+    pub scale: f64,
+    /// Painted under the content; pages default to their background color.
+    pub background: Option<Rgba>,
+}
+
+/// Rasterize `id` and its descendants, cropped to its world bounds.
+pub fn render_node(tree: &DocumentTree, id: &str, options: &RenderOptions, images: &dyn ImageSource) -> Result<Pixmap, String> {
+    let node = tree.get(id).ok_or_else(|| format!("Unknown node: {}", id))?;
+    let bounds = tree
+        .world_bounds(id)
+        .filter(|b| b.width > 0.0 && b.height > 0.0)
+        .ok_or_else(|| format!("Node {} has no visible area", id))?;
+
+    if !(options.scale > 0.0 && options.scale.is_finite()) {
+        return Err(format!("Invalid render scale: {}", options.scale));
+    }
+
+    let width = (bounds.width * options.scale).ceil();
+    let height = (bounds.height * options.scale).ceil();
+    if width > MAX_DIMENSION as f64 || height > MAX_DIMENSION as f64 {
+        return Err(format!(
+            "Render size {}x{} exceeds the {}px limit",
+            width, height, MAX_DIMENSION
+        ));
+    }
+
+    let mut pixmap = Pixmap::new(width.max(1.0) as u32, height.max(1.0) as u32)
+        .ok_or_else(|| "Failed to allocate render target".to_string())?;
+
+    let background = options.background.or(match node.node_type {
+        NodeType::Page => node.background_color,
+        _ => None,
+    });
+    if let Some(bg) = background {
+        pixmap.fill(color(bg, 1.0));
+    }
+
+    let base = multiply(&scale(options.scale, options.scale), &translate(-bounds.x, -bounds.y));
+    let parent_world = tree.parent(id).map(|p| tree.world_transform(p)).unwrap_or(IDENTITY);
+
+    let mut painter = Painter { pixmap, images, image_cache: HashMap::new(), tiles: HashMap::new(), tiling: Vec::new() };
+    painter.draw_subtree(tree, id, &multiply(&base, &parent_world), None);
+
+    Ok(painter.pixmap)
+}
+
+/// Rasterize `id` scaled to fit within `max_width` x `max_height`.
+pub fn render_to_fit(
+    tree: &DocumentTree,
+    id: &str,
+    max_width: u32,
+    max_height: u32,
+    images: &dyn ImageSource,
+) -> Result<Pixmap, String> {
+    let bounds = tree
+        .world_bounds(id)
+        .filter(|b| b.width > 0.0 && b.height > 0.0)
+        .ok_or_else(|| format!("Node {} has no visible area", id))?;
+    let scale = (max_width as f64 / bounds.width).min(max_height as f64 / bounds.height);
+
+    render_node(tree, id, &RenderOptions { scale, background: None }, images)
+}
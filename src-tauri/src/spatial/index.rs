@@ -0,0 +1,230 @@
+//! The scene's spatial index, kept in the backend so picking and marquee
+//! selection only look at the nodes near the pointer. Bounds narrow the
+//! search through an R-tree, then each node's outline in world space
+//! decides the hit exactly, curves and all.
+
+use super::rtree::{Entry, RTree};
+use crate::geometry::{multiply, scale_factor, to_bez_path, Matrix, Rect, IDENTITY};
+use crate::model::{DocumentTree, NodeData, NodeType, WindingRule};
+use kurbo::{Affine, BezPath, Ellipse, Line, ParamCurve, ParamCurveNearest, Point, RoundedRect, Shape};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use tauri::{command, State};
+
+/// Accuracy of curve distances and of shapes built from primitives.
+const ACCURACY: f64 = 1e-3;
+
+/// A node's outline in world space.
+struct Outline {
+    bounds: Rect,
+    /// Paint order; higher is drawn later, on top.
+    order: usize,
+    paths: Vec<(BezPath, WindingRule)>,
+    /// Whether the inside counts, rather than only the stroke.
+    filled: bool,
+    /// Half the stroke width, in world units.
+    stroke: f64,
+}
+
+impl Outline {
+    fn of(node: &NodeData, world: &Matrix, order: usize) -> Option<Outline> {
+        let (width, height) = (node.width.unwrap_or(0.0), node.height.unwrap_or(0.0));
+        let local: Vec<(BezPath, WindingRule)> = match (node.fitted_vector_paths(), node.node_type) {
+            (Some(paths), _) => paths.iter().map(|p| (to_bez_path(p), p.winding_rule)).collect(),
+            (None, _) if width <= 0.0 && height <= 0.0 => return None,
+            (None, NodeType::Ellipse) => {
+                vec![(Ellipse::from_rect(kurbo::Rect::new(0.0, 0.0, width, height)).to_path(ACCURACY), WindingRule::Nonzero)]
+            }
+            (None, _) => {
+                let radius = node.corner_radius.unwrap_or(0.0).min(width / 2.0).min(height / 2.0).max(0.0);
+                vec![(RoundedRect::new(0.0, 0.0, width, height, radius).to_path(ACCURACY), WindingRule::Nonzero)]
+            }
+        };
+
+        let affine = Affine::new(*world);
+        let paths: Vec<(BezPath, WindingRule)> = local.into_iter().map(|(p, rule)| (affine * p, rule)).collect();
+        let has_strokes = node.strokes().iter().any(|p| p.visible());
+        let stroke = if has_strokes { node.stroke_weight() * scale_factor(world) / 2.0 } else { 0.0 };
+        // Containers, text and images are picked anywhere inside, shapes
+        // only where painted
+        let filled = node.fills().iter().any(|p| p.visible())
+            || matches!(node.node_type, NodeType::Frame | NodeType::Component | NodeType::Instance | NodeType::Text | NodeType::Image);
+        let bounds = paths
+            .iter()
+            .map(|(p, _)| p.bounding_box())
+            .reduce(|a, b| a.union(b))
+            .map(|b| Rect::new(b.x0 - stroke, b.y0 - stroke, b.width() + 2.0 * stroke, b.height() + 2.0 * stroke))?;
+        Some(Outline { bounds, order, paths, filled, stroke })
+    }
+
+    fn inside(&self, p: Point) -> bool {
+        self.paths.iter().any(|(path, rule)| match rule {
+            WindingRule::Nonzero => path.winding(p) != 0,
+            WindingRule::Evenodd => path.winding(p) % 2 != 0,
+        })
+    }
+
+    fn hit(&self, p: Point, tolerance: f64) -> bool {
+        if self.filled && self.inside(p) {
+            return true;
+        }
+        let reach = self.stroke + tolerance;
+        reach > 0.0
+            && self
+                .paths
+                .iter()
+                .flat_map(|(path, _)| path.segments())
+                .any(|seg| seg.nearest(p, ACCURACY).distance_sq <= reach * reach)
+    }
+
+    /// Whether any of the outline, or of its inside, lies within `area`.
+    fn touches(&self, area: &Rect) -> bool {
+        let corners = [
+            Point::new(area.x, area.y),
+            Point::new(area.right(), area.y),
+            Point::new(area.right(), area.bottom()),
+            Point::new(area.x, area.bottom()),
+        ];
+        let edges: Vec<Line> = (0..4).map(|i| Line::new(corners[i], corners[(i + 1) % 4])).collect();
+        let within = |p: Point| area.x <= p.x && p.x <= area.right() && area.y <= p.y && p.y <= area.bottom();
+        self.paths.iter().flat_map(|(path, _)| path.segments()).any(|seg| {
+            within(seg.start()) || within(seg.end()) || edges.iter().any(|edge| !seg.intersect_line(*edge).is_empty())
+        }) || (self.filled && self.inside(corners[0]))
+    }
+}
+
+#[derive(Default)]
+struct IndexState {
+    tree: RTree<String>,
+    outlines: HashMap<String, Outline>,
+    next_order: usize,
+}
+
+impl IndexState {
+    fn remove(&mut self, id: &str) {
+        if let Some(outline) = self.outlines.remove(id) {
+            self.tree.remove(&outline.bounds, |v| v == id);
+        }
+    }
+
+    fn insert(&mut self, node: &NodeData, world: &Matrix, order: usize) {
+        self.remove(&node.id);
+        if let Some(outline) = Outline::of(node, world, order) {
+            self.tree.insert(outline.bounds, node.id.clone());
+            self.outlines.insert(node.id.clone(), outline);
+        }
+    }
+
+    /// Ids of the outlines `keep` accepts among those whose bounds meet
+    /// `area`, topmost first.
+    fn query(&self, area: &Rect, keep: impl Fn(&Outline) -> bool) -> Vec<String> {
+        let mut found: Vec<(usize, &String)> = self
+            .tree
+            .search(area)
+            .into_iter()
+            .filter_map(|e| Some((self.outlines.get(&e.value)?, &e.value)))
+            .filter(|(outline, _)| keep(outline))
+            .map(|(outline, id)| (outline.order, id))
+            .collect();
+        found.sort_by_key(|(order, _)| std::cmp::Reverse(*order));
+        found.into_iter().map(|(_, id)| id.clone()).collect()
+    }
+}
+
+/// Index of the nodes of the open document, managed as app state.
+#[derive(Default)]
+pub struct SpatialIndex {
+    state: Mutex<IndexState>,
+}
+
+impl SpatialIndex {
+    fn lock(&self) -> MutexGuard<'_, IndexState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Outline `id` and everything under it, in paint order. Hidden and locked
+/// nodes can't be picked, and neither can anything inside them; groups
+/// are picked through their children.
+fn index_tree(state: &mut IndexState, tree: &DocumentTree, id: &str, parent: &Matrix) {
+    let Some(node) = tree.get(id) else { return };
+    if !node.visible || node.locked {
+        return;
+    }
+    let world = multiply(parent, &node.local_transform());
+    if !matches!(node.node_type, NodeType::Document | NodeType::Page | NodeType::Group) {
+        if let Some(outline) = Outline::of(node, &world, state.next_order) {
+            state.outlines.insert(node.id.clone(), outline);
+        }
+        state.next_order += 1;
+    }
+    for child in tree.children(id) {
+        index_tree(state, tree, child, &world);
+    }
+}
+
+/// A node that changed, with its world transform.
+#[derive(Deserialize)]
+pub struct IndexedNode {
+    pub node: NodeData,
+    pub transform: Matrix,
+}
+
+/// Rebuild the index from `document_json`, a serialized document.
+#[command]
+pub fn set_spatial_index(index: State<'_, SpatialIndex>, document_json: String) -> Result<(), String> {
+    let tree = DocumentTree::parse(&document_json)?;
+    let mut rebuilt = IndexState::default();
+    index_tree(&mut rebuilt, &tree, &tree.root_id, &IDENTITY);
+    rebuilt.tree = RTree::new(rebuilt.outlines.iter().map(|(id, o)| Entry { bounds: o.bounds, value: id.clone() }).collect());
+    *index.lock() = rebuilt;
+    Ok(())
+}
+
+/// Update the nodes in `nodes` and drop those in `removed`. Nodes already
+/// indexed keep their place in paint order, and new ones go on top;
+/// reordering takes a rebuild with `set_spatial_index`.
+#[command]
+pub fn update_spatial_index(index: State<'_, SpatialIndex>, nodes: Vec<IndexedNode>, removed: Vec<String>) -> Result<(), String> {
+    let mut state = index.lock();
+    for id in &removed {
+        state.remove(id);
+    }
+    for IndexedNode { node, transform } in &nodes {
+        let order = match state.outlines.get(&node.id) {
+            Some(outline) => outline.order,
+            None => {
+                state.next_order += 1;
+                state.next_order - 1
+            }
+        };
+        if !node.visible || node.locked || node.node_type == NodeType::Group {
+            state.remove(&node.id);
+        } else {
+            state.insert(node, transform, order);
+        }
+    }
+    Ok(())
+}
+
+/// Nodes under the point `x`, `y`, topmost first. Strokes, and outlines
+/// of unfilled shapes, are hit within `tolerance` of the line.
+#[command]
+pub fn nodes_at_point(index: State<'_, SpatialIndex>, x: f64, y: f64, tolerance: Option<f64>) -> Result<Vec<String>, String> {
+    let tolerance = tolerance.unwrap_or(0.0);
+    if !(tolerance.is_finite() && tolerance >= 0.0) {
+        return Err(format!("Invalid tolerance: {}", tolerance));
+    }
+    let area = Rect::new(x - tolerance, y - tolerance, 2.0 * tolerance, 2.0 * tolerance);
+    Ok(index.lock().query(&area, |outline| outline.hit(Point::new(x, y), tolerance)))
+}
+
+/// Nodes a marquee over `rect` selects, topmost first: those it touches,
+/// or with `contained` only those wholly inside it.
+#[command]
+pub fn nodes_in_rect(index: State<'_, SpatialIndex>, rect: Rect, contained: Option<bool>) -> Result<Vec<String>, String> {
+    let rect = Rect::new(rect.x.min(rect.right()), rect.y.min(rect.bottom()), rect.width.abs(), rect.height.abs());
+    let contained = contained.unwrap_or(false);
+    Ok(index.lock().query(&rect, |outline| if contained { rect.contains(&outline.bounds) } else { outline.touches(&rect) }))
+}
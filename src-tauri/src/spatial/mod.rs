@@ -0,0 +1,7 @@
+//! Spatial queries over the scene: an R-tree of node bounds, the index of
+//! the open document kept with it for picking, and the snap guides worked
+//! out while nodes are dragged.
+
+pub mod index;
+pub mod rtree;
+pub mod snapping;
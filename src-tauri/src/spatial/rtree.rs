@@ -0,0 +1,206 @@
+//! An R-tree over bounding rectangles, for finding what lies in a region
+//! of a scene without looking at every node. Trees are packed by
+//! sort-tile-recursive when built from a list, and stay balanced as
+//! entries are inserted and removed.
+
+use crate::geometry::Rect;
+
+/// Entries per node before it splits.
+const MAX_ENTRIES: usize = 16;
+
+pub struct Entry<T> {
+    pub bounds: Rect,
+    pub value: T,
+}
+
+enum Children<T> {
+    Leaf(Vec<Entry<T>>),
+    Branch(Vec<Node<T>>),
+}
+
+struct Node<T> {
+    bounds: Option<Rect>,
+    children: Children<T>,
+}
+
+fn union_all(rects: impl IntoIterator<Item = Rect>) -> Option<Rect> {
+    rects.into_iter().reduce(|a, b| a.union(&b))
+}
+
+fn area(r: &Rect) -> f64 {
+    r.width * r.height
+}
+
+impl<T> Node<T> {
+    fn leaf(entries: Vec<Entry<T>>) -> Self {
+        Node { bounds: union_all(entries.iter().map(|e| e.bounds)), children: Children::Leaf(entries) }
+    }
+
+    fn branch(nodes: Vec<Node<T>>) -> Self {
+        Node { bounds: union_all(nodes.iter().filter_map(|n| n.bounds)), children: Children::Branch(nodes) }
+    }
+
+    fn refit(&mut self) {
+        self.bounds = match &self.children {
+            Children::Leaf(entries) => union_all(entries.iter().map(|e| e.bounds)),
+            Children::Branch(nodes) => union_all(nodes.iter().filter_map(|n| n.bounds)),
+        };
+    }
+
+    fn search<'a>(&'a self, area: &Rect, found: &mut Vec<&'a Entry<T>>) {
+        if !self.bounds.is_some_and(|b| b.intersects(area)) {
+            return;
+        }
+        match &self.children {
+            Children::Leaf(entries) => found.extend(entries.iter().filter(|e| e.bounds.intersects(area))),
+            Children::Branch(nodes) => nodes.iter().for_each(|n| n.search(area, found)),
+        }
+    }
+
+    /// Add `entry` under this node, returning the second half if the node
+    /// had to split.
+    fn insert(&mut self, entry: Entry<T>) -> Option<Node<T>> {
+        self.bounds = Some(match self.bounds {
+            Some(b) => b.union(&entry.bounds),
+            None => entry.bounds,
+        });
+        match &mut self.children {
+            Children::Leaf(entries) => {
+                entries.push(entry);
+                (entries.len() > MAX_ENTRIES).then(|| {
+                    let half = split(entries, |e| e.bounds);
+                    self.bounds = union_all(entries.iter().map(|e| e.bounds));
+                    Node::leaf(half)
+                })
+            }
+            Children::Branch(nodes) if nodes.is_empty() => {
+                nodes.push(Node::leaf(vec![entry]));
+                None
+            }
+            Children::Branch(nodes) => {
+                // The child that grows least to take it, then the smallest
+                let growth = |n: &Node<T>| {
+                    let b = n.bounds.unwrap_or(entry.bounds);
+                    (area(&b.union(&entry.bounds)) - area(&b), area(&b))
+                };
+                let best = (0..nodes.len()).min_by(|&a, &b| growth(&nodes[a]).partial_cmp(&growth(&nodes[b])).unwrap_or(std::cmp::Ordering::Equal))?;
+                let extra = nodes[best].insert(entry)?;
+                nodes.push(extra);
+                (nodes.len() > MAX_ENTRIES).then(|| {
+                    let half = split(nodes, |n| n.bounds.unwrap_or(Rect::new(0.0, 0.0, 0.0, 0.0)));
+                    self.bounds = union_all(nodes.iter().filter_map(|n| n.bounds));
+                    Node::branch(half)
+                })
+            }
+        }
+    }
+
+    /// Take out the first entry with `bounds` that `matches`.
+    fn remove(&mut self, bounds: &Rect, matches: &mut impl FnMut(&T) -> bool) -> Option<Entry<T>> {
+        if !self.bounds.is_some_and(|b| b.contains(bounds)) {
+            return None;
+        }
+        let removed = match &mut self.children {
+            Children::Leaf(entries) => {
+                let i = entries.iter().position(|e| e.bounds == *bounds && matches(&e.value))?;
+                Some(entries.swap_remove(i))
+            }
+            Children::Branch(nodes) => {
+                let (i, removed) = nodes.iter_mut().enumerate().find_map(|(i, n)| Some((i, n.remove(bounds, matches)?)))?;
+                if nodes[i].bounds.is_none() {
+                    nodes.swap_remove(i);
+                }
+                Some(removed)
+            }
+        };
+        self.refit();
+        removed
+    }
+}
+
+/// Split `items` in two along the axis they spread furthest over, keeping
+/// the first half in place and returning the second.
+fn split<I>(items: &mut Vec<I>, bounds: impl Fn(&I) -> Rect) -> Vec<I> {
+    let centers = || items.iter().map(|i| bounds(i).center());
+    let spread = |axis: fn((f64, f64)) -> f64| {
+        let (lo, hi) = centers().map(axis).fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v)));
+        hi - lo
+    };
+    let axis: fn((f64, f64)) -> f64 = if spread(|c| c.0) >= spread(|c| c.1) { |c| c.0 } else { |c| c.1 };
+    items.sort_by(|a, b| axis(bounds(a).center()).total_cmp(&axis(bounds(b).center())));
+    items.split_off(items.len() / 2)
+}
+
+/// Pack `items` into nodes of up to `MAX_ENTRIES` by sort-tile-recursive:
+/// sorted into vertical slices by x, then each slice into runs by y.
+fn pack<I>(mut items: Vec<I>, bounds: impl Fn(&I) -> Rect) -> Vec<Vec<I>> {
+    let count = items.len().div_ceil(MAX_ENTRIES);
+    let slices = (count as f64).sqrt().ceil() as usize;
+    let per_slice = slices.max(1) * MAX_ENTRIES;
+    items.sort_by(|a, b| bounds(a).center().0.total_cmp(&bounds(b).center().0));
+    let mut groups = Vec::with_capacity(count);
+    while !items.is_empty() {
+        let rest = items.split_off(per_slice.min(items.len()));
+        let mut slice = std::mem::replace(&mut items, rest);
+        slice.sort_by(|a, b| bounds(a).center().1.total_cmp(&bounds(b).center().1));
+        while !slice.is_empty() {
+            let rest = slice.split_off(MAX_ENTRIES.min(slice.len()));
+            groups.push(std::mem::replace(&mut slice, rest));
+        }
+    }
+    groups
+}
+
+pub struct RTree<T> {
+    root: Node<T>,
+}
+
+impl<T> Default for RTree<T> {
+    fn default() -> Self {
+        RTree { root: Node::leaf(Vec::new()) }
+    }
+}
+
+impl<T> RTree<T> {
+    /// A tree holding `entries`, packed so queries touch as few nodes as
+    /// possible.
+    pub fn new(entries: Vec<Entry<T>>) -> Self {
+        let mut level: Vec<Node<T>> = pack(entries, |e| e.bounds).into_iter().map(Node::leaf).collect();
+        while level.len() > 1 {
+            level = pack(level, |n| n.bounds.unwrap_or(Rect::new(0.0, 0.0, 0.0, 0.0))).into_iter().map(Node::branch).collect();
+        }
+        RTree { root: level.pop().unwrap_or_else(|| Node::leaf(Vec::new())) }
+    }
+
+    pub fn bounds(&self) -> Option<Rect> {
+        self.root.bounds
+    }
+
+    /// Entries whose bounds overlap or touch `area`.
+    pub fn search(&self, area: &Rect) -> Vec<&Entry<T>> {
+        let mut found = Vec::new();
+        self.root.search(area, &mut found);
+        found
+    }
+
+    pub fn insert(&mut self, bounds: Rect, value: T) {
+        if let Some(sibling) = self.root.insert(Entry { bounds, value }) {
+            let root = std::mem::replace(&mut self.root, Node::leaf(Vec::new()));
+            self.root = Node::branch(vec![root, sibling]);
+        }
+    }
+
+    /// Remove an entry with exactly `bounds` whose value `matches`.
+    pub fn remove(&mut self, bounds: &Rect, mut matches: impl FnMut(&T) -> bool) -> Option<T> {
+        let removed = self.root.remove(bounds, &mut matches)?;
+        // A root left with one child hands over to it
+        while let Children::Branch(nodes) = &mut self.root.children {
+            if nodes.len() != 1 {
+                break;
+            }
+            let Some(only) = nodes.pop() else { break };
+            self.root = only;
+        }
+        Some(removed.value)
+    }
+}
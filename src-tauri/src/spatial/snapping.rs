@@ -0,0 +1,252 @@
+//! Smart guides: where a node being moved could snap to the edges and
+//! centers of the nodes around it, or to the spacing between them. Only
+//! the nodes near the moving one are looked at, through an R-tree, so
+//! dense artboards stay responsive while dragging.
+
+use super::rtree::{Entry, RTree};
+use crate::geometry::Rect;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use tauri::command;
+
+/// Distance within which a guide snaps, in canvas units, matching the
+/// webview's snap aperture.
+const DEFAULT_THRESHOLD: f64 = 10.0;
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SnapAxis {
+    X,
+    Y,
+}
+
+impl SnapAxis {
+    fn across(self) -> SnapAxis {
+        match self {
+            SnapAxis::X => SnapAxis::Y,
+            SnapAxis::Y => SnapAxis::X,
+        }
+    }
+
+    /// Start and end of `r` along this axis.
+    fn span(self, r: &Rect) -> (f64, f64) {
+        match self {
+            SnapAxis::X => (r.x, r.right()),
+            SnapAxis::Y => (r.y, r.bottom()),
+        }
+    }
+
+    /// The rect covering `along` on this axis and `across` on the other.
+    fn rect(self, along: (f64, f64), across: (f64, f64)) -> Rect {
+        let (a, b) = match self {
+            SnapAxis::X => (along, across),
+            SnapAxis::Y => (across, along),
+        };
+        Rect::new(a.0, b.0, a.1 - a.0, b.1 - b.0)
+    }
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SnapKind {
+    /// An edge lines up with another node's edge.
+    Edge,
+    /// A center lines up with another node's center or edge.
+    Center,
+    /// The node sits at a spacing already used between its neighbours.
+    Gap,
+}
+
+/// A node in view that can be snapped to.
+#[derive(Deserialize)]
+pub struct SnapTarget {
+    pub id: String,
+    pub bounds: Rect,
+}
+
+/// A place the moving node can snap to along one axis.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapCandidate {
+    pub axis: SnapAxis,
+    pub kind: SnapKind,
+    /// How far to move the node along `axis` to snap.
+    pub offset: f64,
+    /// Where the guide line lies along `axis`, or for gaps where the
+    /// node's leading edge ends up.
+    pub position: f64,
+    /// Extent of the guide across the other axis.
+    pub start: f64,
+    pub end: f64,
+    /// The spacing matched, for gaps.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spacing: Option<f64>,
+    /// Nodes the guide is drawn from.
+    pub node_ids: Vec<String>,
+}
+
+/// Start, center and end of a span, as lines to snap.
+fn anchors((start, end): (f64, f64)) -> [(f64, bool); 3] {
+    [(start, false), ((start + end) / 2.0, true), (end, false)]
+}
+
+/// Add `candidate`, folding it into one already found with the same snap.
+fn push(found: &mut Vec<SnapCandidate>, candidate: SnapCandidate) {
+    let same = found.iter_mut().find(|c| {
+        c.axis == candidate.axis
+            && c.kind == candidate.kind
+            && (c.offset - candidate.offset).abs() < 1e-6
+            && (c.position - candidate.position).abs() < 1e-6
+    });
+    match same {
+        Some(c) => {
+            c.start = c.start.min(candidate.start);
+            c.end = c.end.max(candidate.end);
+            for id in candidate.node_ids {
+                if !c.node_ids.contains(&id) {
+                    c.node_ids.push(id);
+                }
+            }
+        }
+        None => found.push(candidate),
+    }
+}
+
+/// Edges and centers of the nodes in `tree` within `threshold` of those of
+/// `moving` along `axis`, looking across the whole of `view`.
+fn align(tree: &RTree<&str>, moving: &Rect, axis: SnapAxis, view: &Rect, threshold: f64, found: &mut Vec<SnapCandidate>) {
+    let (start, end) = axis.span(moving);
+    let strip = axis.rect((start - threshold, end + threshold), axis.across().span(view));
+    let moving_across = axis.across().span(moving);
+    for entry in tree.search(&strip) {
+        let across = axis.across().span(&entry.bounds);
+        for (target, target_center) in anchors(axis.span(&entry.bounds)) {
+            for (from, from_center) in anchors((start, end)) {
+                let offset = target - from;
+                if offset.abs() > threshold {
+                    continue;
+                }
+                let kind = if target_center || from_center { SnapKind::Center } else { SnapKind::Edge };
+                push(
+                    found,
+                    SnapCandidate {
+                        axis,
+                        kind,
+                        offset,
+                        position: target,
+                        start: across.0.min(moving_across.0),
+                        end: across.1.max(moving_across.1),
+                        spacing: None,
+                        node_ids: vec![entry.value.to_string()],
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// Positions along `axis` that centre `moving` between its neighbours in
+/// line with it, or repeat a gap between other nodes in that line.
+fn space(tree: &RTree<&str>, moving: &Rect, axis: SnapAxis, view: &Rect, threshold: f64, found: &mut Vec<SnapCandidate>) {
+    let (start, end) = axis.span(moving);
+    let length = end - start;
+    let band = axis.across().span(moving);
+    let mut row: Vec<&Entry<&str>> = tree
+        .search(&axis.rect(axis.span(view), band))
+        .into_iter()
+        .filter(|e| {
+            let (lo, hi) = axis.across().span(&e.bounds);
+            lo.max(band.0) < hi.min(band.1)
+        })
+        .collect();
+    row.sort_by(|a, b| axis.span(&a.bounds).0.total_cmp(&axis.span(&b.bounds).0));
+
+    // Nearest neighbours on either side, allowing for the node being
+    // within the threshold of touching them
+    let before = row
+        .iter()
+        .filter(|e| axis.span(&e.bounds).1 <= start + threshold && axis.span(&e.bounds).1 < end)
+        .max_by(|a, b| axis.span(&a.bounds).1.total_cmp(&axis.span(&b.bounds).1));
+    let after = row
+        .iter()
+        .filter(|e| axis.span(&e.bounds).0 >= end - threshold && axis.span(&e.bounds).0 > start)
+        .min_by(|a, b| axis.span(&a.bounds).0.total_cmp(&axis.span(&b.bounds).0));
+
+    let mut gap = |position: f64, spacing: f64, ids: &[&str]| {
+        let offset = position - start;
+        if offset.abs() <= threshold && spacing >= 0.0 {
+            let span = ids
+                .iter()
+                .filter_map(|id| row.iter().find(|e| e.value == *id))
+                .map(|e| axis.across().span(&e.bounds))
+                .fold(band, |a, b| (a.0.min(b.0), a.1.max(b.1)));
+            push(
+                found,
+                SnapCandidate {
+                    axis,
+                    kind: SnapKind::Gap,
+                    offset,
+                    position,
+                    start: span.0,
+                    end: span.1,
+                    spacing: Some(spacing),
+                    node_ids: ids.iter().map(|id| id.to_string()).collect(),
+                },
+            );
+        }
+    };
+
+    if let (Some(before), Some(after)) = (before, after) {
+        let (left, right) = (axis.span(&before.bounds).1, axis.span(&after.bounds).0);
+        let position = (left + right - length) / 2.0;
+        gap(position, position - left, &[before.value, after.value]);
+    }
+
+    // Gaps between consecutive nodes in line, other than the one the
+    // moving node is in
+    let mut seen = HashSet::new();
+    for pair in row.windows(2) {
+        let (a, b) = (axis.span(&pair[0].bounds), axis.span(&pair[1].bounds));
+        let spacing = b.0 - a.1;
+        let center = (start + end) / 2.0;
+        if spacing < 0.0 || (a.1 <= center && center <= b.0) || !seen.insert(spacing.to_bits()) {
+            continue;
+        }
+        if let Some(before) = before {
+            let position = axis.span(&before.bounds).1 + spacing;
+            gap(position, spacing, &[pair[0].value, pair[1].value, before.value]);
+        }
+        if let Some(after) = after {
+            let position = axis.span(&after.bounds).0 - spacing - length;
+            gap(position, spacing, &[pair[0].value, pair[1].value, after.value]);
+        }
+    }
+}
+
+/// Snap guides for a node at `moving` among the nodes of `scene`, those
+/// in view less the ones being moved. Guides run across `viewport`, or
+/// the whole scene when it isn't given, and snap within `threshold`.
+/// Candidates come nearest first.
+#[command]
+pub fn snap_candidates(
+    moving: Rect,
+    scene: Vec<SnapTarget>,
+    viewport: Option<Rect>,
+    threshold: Option<f64>,
+) -> Result<Vec<SnapCandidate>, String> {
+    let threshold = threshold.unwrap_or(DEFAULT_THRESHOLD);
+    if !(threshold.is_finite() && threshold >= 0.0) {
+        return Err(format!("Invalid threshold: {}", threshold));
+    }
+    let tree = RTree::new(scene.iter().map(|t| Entry { bounds: t.bounds, value: t.id.as_str() }).collect());
+    let Some(scene_bounds) = tree.bounds() else { return Ok(Vec::new()) };
+    let view = viewport.unwrap_or(scene_bounds).union(&moving);
+
+    let mut found = Vec::new();
+    for axis in [SnapAxis::X, SnapAxis::Y] {
+        align(&tree, &moving, axis, &view, threshold, &mut found);
+        space(&tree, &moving, axis, &view, threshold, &mut found);
+    }
+    found.sort_by(|a, b| a.offset.abs().total_cmp(&b.offset.abs()));
+    Ok(found)
+}
@@ -0,0 +1,127 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tauri::ipc::Channel;
+use tauri::{command, State};
+
+const DEFAULT_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+const MAX_CHUNK_SIZE: usize = 64 * 1024 * 1024;
+
+/// Messages delivered over the channel passed to `read_design_file_stream`.
+#[derive(Serialize, Clone)]
+#[serde(tag = "event", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum ReadStreamEvent {
+    Started { stream_id: u64, total_bytes: u64 },
+    /// Base64-encoded bytes starting at `offset`.
+    Chunk { offset: u64, data: String, bytes_read: u64, total_bytes: u64 },
+    Finished { bytes_read: u64 },
+    Cancelled { bytes_read: u64 },
+    Failed { message: String },
+}
+
+/// Cancellation flags for in-flight streamed reads.
+#[derive(Default)]
+pub struct ReadStreams {
+    next_id: AtomicU64,
+    active: Mutex<HashMap<u64, Arc<AtomicBool>>>,
+}
+
+impl ReadStreams {
+    fn register(&self) -> (u64, Arc<AtomicBool>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let flag = Arc::new(AtomicBool::new(false));
+        self.active.lock().unwrap_or_else(|e| e.into_inner()).insert(id, Arc::clone(&flag));
+        (id, flag)
+    }
+
+    fn finish(&self, id: u64) {
+        self.active.lock().unwrap_or_else(|e| e.into_inner()).remove(&id);
+    }
+
+    fn cancel(&self, id: u64) -> bool {
+        match self.active.lock().unwrap_or_else(|e| e.into_inner()).get(&id) {
+            Some(flag) => {
+                flag.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn stream_file(
+    file: &mut File,
+    total_bytes: u64,
+    chunk_size: usize,
+    cancelled: &AtomicBool,
+    on_event: &Channel<ReadStreamEvent>,
+) -> Result<ReadStreamEvent, String> {
+    let mut buf = vec![0u8; chunk_size];
+    let mut bytes_read: u64 = 0;
+
+    loop {
+        if cancelled.load(Ordering::Relaxed) {
+            return Ok(ReadStreamEvent::Cancelled { bytes_read });
+        }
+
+        let n = file.read(&mut buf)
+            .map_err(|e| format!("Failed to read file: {}", e))?;
+        if n == 0 {
+            return Ok(ReadStreamEvent::Finished { bytes_read });
+        }
+
+        let offset = bytes_read;
+        bytes_read += n as u64;
+        on_event
+            .send(ReadStreamEvent::Chunk {
+                offset,
+                data: BASE64.encode(&buf[..n]),
+                bytes_read,
+                total_bytes: total_bytes.max(bytes_read),
+            })
+            .map_err(|e| format!("Failed to deliver chunk: {}", e))?;
+    }
+}
+
+/// Read a file in chunks on a background thread, reporting progress through
+/// `on_event`. Returns the stream id to pass to `cancel_read_stream`.
+#[command]
+pub fn read_design_file_stream(
+    streams: State<'_, Arc<ReadStreams>>,
+    path: String,
+    chunk_size: Option<usize>,
+    on_event: Channel<ReadStreamEvent>,
+) -> Result<u64, String> {
+    let mut file = File::open(&path)
+        .map_err(|e| format!("Failed to open file: {}", e))?;
+    let total_bytes = file.metadata().map(|m| m.len()).unwrap_or(0);
+    let chunk_size = chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE).clamp(1, MAX_CHUNK_SIZE);
+
+    let streams = Arc::clone(&streams);
+    let (stream_id, cancelled) = streams.register();
+
+    let _ = on_event.send(ReadStreamEvent::Started { stream_id, total_bytes });
+
+    thread::Builder::new()
+        .name(format!("read-stream-{}", stream_id))
+        .spawn(move || {
+            let last = stream_file(&mut file, total_bytes, chunk_size, &cancelled, &on_event)
+                .unwrap_or_else(|message| ReadStreamEvent::Failed { message });
+            streams.finish(stream_id);
+            let _ = on_event.send(last);
+        })
+        .map_err(|e| format!("Failed to start read: {}", e))?;
+
+    Ok(stream_id)
+}
+
+/// Request cancellation; returns false if the stream already completed.
+#[command]
+pub fn cancel_read_stream(streams: State<'_, Arc<ReadStreams>>, stream_id: u64) -> bool {
+    streams.cancel(stream_id)
+}
@@ -0,0 +1,263 @@
+//! Long-running work off the IPC thread. A task is a list of steps — reading
+//! or writing design files, exports and imports — run in order on the async
+//! runtime's blocking pool, with at most one task per core running at once
+//! and the rest queued. Progress is reported on `TASK_EVENT` by task id
+//! after every step. Cancelling stops a task before its next step; a step
+//! already running finishes first.
+
+use crate::backups::BackupManager;
+use crate::commands::{read_design_file, read_design_file_binary, write_contents, EncryptOptions};
+use crate::export::pdf::{export_pdf, PdfExportOptions};
+use crate::export::raster::{export_raster, RasterExportOptions, RasterFormat};
+use crate::export::svg::{export_svg, SvgExportOptions};
+use crate::import::figma::import_fig;
+use crate::import::pdf::{import_pdf, PdfImportOptions};
+use crate::import::raster::{decode_image, DecodeImageOptions};
+use crate::import::sketch::import_sketch;
+use crate::import::svg::{import_svg, SvgImportOptions};
+use crate::locks::FileLocks;
+use crate::mapped::MappedBundles;
+use crate::watcher::FileWatcher;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use tauri::{async_runtime, command, AppHandle, Emitter, Manager, State};
+use tokio::sync::Semaphore;
+
+pub const TASK_EVENT: &str = "task";
+
+/// One step of a task, as the command it stands for would take it.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE", rename_all_fields = "camelCase")]
+pub enum TaskStep {
+    ReadDesignFile { path: String, passphrase: Option<String> },
+    ReadDesignFileBinary { path: String, passphrase: Option<String> },
+    /// `data` is base64 when `binary` is set, and text otherwise.
+    WriteDesignFile {
+        path: String,
+        data: String,
+        #[serde(default)]
+        binary: bool,
+        encrypt: Option<EncryptOptions>,
+        #[serde(default)]
+        compress: bool,
+    },
+    ExportSvg { node_json: String, options: Option<SvgExportOptions> },
+    ExportPdf { node_json: String, options: Option<PdfExportOptions> },
+    ExportRaster { node_json: String, format: RasterFormat, scale: Option<f64>, options: Option<RasterExportOptions> },
+    ImportSvg { path: String, options: Option<SvgImportOptions> },
+    ImportPdf { path: String, options: Option<PdfImportOptions> },
+    ImportFig { path: String },
+    ImportSketch { path: String },
+    DecodeImage { path: String, options: Option<DecodeImageOptions> },
+}
+
+fn to_value<T: Serialize, E: Serialize>(result: Result<T, E>) -> Result<Value, Value> {
+    match result {
+        Ok(value) => serde_json::to_value(value).map_err(|e| Value::String(e.to_string())),
+        Err(error) => Err(serde_json::to_value(error).unwrap_or_default()),
+    }
+}
+
+impl TaskStep {
+    /// Run the step, giving its result or error as the command would.
+    fn run(self, app: &AppHandle) -> Result<Value, Value> {
+        match self {
+            TaskStep::ReadDesignFile { path, passphrase } => to_value(read_design_file(path, passphrase)),
+            TaskStep::ReadDesignFileBinary { path, passphrase } => to_value(read_design_file_binary(path, passphrase)),
+            TaskStep::WriteDesignFile { path, data, binary, encrypt, compress } => {
+                let bytes = if binary {
+                    BASE64.decode(data.as_bytes()).map_err(|e| Value::String(format!("Invalid base64 data: {}", e)))?
+                } else {
+                    data.into_bytes()
+                };
+                let (watcher, backups, locks) = (app.state::<FileWatcher>(), app.state::<BackupManager>(), app.state::<FileLocks>());
+                let mapped = app.state::<MappedBundles>();
+                to_value(write_contents(&watcher, &backups, &locks, &mapped, &path, &bytes, encrypt, compress))
+            }
+            TaskStep::ExportSvg { node_json, options } => to_value(export_svg(node_json, options)),
+            TaskStep::ExportPdf { node_json, options } => to_value(export_pdf(node_json, options)),
+            TaskStep::ExportRaster { node_json, format, scale, options } => to_value(export_raster(node_json, format, scale, options)),
+            TaskStep::ImportSvg { path, options } => to_value(import_svg(path, options)),
+            TaskStep::ImportPdf { path, options } => to_value(import_pdf(path, options)),
+            TaskStep::ImportFig { path } => to_value(import_fig(path)),
+            TaskStep::ImportSketch { path } => to_value(import_sketch(path)),
+            TaskStep::DecodeImage { path, options } => to_value(decode_image(path, options)),
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+#[serde(tag = "event", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum TaskEvent {
+    /// The task has left the queue.
+    Started { task_id: u64, total_steps: usize },
+    /// `result` is that of step `step`, counting from zero.
+    Progress { task_id: u64, step: usize, total_steps: usize, result: Value },
+    Finished { task_id: u64 },
+    Cancelled { task_id: u64, steps_done: usize },
+    /// `error` is that of the step that failed; the steps after it are not
+    /// run.
+    Failed { task_id: u64, step: usize, error: Value },
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskStatus {
+    Queued,
+    Running,
+}
+
+struct Task {
+    label: Option<String>,
+    total_steps: usize,
+    steps_done: usize,
+    status: TaskStatus,
+    cancelled: Arc<AtomicBool>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskInfo {
+    pub task_id: u64,
+    pub label: Option<String>,
+    pub status: TaskStatus,
+    pub steps_done: usize,
+    pub total_steps: usize,
+}
+
+/// Queued and running tasks, managed as app state.
+pub struct TaskPool {
+    next_id: AtomicU64,
+    active: Mutex<HashMap<u64, Task>>,
+    /// One permit per task that may run at once.
+    slots: Arc<Semaphore>,
+}
+
+impl Default for TaskPool {
+    fn default() -> Self {
+        let cores = thread::available_parallelism().map(|n| n.get()).unwrap_or(4);
+        TaskPool { next_id: AtomicU64::new(0), active: Mutex::new(HashMap::new()), slots: Arc::new(Semaphore::new(cores)) }
+    }
+}
+
+impl TaskPool {
+    fn lock(&self) -> MutexGuard<'_, HashMap<u64, Task>> {
+        self.active.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn update(&self, id: u64, change: impl FnOnce(&mut Task)) {
+        if let Some(task) = self.lock().get_mut(&id) {
+            change(task);
+        }
+    }
+
+    /// Queue `steps` as a new task, returning its id.
+    pub fn spawn(self: &Arc<Self>, app: AppHandle, label: Option<String>, steps: Vec<TaskStep>) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.lock().insert(id, Task {
+            label,
+            total_steps: steps.len(),
+            steps_done: 0,
+            status: TaskStatus::Queued,
+            cancelled: Arc::clone(&cancelled),
+        });
+
+        let pool = Arc::clone(self);
+        async_runtime::spawn(async move {
+            // The permit is held until the task's last step is done
+            let permit = Arc::clone(&pool.slots).acquire_owned().await;
+            let (worker, handle) = (Arc::clone(&pool), app.clone());
+            let run = async_runtime::spawn_blocking(move || {
+                let _permit = permit;
+                worker.run(&handle, id, steps, &cancelled)
+            });
+            if run.await.is_err() {
+                let step = pool.lock().remove(&id).map(|task| task.steps_done).unwrap_or_default();
+                let error = Value::String("The task stopped unexpectedly".to_string());
+                let _ = app.emit(TASK_EVENT, TaskEvent::Failed { task_id: id, step, error });
+            }
+        });
+        id
+    }
+
+    fn run(&self, app: &AppHandle, id: u64, steps: Vec<TaskStep>, cancelled: &AtomicBool) {
+        let total_steps = steps.len();
+        self.update(id, |task| task.status = TaskStatus::Running);
+        let _ = app.emit(TASK_EVENT, TaskEvent::Started { task_id: id, total_steps });
+
+        for (step, work) in steps.into_iter().enumerate() {
+            if cancelled.load(Ordering::Relaxed) {
+                self.lock().remove(&id);
+                let _ = app.emit(TASK_EVENT, TaskEvent::Cancelled { task_id: id, steps_done: step });
+                return;
+            }
+            match work.run(app) {
+                Ok(result) => {
+                    self.update(id, |task| task.steps_done = step + 1);
+                    let _ = app.emit(TASK_EVENT, TaskEvent::Progress { task_id: id, step, total_steps, result });
+                }
+                Err(error) => {
+                    self.lock().remove(&id);
+                    let _ = app.emit(TASK_EVENT, TaskEvent::Failed { task_id: id, step, error });
+                    return;
+                }
+            }
+        }
+        self.lock().remove(&id);
+        let _ = app.emit(TASK_EVENT, TaskEvent::Finished { task_id: id });
+    }
+
+    fn cancel(&self, id: u64) -> bool {
+        match self.lock().get(&id) {
+            Some(task) => {
+                task.cancelled.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Run `steps` in the background, in order, as one task. Returns the task's
+/// id straight away; its progress and results arrive on `TASK_EVENT`.
+#[command]
+pub fn start_task(
+    app: AppHandle,
+    pool: State<'_, Arc<TaskPool>>,
+    steps: Vec<TaskStep>,
+    label: Option<String>,
+) -> Result<u64, String> {
+    if steps.is_empty() {
+        return Err("A task needs at least one step".to_string());
+    }
+    Ok(pool.spawn(app, label, steps))
+}
+
+/// Cancel a queued or running task. Returns false if it has already ended.
+#[command]
+pub fn cancel_task(pool: State<'_, Arc<TaskPool>>, task_id: u64) -> bool {
+    pool.cancel(task_id)
+}
+
+#[command]
+pub fn list_tasks(pool: State<'_, Arc<TaskPool>>) -> Vec<TaskInfo> {
+    let mut tasks: Vec<TaskInfo> = pool
+        .lock()
+        .iter()
+        .map(|(&task_id, task)| TaskInfo {
+            task_id,
+            label: task.label.clone(),
+            status: task.status,
+            steps_done: task.steps_done,
+            total_steps: task.total_steps,
+        })
+        .collect();
+    tasks.sort_by_key(|t| t.task_id);
+    tasks
+}
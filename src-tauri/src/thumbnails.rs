@@ -0,0 +1,202 @@
+use crate::atomic::write_atomic;
+use crate::bundle::{is_bundle, load_bundle};
+use crate::commands::read_contents;
+use crate::error::{FileError, FileErrorKind};
+use crate::history::hex_digest;
+use crate::model::{DocumentTree, NodeType};
+use crate::render::{render_to_fit, AssetImages};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{command, State};
+
+const DEFAULT_SIZE: u32 = 256;
+const MAX_SIZE: u32 = 1024;
+const MAX_CACHE_BYTES: u64 = 64 * 1024 * 1024;
+const MAX_CACHE_ENTRIES: usize = 500;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Thumbnail {
+    /// Cached PNG on disk.
+    pub cache_path: String,
+    /// The same PNG as a `data:` URL for direct use in an `<img>`.
+    pub data_url: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// PNG previews of design files, keyed by a hash of the file contents so a
+/// preview is reused until the file changes, wherever it is moved.
+///
+/// Entries are evicted least-recently-used first once the cache exceeds
+/// `MAX_CACHE_BYTES` or `MAX_CACHE_ENTRIES`.
+pub struct ThumbnailCache {
+    dir: PathBuf,
+    /// Source path -> content hash of the most recent thumbnail served for it.
+    index: Mutex<HashMap<String, String>>,
+}
+
+fn modified_millis(meta: &fs::Metadata) -> u64 {
+    meta.modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Render the first frame of the first page, or the whole page if it has no
+/// frames. Compressed files are decompressed; encrypted ones need a
+/// passphrase and so have no preview.
+fn render_preview(path: &str, size: u32) -> Result<Vec<u8>, FileError> {
+    let failed = |e: String| FileError::new(FileErrorKind::Io, path, e);
+    let contents = read_contents(path, None)?;
+    let mut images = AssetImages::default();
+    let document = if is_bundle(&contents.bytes) {
+        let bundle = load_bundle(path).map_err(failed)?;
+        images.assets = bundle.assets.into_iter().collect();
+        bundle.document
+    } else {
+        String::from_utf8(contents.bytes)
+            .map_err(|e| failed(format!("Design file is not valid UTF-8: {}", e)))?
+    };
+
+    let tree = DocumentTree::parse(&document).map_err(failed)?;
+    let page = tree.pages().into_iter().next()
+        .ok_or_else(|| failed("Document has no pages".to_string()))?;
+
+    let frame = tree
+        .children(&page.id)
+        .iter()
+        .filter_map(|id| tree.get(id))
+        .find(|n| n.visible && matches!(n.node_type, NodeType::Frame | NodeType::Component));
+    let target = frame.map(|n| n.id.as_str()).unwrap_or(&page.id);
+
+    let pixmap = render_to_fit(&tree, target, size, size, &images).map_err(failed)?;
+    pixmap.encode_png()
+        .map_err(|e| failed(format!("Failed to encode thumbnail: {}", e)))
+}
+
+impl ThumbnailCache {
+    pub fn new(dir: PathBuf) -> Self {
+        ThumbnailCache { dir, index: Mutex::new(HashMap::new()) }
+    }
+
+    fn index(&self) -> MutexGuard<'_, HashMap<String, String>> {
+        self.index.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn entry_path(&self, hash: &str, size: u32) -> PathBuf {
+        self.dir.join(format!("{}-{}.png", hash, size))
+    }
+
+    fn get(&self, path: &str, size: u32) -> Result<Thumbnail, FileError> {
+        let bytes = fs::read(path)
+            .map_err(|e| FileError::from_io(&e, path, "read file"))?;
+        let hash = hex_digest(&bytes);
+        let entry = self.entry_path(&hash, size);
+
+        let png = match fs::read(&entry) {
+            Ok(png) => {
+                // Bump the mtime so eviction treats this entry as recently used
+                if let Ok(file) = fs::File::options().write(true).open(&entry) {
+                    let _ = file.set_modified(SystemTime::now());
+                }
+                png
+            }
+            Err(_) => {
+                let png = render_preview(path, size)?;
+                fs::create_dir_all(&self.dir)
+                    .map_err(|e| FileError::from_io(&e, &self.dir.to_string_lossy(), "create thumbnail cache"))?;
+                write_atomic(&entry.to_string_lossy(), &png)?;
+                self.evict();
+                png
+            }
+        };
+
+        let previous = self.index().insert(path.to_string(), hash.clone());
+        if let Some(previous) = previous.filter(|p| *p != hash) {
+            self.remove_hash(&previous);
+        }
+
+        let (width, height) = png_size(&png).unwrap_or((0, 0));
+        Ok(Thumbnail {
+            cache_path: entry.to_string_lossy().into_owned(),
+            data_url: format!("data:image/png;base64,{}", BASE64.encode(&png)),
+            width,
+            height,
+        })
+    }
+
+    fn remove_hash(&self, hash: &str) {
+        let Ok(entries) = fs::read_dir(&self.dir) else { return };
+        let prefix = format!("{}-", hash);
+        for entry in entries.flatten() {
+            if entry.file_name().to_string_lossy().starts_with(&prefix) {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+
+    fn invalidate(&self, path: &str) {
+        if let Some(hash) = self.index().remove(path) {
+            self.remove_hash(&hash);
+        }
+        if let Ok(bytes) = fs::read(path) {
+            self.remove_hash(&hex_digest(&bytes));
+        }
+    }
+
+    fn evict(&self) {
+        evict_png_cache(&self.dir, MAX_CACHE_ENTRIES, MAX_CACHE_BYTES);
+    }
+}
+
+/// Drop the least recently used PNGs in `dir` until it holds at most
+/// `max_entries` files and `max_bytes` bytes.
+pub fn evict_png_cache(dir: &Path, max_entries: usize, max_bytes: u64) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    let mut files: Vec<(PathBuf, u64, u64)> = entries
+        .flatten()
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "png"))
+        .filter_map(|e| {
+            let meta = e.metadata().ok()?;
+            Some((e.path(), meta.len(), modified_millis(&meta)))
+        })
+        .collect();
+    files.sort_by_key(|(_, _, mtime)| std::cmp::Reverse(*mtime));
+
+    let mut total = 0;
+    for (index, (path, len, _)) in files.into_iter().enumerate() {
+        total += len;
+        if index >= max_entries || total > max_bytes {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Width and height from a PNG's IHDR chunk.
+pub fn png_size(png: &[u8]) -> Option<(u32, u32)> {
+    let width = u32::from_be_bytes(png.get(16..20)?.try_into().ok()?);
+    let height = u32::from_be_bytes(png.get(20..24)?.try_into().ok()?);
+    Some((width, height))
+}
+
+/// Preview of a design file fitted within `size` x `size` pixels.
+#[command(async)]
+pub fn get_thumbnail(
+    cache: State<'_, ThumbnailCache>,
+    path: String,
+    size: Option<u32>,
+) -> Result<Thumbnail, FileError> {
+    cache.get(&path, size.unwrap_or(DEFAULT_SIZE).clamp(16, MAX_SIZE))
+}
+
+#[command]
+pub fn invalidate_thumbnail(cache: State<'_, ThumbnailCache>, path: String) {
+    cache.invalidate(&path);
+}
@@ -0,0 +1,195 @@
+use crate::history::hex_digest;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::UNIX_EPOCH;
+use tauri::{command, AppHandle, Emitter, State};
+
+pub const FILE_CHANGED_EVENT: &str = "file-changed";
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FileChangedEvent {
+    pub path: String,
+    pub mtime: Option<u64>,
+    pub hash: Option<String>,
+    pub removed: bool,
+}
+
+#[derive(Default)]
+struct WatchState {
+    /// Watched file -> hash of the contents we last saw or wrote ourselves.
+    files: HashMap<PathBuf, Option<String>>,
+    /// Watched directory -> number of watched files inside it.
+    dirs: HashMap<PathBuf, usize>,
+}
+
+/// Watches open documents for modifications made by other programs and emits
+/// `file-changed` events to the frontend.
+///
+/// Parent directories are watched rather than the files themselves, since
+/// atomic saves (ours and most editors') replace the file instead of writing
+/// to it in place.
+pub struct FileWatcher {
+    state: Arc<Mutex<WatchState>>,
+    watcher: Mutex<RecommendedWatcher>,
+}
+
+fn lock(state: &Mutex<WatchState>) -> MutexGuard<'_, WatchState> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Canonical form of a path whose file may not exist right now.
+pub fn normalize(path: &Path) -> PathBuf {
+    if let Ok(p) = path.canonicalize() {
+        return p;
+    }
+    match (path.parent().and_then(|p| p.canonicalize().ok()), path.file_name()) {
+        (Some(dir), Some(name)) => dir.join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+pub fn file_mtime(path: &Path) -> Option<u64> {
+    std::fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as u64)
+}
+
+fn file_hash(path: &Path) -> Option<String> {
+    std::fs::read(path).ok().map(|bytes| hex_digest(&bytes))
+}
+
+fn handle_event(app: &AppHandle, state: &Mutex<WatchState>, event: notify::Event) {
+    if matches!(event.kind, EventKind::Access(_)) {
+        return;
+    }
+
+    for path in event.paths {
+        let path = normalize(&path);
+        if !lock(state).files.contains_key(&path) {
+            continue;
+        }
+        let hash = file_hash(&path);
+
+        let mut state = lock(state);
+        let Some(known) = state.files.get_mut(&path) else { continue };
+        if *known == hash {
+            continue;
+        }
+        *known = hash.clone();
+        drop(state);
+
+        let _ = app.emit(FILE_CHANGED_EVENT, FileChangedEvent {
+            path: path.to_string_lossy().into_owned(),
+            mtime: file_mtime(&path),
+            removed: hash.is_none(),
+            hash,
+        });
+    }
+}
+
+impl FileWatcher {
+    pub fn new(app: AppHandle) -> Result<Self, String> {
+        let state = Arc::new(Mutex::new(WatchState::default()));
+        let handler_state = Arc::clone(&state);
+
+        let watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            if let Ok(event) = res {
+                handle_event(&app, &handler_state, event);
+            }
+        })
+        .map_err(|e| format!("Failed to create file watcher: {}", e))?;
+
+        Ok(FileWatcher { state, watcher: Mutex::new(watcher) })
+    }
+
+    /// Record contents this app is about to write so the resulting filesystem
+    /// event is not reported back as an external modification.
+    pub fn acknowledge(&self, path: &str, bytes: &[u8]) {
+        let path = normalize(Path::new(path));
+        if let Some(known) = lock(&self.state).files.get_mut(&path) {
+            *known = Some(hex_digest(bytes));
+        }
+    }
+
+    fn watcher(&self) -> MutexGuard<'_, RecommendedWatcher> {
+        self.watcher.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // The state lock is never held across calls into notify: its backends may
+    // wait on the event thread, which itself takes the state lock in handle_event.
+
+    fn watch(&self, path: &Path) -> Result<(), String> {
+        let path = normalize(path);
+        let dir = path
+            .parent()
+            .ok_or_else(|| format!("Cannot watch path without a parent: {}", path.display()))?
+            .to_path_buf();
+
+        let new_dir = {
+            let state = lock(&self.state);
+            if state.files.contains_key(&path) {
+                return Ok(());
+            }
+            !state.dirs.contains_key(&dir)
+        };
+
+        if new_dir {
+            self.watcher()
+                .watch(&dir, RecursiveMode::NonRecursive)
+                .map_err(|e| format!("Failed to watch {}: {}", dir.display(), e))?;
+        }
+
+        let hash = file_hash(&path);
+        let mut state = lock(&self.state);
+        if state.files.insert(path, hash).is_none() {
+            *state.dirs.entry(dir).or_insert(0) += 1;
+        }
+        Ok(())
+    }
+
+    fn unwatch(&self, path: &Path) -> Result<(), String> {
+        let path = normalize(path);
+        let Some(dir) = path.parent().map(Path::to_path_buf) else { return Ok(()) };
+
+        let last_in_dir = {
+            let mut state = lock(&self.state);
+            if state.files.remove(&path).is_none() {
+                return Ok(());
+            }
+            match state.dirs.get_mut(&dir) {
+                Some(count) if *count > 1 => {
+                    *count -= 1;
+                    false
+                }
+                Some(_) => {
+                    state.dirs.remove(&dir);
+                    true
+                }
+                None => false,
+            }
+        };
+
+        if last_in_dir {
+            self.watcher()
+                .unwatch(&dir)
+                .map_err(|e| format!("Failed to stop watching {}: {}", dir.display(), e))?;
+        }
+        Ok(())
+    }
+}
+
+#[command]
+pub fn start_watching(watcher: State<'_, FileWatcher>, path: String) -> Result<(), String> {
+    watcher.watch(Path::new(&path))
+}
+
+#[command]
+pub fn stop_watching(watcher: State<'_, FileWatcher>, path: String) -> Result<(), String> {
+    watcher.unwatch(Path::new(&path))
+}